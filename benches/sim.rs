@@ -0,0 +1,306 @@
+//! Benchmarks of the simulation engine, run with `cargo bench`.
+//!
+//! Log decoding runs on synthetic logs. Fork setup & the backrun search run on a hinted swap served by a
+//! `MockChain`, and replay the recorded golden fixtures (see `fixtures/golden`) from their cassettes,
+//! so they measure the engine without the node's latency; fixtures that haven't been recorded are skipped.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ethers::{
+    abi::{encode, Token},
+    contract::EthEvent,
+    providers::Middleware,
+    types::{Address, Block, Bytes, Log, Transaction, TransactionReceipt, H256, I256, U256},
+    utils::id,
+};
+use hindsight::{
+    cassette::{serve, CassetteMode},
+    config::{known_wrapped_native, SUSHISWAP_FACTORY, UNISWAP_V2_FACTORY, UNISWAP_V3_FACTORY},
+    hindsight::Hindsight,
+    mock::MockChain,
+    sim::{
+        events::UniswapV2Swap,
+        fork::{fork_factory, fork_from},
+        pools::{
+            PoolAdapter, UniswapV2Adapter, UniswapV3Adapter, UNISWAP_V2_SWAP_TOPIC,
+            UNISWAP_V3_SWAP_TOPIC,
+        },
+        processor::H256Map,
+    },
+    util::{get_block_info, get_ws_client, WsClient, ETH},
+    Result,
+};
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+use std::{path::PathBuf, str::FromStr};
+use tokio::runtime::Runtime;
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/golden");
+/// Number of logs decoded per iteration of the decoding benches.
+const NUM_LOGS: usize = 1000;
+
+/// A hinted tx & the chain it landed on, served from a cassette or a `MockChain`.
+struct Fixture {
+    name: String,
+    client: WsClient,
+    tx: Transaction,
+    event: EventHistory,
+}
+
+/// The user's swap of 1 WETH for 2000 tokens on a V2 pair that's also listed on Sushiswap, on a `MockChain`.
+///
+/// The mock chain scripts the calls that derive the trade & look up its pools, but has no contract state,
+/// so the user's tx fails to replay on the fork: its search covers everything up to the backrun sims,
+/// which only recorded fixtures measure.
+async fn mock_fixture() -> Result<Fixture> {
+    let call = |signature: &str, args: &[Token]| -> Bytes {
+        [&id(signature)[..], &encode(args)].concat().into()
+    };
+    let returns = |address: Address| -> Bytes { encode(&[Token::Address(address)]).into() };
+    let weth = known_wrapped_native(1).ok_or(anyhow::format_err!("no WETH on mainnet"))?;
+    let token = Address::from_low_u64_be(0x70);
+    let (pair, alt_pair) = (Address::from_low_u64_be(0xa), Address::from_low_u64_be(0xb));
+    let uni_factory: Address = UNISWAP_V2_FACTORY.parse()?;
+    let sushi_factory: Address = SUSHISWAP_FACTORY.parse()?;
+    let tx = Transaction {
+        hash: H256::from_low_u64_be(0x1234),
+        block_number: Some(100.into()),
+        ..Default::default()
+    };
+    let user = H256::from(Address::from_low_u64_be(0x5e));
+    let swap_log = Log {
+        address: pair,
+        topics: vec![UniswapV2Swap::signature(), user, user],
+        data: encode(&[
+            Token::Uint(0.into()),
+            Token::Uint(ETH),
+            Token::Uint(U256::from(2000) * ETH),
+            Token::Uint(0.into()),
+        ])
+        .into(),
+        ..Default::default()
+    };
+    let mut chain = MockChain::new(1)
+        .with_block(Block {
+            number: Some(99.into()),
+            hash: Some(H256::from_low_u64_be(99)),
+            ..Default::default()
+        })
+        .with_transaction(tx.clone())
+        .with_receipt(TransactionReceipt {
+            transaction_hash: tx.hash,
+            block_number: tx.block_number,
+            logs: vec![swap_log],
+            ..Default::default()
+        })
+        .with_call(pair, call("token0()", &[]), returns(token))
+        .with_call(pair, call("token1()", &[]), returns(weth))
+        .with_call(pair, call("factory()", &[]), returns(uni_factory))
+        .with_call(
+            token,
+            call("decimals()", &[]),
+            encode(&[Token::Uint(18.into())]).into(),
+        )
+        .with_call(
+            pair,
+            call("getReserves()", &[]),
+            encode(&[
+                Token::Uint(U256::from(98_000) * ETH),
+                Token::Uint(U256::from(51) * ETH),
+                Token::Uint(0.into()),
+            ])
+            .into(),
+        );
+    for (a, b) in [(token, weth), (weth, token)] {
+        let tokens = [Token::Address(a), Token::Address(b)];
+        chain = chain
+            .with_call(
+                uni_factory,
+                call("getPair(address,address)", &tokens),
+                returns(pair),
+            )
+            .with_call(
+                sushi_factory,
+                call("getPair(address,address)", &tokens),
+                returns(alt_pair),
+            )
+            .with_call(
+                UNISWAP_V3_FACTORY.parse()?,
+                call(
+                    "getPool(address,address,uint24)",
+                    &[
+                        Token::Address(a),
+                        Token::Address(b),
+                        Token::Uint(3000.into()),
+                    ],
+                ),
+                returns(Address::zero()),
+            );
+    }
+    Ok(Fixture {
+        name: "mock_v2_swap".to_owned(),
+        client: chain.serve().await?,
+        event: EventHistory {
+            block: 100,
+            timestamp: 1_700_000_000,
+            hint: Hint {
+                txs: vec![],
+                hash: tx.hash,
+                logs: vec![EventTransactionLog {
+                    address: pair,
+                    topics: vec![UniswapV2Swap::signature()],
+                    data: Default::default(),
+                }],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        },
+        tx,
+    })
+}
+
+/// Serves the mock fixture, and every golden fixture that has a cassette.
+async fn fixtures() -> Result<Vec<Fixture>> {
+    let mut dirs = std::fs::read_dir(FIXTURES_DIR)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<PathBuf>>>()?;
+    dirs.retain(|dir| dir.join("event.json").exists() && dir.join("rpc.jsonl").exists());
+    dirs.sort();
+    let mut fixtures = vec![mock_fixture().await?];
+    for dir in dirs {
+        let event: EventHistory =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("event.json"))?)?;
+        let url = serve(CassetteMode::Replay, &dir.join("rpc.jsonl"), "").await?;
+        let client = get_ws_client(Some(url)).await?;
+        let tx = client
+            .get_transaction(event.hint.hash)
+            .await?
+            .ok_or(anyhow::format_err!(
+                "tx {:?} not in cassette",
+                event.hint.hash
+            ))?;
+        fixtures.push(Fixture {
+            name: dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            client,
+            tx,
+            event,
+        });
+    }
+    Ok(fixtures)
+}
+
+fn swap_log(topic: &str, data: Vec<Token>) -> Log {
+    let user = H256::from(Address::from_low_u64_be(0xb));
+    Log {
+        address: Address::from_low_u64_be(0xa),
+        topics: vec![H256::from_str(topic).unwrap(), user, user],
+        data: encode(&data).into(),
+        ..Default::default()
+    }
+}
+
+fn bench_decode_swaps(c: &mut Criterion) {
+    let v2_log = swap_log(
+        UNISWAP_V2_SWAP_TOPIC,
+        vec![
+            Token::Uint(ETH),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Uint(U256::from(2000) * ETH),
+        ],
+    );
+    let v3_log = swap_log(
+        UNISWAP_V3_SWAP_TOPIC,
+        vec![
+            Token::Int(I256::from_raw(ETH).into_raw()),
+            Token::Int((-I256::from_raw(U256::from(2000) * ETH)).into_raw()),
+            // a price of 1
+            Token::Uint(U256::one() << 96),
+            Token::Uint(ETH),
+            Token::Int(0.into()),
+        ],
+    );
+    let adapters: [(&str, &dyn PoolAdapter, Log); 2] = [
+        ("uniswap_v2", &UniswapV2Adapter, v2_log),
+        ("uniswap_v3", &UniswapV3Adapter, v3_log),
+    ];
+    let mut group = c.benchmark_group("decode_swap");
+    group.throughput(Throughput::Elements(NUM_LOGS as u64));
+    for (name, adapter, log) in adapters {
+        let logs = vec![log; NUM_LOGS];
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for log in &logs {
+                    adapter
+                        .decode_swap(log, std::slice::from_ref(log), 18.into())
+                        .unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_fork_setup(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let fixtures = rt.block_on(fixtures()).unwrap();
+    let mut group = c.benchmark_group("fork_setup");
+    for fixture in &fixtures {
+        // txs are simulated on the block before the one they landed in
+        let block_info = rt
+            .block_on(get_block_info(&fixture.client, fixture.event.block - 1))
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(&fixture.name),
+            &block_info,
+            |b, block_info| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let factory = fork_factory(&fixture.client, block_info).await.unwrap();
+                        fork_from(&factory, block_info)
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// The whole search for a fixture's backruns, dominated by `step_arb`'s sims. Clones of the processor share its
+/// caches, so the fork & pools are set up by the first run, and later runs measure the search itself.
+fn bench_search(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let fixtures = rt.block_on(fixtures()).unwrap();
+    let mut group = c.benchmark_group("search");
+    // each run simulates dozens of backruns
+    group.sample_size(10);
+    for fixture in &fixtures {
+        let hindsight = rt
+            .block_on(
+                Hindsight::builder()
+                    .provider(fixture.client.clone())
+                    .build(),
+            )
+            .unwrap();
+        let event_map: H256Map<EventHistory> =
+            [(fixture.event.hint.hash, fixture.event.to_owned())]
+                .into_iter()
+                .collect();
+        let txs = vec![fixture.tx.to_owned()];
+        group.bench_function(BenchmarkId::from_parameter(&fixture.name), |b| {
+            b.iter(|| {
+                rt.block_on(
+                    hindsight
+                        .clone()
+                        .process_orderflow(&txs, 1, None, event_map.clone()),
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode_swaps, bench_fork_setup, bench_search);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+fn main() {
+    // the gRPC server is generated from its protobuf definition, which requires `protoc`
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/hindsight.proto")
+        .expect("failed to compile proto/hindsight.proto");
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.19;
+
+interface IERC20 {
+    function balanceOf(address account) external view returns (uint256);
+}
+
+interface IUniswapV2Pair {
+    function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+    function swap(uint256 amount0Out, uint256 amount1Out, address to, bytes calldata data) external;
+}
+
+interface IUniswapV3Pool {
+    function swap(
+        address recipient,
+        bool zeroForOne,
+        int256 amountSpecified,
+        uint160 sqrtPriceLimitX96,
+        bytes calldata data
+    ) external returns (int256 amount0, int256 amount1);
+}
+
+/// @title Executor
+/// @notice Runs the backruns that hindsight simulates: a chain of UniswapV2/V3 swaps (or their clones) that must end
+/// with more of the starting token than it began with, paid for with the contract's own balance.
+/// @dev Only the deployer can trade or withdraw. Deploy it with `hindsight deploy-executor`.
+contract Executor {
+    /// One swap of a route. `variant` is 0 for UniswapV2 pools and 1 for UniswapV3 pools.
+    struct Swap {
+        address pool;
+        uint8 variant;
+        address tokenIn;
+        address tokenOut;
+    }
+
+    uint8 internal constant UNISWAP_V2 = 0;
+    uint8 internal constant UNISWAP_V3 = 1;
+    // TickMath.MIN_SQRT_RATIO + 1 & TickMath.MAX_SQRT_RATIO - 1, i.e. no price limit
+    uint160 internal constant MIN_SQRT_RATIO = 4295128740;
+    uint160 internal constant MAX_SQRT_RATIO = 1461446703485210103287273052203988822378723970341;
+
+    address public immutable owner;
+    /// V3 pool that the swap in progress is on; the only caller allowed into `uniswapV3SwapCallback`.
+    address private expectedPool;
+
+    error NotOwner();
+    error InvalidRoute();
+    error UnknownVariant(uint8 variant);
+    error UnexpectedCallback(address caller);
+    error TransferFailed(address token);
+    error Unprofitable(uint256 balanceBefore, uint256 balanceAfter);
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    modifier onlyOwner() {
+        if (msg.sender != owner) revert NotOwner();
+        _;
+    }
+
+    /// @notice Swaps `amountIn` of the first swap's `tokenIn` through `swaps`, reverting unless the contract ends up
+    /// with at least `minProfit` more of it than it started with.
+    /// @return profit How much the contract's balance of the starting token grew.
+    function backrun(uint256 amountIn, Swap[] calldata swaps, uint256 minProfit)
+        external
+        onlyOwner
+        returns (uint256 profit)
+    {
+        if (swaps.length == 0 || swaps[0].tokenIn != swaps[swaps.length - 1].tokenOut) revert InvalidRoute();
+        address quote = swaps[0].tokenIn;
+        uint256 balanceBefore = IERC20(quote).balanceOf(address(this));
+
+        uint256 amount = amountIn;
+        for (uint256 i = 0; i < swaps.length; ++i) {
+            Swap calldata swap = swaps[i];
+            if (i > 0 && swap.tokenIn != swaps[i - 1].tokenOut) revert InvalidRoute();
+            if (swap.variant == UNISWAP_V2) {
+                amount = _swapV2(swap, amount);
+            } else if (swap.variant == UNISWAP_V3) {
+                amount = _swapV3(swap, amount);
+            } else {
+                revert UnknownVariant(swap.variant);
+            }
+        }
+
+        uint256 balanceAfter = IERC20(quote).balanceOf(address(this));
+        if (balanceAfter < balanceBefore + minProfit) revert Unprofitable(balanceBefore, balanceAfter);
+        profit = balanceAfter - balanceBefore;
+    }
+
+    /// @notice Pays a V3 pool what it's owed for the swap in progress.
+    function uniswapV3SwapCallback(int256 amount0Delta, int256 amount1Delta, bytes calldata data) external {
+        if (msg.sender != expectedPool) revert UnexpectedCallback(msg.sender);
+        address tokenIn = abi.decode(data, (address));
+        _transfer(tokenIn, msg.sender, uint256(amount0Delta > 0 ? amount0Delta : amount1Delta));
+    }
+
+    /// @notice Sends `amount` of `token` (or of ETH, if `token` is the zero address) to the owner.
+    function withdraw(address token, uint256 amount) external onlyOwner {
+        if (token == address(0)) {
+            (bool success,) = owner.call{value: amount}("");
+            if (!success) revert TransferFailed(token);
+        } else {
+            _transfer(token, owner, amount);
+        }
+    }
+
+    receive() external payable {}
+
+    function _swapV2(Swap calldata swap, uint256 amountIn) internal returns (uint256 amountOut) {
+        bool zeroForOne = swap.tokenIn < swap.tokenOut;
+        (uint112 reserve0, uint112 reserve1,) = IUniswapV2Pair(swap.pool).getReserves();
+        (uint256 reserveIn, uint256 reserveOut) = zeroForOne ? (reserve0, reserve1) : (reserve1, reserve0);
+        uint256 amountInWithFee = amountIn * 997;
+        amountOut = (amountInWithFee * reserveOut) / (reserveIn * 1000 + amountInWithFee);
+        _transfer(swap.tokenIn, swap.pool, amountIn);
+        (uint256 amount0Out, uint256 amount1Out) = zeroForOne ? (uint256(0), amountOut) : (amountOut, uint256(0));
+        IUniswapV2Pair(swap.pool).swap(amount0Out, amount1Out, address(this), "");
+    }
+
+    function _swapV3(Swap calldata swap, uint256 amountIn) internal returns (uint256 amountOut) {
+        bool zeroForOne = swap.tokenIn < swap.tokenOut;
+        expectedPool = swap.pool;
+        (int256 amount0, int256 amount1) = IUniswapV3Pool(swap.pool).swap(
+            address(this),
+            zeroForOne,
+            int256(amountIn),
+            zeroForOne ? MIN_SQRT_RATIO : MAX_SQRT_RATIO,
+            abi.encode(swap.tokenIn)
+        );
+        expectedPool = address(0);
+        amountOut = uint256(-(zeroForOne ? amount1 : amount0));
+    }
+
+    /// Transfers `amount` of `token`, accepting tokens (like USDT) that don't return a bool.
+    function _transfer(address token, address to, uint256 amount) internal {
+        (bool success, bytes memory data) = token.call(abi.encodeWithSelector(0xa9059cbb, to, amount));
+        if (!success || (data.length > 0 && !abi.decode(data, (bool)))) revert TransferFailed(token);
+    }
+}
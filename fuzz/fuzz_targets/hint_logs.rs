@@ -0,0 +1,41 @@
+//! Feeds arbitrary hinted logs, as MEV-Share's stream might send them, to the filter that picks out swaps.
+#![no_main]
+
+use ethers::types::{Address, Bytes, H256};
+use hindsight::sim::{core::hinted_swap_logs, pools::PoolAdapters};
+use libfuzzer_sys::fuzz_target;
+use mev_share_sse::EventHistory;
+use serde_json::json;
+
+/// A hinted log: its address, whether its first topic is a real swap topic, its topics, and its data.
+type HintLog = ([u8; 20], bool, Vec<[u8; 32]>, Vec<u8>);
+
+fuzz_target!(|logs: Vec<HintLog>| {
+    let swap_topics = PoolAdapters::default().swap_topics();
+    let logs = logs
+        .into_iter()
+        .map(|(address, is_swap, topics, data)| {
+            let mut topics = topics.into_iter().map(H256).collect::<Vec<_>>();
+            // most random topics aren't swaps; make sure some logs get past the topic filter
+            if let (true, Some(topic)) = (is_swap, topics.first_mut()) {
+                *topic = swap_topics[topic.0[0] as usize % swap_topics.len()];
+            }
+            json!({
+                "address": Address::from(address),
+                "topics": topics,
+                "data": Bytes::from(data),
+            })
+        })
+        .collect::<Vec<_>>();
+    let event: EventHistory = serde_json::from_value(json!({
+        "block": 17637019,
+        "timestamp": 1688673408,
+        "hint": { "txs": null, "hash": H256::zero(), "logs": logs },
+    }))
+    .expect("hinted logs of any shape deserialize");
+    if let Ok(swap_logs) = hinted_swap_logs(&event, &swap_topics) {
+        assert!(swap_logs
+            .iter()
+            .all(|log| swap_topics.contains(&log.topics[0])));
+    }
+});
@@ -0,0 +1,22 @@
+//! Feeds arbitrary swaps to every built-in adapter's calldata encoder, which encodes the backruns' swaps.
+#![no_main]
+
+use ethers::types::{Address, U256};
+use hindsight::{interfaces::PoolVariant, sim::pools::PoolAdapters};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: ([u8; 20], [u8; 32], [u8; 20], [u8; 20])| {
+    let (pool, amount_in, token_in, token_out) = input;
+    let adapters = PoolAdapters::default();
+    for variant in [PoolVariant::UniswapV2, PoolVariant::UniswapV3] {
+        let call = adapters.get(variant).unwrap().build_swap_call(
+            Address::from(pool),
+            U256::from_big_endian(&amount_in),
+            Address::from(token_in),
+            Address::from(token_out),
+        );
+        if let Ok(call) = call {
+            assert!(!call.data.is_empty());
+        }
+    }
+});
@@ -0,0 +1,43 @@
+//! Feeds arbitrary receipt logs to every built-in adapter's swap decoding, as `derive_trade_params` does.
+#![no_main]
+
+use ethers::types::{Address, Log, H256, U256};
+use hindsight::sim::{core::decode_pool_swaps, pools::PoolAdapters};
+use libfuzzer_sys::fuzz_target;
+
+/// A receipt log: its address (one of a few, so logs share pools), whether its first topic is a real swap topic,
+/// its topics, and its data.
+type ReceiptLog = (u8, bool, Vec<[u8; 32]>, Vec<u8>);
+
+fuzz_target!(|input: (Vec<ReceiptLog>, u8)| {
+    let (logs, token0_decimals) = input;
+    let adapters = PoolAdapters::default();
+    let swap_topics = adapters.swap_topics();
+    let logs = logs
+        .into_iter()
+        .map(|(pool, is_swap, topics, data)| {
+            let mut topics = topics.into_iter().map(H256).collect::<Vec<_>>();
+            if let (true, Some(topic)) = (is_swap, topics.first_mut()) {
+                *topic = swap_topics[topic.0[0] as usize % swap_topics.len()];
+            }
+            Log {
+                address: Address::from_low_u64_be(pool as u64 % 4),
+                topics,
+                data: data.into(),
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+    for log in &logs {
+        for topic in &swap_topics {
+            let adapter = adapters.by_swap_topic(topic).unwrap();
+            let _ = decode_pool_swaps(
+                adapter,
+                log.address,
+                *topic,
+                &logs,
+                U256::from(token0_decimals),
+            );
+        }
+    }
+});
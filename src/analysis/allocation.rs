@@ -0,0 +1,403 @@
+use super::{backrun_gas, to_decimal};
+use crate::interfaces::{CurvePoint, SimArbResultBatch};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most units that a budget can be split into; the search keeps a table of `resolution + 1` entries per opportunity.
+pub const MAX_RESOLUTION: u64 = 100_000;
+
+#[derive(Clone, Debug)]
+pub struct AllocationOptions {
+    /// Token that the capital is held in. Only arbs quoted in this token are considered.
+    pub profit_token: Address,
+    /// Capital available to the backruns of each block, in the profit token's smallest unit.
+    pub budget: U256,
+    /// Number of equal units that the budget is split into. Amounts in are rounded up to whole units,
+    /// so a higher resolution wastes less of the budget, at the cost of a slower search.
+    pub resolution: u64,
+    /// Gas used per backrun, for backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// Wrapped native token of the chain. Gas is only subtracted from profits in this token.
+    pub native_token: Address,
+}
+
+/// Capital given to one opportunity of a block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Allocation {
+    pub tx_hash: H256,
+    pub start_pool: Address,
+    pub end_pool: Address,
+    /// Amount in that the opportunity was given, in whole tokens.
+    pub amount_in: f64,
+    /// Profit after gas of that amount, in whole tokens.
+    pub profit: f64,
+    /// Amount in of the opportunity's best backrun, in whole tokens.
+    pub optimal_amount_in: f64,
+    /// Profit after gas of its best backrun, in whole tokens.
+    pub optimal_profit: f64,
+}
+
+/// Most profitable split of the budget between the opportunities of one block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockPortfolio {
+    pub block: u64,
+    pub timestamp: u64,
+    /// Number of opportunities still profitable after gas.
+    pub num_opportunities: u64,
+    /// Opportunities given capital, most profitable first.
+    pub allocations: Vec<Allocation>,
+    /// Sum of the allocations' amounts in, in whole tokens.
+    pub capital_used: f64,
+    /// Sum of the allocations' profits, in whole tokens.
+    pub profit: f64,
+    /// Sum of every opportunity's best profit, as if capital were unlimited, in whole tokens.
+    pub unconstrained_profit: f64,
+}
+
+/// Per-block portfolios of one run, under the budget of `AllocationOptions`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocationRun {
+    /// Label of the scan run, if it had one.
+    pub run_label: Option<String>,
+    pub profit_token: Address,
+    /// Capital available in each block, in whole tokens.
+    pub budget: f64,
+    /// Number of blocks whose opportunities needed more than the budget to all be taken at their best.
+    pub num_constrained: u64,
+    /// Sum of the portfolios' profits, in whole tokens.
+    pub profit: f64,
+    /// Sum of every opportunity's best profit, in whole tokens.
+    pub unconstrained_profit: f64,
+    pub blocks: Vec<BlockPortfolio>,
+}
+
+/// One amount in that an opportunity could be given, and its profit after gas.
+struct Choice {
+    amount_in: U256,
+    profit: U256,
+    /// Whole units of the budget that `amount_in` takes.
+    cost: u64,
+}
+
+struct Candidate<'a> {
+    arb: &'a SimArbResultBatch,
+    choices: Vec<Choice>,
+}
+
+/// Amounts in that `arb`'s best backrun could have used: the points of its profit curve (or just its best amount,
+/// for results saved before curves were recorded) that are profitable after `gas_cost`.
+/// Amounts that don't fit in `budget` cost more units than it has.
+fn choices(arb: &SimArbResultBatch, gas_cost: U256, budget: U256, resolution: u64) -> Vec<Choice> {
+    let Some(best) = arb.best_result() else {
+        return vec![];
+    };
+    let trade = &best.backrun_trade;
+    let best_point = CurvePoint {
+        amount_in: trade.amount_in,
+        profit: trade.profit,
+    };
+    let points = match trade.profit_curve.is_empty() {
+        true => vec![best_point],
+        false => trade.profit_curve.clone(),
+    };
+    points
+        .into_iter()
+        .filter(|point| point.profit > gas_cost)
+        .map(|point| {
+            // rounded up, so a portfolio never takes more than the budget; amounts too big to count in units
+            // saturate, and cost more than the budget like any other amount over it
+            let units = point
+                .amount_in
+                .saturating_mul(U256::from(resolution))
+                .saturating_add(budget - 1)
+                / budget;
+            Choice {
+                amount_in: point.amount_in,
+                profit: point.profit - gas_cost,
+                cost: units.min(U256::from(resolution + 1)).as_u64(),
+            }
+        })
+        .collect()
+}
+
+/// Picks at most one of each item's options, as (cost, value), maximizing the summed value of the picks
+/// while their summed cost stays within `capacity`. Returns the index of each item's pick, if it got one.
+fn knapsack(items: &[Vec<(u64, U256)>], capacity: u64) -> Vec<Option<usize>> {
+    let capacity = capacity as usize;
+    // best[c]: the most value that the items so far make with a cost of at most c
+    let mut best = vec![U256::zero(); capacity + 1];
+    let mut picks = vec![];
+    for options in items {
+        let mut next = best.clone();
+        let mut pick = vec![None; capacity + 1];
+        for c in 0..=capacity {
+            for (idx, (cost, value)) in options.iter().enumerate() {
+                let cost = *cost as usize;
+                if cost <= c && best[c - cost] + *value > next[c] {
+                    next[c] = best[c - cost] + *value;
+                    pick[c] = Some(idx);
+                }
+            }
+        }
+        best = next;
+        picks.push(pick);
+    }
+    let mut chosen = vec![None; items.len()];
+    let mut c = capacity;
+    for (item, pick) in picks.iter().enumerate().rev() {
+        if let Some(idx) = pick[c] {
+            chosen[item] = Some(idx);
+            c -= items[item][idx].0 as usize;
+        }
+    }
+    chosen
+}
+
+/// Splits the budget between the opportunities of each block of each run, maximizing the block's profit after gas,
+/// and returns one set of portfolios per run label.
+///
+/// Each opportunity can be given any amount in from its best backrun's profit curve (see `BackrunResult::profit_curve`),
+/// so when the budget doesn't cover every best backrun, some get less than their best amount rather than nothing.
+/// Opportunities aren't assumed to interact, so `arbs` should hold one arb per opportunity (see `read_opportunities`).
+pub fn allocate(arbs: &[SimArbResultBatch], options: &AllocationOptions) -> Vec<AllocationRun> {
+    let mut runs: BTreeMap<Option<String>, BTreeMap<u64, Vec<Candidate>>> = BTreeMap::new();
+    let mut decimals = 18;
+    for arb in arbs {
+        if arb.profit_token != options.profit_token {
+            continue;
+        }
+        let gas_cost = if arb.profit_token == options.native_token {
+            arb.base_fee.unwrap_or_default() * backrun_gas(arb, options.backrun_gas)
+        } else {
+            U256::zero()
+        };
+        let choices = choices(arb, gas_cost, options.budget, options.resolution);
+        if choices.is_empty() {
+            continue;
+        }
+        decimals = arb.profit_decimals;
+        runs.entry(arb.run_label.to_owned())
+            .or_default()
+            .entry(arb.event.block)
+            .or_default()
+            .push(Candidate { arb, choices });
+    }
+
+    let whole = |amount| to_decimal(amount, decimals);
+    runs.into_iter()
+        .map(|(run_label, blocks)| {
+            let mut run = AllocationRun {
+                run_label,
+                profit_token: options.profit_token,
+                budget: whole(options.budget),
+                num_constrained: 0,
+                profit: 0.0,
+                unconstrained_profit: 0.0,
+                blocks: vec![],
+            };
+            for (block, candidates) in blocks {
+                let items = candidates
+                    .iter()
+                    .map(|candidate| {
+                        candidate
+                            .choices
+                            .iter()
+                            .map(|choice| (choice.cost, choice.profit))
+                            .collect()
+                    })
+                    .collect::<Vec<_>>();
+                let picks = knapsack(&items, options.resolution);
+                let mut allocations = vec![];
+                let (mut capital_used, mut profit, mut unconstrained, mut optimal_capital) =
+                    (U256::zero(), U256::zero(), U256::zero(), U256::zero());
+                for (candidate, pick) in candidates.iter().zip(picks) {
+                    // the curve ends at the best backrun, so its last choice is the most profitable
+                    let optimal = candidate.choices.last().expect("candidates have choices");
+                    unconstrained += optimal.profit;
+                    optimal_capital += optimal.amount_in;
+                    let Some(pick) = pick else {
+                        continue;
+                    };
+                    let choice = &candidate.choices[pick];
+                    capital_used += choice.amount_in;
+                    profit += choice.profit;
+                    let trade = &candidate
+                        .arb
+                        .best_result()
+                        .expect("candidates have results")
+                        .backrun_trade;
+                    allocations.push(Allocation {
+                        tx_hash: candidate.arb.event.hint.hash,
+                        start_pool: trade.start_pool,
+                        end_pool: trade.end_pool,
+                        amount_in: whole(choice.amount_in),
+                        profit: whole(choice.profit),
+                        optimal_amount_in: whole(optimal.amount_in),
+                        optimal_profit: whole(optimal.profit),
+                    });
+                }
+                allocations.sort_by(|a, b| b.profit.total_cmp(&a.profit));
+                run.num_constrained += (optimal_capital > options.budget) as u64;
+                run.profit += whole(profit);
+                run.unconstrained_profit += whole(unconstrained);
+                run.blocks.push(BlockPortfolio {
+                    block,
+                    timestamp: candidates[0].arb.event.timestamp,
+                    num_opportunities: candidates.len() as u64,
+                    allocations,
+                    capital_used: whole(capital_used),
+                    profit: whole(profit),
+                    unconstrained_profit: whole(unconstrained),
+                });
+            }
+            run
+        })
+        .collect()
+}
+
+/// Renders each run's summary, followed by a row per block.
+pub fn render_table(runs: &[AllocationRun]) -> String {
+    let mut table = String::new();
+    for run in runs {
+        table.push_str(&format!(
+            "run {}: budget {} {:?} per block\n",
+            run.run_label.as_deref().unwrap_or("(unlabelled)"),
+            run.budget,
+            run.profit_token
+        ));
+        table.push_str(&format!(
+            "  {} blocks with opportunities, {} needing more than the budget\n",
+            run.blocks.len(),
+            run.num_constrained
+        ));
+        table.push_str(&format!(
+            "  allocated profit {:.6} of {:.6} unconstrained ({:.1}%)\n",
+            run.profit,
+            run.unconstrained_profit,
+            if run.unconstrained_profit > 0.0 {
+                run.profit / run.unconstrained_profit * 100.0
+            } else {
+                0.0
+            }
+        ));
+        table.push_str(&format!(
+            "  {:>9} {:>6} {:>7} {:>14} {:>14} {:>14}\n",
+            "block", "opps", "funded", "capital used", "profit", "unconstrained"
+        ));
+        for block in &run.blocks {
+            table.push_str(&format!(
+                "  {:>9} {:>6} {:>7} {:>14.6} {:>14.6} {:>14.6}\n",
+                block.block,
+                block.num_opportunities,
+                block.allocations.len(),
+                block.capital_used,
+                block.profit,
+                block.unconstrained_profit
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::known_wrapped_native, interfaces::SimArbResult};
+
+    fn eth(eth: u64) -> U256 {
+        U256::from(eth) * U256::exp10(18)
+    }
+
+    /// An arb in `block` whose search found each (amount_in, profit) in whole ETH.
+    fn arb(block: u64, hash: u64, curve: &[(u64, u64)]) -> SimArbResultBatch {
+        let (amount_in, profit) = curve[curve.len() - 1];
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.block = block;
+        arb.event.hint.hash = H256::from_low_u64_be(hash);
+        let mut result = SimArbResult::test_example(Address::zero(), eth(amount_in), eth(profit));
+        result.backrun_trade.profit_curve = curve
+            .iter()
+            .map(|(amount_in, profit)| CurvePoint {
+                amount_in: eth(*amount_in),
+                profit: eth(*profit),
+            })
+            .collect();
+        arb.results = vec![result];
+        arb.max_profit = eth(profit);
+        arb
+    }
+
+    #[test]
+    fn it_picks_one_option_per_item() {
+        let items = vec![
+            vec![(2, 3.into()), (5, 5.into())],
+            vec![(4, 3.into())],
+            vec![(3, 4.into()), (6, 6.into())],
+        ];
+        // 2 + 3 units beat any item's biggest option
+        assert_eq!(knapsack(&items, 6), vec![Some(0), None, Some(0)]);
+        assert_eq!(knapsack(&items, 10), vec![Some(0), Some(0), Some(0)]);
+        assert_eq!(knapsack(&items, 1), vec![None, None, None]);
+        assert_eq!(knapsack(&[], 10), vec![]);
+    }
+
+    #[test]
+    fn it_splits_the_budget_between_a_blocks_opportunities() {
+        let weth = known_wrapped_native(1).unwrap();
+        let mut arbs = vec![
+            // 10 ETH in makes 4, but 4 ETH already makes 3
+            arb(1, 1, &[(4, 3), (10, 4)]),
+            arb(1, 2, &[(2, 1), (6, 5)]),
+            // more than the budget
+            arb(2, 3, &[(11, 5)]),
+            arb(2, 4, &[(5, 1)]),
+        ];
+        // saved before curves were recorded
+        arbs[2].results[0].backrun_trade.profit_curve.clear();
+        let options = AllocationOptions {
+            profit_token: weth,
+            budget: eth(10),
+            resolution: 100,
+            backrun_gas: 0,
+            native_token: weth,
+        };
+        let runs = allocate(&arbs, &options);
+        assert_eq!(runs.len(), 1);
+        let run = &runs[0];
+        assert_eq!(run.blocks.len(), 2);
+        assert_eq!(run.num_constrained, 2);
+
+        // 6 ETH to the second arb, and the 4 left to the first
+        let block = &run.blocks[0];
+        assert_eq!(block.num_opportunities, 2);
+        assert_eq!(block.capital_used, 10.0);
+        assert_eq!(block.profit, 8.0);
+        assert_eq!(block.unconstrained_profit, 9.0);
+        assert_eq!(block.allocations[0].tx_hash, H256::from_low_u64_be(2));
+        assert_eq!(block.allocations[1].amount_in, 4.0);
+        assert_eq!(block.allocations[1].optimal_amount_in, 10.0);
+
+        // the 11 ETH backrun doesn't fit at all
+        let block = &run.blocks[1];
+        assert_eq!(block.num_opportunities, 2);
+        assert_eq!(block.allocations.len(), 1);
+        assert_eq!(block.profit, 1.0);
+        assert_eq!(block.unconstrained_profit, 6.0);
+        assert_eq!(run.profit, 9.0);
+        assert!(render_table(&runs).contains("needing more than the budget"));
+    }
+
+    #[test]
+    fn it_prices_huge_amounts_over_the_budget() {
+        let mut arb = arb(1, 1, &[(1, 1)]);
+        arb.results[0].backrun_trade.profit_curve[0].amount_in = U256::MAX;
+        let choices = choices(&arb, U256::zero(), eth(10), 100);
+        assert_eq!(choices.len(), 1);
+        assert_eq!(choices[0].cost, 101);
+    }
+}
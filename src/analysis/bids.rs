@@ -0,0 +1,277 @@
+use super::{backrun_gas, to_decimal};
+use crate::{interfaces::SimArbResultBatch, util::apply_share};
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Realized backruns a route needs before its own bids are used, rather than those of every route.
+pub const MIN_ROUTE_SAMPLES: usize = 3;
+
+/// Share of `arb`'s simulated profit that the backrun that landed after it paid away to win, from 0 to 1:
+/// the simulated profit less what the backrun's sender & contract kept, relative to the simulated profit.
+///
+/// What a backrun keeps is measured before gas, so this counts the bribe (a coinbase transfer, or anything else
+/// it paid out of the profit token) and priority fees paid in the token, but not priority fees paid as gas.
+/// Backruns that kept more than was simulated paid nothing; those that lost paid everything.
+/// None if `arb` wasn't profitable, or no backrun of it was found.
+pub fn bid_share(arb: &SimArbResultBatch) -> Option<f64> {
+    let backrun = arb.realized.as_ref()?.backrun.as_ref()?;
+    if arb.max_profit.is_zero() {
+        return None;
+    }
+    let simulated = to_decimal(arb.max_profit, arb.profit_decimals);
+    let kept = match backrun.profit.is_negative() {
+        true => 0.0,
+        false => to_decimal(backrun.profit.into_raw(), arb.profit_decimals),
+    };
+    Some((1.0 - kept / simulated).clamp(0.0, 1.0))
+}
+
+/// Median of `values`, which mustn't be empty.
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+/// Bids paid to win the opportunities of one route.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteBid {
+    pub start_pool: Address,
+    pub end_pool: Address,
+    /// Number of realized backruns that the bid is estimated from.
+    pub num_backruns: usize,
+    /// Median share of simulated profit paid to win (see `bid_share`).
+    pub bid_share: f64,
+}
+
+/// Required bids per route, estimated from the realized backruns of checked arbs (see `realized`).
+///
+/// Routes are keyed by the pools of each arb's best backrun, so arbs saved without results (e.g. in postgres)
+/// only count towards the bid of every route.
+#[derive(Clone, Debug, Default)]
+pub struct BidModel {
+    routes: BTreeMap<(Address, Address), RouteBid>,
+    /// Median bid share of every realized backrun, for routes with too few of their own.
+    overall: Option<f64>,
+    min_samples: usize,
+}
+
+impl BidModel {
+    /// Estimates the bid of each route of `arbs`. Routes with fewer than `min_samples` realized backruns
+    /// are priced at the median of every route.
+    pub fn estimate(arbs: &[SimArbResultBatch], min_samples: usize) -> Self {
+        let mut routes: BTreeMap<(Address, Address), Vec<f64>> = BTreeMap::new();
+        let mut all = vec![];
+        for arb in arbs {
+            let Some(share) = bid_share(arb) else {
+                continue;
+            };
+            all.push(share);
+            if let Some(result) = arb.best_result() {
+                let trade = &result.backrun_trade;
+                routes
+                    .entry((trade.start_pool, trade.end_pool))
+                    .or_default()
+                    .push(share);
+            }
+        }
+        Self {
+            routes: routes
+                .into_iter()
+                .map(|((start_pool, end_pool), mut shares)| {
+                    let bid = RouteBid {
+                        start_pool,
+                        end_pool,
+                        num_backruns: shares.len(),
+                        bid_share: median(&mut shares),
+                    };
+                    ((start_pool, end_pool), bid)
+                })
+                .collect(),
+            overall: (!all.is_empty()).then(|| median(&mut all)),
+            min_samples,
+        }
+    }
+
+    /// Whether no realized backruns were found to estimate bids from.
+    pub fn is_empty(&self) -> bool {
+        self.overall.is_none()
+    }
+
+    /// Routes with enough realized backruns for their own bid, most backruns first.
+    pub fn routes(&self) -> Vec<&RouteBid> {
+        let mut routes = self
+            .routes
+            .values()
+            .filter(|bid| bid.num_backruns >= self.min_samples)
+            .collect::<Vec<_>>();
+        routes.sort_by_key(|bid| std::cmp::Reverse(bid.num_backruns));
+        routes
+    }
+
+    /// Bid of the route of `arb`'s best backrun, if it has enough realized backruns for its own.
+    pub fn route_bid(&self, arb: &SimArbResultBatch) -> Option<&RouteBid> {
+        let trade = &arb.best_result()?.backrun_trade;
+        self.routes
+            .get(&(trade.start_pool, trade.end_pool))
+            .filter(|bid| bid.num_backruns >= self.min_samples)
+    }
+
+    /// Share of `arb`'s profit that winning it is expected to take: its route's bid,
+    /// or the bid of every route if its route has too few backruns. None if no bids are known.
+    pub fn share_for(&self, arb: &SimArbResultBatch) -> Option<f64> {
+        self.route_bid(arb)
+            .map(|bid| bid.bid_share)
+            .or(self.overall)
+    }
+}
+
+/// Profit of arbs in the chain's wrapped native token that could have been captured after paying to win them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturableSummary {
+    pub profit_token: Address,
+    /// Number of profitable arbs.
+    pub num_opportunities: u64,
+    /// Number of those priced with their own route's bid, rather than every route's.
+    pub num_route_priced: u64,
+    /// Sum of max profits, in whole tokens.
+    pub gross_profit: f64,
+    /// Estimated gas of the arbs still worth sending after bids & gas, in whole tokens.
+    pub gas_cost: f64,
+    /// Estimated bids of those arbs, in whole tokens.
+    pub bids: f64,
+    /// Their profit after bids & gas, in whole tokens.
+    pub capturable_profit: f64,
+}
+
+/// Sums the profit of `arbs` that could have been captured: each arb's profit, less the bid that `bids` expects
+/// its route to take and its gas (the gas recorded with it, else `default_gas`) at its base fee.
+/// Arbs whose bid & gas take all of their profit aren't worth sending, so they capture nothing.
+///
+/// Gas can only be subtracted from profits in the chain's wrapped native token, so only those are summed.
+/// None if `bids` has no bids, or no arb made a profit in the native token.
+pub fn capturable_summary(
+    arbs: &[SimArbResultBatch],
+    bids: &BidModel,
+    default_gas: u64,
+    native_token: Address,
+) -> Option<CapturableSummary> {
+    let (mut num_opportunities, mut num_route_priced) = (0, 0);
+    let (mut gross_profit, mut gas_cost, mut bid_cost, mut capturable) =
+        (U256::zero(), U256::zero(), U256::zero(), U256::zero());
+    for arb in arbs {
+        if arb.profit_token != native_token || arb.max_profit.is_zero() {
+            continue;
+        }
+        let share = bids.share_for(arb)?;
+        num_opportunities += 1;
+        num_route_priced += bids.route_bid(arb).is_some() as u64;
+        gross_profit += arb.max_profit;
+        let bid = apply_share(arb.max_profit, share);
+        let gas = arb.base_fee.unwrap_or_default() * backrun_gas(arb, default_gas);
+        if arb.max_profit > bid + gas {
+            gas_cost += gas;
+            bid_cost += bid;
+            capturable += arb.max_profit - bid - gas;
+        }
+    }
+    if num_opportunities == 0 {
+        return None;
+    }
+    Some(CapturableSummary {
+        profit_token: native_token,
+        num_opportunities,
+        num_route_priced,
+        gross_profit: to_decimal(gross_profit, 18),
+        gas_cost: to_decimal(gas_cost, 18),
+        bids: to_decimal(bid_cost, 18),
+        capturable_profit: to_decimal(capturable, 18),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::known_wrapped_native;
+    use crate::interfaces::{Realized, RealizedBackrun, SimArbResult};
+    use ethers::types::{H256, I256};
+
+    /// An arb of `simulated` milli-ETH on the route from pool `start`, won by a backrun that kept `kept`.
+    fn arb(start: u64, simulated: u64, kept: Option<i64>) -> SimArbResultBatch {
+        let profit = U256::from(simulated) * U256::exp10(15);
+        let mut arb = SimArbResultBatch::test_example();
+        arb.results = vec![SimArbResult::test_example(Address::zero(), profit, profit)];
+        arb.results[0].backrun_trade.start_pool = Address::from_low_u64_be(start);
+        arb.max_profit = profit;
+        arb.base_fee = Some(U256::from(10_000_000_000u64)); // 10 gwei
+        arb.realized = Some(Realized {
+            backrun: kept.map(|kept| RealizedBackrun {
+                tx_hash: H256::zero(),
+                block: 9002,
+                from: Address::zero(),
+                to: None,
+                profit: I256::from(kept) * I256::exp10(15),
+                fee_recipient: None,
+                extra_data: None,
+            }),
+        });
+        arb
+    }
+
+    #[test]
+    fn it_measures_bid_shares() {
+        assert_eq!(bid_share(&arb(0xa, 100, Some(10))), Some(0.9));
+        // kept more than simulated, or lost money
+        assert_eq!(bid_share(&arb(0xa, 100, Some(150))), Some(0.0));
+        assert_eq!(bid_share(&arb(0xa, 100, Some(-5))), Some(1.0));
+        // nobody backran it, or there was nothing to win
+        assert_eq!(bid_share(&arb(0xa, 100, None)), None);
+        assert_eq!(bid_share(&arb(0xa, 0, Some(1))), None);
+    }
+
+    #[test]
+    fn it_prices_routes_by_their_own_bids() {
+        let arbs = vec![
+            // route a: 3 backruns paying 90%, 80% & 50%
+            arb(0xa, 100, Some(10)),
+            arb(0xa, 100, Some(20)),
+            arb(0xa, 100, Some(50)),
+            // route b: a single backrun paying everything
+            arb(0xb, 100, Some(0)),
+        ];
+        let bids = BidModel::estimate(&arbs, 3);
+        assert_eq!(bids.routes().len(), 1);
+        assert_eq!(bids.routes()[0].num_backruns, 3);
+        assert_eq!(bids.share_for(&arbs[0]), Some(0.8));
+        // too few of its own, so priced like every route: the median of 0.5, 0.8, 0.9 & 1.0
+        assert_eq!(bids.route_bid(&arbs[3]), None);
+        assert!((bids.share_for(&arbs[3]).unwrap() - 0.85).abs() < 1e-12);
+        assert!(BidModel::estimate(&[arb(0xa, 100, None)], 3).is_empty());
+
+        let weth = known_wrapped_native(1).unwrap();
+        // 20 milli-ETH on route a, and 1 milli-ETH on route c (bid at 85%), which its gas (3 milli-ETH) takes
+        let summary = capturable_summary(
+            &[arb(0xa, 20, None), arb(0xc, 1, None)],
+            &bids,
+            300_000,
+            weth,
+        )
+        .unwrap();
+        assert_eq!(summary.num_opportunities, 2);
+        assert_eq!(summary.num_route_priced, 1);
+        assert!((summary.gross_profit - 0.021).abs() < 1e-12);
+        assert!((summary.bids - 0.016).abs() < 1e-12);
+        assert!((summary.gas_cost - 0.003).abs() < 1e-12);
+        assert!((summary.capturable_profit - 0.001).abs() < 1e-12);
+        assert_eq!(
+            capturable_summary(&arbs, &BidModel::default(), 300_000, weth),
+            None
+        );
+    }
+}
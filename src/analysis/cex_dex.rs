@@ -0,0 +1,329 @@
+use super::{stats::format_timestamp, to_decimal};
+use crate::data::{
+    cex::{exchange_symbol, CexPriceStore},
+    tokens::TokenMetadata,
+};
+use crate::interfaces::{SimArbResultBatch, UserTradeParams};
+use ethers::types::{Address, H256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How the DEX price after a user's trade compares with the CEX price, and whether it could be captured on-chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DislocationKind {
+    /// A profitable atomic arb, while the DEX price stayed within the spread threshold of the CEX price.
+    /// The arb corrects a gap between DEX pools, not against the market.
+    Atomic,
+    /// The DEX price moved past the spread threshold from the CEX price, but no atomic arb was profitable.
+    /// Only a searcher hedging on the exchange could capture it.
+    CexDex,
+    /// A profitable atomic arb, and a DEX price past the spread threshold.
+    Both,
+    /// Neither profitable nor dislocated.
+    Neither,
+    /// The pair's CEX price (or its tokens' decimals) wasn't known at the time.
+    Unpriced,
+}
+
+impl std::fmt::Display for DislocationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DislocationKind::Atomic => write!(f, "atomic"),
+            DislocationKind::CexDex => write!(f, "cex-dex"),
+            DislocationKind::Both => write!(f, "both"),
+            DislocationKind::Neither => write!(f, "neither"),
+            DislocationKind::Unpriced => write!(f, "unpriced"),
+        }
+    }
+}
+
+/// DEX-vs-CEX spread of the pair that one arb's user traded, at the arb's block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DislocationRow {
+    pub block: u64,
+    pub timestamp: u64,
+    pub tx_hash: H256,
+    pub token: Address,
+    pub quote: Address,
+    /// Exchange symbols of the pair, as TOKEN/QUOTE (e.g. ETH/USDC for WETH quoted in USDC).
+    pub pair: String,
+    /// Quote per token in the user's pool right after the user's trade.
+    pub dex_price: Option<f64>,
+    /// Quote per token on the exchange, as of the block's timestamp.
+    pub cex_price: Option<f64>,
+    /// Gap from the CEX price to the DEX price, in basis points of the CEX price.
+    /// Positive when the token is dearer on the DEX.
+    pub spread_bps: Option<f64>,
+    /// The arb's max profit, in whole profit tokens.
+    pub atomic_profit: f64,
+    pub profit_token: Address,
+    pub kind: DislocationKind,
+}
+
+/// A row per arb with results, and the number of arbs of each kind.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DislocationReport {
+    pub min_spread_bps: f64,
+    pub counts: BTreeMap<DislocationKind, usize>,
+    pub rows: Vec<DislocationRow>,
+}
+
+/// Price of `trade`'s pool after the user's trade, as quote per token.
+///
+/// `UserTradeParams::price` is token1 (in its smallest unit) per whole token0, so it's scaled by token1's decimals.
+pub fn dex_price(trade: &UserTradeParams, token_decimals: u32, quote_decimals: u32) -> Option<f64> {
+    if trade.price.is_zero() {
+        return None;
+    }
+    if trade.token0_is_quote {
+        // token per quote
+        let inverse = to_decimal(trade.price, token_decimals);
+        (inverse > 0.0).then(|| 1.0 / inverse)
+    } else {
+        Some(to_decimal(trade.price, quote_decimals))
+    }
+}
+
+/// The user trade that an arb's spread is measured on: that of its best result, or of its first if none made a profit.
+pub fn user_trade(arb: &SimArbResultBatch) -> Option<&UserTradeParams> {
+    arb.best_result()
+        .or(arb.results.first())
+        .map(|result| &result.user_trade)
+}
+
+/// Exchange symbols of an arb's (token, quote), if both tokens' metadata is known.
+pub fn exchange_symbols<'a>(
+    arb: &SimArbResultBatch,
+    token_metadata: impl Fn(&Address) -> Option<&'a TokenMetadata>,
+) -> Option<(String, String)> {
+    let trade = user_trade(arb)?;
+    let token = token_metadata(&trade.tokens.token)?;
+    let quote = token_metadata(&trade.tokens.quote)?;
+    Some((
+        exchange_symbol(&token.symbol),
+        exchange_symbol(&quote.symbol),
+    ))
+}
+
+/// Compares the DEX price that each of `arbs`' user trades left their pool at with the CEX price at the arb's block,
+/// classifying each arb by whether it was profitable on-chain and whether the spread reached `min_spread_bps`.
+///
+/// CEX prices older than `max_age` seconds at the block's timestamp don't count. Arbs without results are skipped.
+pub fn dislocation_report<'a>(
+    arbs: &[SimArbResultBatch],
+    token_metadata: impl Fn(&Address) -> Option<&'a TokenMetadata>,
+    cex: &CexPriceStore,
+    min_spread_bps: f64,
+    max_age: u64,
+) -> DislocationReport {
+    let mut rows = vec![];
+    for arb in arbs {
+        let trade = match user_trade(arb) {
+            Some(trade) => trade,
+            None => continue,
+        };
+        let (token, quote) = (
+            token_metadata(&trade.tokens.token),
+            token_metadata(&trade.tokens.quote),
+        );
+        let symbol = |metadata: Option<&TokenMetadata>, address: &Address| {
+            metadata
+                .map(|metadata| exchange_symbol(&metadata.symbol))
+                .unwrap_or(format!("{:?}", address))
+        };
+        let (token_symbol, quote_symbol) = (
+            symbol(token, &trade.tokens.token),
+            symbol(quote, &trade.tokens.quote),
+        );
+        let dex_price = match (token, quote) {
+            (Some(token), Some(quote)) => {
+                dex_price(trade, token.decimals as u32, quote.decimals as u32)
+            }
+            _ => None,
+        };
+        let cex_price = cex.price(&token_symbol, &quote_symbol, arb.event.timestamp, max_age);
+        let spread_bps = match (dex_price, cex_price) {
+            (Some(dex_price), Some(cex_price)) => Some((dex_price / cex_price - 1.0) * 10_000.0),
+            _ => None,
+        };
+        let profitable = !arb.max_profit.is_zero();
+        let kind = match spread_bps.map(|spread| spread.abs() >= min_spread_bps) {
+            None => DislocationKind::Unpriced,
+            Some(true) if profitable => DislocationKind::Both,
+            Some(true) => DislocationKind::CexDex,
+            Some(false) if profitable => DislocationKind::Atomic,
+            Some(false) => DislocationKind::Neither,
+        };
+        rows.push(DislocationRow {
+            block: arb.event.block,
+            timestamp: arb.event.timestamp,
+            tx_hash: arb.event.hint.hash,
+            token: trade.tokens.token,
+            quote: trade.tokens.quote,
+            pair: format!("{}/{}", token_symbol, quote_symbol),
+            dex_price,
+            cex_price,
+            spread_bps,
+            atomic_profit: to_decimal(arb.max_profit, arb.profit_decimals),
+            profit_token: arb.profit_token,
+            kind,
+        });
+    }
+    rows.sort_by_key(|row| (row.block, row.tx_hash));
+    let mut counts = BTreeMap::new();
+    for row in &rows {
+        *counts.entry(row.kind).or_insert(0) += 1;
+    }
+    DislocationReport {
+        min_spread_bps,
+        counts,
+        rows,
+    }
+}
+
+fn format_optional(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|value| format!("{:.*}", precision, value))
+        .unwrap_or("-".to_owned())
+}
+
+/// Renders the number of arbs of each kind, followed by a row per arb.
+pub fn render_table(report: &DislocationReport) -> String {
+    let mut table = format!(
+        "spread threshold: {} bps\n{}\n\n",
+        report.min_spread_bps,
+        report
+            .counts
+            .iter()
+            .map(|(kind, count)| format!("{}: {}", kind, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    table.push_str(&format!(
+        "{:<17} {:>9} {:<16} {:>16} {:>16} {:>10} {:>14} {:<9} {}\n",
+        "time",
+        "block",
+        "pair",
+        "dex price",
+        "cex price",
+        "spread bp",
+        "atomic profit",
+        "kind",
+        "tx"
+    ));
+    for row in &report.rows {
+        table.push_str(&format!(
+            "{:<17} {:>9} {:<16} {:>16} {:>16} {:>10} {:>14.6} {:<9} {:?}\n",
+            format_timestamp(row.timestamp),
+            row.block,
+            row.pair,
+            format_optional(row.dex_price, 6),
+            format_optional(row.cex_price, 6),
+            format_optional(row.spread_bps, 1),
+            row.atomic_profit,
+            row.kind.to_string(),
+            row.tx_hash
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cex::CexPricePoint;
+    use crate::interfaces::SimArbResult;
+    use ethers::types::U256;
+    use std::collections::HashMap;
+
+    /// An arb on a WETH/USDC pool (token0 = USDC) whose price was left at `weth_price` USDC per WETH.
+    fn usdc_arb(block: u64, weth_price: f64, profit: u64) -> SimArbResultBatch {
+        let (usdc, weth) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut result = SimArbResult::test_example(usdc, 1.into(), profit.into());
+        result.user_trade.tokens.quote = usdc;
+        result.user_trade.tokens.token = weth;
+        result.user_trade.token0_is_quote = true;
+        // WETH (in wei) per whole USDC
+        result.user_trade.price = U256::from((1e18 / weth_price) as u128);
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.block = block;
+        arb.event.timestamp = block * 12;
+        arb.event.hint.hash = H256::from_low_u64_be(block);
+        arb.profit_token = usdc;
+        arb.profit_decimals = 6;
+        arb.max_profit = profit.into();
+        arb.results = vec![result];
+        arb
+    }
+
+    fn metadata() -> HashMap<Address, TokenMetadata> {
+        [(1, "USDC", 6), (2, "WETH", 18)]
+            .into_iter()
+            .map(|(address, symbol, decimals)| {
+                let address = Address::from_low_u64_be(address);
+                let metadata = TokenMetadata {
+                    address,
+                    symbol: symbol.to_owned(),
+                    decimals,
+                };
+                (address, metadata)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_prices_pools_in_quote_per_token() {
+        let arb = usdc_arb(1, 2000.0, 0);
+        let price = dex_price(&arb.results[0].user_trade, 18, 6).unwrap();
+        assert!((price - 2000.0).abs() < 1e-6);
+
+        let mut trade = arb.results[0].user_trade.clone();
+        trade.token0_is_quote = false;
+        trade.price = U256::from(2_000_000_000u64);
+        assert!((dex_price(&trade, 18, 6).unwrap() - 2000.0).abs() < 1e-9);
+        trade.price = U256::zero();
+        assert_eq!(dex_price(&trade, 18, 6), None);
+    }
+
+    #[test]
+    fn it_classifies_dislocations() {
+        let arbs = vec![
+            usdc_arb(1, 2000.0, 1_000_000),
+            usdc_arb(2, 2010.0, 0),
+            usdc_arb(3, 1980.0, 1_000_000),
+            usdc_arb(4, 2000.5, 0),
+            usdc_arb(1000, 2000.0, 0),
+        ];
+        let cex = CexPriceStore::from_points(
+            (1..=4)
+                .map(|block| CexPricePoint {
+                    pair: "ETH/USDC".to_owned(),
+                    timestamp: block * 12,
+                    price: 2000.0,
+                })
+                .collect(),
+        );
+        let metadata = metadata();
+        let report = dislocation_report(&arbs, |token| metadata.get(token), &cex, 10.0, 60);
+        let kinds = report.rows.iter().map(|row| row.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                DislocationKind::Atomic,
+                DislocationKind::CexDex,
+                DislocationKind::Both,
+                DislocationKind::Neither,
+                // the last CEX price is too old by then
+                DislocationKind::Unpriced,
+            ]
+        );
+        assert_eq!(report.rows[0].pair, "ETH/USDC");
+        assert!((report.rows[1].spread_bps.unwrap() - 50.0).abs() < 1e-3);
+        assert!((report.rows[2].spread_bps.unwrap() + 100.0).abs() < 1e-3);
+        assert_eq!(report.counts[&DislocationKind::Atomic], 1);
+        assert!(render_table(&report).contains("cex-dex"));
+    }
+}
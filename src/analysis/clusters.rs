@@ -0,0 +1,187 @@
+use super::to_decimal;
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::{Address, H256, U256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// (block, profit token, start pool, end pool) of an event's best backrun.
+type OpportunityKey = (u64, Address, Address, Address);
+
+/// One price dislocation, exposed by one or more events in the same block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Opportunity {
+    pub block: u64,
+    pub profit_token: Address,
+    /// Token traded against `profit_token`.
+    pub token: Address,
+    pub start_pool: Address,
+    pub end_pool: Address,
+    /// Hash of the event whose backrun was the most profitable, which stands in for the whole cluster.
+    pub canonical_tx: H256,
+    /// Hashes of every event in the cluster, canonical first.
+    pub tx_hashes: Vec<H256>,
+    /// Profit of the canonical event's backrun, in whole profit tokens.
+    pub profit: f64,
+    /// Sum of every event's profit, which is what the opportunity counted for before clustering.
+    pub summed_profit: f64,
+}
+
+/// Returns the cluster that `arb` belongs to, if its best backrun is profitable.
+///
+/// Events cluster when their best backruns trade through the same pools, in the same direction, in the same block:
+/// backrunning any one of them captures the dislocation that the others exposed.
+fn opportunity_key(arb: &SimArbResultBatch) -> Option<OpportunityKey> {
+    match arb.best_result() {
+        Some(best) if !best.backrun_trade.profit.is_zero() => Some((
+            arb.event.block,
+            arb.profit_token,
+            best.backrun_trade.start_pool,
+            best.backrun_trade.end_pool,
+        )),
+        _ => None,
+    }
+}
+
+fn best_profit(arb: &SimArbResultBatch) -> U256 {
+    arb.best_result()
+        .map(|best| best.backrun_trade.profit)
+        .unwrap_or_default()
+}
+
+/// Groups the indices of profitable `arbs` by cluster, with each cluster's most profitable arb first.
+fn clusters(arbs: &[SimArbResultBatch]) -> BTreeMap<OpportunityKey, Vec<usize>> {
+    let mut clusters: BTreeMap<OpportunityKey, Vec<usize>> = BTreeMap::new();
+    for (idx, arb) in arbs.iter().enumerate() {
+        if let Some(key) = opportunity_key(arb) {
+            clusters.entry(key).or_default().push(idx);
+        }
+    }
+    for members in clusters.values_mut() {
+        // stable, so ties keep the first event
+        members.sort_by(|a, b| best_profit(&arbs[*b]).cmp(&best_profit(&arbs[*a])));
+    }
+    clusters
+}
+
+/// Clusters the profitable events of `arbs` into opportunities, in block order.
+pub fn cluster_opportunities(arbs: &[SimArbResultBatch]) -> Vec<Opportunity> {
+    clusters(arbs)
+        .into_iter()
+        .map(|((block, profit_token, start_pool, end_pool), members)| {
+            let canonical = &arbs[members[0]];
+            let decimals = canonical.profit_decimals;
+            Opportunity {
+                block,
+                profit_token,
+                token: canonical
+                    .best_result()
+                    .map(|best| best.user_trade.tokens.token)
+                    .unwrap_or_default(),
+                start_pool,
+                end_pool,
+                canonical_tx: canonical.event.hint.hash,
+                tx_hashes: members
+                    .iter()
+                    .map(|idx| arbs[*idx].event.hint.hash)
+                    .collect(),
+                profit: to_decimal(best_profit(canonical), decimals),
+                summed_profit: to_decimal(
+                    members
+                        .iter()
+                        .fold(U256::zero(), |sum, idx| sum + best_profit(&arbs[*idx])),
+                    decimals,
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Drops every profitable arb that isn't its cluster's canonical event, so each opportunity's profit counts once.
+/// Unprofitable arbs are kept. Order is preserved.
+pub fn dedupe_arbs(arbs: Vec<SimArbResultBatch>) -> Vec<SimArbResultBatch> {
+    let mut duplicate = vec![false; arbs.len()];
+    for members in clusters(&arbs).values() {
+        for idx in &members[1..] {
+            duplicate[*idx] = true;
+        }
+    }
+    arbs.into_iter()
+        .zip(duplicate)
+        .filter_map(|(arb, duplicate)| (!duplicate).then_some(arb))
+        .collect()
+}
+
+/// Renders `opportunities` as a plain-text table.
+pub fn render_table(opportunities: &[Opportunity]) -> String {
+    let mut table = format!(
+        "{:>10} {:<42} {:<42} {:<42} {:>6} {:>14} {:>14}\n",
+        "block", "profit token", "start pool", "end pool", "events", "profit", "summed profit"
+    );
+    for opp in opportunities {
+        table.push_str(&format!(
+            "{:>10} {:<42} {:<42} {:<42} {:>6} {:>14.6} {:>14.6}\n",
+            opp.block,
+            format!("{:?}", opp.profit_token),
+            format!("{:?}", opp.start_pool),
+            format!("{:?}", opp.end_pool),
+            opp.tx_hashes.len(),
+            opp.profit,
+            opp.summed_profit,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    fn arb(hash: u64, block: u64, start_pool: u64, end_pool: u64, milli: u64) -> SimArbResultBatch {
+        let profit = U256::from(milli) * U256::exp10(15);
+        let mut result = SimArbResult::test_example(Address::zero(), U256::exp10(18), profit);
+        result.backrun_trade.start_pool = Address::from_low_u64_be(start_pool);
+        result.backrun_trade.end_pool = Address::from_low_u64_be(end_pool);
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.hint.hash = H256::from_low_u64_be(hash);
+        arb.event.block = block;
+        arb.results = vec![result];
+        arb.max_profit = profit;
+        arb
+    }
+
+    #[test]
+    fn it_clusters_events_exposing_the_same_dislocation() {
+        let arbs = vec![
+            arb(1, 100, 0xa, 0xb, 10),
+            arb(2, 100, 0xa, 0xb, 30),
+            arb(3, 100, 0xa, 0xb, 20),
+            // opposite direction
+            arb(4, 100, 0xb, 0xa, 5),
+            // next block
+            arb(5, 101, 0xa, 0xb, 10),
+            // unprofitable
+            arb(6, 100, 0xa, 0xb, 0),
+        ];
+        let opportunities = cluster_opportunities(&arbs);
+        assert_eq!(opportunities.len(), 3);
+        let first = &opportunities[0];
+        assert_eq!(first.canonical_tx, H256::from_low_u64_be(2));
+        assert_eq!(
+            first.tx_hashes,
+            [2, 3, 1].map(H256::from_low_u64_be).to_vec()
+        );
+        assert!((first.profit - 0.03).abs() < 1e-12);
+        assert!((first.summed_profit - 0.06).abs() < 1e-12);
+
+        let deduped = dedupe_arbs(arbs);
+        assert_eq!(
+            deduped
+                .iter()
+                .map(|arb| arb.event.hint.hash.to_low_u64_be())
+                .collect::<Vec<_>>(),
+            vec![2, 4, 5, 6]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use super::to_decimal;
+use crate::{
+    hindsight::Hindsight,
+    interfaces::SimArbResultBatch,
+    sim::pools::{UNISWAP_V2_SWAP_TOPIC, UNISWAP_V3_SWAP_TOPIC},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Bytes, Log, H256, U256},
+};
+use mev_share_sse::{EventHistory, EventTransaction, EventTransactionLog};
+use serde::Serialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// `swap(uint256,uint256,address,bytes)` of Uniswap V2 pairs.
+const UNISWAP_V2_SWAP_SELECTOR: [u8; 4] = [0x02, 0x2c, 0x0d, 0x9f];
+/// `swap(address,bool,int256,uint160,bytes)` of Uniswap V3 pools.
+const UNISWAP_V3_SWAP_SELECTOR: [u8; 4] = [0x12, 0x8a, 0xcb, 0x08];
+
+/// How much of a landed tx a searcher is assumed to have seen, from most to least.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HintLevel {
+    /// Every swap log the tx emitted (read from its receipt), as if the originator had shared all logs.
+    Full,
+    /// The event as it was shared on MEV-Share.
+    Hinted,
+    /// The event's logs stripped, leaving its txs' targets, selectors & calldata (if shared).
+    NoLogs,
+    /// Only its txs' function selectors.
+    SelectorOnly,
+}
+
+pub const HINT_LEVELS: [HintLevel; 4] = [
+    HintLevel::Full,
+    HintLevel::Hinted,
+    HintLevel::NoLogs,
+    HintLevel::SelectorOnly,
+];
+
+impl std::fmt::Display for HintLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HintLevel::Full => write!(f, "full"),
+            HintLevel::Hinted => write!(f, "hinted"),
+            HintLevel::NoLogs => write!(f, "no logs"),
+            HintLevel::SelectorOnly => write!(f, "selector"),
+        }
+    }
+}
+
+/// What one level's re-simulation of a tx found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelOutcome {
+    pub level: HintLevel,
+    /// (profit token, decimals, max profit), or None if the sim failed.
+    pub profit: Option<(Address, u32, U256)>,
+}
+
+/// Profit found at one level in one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelProfit {
+    pub profit_token: Address,
+    /// Sum of max profits, in whole tokens.
+    pub profit: f64,
+    /// `profit` as a share of the profit found with full information, if any was.
+    pub retained: Option<f64>,
+}
+
+/// How many of the sampled txs' arbs a searcher would've found at one hint level.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelSummary {
+    pub level: HintLevel,
+    pub num_simulated: u64,
+    /// Number of txs whose re-simulation failed, e.g. because the user's tx reverted on the fork.
+    pub num_failed: u64,
+    pub num_profitable: u64,
+    /// `num_profitable` as a share of the txs profitable with full information (0 to 1), if any were.
+    pub findability: Option<f64>,
+    pub profits: Vec<LevelProfit>,
+}
+
+/// Swap logs that a searcher can tell from `txs` without their logs: those of txs that call a Uniswap pool's `swap`
+/// directly. The synthesized logs only carry the pool & swap topic, which is all the sim reads from hints.
+///
+/// Router calls (which need their calldata decoded) aren't recognized, so this is a lower bound.
+pub fn direct_pool_swap_logs(txs: &[EventTransaction]) -> Vec<EventTransactionLog> {
+    txs.iter()
+        .filter_map(|tx| {
+            let topic = match tx.function_selector.as_ref()?.0 {
+                UNISWAP_V2_SWAP_SELECTOR => UNISWAP_V2_SWAP_TOPIC,
+                UNISWAP_V3_SWAP_SELECTOR => UNISWAP_V3_SWAP_TOPIC,
+                _ => return None,
+            };
+            Some(EventTransactionLog {
+                address: tx.to?,
+                topics: vec![H256::from_str(topic).ok()?],
+                data: Bytes::default(),
+            })
+        })
+        .collect()
+}
+
+/// Reduces `event` to what a searcher would've seen at `level`. `receipt_logs` are the tx's logs, for `HintLevel::Full`.
+pub fn reduce_hint(event: &EventHistory, level: HintLevel, receipt_logs: &[Log]) -> EventHistory {
+    let mut event = event.to_owned();
+    match level {
+        HintLevel::Full => {
+            event.hint.logs = receipt_logs
+                .iter()
+                .map(|log| EventTransactionLog {
+                    address: log.address,
+                    topics: log.topics.to_owned(),
+                    data: log.data.to_owned(),
+                })
+                .collect();
+        }
+        HintLevel::Hinted => {}
+        HintLevel::NoLogs => {
+            event.hint.logs = direct_pool_swap_logs(&event.hint.txs);
+        }
+        HintLevel::SelectorOnly => {
+            for tx in &mut event.hint.txs {
+                tx.to = None;
+                tx.calldata = None;
+            }
+            // without a target, not even a direct pool call tells which pool was traded
+            event.hint.logs = vec![];
+        }
+    }
+    event
+}
+
+/// Re-simulates the tx of `arb` with its hint reduced to each of `HINT_LEVELS`.
+///
+/// Returns None if the tx or its receipt can't be found.
+pub async fn simulate_levels(
+    hindsight: &Hindsight,
+    arb: &SimArbResultBatch,
+) -> Result<Option<Vec<LevelOutcome>>> {
+    let tx_hash = arb.event.hint.hash;
+    let (tx, receipt) = match (
+        hindsight.client.get_transaction(tx_hash).await?,
+        hindsight.receipts.get(&hindsight.client, tx_hash).await?,
+    ) {
+        (Some(tx), Some(receipt)) => (tx, receipt),
+        _ => return Ok(None),
+    };
+    let mut outcomes = vec![];
+    for level in HINT_LEVELS {
+        let event = reduce_hint(&arb.event, level, &receipt.logs);
+        let event_map = [(tx_hash, event)].into_iter().collect();
+        let processed = hindsight
+            .to_owned()
+            .process_orderflow(&vec![tx.to_owned()], 1, None, event_map)
+            .await?;
+        outcomes.push(LevelOutcome {
+            level,
+            profit: processed
+                .arbs
+                .first()
+                .map(|arb| (arb.profit_token, arb.profit_decimals, arb.max_profit)),
+        });
+    }
+    Ok(Some(outcomes))
+}
+
+/// Sums each level's outcomes over every re-simulated tx, relative to what was found with full information.
+pub fn summarize(outcomes: &[Vec<LevelOutcome>]) -> Vec<LevelSummary> {
+    #[derive(Default)]
+    struct Accumulator {
+        num_simulated: u64,
+        num_failed: u64,
+        num_profitable: u64,
+        profits: BTreeMap<Address, (U256, u32)>,
+    }
+    let mut levels: BTreeMap<HintLevel, Accumulator> = BTreeMap::new();
+    for outcome in outcomes.iter().flatten() {
+        let acc = levels.entry(outcome.level).or_default();
+        acc.num_simulated += 1;
+        match outcome.profit {
+            None => acc.num_failed += 1,
+            Some((_, _, profit)) if profit.is_zero() => {}
+            Some((token, decimals, profit)) => {
+                acc.num_profitable += 1;
+                let (sum, sum_decimals) = acc.profits.entry(token).or_default();
+                *sum += profit;
+                *sum_decimals = decimals;
+            }
+        }
+    }
+    let full = levels.get(&HintLevel::Full).map(|full| {
+        let profits = full
+            .profits
+            .iter()
+            .map(|(token, (profit, decimals))| (*token, to_decimal(*profit, *decimals)))
+            .collect::<BTreeMap<_, _>>();
+        (full.num_profitable, profits)
+    });
+    levels
+        .into_iter()
+        .map(|(level, acc)| LevelSummary {
+            level,
+            num_simulated: acc.num_simulated,
+            num_failed: acc.num_failed,
+            num_profitable: acc.num_profitable,
+            findability: full
+                .as_ref()
+                .filter(|(num_profitable, _)| *num_profitable > 0)
+                .map(|(num_profitable, _)| acc.num_profitable as f64 / *num_profitable as f64),
+            profits: acc
+                .profits
+                .iter()
+                .map(|(token, (profit, decimals))| {
+                    let profit = to_decimal(*profit, *decimals);
+                    LevelProfit {
+                        profit_token: *token,
+                        profit,
+                        retained: full
+                            .as_ref()
+                            .and_then(|(_, profits)| profits.get(token))
+                            .filter(|full_profit| **full_profit > 0.0)
+                            .map(|full_profit| profit / full_profit),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Renders a row per hint level, then each level's profit by token.
+pub fn render_table(summaries: &[LevelSummary]) -> String {
+    let mut table = format!(
+        "{:<10} {:>10} {:>8} {:>11} {:>12}\n",
+        "hints", "simulated", "failed", "profitable", "findability"
+    );
+    for summary in summaries {
+        table.push_str(&format!(
+            "{:<10} {:>10} {:>8} {:>11} {:>12}\n",
+            summary.level.to_string(),
+            summary.num_simulated,
+            summary.num_failed,
+            summary.num_profitable,
+            summary
+                .findability
+                .map(|findability| format!("{:.1}%", findability * 100.0))
+                .unwrap_or("-".to_owned())
+        ));
+    }
+    table.push('\n');
+    for summary in summaries {
+        for profit in &summary.profits {
+            table.push_str(&format!(
+                "{:<10} {:?} {:>16.6} {:>10}\n",
+                summary.level.to_string(),
+                profit.profit_token,
+                profit.profit,
+                profit
+                    .retained
+                    .map(|retained| format!("{:.1}%", retained * 100.0))
+                    .unwrap_or("-".to_owned())
+            ));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mev_share_sse::FunctionSelector;
+
+    fn hint_tx(to: u64, selector: [u8; 4]) -> EventTransaction {
+        EventTransaction {
+            to: Some(Address::from_low_u64_be(to)),
+            function_selector: Some(FunctionSelector(selector)),
+            calldata: Some(Bytes::from(vec![1, 2, 3])),
+        }
+    }
+
+    #[test]
+    fn it_reduces_hints() {
+        let mut event = SimArbResultBatch::test_example().event;
+        event.hint.txs = vec![
+            hint_tx(0xa, UNISWAP_V2_SWAP_SELECTOR),
+            // a router call, which can't be told without its logs
+            hint_tx(0xb, [0x38, 0xed, 0x17, 0x39]),
+        ];
+        event.hint.logs = vec![EventTransactionLog::default()];
+        let receipt_logs = vec![Log::default(), Log::default()];
+
+        assert_eq!(
+            reduce_hint(&event, HintLevel::Full, &receipt_logs)
+                .hint
+                .logs
+                .len(),
+            2
+        );
+        assert_eq!(reduce_hint(&event, HintLevel::Hinted, &receipt_logs), event);
+        let no_logs = reduce_hint(&event, HintLevel::NoLogs, &receipt_logs);
+        assert_eq!(no_logs.hint.logs.len(), 1);
+        assert_eq!(no_logs.hint.logs[0].address, Address::from_low_u64_be(0xa));
+        assert_eq!(
+            no_logs.hint.logs[0].topics,
+            vec![H256::from_str(UNISWAP_V2_SWAP_TOPIC).unwrap()]
+        );
+        let selector_only = reduce_hint(&event, HintLevel::SelectorOnly, &receipt_logs);
+        assert!(selector_only.hint.logs.is_empty());
+        assert!(selector_only
+            .hint
+            .txs
+            .iter()
+            .all(|tx| tx.to.is_none() && tx.calldata.is_none() && tx.function_selector.is_some()));
+    }
+
+    #[test]
+    fn it_summarizes_findability() {
+        let weth = Address::from_low_u64_be(1);
+        let outcome = |level, profit: Option<u64>| LevelOutcome {
+            level,
+            profit: profit.map(|profit| (weth, 18, U256::from(profit) * U256::exp10(15))),
+        };
+        let outcomes = vec![
+            vec![
+                outcome(HintLevel::Full, Some(4)),
+                outcome(HintLevel::Hinted, Some(4)),
+                outcome(HintLevel::NoLogs, Some(4)),
+                outcome(HintLevel::SelectorOnly, None),
+            ],
+            vec![
+                outcome(HintLevel::Full, Some(6)),
+                outcome(HintLevel::Hinted, Some(0)),
+                outcome(HintLevel::NoLogs, None),
+                outcome(HintLevel::SelectorOnly, None),
+            ],
+        ];
+        let summaries = summarize(&outcomes);
+        let levels = summaries
+            .iter()
+            .map(|summary| (summary.level, summary.num_profitable, summary.findability))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            vec![
+                (HintLevel::Full, 2, Some(1.0)),
+                (HintLevel::Hinted, 1, Some(0.5)),
+                (HintLevel::NoLogs, 1, Some(0.5)),
+                (HintLevel::SelectorOnly, 0, Some(0.0)),
+            ]
+        );
+        assert_eq!(summaries[3].num_failed, 2);
+        let hinted = &summaries[1].profits[0];
+        assert!((hinted.profit - 0.004).abs() < 1e-12);
+        assert!((hinted.retained.unwrap() - 0.4).abs() < 1e-12);
+        assert!(render_table(&summaries).contains("selector"));
+    }
+}
@@ -0,0 +1,365 @@
+use super::{
+    cex_dex::{dex_price, user_trade},
+    stats::format_timestamp,
+};
+use crate::data::{
+    cex::{exchange_pair, exchange_symbol},
+    tokens::TokenMetadata,
+};
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::Address;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// DEX price that one arb's user trade left its pool at, on one chain.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainPrice {
+    pub chain: String,
+    /// Exchange symbols of the pair as BASE/QUOTE (see `exchange_pair`), so that pools of the same assets
+    /// match across chains whichever token they quote in, e.g. ETH/USDC.
+    pub pair: String,
+    pub timestamp: u64,
+    /// Quote per base.
+    pub price: f64,
+}
+
+/// Prices that the user trades of `arbs` (on `chain`) left their pools at, with pairs named by exchange symbols.
+/// Arbs without results, or whose tokens' metadata isn't known, are skipped.
+pub fn chain_prices<'a>(
+    chain: &str,
+    arbs: &[SimArbResultBatch],
+    token_metadata: impl Fn(&Address) -> Option<&'a TokenMetadata>,
+) -> Vec<ChainPrice> {
+    arbs.iter()
+        .filter_map(|arb| {
+            let trade = user_trade(arb)?;
+            let token = token_metadata(&trade.tokens.token)?;
+            let quote = token_metadata(&trade.tokens.quote)?;
+            let price = dex_price(trade, token.decimals as u32, quote.decimals as u32)?;
+            let token_symbol = exchange_symbol(&token.symbol);
+            let (base, quote) = exchange_pair(&token_symbol, &exchange_symbol(&quote.symbol));
+            Some(ChainPrice {
+                chain: chain.to_owned(),
+                pair: format!("{}/{}", base, quote),
+                timestamp: arb.event.timestamp,
+                // quoted the other way around on this chain
+                price: if base == token_symbol {
+                    price
+                } else {
+                    1.0 / price
+                },
+            })
+        })
+        .collect()
+}
+
+/// Divergence of one pair between two chains over the whole range.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairDivergence {
+    pub pair: String,
+    pub chain_a: String,
+    pub chain_b: String,
+    /// Windows in which both chains had a price.
+    pub aligned_windows: usize,
+    /// Aligned windows whose spread reached the threshold.
+    pub diverged_windows: usize,
+    /// Mean absolute spread of the aligned windows, in basis points of `chain_b`'s price.
+    pub mean_abs_spread_bps: f64,
+}
+
+/// A run of aligned windows in which a pair stayed apart between two chains, in the same direction.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DivergenceEpisode {
+    pub pair: String,
+    pub chain_a: String,
+    pub chain_b: String,
+    /// Start of the first window.
+    pub start: u64,
+    /// End of the last window.
+    pub end: u64,
+    /// Aligned windows in the run.
+    pub windows: usize,
+    /// Mean spread of `chain_a`'s price over `chain_b`'s, in basis points of `chain_b`'s.
+    /// Positive when the base is dearer on `chain_a`.
+    pub mean_spread_bps: f64,
+    /// Widest spread of the run, signed like `mean_spread_bps`.
+    pub max_spread_bps: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DivergenceReport {
+    /// Seconds per window that prices are aligned in.
+    pub window: u64,
+    pub min_spread_bps: f64,
+    /// Seconds that a divergence must last to be reported.
+    pub min_duration: u64,
+    pub pairs: Vec<PairDivergence>,
+    /// Sustained divergences, longest first.
+    pub episodes: Vec<DivergenceEpisode>,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+/// Compares the prices of each pair traded on more than one chain, aligned to windows of `window` seconds
+/// (each chain's median price in the window), and finds the runs of aligned windows in which the chains' prices
+/// stayed at least `min_spread_bps` apart in the same direction for at least `min_duration` seconds.
+///
+/// Windows in which either chain had no trade don't count; a window in which both did, but were closer,
+/// ends a run. Chains' pools aren't arbed atomically against each other, so these are research, not opportunities.
+pub fn divergence_report(
+    prices: &[ChainPrice],
+    window: u64,
+    min_spread_bps: f64,
+    min_duration: u64,
+) -> DivergenceReport {
+    let window = window.max(1);
+    // pair => chain => window start => prices
+    let mut windows: BTreeMap<&str, BTreeMap<&str, BTreeMap<u64, Vec<f64>>>> = BTreeMap::new();
+    for price in prices
+        .iter()
+        .filter(|price| price.price.is_finite() && price.price > 0.0)
+    {
+        windows
+            .entry(&price.pair)
+            .or_default()
+            .entry(&price.chain)
+            .or_default()
+            .entry(price.timestamp - price.timestamp % window)
+            .or_default()
+            .push(price.price);
+    }
+
+    let (mut pairs, mut episodes) = (vec![], vec![]);
+    for (pair, chains) in windows {
+        let chains = chains
+            .into_iter()
+            .map(|(chain, windows)| {
+                let medians = windows
+                    .into_iter()
+                    .map(|(start, mut prices)| (start, median(&mut prices)))
+                    .collect::<BTreeMap<_, _>>();
+                (chain, medians)
+            })
+            .collect::<Vec<_>>();
+        for (i, (chain_a, prices_a)) in chains.iter().enumerate() {
+            for (chain_b, prices_b) in &chains[i + 1..] {
+                let spreads = prices_a
+                    .iter()
+                    .filter_map(|(start, price_a)| {
+                        let price_b = prices_b.get(start)?;
+                        Some((*start, (price_a / price_b - 1.0) * 10_000.0))
+                    })
+                    .collect::<Vec<_>>();
+                if spreads.is_empty() {
+                    continue;
+                }
+                let diverged = |spread: f64| spread.abs() >= min_spread_bps;
+                pairs.push(PairDivergence {
+                    pair: pair.to_owned(),
+                    chain_a: chain_a.to_string(),
+                    chain_b: chain_b.to_string(),
+                    aligned_windows: spreads.len(),
+                    diverged_windows: spreads
+                        .iter()
+                        .filter(|(_, spread)| diverged(*spread))
+                        .count(),
+                    mean_abs_spread_bps: spreads
+                        .iter()
+                        .map(|(_, spread)| spread.abs())
+                        .sum::<f64>()
+                        / spreads.len() as f64,
+                });
+
+                let mut run: Vec<(u64, f64)> = vec![];
+                let mut close_run = |run: &mut Vec<(u64, f64)>| {
+                    if let (Some(first), Some(last)) = (run.first(), run.last()) {
+                        let (start, end) = (first.0, last.0 + window);
+                        if end - start >= min_duration {
+                            let spreads = run.iter().map(|(_, spread)| *spread);
+                            episodes.push(DivergenceEpisode {
+                                pair: pair.to_owned(),
+                                chain_a: chain_a.to_string(),
+                                chain_b: chain_b.to_string(),
+                                start,
+                                end,
+                                windows: run.len(),
+                                mean_spread_bps: spreads.clone().sum::<f64>() / run.len() as f64,
+                                max_spread_bps: spreads
+                                    .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                                    .unwrap_or_default(),
+                            });
+                        }
+                    }
+                    run.clear();
+                };
+                for (start, spread) in spreads {
+                    let same_direction = run.last().is_none_or(|(_, last)| {
+                        last.is_sign_positive() == spread.is_sign_positive()
+                    });
+                    if !diverged(spread) || !same_direction {
+                        close_run(&mut run);
+                    }
+                    if diverged(spread) {
+                        run.push((start, spread));
+                    }
+                }
+                close_run(&mut run);
+            }
+        }
+    }
+    episodes.sort_by(|a, b| {
+        (b.end - b.start)
+            .cmp(&(a.end - a.start))
+            .then(a.start.cmp(&b.start))
+    });
+    DivergenceReport {
+        window,
+        min_spread_bps,
+        min_duration,
+        pairs,
+        episodes,
+    }
+}
+
+/// Renders the divergence of each pair between each two chains, followed by the sustained divergences.
+pub fn render_table(report: &DivergenceReport) -> String {
+    let mut table = format!(
+        "{}s windows, divergences of at least {} bps for {}s\n\n",
+        report.window, report.min_spread_bps, report.min_duration
+    );
+    table.push_str(&format!(
+        "{:<16} {:<12} {:<12} {:>8} {:>9} {:>14}\n",
+        "pair", "chain a", "chain b", "aligned", "diverged", "mean |spread|"
+    ));
+    for row in &report.pairs {
+        table.push_str(&format!(
+            "{:<16} {:<12} {:<12} {:>8} {:>9} {:>14.1}\n",
+            row.pair,
+            row.chain_a,
+            row.chain_b,
+            row.aligned_windows,
+            row.diverged_windows,
+            row.mean_abs_spread_bps
+        ));
+    }
+    table.push_str(&format!(
+        "\n{:<16} {:<12} {:<12} {:<17} {:<17} {:>8} {:>12} {:>12}\n",
+        "pair", "chain a", "chain b", "start", "end", "windows", "mean spread", "max spread"
+    ));
+    for episode in &report.episodes {
+        table.push_str(&format!(
+            "{:<16} {:<12} {:<12} {:<17} {:<17} {:>8} {:>12.1} {:>12.1}\n",
+            episode.pair,
+            episode.chain_a,
+            episode.chain_b,
+            format_timestamp(episode.start),
+            format_timestamp(episode.end),
+            episode.windows,
+            episode.mean_spread_bps,
+            episode.max_spread_bps
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+    use ethers::types::U256;
+
+    fn price(chain: &str, timestamp: u64, price: f64) -> ChainPrice {
+        ChainPrice {
+            chain: chain.to_owned(),
+            pair: "ETH/USDC".to_owned(),
+            timestamp,
+            price,
+        }
+    }
+
+    #[test]
+    fn it_names_pairs_the_same_on_every_chain() {
+        let (usdc, weth) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let metadata = [(usdc, "USDC", 6), (weth, "WETH", 18)]
+            .into_iter()
+            .map(|(address, symbol, decimals)| {
+                let metadata = TokenMetadata {
+                    address,
+                    symbol: symbol.to_owned(),
+                    decimals,
+                };
+                (address, metadata)
+            })
+            .collect::<BTreeMap<_, _>>();
+        // WETH traded against USDC (2000 USDC per WETH), and USDC traded against WETH
+        let arb = |token: Address, quote: Address, price: U256| {
+            let mut result = SimArbResult::test_example(token, 1.into(), 0.into());
+            result.user_trade.tokens.token = token;
+            result.user_trade.tokens.quote = quote;
+            result.user_trade.token0_is_quote = false;
+            result.user_trade.price = price;
+            let mut arb = SimArbResultBatch::test_example();
+            arb.results = vec![result];
+            arb
+        };
+        let arbs = vec![
+            arb(weth, usdc, U256::from(2_000_000_000u64)),
+            // 0.0005 WETH per USDC
+            arb(usdc, weth, U256::exp10(15) / 2),
+        ];
+        let prices = chain_prices("base", &arbs, |token| metadata.get(token));
+        assert_eq!(prices.len(), 2);
+        assert!(prices.iter().all(|price| price.pair == "ETH/USDC"));
+        assert!((prices[0].price - 2000.0).abs() < 1e-9);
+        assert!((prices[1].price - 2000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_finds_sustained_divergences() {
+        let mut prices = vec![];
+        for minute in 0..10 {
+            let timestamp = 1_690_000_020 + minute * 60;
+            prices.push(price("mainnet", timestamp, 2000.0));
+            // base is 50 bps dearer for 4 minutes, then 30 bps cheaper for 2, then back in line
+            let spread = match minute {
+                0..=3 => 0.005,
+                4..=5 => -0.003,
+                _ => 0.0,
+            };
+            prices.push(price("base", timestamp + 5, 2000.0 * (1.0 + spread)));
+        }
+        // an unaligned price on a third chain, and a price that's no use
+        prices.push(price("arbitrum", 1, 2000.0));
+        prices.push(price("base", 1, 0.0));
+
+        let report = divergence_report(&prices, 60, 20.0, 180);
+        let pair = report
+            .pairs
+            .iter()
+            .find(|row| row.chain_a == "base" && row.chain_b == "mainnet")
+            .unwrap();
+        assert_eq!((pair.aligned_windows, pair.diverged_windows), (10, 6));
+        assert!((pair.mean_abs_spread_bps - 26.0).abs() < 1e-6);
+        // the 2-minute run is too short, and the direction flip split the runs
+        assert_eq!(report.episodes.len(), 1);
+        let episode = &report.episodes[0];
+        assert_eq!((episode.windows, episode.end - episode.start), (4, 240));
+        assert!((episode.mean_spread_bps - 50.0).abs() < 1e-6);
+
+        let report = divergence_report(&prices, 60, 20.0, 120);
+        assert_eq!(report.episodes.len(), 2);
+        assert!(report.episodes[1].max_spread_bps < 0.0);
+        assert!(render_table(&report).contains("ETH/USDC"));
+    }
+}
@@ -0,0 +1,426 @@
+use super::to_decimal;
+use crate::{
+    filter::AddressLists,
+    interfaces::{QuoteAsset, SimArbResultBatch},
+    sim::{
+        budget::{SimBudget, TxSimLimits},
+        core::SearchContext,
+        fork::ForkCache,
+        pools::PoolAdapters,
+        processor::{simulate_backrun_arbs, H256Map},
+        registry::PoolRegistry,
+    },
+    util::{ReceiptCache, WsClient},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256, U256},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// Engine version of results saved before engines were versioned.
+pub const UNVERSIONED: &'static str = "unversioned";
+
+/// The engine version that simulated `arb`.
+pub fn engine_version_of(arb: &SimArbResultBatch) -> &str {
+    arb.engine_version.as_deref().unwrap_or(UNVERSIONED)
+}
+
+/// A stored result, re-simulated by the current engine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResimulatedArb {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub baseline_version: String,
+    pub profit_token: Address,
+    pub profit_decimals: u32,
+    pub baseline_profit: U256,
+    /// (start pool, end pool) of the stored best backrun.
+    pub baseline_route: Option<(Address, Address)>,
+    /// None if the current engine failed; see `error`.
+    pub current_profit: Option<U256>,
+    pub current_route: Option<(Address, Address)>,
+    pub error: Option<String>,
+}
+
+/// How a re-simulated result compares to the stored one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftKind {
+    /// Profit changed by no more than the tolerance.
+    Unchanged,
+    Increased,
+    Decreased,
+    NewlyProfitable,
+    NoLongerProfitable,
+    /// The current engine failed to simulate the tx.
+    Failed,
+}
+
+impl ResimulatedArb {
+    /// Change of profit relative to the stored profit, or None if either side isn't a positive profit.
+    pub fn relative_change(&self) -> Option<f64> {
+        let current = self.current_profit?;
+        if self.baseline_profit.is_zero() {
+            return None;
+        }
+        let baseline = to_decimal(self.baseline_profit, self.profit_decimals);
+        Some((to_decimal(current, self.profit_decimals) - baseline) / baseline)
+    }
+
+    /// Compares the profits, treating relative changes of up to `tolerance` (e.g. 0.001 = 0.1%) as unchanged.
+    pub fn kind(&self, tolerance: f64) -> DriftKind {
+        let current = match self.current_profit {
+            Some(current) => current,
+            None => return DriftKind::Failed,
+        };
+        match (self.baseline_profit.is_zero(), current.is_zero()) {
+            (true, true) => DriftKind::Unchanged,
+            (true, false) => DriftKind::NewlyProfitable,
+            (false, true) => DriftKind::NoLongerProfitable,
+            (false, false) => match self.relative_change() {
+                Some(change) if change > tolerance => DriftKind::Increased,
+                Some(change) if change < -tolerance => DriftKind::Decreased,
+                _ => DriftKind::Unchanged,
+            },
+        }
+    }
+
+    pub fn route_changed(&self) -> bool {
+        self.current_profit.is_some() && self.current_route != self.baseline_route
+    }
+}
+
+/// One re-simulated result, as listed among the largest drifts.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftRow {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub baseline_version: String,
+    pub kind: DriftKind,
+    /// Profits, in whole tokens.
+    pub baseline_profit: f64,
+    pub current_profit: Option<f64>,
+    pub route_changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How the current engine's results differ from the stored results, for one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReport {
+    pub profit_token: Address,
+    /// Engine versions that the sampled results were stored by.
+    pub baseline_versions: Vec<String>,
+    pub current_version: String,
+    pub num_sampled: u64,
+    pub num_unchanged: u64,
+    pub num_increased: u64,
+    pub num_decreased: u64,
+    pub num_newly_profitable: u64,
+    pub num_no_longer_profitable: u64,
+    pub num_failed: u64,
+    /// Number of results whose best backrun now trades different pools.
+    pub num_route_changed: u64,
+    /// Sums of profits of the results that both engines simulated, in whole tokens.
+    pub baseline_profit: f64,
+    pub current_profit: f64,
+    /// Change of `current_profit` relative to `baseline_profit` (0 if there was no baseline profit).
+    pub profit_change: f64,
+    /// Results that changed the most, failures first.
+    pub largest: Vec<DriftRow>,
+}
+
+/// Re-simulates `arb`'s tx with the current engine and the built-in AMMs, searching against `quote_assets` without excluding any pools.
+///
+/// Returns None if the user's tx can't be found. Failures of the current engine are returned in the result.
+pub async fn resimulate(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    quote_assets: &[QuoteAsset],
+) -> Result<Option<ResimulatedArb>> {
+    let tx = match client.get_transaction(arb.event.hint.hash).await? {
+        Some(tx) => tx,
+        None => return Ok(None),
+    };
+    let event_map = [(arb.event.hint.hash, arb.event.to_owned())]
+        .into_iter()
+        .collect::<H256Map<_>>();
+    let route_of = |arb: &SimArbResultBatch| {
+        arb.best_result()
+            .map(|best| (best.backrun_trade.start_pool, best.backrun_trade.end_pool))
+    };
+    let search = SearchContext {
+        client,
+        quote_assets,
+        // the default limits neither weigh gas nor pay the coinbase, which is all the wrapped native token is for
+        wrapped_native: None,
+        lists: &AddressLists::default(),
+        adapters: &PoolAdapters::default(),
+        forks: &ForkCache::default(),
+        budget: &SimBudget::default(),
+        receipts: &ReceiptCache::default(),
+        registry: &PoolRegistry::default(),
+        path_finder: None,
+        sim_cache: None,
+        audit: None,
+    };
+    let current = match simulate_backrun_arbs(
+        &search,
+        Arc::new(tx),
+        &event_map,
+        TxSimLimits::default(),
+    )
+    .await
+    {
+        Ok((current, _)) if current.profit_token != arb.profit_token => Err(format!(
+            "profit token changed from {:?} to {:?}",
+            arb.profit_token, current.profit_token
+        )),
+        Ok((current, _)) => Ok(current),
+        Err(err) => Err(err.to_string()),
+    };
+    Ok(Some(ResimulatedArb {
+        tx_hash: arb.event.hint.hash,
+        block: arb.event.block,
+        baseline_version: engine_version_of(arb).to_owned(),
+        profit_token: arb.profit_token,
+        profit_decimals: arb.profit_decimals,
+        baseline_profit: arb.max_profit,
+        baseline_route: route_of(arb),
+        current_profit: current.as_ref().ok().map(|current| current.max_profit),
+        current_route: current.as_ref().ok().and_then(route_of),
+        error: current.err(),
+    }))
+}
+
+/// Compares re-simulated results to the stored ones, one report per profit token.
+///
+/// Profit changes of up to `tolerance` count as unchanged; up to `num_examples` of the largest changes are listed.
+pub fn drift_reports(
+    samples: &[ResimulatedArb],
+    current_version: &str,
+    tolerance: f64,
+    num_examples: usize,
+) -> Vec<DriftReport> {
+    let mut tokens: BTreeMap<Address, Vec<&ResimulatedArb>> = BTreeMap::new();
+    for sample in samples {
+        tokens.entry(sample.profit_token).or_default().push(sample);
+    }
+    tokens
+        .into_iter()
+        .map(|(profit_token, samples)| {
+            let decimals = samples[0].profit_decimals;
+            let count = |kind: DriftKind| {
+                samples
+                    .iter()
+                    .filter(|sample| sample.kind(tolerance) == kind)
+                    .count() as u64
+            };
+            let simulated = samples
+                .iter()
+                .filter(|sample| sample.current_profit.is_some());
+            let baseline_profit = to_decimal(
+                simulated
+                    .clone()
+                    .fold(U256::zero(), |sum, sample| sum + sample.baseline_profit),
+                decimals,
+            );
+            let current_profit = to_decimal(
+                simulated.fold(U256::zero(), |sum, sample| {
+                    sum + sample.current_profit.unwrap_or_default()
+                }),
+                decimals,
+            );
+
+            let mut changed = samples
+                .iter()
+                .filter(|sample| sample.kind(tolerance) != DriftKind::Unchanged)
+                .collect::<Vec<_>>();
+            // failures first, then by how much profit moved
+            let moved = |sample: &ResimulatedArb| match sample.current_profit {
+                Some(current) => (to_decimal(current, decimals)
+                    - to_decimal(sample.baseline_profit, decimals))
+                .abs(),
+                None => f64::INFINITY,
+            };
+            changed.sort_by(|a, b| moved(b).total_cmp(&moved(a)));
+            let largest = changed
+                .into_iter()
+                .take(num_examples)
+                .map(|sample| DriftRow {
+                    tx_hash: sample.tx_hash,
+                    block: sample.block,
+                    baseline_version: sample.baseline_version.to_owned(),
+                    kind: sample.kind(tolerance),
+                    baseline_profit: to_decimal(sample.baseline_profit, decimals),
+                    current_profit: sample
+                        .current_profit
+                        .map(|profit| to_decimal(profit, decimals)),
+                    route_changed: sample.route_changed(),
+                    error: sample.error.to_owned(),
+                })
+                .collect();
+
+            DriftReport {
+                profit_token,
+                baseline_versions: samples
+                    .iter()
+                    .map(|sample| sample.baseline_version.to_owned())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+                current_version: current_version.to_owned(),
+                num_sampled: samples.len() as u64,
+                num_unchanged: count(DriftKind::Unchanged),
+                num_increased: count(DriftKind::Increased),
+                num_decreased: count(DriftKind::Decreased),
+                num_newly_profitable: count(DriftKind::NewlyProfitable),
+                num_no_longer_profitable: count(DriftKind::NoLongerProfitable),
+                num_failed: count(DriftKind::Failed),
+                num_route_changed: samples
+                    .iter()
+                    .filter(|sample| sample.route_changed())
+                    .count() as u64,
+                baseline_profit,
+                current_profit,
+                profit_change: if baseline_profit > 0.0 {
+                    (current_profit - baseline_profit) / baseline_profit
+                } else {
+                    0.0
+                },
+                largest,
+            }
+        })
+        .collect()
+}
+
+/// Renders each report's counts & profit change, followed by its largest drifts.
+pub fn render_table(reports: &[DriftReport]) -> String {
+    let mut table = String::new();
+    for report in reports {
+        table.push_str(&format!(
+            "profit token {:?}: engine {} vs {}, {} results re-simulated\n",
+            report.profit_token,
+            report.baseline_versions.join(", "),
+            report.current_version,
+            report.num_sampled
+        ));
+        table.push_str(&format!(
+            "  unchanged {}, increased {}, decreased {}, newly profitable {}, no longer profitable {}, failed {}, route changed {}\n",
+            report.num_unchanged,
+            report.num_increased,
+            report.num_decreased,
+            report.num_newly_profitable,
+            report.num_no_longer_profitable,
+            report.num_failed,
+            report.num_route_changed
+        ));
+        table.push_str(&format!(
+            "  profit {:.6} -> {:.6} ({:+.2}%)\n",
+            report.baseline_profit,
+            report.current_profit,
+            report.profit_change * 100.0
+        ));
+        if !report.largest.is_empty() {
+            table.push_str(&format!(
+                "\n  {:<66} {:>10} {:<20} {:>14} {:>14}  {}\n",
+                "tx", "block", "drift", "stored", "current", "note"
+            ));
+        }
+        for row in &report.largest {
+            let note = match (&row.error, row.route_changed) {
+                (Some(error), _) => error.chars().take(60).collect(),
+                (None, true) => "route changed".to_owned(),
+                (None, false) => String::new(),
+            };
+            table.push_str(&format!(
+                "  {:<66} {:>10} {:<20} {:>14.6} {:>14}  {}\n",
+                format!("{:?}", row.tx_hash),
+                row.block,
+                format!("{:?}", row.kind),
+                row.baseline_profit,
+                row.current_profit
+                    .map(|profit| format!("{:.6}", profit))
+                    .unwrap_or("-".to_owned()),
+                note
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_drift() {
+        let eth = |milli: u64| U256::from(milli) * U256::exp10(15);
+        let route = Some((Address::from_low_u64_be(0xa), Address::from_low_u64_be(0xb)));
+        let resimulated = |tx: u64, baseline: u64, current: Option<u64>| ResimulatedArb {
+            tx_hash: H256::from_low_u64_be(tx),
+            block: tx,
+            baseline_version: UNVERSIONED.to_owned(),
+            profit_token: Address::zero(),
+            profit_decimals: 18,
+            baseline_profit: eth(baseline),
+            baseline_route: route,
+            current_profit: current.map(eth),
+            current_route: route,
+            error: current.is_none().then(|| "all swaps reverted".to_owned()),
+        };
+        let mut rerouted = resimulated(6, 100, Some(80));
+        rerouted.current_route = None;
+        let samples = vec![
+            resimulated(1, 100, Some(100)),
+            // within tolerance
+            resimulated(2, 10000, Some(10005)),
+            resimulated(3, 0, Some(50)),
+            resimulated(4, 100, Some(0)),
+            resimulated(5, 100, None),
+            rerouted,
+        ];
+        let reports = drift_reports(&samples, "1", 0.001, 3);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.baseline_versions, vec![UNVERSIONED.to_owned()]);
+        assert_eq!(report.num_sampled, 6);
+        assert_eq!(
+            (
+                report.num_unchanged,
+                report.num_increased,
+                report.num_decreased,
+                report.num_newly_profitable,
+                report.num_no_longer_profitable,
+                report.num_failed,
+                report.num_route_changed
+            ),
+            (2, 0, 1, 1, 1, 1, 1)
+        );
+        // the failed result isn't counted in either sum
+        assert!((report.baseline_profit - 10.3).abs() < 1e-12);
+        assert!((report.current_profit - 10.235).abs() < 1e-12);
+        assert_eq!(
+            report
+                .largest
+                .iter()
+                .map(|row| row.kind)
+                .collect::<Vec<_>>(),
+            vec![
+                DriftKind::Failed,
+                DriftKind::NoLongerProfitable,
+                DriftKind::NewlyProfitable
+            ]
+        );
+        assert!(render_table(&reports).contains("all swaps reverted"));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::interfaces::{FailureCategory, SimFailure};
+use ethers::types::{Address, H256};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// Failed simulations of one category.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureRow {
+    pub category: FailureCategory,
+    pub num_failures: u64,
+    /// `num_failures` as a share of all failures (0 to 1).
+    pub share: f64,
+    /// Number of distinct txs with a failure of this category.
+    pub num_txs: u64,
+    /// Hashes of the first few txs that failed this way.
+    pub examples: Vec<H256>,
+    /// Error of the first failure, as a sample of what went wrong.
+    pub sample_error: String,
+}
+
+/// A scan's failed simulations, by category.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureTriage {
+    /// Number of txs that were simulated (i.e. have a saved arb), for scale.
+    pub num_simulated: u64,
+    pub num_profitable: u64,
+    pub num_failures: u64,
+    /// Number of distinct txs with any failure.
+    pub num_failed_txs: u64,
+    /// Categories, most failures first.
+    pub categories: Vec<FailureRow>,
+}
+
+/// Groups `failures` by category, keeping up to `num_examples` example txs for each.
+///
+/// Re-scanning a range saves its failures again, so only the last failure of each (chain, tx, pool) counts.
+pub fn triage(
+    failures: &[SimFailure],
+    num_simulated: u64,
+    num_profitable: u64,
+    num_examples: usize,
+) -> FailureTriage {
+    let mut latest: HashMap<(u64, H256, Option<Address>), usize> = HashMap::new();
+    for (idx, failure) in failures.iter().enumerate() {
+        latest.insert((failure.chain_id, failure.tx_hash, failure.pool), idx);
+    }
+    let mut indices = latest.into_values().collect::<Vec<_>>();
+    // keep the order failures were read in, so examples are the earliest txs
+    indices.sort();
+
+    let mut rows: BTreeMap<FailureCategory, (FailureRow, HashSet<H256>)> = BTreeMap::new();
+    let mut failed_txs = HashSet::new();
+    for failure in indices.into_iter().map(|idx| &failures[idx]) {
+        failed_txs.insert(failure.tx_hash);
+        let (row, txs) = rows.entry(failure.category).or_insert_with(|| {
+            (
+                FailureRow {
+                    category: failure.category,
+                    num_failures: 0,
+                    share: 0.0,
+                    num_txs: 0,
+                    examples: vec![],
+                    sample_error: failure.error.to_owned(),
+                },
+                HashSet::new(),
+            )
+        });
+        row.num_failures += 1;
+        if txs.insert(failure.tx_hash) && row.examples.len() < num_examples {
+            row.examples.push(failure.tx_hash);
+        }
+    }
+
+    let num_failures = rows.values().map(|(row, _)| row.num_failures).sum::<u64>();
+    let mut categories = rows
+        .into_values()
+        .map(|(mut row, txs)| {
+            row.num_txs = txs.len() as u64;
+            row.share = row.num_failures as f64 / num_failures as f64;
+            row
+        })
+        .collect::<Vec<_>>();
+    categories.sort_by_key(|row| Reverse(row.num_failures));
+    FailureTriage {
+        num_simulated,
+        num_profitable,
+        num_failures,
+        num_failed_txs: failed_txs.len() as u64,
+        categories,
+    }
+}
+
+/// Renders `triage` as a summary line followed by a table of categories and their example txs.
+pub fn render_table(triage: &FailureTriage) -> String {
+    let mut table = format!(
+        "{} txs simulated ({} profitable), {} failures across {} txs\n\n",
+        triage.num_simulated, triage.num_profitable, triage.num_failures, triage.num_failed_txs
+    );
+    table.push_str(&format!(
+        "{:<12} {:>9} {:>7} {:>7}  {}\n",
+        "category", "failures", "share", "txs", "sample error"
+    ));
+    for row in &triage.categories {
+        table.push_str(&format!(
+            "{:<12} {:>9} {:>6.1}% {:>7}  {}\n",
+            row.category.to_string(),
+            row.num_failures,
+            row.share * 100.0,
+            row.num_txs,
+            row.sample_error.chars().take(100).collect::<String>(),
+        ));
+        for tx_hash in &row.examples {
+            table.push_str(&format!("{:<12} e.g. {:?}\n", "", tx_hash));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResultBatch;
+
+    #[test]
+    fn it_triages_failures_by_category() {
+        let failure = |tx: u64, pool: u64, error: &str| {
+            let mut event = SimArbResultBatch::test_example().event;
+            event.hint.hash = H256::from_low_u64_be(tx);
+            SimFailure::new(&event, Some(Address::from_low_u64_be(pool)), error)
+        };
+        let failures = vec![
+            failure(1, 0xa, "all swaps reverted"),
+            failure(1, 0xb, "all swaps reverted"),
+            failure(2, 0xa, "swap reverted: 0x (gas used: 21000)"),
+            failure(3, 0xc, "no other pool found, (pool=0x0000…000c)"),
+            failure(
+                4,
+                0xa,
+                "(code: -32000, message: header not found, data: None)",
+            ),
+            // the same failure saved again by a re-scan
+            failure(2, 0xa, "swap reverted: 0x (gas used: 21000)"),
+        ];
+        let triage = triage(&failures, 10, 2, 1);
+        assert_eq!((triage.num_failures, triage.num_failed_txs), (5, 4));
+        let reverted = &triage.categories[0];
+        assert_eq!(reverted.category, FailureCategory::Reverted);
+        assert_eq!((reverted.num_failures, reverted.num_txs), (3, 2));
+        assert!((reverted.share - 0.6).abs() < 1e-12);
+        assert_eq!(reverted.examples, vec![H256::from_low_u64_be(1)]);
+        let categories = triage
+            .categories
+            .iter()
+            .map(|row| row.category)
+            .collect::<HashSet<_>>();
+        assert!(categories.contains(&FailureCategory::NoAltPool));
+        assert!(categories.contains(&FailureCategory::Rpc));
+        assert!(render_table(&triage).contains("no alt pool"));
+    }
+
+    #[test]
+    fn it_categorizes_errors() {
+        assert_eq!(
+            FailureCategory::of("failed to decode swap result: InvalidData"),
+            FailureCategory::Decoding
+        );
+        assert_eq!(
+            FailureCategory::of("panicked: sim_price_v2 panicked. address=0x0"),
+            FailureCategory::Panic
+        );
+        assert_eq!(
+            FailureCategory::of("tx not landed (hash=0x0)"),
+            FailureCategory::Rpc
+        );
+        assert_eq!(
+            FailureCategory::of(
+                "failed to quote price: insufficient liquidity: reserves (0, 1000000)"
+            ),
+            FailureCategory::InsufficientLiquidity
+        );
+        assert_eq!(
+            FailureCategory::of("user tx reverted (hash=0x0): Bytes(0x) (gas used: 21000)"),
+            FailureCategory::UserTxReverted
+        );
+        assert_eq!(
+            FailureCategory::of("unsafe token (address=0x0): sell failed: swap reverted: 0x"),
+            FailureCategory::UnsafeToken
+        );
+        assert_eq!(
+            FailureCategory::of("malformed hint: log of 0x0 has 5 topics"),
+            FailureCategory::MalformedHint
+        );
+        assert_eq!(
+            FailureCategory::of("deadline has elapsed"),
+            FailureCategory::Timeout
+        );
+        assert_eq!(
+            FailureCategory::of("no quote assets configured"),
+            FailureCategory::Other
+        );
+    }
+}
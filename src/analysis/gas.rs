@@ -0,0 +1,181 @@
+use super::{backrun_gas, to_decimal};
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::{Address, U256};
+use serde::Serialize;
+
+/// Base fees (gwei) swept by default.
+pub const DEFAULT_BASE_FEES_GWEI: [f64; 7] = [5.0, 10.0, 20.0, 30.0, 50.0, 100.0, 200.0];
+
+#[derive(Clone, Debug)]
+pub struct GasSweepOptions {
+    /// Base fees to evaluate, in gwei. The base fee each arb was simulated with is always evaluated too.
+    pub base_fees_gwei: Vec<f64>,
+    /// Priority fees to evaluate (on top of each base fee), in gwei.
+    pub tips_gwei: Vec<f64>,
+    /// Gas used per backrun, for backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// Wrapped native token of the chain. Only profits in this token can be compared to gas costs.
+    pub native_token: Address,
+}
+
+/// How many opportunities stay profitable under one gas price assumption.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasScenario {
+    /// Base fee in gwei, or None for the base fee that each arb was simulated with.
+    pub base_fee_gwei: Option<f64>,
+    pub tip_gwei: f64,
+    /// Number of opportunities still profitable after gas.
+    pub num_profitable: u64,
+    /// `num_profitable` as a share of all opportunities (0 to 1).
+    pub share_profitable: f64,
+    /// Profit of the backruns still worth sending, minus their gas, in ETH.
+    pub net_profit: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasSweep {
+    /// Number of events with a profitable backrun (before gas) in the native token.
+    pub num_opportunities: u64,
+    /// Number of profitable events in other tokens, which can't be evaluated.
+    pub num_skipped: u64,
+    pub scenarios: Vec<GasScenario>,
+}
+
+fn gwei(gwei: f64) -> U256 {
+    U256::from((gwei * 1e9) as u128)
+}
+
+/// Re-evaluates stored arbs under each combination of base fee & tip in `options`.
+///
+/// No re-simulation is done: each backrun's profit is compared against its gas used at each gas price.
+/// Arbs saved before base fees were recorded are evaluated at 0 base fee in the "as simulated" scenario.
+pub fn gas_sweep(arbs: &[SimArbResultBatch], options: &GasSweepOptions) -> GasSweep {
+    let mut opportunities = vec![];
+    let mut num_skipped = 0;
+    for arb in arbs {
+        if arb.max_profit.is_zero() {
+            continue;
+        }
+        if arb.profit_token != options.native_token {
+            num_skipped += 1;
+            continue;
+        }
+        opportunities.push((
+            arb.max_profit,
+            arb.base_fee.unwrap_or_default(),
+            backrun_gas(arb, options.backrun_gas),
+        ));
+    }
+
+    let base_fees = std::iter::once(None).chain(options.base_fees_gwei.iter().copied().map(Some));
+    let mut scenarios = vec![];
+    for base_fee_gwei in base_fees {
+        for tip_gwei in &options.tips_gwei {
+            let mut num_profitable = 0;
+            let mut net_profit = U256::zero();
+            for (profit, recorded_base_fee, gas_used) in &opportunities {
+                let base_fee = base_fee_gwei.map(gwei).unwrap_or(*recorded_base_fee);
+                let gas_cost = (base_fee + gwei(*tip_gwei)) * *gas_used;
+                if *profit > gas_cost {
+                    num_profitable += 1;
+                    net_profit += *profit - gas_cost;
+                }
+            }
+            scenarios.push(GasScenario {
+                base_fee_gwei,
+                tip_gwei: *tip_gwei,
+                num_profitable,
+                share_profitable: if opportunities.is_empty() {
+                    0.0
+                } else {
+                    num_profitable as f64 / opportunities.len() as f64
+                },
+                net_profit: to_decimal(net_profit, 18),
+            });
+        }
+    }
+    GasSweep {
+        num_opportunities: opportunities.len() as u64,
+        num_skipped,
+        scenarios,
+    }
+}
+
+/// Renders `sweep` as a plain-text table.
+pub fn render_table(sweep: &GasSweep) -> String {
+    let mut table = format!(
+        "{} opportunities profitable before gas ({} in other tokens skipped)\n",
+        sweep.num_opportunities, sweep.num_skipped
+    );
+    table.push_str(&format!(
+        "{:>14} {:>10} {:>10} {:>8} {:>16}\n",
+        "base fee", "tip", "profitable", "share", "net profit"
+    ));
+    for scenario in &sweep.scenarios {
+        table.push_str(&format!(
+            "{:>14} {:>10} {:>10} {:>7.1}% {:>16.6}\n",
+            scenario
+                .base_fee_gwei
+                .map(|base_fee| format!("{} gwei", base_fee))
+                .unwrap_or("as simulated".to_owned()),
+            format!("{} gwei", scenario.tip_gwei),
+            scenario.num_profitable,
+            scenario.share_profitable * 100.0,
+            scenario.net_profit,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::known_wrapped_native, interfaces::SimArbResult};
+
+    #[test]
+    fn it_sweeps_gas_prices() {
+        let weth = known_wrapped_native(1).unwrap();
+        let arb = |milli_eth: u64, gas_used: Option<u64>| {
+            let mut result =
+                SimArbResult::test_example(Address::zero(), U256::exp10(18), U256::zero());
+            result.backrun_trade.profit = U256::from(milli_eth) * U256::exp10(15);
+            result.backrun_trade.gas_used = gas_used;
+            let mut arb = SimArbResultBatch::test_example();
+            arb.max_profit = result.backrun_trade.profit;
+            arb.results = vec![result];
+            arb.base_fee = Some(gwei(10.0));
+            arb
+        };
+        let mut usdc_arb = arb(1, None);
+        usdc_arb.profit_token = Address::from_low_u64_be(0x1);
+        // 100k gas: 1 mETH per 10 gwei
+        let arbs = vec![
+            arb(5, Some(100_000)),
+            arb(25, Some(100_000)),
+            // unrecorded gas falls back to 500k: 5 mETH per 10 gwei
+            arb(40, None),
+            usdc_arb,
+        ];
+        let sweep = gas_sweep(
+            &arbs,
+            &GasSweepOptions {
+                base_fees_gwei: vec![100.0],
+                tips_gwei: vec![0.0, 100.0],
+                backrun_gas: 500_000,
+                native_token: weth,
+            },
+        );
+        assert_eq!((sweep.num_opportunities, sweep.num_skipped), (3, 1));
+        let profitable = sweep
+            .scenarios
+            .iter()
+            .map(|scenario| scenario.num_profitable)
+            .collect::<Vec<_>>();
+        // as simulated (10 gwei), 10 + 100 gwei tip, 100 gwei, 100 + 100 gwei tip
+        assert_eq!(profitable, vec![3, 1, 1, 1]);
+        assert!((sweep.scenarios[0].net_profit - 0.063).abs() < 1e-12);
+        assert!((sweep.scenarios[2].share_profitable - 1.0 / 3.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::H256;
+use mev_share_sse::EventHistory;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Hint fields that a MEV-Share event may share, in the order they're listed in labels.
+pub const HINT_FIELD_NAMES: [&'static str; 4] = ["logs", "to", "selector", "calldata"];
+
+/// Which hint fields an event shared. Fields of any of the event's txs count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HintFields {
+    pub logs: bool,
+    pub to: bool,
+    pub selector: bool,
+    pub calldata: bool,
+}
+
+impl HintFields {
+    pub fn of(event: &EventHistory) -> Self {
+        let txs = &event.hint.txs;
+        Self {
+            logs: !event.hint.logs.is_empty(),
+            to: txs.iter().any(|tx| tx.to.is_some()),
+            selector: txs.iter().any(|tx| tx.function_selector.is_some()),
+            calldata: txs.iter().any(|tx| tx.calldata.is_some()),
+        }
+    }
+
+    fn present(&self) -> [bool; 4] {
+        [self.logs, self.to, self.selector, self.calldata]
+    }
+
+    /// Number of fields shared (0 to 4).
+    pub fn completeness(&self) -> usize {
+        self.present()
+            .into_iter()
+            .filter(|present| *present)
+            .count()
+    }
+
+    /// Names of the fields shared, joined with "+" (e.g. "logs+to"), or "none".
+    pub fn label(&self) -> String {
+        let names = HINT_FIELD_NAMES
+            .iter()
+            .zip(self.present())
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            "none".to_owned()
+        } else {
+            names.join("+")
+        }
+    }
+}
+
+/// How often events in one group yielded a profitable backrun.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintRow {
+    /// Hint fields shared by the group's events (e.g. "logs+to"), or "with <field>" / "without <field>".
+    pub hints: String,
+    pub num_events: u64,
+    /// Number of events that were simulated (i.e. have a saved arb).
+    pub num_simulated: u64,
+    /// Number of events with a profitable backrun.
+    pub num_profitable: u64,
+    /// `num_profitable` as a share of `num_events` (0 to 1).
+    pub profitable_rate: f64,
+}
+
+impl HintRow {
+    fn add(&mut self, simulated: bool, profitable: bool) {
+        self.num_events += 1;
+        self.num_simulated += simulated as u64;
+        self.num_profitable += profitable as u64;
+        self.profitable_rate = self.num_profitable as f64 / self.num_events as f64;
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintEfficacy {
+    /// True if only the events of saved arbs were available (no events were saved by `scan --save-events`).
+    pub arbs_only: bool,
+    /// One row per combination of hint fields, most complete first.
+    pub by_fields: Vec<HintRow>,
+    /// Rows for events with & without each hint field.
+    pub by_field: Vec<HintRow>,
+}
+
+/// Compares profitability of `events` by which hint fields they shared.
+///
+/// Events are joined to `arbs` by tx hash; events of arbs that aren't in `events` are included too,
+/// since arbs carry their event.
+pub fn hint_efficacy(events: &[EventHistory], arbs: &[SimArbResultBatch]) -> HintEfficacy {
+    let arbs_by_hash = arbs
+        .iter()
+        .map(|arb| (arb.event.hint.hash, arb))
+        .collect::<HashMap<H256, _>>();
+    let mut all_events = events
+        .iter()
+        .map(|event| (event.hint.hash, event))
+        .collect::<HashMap<H256, _>>();
+    for arb in arbs {
+        all_events.entry(arb.event.hint.hash).or_insert(&arb.event);
+    }
+
+    let mut by_fields: BTreeMap<HintFields, HintRow> = BTreeMap::new();
+    let mut by_field = HINT_FIELD_NAMES
+        .iter()
+        .flat_map(|name| {
+            [format!("with {}", name), format!("without {}", name)].map(|hints| HintRow {
+                hints,
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>();
+    for (hash, event) in all_events {
+        let arb = arbs_by_hash.get(&hash);
+        let simulated = arb.is_some();
+        let profitable = arb.map(|arb| !arb.max_profit.is_zero()).unwrap_or(false);
+        let fields = HintFields::of(event);
+        by_fields
+            .entry(fields)
+            .or_insert(HintRow {
+                hints: fields.label(),
+                ..Default::default()
+            })
+            .add(simulated, profitable);
+        for (i, present) in fields.present().into_iter().enumerate() {
+            by_field[i * 2 + !present as usize].add(simulated, profitable);
+        }
+    }
+
+    let mut by_fields = by_fields.into_iter().collect::<Vec<_>>();
+    by_fields.sort_by(|(a, _), (b, _)| b.completeness().cmp(&a.completeness()).then(b.cmp(a)));
+    HintEfficacy {
+        arbs_only: events.is_empty(),
+        by_fields: by_fields.into_iter().map(|(_, row)| row).collect(),
+        by_field: by_field
+            .into_iter()
+            .filter(|row| row.num_events > 0)
+            .collect(),
+    }
+}
+
+fn render_rows(table: &mut String, rows: &[HintRow]) {
+    table.push_str(&format!(
+        "{:<28} {:>10} {:>10} {:>10} {:>8}\n",
+        "hints", "events", "simulated", "profitable", "rate"
+    ));
+    for row in rows {
+        table.push_str(&format!(
+            "{:<28} {:>10} {:>10} {:>10} {:>7.2}%\n",
+            row.hints,
+            row.num_events,
+            row.num_simulated,
+            row.num_profitable,
+            row.profitable_rate * 100.0
+        ));
+    }
+}
+
+/// Renders `efficacy` as two plain-text tables: by combination of fields, then by single field.
+pub fn render_table(efficacy: &HintEfficacy) -> String {
+    let mut table = String::new();
+    if efficacy.arbs_only {
+        table.push_str(
+            "no saved events found; only events with saved arbs are counted (see `scan --save-events`)\n\n",
+        );
+    }
+    render_rows(&mut table, &efficacy.by_fields);
+    table.push('\n');
+    render_rows(&mut table, &efficacy.by_field);
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Address, U256};
+    use mev_share_sse::{EventTransaction, EventTransactionLog};
+
+    #[test]
+    fn it_rates_events_by_hint_fields() {
+        let event = |hash: u64, logs: bool, to: bool| {
+            let mut event = SimArbResultBatch::test_example().event;
+            event.hint.hash = H256::from_low_u64_be(hash);
+            if logs {
+                event.hint.logs = vec![EventTransactionLog::default()];
+            }
+            event.hint.txs = vec![EventTransaction {
+                to: if to { Some(Address::zero()) } else { None },
+                function_selector: None,
+                calldata: None,
+            }];
+            event
+        };
+        let arb = |event: &EventHistory, profit: u64| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event = event.clone();
+            arb.max_profit = U256::from(profit);
+            arb
+        };
+        let events = vec![
+            event(1, true, true),
+            event(2, true, true),
+            event(3, true, false),
+            event(4, false, false),
+        ];
+        let arbs = vec![
+            arb(&events[0], 1),
+            arb(&events[1], 0),
+            arb(&events[2], 1),
+            // not saved as an event, but still counted
+            arb(&event(5, true, true), 1),
+        ];
+        let efficacy = hint_efficacy(&events, &arbs);
+        assert!(!efficacy.arbs_only);
+        let rows = efficacy
+            .by_fields
+            .iter()
+            .map(|row| {
+                (
+                    row.hints.as_str(),
+                    row.num_events,
+                    row.num_simulated,
+                    row.num_profitable,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![("logs+to", 3, 3, 2), ("logs", 1, 1, 1), ("none", 1, 0, 0)]
+        );
+        let with_logs = &efficacy.by_field[0];
+        assert_eq!(with_logs.hints, "with logs");
+        assert_eq!(with_logs.num_events, 4);
+        assert!((with_logs.profitable_rate - 0.75).abs() < 1e-12);
+        // no event shared a selector
+        assert!(efficacy
+            .by_field
+            .iter()
+            .all(|row| row.hints != "with selector"));
+    }
+}
@@ -0,0 +1,240 @@
+use super::{
+    leaderboard::{keep_top, pool_label},
+    to_decimal,
+};
+use crate::{
+    data::tokens::TokenMetadataStore,
+    interfaces::{PoolVariant, SimArbResultBatch},
+};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// A route whose profitable dislocations kept coming back during the scan.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HotRoute {
+    /// Position among the hot routes of `profit_token`, starting at 1.
+    pub rank: usize,
+    pub profit_token: Address,
+    /// Token traded against `profit_token`.
+    pub token: Address,
+    pub start_pool: Address,
+    pub start_variant: PoolVariant,
+    pub start_factory: Option<Address>,
+    pub end_pool: Address,
+    pub end_variant: PoolVariant,
+    pub end_factory: Option<Address>,
+    pub num_opportunities: u64,
+    /// Number of distinct blocks that the route was profitable in.
+    pub num_blocks: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+    /// Blocks with an opportunity per day of the scanned range.
+    pub blocks_per_day: f64,
+    /// Sum of max profits, in whole profit tokens.
+    pub total_profit: f64,
+    pub avg_profit: f64,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    num_opportunities: u64,
+    blocks: BTreeSet<u64>,
+    total_profit: U256,
+    decimals: u32,
+}
+
+/// Finds routes (start pool, end pool) that were profitable in at least `min_blocks` distinct blocks,
+/// keeping the `limit` most frequent for each profit token.
+///
+/// Frequency is measured against the time range of all of `arbs`, so pass every scanned arb, not just profitable ones.
+pub fn hot_routes(arbs: &[SimArbResultBatch], min_blocks: u64, limit: usize) -> Vec<HotRoute> {
+    let first = arbs.iter().map(|arb| arb.event.timestamp).min();
+    let last = arbs.iter().map(|arb| arb.event.timestamp).max();
+    // at least one day, so short scans don't inflate frequencies
+    let days = match (first, last) {
+        (Some(first), Some(last)) => ((last - first) as f64 / SECONDS_PER_DAY).max(1.0),
+        _ => 1.0,
+    };
+
+    let mut routes: HashMap<(Address, Address, Address), (HotRoute, Accumulator)> = HashMap::new();
+    for arb in arbs {
+        let best = match arb.best_result() {
+            Some(best) if !best.backrun_trade.profit.is_zero() => best,
+            _ => continue,
+        };
+        let trade = &best.backrun_trade;
+        let (_, acc) = routes
+            .entry((arb.profit_token, trade.start_pool, trade.end_pool))
+            .or_insert_with(|| {
+                (
+                    HotRoute {
+                        rank: 0,
+                        profit_token: arb.profit_token,
+                        token: best.user_trade.tokens.token,
+                        start_pool: trade.start_pool,
+                        start_variant: trade.start_variant,
+                        start_factory: trade.start_factory,
+                        end_pool: trade.end_pool,
+                        end_variant: trade.end_variant,
+                        end_factory: trade.end_factory,
+                        num_opportunities: 0,
+                        num_blocks: 0,
+                        first_block: 0,
+                        last_block: 0,
+                        blocks_per_day: 0.0,
+                        total_profit: 0.0,
+                        avg_profit: 0.0,
+                    },
+                    Accumulator::default(),
+                )
+            });
+        acc.num_opportunities += 1;
+        acc.blocks.insert(arb.event.block);
+        acc.total_profit += trade.profit;
+        acc.decimals = arb.profit_decimals;
+    }
+
+    let mut rows = routes
+        .into_values()
+        .filter(|(_, acc)| acc.blocks.len() as u64 >= min_blocks)
+        .map(|(mut row, acc)| {
+            row.num_opportunities = acc.num_opportunities;
+            row.num_blocks = acc.blocks.len() as u64;
+            row.first_block = acc.blocks.first().copied().unwrap_or_default();
+            row.last_block = acc.blocks.last().copied().unwrap_or_default();
+            row.blocks_per_day = row.num_blocks as f64 / days;
+            row.total_profit = to_decimal(acc.total_profit, acc.decimals);
+            row.avg_profit = row.total_profit / row.num_opportunities as f64;
+            row
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        a.profit_token
+            .cmp(&b.profit_token)
+            .then(b.num_blocks.cmp(&a.num_blocks))
+            .then(b.total_profit.total_cmp(&a.total_profit))
+            .then((a.start_pool, a.end_pool).cmp(&(b.start_pool, b.end_pool)))
+    });
+    keep_top(
+        rows,
+        limit,
+        |row| row.profit_token,
+        |row, rank| row.rank = rank,
+    )
+}
+
+/// Labels a route by its pair and pools' DEXes, e.g. "PEPE/WETH Uniswap V2 -> Uniswap V3".
+fn route_label(route: &HotRoute, store: &TokenMetadataStore) -> String {
+    format!(
+        "{}/{} {} -> {}",
+        store
+            .get(&route.token)
+            .map(|token| token.symbol.to_owned())
+            .unwrap_or("?".to_owned()),
+        store.symbol(&route.profit_token),
+        pool_label(
+            route.start_factory,
+            route.start_variant,
+            store.pool(&route.start_pool).map(|pool| pool.fee)
+        ),
+        pool_label(
+            route.end_factory,
+            route.end_variant,
+            store.pool(&route.end_pool).map(|pool| pool.fee)
+        ),
+    )
+}
+
+/// Renders `routes` as a plain-text table.
+pub fn render_table(routes: &[HotRoute], store: &TokenMetadataStore) -> String {
+    let mut table = format!(
+        "{:>4} {:<48} {:<42} {:<42} {:>7} {:>9} {:>16} {:>14}\n",
+        "rank",
+        "route",
+        "start pool",
+        "end pool",
+        "blocks",
+        "per day",
+        "total profit",
+        "avg profit"
+    );
+    for route in routes {
+        table.push_str(&format!(
+            "{:>4} {:<48} {:<42} {:<42} {:>7} {:>9.2} {:>16.6} {:>14.6}\n",
+            route.rank,
+            route_label(route, store),
+            format!("{:?}", route.start_pool),
+            format!("{:?}", route.end_pool),
+            route.num_blocks,
+            route.blocks_per_day,
+            route.total_profit,
+            route.avg_profit,
+        ));
+    }
+    table
+}
+
+/// Summarizes `routes` in a few lines, to be sent as a notification.
+pub fn summary(routes: &[HotRoute], store: &TokenMetadataStore, chain_name: &str) -> String {
+    let mut summary = format!("hindsight: {} hot routes on {}\n", routes.len(), chain_name);
+    for route in routes {
+        summary.push_str(&format!(
+            "{}. {} ({:?} -> {:?}): profitable in {} blocks ({:.2}/day), {:.6} {} total\n",
+            route.rank,
+            route_label(route, store),
+            route.start_pool,
+            route.end_pool,
+            route.num_blocks,
+            route.blocks_per_day,
+            route.total_profit,
+            store.symbol(&route.profit_token),
+        ));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_finds_recurring_routes() {
+        let eth = |milli: u64| U256::from(milli) * U256::exp10(15);
+        let arb = |block: u64, start_pool: u64, profit: U256| {
+            let mut result = SimArbResult::test_example(Address::zero(), eth(1000), profit);
+            result.backrun_trade.start_pool = Address::from_low_u64_be(start_pool);
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.block = block;
+            arb.event.timestamp = block * 12;
+            arb.max_profit = profit;
+            arb.results = vec![result];
+            arb
+        };
+        let mut arbs = (0..5)
+            .map(|i| arb(100 + i * 1000, 0xc, eth(10)))
+            .collect::<Vec<_>>();
+        // same block twice: 2 opportunities, but only 1 block
+        arbs.push(arb(100, 0xc, eth(10)));
+        // profitable in too few blocks
+        arbs.push(arb(200, 0xd, eth(500)));
+        arbs.push(arb(300, 0xd, eth(500)));
+        // unprofitable
+        arbs.push(arb(400, 0xe, eth(0)));
+
+        let routes = hot_routes(&arbs, 3, 10);
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+        assert_eq!(route.start_pool, Address::from_low_u64_be(0xc));
+        assert_eq!((route.num_opportunities, route.num_blocks), (6, 5));
+        assert_eq!((route.first_block, route.last_block), (100, 4100));
+        // the scan covers less than a day
+        assert!((route.blocks_per_day - 5.0).abs() < 1e-12);
+        assert_eq!(hot_routes(&arbs, 2, 10).len(), 2);
+        assert_eq!(hot_routes(&arbs, 2, 1).len(), 1);
+    }
+}
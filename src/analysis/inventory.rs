@@ -0,0 +1,433 @@
+use super::{backrun_gas, to_decimal};
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::Address;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Shortfall (relative to the amount needed) left by rounding when funding an amount through conversions.
+const FUNDING_TOLERANCE: f64 = 1e-9;
+
+/// One asset that the inventory holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InventoryAsset {
+    pub address: Address,
+    /// Exchange symbol that the asset is priced as, e.g. ETH for WETH (see `exchange_symbol`).
+    pub symbol: String,
+    pub decimals: u32,
+    /// Balance at the start of each run, in whole tokens.
+    pub starting_balance: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct InventoryOptions {
+    /// Assets held, in order of preference for funding conversions. Only arbs quoted in one of them are replayed.
+    pub assets: Vec<InventoryAsset>,
+    /// Asset that every backrun's profit is converted into. None keeps each profit in the quote asset it was made in.
+    pub settle_into: Option<Address>,
+    /// Cost of each conversion between assets (fees & slippage), in basis points of the amount converted.
+    pub conversion_cost_bps: f64,
+    /// Asset that the inventory is valued in, which must be one of `assets`. Conversions are priced through it.
+    pub value_in: Address,
+    /// Gas used per backrun, for backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// Wrapped native token of the chain. Gas is paid out of its balance, so it's only counted if it's held.
+    pub native_token: Address,
+}
+
+/// Balance of one asset at the start & end of a run, in whole tokens.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetBalance {
+    pub address: Address,
+    pub symbol: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Outcome of replaying one run's backruns against a multi-asset inventory.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryRun {
+    /// Label of the scan run, if it had one.
+    pub run_label: Option<String>,
+    /// Symbol of the asset that profits were converted into, if any.
+    pub settle_into: Option<String>,
+    pub conversion_cost_bps: f64,
+    /// Symbol of the asset that costs & profits are valued in.
+    pub value_in: String,
+    /// Number of profitable backruns quoted in one of the inventory's assets.
+    pub num_opportunities: u64,
+    pub num_taken: u64,
+    /// Backruns skipped because the inventory couldn't cover their amount in (or gas), even with conversions.
+    pub num_unfunded: u64,
+    /// Backruns skipped because gas & conversions would have cost more than they made.
+    pub num_unprofitable: u64,
+    /// Backruns skipped because an asset they needed had no price at the time.
+    pub num_unpriced: u64,
+    pub num_conversions: u64,
+    /// Gas paid by the taken backruns, valued at the time of each.
+    pub gas_cost: f64,
+    /// Cost of every conversion, valued at the time of each.
+    pub conversion_cost: f64,
+    /// What the taken backruns made after gas & conversions, valued at the time of each.
+    pub profit: f64,
+    pub balances: Vec<AssetBalance>,
+}
+
+/// Balances of the inventory's assets (in whole tokens), and what converting between them has cost so far.
+#[derive(Clone)]
+struct Inventory {
+    balances: Vec<f64>,
+    /// Share of each conversion's amount that it costs.
+    cost: f64,
+    num_conversions: u64,
+    /// In the valuation asset.
+    conversion_cost: f64,
+}
+
+impl Inventory {
+    /// Converts `amount` of asset `from` into asset `to`, given each asset's value at the time.
+    fn convert(&mut self, from: usize, to: usize, amount: f64, values: &[Option<f64>]) {
+        let (Some(from_value), Some(to_value)) = (values[from], values[to]) else {
+            return;
+        };
+        self.balances[from] -= amount;
+        self.balances[to] += amount * from_value / to_value * (1.0 - self.cost);
+        self.num_conversions += 1;
+        self.conversion_cost += amount * from_value * self.cost;
+    }
+
+    /// Converts as much of the other (priced) assets into `target`, in order, as it takes for it to hold `amount`.
+    /// Returns whether it does.
+    fn fund(&mut self, target: usize, amount: f64, values: &[Option<f64>]) -> bool {
+        for from in 0..self.balances.len() {
+            let shortfall = amount - self.balances[target];
+            if shortfall <= 0.0 {
+                return true;
+            }
+            let (Some(from_value), Some(to_value)) = (values[from], values[target]) else {
+                continue;
+            };
+            if from == target || self.balances[from] <= 0.0 {
+                continue;
+            }
+            let needed = shortfall * to_value / from_value / (1.0 - self.cost);
+            self.convert(from, target, needed.min(self.balances[from]), values);
+        }
+        if amount - self.balances[target] > amount * FUNDING_TOLERANCE {
+            return false;
+        }
+        self.balances[target] = self.balances[target].max(amount);
+        true
+    }
+}
+
+/// Replays the profitable backruns of each run in order against an inventory of `options.assets`,
+/// and returns the outcome of each run label.
+///
+/// A backrun is taken if the inventory holds its amount in of its quote asset, converting other assets to cover any
+/// shortfall, and if what it makes is worth more than its gas (paid in the native token) and conversions.
+/// Each profit is then kept in its quote asset, or converted into `options.settle_into`.
+/// `value_of` prices a whole token (by exchange symbol) in `options.value_in` at a timestamp.
+pub fn replay_inventory(
+    arbs: &[SimArbResultBatch],
+    options: &InventoryOptions,
+    value_of: impl Fn(&str, u64) -> Option<f64>,
+) -> Vec<InventoryRun> {
+    let index = |address: Address| {
+        options
+            .assets
+            .iter()
+            .position(|asset| asset.address == address)
+    };
+    let (native, settle) = (
+        index(options.native_token),
+        options.settle_into.and_then(index),
+    );
+    let symbol_of = |address| {
+        index(address)
+            .map(|idx| options.assets[idx].symbol.to_owned())
+            .unwrap_or(format!("{:?}", address))
+    };
+    let value_symbol = symbol_of(options.value_in);
+
+    let mut runs: BTreeMap<Option<String>, Vec<&SimArbResultBatch>> = BTreeMap::new();
+    for arb in arbs {
+        let profitable = arb
+            .best_result()
+            .is_some_and(|best| !best.backrun_trade.profit.is_zero());
+        if profitable && index(arb.profit_token).is_some() {
+            runs.entry(arb.run_label.to_owned()).or_default().push(arb);
+        }
+    }
+
+    runs.into_iter()
+        .map(|(run_label, mut arbs)| {
+            arbs.sort_by_key(|arb| (arb.event.block, arb.event.timestamp));
+            let mut inventory = Inventory {
+                balances: options
+                    .assets
+                    .iter()
+                    .map(|asset| asset.starting_balance)
+                    .collect(),
+                cost: options.conversion_cost_bps / 10_000.0,
+                num_conversions: 0,
+                conversion_cost: 0.0,
+            };
+            let mut run = InventoryRun {
+                run_label,
+                settle_into: options.settle_into.map(symbol_of),
+                conversion_cost_bps: options.conversion_cost_bps,
+                value_in: value_symbol.to_owned(),
+                num_opportunities: arbs.len() as u64,
+                num_taken: 0,
+                num_unfunded: 0,
+                num_unprofitable: 0,
+                num_unpriced: 0,
+                num_conversions: 0,
+                gas_cost: 0.0,
+                conversion_cost: 0.0,
+                profit: 0.0,
+                balances: vec![],
+            };
+            for arb in arbs {
+                let quote = index(arb.profit_token).expect("arbs are quoted in a held asset");
+                let trade = &arb
+                    .best_result()
+                    .expect("arbs are profitable")
+                    .backrun_trade;
+                let decimals = options.assets[quote].decimals;
+                let (amount_in, profit) = (
+                    to_decimal(trade.amount_in, decimals),
+                    to_decimal(trade.profit, decimals),
+                );
+                let gas = native.map(|native| {
+                    let cost =
+                        arb.base_fee.unwrap_or_default() * backrun_gas(arb, options.backrun_gas);
+                    (native, to_decimal(cost, options.assets[native].decimals))
+                });
+                let values = options
+                    .assets
+                    .iter()
+                    .map(|asset| match asset.address == options.value_in {
+                        true => Some(1.0),
+                        false => value_of(&asset.symbol, arb.event.timestamp),
+                    })
+                    .collect::<Vec<_>>();
+                let needed = [Some(quote), gas.map(|(native, _)| native), settle];
+                if needed
+                    .into_iter()
+                    .flatten()
+                    .any(|idx| values[idx].is_none())
+                {
+                    run.num_unpriced += 1;
+                    continue;
+                }
+
+                let mut next = inventory.clone();
+                if !next.fund(quote, amount_in, &values) {
+                    run.num_unfunded += 1;
+                    continue;
+                }
+                next.balances[quote] += profit;
+                let mut gas_value = 0.0;
+                if let Some((native, gas)) = gas {
+                    if !next.fund(native, gas, &values) {
+                        run.num_unfunded += 1;
+                        continue;
+                    }
+                    next.balances[native] -= gas;
+                    gas_value = gas * values[native].unwrap_or_default();
+                }
+                if let Some(settle) = settle.filter(|settle| *settle != quote) {
+                    let amount = profit.min(next.balances[quote]);
+                    next.convert(quote, settle, amount, &values);
+                }
+                let conversion_value = next.conversion_cost - inventory.conversion_cost;
+                let gained =
+                    profit * values[quote].unwrap_or_default() - gas_value - conversion_value;
+                if gained <= 0.0 {
+                    run.num_unprofitable += 1;
+                    continue;
+                }
+                inventory = next;
+                run.num_taken += 1;
+                run.gas_cost += gas_value;
+                run.profit += gained;
+            }
+            run.num_conversions = inventory.num_conversions;
+            run.conversion_cost = inventory.conversion_cost;
+            run.balances = options
+                .assets
+                .iter()
+                .zip(&inventory.balances)
+                .map(|(asset, end)| AssetBalance {
+                    address: asset.address,
+                    symbol: asset.symbol.to_owned(),
+                    start: asset.starting_balance,
+                    end: *end,
+                })
+                .collect();
+            run
+        })
+        .collect()
+}
+
+/// Renders each run's summary, followed by the start & end balance of each asset.
+pub fn render_table(runs: &[InventoryRun]) -> String {
+    let mut table = String::new();
+    for run in runs {
+        table.push_str(&format!(
+            "run {}: {}, conversions cost {} bps, valued in {}\n",
+            run.run_label.as_deref().unwrap_or("(unlabelled)"),
+            match &run.settle_into {
+                Some(symbol) => format!("settling into {}", symbol),
+                None => "keeping profits in their quote asset".to_owned(),
+            },
+            run.conversion_cost_bps,
+            run.value_in
+        ));
+        table.push_str(&format!(
+            "  {} opportunities, {} taken; skipped {} unfunded, {} unprofitable after costs, {} unpriced\n",
+            run.num_opportunities,
+            run.num_taken,
+            run.num_unfunded,
+            run.num_unprofitable,
+            run.num_unpriced
+        ));
+        table.push_str(&format!(
+            "  profit {:.6} {} after {:.6} gas & {:.6} in {} conversions\n",
+            run.profit, run.value_in, run.gas_cost, run.conversion_cost, run.num_conversions
+        ));
+        table.push_str(&format!("  {:<8} {:>18} {:>18}\n", "asset", "start", "end"));
+        for balance in &run.balances {
+            table.push_str(&format!(
+                "  {:<8} {:>18.6} {:>18.6}\n",
+                balance.symbol, balance.start, balance.end
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::known_wrapped_native, interfaces::SimArbResult};
+    use ethers::{types::U256, utils::parse_units};
+
+    fn units(amount: f64, decimals: u32) -> U256 {
+        parse_units(amount.to_string(), decimals).unwrap().into()
+    }
+
+    /// A backrun of `amount_in` that made `profit` (in whole tokens of `asset`) at `timestamp`.
+    fn arb(
+        asset: &InventoryAsset,
+        timestamp: u64,
+        amount_in: f64,
+        profit: f64,
+    ) -> SimArbResultBatch {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.block = timestamp;
+        arb.event.timestamp = timestamp;
+        arb.profit_token = asset.address;
+        arb.profit_decimals = asset.decimals;
+        arb.base_fee = None;
+        let (amount_in, profit) = (
+            units(amount_in, asset.decimals),
+            units(profit, asset.decimals),
+        );
+        let mut result = SimArbResult::test_example(Address::zero(), amount_in, profit);
+        result.user_trade.tokens.quote = asset.address;
+        arb.results = vec![result];
+        arb.max_profit = profit;
+        arb
+    }
+
+    /// 1 WETH & no USDC, with ETH at 2000 USDC until timestamp 100.
+    fn setup() -> (InventoryAsset, InventoryAsset, InventoryOptions) {
+        let weth = InventoryAsset {
+            address: known_wrapped_native(1).unwrap(),
+            symbol: "ETH".to_owned(),
+            decimals: 18,
+            starting_balance: 1.0,
+        };
+        let usdc = InventoryAsset {
+            address: Address::from_low_u64_be(0xc),
+            symbol: "USDC".to_owned(),
+            decimals: 6,
+            starting_balance: 0.0,
+        };
+        let options = InventoryOptions {
+            assets: vec![weth.clone(), usdc.clone()],
+            settle_into: Some(usdc.address),
+            conversion_cost_bps: 100.0,
+            value_in: usdc.address,
+            backrun_gas: 300_000,
+            native_token: weth.address,
+        };
+        (weth, usdc, options)
+    }
+
+    fn value_of(symbol: &str, timestamp: u64) -> Option<f64> {
+        (symbol == "ETH" && timestamp < 100).then_some(2000.0)
+    }
+
+    #[test]
+    fn it_converts_inventory_to_fund_and_settle_backruns() {
+        let (weth, usdc, options) = setup();
+        let mut costly = arb(&weth, 4, 0.1, 0.001);
+        costly.base_fee = Some(U256::from(10_000_000_000u64)); // 0.003 ETH of gas
+        let arbs = vec![
+            // settled into 200 USDC, less 1%
+            arb(&weth, 1, 1.0, 0.1),
+            // 802 USDC short, converted from WETH
+            arb(&usdc, 2, 1000.0, 10.0),
+            // the inventory is worth less than 5 WETH
+            arb(&weth, 3, 5.0, 1.0),
+            // gas takes more than the profit
+            costly,
+            arb(&weth, 100, 1.0, 0.1),
+        ];
+        let runs = replay_inventory(&arbs, &options, value_of);
+        assert_eq!(runs.len(), 1);
+        let run = &runs[0];
+        assert_eq!(run.settle_into.as_deref(), Some("USDC"));
+        assert_eq!(run.num_opportunities, 5);
+        assert_eq!(run.num_taken, 2);
+        assert_eq!(
+            (run.num_unfunded, run.num_unprofitable, run.num_unpriced),
+            (1, 1, 1)
+        );
+        assert_eq!(run.num_conversions, 2);
+        let sold = 802.0 / 2000.0 / 0.99;
+        assert!((run.conversion_cost - (2.0 + sold * 20.0)).abs() < 1e-9);
+        assert!((run.profit - (200.0 + 10.0 - run.conversion_cost)).abs() < 1e-9);
+        assert!((run.balances[0].end - (1.0 - sold)).abs() < 1e-9);
+        assert!((run.balances[1].end - 1010.0).abs() < 1e-9);
+        assert!(render_table(&runs).contains("settling into USDC"));
+    }
+
+    #[test]
+    fn it_keeps_profits_in_their_quote_asset() {
+        let (weth, usdc, options) = setup();
+        let options = InventoryOptions {
+            assets: vec![
+                weth.clone(),
+                InventoryAsset {
+                    starting_balance: 1000.0,
+                    ..usdc.clone()
+                },
+            ],
+            settle_into: None,
+            ..options
+        };
+        let arbs = vec![arb(&weth, 1, 1.0, 0.1), arb(&usdc, 2, 1000.0, 10.0)];
+        let run = &replay_inventory(&arbs, &options, value_of)[0];
+        assert_eq!((run.num_taken, run.num_conversions), (2, 0));
+        assert!((run.balances[0].end - 1.1).abs() < 1e-9);
+        assert!((run.balances[1].end - 1010.0).abs() < 1e-9);
+        assert!((run.profit - 210.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,290 @@
+use super::to_decimal;
+use crate::{
+    interfaces::{QuoteAsset, SimArbResultBatch},
+    sim::{core::sim_backrun_at, pools::PoolAdapters},
+    util::{get_block_info, WsClient},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// Delayed backruns are tried with the original `amount_in` divided by each of these,
+/// since less is usually needed once the price has partly recovered.
+pub const AMOUNT_DIVISORS: [u64; 5] = [1, 2, 4, 8, 16];
+
+/// Best profit of one arb's backrun route after each delay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelayedProfits {
+    pub profit_token: Address,
+    pub profit_decimals: u32,
+    /// Profit with the backrun delayed by `i` blocks. Index 0 is the original simulation.
+    pub profits: Vec<U256>,
+}
+
+/// Profit that the sampled arbs kept after one delay.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DelayPoint {
+    /// Blocks that the backrun was delayed by.
+    pub delay: u64,
+    /// Number of sampled arbs still profitable.
+    pub num_profitable: u64,
+    /// Sum of profits, in whole tokens.
+    pub profit: f64,
+    /// `profit` as a share of the undelayed profit (0 to 1).
+    pub retained: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyDecay {
+    pub profit_token: Address,
+    pub num_sampled: u64,
+    /// First delay that kept at most half of the undelayed profit, if any did.
+    pub half_life: Option<u64>,
+    pub points: Vec<DelayPoint>,
+}
+
+/// Picks up to `size` profitable arbs with stored results, evenly spaced over time.
+pub fn sample(arbs: &[SimArbResultBatch], size: usize) -> Vec<&SimArbResultBatch> {
+    let candidates = arbs
+        .iter()
+        .filter(|arb| !arb.max_profit.is_zero() && arb.best_result().is_some())
+        .collect::<Vec<_>>();
+    sample_evenly(candidates, size)
+}
+
+/// Picks up to `size` of `candidates`, evenly spaced over time. A `size` of 0 picks all of them.
+pub fn sample_evenly(
+    mut candidates: Vec<&SimArbResultBatch>,
+    size: usize,
+) -> Vec<&SimArbResultBatch> {
+    candidates.sort_by_key(|arb| (arb.event.timestamp, arb.event.hint.hash));
+    if size == 0 || candidates.len() <= size {
+        return candidates;
+    }
+    (0..size)
+        .map(|i| candidates[i * candidates.len() / size])
+        .collect()
+}
+
+/// Re-simulates `arb`'s best backrun route with the backrun delayed by 1..=`max_delay` blocks.
+///
+/// A backrun delayed by `n` blocks runs on the state at the end of the `n`th block after the one
+/// before the user's tx, so it sees every tx that landed in between (including other backruns).
+/// Returns None if the user's tx can't be found, or its quote asset isn't in `quote_assets`.
+pub async fn simulate_delays(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    quote_assets: &[QuoteAsset],
+    max_delay: u64,
+    adapters: &PoolAdapters,
+) -> Result<Option<DelayedProfits>> {
+    let profits = simulate_route(client, arb, quote_assets, 1..=max_delay, adapters).await?;
+    Ok(profits.map(|profits| DelayedProfits {
+        profit_token: arb.profit_token,
+        profit_decimals: arb.profit_decimals,
+        profits,
+    }))
+}
+
+/// Like `simulate_delays`, for a single delay of `delay` blocks.
+pub async fn simulate_delay(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    quote_assets: &[QuoteAsset],
+    delay: u64,
+    adapters: &PoolAdapters,
+) -> Result<Option<U256>> {
+    let profits = simulate_route(client, arb, quote_assets, delay..=delay, adapters).await?;
+    Ok(profits.and_then(|profits| profits.get(1).copied()))
+}
+
+/// Best profit of `arb`'s best backrun route undelayed, then with the backrun delayed by each of `delays`.
+async fn simulate_route(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    quote_assets: &[QuoteAsset],
+    delays: RangeInclusive<u64>,
+    adapters: &PoolAdapters,
+) -> Result<Option<Vec<U256>>> {
+    let best = match arb.best_result() {
+        Some(best) => best,
+        None => return Ok(None),
+    };
+    let quote_asset = match quote_assets
+        .iter()
+        .find(|asset| asset.address == best.user_trade.tokens.quote)
+    {
+        Some(asset) => asset,
+        None => return Ok(None),
+    };
+    let user_block = match client.get_transaction(arb.event.hint.hash).await? {
+        Some(tx) => match tx.block_number {
+            Some(block) => block.as_u64(),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let amounts_in = AMOUNT_DIVISORS
+        .iter()
+        .map(|divisor| best.backrun_trade.amount_in / *divisor)
+        .filter(|amount_in| !amount_in.is_zero())
+        .collect::<Vec<_>>();
+    let mut profits = vec![best.backrun_trade.profit];
+    for delay in delays {
+        let block_info = get_block_info(client, user_block - 1 + delay).await?;
+        profits.push(
+            sim_backrun_at(
+                client,
+                &block_info,
+                best,
+                quote_asset,
+                &amounts_in,
+                adapters,
+            )
+            .await?,
+        );
+    }
+    Ok(Some(profits))
+}
+
+/// Sums delayed profits into one decay curve per profit token.
+pub fn decay_curves(samples: &[DelayedProfits]) -> Vec<LatencyDecay> {
+    let mut tokens: BTreeMap<Address, Vec<&DelayedProfits>> = BTreeMap::new();
+    for sample in samples {
+        tokens.entry(sample.profit_token).or_default().push(sample);
+    }
+    tokens
+        .into_iter()
+        .map(|(profit_token, samples)| {
+            let decimals = samples[0].profit_decimals;
+            let num_delays = samples.iter().map(|s| s.profits.len()).max().unwrap_or(0);
+            let mut points = vec![];
+            for delay in 0..num_delays {
+                let profits = samples
+                    .iter()
+                    .map(|s| s.profits.get(delay).copied().unwrap_or_default());
+                let num_profitable = profits.clone().filter(|p| !p.is_zero()).count() as u64;
+                let profit = to_decimal(profits.fold(U256::zero(), |a, b| a + b), decimals);
+                points.push(DelayPoint {
+                    delay: delay as u64,
+                    num_profitable,
+                    profit,
+                    retained: 0.0,
+                });
+            }
+            let undelayed = points.first().map(|point| point.profit).unwrap_or(0.0);
+            for point in points.iter_mut() {
+                point.retained = if undelayed > 0.0 {
+                    point.profit / undelayed
+                } else {
+                    0.0
+                };
+            }
+            LatencyDecay {
+                profit_token,
+                num_sampled: samples.len() as u64,
+                half_life: points
+                    .iter()
+                    .find(|point| point.retained <= 0.5)
+                    .map(|point| point.delay),
+                points,
+            }
+        })
+        .collect()
+}
+
+/// Renders each curve as a plain-text table, with a bar per delay showing the profit retained.
+pub fn render_table(curves: &[LatencyDecay]) -> String {
+    let mut table = String::new();
+    for curve in curves {
+        table.push_str(&format!(
+            "profit token {:?}: {} arbs sampled, half-life {}\n",
+            curve.profit_token,
+            curve.num_sampled,
+            curve
+                .half_life
+                .map(|delay| format!("{} blocks", delay))
+                .unwrap_or("not reached".to_owned())
+        ));
+        table.push_str(&format!(
+            "{:>6} {:>10} {:>16} {:>9}\n",
+            "delay", "profitable", "profit", "retained"
+        ));
+        for point in &curve.points {
+            table.push_str(&format!(
+                "{:>6} {:>10} {:>16.6} {:>8.1}% {}\n",
+                point.delay,
+                point.num_profitable,
+                point.profit,
+                point.retained * 100.0,
+                "#".repeat((point.retained.clamp(0.0, 1.0) * 40.0).round() as usize)
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_samples_evenly() {
+        let arbs = (0..10u64)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.timestamp = i;
+                arb.max_profit = U256::from(i);
+                arb.results = vec![SimArbResult::test_example(
+                    Address::zero(),
+                    U256::exp10(18),
+                    U256::from(i),
+                )];
+                arb
+            })
+            .collect::<Vec<_>>();
+        // arb 0 isn't profitable
+        let timestamps = sample(&arbs, 3)
+            .iter()
+            .map(|arb| arb.event.timestamp)
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![1, 4, 7]);
+        assert_eq!(sample(&arbs, 20).len(), 9);
+    }
+
+    #[test]
+    fn it_computes_decay_curves() {
+        let weth = Address::from_low_u64_be(0x1);
+        let delayed = |profits: [u64; 3]| DelayedProfits {
+            profit_token: weth,
+            profit_decimals: 18,
+            profits: profits
+                .iter()
+                .map(|milli_eth| U256::from(*milli_eth) * U256::exp10(15))
+                .collect(),
+        };
+        let curves = decay_curves(&[delayed([30, 20, 0]), delayed([10, 0, 0])]);
+        assert_eq!(curves.len(), 1);
+        let curve = &curves[0];
+        assert_eq!(curve.num_sampled, 2);
+        assert_eq!(
+            curve
+                .points
+                .iter()
+                .map(|point| point.num_profitable)
+                .collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+        assert!((curve.points[0].profit - 0.04).abs() < 1e-12);
+        assert!((curve.points[1].retained - 0.5).abs() < 1e-12);
+        assert_eq!(curve.half_life, Some(1));
+    }
+}
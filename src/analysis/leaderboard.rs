@@ -0,0 +1,529 @@
+use super::to_decimal;
+use crate::{
+    config::known_dex_name,
+    data::tokens::TokenMetadataStore,
+    interfaces::{PoolVariant, SimArbResultBatch},
+};
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+pub const LEADERBOARD_KIND_NAMES: [&'static str; 3] = ["tokens", "routes", "dexes"];
+
+/// What a leaderboard ranks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LeaderboardKind {
+    #[default]
+    Tokens,
+    /// (start pool, end pool) pairs that backruns traded through.
+    Routes,
+    /// (start DEX, end DEX) pairs, where a DEX is a factory, pool variant & fee tier.
+    Dexes,
+}
+
+impl fmt::Display for LeaderboardKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeaderboardKind::Tokens => write!(f, "tokens"),
+            LeaderboardKind::Routes => write!(f, "routes"),
+            LeaderboardKind::Dexes => write!(f, "dexes"),
+        }
+    }
+}
+
+impl FromStr for LeaderboardKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokens" => Ok(LeaderboardKind::Tokens),
+            "routes" => Ok(LeaderboardKind::Routes),
+            "dexes" => Ok(LeaderboardKind::Dexes),
+            _ => Err(format!("invalid leaderboard: {}", s)),
+        }
+    }
+}
+
+/// Profitability of backruns on one token, for one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRow {
+    /// Position on the leaderboard of `profit_token`, starting at 1.
+    pub rank: usize,
+    pub token: Address,
+    /// Token symbol, if known to the token metadata store.
+    pub symbol: Option<String>,
+    pub profit_token: Address,
+    /// Number of events with a profitable backrun on this token.
+    pub num_opportunities: u64,
+    /// Sum of max profits, in whole profit tokens.
+    pub total_profit: f64,
+    pub avg_profit: f64,
+    /// Average backrun `amount_in`, in whole profit tokens.
+    pub avg_amount_in: f64,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    num_opportunities: u64,
+    total_profit: U256,
+    total_amount_in: U256,
+    decimals: u32,
+}
+
+/// Ranks tokens by total simulated profit, keeping the top `limit` for each profit token.
+///
+/// Each profitable event is credited to the token of its most profitable backrun.
+/// Profits in different tokens can't be compared, so each profit token gets its own ranking.
+pub fn token_leaderboard(arbs: &[SimArbResultBatch], limit: usize) -> Vec<TokenRow> {
+    let mut tokens: HashMap<(Address, Address), Accumulator> = HashMap::new();
+    for arb in arbs {
+        let best = match arb.best_result() {
+            Some(best) if !best.backrun_trade.profit.is_zero() => best,
+            _ => continue,
+        };
+        let acc = tokens
+            .entry((arb.profit_token, best.user_trade.tokens.token))
+            .or_default();
+        acc.num_opportunities += 1;
+        acc.total_profit += best.backrun_trade.profit;
+        acc.total_amount_in += best.backrun_trade.amount_in;
+        acc.decimals = arb.profit_decimals;
+    }
+
+    let mut rows = tokens
+        .into_iter()
+        .map(|((profit_token, token), acc)| {
+            let total_profit = to_decimal(acc.total_profit, acc.decimals);
+            let num = acc.num_opportunities as f64;
+            TokenRow {
+                rank: 0,
+                token,
+                symbol: None,
+                profit_token,
+                num_opportunities: acc.num_opportunities,
+                total_profit,
+                avg_profit: total_profit / num,
+                avg_amount_in: to_decimal(acc.total_amount_in, acc.decimals) / num,
+            }
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        a.profit_token
+            .cmp(&b.profit_token)
+            .then(b.total_profit.total_cmp(&a.total_profit))
+            .then(a.token.cmp(&b.token))
+    });
+    keep_top(
+        rows,
+        limit,
+        |row| row.profit_token,
+        |row, rank| row.rank = rank,
+    )
+}
+
+/// Ranks `rows` (sorted by profit token, then best first) within each profit token, keeping the top `limit` of each.
+pub fn keep_top<T>(
+    rows: Vec<T>,
+    limit: usize,
+    profit_token: impl Fn(&T) -> Address,
+    set_rank: impl Fn(&mut T, usize),
+) -> Vec<T> {
+    let mut ranked = vec![];
+    let mut rank = 0;
+    let mut last_profit_token = None;
+    for mut row in rows {
+        if last_profit_token != Some(profit_token(&row)) {
+            last_profit_token = Some(profit_token(&row));
+            rank = 0;
+        }
+        rank += 1;
+        if rank <= limit {
+            set_rank(&mut row, rank);
+            ranked.push(row);
+        }
+    }
+    ranked
+}
+
+/// Profitability of backruns through one (start pool, end pool) route.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteRow {
+    /// Position on the leaderboard of `profit_token`, starting at 1.
+    pub rank: usize,
+    pub start_pool: Address,
+    pub start_variant: PoolVariant,
+    /// Factory of the start pool, if it was recorded.
+    pub start_factory: Option<Address>,
+    /// Pool fee in hundredths of a bip, if known to the token metadata store.
+    pub start_fee: Option<u32>,
+    pub end_pool: Address,
+    pub end_variant: PoolVariant,
+    pub end_factory: Option<Address>,
+    pub end_fee: Option<u32>,
+    /// Token traded against `profit_token`.
+    pub token: Address,
+    pub symbol: Option<String>,
+    pub profit_token: Address,
+    /// Number of events whose most profitable backrun took this route.
+    pub num_opportunities: u64,
+    /// Sum of max profits, in whole profit tokens.
+    pub total_profit: f64,
+    pub avg_profit: f64,
+}
+
+/// Ranks routes by total simulated profit, keeping the top `limit` for each profit token.
+///
+/// Each profitable event is credited to the route of its most profitable backrun.
+pub fn route_leaderboard(arbs: &[SimArbResultBatch], limit: usize) -> Vec<RouteRow> {
+    let mut routes: HashMap<(Address, Address, Address), (RouteRow, U256, u32)> = HashMap::new();
+    for arb in arbs {
+        let best = match arb.best_result() {
+            Some(best) if !best.backrun_trade.profit.is_zero() => best,
+            _ => continue,
+        };
+        let trade = &best.backrun_trade;
+        let (row, total_profit, decimals) = routes
+            .entry((arb.profit_token, trade.start_pool, trade.end_pool))
+            .or_insert_with(|| {
+                (
+                    RouteRow {
+                        rank: 0,
+                        start_pool: trade.start_pool,
+                        start_variant: trade.start_variant,
+                        start_factory: trade.start_factory,
+                        start_fee: None,
+                        end_pool: trade.end_pool,
+                        end_variant: trade.end_variant,
+                        end_factory: trade.end_factory,
+                        end_fee: None,
+                        token: best.user_trade.tokens.token,
+                        symbol: None,
+                        profit_token: arb.profit_token,
+                        num_opportunities: 0,
+                        total_profit: 0.0,
+                        avg_profit: 0.0,
+                    },
+                    U256::zero(),
+                    arb.profit_decimals,
+                )
+            });
+        row.num_opportunities += 1;
+        *total_profit += trade.profit;
+        *decimals = arb.profit_decimals;
+    }
+
+    let mut rows = routes
+        .into_values()
+        .map(|(mut row, total_profit, decimals)| {
+            row.total_profit = to_decimal(total_profit, decimals);
+            row.avg_profit = row.total_profit / row.num_opportunities as f64;
+            row
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        a.profit_token
+            .cmp(&b.profit_token)
+            .then(b.total_profit.total_cmp(&a.total_profit))
+            .then((a.start_pool, a.end_pool).cmp(&(b.start_pool, b.end_pool)))
+    });
+    keep_top(
+        rows,
+        limit,
+        |row| row.profit_token,
+        |row, rank| row.rank = rank,
+    )
+}
+
+/// Profitability of backruns between two DEXes, summed over every route between them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DexRow {
+    /// Position on the leaderboard of `profit_token`, starting at 1.
+    pub rank: usize,
+    /// DEX of the start pool, as labelled by `pool_label`.
+    pub start_dex: String,
+    pub end_dex: String,
+    pub profit_token: Address,
+    /// Number of distinct routes between the two DEXes.
+    pub num_routes: u64,
+    pub num_opportunities: u64,
+    /// Sum of max profits, in whole profit tokens.
+    pub total_profit: f64,
+    /// Fraction of all of `profit_token`'s profit that was made between these DEXes.
+    pub profit_share: f64,
+}
+
+/// Groups `routes` by their (start DEX, end DEX) pair, keeping the top `limit` pairs for each profit token.
+///
+/// Pass every route (not just the top routes) with its metadata joined, so that fee tiers are known
+/// and the totals cover all profit.
+pub fn dex_leaderboard(routes: &[RouteRow], limit: usize) -> Vec<DexRow> {
+    let mut dexes: HashMap<(Address, String, String), DexRow> = HashMap::new();
+    let mut totals: HashMap<Address, f64> = HashMap::new();
+    for route in routes {
+        let start_dex = pool_label(route.start_factory, route.start_variant, route.start_fee);
+        let end_dex = pool_label(route.end_factory, route.end_variant, route.end_fee);
+        let row = dexes
+            .entry((route.profit_token, start_dex.to_owned(), end_dex.to_owned()))
+            .or_insert_with(|| DexRow {
+                rank: 0,
+                start_dex,
+                end_dex,
+                profit_token: route.profit_token,
+                num_routes: 0,
+                num_opportunities: 0,
+                total_profit: 0.0,
+                profit_share: 0.0,
+            });
+        row.num_routes += 1;
+        row.num_opportunities += route.num_opportunities;
+        row.total_profit += route.total_profit;
+        *totals.entry(route.profit_token).or_default() += route.total_profit;
+    }
+
+    let mut rows = dexes
+        .into_values()
+        .map(|mut row| {
+            let total = totals[&row.profit_token];
+            row.profit_share = if total > 0.0 {
+                row.total_profit / total
+            } else {
+                0.0
+            };
+            row
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        a.profit_token
+            .cmp(&b.profit_token)
+            .then(b.total_profit.total_cmp(&a.total_profit))
+            .then((&a.start_dex, &a.end_dex).cmp(&(&b.start_dex, &b.end_dex)))
+    });
+    keep_top(
+        rows,
+        limit,
+        |row| row.profit_token,
+        |row, rank| row.rank = rank,
+    )
+}
+
+/// Fills in each row's `symbol` from `store`.
+pub fn join_symbols(rows: &mut [TokenRow], store: &TokenMetadataStore) {
+    for row in rows {
+        row.symbol = store.get(&row.token).map(|token| token.symbol.to_owned());
+    }
+}
+
+/// Fills in each row's token symbol and pool fees from `store`.
+pub fn join_route_metadata(rows: &mut [RouteRow], store: &TokenMetadataStore) {
+    for row in rows {
+        row.symbol = store.get(&row.token).map(|token| token.symbol.to_owned());
+        row.start_fee = store.pool(&row.start_pool).map(|pool| pool.fee);
+        row.end_fee = store.pool(&row.end_pool).map(|pool| pool.fee);
+    }
+}
+
+/// Names a pool by its DEX, variant and fee tier, e.g. "Uniswap V3 5bp" or "Sushiswap V2".
+///
+/// Pools of unknown factories are named by variant & factory address,
+/// and pools whose factory wasn't recorded by variant alone (e.g. "UniswapV2").
+pub fn pool_label(factory: Option<Address>, variant: PoolVariant, fee: Option<u32>) -> String {
+    let dex = match factory {
+        Some(factory) => match known_dex_name(factory) {
+            Some(name) => match variant {
+                PoolVariant::UniswapV2 => format!("{} V2", name),
+                PoolVariant::UniswapV3 => format!("{} V3", name),
+                PoolVariant::Custom(_) => format!("{} {:?}", name, variant),
+            },
+            None => format!("{:?} clone {:?}", variant, factory),
+        },
+        None => format!("{:?}", variant),
+    };
+    match fee {
+        Some(fee) => format!("{} {}bp", dex, fee as f64 / 100.0),
+        None => dex,
+    }
+}
+
+/// Renders `rows` as a plain-text table, labelling each route by its pair and pools.
+pub fn render_route_table(rows: &[RouteRow], store: &TokenMetadataStore) -> String {
+    let mut table = format!(
+        "{:>4} {:<20} {:<42} {:<16} {:<42} {:<16} {:>8} {:>16} {:>14}\n",
+        "rank", "pair", "start pool", "", "end pool", "", "opps", "total profit", "avg profit"
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "{:>4} {:<20} {:<42} {:<16} {:<42} {:<16} {:>8} {:>16.6} {:>14.6}\n",
+            row.rank,
+            format!(
+                "{}/{}",
+                row.symbol.as_deref().unwrap_or("?"),
+                store.symbol(&row.profit_token)
+            ),
+            format!("{:?}", row.start_pool),
+            pool_label(row.start_factory, row.start_variant, row.start_fee),
+            format!("{:?}", row.end_pool),
+            pool_label(row.end_factory, row.end_variant, row.end_fee),
+            row.num_opportunities,
+            row.total_profit,
+            row.avg_profit,
+        ));
+    }
+    table
+}
+
+/// Renders `rows` as a plain-text table, with each DEX pair's share of its profit token's total profit.
+pub fn render_dex_table(rows: &[DexRow], store: &TokenMetadataStore) -> String {
+    let mut table = format!(
+        "{:>4} {:<24} {:<24} {:<12} {:>7} {:>8} {:>16} {:>7}\n",
+        "rank", "start dex", "end dex", "profit in", "routes", "opps", "total profit", "share"
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "{:>4} {:<24} {:<24} {:<12} {:>7} {:>8} {:>16.6} {:>6.1}%\n",
+            row.rank,
+            row.start_dex,
+            row.end_dex,
+            store.symbol(&row.profit_token),
+            row.num_routes,
+            row.num_opportunities,
+            row.total_profit,
+            row.profit_share * 100.0,
+        ));
+    }
+    table
+}
+
+/// Renders `rows` as a plain-text table. Profit tokens are shown by symbol when `store` knows them.
+pub fn render_table(rows: &[TokenRow], store: &TokenMetadataStore) -> String {
+    let mut table = format!(
+        "{:>4} {:<12} {:<42} {:<12} {:>8} {:>16} {:>14} {:>14}\n",
+        "rank", "symbol", "token", "profit in", "opps", "total profit", "avg profit", "avg size"
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "{:>4} {:<12} {:<42} {:<12} {:>8} {:>16.6} {:>14.6} {:>14.4}\n",
+            row.rank,
+            row.symbol.as_deref().unwrap_or("?"),
+            format!("{:?}", row.token),
+            store.symbol(&row.profit_token),
+            row.num_opportunities,
+            row.total_profit,
+            row.avg_profit,
+            row.avg_amount_in,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_ranks_tokens_by_profit() {
+        let shib = Address::from_low_u64_be(0x1);
+        let pepe = Address::from_low_u64_be(0x2);
+        let eth = |milli: u64| U256::from(milli) * U256::exp10(15);
+        let arb = |results: Vec<SimArbResult>| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.max_profit = results
+                .iter()
+                .map(|result| result.backrun_trade.profit)
+                .max()
+                .unwrap_or_default();
+            arb.results = results;
+            arb
+        };
+        let arbs = vec![
+            arb(vec![SimArbResult::test_example(shib, eth(1000), eth(10))]),
+            arb(vec![SimArbResult::test_example(shib, eth(3000), eth(20))]),
+            // only the best result of an event counts
+            arb(vec![
+                SimArbResult::test_example(shib, eth(1000), eth(5)),
+                SimArbResult::test_example(pepe, eth(2000), eth(40)),
+            ]),
+            // unprofitable
+            arb(vec![SimArbResult::test_example(pepe, eth(1000), eth(0))]),
+        ];
+        let rows = token_leaderboard(&arbs, 10);
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].rank, rows[0].token), (1, pepe));
+        assert_eq!(rows[0].num_opportunities, 1);
+        assert_eq!((rows[1].rank, rows[1].token), (2, shib));
+        assert_eq!(rows[1].num_opportunities, 2);
+        assert!((rows[1].total_profit - 0.03).abs() < 1e-12);
+        assert!((rows[1].avg_profit - 0.015).abs() < 1e-12);
+        assert!((rows[1].avg_amount_in - 2.0).abs() < 1e-12);
+
+        assert_eq!(token_leaderboard(&arbs, 1).len(), 1);
+    }
+
+    #[test]
+    fn it_ranks_routes_by_profit() {
+        let shib = Address::from_low_u64_be(0x1);
+        let eth = |milli: u64| U256::from(milli) * U256::exp10(15);
+        let arb = |start_pool: u64, profit: U256| {
+            let mut result = SimArbResult::test_example(shib, eth(1000), profit);
+            result.backrun_trade.start_pool = Address::from_low_u64_be(start_pool);
+            result.backrun_trade.start_variant = PoolVariant::UniswapV3;
+            let mut arb = SimArbResultBatch::test_example();
+            arb.max_profit = profit;
+            arb.results = vec![result];
+            arb
+        };
+        let arbs = vec![arb(0xc, eth(10)), arb(0xc, eth(10)), arb(0xd, eth(15))];
+        let rows = route_leaderboard(&arbs, 10);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].start_pool, Address::from_low_u64_be(0xc));
+        assert_eq!(rows[0].start_variant, PoolVariant::UniswapV3);
+        assert_eq!(rows[0].end_pool, Address::from_low_u64_be(0xb));
+        assert_eq!(rows[0].num_opportunities, 2);
+        assert!((rows[0].total_profit - 0.02).abs() < 1e-12);
+        assert_eq!((rows[1].rank, rows[1].num_opportunities), (2, 1));
+    }
+
+    #[test]
+    fn it_ranks_dexes_by_profit() {
+        let shib = Address::from_low_u64_be(0x1);
+        let uniswap_v2 = crate::config::UNISWAP_V2_FACTORY.parse().ok();
+        let sushiswap = crate::config::SUSHISWAP_FACTORY.parse().ok();
+        let eth = |milli: u64| U256::from(milli) * U256::exp10(15);
+        let arb = |start_pool: u64, start_factory: Option<Address>, profit: U256| {
+            let mut result = SimArbResult::test_example(shib, eth(1000), profit);
+            result.backrun_trade.start_pool = Address::from_low_u64_be(start_pool);
+            result.backrun_trade.start_factory = start_factory;
+            result.backrun_trade.end_factory = uniswap_v2;
+            let mut arb = SimArbResultBatch::test_example();
+            arb.max_profit = profit;
+            arb.results = vec![result];
+            arb
+        };
+        let arbs = vec![
+            arb(0xc, sushiswap, eth(10)),
+            arb(0xd, sushiswap, eth(20)),
+            // factory wasn't recorded
+            arb(0xe, None, eth(10)),
+        ];
+        let routes = route_leaderboard(&arbs, usize::MAX);
+        let rows = dex_leaderboard(&routes, 10);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            (rows[0].start_dex.as_str(), rows[0].end_dex.as_str()),
+            ("Sushiswap V2", "Uniswap V2")
+        );
+        assert_eq!((rows[0].num_routes, rows[0].num_opportunities), (2, 2));
+        assert!((rows[0].profit_share - 0.75).abs() < 1e-12);
+        assert_eq!(rows[1].start_dex, "UniswapV2");
+        assert_eq!(
+            pool_label(
+                crate::config::UNISWAP_V3_FACTORY.parse().ok(),
+                PoolVariant::UniswapV3,
+                Some(500)
+            ),
+            "Uniswap V3 5bp"
+        );
+    }
+}
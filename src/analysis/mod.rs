@@ -0,0 +1,132 @@
+pub mod allocation;
+pub mod bids;
+pub mod cex_dex;
+pub mod clusters;
+pub mod counterfactual;
+pub mod cross_chain;
+pub mod drift;
+pub mod failures;
+pub mod gas;
+pub mod hints;
+pub mod hot_routes;
+pub mod inventory;
+pub mod latency;
+pub mod leaderboard;
+pub mod pnl;
+pub mod position;
+pub mod realized;
+pub mod reconcile;
+pub mod report;
+pub mod sizing;
+pub mod stats;
+pub mod sweep;
+pub mod volatility;
+
+use crate::{
+    data::arbs::{ArbDatabase, ArbFilterParams},
+    info,
+    interfaces::{SimArbResultBatch, SimFailure},
+    Result,
+};
+use ethers::{types::U256, utils::format_units};
+use mev_share_sse::EventHistory;
+
+const NUM_ARBS_PER_READ: i64 = 3000;
+
+/// Reads every arb matching `filter` from `db`.
+pub async fn read_all_arbs(
+    db: &ArbDatabase,
+    filter: &ArbFilterParams,
+) -> Result<Vec<SimArbResultBatch>> {
+    // count first so a concurrently running scan can't keep us reading forever
+    let total_arbs = db.get_num_arbs(filter).await?;
+    let mut arbs = vec![];
+    while (arbs.len() as u64) < total_arbs {
+        let mut page = db
+            .read_arbs(filter, Some(arbs.len() as u64), Some(NUM_ARBS_PER_READ))
+            .await?;
+        if page.is_empty() {
+            break;
+        }
+        arbs.append(&mut page);
+    }
+    info!("read {} arbs", arbs.len());
+    // simulated on blocks that were reorged out, so they never reflected the chain
+    let num_read = arbs.len();
+    arbs.retain(|arb| !arb.orphaned);
+    if arbs.len() < num_read {
+        info!("skipped {} orphaned arbs", num_read - arbs.len());
+    }
+    Ok(arbs)
+}
+
+/// Reads every arb matching `filter` from `db`, keeping one canonical arb for each opportunity
+/// (see `clusters::dedupe_arbs`) so that profit exposed by several events only counts once.
+pub async fn read_opportunities(
+    db: &ArbDatabase,
+    filter: &ArbFilterParams,
+) -> Result<Vec<SimArbResultBatch>> {
+    let arbs = read_all_arbs(db, filter).await?;
+    let num_events = arbs.len();
+    let arbs = clusters::dedupe_arbs(arbs);
+    info!(
+        "merged {} duplicate events into their opportunities",
+        num_events - arbs.len()
+    );
+    Ok(arbs)
+}
+
+/// Reads every saved event matching the block & timestamp ranges of `filter` from `db`.
+pub async fn read_all_events(
+    db: &ArbDatabase,
+    filter: &ArbFilterParams,
+) -> Result<Vec<EventHistory>> {
+    let mut events = vec![];
+    loop {
+        let mut page = db
+            .read_events(filter, Some(events.len() as u64), Some(NUM_ARBS_PER_READ))
+            .await?;
+        let last_page = (page.len() as i64) < NUM_ARBS_PER_READ;
+        events.append(&mut page);
+        if last_page {
+            break;
+        }
+    }
+    info!("read {} events", events.len());
+    Ok(events)
+}
+
+/// Reads every saved failure matching `filter` from `db`.
+pub async fn read_all_failures(
+    db: &ArbDatabase,
+    filter: &ArbFilterParams,
+) -> Result<Vec<SimFailure>> {
+    let mut failures = vec![];
+    loop {
+        let mut page = db
+            .read_failures(filter, Some(failures.len() as u64), Some(NUM_ARBS_PER_READ))
+            .await?;
+        let last_page = (page.len() as i64) < NUM_ARBS_PER_READ;
+        failures.append(&mut page);
+        if last_page {
+            break;
+        }
+    }
+    info!("read {} failures", failures.len());
+    Ok(failures)
+}
+
+/// Gas used by `arb`'s best backrun, or `default` if it wasn't recorded.
+pub fn backrun_gas(arb: &SimArbResultBatch, default: u64) -> u64 {
+    arb.best_result()
+        .and_then(|result| result.backrun_trade.gas_used)
+        .unwrap_or(default)
+}
+
+/// Converts `amount` (in a token's smallest unit) to whole tokens.
+pub fn to_decimal(amount: U256, decimals: u32) -> f64 {
+    format_units(amount, decimals)
+        .ok()
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or_default()
+}
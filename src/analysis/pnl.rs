@@ -0,0 +1,290 @@
+use super::{backrun_gas, to_decimal};
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Number of columns in the plotted curve.
+const PLOT_WIDTH: usize = 60;
+/// Number of rows in the plotted curve.
+const PLOT_HEIGHT: usize = 12;
+
+#[derive(Clone, Debug)]
+pub struct PnlOptions {
+    /// Token that the bankroll is held in. Only arbs quoted in this token are considered.
+    pub profit_token: Address,
+    /// Starting capital, in the profit token's smallest unit.
+    pub bankroll: U256,
+    /// Number of blocks that a backrun's `amount_in` stays busy, counting the block it lands in.
+    /// With 0, capital is never locked, so only the bankroll itself limits which backruns are taken.
+    pub lockup_blocks: u64,
+    /// Reinvest profits, growing the capital available to later backruns.
+    pub compound: bool,
+    /// Gas used per backrun, for backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// Wrapped native token of the chain. Gas is only subtracted from profits in this token.
+    pub native_token: Address,
+}
+
+/// Cumulative PnL after one taken backrun.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PnlPoint {
+    pub block: u64,
+    pub timestamp: u64,
+    /// Realizable PnL so far, in whole tokens.
+    pub cumulative_pnl: f64,
+    /// Sum of every opportunity's profit so far (taken or not), in whole tokens.
+    pub cumulative_gross: f64,
+}
+
+/// Realizable PnL of one run, under the capital constraints of `PnlOptions`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PnlCurve {
+    /// Label of the scan run, if it had one.
+    pub run_label: Option<String>,
+    pub profit_token: Address,
+    /// Starting capital, in whole tokens.
+    pub bankroll: f64,
+    pub lockup_blocks: u64,
+    pub compound: bool,
+    /// Number of opportunities still profitable after gas.
+    pub num_opportunities: u64,
+    pub num_taken: u64,
+    /// Opportunities skipped because not enough capital was free.
+    pub num_skipped: u64,
+    /// Sum of every opportunity's profit after gas, in whole tokens.
+    pub gross_profit: f64,
+    /// Sum of the taken opportunities' profit after gas, in whole tokens.
+    pub realizable_profit: f64,
+    pub points: Vec<PnlPoint>,
+}
+
+struct Opportunity {
+    block: u64,
+    timestamp: u64,
+    amount_in: U256,
+    profit: U256,
+}
+
+/// Replays the opportunities of each run in order, taking a backrun only if its `amount_in` is free,
+/// and returns one PnL curve per run label.
+///
+/// Each taken backrun locks its `amount_in` until `lockup_blocks` blocks have passed.
+/// Backruns that aren't profitable after gas are ignored.
+pub fn pnl_curves(arbs: &[SimArbResultBatch], options: &PnlOptions) -> Vec<PnlCurve> {
+    let mut runs: BTreeMap<Option<String>, Vec<Opportunity>> = BTreeMap::new();
+    let mut decimals = 18;
+    for arb in arbs {
+        if arb.profit_token != options.profit_token {
+            continue;
+        }
+        let best = match arb.best_result() {
+            Some(best) if !best.backrun_trade.profit.is_zero() => best,
+            _ => continue,
+        };
+        decimals = arb.profit_decimals;
+        let gas_cost = if arb.profit_token == options.native_token {
+            arb.base_fee.unwrap_or_default() * backrun_gas(arb, options.backrun_gas)
+        } else {
+            U256::zero()
+        };
+        if best.backrun_trade.profit <= gas_cost {
+            continue;
+        }
+        runs.entry(arb.run_label.to_owned())
+            .or_default()
+            .push(Opportunity {
+                block: arb.event.block,
+                timestamp: arb.event.timestamp,
+                amount_in: best.backrun_trade.amount_in,
+                profit: best.backrun_trade.profit - gas_cost,
+            });
+    }
+
+    runs.into_iter()
+        .map(|(run_label, mut opportunities)| {
+            opportunities.sort_by_key(|opp| (opp.block, opp.timestamp));
+            // (block that the capital is free again, amount)
+            let mut locked: Vec<(u64, U256)> = vec![];
+            let mut capital = options.bankroll;
+            let mut pnl = U256::zero();
+            let mut gross = U256::zero();
+            let mut num_skipped = 0;
+            let mut points = vec![];
+            for opp in &opportunities {
+                gross += opp.profit;
+                locked.retain(|(free_at, _)| *free_at > opp.block);
+                let busy = locked
+                    .iter()
+                    .fold(U256::zero(), |busy, (_, amount)| busy + *amount);
+                if busy + opp.amount_in > capital {
+                    num_skipped += 1;
+                    continue;
+                }
+                if options.lockup_blocks > 0 {
+                    locked.push((opp.block + options.lockup_blocks, opp.amount_in));
+                }
+                pnl += opp.profit;
+                if options.compound {
+                    capital += opp.profit;
+                }
+                points.push(PnlPoint {
+                    block: opp.block,
+                    timestamp: opp.timestamp,
+                    cumulative_pnl: to_decimal(pnl, decimals),
+                    cumulative_gross: to_decimal(gross, decimals),
+                });
+            }
+            PnlCurve {
+                run_label,
+                profit_token: options.profit_token,
+                bankroll: to_decimal(options.bankroll, decimals),
+                lockup_blocks: options.lockup_blocks,
+                compound: options.compound,
+                num_opportunities: opportunities.len() as u64,
+                num_taken: points.len() as u64,
+                num_skipped,
+                gross_profit: to_decimal(gross, decimals),
+                realizable_profit: to_decimal(pnl, decimals),
+                points,
+            }
+        })
+        .collect()
+}
+
+/// Plots `values` (in order) as rows of text, highest values at the top.
+fn plot(values: &[f64]) -> Vec<String> {
+    if values.is_empty() {
+        return vec![];
+    }
+    let max = values.iter().copied().fold(0.0, f64::max);
+    // the last value of each column's slice of `values`
+    let columns = (1..=PLOT_WIDTH.min(values.len()))
+        .map(|col| values[col * values.len() / PLOT_WIDTH.min(values.len()) - 1])
+        .collect::<Vec<_>>();
+    (0..PLOT_HEIGHT)
+        .rev()
+        .map(|row| {
+            let threshold = max * (row as f64 + 0.5) / PLOT_HEIGHT as f64;
+            columns
+                .iter()
+                .map(|value| if *value >= threshold { '#' } else { ' ' })
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// Renders each curve's summary, followed by a plot of its cumulative PnL.
+pub fn render_table(curves: &[PnlCurve]) -> String {
+    let mut table = String::new();
+    for curve in curves {
+        table.push_str(&format!(
+            "run {}: bankroll {} {:?}, capital locked for {} block(s){}\n",
+            curve.run_label.as_deref().unwrap_or("(unlabelled)"),
+            curve.bankroll,
+            curve.profit_token,
+            curve.lockup_blocks,
+            if curve.compound { ", compounding" } else { "" }
+        ));
+        table.push_str(&format!(
+            "  {} opportunities, {} taken, {} skipped for lack of capital\n",
+            curve.num_opportunities, curve.num_taken, curve.num_skipped
+        ));
+        table.push_str(&format!(
+            "  realizable profit {:.6} of {:.6} gross ({:.1}%)\n",
+            curve.realizable_profit,
+            curve.gross_profit,
+            if curve.gross_profit > 0.0 {
+                curve.realizable_profit / curve.gross_profit * 100.0
+            } else {
+                0.0
+            }
+        ));
+        let values = curve
+            .points
+            .iter()
+            .map(|point| point.cumulative_pnl)
+            .collect::<Vec<_>>();
+        for line in plot(&values) {
+            table.push_str(&format!("  |{}\n", line));
+        }
+        if let (Some(first), Some(last)) = (curve.points.first(), curve.points.last()) {
+            table.push_str(&format!(
+                "  +{}\n   block {} .. {}\n",
+                "-".repeat(values.len().min(PLOT_WIDTH)),
+                first.block,
+                last.block
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::known_wrapped_native, interfaces::SimArbResult};
+
+    fn eth(eth: u64) -> U256 {
+        U256::from(eth) * U256::exp10(18)
+    }
+
+    fn arb(block: u64, amount_in: U256, profit: U256) -> SimArbResultBatch {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.block = block;
+        arb.event.timestamp = block * 12;
+        arb.results = vec![SimArbResult::test_example(
+            Address::zero(),
+            amount_in,
+            profit,
+        )];
+        arb.max_profit = profit;
+        arb
+    }
+
+    #[test]
+    fn it_skips_opportunities_when_capital_is_busy() {
+        let weth = known_wrapped_native(1).unwrap();
+        let arbs = vec![
+            arb(1, eth(6), eth(1)),
+            // 6 of 10 busy until block 3
+            arb(2, eth(6), eth(2)),
+            arb(2, eth(4), eth(1)),
+            arb(3, eth(10), eth(3)),
+            // needs more than the bankroll
+            arb(4, eth(11), eth(5)),
+        ];
+        let options = PnlOptions {
+            profit_token: weth,
+            bankroll: eth(10),
+            lockup_blocks: 2,
+            compound: false,
+            backrun_gas: 0,
+            native_token: weth,
+        };
+        let curves = pnl_curves(&arbs, &options);
+        assert_eq!(curves.len(), 1);
+        let curve = &curves[0];
+        assert_eq!(curve.num_opportunities, 5);
+        // blocks 1 & 2 (4 eth) fit; block 3 waits on block 2's lockup
+        assert_eq!((curve.num_taken, curve.num_skipped), (2, 3));
+        assert_eq!(curve.realizable_profit, 2.0);
+        assert_eq!(curve.gross_profit, 12.0);
+
+        // with compounding & a shorter lockup, every backrun fits (including the 11 eth one)
+        let curves = pnl_curves(
+            &arbs,
+            &PnlOptions {
+                compound: true,
+                lockup_blocks: 1,
+                ..options
+            },
+        );
+        let curve = &curves[0];
+        assert_eq!((curve.num_taken, curve.num_skipped), (5, 0));
+        assert_eq!(curve.points.last().unwrap().cumulative_pnl, 12.0);
+    }
+}
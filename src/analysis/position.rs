@@ -0,0 +1,247 @@
+use super::{latency::AMOUNT_DIVISORS, to_decimal};
+use crate::{
+    interfaces::{QuoteAsset, SimArbResult, SimArbResultBatch},
+    sim::{core::sim_backrun_at, pools::PoolAdapters},
+    util::{get_block_info, WsClient},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Profit of one opportunity's route when backrunning the user's tx, and at the top of its block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionedProfit {
+    pub profit_token: Address,
+    pub profit_decimals: u32,
+    pub backrun_profit: U256,
+    /// Best profit of the route's pools (in either direction) before the user's tx.
+    pub top_of_block_profit: U256,
+}
+
+impl PositionedProfit {
+    /// Part of the backrun's profit that was already available at the top of the block.
+    pub fn positional(&self) -> U256 {
+        self.top_of_block_profit.min(self.backrun_profit)
+    }
+
+    /// Part of the backrun's profit that only the user's tx made available.
+    pub fn flow(&self) -> U256 {
+        self.backrun_profit - self.positional()
+    }
+}
+
+/// How one profit token's sampled MEV splits into positional & flow-driven profit.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionSplit {
+    pub profit_token: Address,
+    pub num_sampled: u64,
+    /// Number of opportunities whose pools were already dislocated at the top of the block.
+    pub num_positional: u64,
+    /// Sums of profits, in whole tokens.
+    pub backrun_profit: f64,
+    pub top_of_block_profit: f64,
+    /// Backrun profit that was already available at the top of the block.
+    pub positional_profit: f64,
+    /// Backrun profit that only the user's tx made available.
+    pub flow_profit: f64,
+    /// `positional_profit` as a share of `backrun_profit` (0 to 1).
+    pub positional_share: f64,
+}
+
+/// Swaps the pools of `result`'s backrun, to try the route in the other direction.
+fn reversed(result: &SimArbResult) -> SimArbResult {
+    let mut reversed = result.to_owned();
+    let trade = &mut reversed.backrun_trade;
+    std::mem::swap(&mut trade.start_pool, &mut trade.end_pool);
+    std::mem::swap(&mut trade.start_variant, &mut trade.end_variant);
+    std::mem::swap(&mut trade.start_factory, &mut trade.end_factory);
+    reversed
+}
+
+/// Simulates `arb`'s best backrun route at the top of the block that the user's tx landed in,
+/// i.e. on the state before the user's tx, in both directions.
+///
+/// Like the original simulation, the state is the end of the previous block, so txs that landed
+/// ahead of the user's tx in its block aren't seen.
+/// Returns None if the user's tx can't be found, or its quote asset isn't in `quote_assets`.
+pub async fn simulate_top_of_block(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    quote_assets: &[QuoteAsset],
+    adapters: &PoolAdapters,
+) -> Result<Option<PositionedProfit>> {
+    let best = match arb.best_result() {
+        Some(best) => best,
+        None => return Ok(None),
+    };
+    let quote_asset = match quote_assets
+        .iter()
+        .find(|asset| asset.address == best.user_trade.tokens.quote)
+    {
+        Some(asset) => asset,
+        None => return Ok(None),
+    };
+    let user_block = match client.get_transaction(arb.event.hint.hash).await? {
+        Some(tx) => match tx.block_number {
+            Some(block) => block.as_u64(),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let amounts_in = AMOUNT_DIVISORS
+        .iter()
+        .map(|divisor| best.backrun_trade.amount_in / *divisor)
+        .filter(|amount_in| !amount_in.is_zero())
+        .collect::<Vec<_>>();
+    let block_info = get_block_info(client, user_block - 1).await?;
+    let forward = sim_backrun_at(
+        client,
+        &block_info,
+        best,
+        quote_asset,
+        &amounts_in,
+        adapters,
+    )
+    .await?;
+    let backward = sim_backrun_at(
+        client,
+        &block_info,
+        &reversed(best),
+        quote_asset,
+        &amounts_in,
+        adapters,
+    )
+    .await?;
+    Ok(Some(PositionedProfit {
+        profit_token: arb.profit_token,
+        profit_decimals: arb.profit_decimals,
+        backrun_profit: best.backrun_trade.profit,
+        top_of_block_profit: forward.max(backward),
+    }))
+}
+
+/// Sums positioned profits into one split per profit token.
+pub fn position_splits(samples: &[PositionedProfit]) -> Vec<PositionSplit> {
+    let mut tokens: BTreeMap<Address, Vec<&PositionedProfit>> = BTreeMap::new();
+    for sample in samples {
+        tokens.entry(sample.profit_token).or_default().push(sample);
+    }
+    tokens
+        .into_iter()
+        .map(|(profit_token, samples)| {
+            let decimals = samples[0].profit_decimals;
+            let sum = |amount: fn(&PositionedProfit) -> U256| {
+                to_decimal(
+                    samples
+                        .iter()
+                        .fold(U256::zero(), |sum, sample| sum + amount(sample)),
+                    decimals,
+                )
+            };
+            let backrun_profit = sum(|sample| sample.backrun_profit);
+            let positional_profit = sum(|sample| sample.positional());
+            PositionSplit {
+                profit_token,
+                num_sampled: samples.len() as u64,
+                num_positional: samples
+                    .iter()
+                    .filter(|sample| !sample.top_of_block_profit.is_zero())
+                    .count() as u64,
+                backrun_profit,
+                top_of_block_profit: sum(|sample| sample.top_of_block_profit),
+                positional_profit,
+                flow_profit: sum(|sample| sample.flow()),
+                positional_share: if backrun_profit > 0.0 {
+                    positional_profit / backrun_profit
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect()
+}
+
+/// Renders `splits` as a plain-text table.
+pub fn render_table(splits: &[PositionSplit]) -> String {
+    let mut table = format!(
+        "{:<42} {:>7} {:>10} {:>16} {:>16} {:>16} {:>16} {:>10}\n",
+        "profit token",
+        "sampled",
+        "positional",
+        "backrun profit",
+        "top of block",
+        "positional",
+        "flow",
+        "positional"
+    );
+    table.push_str(&format!(
+        "{:<42} {:>7} {:>10} {:>16} {:>16} {:>16} {:>16} {:>10}\n",
+        "", "", "opps", "", "profit", "profit", "profit", "share"
+    ));
+    for split in splits {
+        table.push_str(&format!(
+            "{:<42} {:>7} {:>10} {:>16.6} {:>16.6} {:>16.6} {:>16.6} {:>9.1}%\n",
+            format!("{:?}", split.profit_token),
+            split.num_sampled,
+            split.num_positional,
+            split.backrun_profit,
+            split.top_of_block_profit,
+            split.positional_profit,
+            split.flow_profit,
+            split.positional_share * 100.0,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_positional_and_flow_profit() {
+        let weth = Address::from_low_u64_be(0x1);
+        let positioned = |backrun: u64, top_of_block: u64| PositionedProfit {
+            profit_token: weth,
+            profit_decimals: 18,
+            backrun_profit: U256::from(backrun) * U256::exp10(15),
+            top_of_block_profit: U256::from(top_of_block) * U256::exp10(15),
+        };
+        let splits = position_splits(&[
+            // the user's tx made all of it
+            positioned(30, 0),
+            // half was already there
+            positioned(20, 10),
+            // the user's tx shrank a dislocation that was already there
+            positioned(10, 40),
+        ]);
+        assert_eq!(splits.len(), 1);
+        let split = &splits[0];
+        assert_eq!((split.num_sampled, split.num_positional), (3, 2));
+        assert!((split.backrun_profit - 0.06).abs() < 1e-12);
+        assert!((split.top_of_block_profit - 0.05).abs() < 1e-12);
+        assert!((split.positional_profit - 0.02).abs() < 1e-12);
+        assert!((split.flow_profit - 0.04).abs() < 1e-12);
+        assert!((split.positional_share - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_reverses_routes() {
+        let mut result = SimArbResult::test_example(Address::zero(), U256::exp10(18), U256::one());
+        result.backrun_trade.end_pool = Address::from_low_u64_be(0xe);
+        let reversed = reversed(&result);
+        assert_eq!(
+            reversed.backrun_trade.start_pool,
+            Address::from_low_u64_be(0xe)
+        );
+        assert_eq!(
+            reversed.backrun_trade.end_pool,
+            result.backrun_trade.start_pool
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use super::to_decimal;
+use crate::{
+    interfaces::{Realized, RealizedBackrun, SimArbResultBatch},
+    labels::Labels,
+    util::WsClient,
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, TransactionReceipt, H256, I256, U256},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
+
+/// Number of blocks after the user's tx's block that are searched for a backrun.
+pub const BLOCKS_AFTER: u64 = 1;
+
+// Swap(address,address,int256,int256,uint160,uint128,int24)
+const UNIV3_SWAP_TOPIC: &'static str =
+    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+// Swap(address,uint256,uint256,uint256,uint256,address)
+const UNIV2_SWAP_TOPIC: &'static str =
+    "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+// Transfer(address,address,uint256)
+const TRANSFER_TOPIC: &'static str =
+    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+fn topic(hex: &str) -> H256 {
+    H256::from_str(hex).expect("that's some bad hash")
+}
+
+/// Pools that a backrun of `arb` would trade on: those of the simulated backruns & the user's swaps.
+fn pools_of_interest(arb: &SimArbResultBatch) -> HashSet<Address> {
+    let mut pools = arb
+        .results
+        .iter()
+        .flat_map(|result| {
+            [
+                result.user_trade.pool,
+                result.backrun_trade.start_pool,
+                result.backrun_trade.end_pool,
+            ]
+        })
+        .collect::<HashSet<_>>();
+    // results aren't always stored (e.g. postgres), but hints are
+    pools.extend(arb.event.hint.logs.iter().map(|log| log.address));
+    pools
+}
+
+/// Net amount of `token` received by `accounts` in `receipt`'s Transfer logs.
+fn net_transfers(receipt: &TransactionReceipt, token: Address, accounts: &[Address]) -> I256 {
+    let transfer_topic = topic(TRANSFER_TOPIC);
+    receipt
+        .logs
+        .iter()
+        .filter(|log| {
+            log.address == token && log.topics.len() == 3 && log.topics[0] == transfer_topic
+        })
+        .fold(I256::zero(), |net, log| {
+            let from = Address::from(log.topics[1]);
+            let to = Address::from(log.topics[2]);
+            let amount = I256::from_raw(U256::from_big_endian(&log.data));
+            match (accounts.contains(&from), accounts.contains(&to)) {
+                (false, true) => net + amount,
+                (true, false) => net - amount,
+                _ => net,
+            }
+        })
+}
+
+/// Finds the first tx in a block's `receipts` that looks like a backrun: it swaps on at least two pools,
+/// at least one of which is in `pools`. In the user's own block, only txs after `after_index` count.
+pub fn find_backrun(
+    receipts: &[TransactionReceipt],
+    user_tx: H256,
+    after_index: Option<u64>,
+    pools: &HashSet<Address>,
+    profit_token: Address,
+) -> Option<RealizedBackrun> {
+    let swap_topics = [topic(UNIV2_SWAP_TOPIC), topic(UNIV3_SWAP_TOPIC)];
+    let mut receipts = receipts.iter().collect::<Vec<_>>();
+    receipts.sort_by_key(|receipt| receipt.transaction_index);
+    receipts
+        .into_iter()
+        .filter(|receipt| {
+            receipt.transaction_hash != user_tx
+                && receipt.status == Some(1.into())
+                && after_index
+                    .map(|index| receipt.transaction_index.as_u64() > index)
+                    .unwrap_or(true)
+        })
+        .find_map(|receipt| {
+            let swapped_pools = receipt
+                .logs
+                .iter()
+                .filter(|log| !log.topics.is_empty() && swap_topics.contains(&log.topics[0]))
+                .map(|log| log.address)
+                .collect::<HashSet<_>>();
+            if swapped_pools.len() < 2 || swapped_pools.is_disjoint(pools) {
+                return None;
+            }
+            let accounts = [Some(receipt.from), receipt.to]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            Some(RealizedBackrun {
+                tx_hash: receipt.transaction_hash,
+                block: receipt.block_number.unwrap_or_default().as_u64(),
+                from: receipt.from,
+                to: receipt.to,
+                profit: net_transfers(receipt, profit_token, &accounts),
+                fee_recipient: None,
+                extra_data: None,
+            })
+        })
+}
+
+/// Checks the user's block and the next `BLOCKS_AFTER` blocks for a backrun of `arb`'s user tx.
+///
+/// Returns None if it can't be checked: the user's tx never landed, or there are no pools to look for.
+pub async fn check_realized(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+) -> Result<Option<Realized>> {
+    let pools = pools_of_interest(arb);
+    if pools.is_empty() {
+        return Ok(None);
+    }
+    let user_tx = arb.event.hint.hash;
+    let (user_block, user_index) = match client.get_transaction(user_tx).await? {
+        Some(tx) => match (tx.block_number, tx.transaction_index) {
+            (Some(block), Some(index)) => (block.as_u64(), index.as_u64()),
+            _ => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    for block in user_block..=user_block + BLOCKS_AFTER {
+        let receipts = client.get_block_receipts(block).await?;
+        let after_index = (block == user_block).then_some(user_index);
+        if let Some(mut backrun) =
+            find_backrun(&receipts, user_tx, after_index, &pools, arb.profit_token)
+        {
+            if let Some(block) = client.get_block(block).await? {
+                backrun.fee_recipient = block.author;
+                backrun.extra_data = Some(block.extra_data);
+            }
+            return Ok(Some(Realized {
+                backrun: Some(backrun),
+            }));
+        }
+    }
+    Ok(Some(Realized { backrun: None }))
+}
+
+/// Simulated vs. realized profits of checked arbs, for one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegretSummary {
+    pub profit_token: Address,
+    /// Number of checked events with a profitable simulated backrun.
+    pub num_opportunities: u64,
+    /// Number of those that were backrun on-chain.
+    pub num_realized: u64,
+    /// Sum of simulated max profits, in whole tokens.
+    pub simulated_profit: f64,
+    /// Sum of realized backrun profits, in whole tokens.
+    pub realized_profit: f64,
+    /// Simulated profit that nobody captured: the full simulated profit of opportunities that
+    /// weren't backrun, plus the shortfall of backruns that made less than the simulation.
+    pub regret: f64,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    num_opportunities: u64,
+    num_realized: u64,
+    simulated_profit: U256,
+    realized_profit: U256,
+    regret: U256,
+    decimals: u32,
+}
+
+/// Summarizes the arbs that have been checked for realized backruns, per profit token.
+pub fn regret_summary(arbs: &[SimArbResultBatch]) -> Vec<RegretSummary> {
+    let mut tokens: BTreeMap<Address, Accumulator> = BTreeMap::new();
+    for arb in arbs {
+        let realized = match &arb.realized {
+            Some(realized) if !arb.max_profit.is_zero() => realized,
+            _ => continue,
+        };
+        let acc = tokens.entry(arb.profit_token).or_default();
+        acc.num_opportunities += 1;
+        acc.simulated_profit += arb.max_profit;
+        acc.decimals = arb.profit_decimals;
+        let realized_profit = match &realized.backrun {
+            Some(backrun) => {
+                acc.num_realized += 1;
+                if backrun.profit.is_positive() {
+                    backrun.profit.into_raw()
+                } else {
+                    U256::zero()
+                }
+            }
+            None => U256::zero(),
+        };
+        acc.realized_profit += realized_profit;
+        acc.regret += arb.max_profit.saturating_sub(realized_profit);
+    }
+    tokens
+        .into_iter()
+        .map(|(profit_token, acc)| RegretSummary {
+            profit_token,
+            num_opportunities: acc.num_opportunities,
+            num_realized: acc.num_realized,
+            simulated_profit: to_decimal(acc.simulated_profit, acc.decimals),
+            realized_profit: to_decimal(acc.realized_profit, acc.decimals),
+            regret: to_decimal(acc.regret, acc.decimals),
+        })
+        .collect()
+}
+
+/// Realized backruns on one route, by one searcher, in blocks by one builder.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributionRow {
+    /// Pools of the most profitable simulated backrun, if results were stored.
+    pub start_pool: Option<Address>,
+    pub end_pool: Option<Address>,
+    pub searcher: String,
+    pub builder: String,
+    pub profit_token: Address,
+    pub num_backruns: u64,
+    /// Sum of realized profits, in whole tokens.
+    pub realized_profit: f64,
+}
+
+/// Start & end pool, searcher, builder and profit token that realized backruns are attributed to.
+type AttributionKey = (Option<Address>, Option<Address>, String, String, Address);
+
+/// Attributes realized backruns to searchers & builders, grouped by route.
+/// Routes are ordered by address, and their rows by number of backruns (most first).
+pub fn attribution(arbs: &[SimArbResultBatch], labels: &Labels) -> Vec<AttributionRow> {
+    // number of backruns, summed profit & the profit token's decimals
+    let mut rows: HashMap<AttributionKey, (u64, I256, u32)> = HashMap::new();
+    for arb in arbs {
+        let backrun = match arb
+            .realized
+            .as_ref()
+            .and_then(|realized| realized.backrun.as_ref())
+        {
+            Some(backrun) => backrun,
+            None => continue,
+        };
+        let route = arb.best_result().map(|result| &result.backrun_trade);
+        let (num_backruns, profit, decimals) = rows
+            .entry((
+                route.map(|trade| trade.start_pool),
+                route.map(|trade| trade.end_pool),
+                labels.searcher(backrun),
+                labels.builder(backrun),
+                arb.profit_token,
+            ))
+            .or_default();
+        *num_backruns += 1;
+        *profit += backrun.profit;
+        *decimals = arb.profit_decimals;
+    }
+    let mut rows = rows
+        .into_iter()
+        .map(
+            |(
+                (start_pool, end_pool, searcher, builder, profit_token),
+                (num_backruns, profit, decimals),
+            )| {
+                let realized_profit = to_decimal(profit.unsigned_abs(), decimals);
+                AttributionRow {
+                    start_pool,
+                    end_pool,
+                    searcher,
+                    builder,
+                    profit_token,
+                    num_backruns,
+                    realized_profit: if profit.is_negative() {
+                        -realized_profit
+                    } else {
+                        realized_profit
+                    },
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        (a.start_pool, a.end_pool)
+            .cmp(&(b.start_pool, b.end_pool))
+            .then(b.num_backruns.cmp(&a.num_backruns))
+            .then(a.searcher.cmp(&b.searcher))
+            .then(a.builder.cmp(&b.builder))
+    });
+    rows
+}
+
+fn format_pool(pool: Option<Address>) -> String {
+    pool.map(|pool| format!("{:?}", pool))
+        .unwrap_or("?".to_owned())
+}
+
+/// Renders `rows` as a plain-text table.
+pub fn render_attribution_table(rows: &[AttributionRow]) -> String {
+    let mut table = format!(
+        "{:<42} {:<42} {:<42} {:<24} {:>8} {:>16}\n",
+        "start pool", "end pool", "searcher", "builder", "backruns", "realized profit"
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "{:<42} {:<42} {:<42} {:<24} {:>8} {:>16.6}\n",
+            format_pool(row.start_pool),
+            format_pool(row.end_pool),
+            row.searcher,
+            row.builder,
+            row.num_backruns,
+            row.realized_profit,
+        ));
+    }
+    table
+}
+
+/// Renders `summary` as a plain-text table, naming profit tokens by their `labels`.
+pub fn render_table(summary: &[RegretSummary], labels: &Labels) -> String {
+    let mut table = format!(
+        "{:<42} {:>8} {:>8} {:>16} {:>16} {:>16}\n",
+        "profit token", "opps", "realized", "simulated", "realized profit", "regret"
+    );
+    for row in summary {
+        table.push_str(&format!(
+            "{:<42} {:>8} {:>8} {:>16.6} {:>16.6} {:>16.6}\n",
+            labels.name_of(&row.profit_token),
+            row.num_opportunities,
+            row.num_realized,
+            row.simulated_profit,
+            row.realized_profit,
+            row.regret,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::known_wrapped_native;
+    use ethers::types::{Bytes, Log, U64};
+
+    fn log(address: Address, topics: Vec<H256>, data: Vec<u8>) -> Log {
+        Log {
+            address,
+            topics,
+            data: Bytes::from(data),
+            ..Default::default()
+        }
+    }
+
+    fn receipt(index: u64, logs: Vec<Log>) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(index + 1),
+            transaction_index: U64::from(index),
+            block_number: Some(U64::from(100)),
+            from: Address::from_low_u64_be(0xf00),
+            to: Some(Address::from_low_u64_be(0xb07)),
+            status: Some(1.into()),
+            logs,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_finds_realized_backruns() {
+        let weth = known_wrapped_native(1).unwrap();
+        let pool_a = Address::from_low_u64_be(0xa);
+        let pool_b = Address::from_low_u64_be(0xb);
+        let bot = H256::from(Address::from_low_u64_be(0xb07));
+        let other = H256::from(pool_a);
+        let mut amount = [0u8; 32];
+        U256::exp10(16).to_big_endian(&mut amount);
+        let swaps = || {
+            vec![
+                log(pool_a, vec![topic(UNIV2_SWAP_TOPIC)], vec![]),
+                log(pool_b, vec![topic(UNIV3_SWAP_TOPIC)], vec![]),
+            ]
+        };
+        let mut backrun_logs = swaps();
+        backrun_logs.push(log(
+            weth,
+            vec![topic(TRANSFER_TOPIC), other, bot],
+            amount.to_vec(),
+        ));
+        let receipts = vec![
+            // frontrun: before the user's tx
+            receipt(0, swaps()),
+            // the user's tx: a single swap
+            receipt(1, vec![log(pool_a, vec![topic(UNIV2_SWAP_TOPIC)], vec![])]),
+            receipt(2, backrun_logs),
+        ];
+        let user_tx = receipts[1].transaction_hash;
+        let pools = HashSet::from([pool_a]);
+
+        let backrun = find_backrun(&receipts, user_tx, Some(1), &pools, weth).unwrap();
+        assert_eq!(backrun.tx_hash, receipts[2].transaction_hash);
+        assert_eq!(backrun.profit, I256::from_raw(U256::exp10(16)));
+        // in the next block, any tx counts
+        let backrun = find_backrun(&receipts, user_tx, None, &pools, weth).unwrap();
+        assert_eq!(backrun.tx_hash, receipts[0].transaction_hash);
+        // no backrun on unrelated pools
+        let pools = HashSet::from([Address::from_low_u64_be(0xc)]);
+        assert!(find_backrun(&receipts, user_tx, Some(1), &pools, weth).is_none());
+    }
+
+    #[test]
+    fn it_summarizes_regret() {
+        let arb = |profit: u64, realized: Option<i64>| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.max_profit = U256::from(profit) * U256::exp10(15);
+            arb.realized = Some(Realized {
+                backrun: realized.map(|profit| RealizedBackrun {
+                    tx_hash: H256::zero(),
+                    block: 9002,
+                    from: Address::zero(),
+                    to: None,
+                    profit: I256::from(profit) * I256::exp10(15),
+                    fee_recipient: None,
+                    extra_data: None,
+                }),
+            });
+            arb
+        };
+        let mut unchecked = arb(100, None);
+        unchecked.realized = None;
+        let arbs = vec![arb(10, Some(4)), arb(20, None), arb(5, Some(-1)), unchecked];
+        let summary = regret_summary(&arbs);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].num_opportunities, 3);
+        assert_eq!(summary[0].num_realized, 2);
+        assert!((summary[0].simulated_profit - 0.035).abs() < 1e-12);
+        assert!((summary[0].realized_profit - 0.004).abs() < 1e-12);
+        assert!((summary[0].regret - 0.031).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_attributes_backruns() {
+        let arb = |bot: u64, extra_data: &str| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.realized = Some(Realized {
+                backrun: Some(RealizedBackrun {
+                    tx_hash: H256::zero(),
+                    block: 9002,
+                    from: Address::zero(),
+                    to: Some(Address::from_low_u64_be(bot)),
+                    profit: I256::exp10(16),
+                    fee_recipient: None,
+                    extra_data: Some(Bytes::from(extra_data.as_bytes().to_vec())),
+                }),
+            });
+            arb
+        };
+        let arbs = vec![
+            arb(0x1, "beaverbuild.org"),
+            arb(0x2, "titanbuilder.xyz"),
+            arb(0x2, "Titan (titanbuilder.xyz)"),
+        ];
+        let rows = attribution(&arbs, &Labels::default());
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].searcher,
+            format!("{:?}", Address::from_low_u64_be(0x2))
+        );
+        assert_eq!(
+            (rows[0].builder.as_str(), rows[0].num_backruns),
+            ("titan", 2)
+        );
+        assert!((rows[0].realized_profit - 0.02).abs() < 1e-12);
+        assert_eq!(rows[1].builder, "beaverbuild");
+    }
+}
@@ -0,0 +1,349 @@
+use super::to_decimal;
+use crate::{data::external::ExternalArb, interfaces::SimArbResultBatch, labels::Labels};
+use ethers::types::{Address, H256, I256, U256};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// How a simulated event was matched to an external dataset's arb.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MatchedBy {
+    /// The arb is the event's realized backrun (or the user's tx itself).
+    Tx,
+    /// The arb landed in the event's block, with the same profit token.
+    Block,
+}
+
+/// Arbs of one profit token that an external dataset & hindsight's simulations agree or disagree on.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileRow {
+    /// None for arbs that the dataset has no profit token for.
+    pub profit_token: Option<Address>,
+    /// Simulated events matched to one of the dataset's arbs by tx hash.
+    pub matched_by_tx: u64,
+    /// Simulated events matched to one of the dataset's arbs by block & profit token.
+    pub matched_by_block: u64,
+    /// Matched events whose simulation found no profit.
+    pub matched_unprofitable: u64,
+    /// Profitable simulated events that the dataset has no arb for.
+    pub hindsight_only: u64,
+    /// Arbs of the dataset that no simulated event matched, e.g. because their orderflow wasn't shared.
+    pub external_only: u64,
+    /// Sum of the simulated profit of matched events, in whole tokens.
+    pub simulated_profit: f64,
+    /// Sum of the dataset's profit of matched arbs, in whole tokens.
+    pub external_profit: f64,
+    /// Share of the matched events' simulated profit that the dataset's arbs realized.
+    pub capture_ratio: Option<f64>,
+    /// Sum of the dataset's USD profit of matched arbs, if it has USD profits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_profit_usd: Option<f64>,
+    /// Example txs of profitable simulated events that the dataset has no arb for.
+    pub hindsight_only_examples: Vec<H256>,
+    /// Example arb txs of the dataset that no simulated event matched.
+    pub external_only_examples: Vec<H256>,
+}
+
+/// A comparison of an external dataset's arbs to hindsight's simulations, over the blocks that both cover.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    /// Blocks that both the dataset & the simulations cover; None if they don't overlap.
+    pub blocks: Option<(u64, u64)>,
+    pub num_external: usize,
+    pub num_simulated: usize,
+    pub rows: Vec<ReconcileRow>,
+}
+
+/// First & last block of `external`'s arbs.
+pub fn block_range(external: &[ExternalArb]) -> Option<(u64, u64)> {
+    let first = external.iter().map(|arb| arb.block).min()?;
+    let last = external.iter().map(|arb| arb.block).max()?;
+    Some((first, last))
+}
+
+#[derive(Default)]
+struct RowAcc {
+    row: ReconcileRow,
+    simulated_profit: U256,
+    external_profit: I256,
+    decimals: Option<u32>,
+}
+
+/// Matches `external` arbs to simulated events of `arbs`, over the blocks that both cover, and sums up where
+/// they agree by profit token, keeping up to `num_examples` example txs of each disagreement.
+///
+/// An event matches an arb whose tx is its realized backrun (or its own tx); failing that, an arb in its block
+/// with the same profit token (or none). Each arb matches one event at most.
+pub fn reconcile(
+    external: &[ExternalArb],
+    arbs: &[SimArbResultBatch],
+    num_examples: usize,
+) -> Reconciliation {
+    let simulated = (
+        arbs.iter().map(|arb| arb.event.block).min(),
+        arbs.iter().map(|arb| arb.event.block).max(),
+    );
+    let blocks = match (block_range(external), simulated) {
+        (Some((first, last)), (Some(sim_first), Some(sim_last))) => {
+            Some((first.max(sim_first), last.min(sim_last))).filter(|(first, last)| first <= last)
+        }
+        _ => None,
+    };
+    let Some((first, last)) = blocks else {
+        return Reconciliation {
+            num_external: external.len(),
+            num_simulated: arbs.len(),
+            ..Default::default()
+        };
+    };
+    let in_range = |block: u64| block >= first && block <= last;
+    let external = external
+        .iter()
+        .filter(|arb| in_range(arb.block))
+        .collect::<Vec<_>>();
+    let arbs = arbs
+        .iter()
+        .filter(|arb| in_range(arb.event.block))
+        .collect::<Vec<_>>();
+
+    let mut by_tx: HashMap<H256, Vec<usize>> = HashMap::new();
+    let mut by_block: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (idx, arb) in external.iter().enumerate() {
+        by_tx.entry(arb.tx_hash).or_default().push(idx);
+        by_block.entry(arb.block).or_default().push(idx);
+    }
+    let mut matched = vec![false; external.len()];
+    let mut rows: BTreeMap<Option<Address>, RowAcc> = BTreeMap::new();
+    for arb in &arbs {
+        let same_token = |idx: &usize| {
+            external[*idx]
+                .profit_token
+                .is_none_or(|token| token == arb.profit_token)
+        };
+        let txs = [
+            arb.realized
+                .as_ref()
+                .and_then(|realized| realized.backrun.as_ref())
+                .map(|backrun| backrun.tx_hash),
+            Some(arb.event.hint.hash),
+        ];
+        let by_tx = txs
+            .iter()
+            .flatten()
+            .filter_map(|tx_hash| by_tx.get(tx_hash))
+            .flatten()
+            .filter(|idx| !matched[**idx])
+            .min_by_key(|idx| !same_token(idx))
+            .map(|idx| (*idx, MatchedBy::Tx));
+        let found = by_tx.or(by_block
+            .get(&arb.event.block)
+            .into_iter()
+            .flatten()
+            .find(|idx| !matched[**idx] && same_token(idx))
+            .map(|idx| (*idx, MatchedBy::Block)));
+
+        let acc = rows.entry(Some(arb.profit_token)).or_default();
+        acc.decimals = Some(arb.profit_decimals);
+        match found {
+            Some((idx, matched_by)) => {
+                matched[idx] = true;
+                let row = &mut acc.row;
+                match matched_by {
+                    MatchedBy::Tx => row.matched_by_tx += 1,
+                    MatchedBy::Block => row.matched_by_block += 1,
+                }
+                if arb.max_profit.is_zero() {
+                    row.matched_unprofitable += 1;
+                }
+                acc.simulated_profit += arb.max_profit;
+                if let Some(amount) = external[idx].profit_amount {
+                    acc.external_profit += amount;
+                }
+                if let Some(usd) = external[idx].profit_usd {
+                    row.external_profit_usd =
+                        Some(row.external_profit_usd.unwrap_or_default() + usd);
+                }
+            }
+            None if !arb.max_profit.is_zero() => {
+                acc.row.hindsight_only += 1;
+                if acc.row.hindsight_only_examples.len() < num_examples {
+                    acc.row.hindsight_only_examples.push(arb.event.hint.hash);
+                }
+            }
+            None => {}
+        }
+    }
+    for (arb, _) in external
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+    {
+        let row = &mut rows.entry(arb.profit_token).or_default().row;
+        row.external_only += 1;
+        if row.external_only_examples.len() < num_examples {
+            row.external_only_examples.push(arb.tx_hash);
+        }
+    }
+
+    let rows = rows
+        .into_iter()
+        // tokens whose events were all unprofitable & unmatched have nothing to compare
+        .filter(|(_, acc)| {
+            let row = &acc.row;
+            row.matched_by_tx + row.matched_by_block + row.hindsight_only + row.external_only > 0
+        })
+        .map(|(profit_token, acc)| {
+            let decimals = acc.decimals.unwrap_or(18);
+            let simulated_profit = to_decimal(acc.simulated_profit, decimals);
+            let external_profit = match acc.external_profit.is_negative() {
+                true => -to_decimal(acc.external_profit.unsigned_abs(), decimals),
+                false => to_decimal(acc.external_profit.into_raw(), decimals),
+            };
+            ReconcileRow {
+                profit_token,
+                simulated_profit,
+                external_profit,
+                capture_ratio: (simulated_profit > 0.0).then(|| external_profit / simulated_profit),
+                ..acc.row
+            }
+        })
+        .collect();
+    Reconciliation {
+        blocks,
+        num_external: external.len(),
+        num_simulated: arbs.len(),
+        rows,
+    }
+}
+
+/// Renders `reconciliation` as a plain-text table, naming profit tokens by their `labels`.
+pub fn render_table(reconciliation: &Reconciliation, labels: &Labels) -> String {
+    let Some((first, last)) = reconciliation.blocks else {
+        return format!(
+            "the dataset's {} arbs & the {} simulated events don't cover any of the same blocks\n",
+            reconciliation.num_external, reconciliation.num_simulated
+        );
+    };
+    let mut table = format!(
+        "{} dataset arbs & {} simulated events in blocks {}..={}\n\n",
+        reconciliation.num_external, reconciliation.num_simulated, first, last
+    );
+    table.push_str(&format!(
+        "{:<42} {:>8} {:>8} {:>12} {:>14} {:>13} {:>16} {:>16} {:>8}\n",
+        "profit token",
+        "by tx",
+        "by block",
+        "unprofitable",
+        "hindsight only",
+        "dataset only",
+        "simulated",
+        "dataset profit",
+        "capture"
+    ));
+    for row in &reconciliation.rows {
+        table.push_str(&format!(
+            "{:<42} {:>8} {:>8} {:>12} {:>14} {:>13} {:>16.6} {:>16.6} {:>8}\n",
+            row.profit_token
+                .map(|token| labels.name_of(&token))
+                .unwrap_or("?".to_owned()),
+            row.matched_by_tx,
+            row.matched_by_block,
+            row.matched_unprofitable,
+            row.hindsight_only,
+            row.external_only,
+            row.simulated_profit,
+            row.external_profit,
+            row.capture_ratio
+                .map(|ratio| format!("{:.1}%", ratio * 100.0))
+                .unwrap_or("-".to_owned()),
+        ));
+        for tx_hash in &row.hindsight_only_examples {
+            table.push_str(&format!("{:<42} hindsight only, e.g. {:?}\n", "", tx_hash));
+        }
+        for tx_hash in &row.external_only_examples {
+            table.push_str(&format!("{:<42} dataset only, e.g. {:?}\n", "", tx_hash));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::external::DatasetSource,
+        interfaces::{Realized, RealizedBackrun},
+    };
+
+    fn hash(n: u64) -> H256 {
+        H256::from_low_u64_be(n)
+    }
+
+    #[test]
+    fn it_reconciles_datasets_with_simulations() {
+        let weth = Address::from_low_u64_be(0xe7);
+        let sim = |tx: u64, block: u64, max_profit: u64| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.hint.hash = hash(tx);
+            arb.event.block = block;
+            arb.max_profit = U256::from(max_profit) * U256::exp10(18);
+            arb.profit_token = weth;
+            arb.profit_decimals = 18;
+            arb
+        };
+        let external = |tx: u64, block: u64, profit: u64| ExternalArb {
+            source: DatasetSource::MevInspect,
+            tx_hash: hash(tx),
+            block,
+            searcher: None,
+            profit_token: Some(weth),
+            profit_amount: Some(I256::from(profit) * I256::exp10(17)),
+            profit_usd: None,
+        };
+        let mut backrun = sim(1, 100, 2);
+        backrun.realized = Some(Realized {
+            backrun: Some(RealizedBackrun {
+                tx_hash: hash(0xb1),
+                block: 100,
+                from: Address::zero(),
+                to: None,
+                profit: I256::zero(),
+                fee_recipient: None,
+                extra_data: None,
+            }),
+        });
+        let arbs = vec![
+            backrun,
+            // matched by block
+            sim(2, 101, 1),
+            // nobody took it
+            sim(3, 102, 1),
+            // past the end of the dataset
+            sim(4, 200, 1),
+        ];
+        let dataset = vec![
+            external(0xb1, 100, 15),
+            external(0xb2, 101, 5),
+            external(0xb3, 103, 1),
+            // before the first simulated block
+            external(0xb0, 90, 1),
+        ];
+        let reconciliation = reconcile(&dataset, &arbs, 3);
+        assert_eq!(reconciliation.blocks, Some((100, 103)));
+        assert_eq!(
+            (reconciliation.num_external, reconciliation.num_simulated),
+            (3, 3)
+        );
+        let row = &reconciliation.rows[0];
+        assert_eq!(row.profit_token, Some(weth));
+        assert_eq!((row.matched_by_tx, row.matched_by_block), (1, 1));
+        assert_eq!((row.hindsight_only, row.external_only), (1, 1));
+        assert_eq!(row.hindsight_only_examples, vec![hash(3)]);
+        assert_eq!(row.external_only_examples, vec![hash(0xb3)]);
+        assert_eq!((row.simulated_profit, row.external_profit), (3.0, 2.0));
+        assert!(render_table(&reconciliation, &Labels::default()).contains("blocks 100..=103"));
+
+        assert_eq!(reconcile(&dataset[3..], &arbs, 3).blocks, None);
+    }
+}
@@ -0,0 +1,673 @@
+use super::{
+    bids::{CapturableSummary, RouteBid},
+    leaderboard::{pool_label, DexRow, RouteRow, TokenRow},
+    realized::RegretSummary,
+    stats::{format_timestamp, BucketSize, BucketStats},
+};
+use crate::data::tokens::TokenMetadataStore;
+use ethers::types::Address;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+pub const REPORT_FORMAT_NAMES: [&'static str; 2] = ["html", "markdown"];
+
+/// File format of a report.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReportFormat {
+    /// Single HTML page with inline SVG charts.
+    #[default]
+    Html,
+    /// Markdown with mermaid charts (rendered by e.g. GitHub).
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Markdown => "md",
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFormat::Html => write!(f, "html"),
+            ReportFormat::Markdown => write!(f, "markdown"),
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(ReportFormat::Html),
+            "markdown" => Ok(ReportFormat::Markdown),
+            _ => Err(format!("invalid report format: {}", s)),
+        }
+    }
+}
+
+/// Everything that goes into a report, already computed.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub title: String,
+    /// Lines describing what the report covers (e.g. chain, block range, run label).
+    pub description: Vec<String>,
+    pub bucket_size: Option<BucketSize>,
+    pub stats: Vec<BucketStats>,
+    pub tokens: Vec<TokenRow>,
+    pub routes: Vec<RouteRow>,
+    pub dexes: Vec<DexRow>,
+    /// Empty if no arbs were checked for realized backruns.
+    pub regret: Vec<RegretSummary>,
+    /// Profit left after each arb's required bid & gas. None if no realized backruns were found to estimate bids from.
+    pub capturable: Option<CapturableSummary>,
+    /// Routes with enough realized backruns for their own bid.
+    pub bids: Vec<RouteBid>,
+}
+
+struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+/// Bar chart of one value per label.
+struct Chart {
+    title: String,
+    labels: Vec<String>,
+    values: Vec<f64>,
+}
+
+struct Section {
+    heading: String,
+    charts: Vec<Chart>,
+    tables: Vec<Table>,
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value
+        .map(|value| format!("{:.6}", value))
+        .unwrap_or("-".to_owned())
+}
+
+/// Lays out `report` as sections of charts & tables, independent of the output format.
+fn sections(report: &Report, store: &TokenMetadataStore) -> Vec<Section> {
+    let mut stats_by_token: BTreeMap<Address, Vec<&BucketStats>> = BTreeMap::new();
+    for row in &report.stats {
+        stats_by_token
+            .entry(row.profit_token)
+            .or_default()
+            .push(row);
+    }
+    let sum_optional = |rows: &[&BucketStats], value: fn(&BucketStats) -> Option<f64>| {
+        rows.iter().map(|row| value(row)).sum::<Option<f64>>()
+    };
+
+    let mut sections = vec![Section {
+        heading: "Summary".to_owned(),
+        charts: vec![],
+        tables: vec![Table {
+            headers: vec![
+                "profit token",
+                "events",
+                "profitable",
+                "gross profit",
+                "net profit",
+            ],
+            rows: stats_by_token
+                .iter()
+                .map(|(profit_token, rows)| {
+                    vec![
+                        store.symbol(profit_token),
+                        rows.iter().map(|row| row.num_arbs).sum::<u64>().to_string(),
+                        rows.iter()
+                            .map(|row| row.num_profitable)
+                            .sum::<u64>()
+                            .to_string(),
+                        format!(
+                            "{:.6}",
+                            rows.iter().map(|row| row.gross_profit).sum::<f64>()
+                        ),
+                        format_optional(sum_optional(rows, |row| row.net_profit)),
+                    ]
+                })
+                .collect(),
+        }],
+    }];
+
+    let per_bucket = report
+        .bucket_size
+        .map(|bucket_size| format!(" ({})", bucket_size))
+        .unwrap_or_default();
+    for (profit_token, rows) in &stats_by_token {
+        let symbol = store.symbol(profit_token);
+        let labels = rows
+            .iter()
+            .map(|row| format_timestamp(row.bucket_start))
+            .collect::<Vec<_>>();
+        sections.push(Section {
+            heading: format!("Profit over time in {}{}", symbol, per_bucket),
+            charts: vec![
+                Chart {
+                    title: format!("gross profit ({})", symbol),
+                    labels: labels.clone(),
+                    values: rows.iter().map(|row| row.gross_profit).collect(),
+                },
+                Chart {
+                    title: "profitable opportunities".to_owned(),
+                    labels,
+                    values: rows.iter().map(|row| row.num_profitable as f64).collect(),
+                },
+            ],
+            tables: vec![Table {
+                headers: vec![
+                    "bucket",
+                    "events",
+                    "profitable",
+                    "gross profit",
+                    "gas (ETH)",
+                    "net profit",
+                    "pools",
+                ],
+                rows: rows
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            format_timestamp(row.bucket_start),
+                            row.num_arbs.to_string(),
+                            row.num_profitable.to_string(),
+                            format!("{:.6}", row.gross_profit),
+                            format_optional(row.gas_cost),
+                            format_optional(row.net_profit),
+                            row.unique_pools.to_string(),
+                        ]
+                    })
+                    .collect(),
+            }],
+        });
+    }
+
+    if !report.tokens.is_empty() {
+        sections.push(Section {
+            heading: "Top tokens".to_owned(),
+            charts: vec![],
+            tables: vec![Table {
+                headers: vec![
+                    "rank",
+                    "symbol",
+                    "token",
+                    "profit in",
+                    "opps",
+                    "total profit",
+                    "avg profit",
+                    "avg size",
+                ],
+                rows: report
+                    .tokens
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            row.rank.to_string(),
+                            row.symbol.to_owned().unwrap_or("?".to_owned()),
+                            format!("{:?}", row.token),
+                            store.symbol(&row.profit_token),
+                            row.num_opportunities.to_string(),
+                            format!("{:.6}", row.total_profit),
+                            format!("{:.6}", row.avg_profit),
+                            format!("{:.4}", row.avg_amount_in),
+                        ]
+                    })
+                    .collect(),
+            }],
+        });
+    }
+    if !report.routes.is_empty() {
+        sections.push(Section {
+            heading: "Top routes".to_owned(),
+            charts: vec![],
+            tables: vec![Table {
+                headers: vec![
+                    "rank",
+                    "pair",
+                    "start pool",
+                    "end pool",
+                    "opps",
+                    "total profit",
+                    "avg profit",
+                ],
+                rows: report
+                    .routes
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            row.rank.to_string(),
+                            format!(
+                                "{}/{}",
+                                row.symbol.as_deref().unwrap_or("?"),
+                                store.symbol(&row.profit_token)
+                            ),
+                            format!(
+                                "{:?} ({})",
+                                row.start_pool,
+                                pool_label(row.start_factory, row.start_variant, row.start_fee)
+                            ),
+                            format!(
+                                "{:?} ({})",
+                                row.end_pool,
+                                pool_label(row.end_factory, row.end_variant, row.end_fee)
+                            ),
+                            row.num_opportunities.to_string(),
+                            format!("{:.6}", row.total_profit),
+                            format!("{:.6}", row.avg_profit),
+                        ]
+                    })
+                    .collect(),
+            }],
+        });
+    }
+    if !report.dexes.is_empty() {
+        let mut dexes_by_token: BTreeMap<Address, Vec<&DexRow>> = BTreeMap::new();
+        for row in &report.dexes {
+            dexes_by_token
+                .entry(row.profit_token)
+                .or_default()
+                .push(row);
+        }
+        sections.push(Section {
+            heading: "Profit by DEX".to_owned(),
+            charts: dexes_by_token
+                .iter()
+                .map(|(profit_token, rows)| Chart {
+                    title: format!("total profit ({})", store.symbol(profit_token)),
+                    labels: rows
+                        .iter()
+                        .map(|row| format!("{} -> {}", row.start_dex, row.end_dex))
+                        .collect(),
+                    values: rows.iter().map(|row| row.total_profit).collect(),
+                })
+                .collect(),
+            tables: vec![Table {
+                headers: vec![
+                    "rank",
+                    "start dex",
+                    "end dex",
+                    "profit in",
+                    "routes",
+                    "opps",
+                    "total profit",
+                    "share",
+                ],
+                rows: report
+                    .dexes
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            row.rank.to_string(),
+                            row.start_dex.to_owned(),
+                            row.end_dex.to_owned(),
+                            store.symbol(&row.profit_token),
+                            row.num_routes.to_string(),
+                            row.num_opportunities.to_string(),
+                            format!("{:.6}", row.total_profit),
+                            format!("{:.1}%", row.profit_share * 100.0),
+                        ]
+                    })
+                    .collect(),
+            }],
+        });
+    }
+    if !report.regret.is_empty() {
+        sections.push(Section {
+            heading: "Missed MEV".to_owned(),
+            charts: vec![],
+            tables: vec![Table {
+                headers: vec![
+                    "profit token",
+                    "opportunities",
+                    "backrun",
+                    "simulated profit",
+                    "realized profit",
+                    "regret",
+                ],
+                rows: report
+                    .regret
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            store.symbol(&row.profit_token),
+                            row.num_opportunities.to_string(),
+                            row.num_realized.to_string(),
+                            format!("{:.6}", row.simulated_profit),
+                            format!("{:.6}", row.realized_profit),
+                            format!("{:.6}", row.regret),
+                        ]
+                    })
+                    .collect(),
+            }],
+        });
+    }
+    if let Some(capturable) = &report.capturable {
+        let mut tables = vec![Table {
+            headers: vec![
+                "profit token",
+                "opportunities",
+                "route-priced",
+                "gross profit",
+                "gas",
+                "bids",
+                "capturable profit",
+            ],
+            rows: vec![vec![
+                store.symbol(&capturable.profit_token),
+                capturable.num_opportunities.to_string(),
+                capturable.num_route_priced.to_string(),
+                format!("{:.6}", capturable.gross_profit),
+                format!("{:.6}", capturable.gas_cost),
+                format!("{:.6}", capturable.bids),
+                format!("{:.6}", capturable.capturable_profit),
+            ]],
+        }];
+        if !report.bids.is_empty() {
+            tables.push(Table {
+                headers: vec!["start pool", "end pool", "backruns", "bid share"],
+                rows: report
+                    .bids
+                    .iter()
+                    .map(|row| {
+                        vec![
+                            format!("{:?}", row.start_pool),
+                            format!("{:?}", row.end_pool),
+                            row.num_backruns.to_string(),
+                            format!("{:.1}%", row.bid_share * 100.0),
+                        ]
+                    })
+                    .collect(),
+            });
+        }
+        sections.push(Section {
+            heading: "Capturable profit".to_owned(),
+            charts: vec![],
+            tables,
+        });
+    }
+    sections
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn svg_bar_chart(chart: &Chart) -> String {
+    const WIDTH: f64 = 720.0;
+    const HEIGHT: f64 = 240.0;
+    const LEFT: f64 = 80.0;
+    const TOP: f64 = 30.0;
+    const BOTTOM: f64 = 30.0;
+    let plot_width = WIDTH - LEFT - 10.0;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let max = chart.values.iter().copied().fold(0.0, f64::max);
+    let scale = if max > 0.0 { plot_height / max } else { 0.0 };
+    let bar_width = plot_width / chart.values.len().max(1) as f64;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+        <text x=\"{left}\" y=\"18\" class=\"title\">{title}</text>\n\
+        <line x1=\"{left}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{bottom}\" class=\"axis\"/>\n\
+        <text x=\"{label_x}\" y=\"{top}\" class=\"tick\" text-anchor=\"end\">{max:.4}</text>\n\
+        <text x=\"{label_x}\" y=\"{bottom}\" class=\"tick\" text-anchor=\"end\">0</text>\n",
+        w = WIDTH,
+        h = HEIGHT,
+        left = LEFT,
+        right = WIDTH - 10.0,
+        top = TOP + 4.0,
+        bottom = TOP + plot_height,
+        label_x = LEFT - 6.0,
+        title = escape_html(&chart.title),
+        max = max,
+    );
+    for (i, (label, value)) in chart.labels.iter().zip(&chart.values).enumerate() {
+        let height = value.max(0.0) * scale;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" class=\"bar\"><title>{}: {}</title></rect>\n",
+            LEFT + i as f64 * bar_width + bar_width * 0.1,
+            TOP + plot_height - height,
+            bar_width * 0.8,
+            height,
+            escape_html(label),
+            value
+        ));
+    }
+    // only the first & last labels fit
+    if let (Some(first), Some(last)) = (chart.labels.first(), chart.labels.last()) {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" class=\"tick\">{}</text>\n",
+            LEFT,
+            HEIGHT - 8.0,
+            escape_html(first)
+        ));
+        if chart.labels.len() > 1 {
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" class=\"tick\" text-anchor=\"end\">{}</text>\n",
+                WIDTH - 10.0,
+                HEIGHT - 8.0,
+                escape_html(last)
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+const HTML_STYLE: &'static str =
+    "body { font-family: sans-serif; margin: 2em auto; max-width: 1100px; color: #222; }
+table { border-collapse: collapse; margin: 1em 0; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }
+th { background: #f3f3f3; }
+svg { display: block; margin: 1em 0; }
+.bar { fill: #4a7bd0; }
+.axis { stroke: #888; }
+.title { font-size: 14px; font-weight: bold; }
+.tick { font-size: 11px; fill: #555; }";
+
+/// Renders `report` as a self-contained HTML page (no external scripts, styles, or images).
+pub fn render_html(report: &Report, store: &TokenMetadataStore) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{style}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        title = escape_html(&report.title),
+        style = HTML_STYLE
+    );
+    for line in &report.description {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(line)));
+    }
+    for section in sections(report, store) {
+        html.push_str(&format!("<h2>{}</h2>\n", escape_html(&section.heading)));
+        for chart in &section.charts {
+            html.push_str(&svg_bar_chart(chart));
+        }
+        for table in &section.tables {
+            html.push_str("<table>\n<tr>");
+            for header in &table.headers {
+                html.push_str(&format!("<th>{}</th>", escape_html(header)));
+            }
+            html.push_str("</tr>\n");
+            for row in &table.rows {
+                html.push_str("<tr>");
+                for cell in row {
+                    html.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                }
+                html.push_str("</tr>\n");
+            }
+            html.push_str("</table>\n");
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn mermaid_bar_chart(chart: &Chart) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "'"));
+    format!(
+        "```mermaid\nxychart-beta\n    title {}\n    x-axis [{}]\n    bar [{}]\n```\n",
+        quote(&chart.title),
+        chart
+            .labels
+            .iter()
+            .map(|label| quote(label))
+            .collect::<Vec<_>>()
+            .join(", "),
+        chart
+            .values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Renders `report` as markdown. Charts are mermaid diagrams, which render on e.g. GitHub.
+pub fn render_markdown(report: &Report, store: &TokenMetadataStore) -> String {
+    let mut markdown = format!("# {}\n\n", report.title);
+    for line in &report.description {
+        markdown.push_str(&format!("{}\n\n", line));
+    }
+    for section in sections(report, store) {
+        markdown.push_str(&format!("## {}\n\n", section.heading));
+        for chart in &section.charts {
+            markdown.push_str(&mermaid_bar_chart(chart));
+            markdown.push('\n');
+        }
+        for table in &section.tables {
+            markdown.push_str(&format!("| {} |\n", table.headers.join(" | ")));
+            markdown.push_str(&format!("|{}\n", " ---: |".repeat(table.headers.len())));
+            for row in &table.rows {
+                markdown.push_str(&format!(
+                    "| {} |\n",
+                    row.iter()
+                        .map(|cell| escape_markdown_cell(cell))
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                ));
+            }
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_report() -> Report {
+        let weth = Address::from_low_u64_be(0x1);
+        let bucket = |bucket_start: u64, gross_profit: f64| BucketStats {
+            bucket_start,
+            profit_token: weth,
+            dexes: None,
+            num_arbs: 10,
+            num_profitable: 2,
+            gross_profit,
+            gas_cost: Some(0.01),
+            net_profit: Some(gross_profit - 0.01),
+            unique_pools: 4,
+        };
+        Report {
+            title: "test <report>".to_owned(),
+            description: vec!["chain: mainnet".to_owned()],
+            bucket_size: Some(BucketSize::Daily),
+            stats: vec![bucket(86_400, 0.5), bucket(2 * 86_400, 1.5)],
+            ..Default::default()
+        }
+    }
+
+    /// A store with nothing cached, so tokens are shown by address.
+    fn empty_store() -> TokenMetadataStore {
+        TokenMetadataStore::load(0).unwrap()
+    }
+
+    #[test]
+    fn it_renders_html_reports() {
+        let html = render_html(&test_report(), &empty_store());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>test &lt;report&gt;</h1>"));
+        assert_eq!(html.matches("<svg").count(), 2);
+        // 2 bars per chart
+        assert_eq!(html.matches("<rect").count(), 4);
+        // summary sums the buckets
+        assert!(html.contains("<td>20</td><td>4</td><td>2.000000</td><td>1.980000</td>"));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn it_renders_markdown_reports() {
+        let markdown = render_markdown(&test_report(), &empty_store());
+        assert!(markdown.starts_with("# test <report>\n"));
+        assert_eq!(markdown.matches("```mermaid").count(), 2);
+        assert!(markdown.contains("    bar [0.5, 1.5]\n"));
+        assert!(
+            markdown.contains("| 1970-01-02 00:00 | 10 | 2 | 0.500000 | 0.010000 | 0.490000 | 4 |")
+        );
+        // no leaderboards or realized backruns were given
+        assert!(!markdown.contains("## Top tokens"));
+        assert!(!markdown.contains("## Missed MEV"));
+        assert!(!markdown.contains("## Profit by DEX"));
+        assert!(!markdown.contains("## Capturable profit"));
+    }
+
+    #[test]
+    fn it_renders_profit_by_dex() {
+        let report = Report {
+            dexes: vec![DexRow {
+                rank: 1,
+                start_dex: "Sushiswap V2".to_owned(),
+                end_dex: "Uniswap V3 5bp".to_owned(),
+                profit_token: Address::from_low_u64_be(0x1),
+                num_routes: 3,
+                num_opportunities: 7,
+                total_profit: 1.25,
+                profit_share: 1.0,
+            }],
+            ..test_report()
+        };
+        let markdown = render_markdown(&report, &empty_store());
+        assert!(markdown.contains("## Profit by DEX"));
+        assert!(markdown.contains("| 1 | Sushiswap V2 | Uniswap V3 5bp |"));
+        assert!(markdown.contains("| 3 | 7 | 1.250000 | 100.0% |"));
+    }
+
+    #[test]
+    fn it_renders_capturable_profit() {
+        let report = Report {
+            capturable: Some(CapturableSummary {
+                profit_token: Address::from_low_u64_be(0x1),
+                num_opportunities: 4,
+                num_route_priced: 3,
+                gross_profit: 1.0,
+                gas_cost: 0.05,
+                bids: 0.8,
+                capturable_profit: 0.15,
+            }),
+            bids: vec![RouteBid {
+                start_pool: Address::from_low_u64_be(0xa),
+                end_pool: Address::from_low_u64_be(0xb),
+                num_backruns: 3,
+                bid_share: 0.8,
+            }],
+            ..test_report()
+        };
+        let markdown = render_markdown(&report, &empty_store());
+        assert!(markdown.contains("## Capturable profit"));
+        assert!(markdown.contains("| 4 | 3 | 1.000000 | 0.050000 | 0.800000 | 0.150000 |"));
+        assert!(markdown.contains("| 3 | 80.0% |"));
+    }
+}
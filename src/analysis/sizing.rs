@@ -0,0 +1,261 @@
+use super::to_decimal;
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::Address;
+use serde::Serialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+pub const SIZING_GROUP_NAMES: [&'static str; 3] = ["all", "tokens", "routes"];
+
+/// Percentiles reported for each group.
+const PERCENTILES: [f64; 4] = [50.0, 75.0, 90.0, 99.0];
+
+/// How opportunities are grouped before their sizes are summarized.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SizingGroup {
+    /// One group per profit token.
+    #[default]
+    All,
+    /// One group per traded token.
+    Tokens,
+    /// One group per (start pool, end pool) route.
+    Routes,
+}
+
+impl fmt::Display for SizingGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizingGroup::All => write!(f, "all"),
+            SizingGroup::Tokens => write!(f, "tokens"),
+            SizingGroup::Routes => write!(f, "routes"),
+        }
+    }
+}
+
+impl FromStr for SizingGroup {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(SizingGroup::All),
+            "tokens" => Ok(SizingGroup::Tokens),
+            "routes" => Ok(SizingGroup::Routes),
+            _ => Err(format!("invalid sizing group: {}", s)),
+        }
+    }
+}
+
+/// Number of opportunities whose `amount_in` was at most `upper` (and above the previous bin's `upper`).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBin {
+    pub upper: f64,
+    pub count: u64,
+}
+
+/// Distribution of the optimal `amount_in` of profitable backruns in one group.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeDistribution {
+    pub profit_token: Address,
+    /// Traded token, when grouped by token.
+    pub token: Option<Address>,
+    /// Route, when grouped by route.
+    pub start_pool: Option<Address>,
+    pub end_pool: Option<Address>,
+    pub count: u64,
+    /// (percentile, amount_in) pairs, in whole profit tokens.
+    pub percentiles: Vec<(f64, f64)>,
+    pub max: f64,
+    pub histogram: Vec<HistogramBin>,
+}
+
+/// Returns the smallest number on the 1-2-5 scale (..., 0.5, 1, 2, 5, 10, ...) that is >= `value`.
+fn bin_upper(value: f64) -> f64 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|upper| *upper >= value * (1.0 - 1e-12))
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Nearest-rank percentile of `sorted`, which must not be empty.
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Summarizes the `amount_in` of each profitable event's best backrun, per `group`.
+/// Groups are ordered by number of opportunities (most first); only the top `limit` are kept.
+pub fn size_distributions(
+    arbs: &[SimArbResultBatch],
+    group: SizingGroup,
+    limit: usize,
+) -> Vec<SizeDistribution> {
+    type Key = (Address, Option<Address>, Option<Address>, Option<Address>);
+    let mut groups: HashMap<Key, Vec<f64>> = HashMap::new();
+    for arb in arbs {
+        let best = match arb.best_result() {
+            Some(best) if !best.backrun_trade.profit.is_zero() => best,
+            _ => continue,
+        };
+        let trade = &best.backrun_trade;
+        let key = match group {
+            SizingGroup::All => (arb.profit_token, None, None, None),
+            SizingGroup::Tokens => (
+                arb.profit_token,
+                Some(best.user_trade.tokens.token),
+                None,
+                None,
+            ),
+            SizingGroup::Routes => (
+                arb.profit_token,
+                None,
+                Some(trade.start_pool),
+                Some(trade.end_pool),
+            ),
+        };
+        groups
+            .entry(key)
+            .or_default()
+            .push(to_decimal(trade.amount_in, arb.profit_decimals));
+    }
+
+    let mut distributions = groups
+        .into_iter()
+        .map(
+            |((profit_token, token, start_pool, end_pool), mut amounts)| {
+                amounts.sort_by(|a, b| a.total_cmp(b));
+                let mut histogram: Vec<HistogramBin> = vec![];
+                for amount in &amounts {
+                    let upper = bin_upper(*amount);
+                    match histogram.last_mut() {
+                        Some(bin) if bin.upper == upper => bin.count += 1,
+                        _ => histogram.push(HistogramBin { upper, count: 1 }),
+                    }
+                }
+                SizeDistribution {
+                    profit_token,
+                    token,
+                    start_pool,
+                    end_pool,
+                    count: amounts.len() as u64,
+                    percentiles: PERCENTILES
+                        .iter()
+                        .map(|pct| (*pct, percentile(&amounts, *pct)))
+                        .collect(),
+                    max: *amounts.last().expect("groups are never empty"),
+                    histogram,
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    distributions.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then((a.profit_token, a.token, a.start_pool, a.end_pool).cmp(&(
+                b.profit_token,
+                b.token,
+                b.start_pool,
+                b.end_pool,
+            )))
+    });
+    distributions.truncate(limit);
+    distributions
+}
+
+fn group_label(distribution: &SizeDistribution) -> String {
+    match (
+        distribution.token,
+        distribution.start_pool,
+        distribution.end_pool,
+    ) {
+        (Some(token), _, _) => format!("token {:?}", token),
+        (_, Some(start_pool), Some(end_pool)) => {
+            format!("route {:?} -> {:?}", start_pool, end_pool)
+        }
+        _ => "all".to_owned(),
+    }
+}
+
+/// Renders each distribution's percentiles, followed by its histogram.
+pub fn render_table(distributions: &[SizeDistribution]) -> String {
+    let mut table = String::new();
+    for distribution in distributions {
+        table.push_str(&format!(
+            "{} (profit token {:?}): {} opportunities\n",
+            group_label(distribution),
+            distribution.profit_token,
+            distribution.count
+        ));
+        for (pct, amount) in &distribution.percentiles {
+            table.push_str(&format!("  {:.0}% needed <= {:.4}\n", pct, amount));
+        }
+        table.push_str(&format!("  max {:.4}\n", distribution.max));
+        let widest = distribution
+            .histogram
+            .iter()
+            .map(|bin| bin.count)
+            .max()
+            .unwrap_or(1);
+        for bin in &distribution.histogram {
+            table.push_str(&format!(
+                "  <= {:>12} {:>8} {}\n",
+                bin.upper,
+                bin.count,
+                "#".repeat(((bin.count * 40) / widest).max(1) as usize)
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+    use ethers::types::U256;
+
+    #[test]
+    fn it_bins_amounts() {
+        assert_eq!(bin_upper(0.3), 0.5);
+        assert_eq!(bin_upper(1.0), 1.0);
+        assert_eq!(bin_upper(1.5), 2.0);
+        assert_eq!(bin_upper(7.9), 10.0);
+        assert_eq!(bin_upper(20.0), 20.0);
+    }
+
+    #[test]
+    fn it_summarizes_amount_in() {
+        let shib = Address::from_low_u64_be(0x1);
+        let arbs = (1..=10u64)
+            .map(|eth| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.results = vec![SimArbResult::test_example(
+                    shib,
+                    U256::from(eth) * U256::exp10(18),
+                    U256::exp10(15),
+                )];
+                arb
+            })
+            .collect::<Vec<_>>();
+        let distributions = size_distributions(&arbs, SizingGroup::Tokens, 10);
+        assert_eq!(distributions.len(), 1);
+        let distribution = &distributions[0];
+        assert_eq!(distribution.token, Some(shib));
+        assert_eq!(distribution.count, 10);
+        assert_eq!(distribution.percentiles[0], (50.0, 5.0));
+        assert_eq!(distribution.percentiles[2], (90.0, 9.0));
+        assert_eq!(distribution.max, 10.0);
+        // 1 | 2 | 3,4,5 | 6..10
+        let counts = distribution
+            .histogram
+            .iter()
+            .map(|bin| bin.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 1, 3, 5]);
+    }
+}
@@ -0,0 +1,322 @@
+use super::{backrun_gas, leaderboard::pool_label, to_decimal};
+use crate::interfaces::SimArbResultBatch;
+use chrono::DateTime;
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+/// Gas used by a two-swap backrun, used to estimate gas costs when the real amount isn't known.
+pub const ESTIMATED_BACKRUN_GAS: u64 = 300_000;
+
+pub const BUCKET_SIZE_NAMES: [&'static str; 3] = ["hourly", "daily", "weekly"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BucketSize {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+impl BucketSize {
+    pub fn seconds(&self) -> u64 {
+        match self {
+            BucketSize::Hourly => 3600,
+            BucketSize::Daily => 86400,
+            BucketSize::Weekly => 86400 * 7,
+        }
+    }
+
+    /// Returns the start of the (UTC) bucket that `timestamp` falls in. Weeks start on Monday.
+    pub fn bucket_start(&self, timestamp: u64) -> u64 {
+        // the unix epoch was a Thursday
+        let offset = match self {
+            BucketSize::Weekly => 86400 * 4,
+            _ => 0,
+        };
+        let shifted = timestamp.saturating_sub(offset);
+        shifted - shifted % self.seconds() + offset
+    }
+}
+
+impl fmt::Display for BucketSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BucketSize::Hourly => write!(f, "hourly"),
+            BucketSize::Daily => write!(f, "daily"),
+            BucketSize::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+impl FromStr for BucketSize {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hourly" => Ok(BucketSize::Hourly),
+            "daily" => Ok(BucketSize::Daily),
+            "weekly" => Ok(BucketSize::Weekly),
+            _ => Err(format!("invalid bucket size: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatsOptions {
+    pub bucket_size: BucketSize,
+    /// Gas used per backrun, to estimate gas costs of backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// Wrapped native token of the chain. Gas costs can only be subtracted from profits in this token.
+    pub native_token: Address,
+    /// Break each bucket down by the (start DEX, end DEX) pair of each event's best backrun.
+    pub by_dex: bool,
+}
+
+/// Aggregated arbs of one time bucket, for one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketStats {
+    /// Unix timestamp (seconds) of the start of the bucket.
+    pub bucket_start: u64,
+    pub profit_token: Address,
+    /// (start DEX, end DEX) of the events' best backruns, e.g. "Sushiswap V2 -> Uniswap V3".
+    /// Only set when stats are broken down by DEX.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dexes: Option<String>,
+    /// Number of simulated events.
+    pub num_arbs: u64,
+    /// Number of events with a profitable backrun (before gas).
+    pub num_profitable: u64,
+    /// Sum of max profits, in whole tokens.
+    pub gross_profit: f64,
+    /// Estimated gas spent on backruns that are still profitable after gas, in ETH.
+    /// Only known for profits in the native token.
+    pub gas_cost: Option<f64>,
+    /// Profit of backruns that are still profitable after gas, minus gas, in whole tokens.
+    pub net_profit: Option<f64>,
+    /// Number of distinct pools that the backruns traded on.
+    pub unique_pools: usize,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    num_arbs: u64,
+    num_profitable: u64,
+    gross_profit: U256,
+    gas_cost: U256,
+    net_profit: U256,
+    pools: HashSet<Address>,
+    decimals: u32,
+}
+
+/// Groups `arbs` into time buckets (and by profit token) and aggregates each bucket, oldest first.
+///
+/// Arbs saved before base fees were recorded count as having no gas cost.
+pub fn bucket_stats(arbs: &[SimArbResultBatch], options: &StatsOptions) -> Vec<BucketStats> {
+    let mut buckets: BTreeMap<(u64, Address, Option<String>), Accumulator> = BTreeMap::new();
+    for arb in arbs {
+        let bucket_start = options.bucket_size.bucket_start(arb.event.timestamp);
+        let dexes = options.by_dex.then(|| dex_pair(arb));
+        let acc = buckets
+            .entry((bucket_start, arb.profit_token, dexes))
+            .or_default();
+        acc.num_arbs += 1;
+        acc.decimals = arb.profit_decimals;
+        for result in &arb.results {
+            acc.pools.insert(result.backrun_trade.start_pool);
+            acc.pools.insert(result.backrun_trade.end_pool);
+        }
+        if arb.max_profit.is_zero() {
+            continue;
+        }
+        acc.num_profitable += 1;
+        acc.gross_profit += arb.max_profit;
+        let gas_cost = arb.base_fee.unwrap_or_default() * backrun_gas(arb, options.backrun_gas);
+        if arb.max_profit > gas_cost {
+            acc.gas_cost += gas_cost;
+            acc.net_profit += arb.max_profit - gas_cost;
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|((bucket_start, profit_token, dexes), acc)| {
+            let is_native = profit_token == options.native_token;
+            BucketStats {
+                bucket_start,
+                profit_token,
+                dexes,
+                num_arbs: acc.num_arbs,
+                num_profitable: acc.num_profitable,
+                gross_profit: to_decimal(acc.gross_profit, acc.decimals),
+                gas_cost: is_native.then(|| to_decimal(acc.gas_cost, 18)),
+                net_profit: is_native.then(|| to_decimal(acc.net_profit, acc.decimals)),
+                unique_pools: acc.pools.len(),
+            }
+        })
+        .collect()
+}
+
+/// Labels the (start DEX, end DEX) pair of an arb's best backrun. Fee tiers aren't known here, so V3 pools aren't told apart by fee.
+fn dex_pair(arb: &SimArbResultBatch) -> String {
+    match arb.best_result() {
+        Some(best) => {
+            let trade = &best.backrun_trade;
+            format!(
+                "{} -> {}",
+                pool_label(trade.start_factory, trade.start_variant, None),
+                pool_label(trade.end_factory, trade.end_variant, None)
+            )
+        }
+        None => "-".to_owned(),
+    }
+}
+
+/// Formats a unix timestamp in UTC with `format` (see `chrono::format::strftime`).
+fn format_utc(timestamp: u64, format: &str) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.format(format).to_string())
+        .unwrap_or(timestamp.to_string())
+}
+
+/// Formats a unix timestamp as a UTC date & time.
+pub fn format_timestamp(timestamp: u64) -> String {
+    format_utc(timestamp, "%Y-%m-%d %H:%M")
+}
+
+/// Formats a unix timestamp as a UTC date.
+pub fn format_date(timestamp: u64) -> String {
+    format_utc(timestamp, "%Y-%m-%d")
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value
+        .map(|value| format!("{:.6}", value))
+        .unwrap_or("-".to_owned())
+}
+
+/// Renders `stats` as a plain-text table, with a DEX column if the stats are broken down by DEX.
+pub fn render_table(stats: &[BucketStats]) -> String {
+    let by_dex = stats.iter().any(|row| row.dexes.is_some());
+    let dex_column = |dexes: &str| {
+        if by_dex {
+            format!("{:<32} ", dexes)
+        } else {
+            String::new()
+        }
+    };
+    let mut table = format!(
+        "{:<17} {}{:<42} {:>8} {:>10} {:>16} {:>14} {:>16} {:>6}\n",
+        "bucket",
+        dex_column("dexes"),
+        "profit token",
+        "arbs",
+        "profitable",
+        "gross profit",
+        "gas (ETH)",
+        "net profit",
+        "pools"
+    );
+    for row in stats {
+        table.push_str(&format!(
+            "{:<17} {}{:<42} {:>8} {:>10} {:>16.6} {:>14} {:>16} {:>6}\n",
+            format_timestamp(row.bucket_start),
+            dex_column(row.dexes.as_deref().unwrap_or("")),
+            format!("{:?}", row.profit_token),
+            row.num_arbs,
+            row.num_profitable,
+            row.gross_profit,
+            format_optional(row.gas_cost),
+            format_optional(row.net_profit),
+            row.unique_pools,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::known_wrapped_native;
+
+    #[test]
+    fn it_aligns_buckets() {
+        // 2023-08-10 (a Thursday) 13:37:00 UTC
+        let timestamp = 1691674620;
+        assert_eq!(BucketSize::Hourly.bucket_start(timestamp), 1691672400);
+        assert_eq!(BucketSize::Daily.bucket_start(timestamp), 1691625600);
+        // Monday, 2023-08-07
+        assert_eq!(BucketSize::Weekly.bucket_start(timestamp), 1691366400);
+    }
+
+    #[test]
+    fn it_aggregates_buckets() {
+        let weth = known_wrapped_native(1).unwrap();
+        let arb = |timestamp: u64, profit: u64| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.timestamp = timestamp;
+            arb.max_profit = U256::from(profit) * U256::exp10(15);
+            arb.base_fee = Some(U256::from(10_000_000_000u64)); // 10 gwei
+            arb
+        };
+        let arbs = vec![
+            arb(1691674620, 10),
+            arb(1691674620 + 60, 1),
+            arb(1691674620 + 86400, 0),
+        ];
+        let stats = bucket_stats(
+            &arbs,
+            &StatsOptions {
+                bucket_size: BucketSize::Daily,
+                backrun_gas: ESTIMATED_BACKRUN_GAS,
+                native_token: weth,
+                by_dex: false,
+            },
+        );
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].num_arbs, 2);
+        assert_eq!(stats[0].num_profitable, 2);
+        assert!((stats[0].gross_profit - 0.011).abs() < 1e-12);
+        // gas is 0.003 ETH per backrun, so only the 0.01 ETH backrun is worth sending
+        assert!((stats[0].gas_cost.unwrap() - 0.003).abs() < 1e-12);
+        assert!((stats[0].net_profit.unwrap() - 0.007).abs() < 1e-12);
+        assert_eq!(stats[1].num_profitable, 0);
+        assert!(render_table(&stats).lines().count() == 3);
+    }
+
+    #[test]
+    fn it_breaks_buckets_down_by_dex() {
+        let weth = known_wrapped_native(1).unwrap();
+        let sushiswap = crate::config::SUSHISWAP_FACTORY.parse().ok();
+        let arb = |start_factory: Option<Address>| {
+            let profit = U256::exp10(16);
+            let mut result =
+                crate::interfaces::SimArbResult::test_example(Address::zero(), profit, profit);
+            result.backrun_trade.start_factory = start_factory;
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.timestamp = 1691674620;
+            arb.results = vec![result];
+            arb.max_profit = profit;
+            arb
+        };
+        let arbs = vec![arb(sushiswap), arb(sushiswap), arb(None)];
+        let stats = bucket_stats(
+            &arbs,
+            &StatsOptions {
+                bucket_size: BucketSize::Daily,
+                backrun_gas: ESTIMATED_BACKRUN_GAS,
+                native_token: weth,
+                by_dex: true,
+            },
+        );
+        assert_eq!(stats.len(), 2);
+        let sushi = stats
+            .iter()
+            .find(|row| row.dexes.as_deref() == Some("Sushiswap V2 -> UniswapV2"))
+            .unwrap();
+        assert_eq!(sushi.num_arbs, 2);
+        assert!(render_table(&stats).contains("dexes"));
+    }
+}
@@ -0,0 +1,179 @@
+use super::to_decimal;
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::{Address, H256, U256};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// What replaying one block found.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockSweep {
+    pub block: u64,
+    pub num_txs: u64,
+    /// Number of txs that couldn't be replayed on the fork; the rest of the block is replayed without them.
+    pub num_replay_failures: u64,
+    /// Number of successful txs that emitted a swap log of a supported AMM.
+    pub num_price_moving: u64,
+    /// Number of price-moving txs with no backrun to simulate, or whose sim failed.
+    pub num_sim_failures: u64,
+    /// Number of price-moving txs with a profitable backrun.
+    pub num_profitable: u64,
+}
+
+/// Intra-block MEV found by the sweep in one profit token, and how much of it hint-driven runs found.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepCoverage {
+    pub profit_token: Address,
+    /// Number of txs the sweep found a profitable backrun of.
+    pub num_opportunities: u64,
+    /// Number of those that a hint-driven run also found profitable.
+    pub num_hinted: u64,
+    /// Sum of the sweep's max profits, in whole tokens.
+    pub sweep_profit: f64,
+    /// Sum of the hint-driven runs' max profits of the same txs, in whole tokens.
+    pub hinted_profit: f64,
+    /// `hinted_profit` as a share of `sweep_profit`, if the sweep found any.
+    pub coverage: Option<f64>,
+}
+
+/// Blocks replayed by a sweep, and how its arbs compare to those found from hints.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SweepReport {
+    pub blocks: Vec<BlockSweep>,
+    pub coverage: Vec<SweepCoverage>,
+}
+
+/// Compares the profitable arbs of a sweep with those that hint-driven runs found for the same txs, per profit token.
+///
+/// Txs are matched by hash. A hint-driven arb only counts if it's quoted in the same token as the sweep's,
+/// and if several runs simulated a tx, its most profitable arb is used.
+pub fn coverage(sweep: &[SimArbResultBatch], hinted: &[SimArbResultBatch]) -> Vec<SweepCoverage> {
+    let mut hinted_profits: BTreeMap<(H256, Address), U256> = BTreeMap::new();
+    for arb in hinted.iter().filter(|arb| !arb.max_profit.is_zero()) {
+        let profit = hinted_profits
+            .entry((arb.event.hint.hash, arb.profit_token))
+            .or_default();
+        *profit = (*profit).max(arb.max_profit);
+    }
+    #[derive(Default)]
+    struct Accumulator {
+        num_opportunities: u64,
+        num_hinted: u64,
+        sweep_profit: U256,
+        hinted_profit: U256,
+        decimals: u32,
+    }
+    let mut tokens: BTreeMap<Address, Accumulator> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for arb in sweep.iter().filter(|arb| !arb.max_profit.is_zero()) {
+        // a tx swept more than once is only counted the first time
+        if !seen.insert(arb.event.hint.hash) {
+            continue;
+        }
+        let acc = tokens.entry(arb.profit_token).or_default();
+        acc.num_opportunities += 1;
+        acc.sweep_profit += arb.max_profit;
+        acc.decimals = arb.profit_decimals;
+        if let Some(profit) = hinted_profits.get(&(arb.event.hint.hash, arb.profit_token)) {
+            acc.num_hinted += 1;
+            acc.hinted_profit += *profit;
+        }
+    }
+    tokens
+        .into_iter()
+        .map(|(profit_token, acc)| {
+            let sweep_profit = to_decimal(acc.sweep_profit, acc.decimals);
+            let hinted_profit = to_decimal(acc.hinted_profit, acc.decimals);
+            SweepCoverage {
+                profit_token,
+                num_opportunities: acc.num_opportunities,
+                num_hinted: acc.num_hinted,
+                sweep_profit,
+                hinted_profit,
+                coverage: (sweep_profit > 0.0).then(|| hinted_profit / sweep_profit),
+            }
+        })
+        .collect()
+}
+
+pub fn render_table(report: &SweepReport) -> String {
+    let mut table = format!(
+        "{:<10} {:>6} {:>10} {:>13} {:>13} {:>11}\n",
+        "block", "txs", "replay err", "price-moving", "sim failures", "profitable"
+    );
+    for block in &report.blocks {
+        table.push_str(&format!(
+            "{:<10} {:>6} {:>10} {:>13} {:>13} {:>11}\n",
+            block.block,
+            block.num_txs,
+            block.num_replay_failures,
+            block.num_price_moving,
+            block.num_sim_failures,
+            block.num_profitable
+        ));
+    }
+    table.push_str(&format!(
+        "\n{:<44} {:>6} {:>8} {:>16} {:>16} {:>9}\n",
+        "profit token", "arbs", "hinted", "sweep profit", "hinted profit", "coverage"
+    ));
+    for row in &report.coverage {
+        table.push_str(&format!(
+            "{:<44} {:>6} {:>8} {:>16.6} {:>16.6} {:>9}\n",
+            format!("{:?}", row.profit_token),
+            row.num_opportunities,
+            row.num_hinted,
+            row.sweep_profit,
+            row.hinted_profit,
+            row.coverage
+                .map(|coverage| format!("{:.1}%", coverage * 100.0))
+                .unwrap_or("-".to_owned())
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arb(hash: u64, token: u64, profit: u64) -> SimArbResultBatch {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.hint.hash = H256::from_low_u64_be(hash);
+        arb.profit_token = Address::from_low_u64_be(token);
+        arb.profit_decimals = 18;
+        arb.max_profit = U256::from(profit) * U256::exp10(15);
+        arb
+    }
+
+    #[test]
+    fn it_measures_how_much_of_the_sweep_hints_found() {
+        let sweep = vec![
+            arb(1, 0xa, 100),
+            arb(2, 0xa, 50),
+            arb(3, 0xa, 0),
+            arb(4, 0xb, 10),
+            // swept twice
+            arb(1, 0xa, 100),
+        ];
+        let hinted = vec![
+            // found by two runs
+            arb(1, 0xa, 40),
+            arb(1, 0xa, 60),
+            // quoted in another token, so it doesn't match
+            arb(4, 0xa, 10),
+            // not swept
+            arb(5, 0xa, 30),
+        ];
+        let coverage = coverage(&sweep, &hinted);
+        assert_eq!(coverage.len(), 2);
+        assert_eq!(coverage[0].num_opportunities, 2);
+        assert_eq!(coverage[0].num_hinted, 1);
+        assert!((coverage[0].sweep_profit - 0.15).abs() < 1e-12);
+        assert!((coverage[0].hinted_profit - 0.06).abs() < 1e-12);
+        assert!((coverage[0].coverage.unwrap() - 0.4).abs() < 1e-12);
+        assert_eq!(coverage[1].num_hinted, 0);
+        assert_eq!(coverage[1].coverage, Some(0.0));
+    }
+}
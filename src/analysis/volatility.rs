@@ -0,0 +1,297 @@
+use super::{
+    stats::{format_timestamp, BucketSize},
+    to_decimal,
+};
+use crate::interfaces::SimArbResultBatch;
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Profit & ETH price movement of one time bucket, for one profit token.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolatilityBucket {
+    /// Unix timestamp (seconds) of the start of the bucket.
+    pub bucket_start: u64,
+    pub num_profitable: u64,
+    /// Sum of max profits, in whole profit tokens.
+    pub gross_profit: f64,
+    /// Number of blocks in the bucket that the price feed was read at.
+    pub num_prices: usize,
+    /// Mean ETH price of the bucket's blocks.
+    pub mean_price: Option<f64>,
+    /// Absolute return from the bucket's first to its last price, e.g. 0.02 = price moved 2%.
+    pub abs_return: Option<f64>,
+    /// Standard deviation of the log returns between the bucket's consecutive prices.
+    pub volatility: Option<f64>,
+}
+
+/// Least-squares fit of a bucket's gross profit against one price variable.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fit {
+    /// Name of the variable that profit is regressed on.
+    pub variable: &'static str,
+    /// Number of buckets that the variable is known for.
+    pub num_buckets: usize,
+    /// Pearson correlation coefficient.
+    pub correlation: f64,
+    /// Change in gross profit per unit of the variable.
+    pub slope: f64,
+    pub intercept: f64,
+    pub r_squared: f64,
+}
+
+/// How one profit token's profits move with the ETH price.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolatilityReport {
+    pub profit_token: Address,
+    pub buckets: Vec<VolatilityBucket>,
+    /// Fits of gross profit against volatility, absolute return, and mean price.
+    /// Variables with fewer than 3 known buckets (or no variance) aren't fitted.
+    pub fits: Vec<Fit>,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    num_profitable: u64,
+    gross_profit: U256,
+    decimals: u32,
+}
+
+/// Fits `y = slope * x + intercept` by least squares. Returns None for fewer than 3 points or if either side is constant.
+pub fn fit(variable: &'static str, points: &[(f64, f64)]) -> Option<Fit> {
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    let correlation = cov / (var_x * var_y).sqrt();
+    let slope = cov / var_x;
+    Some(Fit {
+        variable,
+        num_buckets: points.len(),
+        correlation,
+        slope,
+        intercept: mean_y - slope * mean_x,
+        r_squared: correlation.powi(2),
+    })
+}
+
+/// Standard deviation of the log returns between consecutive `prices`, if there are at least 2 returns.
+fn log_return_volatility(prices: &[f64]) -> Option<f64> {
+    let returns = prices
+        .windows(2)
+        .filter(|pair| pair[0] > 0.0 && pair[1] > 0.0)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect::<Vec<_>>();
+    if returns.len() < 2 {
+        return None;
+    }
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+    Some(variance.sqrt())
+}
+
+/// Buckets `arbs` by time and joins each bucket against the ETH prices (by block) read in it,
+/// then fits each profit token's bucketed profit against the ETH price's movement.
+///
+/// The price samples of a bucket are the blocks of all of its arbs, whatever their profit token.
+pub fn volatility_reports(
+    arbs: &[SimArbResultBatch],
+    prices: &BTreeMap<u64, f64>,
+    bucket_size: BucketSize,
+) -> Vec<VolatilityReport> {
+    let mut buckets: BTreeMap<(Address, u64), Accumulator> = BTreeMap::new();
+    let mut bucket_blocks: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+    for arb in arbs {
+        let bucket_start = bucket_size.bucket_start(arb.event.timestamp);
+        bucket_blocks
+            .entry(bucket_start)
+            .or_default()
+            .insert(arb.event.block);
+        let acc = buckets.entry((arb.profit_token, bucket_start)).or_default();
+        acc.decimals = arb.profit_decimals;
+        if !arb.max_profit.is_zero() {
+            acc.num_profitable += 1;
+            acc.gross_profit += arb.max_profit;
+        }
+    }
+
+    let mut reports: BTreeMap<Address, Vec<VolatilityBucket>> = BTreeMap::new();
+    for ((profit_token, bucket_start), acc) in buckets {
+        // blocks are sorted, so prices are in order
+        let bucket_prices = bucket_blocks[&bucket_start]
+            .iter()
+            .filter_map(|block| prices.get(block).copied())
+            .collect::<Vec<_>>();
+        let num_prices = bucket_prices.len();
+        let (first, last) = (bucket_prices.first(), bucket_prices.last());
+        reports
+            .entry(profit_token)
+            .or_default()
+            .push(VolatilityBucket {
+                bucket_start,
+                num_profitable: acc.num_profitable,
+                gross_profit: to_decimal(acc.gross_profit, acc.decimals),
+                num_prices,
+                mean_price: (num_prices > 0)
+                    .then(|| bucket_prices.iter().sum::<f64>() / num_prices as f64),
+                abs_return: match (first, last) {
+                    (Some(first), Some(last)) if num_prices > 1 && *first > 0.0 => {
+                        Some((last / first - 1.0).abs())
+                    }
+                    _ => None,
+                },
+                volatility: log_return_volatility(&bucket_prices),
+            });
+    }
+
+    reports
+        .into_iter()
+        .map(|(profit_token, buckets)| {
+            let points = |value: fn(&VolatilityBucket) -> Option<f64>| {
+                buckets
+                    .iter()
+                    .filter_map(|bucket| value(bucket).map(|x| (x, bucket.gross_profit)))
+                    .collect::<Vec<_>>()
+            };
+            let fits = [
+                fit("volatility", &points(|bucket| bucket.volatility)),
+                fit("abs return", &points(|bucket| bucket.abs_return)),
+                fit("mean price", &points(|bucket| bucket.mean_price)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            VolatilityReport {
+                profit_token,
+                buckets,
+                fits,
+            }
+        })
+        .collect()
+}
+
+fn format_optional(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|value| format!("{:.*}", precision, value))
+        .unwrap_or("-".to_owned())
+}
+
+/// Renders each report's fits, followed by its buckets.
+pub fn render_table(reports: &[VolatilityReport]) -> String {
+    let mut table = String::new();
+    for report in reports {
+        table.push_str(&format!("profit token {:?}\n", report.profit_token));
+        if report.fits.is_empty() {
+            table.push_str("  not enough priced buckets to fit\n");
+        }
+        for fit in &report.fits {
+            table.push_str(&format!(
+                "  profit ~ {:<10}  n={:<5} r={:>7.3}  r2={:>6.3}  slope={:.6}  intercept={:.6}\n",
+                fit.variable,
+                fit.num_buckets,
+                fit.correlation,
+                fit.r_squared,
+                fit.slope,
+                fit.intercept
+            ));
+        }
+        table.push_str(&format!(
+            "\n  {:<17} {:>10} {:>16} {:>7} {:>12} {:>10} {:>12}\n",
+            "bucket",
+            "profitable",
+            "gross profit",
+            "prices",
+            "mean price",
+            "abs return",
+            "volatility"
+        ));
+        for bucket in &report.buckets {
+            table.push_str(&format!(
+                "  {:<17} {:>10} {:>16.6} {:>7} {:>12} {:>10} {:>12}\n",
+                format_timestamp(bucket.bucket_start),
+                bucket.num_profitable,
+                bucket.gross_profit,
+                bucket.num_prices,
+                format_optional(bucket.mean_price, 2),
+                format_optional(bucket.abs_return, 4),
+                format_optional(bucket.volatility, 6),
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fits_lines() {
+        let fitted = fit("x", &[(1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]).unwrap();
+        assert!((fitted.slope - 2.0).abs() < 1e-12);
+        assert!((fitted.intercept - 1.0).abs() < 1e-12);
+        assert!((fitted.correlation - 1.0).abs() < 1e-12);
+        assert!(fit("x", &[(1.0, 3.0), (2.0, 5.0)]).is_none());
+        assert!(fit("x", &[(1.0, 3.0), (1.0, 5.0), (1.0, 4.0)]).is_none());
+    }
+
+    #[test]
+    fn it_joins_profits_with_prices() {
+        let arb = |block: u64, timestamp: u64, milli: u64| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.block = block;
+            arb.event.timestamp = timestamp;
+            arb.max_profit = U256::from(milli) * U256::exp10(15);
+            arb
+        };
+        // hourly buckets: the calm hours make little profit, the volatile hours make more
+        let arbs = (0..4)
+            .flat_map(|hour| {
+                let block = hour * 10;
+                let profit = if hour % 2 == 0 { 1 } else { 10 };
+                (0..3).map(move |i| arb(block + i, hour * 3600 + i, profit))
+            })
+            .collect::<Vec<_>>();
+        let prices = (0..4u64)
+            .flat_map(|hour| {
+                let swing = if hour % 2 == 0 { 1.0 } else { 50.0 };
+                [
+                    (hour * 10, 2000.0),
+                    (hour * 10 + 1, 2000.0 + swing),
+                    (hour * 10 + 2, 2000.0 - swing),
+                ]
+            })
+            .collect::<BTreeMap<_, _>>();
+        let reports = volatility_reports(&arbs, &prices, BucketSize::Hourly);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.buckets.len(), 4);
+        assert_eq!(report.buckets[0].num_prices, 3);
+        assert!((report.buckets[1].gross_profit - 0.03).abs() < 1e-12);
+        let volatility = report
+            .fits
+            .iter()
+            .find(|fit| fit.variable == "volatility")
+            .unwrap();
+        assert_eq!(volatility.num_buckets, 4);
+        assert!(volatility.correlation > 0.99);
+        // the mean price never changes, so profit can't be fitted against it
+        assert!(!report.fits.iter().any(|fit| fit.variable == "mean price"));
+        assert!(render_table(&reports).contains("profit ~ volatility"));
+    }
+}
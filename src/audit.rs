@@ -0,0 +1,527 @@
+use crate::{
+    data::EXPORT_DIR,
+    interfaces::{FailureCategory, SimArbResult},
+    Result,
+};
+use ethers::types::{Address, H256};
+use mev_share_sse::EventHistory;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Stage of the sim pipeline at which a decision is made, in the order that events go through them.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditStage {
+    /// Whether an event's tx is backrun at all (one decision per tx).
+    Event,
+    /// Whether a pool that the tx swapped on is arbed (one decision per pool).
+    Pool,
+    /// Whether a route between that pool & another one is simulated (one decision per route).
+    Route,
+    /// How a simulated route's search ended (one decision per simulated route).
+    Sim,
+}
+
+impl std::fmt::Display for AuditStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditStage::Event => write!(f, "event"),
+            AuditStage::Pool => write!(f, "pool"),
+            AuditStage::Route => write!(f, "route"),
+            AuditStage::Sim => write!(f, "sim"),
+        }
+    }
+}
+
+/// What the engine decided about an event, pool, or route of an audited tx.
+///
+/// Every decision but `Routed`, `Simulated` & `Profitable` drops its event, pool, or route from the funnel.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditDecision {
+    /// None of the event's hinted logs has the topic of a swap that an adapter decodes.
+    NoSwapHint,
+    /// The event's hinted logs didn't have the layout of EVM logs.
+    MalformedHint,
+    /// The event touches a token or pool excluded by the address lists.
+    ExcludedEvent,
+    /// The tx was sent by, or to, a known searcher, so it's not user orderflow.
+    SearcherTx,
+    /// The user's tx reverted when replayed on the fork, so none of its pools were tried.
+    UserTxReverted,
+    /// The tx's simulation failed as a whole (e.g. the node didn't have its receipt).
+    TxFailed,
+    /// The pool is excluded by the address lists.
+    ExcludedPool,
+    /// The pool trades a token excluded by the address lists.
+    ExcludedToken,
+    /// The pool doesn't trade any of the quote assets.
+    NoQuoteAsset,
+    /// No other pool trades the pool's pair.
+    NoAltPool,
+    /// The pool's token failed the safety screen.
+    UnsafeToken,
+    /// The pool's routes were considered.
+    Routed,
+    /// The other pool of the route couldn't be priced.
+    QuoteFailed,
+    /// Both pools of the route are priced the same after the user's tx.
+    SamePrice,
+    /// The price gap between the route's pools is below `SIM_MIN_PRICE_GAP`.
+    BelowSpread,
+    /// The price gap between the route's pools can't pay for the backrun's gas.
+    BelowGasCost,
+    /// The route was simulated.
+    Simulated,
+    /// The tx's sim budget ran out before the route's search found a profit.
+    BudgetCap,
+    /// The route's backrun reverted or halted in the EVM.
+    Reverted,
+    /// The route's search failed for another reason.
+    SimFailed,
+    /// The route's search finished without finding a profit.
+    Unprofitable,
+    /// The route's search found a profitable backrun.
+    Profitable,
+}
+
+impl AuditDecision {
+    pub fn stage(&self) -> AuditStage {
+        match self {
+            AuditDecision::NoSwapHint
+            | AuditDecision::MalformedHint
+            | AuditDecision::ExcludedEvent
+            | AuditDecision::SearcherTx
+            | AuditDecision::UserTxReverted
+            | AuditDecision::TxFailed => AuditStage::Event,
+            AuditDecision::ExcludedPool
+            | AuditDecision::ExcludedToken
+            | AuditDecision::NoQuoteAsset
+            | AuditDecision::NoAltPool
+            | AuditDecision::UnsafeToken
+            | AuditDecision::Routed => AuditStage::Pool,
+            AuditDecision::QuoteFailed
+            | AuditDecision::SamePrice
+            | AuditDecision::BelowSpread
+            | AuditDecision::BelowGasCost
+            | AuditDecision::Simulated => AuditStage::Route,
+            AuditDecision::BudgetCap
+            | AuditDecision::Reverted
+            | AuditDecision::SimFailed
+            | AuditDecision::Unprofitable
+            | AuditDecision::Profitable => AuditStage::Sim,
+        }
+    }
+
+    /// Whether the event, pool, or route goes on to the next stage (or, for `Profitable`, produced a result).
+    pub fn passes(&self) -> bool {
+        matches!(
+            self,
+            AuditDecision::Routed | AuditDecision::Simulated | AuditDecision::Profitable
+        )
+    }
+
+    /// Decision about a tx whose simulation failed with `error`.
+    pub fn of_tx_error(error: &str) -> Self {
+        match FailureCategory::of(error) {
+            FailureCategory::MalformedHint => AuditDecision::MalformedHint,
+            FailureCategory::UserTxReverted => AuditDecision::UserTxReverted,
+            _ => AuditDecision::TxFailed,
+        }
+    }
+
+    /// Decision about a simulated route whose search ended with `res`.
+    /// `truncated` is whether the tx's sim budget cut any of its searches short.
+    pub fn of_sim(res: std::result::Result<&SimArbResult, &str>, truncated: bool) -> Self {
+        match res {
+            Ok(res) if !res.backrun_trade.profit.is_zero() => AuditDecision::Profitable,
+            Ok(_) if truncated => AuditDecision::BudgetCap,
+            Ok(_) => AuditDecision::Unprofitable,
+            Err(err) if err.contains("budget exhausted") => AuditDecision::BudgetCap,
+            Err(err) => match FailureCategory::of(err) {
+                FailureCategory::Reverted => AuditDecision::Reverted,
+                _ => AuditDecision::SimFailed,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for AuditDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditDecision::NoSwapHint => write!(f, "no swap hint"),
+            AuditDecision::MalformedHint => write!(f, "malformed hint"),
+            AuditDecision::ExcludedEvent => write!(f, "excluded event"),
+            AuditDecision::SearcherTx => write!(f, "searcher tx"),
+            AuditDecision::UserTxReverted => write!(f, "user reverted"),
+            AuditDecision::TxFailed => write!(f, "tx failed"),
+            AuditDecision::ExcludedPool => write!(f, "excluded pool"),
+            AuditDecision::ExcludedToken => write!(f, "excluded token"),
+            AuditDecision::NoQuoteAsset => write!(f, "no quote asset"),
+            AuditDecision::NoAltPool => write!(f, "no alt pool"),
+            AuditDecision::UnsafeToken => write!(f, "unsafe token"),
+            AuditDecision::Routed => write!(f, "routed"),
+            AuditDecision::QuoteFailed => write!(f, "quote failed"),
+            AuditDecision::SamePrice => write!(f, "same price"),
+            AuditDecision::BelowSpread => write!(f, "below spread"),
+            AuditDecision::BelowGasCost => write!(f, "below gas cost"),
+            AuditDecision::Simulated => write!(f, "simulated"),
+            AuditDecision::BudgetCap => write!(f, "budget cap"),
+            AuditDecision::Reverted => write!(f, "reverted"),
+            AuditDecision::SimFailed => write!(f, "sim failed"),
+            AuditDecision::Unprofitable => write!(f, "unprofitable"),
+            AuditDecision::Profitable => write!(f, "profitable"),
+        }
+    }
+}
+
+/// A decision about an audited tx, or one of its pools or routes.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub tx_hash: H256,
+    pub block: u64,
+    /// The pool decided about; for routes, the other pool of the route. None for decisions about the whole event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<Address>,
+    pub decision: AuditDecision,
+    /// Error behind the decision, if it was made because something failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collects the decisions that the engine makes about a sample of events, to find where opportunities are lost.
+///
+/// Events are sampled by tx hash, so the same events are audited every time a range is scanned.
+/// Clones share their records.
+#[derive(Clone, Debug, Default)]
+pub struct AuditLog {
+    /// Share of events that are audited (0 to 1).
+    sample_rate: f64,
+    /// Where records are appended by `save`. In-memory only if None.
+    path: Option<PathBuf>,
+    /// Txs audited by earlier runs of the session, which aren't audited again.
+    audited: Arc<HashSet<H256>>,
+    records: Arc<Mutex<Vec<AuditRecord>>>,
+}
+
+impl AuditLog {
+    /// Audits `sample_rate` of events in memory.
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Audits `sample_rate` of events into `session`'s records, resuming the session if it exists.
+    /// Txs that the session already has records of aren't audited again.
+    pub fn open(session: &str, sample_rate: f64) -> Result<Self> {
+        if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+            return Err(anyhow::format_err!(
+                "audit sample rate must be in (0, 1], got {}",
+                sample_rate
+            ));
+        }
+        let path = audit_path(session);
+        let audited = load_records(&path)?
+            .into_iter()
+            .map(|record| record.tx_hash)
+            .collect();
+        Ok(Self {
+            sample_rate,
+            path: Some(path),
+            audited: Arc::new(audited),
+            ..Default::default()
+        })
+    }
+
+    /// Whether the tx of `tx_hash` is audited.
+    pub fn samples(&self, tx_hash: &H256) -> bool {
+        // tx hashes are uniformly distributed, so their leading bytes make a stable sample
+        let position =
+            u64::from_be_bytes(tx_hash.0[..8].try_into().unwrap()) as f64 / u64::MAX as f64;
+        position < self.sample_rate && !self.audited.contains(tx_hash)
+    }
+
+    /// Records `decision` about `event`'s tx (or one of its pools), if it's sampled.
+    pub fn record(
+        &self,
+        event: &EventHistory,
+        pool: Option<Address>,
+        decision: AuditDecision,
+        error: Option<&str>,
+    ) {
+        if !self.samples(&event.hint.hash) {
+            return;
+        }
+        self.records.lock().unwrap().push(AuditRecord {
+            tx_hash: event.hint.hash,
+            block: event.block,
+            pool,
+            decision,
+            error: error.map(|error| error.to_owned()),
+        });
+    }
+
+    /// Takes the records collected since the last call (or `save`).
+    pub fn drain(&self) -> Vec<AuditRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap())
+    }
+
+    /// Appends the records collected since the last save to the session's file.
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => append_records(path, &self.drain()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Where `session`'s audit records are saved.
+pub fn audit_path(session: &str) -> PathBuf {
+    PathBuf::from(format!("{}/audit_{}.jsonl", EXPORT_DIR, session))
+}
+
+/// Reads the records saved at `path`, one per line. A file that doesn't exist has no records.
+pub fn load_records(path: &Path) -> Result<Vec<AuditRecord>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    BufReader::new(std::fs::File::open(path)?)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Appends `records` to the file at `path`, one per line.
+pub fn append_records(path: &Path, records: &[AuditRecord]) -> Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    for record in records {
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+    }
+    Ok(())
+}
+
+/// Events, pools, or routes dropped at a stage for one reason.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunnelDrop {
+    pub decision: AuditDecision,
+    pub count: u64,
+    /// Hashes of the first few txs that were dropped this way.
+    pub examples: Vec<H256>,
+}
+
+/// How many events, pools, or routes entered & passed a stage, and why the rest were dropped.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunnelStage {
+    pub stage: AuditStage,
+    pub entered: u64,
+    pub passed: u64,
+    /// Most dropped first.
+    pub drops: Vec<FunnelDrop>,
+}
+
+/// Groups `records` into a funnel of the pipeline's stages, keeping up to `num_examples` example txs per drop reason.
+///
+/// Pools & routes of txs that were dropped as a whole (e.g. whose replay reverted) don't count at later stages.
+pub fn funnel(records: &[AuditRecord], num_examples: usize) -> Vec<FunnelStage> {
+    let dropped_txs = records
+        .iter()
+        .filter(|record| record.decision.stage() == AuditStage::Event)
+        .map(|record| record.tx_hash)
+        .collect::<HashSet<_>>();
+    let (mut txs, mut counted_drops) = (HashSet::new(), HashSet::new());
+    let mut stages: HashMap<AuditStage, (u64, HashMap<AuditDecision, FunnelDrop>)> = HashMap::new();
+    for record in records {
+        txs.insert(record.tx_hash);
+        let counted = match record.decision.stage() {
+            // a tx is dropped once, however its drop was recorded
+            AuditStage::Event => counted_drops.insert(record.tx_hash),
+            _ => !dropped_txs.contains(&record.tx_hash),
+        };
+        if !counted {
+            continue;
+        }
+        let (passed, drops) = stages.entry(record.decision.stage()).or_default();
+        if record.decision.passes() {
+            *passed += 1;
+            continue;
+        }
+        let drop = drops.entry(record.decision).or_insert(FunnelDrop {
+            decision: record.decision,
+            count: 0,
+            examples: vec![],
+        });
+        drop.count += 1;
+        if drop.examples.len() < num_examples && !drop.examples.contains(&record.tx_hash) {
+            drop.examples.push(record.tx_hash);
+        }
+    }
+    // every audited tx enters the first stage, whether or not it was dropped there
+    let num_txs = txs.len() as u64;
+    let mut funnel = [
+        AuditStage::Event,
+        AuditStage::Pool,
+        AuditStage::Route,
+        AuditStage::Sim,
+    ]
+    .into_iter()
+    .map(|stage| {
+        let (passed, drops) = stages.remove(&stage).unwrap_or_default();
+        let mut drops = drops.into_values().collect::<Vec<_>>();
+        drops.sort_by_key(|drop| (std::cmp::Reverse(drop.count), drop.decision));
+        let num_dropped = drops.iter().map(|drop| drop.count).sum::<u64>();
+        FunnelStage {
+            stage,
+            entered: passed + num_dropped,
+            passed,
+            drops,
+        }
+    })
+    .collect::<Vec<_>>();
+    funnel[0].entered = num_txs;
+    funnel[0].passed = num_txs - funnel[0].drops.iter().map(|drop| drop.count).sum::<u64>();
+    funnel
+}
+
+/// Renders `funnel` as a plain-text table, one row per drop reason under each stage.
+pub fn render_table(funnel: &[FunnelStage]) -> String {
+    let mut table = format!(
+        "{:<8} {:<16} {:>8} {:>8}\n",
+        "stage", "decision", "count", "share"
+    );
+    for stage in funnel {
+        let share = |count: u64| match stage.entered {
+            0 => 0.0,
+            entered => count as f64 / entered as f64 * 100.0,
+        };
+        table.push_str(&format!(
+            "{:<8} {:<16} {:>8} {:>7.1}%\n",
+            stage.stage, "entered", stage.entered, 100.0
+        ));
+        for drop in &stage.drops {
+            table.push_str(&format!(
+                "{:<8} {:<16} {:>8} {:>7.1}%\n",
+                "",
+                drop.decision.to_string(),
+                drop.count,
+                share(drop.count)
+            ));
+        }
+        table.push_str(&format!(
+            "{:<8} {:<16} {:>8} {:>7.1}%\n",
+            "",
+            "passed",
+            stage.passed,
+            share(stage.passed)
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tx: u64, pool: Option<u64>, decision: AuditDecision) -> AuditRecord {
+        AuditRecord {
+            tx_hash: H256::from_low_u64_be(tx),
+            block: 1,
+            pool: pool.map(Address::from_low_u64_be),
+            decision,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn it_builds_a_funnel() {
+        let records = vec![
+            record(1, None, AuditDecision::NoSwapHint),
+            // tx 2 has a pool without an alt pool, and one with two routes
+            record(2, Some(1), AuditDecision::NoAltPool),
+            record(2, Some(2), AuditDecision::Routed),
+            record(2, Some(3), AuditDecision::SamePrice),
+            record(2, Some(4), AuditDecision::Simulated),
+            record(2, Some(4), AuditDecision::Profitable),
+            // tx 3's pools don't count, since its replay reverted
+            record(3, Some(5), AuditDecision::Routed),
+            record(3, None, AuditDecision::UserTxReverted),
+        ];
+        let funnel = funnel(&records, 5);
+        let counts = funnel
+            .iter()
+            .map(|stage| (stage.stage, stage.entered, stage.passed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                (AuditStage::Event, 3, 1),
+                (AuditStage::Pool, 2, 1),
+                (AuditStage::Route, 2, 1),
+                (AuditStage::Sim, 1, 1),
+            ]
+        );
+        assert_eq!(funnel[0].drops.len(), 2);
+        assert_eq!(funnel[1].drops[0].decision, AuditDecision::NoAltPool);
+        assert_eq!(funnel[1].drops[0].examples, vec![H256::from_low_u64_be(2)]);
+    }
+
+    #[test]
+    fn it_samples_txs_by_hash() {
+        let log = AuditLog::new(0.5);
+        let low = H256::from_low_u64_be(1);
+        let mut high = H256::zero();
+        high.0[0] = 0xff;
+        assert!(log.samples(&low));
+        assert!(!log.samples(&high));
+        assert!(!AuditLog::new(0.0).samples(&low));
+        assert!(AuditLog::new(1.0).samples(&high));
+    }
+
+    #[test]
+    fn it_classifies_sim_outcomes() {
+        let mut res = SimArbResult::test_example(Address::zero(), 1.into(), 0.into());
+        assert_eq!(
+            AuditDecision::of_sim(Ok(&res), false),
+            AuditDecision::Unprofitable
+        );
+        assert_eq!(
+            AuditDecision::of_sim(Ok(&res), true),
+            AuditDecision::BudgetCap
+        );
+        res.backrun_trade.profit = 1.into();
+        assert_eq!(
+            AuditDecision::of_sim(Ok(&res), true),
+            AuditDecision::Profitable
+        );
+        assert_eq!(
+            AuditDecision::of_sim(Err("tx sim budget exhausted"), false),
+            AuditDecision::BudgetCap
+        );
+        assert_eq!(
+            AuditDecision::of_sim(Err("execution reverted"), false),
+            AuditDecision::Reverted
+        );
+        assert_eq!(
+            AuditDecision::of_tx_error("user tx reverted: deadline"),
+            AuditDecision::UserTxReverted
+        );
+    }
+}
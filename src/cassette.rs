@@ -0,0 +1,293 @@
+use crate::{config::Config, debug, info, warn, Result};
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, RpcError, Ws};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+/// Whether a cassette is recorded from a node, or replayed in place of one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// A node's response to a JSON-RPC request: its `result`, or its `error` object.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Recorded {
+    Result(Value),
+    Error(Value),
+}
+
+/// A line of a cassette file.
+#[derive(Debug, Deserialize, Serialize)]
+struct CassetteEntry {
+    request: String,
+    response: Recorded,
+}
+
+/// Responses of a node by request, kept in a file of JSON lines that grows as they're recorded.
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    responses: HashMap<String, Recorded>,
+}
+
+/// Key of a request in a cassette: its method & params, without the JSON-RPC id, which changes between runs.
+pub fn request_key(method: &str, params: &Value) -> String {
+    format!("{} {}", method, params)
+}
+
+impl Cassette {
+    /// Loads the cassette at `path`. A cassette that doesn't exist yet is empty when recording, and an error when replaying.
+    pub fn open(path: &Path, mode: CassetteMode) -> Result<Self> {
+        let mut responses = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(std::fs::File::open(path)?).lines() {
+                let entry: CassetteEntry = serde_json::from_str(&line?)?;
+                responses.insert(entry.request, entry.response);
+            }
+        } else if mode == CassetteMode::Replay {
+            return Err(anyhow::format_err!(
+                "cassette {} not found; record it with --record",
+                path.display()
+            ));
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            responses,
+        })
+    }
+
+    pub fn get(&self, request: &str) -> Option<&Recorded> {
+        self.responses.get(request)
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    /// Saves the response to `request`, unless one was already recorded. Written through to the file,
+    /// so a run that's cut short keeps what it recorded.
+    pub fn record(&mut self, request: &str, response: Recorded) -> Result<()> {
+        if self.responses.contains_key(request) {
+            return Ok(());
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let entry = CassetteEntry {
+            request: request.to_owned(),
+            response,
+        };
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.responses.insert(entry.request, entry.response);
+        Ok(())
+    }
+}
+
+/// Answers JSON-RPC requests in place of a node; see `serve_rpc`.
+#[async_trait]
+pub trait RpcResponder: Send + Sync {
+    /// The response to a request for `method` with `params`.
+    async fn respond(&self, method: &str, params: &Value) -> Recorded;
+}
+
+/// Answers requests from a cassette, forwarding them to the node (and recording the responses) when recording.
+struct CassettePlayer {
+    cassette: Mutex<Cassette>,
+    node: Option<Ws>,
+}
+
+#[async_trait]
+impl RpcResponder for CassettePlayer {
+    async fn respond(&self, method: &str, params: &Value) -> Recorded {
+        let key = request_key(method, params);
+        let Some(node) = &self.node else {
+            return self
+                .cassette
+                .lock()
+                .await
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| rpc_error(format!("request not in cassette: {}", key)));
+        };
+        let response = match node.request::<_, Value>(method, params).await {
+            Ok(result) => Recorded::Result(result),
+            Err(err) => match err.as_error_response() {
+                Some(err) => Recorded::Error(
+                    json!({ "code": err.code, "message": err.message, "data": err.data }),
+                ),
+                // only the node's own errors are recorded; a failed connection isn't part of the chain's history
+                None => return rpc_error(format!("node didn't answer {}: {}", key, err)),
+            },
+        };
+        if let Err(err) = self.cassette.lock().await.record(&key, response.to_owned()) {
+            warn!("failed to record {}: {}", method, err);
+        }
+        response
+    }
+}
+
+/// A JSON-RPC error response with `message`.
+pub fn rpc_error(message: String) -> Recorded {
+    Recorded::Error(json!({ "code": -32000, "message": message }))
+}
+
+/// Serves JSON-RPC over WebSocket on a local port, in place of the node at `upstream`: requests are forwarded to
+/// the node and recorded to the cassette at `path`, or answered from the cassette without connecting to the node.
+///
+/// Returns the URL to connect to instead of the node's. Serves until the process exits.
+pub async fn serve(mode: CassetteMode, path: &Path, upstream: &str) -> Result<String> {
+    let cassette = Cassette::open(path, mode)?;
+    let node = match mode {
+        CassetteMode::Record => Some(Ws::connect(upstream).await?),
+        CassetteMode::Replay => None,
+    };
+    let num_responses = cassette.len();
+    let url = serve_rpc(Arc::new(CassettePlayer {
+        cassette: Mutex::new(cassette),
+        node,
+    }))
+    .await?;
+    info!(
+        "{} cassette {} ({} responses) on {}",
+        match mode {
+            CassetteMode::Record => "recording",
+            CassetteMode::Replay => "replaying",
+        },
+        path.display(),
+        num_responses,
+        url
+    );
+    Ok(url)
+}
+
+/// Serves JSON-RPC over WebSocket on a local port, answering every request with `responder`.
+///
+/// Returns the URL to connect to. Serves until the process exits.
+pub async fn serve_rpc(responder: Arc<dyn RpcResponder>) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("ws://{}", listener.local_addr()?);
+    tokio::task::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!("rpc server stopped accepting connections: {}", err);
+                    return;
+                }
+            };
+            let responder = responder.clone();
+            tokio::task::spawn(async move {
+                if let Err(err) = serve_client(stream, responder).await {
+                    debug!("rpc client {} disconnected: {}", peer, err);
+                }
+            });
+        }
+    });
+    Ok(url)
+}
+
+/// Points every chain of `config` at a cassette of its node in `dir`, named by chain id (e.g. `1.jsonl`).
+pub async fn use_cassettes(config: &mut Config, mode: CassetteMode, dir: &Path) -> Result<()> {
+    if mode == CassetteMode::Record {
+        std::fs::create_dir_all(dir)?;
+    }
+    for chain in config.chains.iter_mut() {
+        let url = serve(
+            mode,
+            &dir.join(format!("{}.jsonl", chain.chain_id)),
+            &chain.rpc_url_ws,
+        )
+        .await?;
+        if config.rpc_url_ws == chain.rpc_url_ws {
+            config.rpc_url_ws = url.to_owned();
+        }
+        chain.rpc_url_ws = url;
+    }
+    Ok(())
+}
+
+/// Answers the requests of the client on `stream`, each as soon as its response is ready, until either side closes.
+async fn serve_client(stream: TcpStream, responder: Arc<dyn RpcResponder>) -> Result<()> {
+    let (mut sink, mut incoming) = accept_async(stream).await?.split();
+    let (responses, mut outgoing) = mpsc::unbounded_channel();
+    loop {
+        tokio::select! {
+            message = incoming.next() => match message {
+                Some(Ok(Message::Text(request))) => {
+                    let (responder, responses) = (responder.clone(), responses.clone());
+                    tokio::task::spawn(async move {
+                        let _ = responses.send(respond(&request, responder.as_ref()).await);
+                    });
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            },
+            Some(response) = outgoing.recv() => sink.send(Message::Text(response)).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Answers the JSON-RPC `request` with `responder`.
+async fn respond(request: &str, responder: &dyn RpcResponder) -> String {
+    let request: Value = serde_json::from_str(request).unwrap_or_default();
+    let method = request["method"].as_str().unwrap_or_default();
+    let params = request.get("params").cloned().unwrap_or_default();
+    let id = request["id"].clone();
+    match responder.respond(method, &params).await {
+        Recorded::Result(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Recorded::Error(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        providers::{Middleware, Provider},
+        types::U256,
+    };
+
+    #[tokio::test]
+    async fn it_replays_recorded_responses() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("cassette-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(Cassette::open(&path, CassetteMode::Replay).is_err());
+        let mut cassette = Cassette::open(&path, CassetteMode::Record)?;
+        cassette.record(
+            &request_key("eth_chainId", &Value::Null),
+            Recorded::Result(json!("0x2a")),
+        )?;
+        drop(cassette);
+
+        let url = serve(CassetteMode::Replay, &path, "ws://unused").await?;
+        let client = Provider::<Ws>::connect(url).await?;
+        assert_eq!(client.get_chainid().await?, U256::from(42));
+        let err = client.get_block_number().await.unwrap_err();
+        assert!(err.to_string().contains("not in cassette"), "{}", err);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -1,16 +1,62 @@
-use clap::{Parser, Subcommand};
-use hindsight::data::db::DbEngine;
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    Args, Parser, Subcommand,
+};
+use ethers::types::Address;
+use hindsight::{
+    analysis::{
+        leaderboard::{LeaderboardKind, LEADERBOARD_KIND_NAMES},
+        report::{ReportFormat, REPORT_FORMAT_NAMES},
+        sizing::{SizingGroup, SIZING_GROUP_NAMES},
+        stats::{BucketSize, BUCKET_SIZE_NAMES},
+    },
+    commands::{OutputFormat, OUTPUT_FORMAT_NAMES},
+    data::{
+        db::DB_ENGINE_NAMES,
+        external::{DatasetSource, DATASET_SOURCE_NAMES},
+    },
+    interfaces::{LabelKind, LABEL_KIND_NAMES},
+    telemetry::{LogFormat, LOG_FORMAT_NAMES},
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+    /// Config profile to use, from `hindsight.toml` (or the file at `HINDSIGHT_CONFIG`).
+    #[arg(long, global = true, env = "HINDSIGHT_PROFILE")]
+    pub profile: Option<String>,
+    /// Print every command's output (results, stats, and errors) in this format, overriding its `--format`.
+    /// Logs always go to stderr.
+    #[arg(long, global = true, value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+    pub output: Option<OutputFormat>,
+    /// Format of the logs written to stderr; `json` writes one object per line, for log stores like Loki or Elasticsearch.
+    #[arg(long, global = true, env = "HINDSIGHT_LOG_FORMAT", default_value = "text", value_parser = PossibleValuesParser::new(LOG_FORMAT_NAMES).map(|s| s.parse::<LogFormat>().unwrap()))]
+    pub log_format: LogFormat,
+    /// Export tracing spans of the sim pipeline to the OTLP collector at `OTEL_EXPORTER_OTLP_ENDPOINT` (e.g. Jaeger).
+    #[arg(long, global = true)]
+    pub otlp: bool,
+    /// Record every response of the chains' nodes to cassettes in this directory (one per chain), to `--replay` later.
+    #[arg(long, global = true, conflicts_with = "replay")]
+    pub record: Option<std::path::PathBuf>,
+    /// Answer the nodes' requests from the cassettes that `--record` saved in this directory, without connecting to the nodes.
+    #[arg(long, global = true)]
+    pub replay: Option<std::path::PathBuf>,
+    /// Simulate in a fixed order (each tx's routes & search steps one at a time, results in the order of their txs),
+    /// so runs over the same `--replay` cassettes give identical results. Ignores `TX_MAX_SIM_SECS`.
+    #[arg(long, global = true)]
+    pub deterministic: bool,
 }
 
 impl Cli {
+    /// Parses the command line, applying `--output` to the command's `--format`.
     pub fn parse_args() -> Self {
-        Self::parse()
+        let mut cli = Self::parse();
+        if let (Some(output), Some(command)) = (cli.output, cli.command.as_mut()) {
+            command.set_format(output);
+        }
+        cli
     }
 }
 
@@ -22,8 +68,8 @@ pub enum Commands {
         /// Scan from this block.
         #[arg(short, long)]
         block_start: Option<u32>,
-        /// Scan from this block.
-        #[arg(short, long)]
+        /// Scan from this timestamp.
+        #[arg(short, long, conflicts_with = "block_start")]
         timestamp_start: Option<u32>,
         /// Scan until this block.
         #[arg(long)]
@@ -31,15 +77,112 @@ pub enum Commands {
         /// Scan until this timestamp.
         #[arg(long)]
         timestamp_end: Option<u32>,
-        /// Number of transactions to simulate concurrently. Defaults to 1/2 the CPU cores on host.
+        /// Number of transactions to simulate concurrently. Defaults to the profile's `scan.batch_size`, or 1/2 the CPU cores on host.
         #[arg(short = 'n', long)]
         batch_size: Option<usize>,
-        /// DB Engine to use to store arb data. Defaults to "mongo".
+        /// Tune the batch size (starting from --batch-size) as the scan runs, raising it while throughput improves
+        /// and lowering it when RPC errors or latency spike.
+        #[arg(long)]
+        auto_tune: bool,
+        /// Lowest batch size that --auto-tune may pick.
+        #[arg(long, default_value_t = 1)]
+        min_batch_size: usize,
+        /// Highest batch size that --auto-tune may pick. Defaults to 4x the batch size.
+        #[arg(long)]
+        max_batch_size: Option<usize>,
+        /// DB Engine to use to store arb data. Defaults to the profile's `db`, or "mongo".
         #[arg(
             long = "db",
-            help = &format!("<{}>: DB engine to store arb data, defaults to mongo", DbEngine::enum_flags())
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine to store arb data, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
         )]
-        db_engine: Option<DbEngine>,
+        db_engine: Option<String>,
+        /// Label to tag this run's results with, so they can be queried separately later.
+        #[arg(long)]
+        run_label: Option<String>,
+        /// Unix socket to listen on for commands that tune the scan while it runs (e.g. `set batch_size 4`).
+        #[arg(long)]
+        control_socket: Option<std::path::PathBuf>,
+        /// TOML file of tokens & pools to allow/deny. Changes are picked up without restarting.
+        #[arg(long)]
+        address_lists: Option<std::path::PathBuf>,
+        /// Save every fetched event to the DB, so that `hints` can compare events with & without arbs.
+        #[arg(long)]
+        save_events: bool,
+        /// Look for swaps in the receipts of txs whose events hint no logs, instead of skipping them. Costs a request per such event.
+        #[arg(long)]
+        replay_private: bool,
+        /// Reuse backrun sims that earlier scans of the same txs ran (kept per chain in `./arbData/`), and keep the new ones.
+        #[arg(long)]
+        sim_cache: bool,
+        /// Re-simulate every profitable result on a freshly built fork before saving it, flagging those that don't reproduce.
+        #[arg(long)]
+        verify: bool,
+        /// Post to the webhook at `NOTIFY_URL` (and chat bots) when each chain's scan starts, and a summary when it ends or fails.
+        #[arg(long)]
+        notify: bool,
+        /// Write per-block profit & opportunity counts to the InfluxDB endpoint at `METRICS_URL` as the scan runs.
+        #[arg(long)]
+        metrics: bool,
+        /// Push every result as JSON to WebSocket clients connected to this address (e.g. 127.0.0.1:8546), as soon as it's produced.
+        #[arg(long)]
+        results_ws: Option<std::net::SocketAddr>,
+        /// Alert the webhooks at `ALERT_URLS` (and chat bots) about every arb with at least this much profit (in whole units of its profit token).
+        #[arg(long)]
+        alert_min_profit: Option<f64>,
+        /// Maximum number of alerts to send per minute; the rest are skipped.
+        #[arg(long, default_value_t = 10)]
+        alert_rate_limit: usize,
+        /// Paper-trade every opportunity into this session's ledger, as if a bot had been sending the backruns.
+        /// An existing session is resumed.
+        #[arg(long)]
+        paper: Option<String>,
+        /// Priority fee that paper trades pay on top of the base fee, in gwei.
+        #[arg(long, default_value_t = 1.0)]
+        paper_tip: f64,
+        /// Percentage of each paper trade's profit after gas that's paid as a builder bribe.
+        #[arg(long, default_value_t = 90.0)]
+        paper_bribe: f64,
+        /// Blocks it takes the paper bot to react; opportunities are re-simulated this many blocks later.
+        #[arg(long, default_value_t = 0)]
+        paper_latency_blocks: u64,
+        /// Milliseconds from an event arriving to the paper bot's backrun reaching the builder. Opportunities are
+        /// re-simulated on the block the backrun would've landed in, given when the event was emitted & observed block times.
+        #[arg(long, conflicts_with = "paper_latency_blocks")]
+        paper_latency_ms: Option<u64>,
+        /// Sign an arb of every opportunity with the auth signer and submit it as a bundle to the chain's relays
+        /// (`RELAY_URLS_<CHAIN>`), through its executor (see `deploy-executor`). These aren't backruns: the user's tx
+        /// has already landed, so each arb is sent alone for the next block, and only if the executor's call is
+        /// still profitable after gas on the latest state. Only while following new events, and only on one chain
+        /// (`CHAINS` must name a single chain).
+        #[arg(long, requires = "send_arbs_daily_limit")]
+        send_arbs: bool,
+        /// Most gas that --send-arbs's bundles may cost per UTC day, in whole native tokens of the scanned chain
+        /// (e.g. ETH on mainnet), counting every submitted bundle as if it landed.
+        #[arg(long)]
+        send_arbs_daily_limit: Option<f64>,
+        /// Priority fee that --send-arbs's arbs pay on top of the base fee, in gwei.
+        #[arg(long, default_value_t = 1.0)]
+        send_arbs_tip: f64,
+        /// Record why each sampled event did or didn't produce a result into this audit session (see `audit`).
+        /// An existing session is resumed.
+        #[arg(long)]
+        audit: Option<String>,
+        /// Share of events that --audit samples (0 to 1). Events are sampled by tx hash, so rescans sample the same ones.
+        #[arg(long, default_value_t = 0.1)]
+        audit_sample: f64,
+        /// File of borrowers (one address per line) whose Aave V3 & Compound V3 positions are checked after each tx.
+        /// Liquidations that a tx makes possible are simulated & saved alongside arbs.
+        #[arg(long)]
+        liquidations: Option<std::path::PathBuf>,
+        /// Also backrun cycles of up to this many pools (at least 3) through each user's pool, found in a graph of
+        /// recently traded pools that's indexed while scanning. Only two-pool routes are searched if not set.
+        #[arg(long)]
+        max_hops: Option<usize>,
+        /// Don't simulate anything: fetch the range's events, then print how many txs, EVM runs & RPC requests
+        /// scanning it would take, and roughly how long, with the other options' settings. Needs a start.
+        #[arg(long)]
+        estimate: bool,
     },
     /// Export arbs from DB to a JSON file.
     Export {
@@ -67,14 +210,605 @@ pub enum Commands {
         /// TODO: DRY this up
         #[arg(
             long = "db",
-            help = &format!("<{}>: DB engine to read arb data from, defaults to mongo", DbEngine::enum_flags())
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine to read arb data from, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
         )]
-        read_db: Option<DbEngine>,
+        read_db: Option<String>,
         #[arg(
             short = 'o',
             long = "db-out",
-            help = &format!("<{}>: DB engine to write arb data to, default None (save to file). Ignored if --filename is specified.", DbEngine::enum_flags())
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine to write arb data to, default None (save to file). Ignored if --filename is specified.", DB_ENGINE_NAMES.join(" | "))
+        )]
+        write_db: Option<String>,
+        /// Name of the chain to export arbs from, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        /// Only export arbs from the scan run with this label.
+        #[arg(long)]
+        run_label: Option<String>,
+    },
+    /// Aggregate stored arbs into time buckets: count, gross/net profit, and unique pools.
+    Stats {
+        /// Size of each time bucket.
+        #[arg(long, default_value = "daily", value_parser = PossibleValuesParser::new(BUCKET_SIZE_NAMES).map(|s| s.parse::<BucketSize>().unwrap()))]
+        bucket: BucketSize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        /// Break each bucket down by the (start DEX, end DEX) pair of each event's best backrun.
+        #[arg(long)]
+        by_dex: bool,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Rank tokens, routes or DEXes by total simulated profit.
+    Leaderboard {
+        /// What to rank: tokens, (start pool, end pool) routes, or (start DEX, end DEX) pairs.
+        #[arg(long, default_value = "tokens", value_parser = PossibleValuesParser::new(LEADERBOARD_KIND_NAMES).map(|s| s.parse::<LeaderboardKind>().unwrap()))]
+        by: LeaderboardKind,
+        /// Number of rows to show for each profit token.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Check stored arbs for backruns that actually landed on-chain, and compare realized to simulated profit.
+    Realized {
+        /// Check arbs again even if they were checked before.
+        #[arg(long)]
+        recheck: bool,
+        /// Attribute realized backruns to searchers & builders, per route, instead of summarizing profits.
+        #[arg(long)]
+        attribute: bool,
+        /// TOML file labeling searchers, builders & other addresses, over the known & saved labels (see `labels`).
+        #[arg(long)]
+        labels: Option<std::path::PathBuf>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Show how much capital profitable backruns needed: percentiles & a histogram of optimal `amount_in`.
+    Sizing {
+        /// Group opportunities by profit token only (all), by traded token, or by route.
+        #[arg(long, default_value = "all", value_parser = PossibleValuesParser::new(SIZING_GROUP_NAMES).map(|s| s.parse::<SizingGroup>().unwrap()))]
+        by: SizingGroup,
+        /// Number of groups to show, most opportunities first.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Replay profitable arbs with a limited bankroll, showing the PnL that one searcher could have realized.
+    Pnl {
+        /// Starting capital, in whole units of the profit token (e.g. 10 => 10 WETH).
+        #[arg(long)]
+        bankroll: f64,
+        /// Number of blocks that a backrun's capital stays busy, counting the block it lands in.
+        #[arg(long, default_value_t = 1)]
+        lockup_blocks: u64,
+        /// Reinvest profits into the bankroll.
+        #[arg(long)]
+        compound: bool,
+        /// Token that the bankroll is held in. Defaults to the chain's wrapped native token.
+        #[arg(long)]
+        token: Option<Address>,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Replay profitable backruns against an inventory of the chain's quote assets, converting between them as needed.
+    Inventory {
+        /// Starting balances as SYMBOL=amount (e.g. WETH=10,USDC=20000). Quote assets not listed start empty.
+        #[arg(long, value_delimiter = ',')]
+        balances: Vec<String>,
+        /// Quote asset to convert each backrun's profit into. Without it, each profit stays in its quote asset.
+        #[arg(long)]
+        settle_into: Option<String>,
+        /// Quote asset that costs & profits are valued in. Defaults to the chain's first quote asset.
+        #[arg(long)]
+        value_in: Option<String>,
+        /// Cost of each conversion between assets (fees & slippage), in basis points of the amount converted.
+        #[arg(long, default_value_t = 30.0)]
+        conversion_cost_bps: f64,
+        /// CSV of `timestamp,BASE/QUOTE,price` rows to convert with.
+        /// Without it, 1-minute prices are fetched from Binance and cached in `./arbData/`.
+        #[arg(long)]
+        prices: Option<std::path::PathBuf>,
+        /// Ignore prices older than this many seconds at a backrun's timestamp.
+        #[arg(long, default_value_t = 120)]
+        max_price_age: u64,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Split a fixed budget between the opportunities of each block, showing the profit that it could have earned per block.
+    Allocate {
+        /// Capital available in each block, in whole units of the profit token (e.g. 10 => 10 WETH).
+        #[arg(long)]
+        budget: f64,
+        /// Number of equal units that the budget is split into (at most 100000); amounts in are rounded up to whole units.
+        #[arg(long, default_value_t = 1000)]
+        resolution: u64,
+        /// Token that the budget is held in. Defaults to the chain's wrapped native token.
+        #[arg(long)]
+        token: Option<Address>,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Write stats, leaderboards, and charts of profit over time to a single HTML or markdown file in `./arbData/`.
+    Report {
+        /// File format.
+        #[arg(long, default_value = "html", value_parser = PossibleValuesParser::new(REPORT_FORMAT_NAMES).map(|s| s.parse::<ReportFormat>().unwrap()))]
+        format: ReportFormat,
+        /// File to save the report to. (Default="report_{unix-timestamp}.{html|md}")
+        #[arg(short, long)]
+        filename: Option<String>,
+        /// Size of each time bucket in the charts.
+        #[arg(long, default_value = "daily", value_parser = PossibleValuesParser::new(BUCKET_SIZE_NAMES).map(|s| s.parse::<BucketSize>().unwrap()))]
+        bucket: BucketSize,
+        /// Number of rows in each leaderboard.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Compare how often events yielded profitable backruns, by which hint fields they shared.
+    ///
+    /// Events without arbs are only counted if they were saved with `scan --save-events`.
+    Hints {
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Find routes whose profitable dislocations recur across the scan, and save them as hot routes.
+    HotRoutes {
+        /// Minimum number of distinct blocks that a route must be profitable in.
+        #[arg(long, default_value_t = 5)]
+        min_blocks: u64,
+        /// Number of routes to keep for each profit token, most frequent first.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Post a summary to the webhook at `NOTIFY_URL` (and chat bots).
+        #[arg(long)]
+        notify: bool,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Correlate bucketed profit with the volatility of the ETH price, read from a Chainlink feed at each arb's block.
+    Volatility {
+        /// Size of each time bucket.
+        #[arg(long, default_value = "hourly", value_parser = PossibleValuesParser::new(BUCKET_SIZE_NAMES).map(|s| s.parse::<BucketSize>().unwrap()))]
+        bucket: BucketSize,
+        /// Chainlink ETH/USD feed to read. Defaults to the chain's known feed.
+        #[arg(long)]
+        feed: Option<Address>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Compare the DEX price left by each arb's user trade with the CEX price at its block, separating atomic arbs from
+    /// opportunities that only a CEX-hedged trade could capture.
+    CexDex {
+        /// CSV of `timestamp,BASE/QUOTE,price` rows (e.g. `1690000000,ETH/USDT,1890.5`) to compare against.
+        /// Without it, 1-minute prices are fetched from Binance and cached in `./arbData/`.
+        #[arg(long)]
+        prices: Option<std::path::PathBuf>,
+        /// Spread (in basis points of the CEX price) from which the DEX price counts as dislocated.
+        #[arg(long, default_value_t = 10.0)]
+        min_spread_bps: f64,
+        /// Ignore CEX prices older than this many seconds at a block's timestamp.
+        #[arg(long, default_value_t = 120)]
+        max_price_age: u64,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Compare the DEX prices of pairs traded on more than one configured chain, showing where they stayed apart.
+    ///
+    /// Arbs are selected by timestamp, since block numbers differ between chains.
+    CrossChain {
+        /// Chains to compare, as configured in `CHAINS`. Defaults to every configured chain.
+        #[arg(long, value_delimiter = ',')]
+        chains: Option<Vec<String>>,
+        /// Seconds per window that the chains' prices are aligned in.
+        #[arg(long, default_value_t = 60)]
+        window: u64,
+        /// Spread (in basis points) from which two chains' prices count as diverged.
+        #[arg(long, default_value_t = 20.0)]
+        min_spread_bps: f64,
+        /// Seconds that a divergence must last to be reported.
+        #[arg(long, default_value_t = 300)]
+        min_duration: u64,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// List the opportunities that profitable arbs cluster into: events in the same block whose best backruns trade the same pools in the same direction.
+    Opportunities {
+        /// Only list opportunities that more than one event exposed.
+        #[arg(long)]
+        duplicates_only: bool,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Re-simulate a sample of profitable arbs with the backrun delayed by 1..N blocks, showing how fast profit decays.
+    Latency {
+        /// Maximum number of blocks to delay backruns by.
+        #[arg(long, default_value_t = 3)]
+        max_delay: u64,
+        /// Number of profitable arbs to re-simulate, spread evenly over the queried range.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        sample: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Re-simulate a sample of arbs' txs with less & less of their hints (every log, as hinted, logs stripped, selector only),
+    /// showing how many arbs a searcher would still find at each level.
+    HintCounterfactual {
+        /// Number of arbs to re-simulate, spread evenly over the queried range. 0 re-simulates all of them.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        sample: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Replay every tx of a block range in order and backrun each price-moving tx, whether or not it was hinted on MEV-Share,
+    /// showing the block range's intra-block MEV and how much of it hint-driven runs found.
+    /// Arbs are saved to the queried DB with the run label (default "sweep").
+    Sweep {
+        /// Number of a block's price-moving txs to backrun at once.
+        #[arg(long, default_value_t = 4)]
+        batch_size: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Re-simulate a sample of profitable opportunities at the top of their blocks, showing how much MEV was positional vs flow-driven.
+    Position {
+        /// Number of profitable opportunities to re-simulate, spread evenly over the queried range. 0 re-simulates all of them.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        sample: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Re-simulate a sample of stored results with the current simulation engine, showing how their profits drifted.
+    Drift {
+        /// Engine version of the results to re-simulate. Defaults to every version but the current one.
+        #[arg(long)]
+        baseline: Option<String>,
+        /// Number of results to re-simulate, spread evenly over the queried range. 0 re-simulates all of them.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        sample: usize,
+        /// Relative profit changes up to this are reported as unchanged (0.001 = 0.1%).
+        #[arg(long, default_value_t = 0.001)]
+        tolerance: f64,
+        /// Exit with an error if the total profit of any profit token drifts by more than this (0.05 = 5%).
+        #[arg(long)]
+        max_drift: Option<f64>,
+        /// Number of the largest drifts to list for each profit token.
+        #[arg(long, default_value_t = 10)]
+        examples: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Re-evaluate stored arbs under a sweep of gas prices, showing how many stay profitable.
+    GasSweep {
+        /// Base fees to evaluate, in gwei. Defaults to 5,10,20,30,50,100,200.
+        #[arg(long, value_delimiter = ',')]
+        base_fees: Option<Vec<f64>>,
+        /// Priority fees to evaluate on top of each base fee, in gwei.
+        #[arg(long, value_delimiter = ',', default_value = "0")]
+        tips: Vec<f64>,
+        /// Gas used per backrun, for arbs saved before gas was recorded. Defaults to 300000.
+        #[arg(long)]
+        backrun_gas: Option<u64>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Break down the simulations that failed during scans by cause (reverts, missing pools, RPC errors, ...).
+    Failures {
+        /// Number of example txs to list for each cause.
+        #[arg(long, default_value_t = 3)]
+        examples: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Compare the arbitrages of an external MEV dataset (mev-inspect-py or a Dune export) to the simulated arbs,
+    /// over the blocks both cover: which arbs both found, which only one did, and how their profits compare.
+    Reconcile {
+        /// CSV export of `--source` to import first; imported arbs are kept for later runs.
+        #[arg(long)]
+        import: Option<std::path::PathBuf>,
+        /// Dataset that arbs are imported from & compared.
+        #[arg(long, default_value = "mev-inspect", value_parser = PossibleValuesParser::new(DATASET_SOURCE_NAMES).map(|s| s.parse::<DatasetSource>().unwrap()))]
+        source: DatasetSource,
+        /// Number of example txs to list of each disagreement.
+        #[arg(long, default_value_t = 3)]
+        examples: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+        #[command(flatten)]
+        query: ArbQuery,
+    },
+    /// Summarize the hypothetical PnL of a paper-trading session (see `scan --paper`).
+    Paper {
+        /// Name of the session.
+        session: String,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+    },
+    /// Show where an audit session's events (see `scan --audit`) dropped out of the sim pipeline, stage by stage.
+    Audit {
+        /// Name of the session.
+        session: String,
+        /// Number of example txs to list for each reason.
+        #[arg(long, default_value_t = 3)]
+        examples: usize,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+    },
+    /// Simulate backruns of tx hashes or JSON events read from stdin (one per line), writing results to stdout as JSON lines.
+    Pipe {
+        /// Name of the chain to simulate on, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        /// Number of lines simulated at once. Results are written in input order either way.
+        #[arg(short = 'n', long, default_value_t = 4)]
+        concurrency: usize,
+    },
+    /// Explore interactively: simulate txs, price pools at past blocks, and rank stored results, one command per line.
+    Repl {
+        /// Name of the chain to explore, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        #[arg(
+            long = "db",
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine that `top` reads results from, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
+        )]
+        db_engine: Option<String>,
+        /// Format that results are printed in; `format <table|json>` changes it during the session.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+    },
+    /// Check the node & config by simulating a bundled mainnet tx with a known backrun, failing unless it's found.
+    /// Pass `--replay` to run it offline from a cassette recorded with `--record`.
+    Selftest {
+        /// Name of the chain whose node is tested, as configured in `CHAINS`; it must be mainnet. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        /// Fork the node with a local Anvil node pinned at the tx's block, and simulate against that. Requires `anvil` on the PATH.
+        #[arg(long)]
+        anvil: bool,
+    },
+    /// Index every pool that the chain's DEX factories deployed (see `DEX_FACTORIES_<CHAIN>`) into the DB,
+    /// resuming from the pools already saved. `scan` reads them back instead of fetching each pool's tokens & factory.
+    Indexer {
+        /// Name of the chain to index, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        #[arg(
+            long = "db",
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine to save pools to, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
         )]
-        write_db: Option<DbEngine>,
+        db_engine: Option<String>,
+        /// Keep indexing new blocks after catching up to the head.
+        #[arg(long)]
+        follow: bool,
+        /// Seconds between rounds of indexing new blocks, with --follow.
+        #[arg(long, default_value_t = 12)]
+        interval: u64,
+        /// Blocks of a factory's logs requested at once; lower it if the node limits log ranges.
+        #[arg(long, default_value_t = hindsight::indexer::INDEX_CHUNK_BLOCKS)]
+        chunk_blocks: u64,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
     },
+    /// List the labeled addresses of a chain (routers, searchers, builders, stablecoins & tokens): those that ship
+    /// with hindsight and those saved in the DB. Scans skip known searchers' txs, and reports name addresses by their labels.
+    Labels {
+        /// Name of the chain to list labels of, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        #[arg(
+            long = "db",
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine that labels are saved in, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
+        )]
+        db_engine: Option<String>,
+        /// TOML file of labels (see `realized --labels`) to save to the DB first, replacing saved labels of the same addresses.
+        #[arg(long)]
+        import: Option<std::path::PathBuf>,
+        /// Only list labels of this kind.
+        #[arg(long, value_parser = PossibleValuesParser::new(LABEL_KIND_NAMES).map(|s| s.parse::<LabelKind>().unwrap()))]
+        kind: Option<LabelKind>,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+    },
+    /// Measure tokens' transfer taxes & transaction limits by buying & selling them on a fork, and save them
+    /// so scans screen those tokens by them.
+    TokenTax {
+        /// Tokens to measure.
+        #[arg(required = true)]
+        tokens: Vec<Address>,
+        /// Name of the chain the tokens are on, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        /// Measure the tokens on the state at the end of this block. Defaults to the latest block.
+        #[arg(long)]
+        block: Option<u64>,
+        /// Measure tokens again even if they were measured before.
+        #[arg(long)]
+        refresh: bool,
+        /// Output format.
+        #[arg(long, default_value = "table", value_parser = PossibleValuesParser::new(OUTPUT_FORMAT_NAMES).map(|s| s.parse::<OutputFormat>().unwrap()))]
+        format: OutputFormat,
+    },
+    /// Deploy the executor contract (`contracts/Executor.sol`) from the auth signer, and store its address in `.env`.
+    DeployExecutor {
+        /// Name of the chain to deploy on, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        /// Compiled contract artifact to deploy; build it with `forge build --root contracts`.
+        #[arg(long, default_value = hindsight::executor::DEFAULT_ARTIFACT)]
+        artifact: std::path::PathBuf,
+        /// Env file that the address is stored in.
+        #[arg(long, default_value = ".env")]
+        env_file: std::path::PathBuf,
+        /// Only print the address; don't store it.
+        #[arg(long)]
+        no_save: bool,
+    },
+    /// Serve on-demand backrun simulations over gRPC (see `proto/hindsight.proto`).
+    #[cfg(feature = "grpc")]
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        addr: std::net::SocketAddr,
+        /// Name of the chain to simulate on, as configured in `CHAINS`. Defaults to the first configured chain.
+        #[arg(long)]
+        chain: Option<String>,
+        #[arg(
+            long = "db",
+            value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+            help = &format!("<{}>: DB engine to save results to; results aren't saved if omitted", DB_ENGINE_NAMES.join(" | "))
+        )]
+        db_engine: Option<String>,
+    },
+}
+
+impl Commands {
+    /// Sets the output format of commands that print results.
+    fn set_format(&mut self, output: OutputFormat) {
+        match self {
+            Commands::Stats { format, .. }
+            | Commands::Leaderboard { format, .. }
+            | Commands::Realized { format, .. }
+            | Commands::Sizing { format, .. }
+            | Commands::Pnl { format, .. }
+            | Commands::Allocate { format, .. }
+            | Commands::Inventory { format, .. }
+            | Commands::Hints { format, .. }
+            | Commands::HotRoutes { format, .. }
+            | Commands::Volatility { format, .. }
+            | Commands::CexDex { format, .. }
+            | Commands::CrossChain { format, .. }
+            | Commands::Opportunities { format, .. }
+            | Commands::Latency { format, .. }
+            | Commands::HintCounterfactual { format, .. }
+            | Commands::Sweep { format, .. }
+            | Commands::Position { format, .. }
+            | Commands::Drift { format, .. }
+            | Commands::GasSweep { format, .. }
+            | Commands::Failures { format, .. }
+            | Commands::Reconcile { format, .. }
+            | Commands::Paper { format, .. }
+            | Commands::Audit { format, .. }
+            | Commands::Indexer { format, .. }
+            | Commands::Labels { format, .. }
+            | Commands::TokenTax { format, .. }
+            | Commands::Repl { format, .. } => *format = output,
+            // these print a summary of what they did, which main formats with `Cli::output`
+            _ => {}
+        }
+    }
+}
+
+/// Selects the stored arbs that a report is made from.
+#[derive(Args)]
+pub struct ArbQuery {
+    /// Only include arbs starting from this timestamp.
+    #[arg(short, long)]
+    pub timestamp_start: Option<u32>,
+    /// Only include arbs up to this timestamp.
+    #[arg(long)]
+    pub timestamp_end: Option<u32>,
+    /// Only include arbs starting from this block.
+    #[arg(short, long)]
+    pub block_start: Option<u32>,
+    /// Only include arbs up to this block.
+    #[arg(long)]
+    pub block_end: Option<u32>,
+    #[arg(
+        long = "db",
+        value_parser = PossibleValuesParser::new(DB_ENGINE_NAMES),
+        help = &format!("<{}>: DB engine to read arb data from, defaults to mongo", DB_ENGINE_NAMES.join(" | "))
+    )]
+    pub read_db: Option<String>,
+    /// Name of the chain to report on, as configured in `CHAINS`. Defaults to the first configured chain.
+    #[arg(long)]
+    pub chain: Option<String>,
+    /// Only include arbs from the scan run with this label.
+    #[arg(long)]
+    pub run_label: Option<String>,
 }
@@ -0,0 +1,41 @@
+use crate::analysis::{
+    allocation::{allocate, render_table, AllocationOptions},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    EXPORT_DIR,
+};
+use crate::{info, Result};
+use std::path::PathBuf;
+
+/// Splits a per-block budget between the opportunities of each block, saves each run's portfolios to `EXPORT_DIR`,
+/// and prints them.
+///
+/// Portfolios are saved as `allocation_{chain_id}_{run_label}.json`, replacing the run's previous portfolios.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    chain_id: u64,
+    options: AllocationOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let runs = allocate(&arbs, &options);
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    for run in &runs {
+        let path = PathBuf::from(EXPORT_DIR).join(format!(
+            "allocation_{}_{}.json",
+            chain_id,
+            run.run_label.as_deref().unwrap_or("unlabelled")
+        ));
+        std::fs::write(&path, serde_json::to_string_pretty(run)?)?;
+        info!("saved block portfolios to {:?}", path);
+    }
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&runs)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&runs)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::audit::{audit_path, funnel, load_records, render_table};
+use crate::commands::OutputFormat;
+use crate::Result;
+
+/// Prints the funnel of the audit `session`'s records so far.
+pub fn run(session: &str, num_examples: usize, format: OutputFormat) -> Result<()> {
+    let path = audit_path(session);
+    if !path.exists() {
+        return Err(anyhow::format_err!(
+            "no audit session {:?} (expected {:?}); start one with `scan --audit {}`",
+            session,
+            path,
+            session
+        ));
+    }
+    let funnel = funnel(&load_records(&path)?, num_examples);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&funnel)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&funnel)?),
+    }
+    Ok(())
+}
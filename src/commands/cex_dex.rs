@@ -0,0 +1,69 @@
+use crate::analysis::{
+    cex_dex::{dislocation_report, exchange_symbols, render_table},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    cex::{CexPriceSource, CexPriceStore},
+    tokens::TokenMetadataStore,
+};
+use crate::util::WsClient;
+use crate::Result;
+
+#[derive(Clone, Debug)]
+pub struct CexDexOptions {
+    pub source: CexPriceSource,
+    /// Spread (in basis points of the CEX price) from which the DEX price counts as dislocated.
+    pub min_spread_bps: f64,
+    /// CEX prices older than this (in seconds) at a block's timestamp aren't used.
+    pub max_age: u64,
+}
+
+/// Compares the DEX price left by the user's trade of every arb matching `params` with the CEX price at its block,
+/// fetching token metadata (and exchange prices, for `CexPriceSource::Binance`) as needed.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain_id: u64,
+    options: CexDexOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let mut store = TokenMetadataStore::load(chain_id)?;
+    let tokens = arbs
+        .iter()
+        .flat_map(|arb| &arb.results)
+        .flat_map(|result| {
+            [
+                result.user_trade.tokens.token,
+                result.user_trade.tokens.quote,
+            ]
+        })
+        .collect::<Vec<_>>();
+    store.fetch_missing(client, &tokens).await?;
+
+    let mut cex = CexPriceStore::load(&options.source)?;
+    let needed = arbs
+        .iter()
+        .filter_map(|arb| {
+            let (token, quote) = exchange_symbols(arb, |token| store.get(token))?;
+            Some((token, quote, arb.event.timestamp))
+        })
+        .collect::<Vec<_>>();
+    cex.fetch_missing(&needed).await?;
+
+    let report = dislocation_report(
+        &arbs,
+        |token| store.get(token),
+        &cex,
+        options.min_spread_bps,
+        options.max_age,
+    );
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&report)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::analysis::{
+    counterfactual::{render_table, simulate_levels, summarize},
+    latency::sample_evenly,
+    read_all_arbs,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::hindsight::Hindsight;
+use crate::{info, log_error, Result};
+use futures::future::join_all;
+
+/// Number of txs re-simulated at once. Each one is simulated once per hint level.
+const SIM_BATCH_SIZE: usize = 4;
+
+/// Re-simulates a sample of `sample_size` stored arbs' txs with their hints reduced to each `HintLevel`,
+/// and prints how many arbs (and how much profit) each level still finds.
+pub async fn run(
+    params: ArbFilterParams,
+    db: &ArbDatabase,
+    hindsight: &Hindsight,
+    sample_size: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_all_arbs(db, &params).await?;
+    let sampled = sample_evenly(arbs.iter().collect(), sample_size);
+    info!("re-simulating {} txs at each hint level", sampled.len());
+    let mut outcomes = vec![];
+    for batch in sampled.chunks(SIM_BATCH_SIZE) {
+        let results = join_all(batch.iter().map(|arb| simulate_levels(hindsight, arb))).await;
+        for (arb, result) in batch.iter().zip(results) {
+            match result {
+                Ok(Some(levels)) => outcomes.push(levels),
+                Ok(None) => {}
+                Err(err) => log_error!(
+                    "failed to re-simulate {:?} with reduced hints: {}",
+                    arb.event.hint.hash,
+                    err
+                ),
+            }
+        }
+    }
+
+    let summaries = summarize(&outcomes);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&summaries)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summaries)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::analysis::{
+    cross_chain::{chain_prices, divergence_report, render_table},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::config::ChainConfig;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    tokens::TokenMetadataStore,
+};
+use crate::util::WsClient;
+use crate::Result;
+
+#[derive(Clone, Debug)]
+pub struct CrossChainOptions {
+    /// Seconds per window that the chains' prices are aligned in.
+    pub window: u64,
+    /// Spread (in basis points) from which two chains' prices count as diverged.
+    pub min_spread_bps: f64,
+    /// Seconds that a divergence must last to be reported.
+    pub min_duration: u64,
+}
+
+/// Where one chain's arbs are read from, and the client its token metadata is fetched with.
+pub struct ChainQuery {
+    pub chain: ChainConfig,
+    pub db: ArbDatabase,
+    pub params: ArbFilterParams,
+    pub client: WsClient,
+}
+
+/// Compares the DEX prices that the user trades of each chain's arbs left their pools at,
+/// reporting the pairs whose prices diverged between chains (see `divergence_report`).
+pub async fn run(
+    chains: &[ChainQuery],
+    options: CrossChainOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    if chains.len() < 2 {
+        return Err(anyhow::format_err!(
+            "cross-chain needs at least 2 chains, but {} were given",
+            chains.len()
+        ));
+    }
+    let mut prices = vec![];
+    for query in chains {
+        let arbs = read_opportunities(&query.db, &query.params).await?;
+        let mut store = TokenMetadataStore::load(query.chain.chain_id)?;
+        let tokens = arbs
+            .iter()
+            .flat_map(|arb| &arb.results)
+            .flat_map(|result| {
+                [
+                    result.user_trade.tokens.token,
+                    result.user_trade.tokens.quote,
+                ]
+            })
+            .collect::<Vec<_>>();
+        store.fetch_missing(&query.client, &tokens).await?;
+        prices.extend(chain_prices(&query.chain.name, &arbs, |token| {
+            store.get(token)
+        }));
+    }
+    let report = divergence_report(
+        &prices,
+        options.window,
+        options.min_spread_bps,
+        options.min_duration,
+    );
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&report)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
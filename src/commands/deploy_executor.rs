@@ -0,0 +1,56 @@
+use crate::{
+    config::{store_env_var, ChainConfig, Config},
+    executor::{deploy, load_artifact},
+    info,
+    signer::Signer,
+    util::WsClient,
+    Result,
+};
+use ethers::providers::Middleware;
+use std::path::Path;
+
+/// Deploys the executor contract compiled to `artifact` on `chain` from the auth signer, then stores its address in
+/// `env_file` (unless it's None) so later runs pick it up.
+pub async fn run(
+    client: &WsClient,
+    config: &Config,
+    chain: &ChainConfig,
+    artifact: &Path,
+    env_file: Option<&Path>,
+) -> Result<()> {
+    let signer_config = config.auth_signer.as_ref().ok_or(anyhow::format_err!(
+        "deploy-executor requires an auth signer; set AUTH_SIGNER_KEYSTORE, AUTH_SIGNER_LEDGER or AUTH_SIGNER_KEY"
+    ))?;
+    let bytecode = load_artifact(artifact)?;
+    let rpc_chain_id = client.get_chainid().await?.as_u64();
+    if rpc_chain_id != chain.chain_id {
+        return Err(anyhow::format_err!(
+            "chain '{}' is configured with chain id {}, but its node is on chain {}",
+            chain.name,
+            chain.chain_id,
+            rpc_chain_id
+        ));
+    }
+    let signer = Signer::load(signer_config, chain.chain_id).await?;
+    if let Some(executor) = chain.executor {
+        info!(
+            "replacing the executor {:?} configured for chain '{}'",
+            executor, chain.name
+        );
+    }
+    info!(
+        "deploying the executor on chain '{}' from {:?}",
+        chain.name,
+        signer.address()
+    );
+    let executor = deploy(client, &signer, bytecode).await?;
+    println!("deployed the executor to {:?}", executor);
+    match env_file {
+        Some(path) => {
+            store_env_var(path, &chain.executor_var(), &format!("{:?}", executor))?;
+            println!("stored it as {} in {:?}", chain.executor_var(), path);
+        }
+        None => println!("set {}={:?} to use it", chain.executor_var(), executor),
+    }
+    Ok(())
+}
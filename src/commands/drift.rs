@@ -0,0 +1,97 @@
+use crate::analysis::{
+    drift::{drift_reports, engine_version_of, render_table, resimulate},
+    latency::sample_evenly,
+    read_all_arbs,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::interfaces::QuoteAsset;
+use crate::sim::ENGINE_VERSION;
+use crate::util::WsClient;
+use crate::{info, log_error, Result};
+use futures::future::join_all;
+
+/// Number of results re-simulated at once. Each one runs a full backrun search.
+const SIM_BATCH_SIZE: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct DriftOptions {
+    /// Only re-simulate results of this engine version. Defaults to every version but the current one.
+    pub baseline: Option<String>,
+    /// Number of stored results to re-simulate, spread evenly over the queried range. 0 re-simulates all of them.
+    pub sample_size: usize,
+    /// Relative profit changes up to this (e.g. 0.001 = 0.1%) count as unchanged.
+    pub tolerance: f64,
+    /// Fail if any profit token's total profit drifts by more than this (e.g. 0.05 = 5%).
+    pub max_drift: Option<f64>,
+    /// Number of the largest drifts to list for each profit token.
+    pub num_examples: usize,
+}
+
+/// Re-simulates a sample of stored results with the current engine and prints how their profits drifted.
+pub async fn run(
+    params: ArbFilterParams,
+    db: &ArbDatabase,
+    client: &WsClient,
+    quote_assets: &[QuoteAsset],
+    options: DriftOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_all_arbs(db, &params).await?;
+    // results stored without their simulations (e.g. in postgres) have no route to compare
+    let candidates = arbs
+        .iter()
+        .filter(|arb| !arb.results.is_empty() || arb.max_profit.is_zero())
+        .filter(|arb| match &options.baseline {
+            Some(baseline) => engine_version_of(arb) == baseline,
+            None => engine_version_of(arb) != ENGINE_VERSION,
+        })
+        .collect::<Vec<_>>();
+    let sampled = sample_evenly(candidates, options.sample_size);
+    info!(
+        "re-simulating {} results with engine version {}",
+        sampled.len(),
+        ENGINE_VERSION
+    );
+    let mut resimulated = vec![];
+    for batch in sampled.chunks(SIM_BATCH_SIZE) {
+        let results = join_all(
+            batch
+                .iter()
+                .map(|arb| resimulate(client, arb, quote_assets)),
+        )
+        .await;
+        for (arb, result) in batch.iter().zip(results) {
+            match result {
+                Ok(Some(arb)) => resimulated.push(arb),
+                Ok(None) => {}
+                Err(err) => log_error!("failed to re-simulate {:?}: {}", arb.event.hint.hash, err),
+            }
+        }
+    }
+
+    let reports = drift_reports(
+        &resimulated,
+        ENGINE_VERSION,
+        options.tolerance,
+        options.num_examples,
+    );
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&reports)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+    }
+    if let Some(max_drift) = options.max_drift {
+        if let Some(report) = reports
+            .iter()
+            .find(|report| report.profit_change.abs() > max_drift)
+        {
+            return Err(anyhow::format_err!(
+                "profit in {:?} drifted by {:+.2}%, more than the allowed {:.2}%",
+                report.profit_token,
+                report.profit_change * 100.0,
+                max_drift * 100.0
+            ));
+        }
+    }
+    Ok(())
+}
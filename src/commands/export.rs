@@ -1,12 +1,24 @@
+use crate::commands::OutputFormat;
 use crate::data::arbs::{ArbDatabase, ArbFilterParams, WriteEngine};
-use crate::Result;
+use crate::{info, Result};
+use serde_json::json;
 
 pub async fn run(
     params: ArbFilterParams,
     read_db: &ArbDatabase,
     write_dest: WriteEngine,
+    format: OutputFormat,
 ) -> Result<()> {
-    println!("exporting arbs... {:?}", params);
-    read_db.export_arbs(write_dest, &params).await?;
+    info!("exporting arbs... {:?}", params);
+    // the exporter reads up to the number of arbs there were when it started
+    let num_arbs = read_db.get_num_arbs(&params).await?;
+    read_db.export_arbs(write_dest.to_owned(), &params).await?;
+    if format == OutputFormat::Json {
+        let (file, db) = match &write_dest {
+            WriteEngine::File(filename) => (json!(filename), json!(null)),
+            WriteEngine::Db(engine) => (json!(null), json!(engine.to_string())),
+        };
+        println!("{}", json!({ "numArbs": num_arbs, "file": file, "db": db }));
+    }
     Ok(())
 }
@@ -0,0 +1,33 @@
+use crate::analysis::{
+    failures::{render_table, triage},
+    read_all_failures,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::Result;
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    num_examples: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let failures = read_all_failures(read_db, &params).await?;
+    // failures are compared against every simulated tx, not just those over the min profit
+    let all_params = ArbFilterParams {
+        min_profit: None,
+        ..params.clone()
+    };
+    let num_simulated = read_db.get_num_arbs(&all_params).await?;
+    let profitable_params = ArbFilterParams {
+        min_profit: Some(1.into()),
+        ..params
+    };
+    let num_profitable = read_db.get_num_arbs(&profitable_params).await?;
+    let triage = triage(&failures, num_simulated, num_profitable, num_examples);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&triage)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&triage)?),
+    }
+    Ok(())
+}
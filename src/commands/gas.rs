@@ -0,0 +1,22 @@
+use crate::analysis::{
+    gas::{gas_sweep, render_table, GasSweepOptions},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::Result;
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    options: GasSweepOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let sweep = gas_sweep(&arbs, &options);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&sweep)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&sweep)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::analysis::{
+    hints::{hint_efficacy, render_table},
+    read_all_arbs, read_all_events,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::Result;
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    format: OutputFormat,
+) -> Result<()> {
+    let events = read_all_events(read_db, &params).await?;
+    let arbs = read_all_arbs(read_db, &params).await?;
+    let efficacy = hint_efficacy(&events, &arbs);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&efficacy)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&efficacy)?),
+    }
+    Ok(())
+}
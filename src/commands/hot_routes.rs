@@ -0,0 +1,71 @@
+use crate::analysis::{
+    hot_routes::{hot_routes, render_table, summary},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::config::ChainConfig;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    tokens::TokenMetadataStore,
+    EXPORT_DIR,
+};
+use crate::notify::{notify_all, Destination};
+use crate::util::WsClient;
+use crate::{info, Result};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug)]
+pub struct HotRouteOptions {
+    /// Minimum number of distinct blocks that a route must be profitable in.
+    pub min_blocks: u64,
+    /// Number of routes to keep for each profit token.
+    pub limit: usize,
+    /// Where to post a summary of the hot routes, if anywhere.
+    pub notify: Vec<Destination>,
+}
+
+/// Finds the routes that were profitable in at least `min_blocks` blocks, saves them to
+/// `EXPORT_DIR/hot_routes_{chain_id}.json`, and prints them.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain: &ChainConfig,
+    options: HotRouteOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let routes = hot_routes(&arbs, options.min_blocks, options.limit);
+
+    let mut store = TokenMetadataStore::load(chain.chain_id)?;
+    let tokens = routes
+        .iter()
+        .flat_map(|route| [route.token, route.profit_token])
+        .collect::<Vec<_>>();
+    let pools = routes
+        .iter()
+        .flat_map(|route| {
+            [
+                (route.start_pool, route.start_variant),
+                (route.end_pool, route.end_variant),
+            ]
+        })
+        .collect::<Vec<_>>();
+    store.fetch_missing(client, &tokens).await?;
+    store.fetch_missing_pools(client, &pools).await?;
+
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    let path = PathBuf::from(EXPORT_DIR).join(format!("hot_routes_{}.json", chain.chain_id));
+    std::fs::write(&path, serde_json::to_string_pretty(&routes)?)?;
+    info!("saved {} hot routes to {:?}", routes.len(), path);
+
+    if !options.notify.is_empty() {
+        notify_all(&options.notify, &summary(&routes, &store, &chain.name)).await?;
+        info!("sent hot routes summary");
+    }
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&routes, &store)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&routes)?),
+    }
+    Ok(())
+}
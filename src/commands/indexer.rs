@@ -0,0 +1,84 @@
+use crate::commands::OutputFormat;
+use crate::config::known_dex_name;
+use crate::indexer::PoolIndexer;
+use crate::interfaces::PoolVariant;
+use crate::{info, Result};
+use ethers::types::Address;
+use serde::Serialize;
+use std::time::Duration;
+
+/// What the indexer has saved of one factory's pools.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FactorySummary {
+    pub factory: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dex: Option<String>,
+    pub variant: PoolVariant,
+    /// Number of pools saved by this run.
+    pub num_indexed: usize,
+    /// Number of the factory's pools in the DB.
+    pub num_pools: usize,
+    /// Block of the newest saved pool.
+    pub latest_block: Option<u64>,
+}
+
+fn render_table(summaries: &[FactorySummary]) -> String {
+    let mut table = format!(
+        "{:<44} {:<10} {:<10} {:>9} {:>9} {:>12}\n",
+        "factory", "dex", "variant", "indexed", "pools", "latest block"
+    );
+    for row in summaries {
+        table.push_str(&format!(
+            "{:<44} {:<10} {:<10} {:>9} {:>9} {:>12}\n",
+            format!("{:?}", row.factory),
+            row.dex.as_deref().unwrap_or("-"),
+            format!("{:?}", row.variant),
+            row.num_indexed,
+            row.num_pools,
+            row.latest_block
+                .map(|block| block.to_string())
+                .unwrap_or("-".to_owned())
+        ));
+    }
+    table
+}
+
+/// Indexes the pools of `indexer`'s factories up to the head, resuming from those already saved,
+/// and prints what's saved of each factory. If `follow` is set, new blocks are then indexed every `follow`, forever.
+pub async fn run(
+    indexer: &PoolIndexer,
+    follow: Option<Duration>,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut next_blocks = indexer.resume_blocks().await?;
+    let num_indexed = indexer.index_to_head(&mut next_blocks).await?;
+    let saved = indexer.db.read_pools(indexer.chain_id).await?;
+    let summaries = indexer
+        .factories
+        .iter()
+        .map(|factory| {
+            let pools = saved
+                .iter()
+                .filter(|pool| pool.factory == factory.factory)
+                .collect::<Vec<_>>();
+            FactorySummary {
+                factory: factory.factory,
+                dex: known_dex_name(factory.factory).map(str::to_owned),
+                variant: factory.variant,
+                num_indexed: num_indexed.get(&factory.factory).copied().unwrap_or(0),
+                num_pools: pools.len(),
+                latest_block: pools.iter().map(|pool| pool.created_block).max(),
+            }
+        })
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&summaries)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summaries)?),
+    }
+    if let Some(interval) = follow {
+        info!("following new blocks every {:?}", interval);
+        indexer.follow(next_blocks, interval).await;
+    }
+    Ok(())
+}
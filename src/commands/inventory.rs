@@ -0,0 +1,139 @@
+use crate::analysis::{
+    inventory::{render_table, replay_inventory, InventoryAsset, InventoryOptions},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::config::ChainConfig;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    cex::{exchange_symbol, CexPriceSource, CexPriceStore},
+    tokens::TokenMetadataStore,
+};
+use crate::util::WsClient;
+use crate::Result;
+use ethers::types::Address;
+
+#[derive(Clone, Debug)]
+pub struct InventorySettings {
+    /// Starting balances, as `SYMBOL=amount` entries for the chain's quote assets (e.g. `WETH=10`).
+    pub balances: Vec<String>,
+    /// Quote asset (symbol or address) that profits are converted into, if any.
+    pub settle_into: Option<String>,
+    /// Quote asset (symbol or address) that the inventory is valued in. Defaults to the chain's first quote asset.
+    pub value_in: Option<String>,
+    /// Cost of each conversion, in basis points of the amount converted.
+    pub conversion_cost_bps: f64,
+    /// Where conversion prices come from.
+    pub source: CexPriceSource,
+    /// Prices older than this (in seconds) at a backrun's timestamp aren't used.
+    pub max_age: u64,
+    pub backrun_gas: u64,
+}
+
+/// Index of the asset in `assets` that `name` (a symbol or address) names.
+fn find_asset(assets: &[InventoryAsset], name: &str) -> Result<usize> {
+    let symbol = exchange_symbol(name);
+    assets
+        .iter()
+        .position(|asset| {
+            asset.symbol == symbol || name.parse::<Address>().ok() == Some(asset.address)
+        })
+        .ok_or(anyhow::format_err!(
+            "{} isn't one of the chain's quote assets ({})",
+            name,
+            assets
+                .iter()
+                .map(|asset| asset.symbol.to_owned())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+}
+
+/// Replays every profitable arb matching `params` against an inventory of `chain`'s quote assets,
+/// fetching token metadata (and exchange prices, for `CexPriceSource::Binance`) as needed.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain: &ChainConfig,
+    settings: InventorySettings,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut store = TokenMetadataStore::load(chain.chain_id)?;
+    let addresses = chain
+        .quote_assets
+        .iter()
+        .map(|asset| asset.address)
+        .collect::<Vec<_>>();
+    store.fetch_missing(client, &addresses).await?;
+    let mut assets = chain
+        .quote_assets
+        .iter()
+        .map(|asset| InventoryAsset {
+            address: asset.address,
+            symbol: exchange_symbol(&store.symbol(&asset.address)),
+            decimals: asset.decimals,
+            starting_balance: 0.0,
+        })
+        .collect::<Vec<_>>();
+    if assets.is_empty() {
+        return Err(anyhow::format_err!("{} has no quote assets", chain.name));
+    }
+    for entry in &settings.balances {
+        let (name, amount) = entry
+            .split_once('=')
+            .and_then(|(name, amount)| Some((name, amount.trim().parse::<f64>().ok()?)))
+            .filter(|(_, amount)| *amount >= 0.0)
+            .ok_or(anyhow::format_err!(
+                "invalid balance '{}' (expected SYMBOL=amount)",
+                entry
+            ))?;
+        let idx = find_asset(&assets, name.trim())?;
+        assets[idx].starting_balance = amount;
+    }
+    let settle_into = match &settings.settle_into {
+        Some(name) => Some(assets[find_asset(&assets, name)?].address),
+        None => None,
+    };
+    let value_idx = match &settings.value_in {
+        Some(name) => find_asset(&assets, name)?,
+        None => 0,
+    };
+    let value_symbol = assets[value_idx].symbol.to_owned();
+
+    let arbs = read_opportunities(read_db, &params).await?;
+    let mut cex = CexPriceStore::load(&settings.source)?;
+    let needed = arbs
+        .iter()
+        .flat_map(|arb| {
+            assets
+                .iter()
+                .filter(|asset| asset.symbol != value_symbol)
+                .map(|asset| {
+                    (
+                        asset.symbol.to_owned(),
+                        value_symbol.to_owned(),
+                        arb.event.timestamp,
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+    cex.fetch_missing(&needed).await?;
+
+    let options = InventoryOptions {
+        value_in: assets[value_idx].address,
+        assets,
+        settle_into,
+        conversion_cost_bps: settings.conversion_cost_bps,
+        backrun_gas: settings.backrun_gas,
+        native_token: chain.wrapped_native,
+    };
+    let runs = replay_inventory(&arbs, &options, |symbol, timestamp| {
+        cex.price(symbol, &value_symbol, timestamp, settings.max_age)
+    });
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&runs)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&runs)?),
+    }
+    Ok(())
+}
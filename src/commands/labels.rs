@@ -0,0 +1,48 @@
+use crate::commands::OutputFormat;
+use crate::data::arbs::ArbDatabase;
+use crate::interfaces::{AddressLabel, LabelKind};
+use crate::labels::{load_labels, Labels};
+use crate::{info, Result};
+use std::path::Path;
+
+fn render_table(labels: &[AddressLabel]) -> String {
+    let mut table = format!("{:<44} {:<16} {}\n", "address", "kind", "name");
+    for label in labels {
+        table.push_str(&format!(
+            "{:<44} {:<16} {}\n",
+            format!("{:?}", label.address),
+            label.kind.to_string(),
+            label.name
+        ));
+    }
+    table
+}
+
+/// Saves the labels in the TOML file at `import` (if given) to `db`, then prints the labels of `chain_id`
+/// (those that ship with hindsight, overridden by the saved ones), only those of `kind` if given.
+///
+/// Builder tags aren't addresses, so they're not saved; pass the file to `realized --labels` to use them.
+pub async fn run(
+    db: &ArbDatabase,
+    chain_id: u64,
+    import: Option<&Path>,
+    kind: Option<LabelKind>,
+    format: OutputFormat,
+) -> Result<()> {
+    if let Some(path) = import {
+        let imported = Labels::load(path)?.address_labels(chain_id);
+        db.write_labels(&imported).await?;
+        info!("saved {} labels from {:?}", imported.len(), path);
+    }
+    let labels = load_labels(chain_id, Some(db), None)
+        .await?
+        .address_labels(chain_id)
+        .into_iter()
+        .filter(|label| kind.is_none_or(|kind| label.kind == kind))
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&labels)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&labels)?),
+    }
+    Ok(())
+}
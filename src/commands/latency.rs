@@ -0,0 +1,63 @@
+use crate::analysis::{
+    latency::{decay_curves, render_table, sample, simulate_delays},
+    read_all_arbs,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::interfaces::QuoteAsset;
+use crate::sim::pools::PoolAdapters;
+use crate::util::WsClient;
+use crate::{info, log_error, Result};
+use futures::future::join_all;
+
+/// Number of arbs re-simulated at once. Each one forks the EVM for every delay & amount tried.
+const SIM_BATCH_SIZE: usize = 4;
+
+/// Re-simulates a sample of `sample_size` profitable arbs with their backruns delayed by
+/// 1..=`max_delay` blocks, and prints how much profit is left after each delay.
+pub async fn run(
+    params: ArbFilterParams,
+    db: &ArbDatabase,
+    client: &WsClient,
+    quote_assets: &[QuoteAsset],
+    max_delay: u64,
+    sample_size: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_all_arbs(db, &params).await?;
+    let sampled = sample(&arbs, sample_size);
+    info!(
+        "re-simulating {} arbs with up to {} blocks of delay",
+        sampled.len(),
+        max_delay
+    );
+    let mut delayed = vec![];
+    // the CLI only simulates the built-in AMMs
+    let adapters = PoolAdapters::default();
+    for batch in sampled.chunks(SIM_BATCH_SIZE) {
+        let results = join_all(
+            batch
+                .iter()
+                .map(|arb| simulate_delays(client, arb, quote_assets, max_delay, &adapters)),
+        )
+        .await;
+        for (arb, result) in batch.iter().zip(results) {
+            match result {
+                Ok(Some(profits)) => delayed.push(profits),
+                Ok(None) => {}
+                Err(err) => log_error!(
+                    "failed to re-simulate {:?} with delay: {}",
+                    arb.event.hint.hash,
+                    err
+                ),
+            }
+        }
+    }
+
+    let curves = decay_curves(&delayed);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&curves)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&curves)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::analysis::{
+    leaderboard::{
+        dex_leaderboard, join_route_metadata, join_symbols, render_dex_table, render_route_table,
+        render_table, route_leaderboard, token_leaderboard, DexRow, LeaderboardKind, RouteRow,
+        TokenRow,
+    },
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    tokens::TokenMetadataStore,
+};
+use crate::interfaces::SimArbResultBatch;
+use crate::util::WsClient;
+use crate::Result;
+
+/// Ranks tokens by profit, with symbols fetched into `store` as needed.
+pub async fn ranked_tokens(
+    arbs: &[SimArbResultBatch],
+    store: &mut TokenMetadataStore,
+    client: &WsClient,
+    limit: usize,
+) -> Result<Vec<TokenRow>> {
+    let mut rows = token_leaderboard(arbs, limit);
+    let tokens = rows
+        .iter()
+        .flat_map(|row| [row.token, row.profit_token])
+        .collect::<Vec<_>>();
+    store.fetch_missing(client, &tokens).await?;
+    join_symbols(&mut rows, store);
+    Ok(rows)
+}
+
+/// Ranks routes by profit, with symbols & pool fees fetched into `store` as needed.
+pub async fn ranked_routes(
+    arbs: &[SimArbResultBatch],
+    store: &mut TokenMetadataStore,
+    client: &WsClient,
+    limit: usize,
+) -> Result<Vec<RouteRow>> {
+    let mut rows = route_leaderboard(arbs, limit);
+    let tokens = rows
+        .iter()
+        .flat_map(|row| [row.token, row.profit_token])
+        .collect::<Vec<_>>();
+    let pools = rows
+        .iter()
+        .flat_map(|row| {
+            [
+                (row.start_pool, row.start_variant),
+                (row.end_pool, row.end_variant),
+            ]
+        })
+        .collect::<Vec<_>>();
+    store.fetch_missing(client, &tokens).await?;
+    store.fetch_missing_pools(client, &pools).await?;
+    join_route_metadata(&mut rows, store);
+    Ok(rows)
+}
+
+/// Ranks (start DEX, end DEX) pairs by profit, telling fee tiers apart by the fees of every route's pools.
+pub async fn ranked_dexes(
+    arbs: &[SimArbResultBatch],
+    store: &mut TokenMetadataStore,
+    client: &WsClient,
+    limit: usize,
+) -> Result<Vec<DexRow>> {
+    let routes = ranked_routes(arbs, store, client, usize::MAX).await?;
+    Ok(dex_leaderboard(&routes, limit))
+}
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain_id: u64,
+    kind: LeaderboardKind,
+    limit: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let mut store = TokenMetadataStore::load(chain_id)?;
+    match kind {
+        LeaderboardKind::Tokens => {
+            let rows = ranked_tokens(&arbs, &mut store, client, limit).await?;
+            match format {
+                OutputFormat::Table => print!("{}", render_table(&rows, &store)),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            }
+        }
+        LeaderboardKind::Routes => {
+            let rows = ranked_routes(&arbs, &mut store, client, limit).await?;
+            match format {
+                OutputFormat::Table => print!("{}", render_route_table(&rows, &store)),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            }
+        }
+        LeaderboardKind::Dexes => {
+            let rows = ranked_dexes(&arbs, &mut store, client, limit).await?;
+            match format {
+                OutputFormat::Table => print!("{}", render_dex_table(&rows, &store)),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,2 +1,65 @@
+pub mod allocate;
+pub mod audit;
+pub mod cex_dex;
+pub mod counterfactual;
+pub mod cross_chain;
+pub mod deploy_executor;
+pub mod drift;
 pub mod export;
+pub mod failures;
+pub mod gas;
+pub mod hints;
+pub mod hot_routes;
+pub mod indexer;
+pub mod inventory;
+pub mod labels;
+pub mod latency;
+pub mod leaderboard;
+pub mod opportunities;
+pub mod paper;
+pub mod pipe;
+pub mod pnl;
+pub mod position;
+pub mod realized;
+pub mod reconcile;
+pub mod repl;
+pub mod report;
 pub mod scan;
+pub mod selftest;
+pub mod sizing;
+pub mod stats;
+pub mod sweep;
+pub mod token_tax;
+pub mod volatility;
+
+use std::{fmt, str::FromStr};
+
+pub const OUTPUT_FORMAT_NAMES: [&'static str; 2] = ["table", "json"];
+
+/// How reports are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("invalid output format: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::analysis::{
+    clusters::{cluster_opportunities, render_table},
+    read_all_arbs,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::{info, Result};
+
+/// Prints the opportunities that the profitable arbs matching `params` cluster into.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    duplicates_only: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_all_arbs(read_db, &params).await?;
+    let mut opportunities = cluster_opportunities(&arbs);
+    let num_events = opportunities
+        .iter()
+        .map(|opp| opp.tx_hashes.len())
+        .sum::<usize>();
+    info!(
+        "{} profitable events cluster into {} opportunities",
+        num_events,
+        opportunities.len()
+    );
+    if duplicates_only {
+        opportunities.retain(|opp| opp.tx_hashes.len() > 1);
+    }
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&opportunities)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&opportunities)?),
+    }
+    Ok(())
+}
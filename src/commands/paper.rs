@@ -0,0 +1,20 @@
+use crate::commands::OutputFormat;
+use crate::paper::{ledger_path, render_table, summarize, PaperLedger};
+use crate::Result;
+
+/// Prints the hypothetical PnL of the paper-trading `session` so far.
+pub async fn run(session: &str, format: OutputFormat) -> Result<()> {
+    let path = ledger_path(session);
+    let ledger = PaperLedger::load(&path)?.ok_or(anyhow::format_err!(
+        "no paper session {:?} (expected {:?}); start one with `scan --paper {}`",
+        session,
+        path,
+        session
+    ))?;
+    let summary = summarize(&ledger);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&summary)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+    }
+    Ok(())
+}
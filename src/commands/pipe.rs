@@ -0,0 +1,169 @@
+use crate::{info, interfaces::SimArbResultBatch, service::SimService, Result};
+use ethers::types::H256;
+use futures::{stream, StreamExt};
+use mev_share_sse::EventHistory;
+use serde::Serialize;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+/// A line of the pipeline's input.
+#[derive(Clone, Debug)]
+pub enum PipeInput {
+    /// Hash of a landed tx, whose event is made up from its receipt's logs.
+    TxHash(H256),
+    /// A MEV-Share event as JSON, e.g. from the events API or `export`ed results.
+    Event(EventHistory),
+}
+
+impl PipeInput {
+    /// Parses a JSON event (if the line starts with `{`) or a tx hash.
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            let event = serde_json::from_str(line)
+                .map_err(|err| anyhow::format_err!("invalid event: {}", err))?;
+            return Ok(PipeInput::Event(event));
+        }
+        H256::from_str(line)
+            .map(PipeInput::TxHash)
+            .map_err(|err| anyhow::format_err!("invalid tx hash {:?}: {}", line, err))
+    }
+
+    pub fn tx_hash(&self) -> H256 {
+        match self {
+            PipeInput::TxHash(tx_hash) => *tx_hash,
+            PipeInput::Event(event) => event.hint.hash,
+        }
+    }
+}
+
+/// Output line of an input line that couldn't be simulated.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipeError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<H256>,
+    /// The line itself, if it couldn't be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    pub error: String,
+}
+
+/// Simulates backruns of the tx hashes or JSON events read from `input` (one per line),
+/// writing a JSON line to `output` for each of them, in input order.
+///
+/// Up to `concurrency` lines are simulated at once. Lines that fail are written as a `PipeError`,
+/// so every non-empty input line gets exactly one output line. Stops at the end of `input`,
+/// or when `output` is closed (e.g. by `head`).
+pub async fn run<R, W>(
+    service: &SimService,
+    input: R,
+    mut output: W,
+    concurrency: usize,
+) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let lines = stream::unfold(input.lines(), |mut lines| async move {
+        match lines.next_line().await {
+            Ok(Some(line)) => Some((Ok(line), lines)),
+            Ok(None) => None,
+            Err(err) => Some((Err(err), lines)),
+        }
+    });
+    let mut results = Box::pin(
+        lines
+            .filter(|line| {
+                let blank = matches!(line, Ok(line) if line.trim().is_empty());
+                async move { !blank }
+            })
+            .map(|line| async move {
+                match line {
+                    Ok(line) => Ok(simulate_line(service, &line).await),
+                    Err(err) => Err(err),
+                }
+            })
+            .buffered(concurrency.max(1)),
+    );
+    let (mut num_lines, mut num_failed) = (0, 0);
+    while let Some(res) = results.next().await {
+        num_lines += 1;
+        let line = match res? {
+            Ok(arb) => serde_json::to_string(&arb)?,
+            Err(err) => {
+                num_failed += 1;
+                serde_json::to_string(&err)?
+            }
+        };
+        let written = match output.write_all(format!("{}\n", line).as_bytes()).await {
+            Ok(()) => output.flush().await,
+            Err(err) => Err(err),
+        };
+        match written {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    info!("simulated {} txs ({} failed)", num_lines, num_failed);
+    Ok(())
+}
+
+/// Simulates `line`, returning its result or why it failed.
+async fn simulate_line(
+    service: &SimService,
+    line: &str,
+) -> std::result::Result<SimArbResultBatch, PipeError> {
+    let input = PipeInput::parse(line).map_err(|err| PipeError {
+        tx_hash: None,
+        input: Some(line.trim().to_owned()),
+        error: err.to_string(),
+    })?;
+    let tx_hash = input.tx_hash();
+    let res = match input {
+        PipeInput::TxHash(tx_hash) => service.simulate_backrun(tx_hash).await,
+        PipeInput::Event(event) => service.simulate_event_backrun(event).await,
+    };
+    res.map_err(|err| PipeError {
+        tx_hash: Some(tx_hash),
+        input: None,
+        error: format!("{:#}", err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_pipeline_input() -> Result<()> {
+        let tx_hash = H256::from_low_u64_be(0xabc);
+        let input = PipeInput::parse(&format!("  {:?}\n", tx_hash))?;
+        assert!(matches!(input, PipeInput::TxHash(hash) if hash == tx_hash));
+
+        let mut event = SimArbResultBatch::test_example().event;
+        event.hint.hash = tx_hash;
+        let input = PipeInput::parse(&serde_json::to_string(&event)?)?;
+        assert!(matches!(input, PipeInput::Event(_)));
+        assert_eq!(input.tx_hash(), tx_hash);
+
+        assert!(PipeInput::parse("0xnope").is_err());
+        assert!(PipeInput::parse("{\"block\": 1}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_writes_errors_as_json() -> Result<()> {
+        let error = PipeError {
+            tx_hash: Some(H256::from_low_u64_be(0xabc)),
+            input: None,
+            error: "tx not found".to_owned(),
+        };
+        let json = serde_json::to_value(&error)?;
+        assert_eq!(json["error"], "tx not found");
+        assert!(json["txHash"].is_string());
+        assert!(json.get("input").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use crate::analysis::{
+    pnl::{pnl_curves, render_table, PnlOptions},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    EXPORT_DIR,
+};
+use crate::{info, Result};
+use std::path::PathBuf;
+
+/// Computes the realizable PnL curve of each run, saves each curve to `EXPORT_DIR`, and prints them.
+///
+/// Curves are saved as `pnl_{chain_id}_{run_label}.json`, replacing the run's previous curve.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    chain_id: u64,
+    options: PnlOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let curves = pnl_curves(&arbs, &options);
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    for curve in &curves {
+        let path = PathBuf::from(EXPORT_DIR).join(format!(
+            "pnl_{}_{}.json",
+            chain_id,
+            curve.run_label.as_deref().unwrap_or("unlabelled")
+        ));
+        std::fs::write(&path, serde_json::to_string_pretty(curve)?)?;
+        info!("saved pnl curve to {:?}", path);
+    }
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&curves)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&curves)?),
+    }
+    Ok(())
+}
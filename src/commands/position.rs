@@ -0,0 +1,62 @@
+use crate::analysis::{
+    latency::sample,
+    position::{position_splits, render_table, simulate_top_of_block},
+    read_opportunities,
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::interfaces::QuoteAsset;
+use crate::sim::pools::PoolAdapters;
+use crate::util::WsClient;
+use crate::{info, log_error, Result};
+use futures::future::join_all;
+
+/// Number of opportunities re-simulated at once. Each one forks the EVM for every direction & amount tried.
+const SIM_BATCH_SIZE: usize = 4;
+
+/// Re-simulates a sample of `sample_size` profitable opportunities at the top of their blocks,
+/// and prints how much of their profit was positional (already there) vs flow-driven (made by the user's tx).
+pub async fn run(
+    params: ArbFilterParams,
+    db: &ArbDatabase,
+    client: &WsClient,
+    quote_assets: &[QuoteAsset],
+    sample_size: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(db, &params).await?;
+    let sampled = sample(&arbs, sample_size);
+    info!(
+        "re-simulating {} opportunities at the top of their blocks",
+        sampled.len()
+    );
+    let mut positioned = vec![];
+    // the CLI only simulates the built-in AMMs
+    let adapters = PoolAdapters::default();
+    for batch in sampled.chunks(SIM_BATCH_SIZE) {
+        let results = join_all(
+            batch
+                .iter()
+                .map(|arb| simulate_top_of_block(client, arb, quote_assets, &adapters)),
+        )
+        .await;
+        for (arb, result) in batch.iter().zip(results) {
+            match result {
+                Ok(Some(profit)) => positioned.push(profit),
+                Ok(None) => {}
+                Err(err) => log_error!(
+                    "failed to re-simulate {:?} at top of block: {}",
+                    arb.event.hint.hash,
+                    err
+                ),
+            }
+        }
+    }
+
+    let splits = position_splits(&positioned);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&splits)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&splits)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::analysis::{
+    read_all_arbs,
+    realized::{
+        attribution, check_realized, regret_summary, render_attribution_table, render_table,
+    },
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::labels::Labels;
+use crate::util::WsClient;
+use crate::{info, log_error, Result};
+use futures::future::join_all;
+
+/// Number of arbs checked at once.
+const CHECK_BATCH_SIZE: usize = 8;
+
+/// Checks profitable arbs for realized backruns, saves what was found, and prints a summary.
+///
+/// Arbs that were already checked are skipped unless `recheck` is set.
+/// If `attribute` is set, realized backruns are attributed to searchers & builders (named by `labels`) instead.
+pub async fn run(
+    params: ArbFilterParams,
+    db: &ArbDatabase,
+    client: &WsClient,
+    recheck: bool,
+    attribute: bool,
+    labels: &Labels,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut arbs = read_all_arbs(db, &params).await?;
+    let mut unchecked = arbs
+        .iter_mut()
+        .filter(|arb| !arb.max_profit.is_zero() && (recheck || arb.realized.is_none()))
+        .collect::<Vec<_>>();
+    info!("checking {} arbs for realized backruns", unchecked.len());
+    for batch in unchecked.chunks_mut(CHECK_BATCH_SIZE) {
+        let results = join_all(batch.iter().map(|arb| check_realized(client, arb))).await;
+        let mut checked = vec![];
+        for (arb, result) in batch.iter_mut().zip(results) {
+            match result {
+                Ok(Some(realized)) => {
+                    arb.realized = Some(realized);
+                    checked.push(arb.to_owned());
+                }
+                Ok(None) => {}
+                Err(err) => log_error!(
+                    "failed to check realized backrun of {:?}: {}",
+                    arb.event.hint.hash,
+                    err
+                ),
+            }
+        }
+        db.write_realized(&checked).await?;
+    }
+
+    if attribute {
+        let rows = attribution(&arbs, labels);
+        match format {
+            OutputFormat::Table => print!("{}", render_attribution_table(&rows)),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        }
+        return Ok(());
+    }
+    let summary = regret_summary(&arbs);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&summary, labels)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+    }
+    Ok(())
+}
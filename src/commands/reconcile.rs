@@ -0,0 +1,60 @@
+use crate::analysis::{
+    read_all_arbs,
+    reconcile::{block_range, reconcile, render_table},
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::data::external::{
+    import_external_arbs, load_external_arbs, parse_dataset, DatasetSource,
+};
+use crate::labels::load_labels;
+use crate::{info, Result};
+use std::path::Path;
+
+/// Imports the CSV export of `source` at `import` (if given), then compares the imported arbs of `source`
+/// to the arbs simulated on `chain_id`, over the blocks both cover (within `params`' block range, if given).
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    chain_id: u64,
+    import: Option<&Path>,
+    source: DatasetSource,
+    num_examples: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    if let Some(path) = import {
+        let arbs = parse_dataset(source, &std::fs::read_to_string(path)?)
+            .map_err(|err| anyhow::format_err!("invalid dataset {:?}: {}", path, err))?;
+        import_external_arbs(chain_id, &arbs)?;
+        info!("imported {} {} arbs from {:?}", arbs.len(), source, path);
+    }
+    let external = load_external_arbs(chain_id)?
+        .into_iter()
+        .filter(|arb| {
+            arb.source == source
+                && params
+                    .block_start
+                    .is_none_or(|start| arb.block >= start as u64)
+                && params.block_end.is_none_or(|end| arb.block <= end as u64)
+        })
+        .collect::<Vec<_>>();
+    let (first, last) = block_range(&external).ok_or(anyhow::format_err!(
+        "no {} arbs imported for chain {} in the block range; import a dataset with --import",
+        source,
+        chain_id
+    ))?;
+    // only the dataset's blocks can be compared
+    let params = ArbFilterParams {
+        block_start: Some(first as u32),
+        block_end: Some(last as u32),
+        ..params
+    };
+    let arbs = read_all_arbs(read_db, &params).await?;
+    let labels = load_labels(chain_id, Some(read_db), None).await?;
+    let reconciliation = reconcile(&external, &arbs, num_examples);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&reconciliation, &labels)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reconciliation)?),
+    }
+    Ok(())
+}
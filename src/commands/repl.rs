@@ -0,0 +1,404 @@
+use crate::analysis::{
+    leaderboard::{render_dex_table, render_route_table, render_table, LeaderboardKind},
+    read_opportunities, to_decimal,
+};
+use crate::commands::{
+    leaderboard::{ranked_dexes, ranked_routes, ranked_tokens},
+    OutputFormat,
+};
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    tokens::TokenMetadataStore,
+};
+use crate::interfaces::{PoolVariant, SimArbResultBatch};
+use crate::service::SimService;
+use crate::util::get_block_info;
+use crate::Result;
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256},
+};
+use serde::Serialize;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Number of rows that `top` prints if no limit is given.
+pub const DEFAULT_TOP_LIMIT: usize = 10;
+
+pub const REPL_HELP: &str = "\
+commands:
+  sim <tx hash>                          simulate backruns of a landed tx
+  price <pool> [@ <block>]               price of a pool, at the latest block by default
+  top <tokens|routes|dexes> [<window>] [<limit>]
+                                         leaderboard of stored results, e.g. `top tokens 7d`;
+                                         windows are like 30m, 12h, 7d or 2w (all results by default)
+  format <table|json>                    print the results of later commands in this format
+  help                                   show this message
+  exit                                   leave the REPL
+";
+
+/// A line of input to the REPL.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplCommand {
+    Help,
+    /// Simulates backruns of a landed tx.
+    Sim(H256),
+    /// Prices a pool at a block, or the latest block.
+    Price {
+        pool: Address,
+        block: Option<u64>,
+    },
+    /// Ranks stored results of the last `window` seconds (or all of them).
+    Top {
+        kind: LeaderboardKind,
+        window: Option<u64>,
+        limit: usize,
+    },
+    Format(OutputFormat),
+    Exit,
+}
+
+/// Parses a window of time like `30m`, `12h`, `7d` or `2w` into seconds.
+pub fn parse_window(window: &str) -> Result<u64> {
+    let invalid = || anyhow::format_err!("invalid window {:?}, expected e.g. 12h or 7d", window);
+    let unit_idx = window
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = window.split_at(unit_idx);
+    let amount = amount.parse::<u64>().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(amount * unit_secs)
+}
+
+impl ReplCommand {
+    /// Parses a line of input. Returns None for a blank line.
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((command, args)) = words.split_first() else {
+            return Ok(None);
+        };
+        let usage = |usage: &str| anyhow::format_err!("usage: {}", usage);
+        let command = match (*command, args) {
+            ("help", []) => ReplCommand::Help,
+            ("exit" | "quit", []) => ReplCommand::Exit,
+            ("sim", [tx_hash]) => ReplCommand::Sim(
+                H256::from_str(tx_hash)
+                    .map_err(|err| anyhow::format_err!("invalid tx hash {:?}: {}", tx_hash, err))?,
+            ),
+            ("sim", _) => return Err(usage("sim <tx hash>")),
+            ("price", [pool, rest @ ..]) => {
+                let pool = Address::from_str(pool)
+                    .map_err(|err| anyhow::format_err!("invalid pool {:?}: {}", pool, err))?;
+                // accepts both `@ 123` and `@123`
+                let block = match rest.join("").strip_prefix('@') {
+                    Some(block) => Some(
+                        block
+                            .parse::<u64>()
+                            .map_err(|_| anyhow::format_err!("invalid block {:?}", block))?,
+                    ),
+                    None if rest.is_empty() => None,
+                    None => return Err(usage("price <pool> [@ <block>]")),
+                };
+                ReplCommand::Price { pool, block }
+            }
+            ("price", _) => return Err(usage("price <pool> [@ <block>]")),
+            ("top", [kind, rest @ ..]) if rest.len() <= 2 => {
+                let kind = kind
+                    .parse::<LeaderboardKind>()
+                    .map_err(|err| anyhow::format_err!(err))?;
+                let (mut window, mut limit) = (None, DEFAULT_TOP_LIMIT);
+                for arg in rest {
+                    match arg.parse::<usize>() {
+                        Ok(num_rows) => limit = num_rows,
+                        Err(_) => window = Some(parse_window(arg)?),
+                    }
+                }
+                ReplCommand::Top {
+                    kind,
+                    window,
+                    limit,
+                }
+            }
+            ("top", _) => return Err(usage("top <tokens|routes|dexes> [<window>] [<limit>]")),
+            ("format", [format]) => ReplCommand::Format(
+                format
+                    .parse::<OutputFormat>()
+                    .map_err(|err| anyhow::format_err!(err))?,
+            ),
+            ("format", _) => return Err(usage("format <table|json>")),
+            (command, _) => {
+                return Err(anyhow::format_err!(
+                    "unknown command {:?}, try `help`",
+                    command
+                ))
+            }
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Price of a pool at a block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolPrice {
+    pub pool: Address,
+    pub variant: PoolVariant,
+    pub block: u64,
+    pub token0: Address,
+    pub token1: Address,
+    /// Whole token1 per whole token0.
+    pub price: f64,
+}
+
+/// An interactive session on one chain: ad-hoc sims with `service`, and queries of the results stored in `db`.
+pub struct Repl {
+    pub service: SimService,
+    /// DB that `top` reads results from; `top` fails without one.
+    pub db: Option<ArbDatabase>,
+    pub store: TokenMetadataStore,
+    pub format: OutputFormat,
+}
+
+impl Repl {
+    /// Runs `command`, returning what to print.
+    pub async fn execute(&mut self, command: ReplCommand) -> Result<String> {
+        match command {
+            ReplCommand::Help => Ok(REPL_HELP.to_owned()),
+            ReplCommand::Exit => Ok(String::new()),
+            ReplCommand::Format(format) => {
+                self.format = format;
+                Ok(String::new())
+            }
+            ReplCommand::Sim(tx_hash) => {
+                let arb = self.service.simulate_backrun(tx_hash).await?;
+                match self.format {
+                    OutputFormat::Table => self.render_sim(&arb).await,
+                    OutputFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(&arb)?)),
+                }
+            }
+            ReplCommand::Price { pool, block } => {
+                let price = self.price(pool, block).await?;
+                match self.format {
+                    OutputFormat::Table => Ok(format!(
+                        "1 {} = {} {} on {:?} ({:?}) at block {}\n",
+                        self.store.symbol(&price.token0),
+                        price.price,
+                        self.store.symbol(&price.token1),
+                        price.pool,
+                        price.variant,
+                        price.block
+                    )),
+                    OutputFormat::Json => {
+                        Ok(format!("{}\n", serde_json::to_string_pretty(&price)?))
+                    }
+                }
+            }
+            ReplCommand::Top {
+                kind,
+                window,
+                limit,
+            } => self.top(kind, window, limit).await,
+        }
+    }
+
+    /// Prices `pool` at the end of `block` (or the latest block) on a fork.
+    pub async fn price(&mut self, pool: Address, block: Option<u64>) -> Result<PoolPrice> {
+        let hindsight = &self.service.hindsight;
+        let client = &hindsight.client;
+        let block = match block {
+            Some(block) => block,
+            None => client.get_block_number().await?.as_u64(),
+        };
+        let (token0, token1) = hindsight.pools.pair_tokens(client, pool).await?;
+        let block_info = get_block_info(client, block).await?;
+        let mut evm = hindsight.forks.fork(client, &block_info).await?;
+        let (variant, price) = hindsight
+            .pool_adapters
+            .quote_any(&mut evm, pool, (token0, token1))
+            .await?;
+        let token1_decimals = hindsight.pools.decimals(client, token1).await?;
+        self.store.fetch_missing(client, &[token0, token1]).await?;
+        Ok(PoolPrice {
+            pool,
+            variant,
+            block,
+            token0,
+            token1,
+            price: to_decimal(price, token1_decimals.as_u32()),
+        })
+    }
+
+    async fn top(
+        &mut self,
+        kind: LeaderboardKind,
+        window: Option<u64>,
+        limit: usize,
+    ) -> Result<String> {
+        let db = self
+            .db
+            .as_ref()
+            .ok_or(anyhow::format_err!("`top` needs a DB to read results from"))?;
+        let hindsight = &self.service.hindsight;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let params = ArbFilterParams {
+            timestamp_start: window.map(|window| now.saturating_sub(window) as u32),
+            chain_id: Some(hindsight.chain_id),
+            ..ArbFilterParams::none()
+        };
+        let arbs = read_opportunities(db, &params).await?;
+        let client = &hindsight.client;
+        let store = &mut self.store;
+        let format = self.format;
+        Ok(match kind {
+            LeaderboardKind::Tokens => {
+                let rows = ranked_tokens(&arbs, store, client, limit).await?;
+                match format {
+                    OutputFormat::Table => render_table(&rows, store),
+                    OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(&rows)?),
+                }
+            }
+            LeaderboardKind::Routes => {
+                let rows = ranked_routes(&arbs, store, client, limit).await?;
+                match format {
+                    OutputFormat::Table => render_route_table(&rows, store),
+                    OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(&rows)?),
+                }
+            }
+            LeaderboardKind::Dexes => {
+                let rows = ranked_dexes(&arbs, store, client, limit).await?;
+                match format {
+                    OutputFormat::Table => render_dex_table(&rows, store),
+                    OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(&rows)?),
+                }
+            }
+        })
+    }
+
+    /// Summarizes a sim's result: its best profit, then every route tried, most profitable first.
+    async fn render_sim(&mut self, arb: &SimArbResultBatch) -> Result<String> {
+        let client = &self.service.hindsight.client;
+        let tokens = arb
+            .results
+            .iter()
+            .map(|result| result.user_trade.tokens.token)
+            .chain([arb.profit_token])
+            .collect::<Vec<_>>();
+        self.store.fetch_missing(client, &tokens).await?;
+        let profit_symbol = self.store.symbol(&arb.profit_token);
+        let mut out = format!(
+            "{:?} at block {}: max profit {} {} over {} routes\n",
+            arb.event.hint.hash,
+            arb.event.block,
+            to_decimal(arb.max_profit, arb.profit_decimals),
+            profit_symbol,
+            arb.results.len()
+        );
+        let mut results = arb.results.iter().collect::<Vec<_>>();
+        results.sort_by_key(|result| std::cmp::Reverse(result.backrun_trade.profit));
+        for result in results {
+            let backrun = &result.backrun_trade;
+            let decimals = result.user_trade.quote_asset.decimals;
+            out.push_str(&format!(
+                "  {:<12} {:?} -> {:?}  in {} {}, profit {}\n",
+                self.store.symbol(&result.user_trade.tokens.token),
+                backrun.start_pool,
+                backrun.end_pool,
+                to_decimal(backrun.amount_in, decimals),
+                self.store.symbol(&result.user_trade.tokens.quote),
+                to_decimal(backrun.profit, decimals)
+            ));
+        }
+        Ok(out)
+    }
+}
+
+/// Reads commands from `input` (one per line) until `exit` or the end of `input`, writing each one's output
+/// to `output` after a prompt. Failed commands print their error, and the session goes on.
+pub async fn run<R, W>(repl: &mut Repl, input: R, mut output: W) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = input.lines();
+    output
+        .write_all(b"hindsight repl; type `help` for commands\n")
+        .await?;
+    loop {
+        output.write_all(b"> ").await?;
+        output.flush().await?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let printed = match ReplCommand::parse(&line) {
+            Ok(None) => continue,
+            Ok(Some(ReplCommand::Exit)) => break,
+            Ok(Some(command)) => repl.execute(command).await,
+            Err(err) => Err(err),
+        };
+        let printed = printed.unwrap_or_else(|err| format!("error: {}\n", err));
+        output.write_all(printed.as_bytes()).await?;
+    }
+    output.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_commands() {
+        let pool = Address::from_low_u64_be(1);
+        assert_eq!(ReplCommand::parse("  ").unwrap(), None);
+        assert_eq!(
+            ReplCommand::parse(&format!("price {:?} @ 17000000", pool)).unwrap(),
+            Some(ReplCommand::Price {
+                pool,
+                block: Some(17_000_000)
+            })
+        );
+        assert_eq!(
+            ReplCommand::parse(&format!("price {:?} @17000000", pool)).unwrap(),
+            Some(ReplCommand::Price {
+                pool,
+                block: Some(17_000_000)
+            })
+        );
+        assert_eq!(
+            ReplCommand::parse(&format!("price {:?}", pool)).unwrap(),
+            Some(ReplCommand::Price { pool, block: None })
+        );
+        assert_eq!(
+            ReplCommand::parse("top tokens 7d").unwrap(),
+            Some(ReplCommand::Top {
+                kind: LeaderboardKind::Tokens,
+                window: Some(7 * 24 * 60 * 60),
+                limit: DEFAULT_TOP_LIMIT
+            })
+        );
+        assert_eq!(
+            ReplCommand::parse("top routes 5").unwrap(),
+            Some(ReplCommand::Top {
+                kind: LeaderboardKind::Routes,
+                window: None,
+                limit: 5
+            })
+        );
+        assert!(ReplCommand::parse("sim 0x12").is_err());
+        assert!(ReplCommand::parse("top tokens 7y").is_err());
+        assert!(ReplCommand::parse("price").is_err());
+        assert!(ReplCommand::parse("frobnicate").is_err());
+        assert_eq!(parse_window("12h").unwrap(), 12 * 60 * 60);
+        assert!(parse_window("h").is_err());
+    }
+}
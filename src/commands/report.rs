@@ -0,0 +1,109 @@
+use crate::analysis::{
+    bids::{capturable_summary, BidModel, MIN_ROUTE_SAMPLES},
+    read_opportunities,
+    realized::regret_summary,
+    report::{render_html, render_markdown, Report, ReportFormat},
+    stats::{bucket_stats, format_timestamp, BucketSize, StatsOptions},
+};
+use crate::commands::leaderboard::{ranked_dexes, ranked_routes, ranked_tokens};
+use crate::commands::OutputFormat;
+use crate::config::ChainConfig;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    tokens::TokenMetadataStore,
+    EXPORT_DIR,
+};
+use crate::util::WsClient;
+use crate::{info, Result};
+use serde_json::json;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug)]
+pub struct ReportOptions {
+    pub format: ReportFormat,
+    pub bucket_size: BucketSize,
+    /// Number of rows in each leaderboard.
+    pub limit: usize,
+    /// Gas used per backrun, for backruns whose gas wasn't recorded.
+    pub backrun_gas: u64,
+    /// File to save the report to, in `EXPORT_DIR`. Defaults to "report_{unix-timestamp}.{ext}".
+    pub filename: Option<String>,
+    /// With `Json`, the report's path is printed as JSON once it's saved.
+    pub output: OutputFormat,
+}
+
+/// Writes a report of stats, leaderboards, and realized backruns of the arbs matching `params`.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain: &ChainConfig,
+    options: ReportOptions,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let mut store = TokenMetadataStore::load(chain.chain_id)?;
+    let tokens = ranked_tokens(&arbs, &mut store, client, options.limit).await?;
+    let routes = ranked_routes(&arbs, &mut store, client, options.limit).await?;
+    let dexes = ranked_dexes(&arbs, &mut store, client, options.limit).await?;
+    let stats = bucket_stats(
+        &arbs,
+        &StatsOptions {
+            bucket_size: options.bucket_size,
+            backrun_gas: options.backrun_gas,
+            native_token: chain.wrapped_native,
+            by_dex: false,
+        },
+    );
+    let profit_tokens = stats.iter().map(|row| row.profit_token).collect::<Vec<_>>();
+    store.fetch_missing(client, &profit_tokens).await?;
+
+    let mut description = vec![format!("chain: {} ({})", chain.name, chain.chain_id)];
+    let first = arbs.iter().min_by_key(|arb| arb.event.timestamp);
+    let last = arbs.iter().max_by_key(|arb| arb.event.timestamp);
+    if let (Some(first), Some(last)) = (first, last) {
+        description.push(format!(
+            "{} events from {} (block {}) to {} (block {})",
+            arbs.len(),
+            format_timestamp(first.event.timestamp),
+            first.event.block,
+            format_timestamp(last.event.timestamp),
+            last.event.block
+        ));
+    }
+    if let Some(run_label) = &params.run_label {
+        description.push(format!("run: {}", run_label));
+    }
+    let bids = BidModel::estimate(&arbs, MIN_ROUTE_SAMPLES);
+    let report = Report {
+        title: format!("hindsight report: {}", chain.name),
+        description,
+        bucket_size: Some(options.bucket_size),
+        stats,
+        tokens,
+        routes,
+        dexes,
+        regret: regret_summary(&arbs),
+        capturable: capturable_summary(&arbs, &bids, options.backrun_gas, chain.wrapped_native),
+        bids: bids.routes().into_iter().cloned().collect(),
+    };
+    let contents = match options.format {
+        ReportFormat::Html => render_html(&report, &store),
+        ReportFormat::Markdown => render_markdown(&report, &store),
+    };
+
+    let filename = options.filename.unwrap_or(format!(
+        "report_{}.{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        options.format.extension()
+    ));
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    let path = PathBuf::from(EXPORT_DIR).join(filename);
+    std::fs::write(&path, contents)?;
+    info!("saved report to {:?}", path);
+    if options.output == OutputFormat::Json {
+        println!("{}", json!({ "path": path }));
+    }
+    Ok(())
+}
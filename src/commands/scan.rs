@@ -1,31 +1,292 @@
+use crate::analysis::{stats::format_timestamp, to_decimal};
+use crate::audit::{AuditDecision, AuditLog};
+use crate::commands::OutputFormat;
+use crate::config::ChainConfig;
+use crate::control::ScanControl;
 use crate::data::arbs::ArbDatabase;
-use crate::data::db::DbEngine;
-use crate::event_history::event_history_url;
-use crate::hindsight::Hindsight;
-use crate::info;
-use crate::sim::processor::H256Map;
-use crate::util::{fetch_txs, filter_events_by_topic, WsClient};
-use crate::Result;
-use ethers::types::H256;
+use crate::data::db::{Db, DbEngine};
+use crate::data::tokens::TokenMetadataStore;
+use crate::estimate::{
+    estimate_workload, fetch_range, measure_latency, WorkloadEstimate, WorkloadSettings,
+};
+use crate::event_history::{EventSource, MevShareEvents};
+use crate::filter::WatchedAddressLists;
+use crate::hindsight::{Hindsight, ProcessedOrderflow, SimOptions};
+use crate::labels::{load_labels, Labels};
+use crate::live;
+use crate::metrics::{progress_line, sim_cache_line, MetricTags, MetricsBuffer, MetricsSink};
+use crate::notify::{notify_all, ArbAlerts, Destination};
+use crate::paper::PaperTrader;
+use crate::relay::{check_single_chain, ArbSender};
+use crate::reorg::ReorgWatch;
+use crate::service::{fill_private_hints, RESULTS_CHANNEL_SIZE};
+use crate::sim::{
+    budget::{SimBudget, TxSimLimits},
+    cache::SimCache,
+    graph::{PathFinder, PoolGraph},
+    liquidation::{known_lending_markets, simulate_liquidations, LendingMarket},
+    pools::PoolAdapters,
+    processor::H256Map,
+};
+use crate::util::{fetch_txs, filter_events_by_topic, get_ws_client, panic_message, WsClient};
+use crate::{info, log_error, warn};
+use crate::{Error, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Transaction, H256, U256},
+};
+use futures::future;
 use mev_share_sse::{EventClient, EventHistory, EventHistoryParams};
-use std::str::FromStr;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Most cycles through more pools that are backrun per user trade, when `ScanOptions::max_hops` is set.
+const MAX_CYCLES_PER_TRADE: usize = 4;
+/// Seconds between rounds of indexing new blocks into the pool graph; about one block on mainnet.
+const GRAPH_INDEX_INTERVAL_SECS: u64 = 12;
 
 #[derive(Clone, Debug)]
 pub struct ScanOptions {
     pub batch_size: usize,
-    pub block_start: u32,
+    /// Bounds to auto-tune the batch size within (see `ScanControl::auto_tune`), starting from `batch_size`.
+    pub auto_tune: Option<RangeInclusive<usize>>,
+    /// Scan from this block. If neither start param is set, the scan resumes from the latest arb in the DB.
+    pub block_start: Option<u32>,
     pub block_end: Option<u32>,
-    pub timestamp_start: u32,
+    /// Scan from this timestamp. If neither start param is set, the scan resumes from the latest arb in the DB.
+    pub timestamp_start: Option<u32>,
     pub timestamp_end: Option<u32>,
     pub db_engine: DbEngine,
+    /// Label to tag this run's results with.
+    pub run_label: Option<String>,
+    /// Unix socket to accept runtime control commands on (see `ScanControl::handle_command`).
+    pub control_socket: Option<PathBuf>,
+    /// File of tokens & pools to allow/deny (see `filter::AddressLists`). Reloaded when it changes.
+    pub address_lists: Option<PathBuf>,
+    /// Save every fetched event to the DB (not just those with simulated arbs), for hint analysis.
+    pub save_events: bool,
+    /// Find the swaps of events that hint no logs in their txs' receipts (see `fill_private_hints`), rather than skipping them.
+    pub replay_private: bool,
+    /// Where to post when each chain's scan starts, and its `ScanSummary` when the scan ends or fails.
+    pub notify: Vec<Destination>,
+    /// Endpoint to write per-block metrics to while scanning.
+    pub metrics: Option<MetricsSink>,
+    /// Address to push every result to WebSocket clients on, as soon as it's produced.
+    pub results_ws: Option<SocketAddr>,
+    /// Webhooks to alert when an arb's profit reaches a threshold.
+    pub alerts: Option<ArbAlerts>,
+    /// Ledger to paper-trade every opportunity into, as if a bot had been sending the backruns.
+    pub paper: Option<Arc<PaperTrader>>,
+    /// Signs & submits an arb of every opportunity that's still profitable on the latest state to the chain's
+    /// relays, within a daily spend limit. Only for scans that follow new events.
+    pub send_arbs: Option<Arc<ArbSender>>,
+    /// Bounds the forks & sims running at once. Shared by every chain, like `batch_size`.
+    pub sim_budget: SimBudget,
+    /// Caps the sims & time spent on each tx.
+    pub tx_limits: TxSimLimits,
+    /// Reuse the outcomes of backrun sims run by earlier scans (see `SimCache`), and save new ones after every batch.
+    pub sim_cache: bool,
+    /// Record why each sampled event did or didn't produce a result (see `AuditLog`), saving the records after every batch.
+    pub audit: Option<AuditLog>,
+    /// With `Json`, each chain's `ScanSummary` is printed as a line of JSON when its scan ends.
+    pub output: OutputFormat,
+    /// Borrowers whose positions are checked for liquidations made possible by each tx (see `sim::liquidation`).
+    /// Empty to skip liquidations.
+    pub liquidation_borrowers: Vec<Address>,
+    /// Most pools in the cycles backrun through each user's pool (see `PathFinder`). Only two-pool routes if not set.
+    pub max_hops: Option<usize>,
+}
+
+/// What a chain's scan covered & found, posted to `ScanOptions::notify` when the scan ends.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanSummary {
+    /// (block, timestamp) of the first & last processed events.
+    pub first_event: Option<(u64, u64)>,
+    pub last_event: Option<(u64, u64)>,
+    /// Number of events that passed the filters.
+    pub num_events: usize,
+    pub num_txs: usize,
+    /// Number of simulated arbs, profitable or not.
+    pub num_arbs: usize,
+    pub num_profitable: usize,
+    /// Number of txs whose simulation failed.
+    pub num_failed: usize,
+    /// Sum of max profits and decimals, by profit token.
+    pub profits: BTreeMap<Address, (U256, u32)>,
+}
+
+impl ScanSummary {
+    fn add_events(&mut self, events: &[EventHistory]) {
+        for event in events {
+            let position = (event.block, event.timestamp);
+            self.first_event = Some(
+                self.first_event
+                    .map_or(position, |first| first.min(position)),
+            );
+            self.last_event = Some(self.last_event.map_or(position, |last| last.max(position)));
+        }
+        self.num_events += events.len();
+    }
+
+    fn add_orderflow(&mut self, num_txs: usize, processed: &ProcessedOrderflow) {
+        self.num_txs += num_txs;
+        self.num_arbs += processed.arbs.len();
+        self.num_failed += processed.num_failed;
+        for arb in &processed.arbs {
+            if arb.max_profit.is_zero() {
+                continue;
+            }
+            self.num_profitable += 1;
+            let (profit, decimals) = self.profits.entry(arb.profit_token).or_default();
+            *profit += arb.max_profit;
+            *decimals = arb.profit_decimals;
+        }
+    }
+
+    /// Renders the summary as a short message, naming profit tokens by their symbols in `store` (if given).
+    /// `error` is the error that stopped the scan, if any.
+    pub fn message(
+        &self,
+        chain_name: &str,
+        store: Option<&TokenMetadataStore>,
+        error: Option<&Error>,
+    ) -> String {
+        let mut message = match error {
+            Some(err) => format!("hindsight: scan FAILED on {}: {}\n", chain_name, err),
+            None => format!("hindsight: scan finished on {}\n", chain_name),
+        };
+        match (self.first_event, self.last_event) {
+            (Some((first_block, first_timestamp)), Some((last_block, last_timestamp))) => message
+                .push_str(&format!(
+                    "blocks {}..={} ({} to {})\n",
+                    first_block,
+                    last_block,
+                    format_timestamp(first_timestamp),
+                    format_timestamp(last_timestamp)
+                )),
+            _ => message.push_str("no events processed\n"),
+        }
+        message.push_str(&format!(
+            "{} events, {} txs, {} arbs ({} profitable), {} failed simulations\n",
+            self.num_events, self.num_txs, self.num_arbs, self.num_profitable, self.num_failed
+        ));
+        for (token, (profit, decimals)) in &self.profits {
+            message.push_str(&format!(
+                "profit: {:.6} {}\n",
+                to_decimal(*profit, *decimals),
+                store
+                    .map(|store| store.symbol(token))
+                    .unwrap_or(format!("{:?}", token))
+            ));
+        }
+        message
+    }
+
+    /// Renders the summary as JSON, with the error that stopped the scan (if any).
+    pub fn to_json(&self, chain: &ChainConfig, error: Option<&Error>) -> serde_json::Value {
+        let position = |position: Option<(u64, u64)>| {
+            position.map(|(block, timestamp)| json!({ "block": block, "timestamp": timestamp }))
+        };
+        json!({
+            "chain": chain.name,
+            "chainId": chain.chain_id,
+            "firstEvent": position(self.first_event),
+            "lastEvent": position(self.last_event),
+            "numEvents": self.num_events,
+            "numTxs": self.num_txs,
+            "numArbs": self.num_arbs,
+            "numProfitable": self.num_profitable,
+            "numFailed": self.num_failed,
+            "profits": self
+                .profits
+                .iter()
+                .map(|(token, (profit, decimals))| {
+                    json!({
+                        "profitToken": token,
+                        "profit": profit,
+                        "profitDecimal": to_decimal(*profit, *decimals),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "error": error.map(|err| err.to_string()),
+        })
+    }
+}
+
+impl ScanOptions {
+    /// Renders a short message announcing that a scan of `chain_name` with these options started.
+    pub fn start_message(&self, chain_name: &str) -> String {
+        let start = match (self.block_start, self.timestamp_start) {
+            (Some(block), _) => format!("from block {}", block),
+            (None, Some(timestamp)) => format!("from {}", format_timestamp(timestamp as u64)),
+            (None, None) => "from the last saved arb".to_owned(),
+        };
+        let end = match (self.block_end, self.timestamp_end) {
+            (Some(block), _) => format!("until block {}", block),
+            (None, Some(timestamp)) => format!("until {}", format_timestamp(timestamp as u64)),
+            (None, None) => "following new events".to_owned(),
+        };
+        let mut message = format!(
+            "hindsight: scan started on {} {}, {}",
+            chain_name, start, end
+        );
+        if let Some(run_label) = &self.run_label {
+            message.push_str(&format!(" (run {})", run_label));
+        }
+        message
+    }
+
+    /// Returns a copy of these options with start params filled in from `db` if they weren't specified.
+    pub async fn resolve_start(self, db: &ArbDatabase) -> Result<Self> {
+        /* If no start/end params are defined,
+            refine params based on ranges present in DB.
+            Overwriting old results may be accomplished by setting the start/end timestamp/block params.
+            Replace the provided timestamp/block params with the latest respective
+            value + 1 in the DB if it's higher than the param.
+            We add 1 to prevent duplicates. If an arb is saved in the DB,
+            then we know we've scanned & simulated up to that point.
+            Timestamp is evaluated by default, falls back to block.
+        */
+        let (block_start, timestamp_start) =
+            if self.block_start.is_none() && self.timestamp_start.is_none() {
+                let db_ranges = db.get_previously_saved_ranges().await?;
+                info!("previously saved event ranges: {:?}", db_ranges);
+                (
+                    db_ranges.latest_block as u32,
+                    db_ranges.latest_timestamp as u32,
+                )
+            } else {
+                if self.block_start.is_some() && self.timestamp_start.is_some() {
+                    return Err(anyhow::format_err!(
+                        "cannot specify both block_start and timestamp_start"
+                    ));
+                }
+                // use whichever is specified; the other (being 1) will not alter the selection
+                (
+                    self.block_start.unwrap_or(1),
+                    self.timestamp_start.unwrap_or(1),
+                )
+            };
+        Ok(Self {
+            block_start: Some(block_start),
+            timestamp_start: Some(timestamp_start),
+            ..self
+        })
+    }
 }
 
 impl Into<EventHistoryParams> for ScanOptions {
     fn into(self) -> EventHistoryParams {
         EventHistoryParams {
-            block_start: Some(self.block_start.into()),
+            block_start: Some(self.block_start.unwrap_or(1).into()),
             block_end: self.block_end.map(|x| x.into()),
-            timestamp_start: Some(self.timestamp_start.into()),
+            timestamp_start: Some(self.timestamp_start.unwrap_or(1).into()),
             timestamp_end: self.timestamp_end.map(|x| x.into()),
             limit: Some(500),
             offset: Some(0),
@@ -33,45 +294,411 @@ impl Into<EventHistoryParams> for ScanOptions {
     }
 }
 
-fn uniswap_topics() -> Vec<H256> {
-    vec![
-        // univ3
-        // Swap(address,address,int256,int256,uint160,uint128,int24)
-        H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")
-            .expect("that's some bad hash"),
-        // univ2
-        // Swap(address,uint256,uint256,uint256,uint256,address)
-        H256::from_str("0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822")
-            .expect("that's some bad hash"),
-    ]
+/// Scans every chain in `chains` concurrently.
+///
+/// All chains share one simulation limit of `params.batch_size` txs, so adding chains
+/// doesn't multiply the load on the host. Returns when every chain's scan has finished.
+///
+/// If `params.control_socket` is set, the limit and other settings can be changed while the scan runs.
+/// With `params.auto_tune`, the limit is also adjusted after every batch.
+pub async fn run_chains(
+    params: ScanOptions,
+    chains: &Vec<ChainConfig>,
+    mevshare: &EventClient,
+) -> Result<()> {
+    if params.send_arbs.is_some() {
+        check_single_chain(chains)?;
+    }
+    let control = Arc::new(ScanControl::new(params.batch_size, params.batch_size));
+    if let Some(bounds) = params.auto_tune.to_owned() {
+        control.auto_tune(bounds)?;
+    }
+    if let Some(socket) = params.control_socket.to_owned() {
+        let control = control.clone();
+        tokio::task::spawn(async move {
+            if let Err(err) = control.serve(socket).await {
+                log_error!("scan control socket failed: {}", err);
+            }
+        });
+    }
+    let address_lists = match params.address_lists.to_owned() {
+        Some(path) => {
+            let address_lists = Arc::new(WatchedAddressLists::load(path)?);
+            tokio::task::spawn(address_lists.clone().watch(Duration::from_secs(5)));
+            Some(address_lists)
+        }
+        None => None,
+    };
+    let results_feed = match params.results_ws {
+        Some(addr) => {
+            let (results, _) = broadcast::channel(RESULTS_CHANNEL_SIZE);
+            let feed = results.clone();
+            tokio::task::spawn(async move {
+                if let Err(err) = live::serve(addr, feed).await {
+                    log_error!("results websocket failed: {}", err);
+                }
+            });
+            Some(results)
+        }
+        None => None,
+    };
+    // each chain scans on its own task, so one chain's sims don't hold up another's
+    let scans = chains.iter().map(|chain| {
+        let chain = chain.to_owned();
+        let mevshare = mevshare.to_owned();
+        let params = params.clone();
+        let control = control.clone();
+        let address_lists = address_lists.clone();
+        let results_feed = results_feed.clone();
+        tokio::task::spawn(async move {
+            let chain = &chain;
+            let sim_cache = match params.sim_cache {
+                true => Some(SimCache::load(chain.chain_id)?),
+                false => None,
+            };
+            let path_finder = match params.max_hops {
+                Some(max_hops) => Some(PathFinder {
+                    graph: PoolGraph::load(chain.chain_id)?,
+                    max_hops,
+                    max_cycles: MAX_CYCLES_PER_TRADE,
+                }),
+                None => None,
+            };
+            let db = Db::try_new(
+                params
+                    .db_engine
+                    .to_owned()
+                    .with_namespace(&chain.db_namespace),
+            )
+            .await?;
+            let mut builder = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .store(db.connect)
+                .event_source(Arc::new(MevShareEvents::new(
+                    mevshare.to_owned(),
+                    &chain.events_api_url,
+                )))
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    address_lists,
+                    sim_limit: None,
+                    pool_adapters: Default::default(),
+                    sim_budget: params.sim_budget.to_owned(),
+                    tx_limits: params.tx_limits,
+                    sim_cache,
+                    audit: params.audit.to_owned(),
+                    path_finder: path_finder.to_owned(),
+                })
+                .control(control)
+                .run_tags(chain.chain_id, params.run_label.to_owned());
+            if let Some(results) = results_feed {
+                builder = builder.results_feed(results);
+            }
+            let hindsight = builder.build().await?;
+            // pools saved by the `indexer` don't need their tokens & factory fetched
+            if let Some(store) = &hindsight.store {
+                match store.read_pools(chain.chain_id).await {
+                    Ok(pools) if !pools.is_empty() => {
+                        hindsight.pools.seed(&pools);
+                        info!(
+                            "seeded the pool registry with {} indexed pools",
+                            pools.len()
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(err) => log_error!("failed to read indexed pools: {}", err),
+                }
+            }
+            // tokens measured by `token-tax` are screened by their taxes instead of a probe on each fork
+            match TokenMetadataStore::load(chain.chain_id) {
+                Ok(tokens) if !tokens.taxes().is_empty() => {
+                    hindsight.pools.seed_taxes(&tokens.taxes());
+                    info!("seeded {} measured token taxes", tokens.taxes().len());
+                }
+                Ok(_) => {}
+                Err(err) => log_error!("failed to load measured token taxes: {}", err),
+            }
+            if let Some(path_finder) = path_finder {
+                tokio::task::spawn(path_finder.graph.follow(
+                    hindsight.client.clone(),
+                    hindsight.pools.clone(),
+                    Duration::from_secs(GRAPH_INDEX_INTERVAL_SECS),
+                ));
+            }
+            let notify = params.notify.to_owned();
+            let output = params.output;
+            // failures are logged by `notify_all`; they shouldn't stop the scan
+            notify_all(&notify, &params.start_message(&chain.name))
+                .await
+                .ok();
+            let mut summary = ScanSummary::default();
+            let res = hindsight.scan(params, chain, &mut summary).await;
+            if let Err(err) = &res {
+                log_error!(
+                    "scan failed on chain {} ({}): {}",
+                    chain.name,
+                    chain.chain_id,
+                    err
+                );
+            }
+            if output == OutputFormat::Json {
+                println!("{}", summary.to_json(chain, res.as_ref().err()));
+            }
+            if !notify.is_empty() {
+                // only cached symbols are used; fetching missing ones isn't worth delaying the notification
+                let store = TokenMetadataStore::load(chain.chain_id).ok();
+                let message = summary.message(&chain.name, store.as_ref(), res.as_ref().err());
+                if notify_all(&notify, &message).await.is_err() {
+                    log_error!("failed to send scan summary for {}", chain.name);
+                }
+            }
+            res
+        })
+    });
+    for res in future::join_all(scans).await {
+        res.map_err(|err| anyhow::format_err!("scan {}", panic_message(err)))??;
+    }
+    Ok(())
+}
+
+fn render_estimates(estimates: &[(String, WorkloadEstimate)]) -> String {
+    let mut table = format!(
+        "{:<12} {:>9} {:>10} {:>8} {:>8} {:>8} {:>12} {:>14} {:>12} {:>10}\n",
+        "chain",
+        "events",
+        "candidates",
+        "blocks",
+        "pools",
+        "routes",
+        "evm runs",
+        "max evm runs",
+        "rpc calls",
+        "runtime"
+    );
+    for (chain, estimate) in estimates {
+        table.push_str(&format!(
+            "{:<12} {:>9} {:>10} {:>8} {:>8} {:>8} {:>12} {:>14} {:>12} {:>10}\n",
+            chain,
+            estimate.num_events,
+            estimate.num_candidates,
+            estimate.num_blocks,
+            format!("{}/{}", estimate.num_indexed_pools, estimate.num_pools),
+            estimate.num_routes,
+            estimate.evm_runs,
+            estimate.max_evm_runs,
+            estimate.rpc_requests,
+            format!("{:.1}h", estimate.runtime_secs / 3600.0)
+        ));
+    }
+    table.push_str("pools: indexed/swapped. runtime assumes the measured node latency & rough EVM costs (see `estimate`).\n");
+    table
 }
 
-pub async fn run(
+/// Fetches the events of every chain's range in `params` (without simulating anything) and prints a prediction of
+/// the work a scan of it would do with `params`' settings (see `estimate::estimate_workload`).
+///
+/// Pools that the `indexer` saved to the DB are counted as known; if the DB can't be reached, none are.
+pub async fn estimate_chains(
     params: ScanOptions,
-    ws_client: &WsClient,
+    chains: &[ChainConfig],
     mevshare: &EventClient,
+) -> Result<()> {
+    let lists = match params.address_lists.to_owned() {
+        Some(path) => Some(WatchedAddressLists::load(path)?.get()),
+        None => None,
+    };
+    let cores = std::thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1);
+    let settings = WorkloadSettings {
+        batch_size: params.batch_size,
+        evm_parallelism: params.sim_budget.size().min(cores),
+        max_sims: params.tx_limits.max_sims,
+        screen_tokens: params.tx_limits.max_token_tax.is_some(),
+        replay_private: params.replay_private,
+        cycles_per_trade: match params.max_hops {
+            Some(_) => MAX_CYCLES_PER_TRADE,
+            None => 0,
+        },
+    };
+    let mut estimates = vec![];
+    for chain in chains {
+        let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+        let event_source = MevShareEvents::new(mevshare.to_owned(), &chain.events_api_url);
+        info!("[{}] fetching events to estimate", chain.name);
+        let events = fetch_range(&event_source, params.clone().into()).await?;
+        let db_engine = params
+            .db_engine
+            .to_owned()
+            .with_namespace(&chain.db_namespace);
+        let indexed = match Db::try_new(db_engine).await {
+            Ok(db) => db.connect.read_pools(chain.chain_id).await?,
+            Err(err) => {
+                warn!("[{}] not counting indexed pools: {}", chain.name, err);
+                vec![]
+            }
+        };
+        let estimate = estimate_workload(
+            chain.chain_id,
+            &events,
+            &PoolAdapters::default().swap_topics(),
+            lists.as_deref(),
+            &indexed,
+            &settings,
+            measure_latency(&client).await?,
+        );
+        estimates.push((chain.name.to_owned(), estimate));
+    }
+    match params.output {
+        OutputFormat::Table => print!("{}", render_estimates(&estimates)),
+        OutputFormat::Json => {
+            let estimates = estimates
+                .iter()
+                .map(|(chain, estimate)| {
+                    let mut json = serde_json::to_value(estimate)?;
+                    json["chain"] = json!(chain);
+                    Ok(json)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            println!("{}", serde_json::to_string_pretty(&estimates)?)
+        }
+    }
+    Ok(())
+}
+
+/// Writes `lines` to `sink`, logging failures; a metrics outage shouldn't stop the scan.
+async fn write_metrics(sink: &MetricsSink, lines: &[String]) {
+    if let Err(err) = sink.write(lines).await {
+        log_error!("failed to write {} metrics: {}", lines.len(), err);
+    }
+}
+
+/// Re-checks the blocks of watched results that are buried deep enough, logging failures;
+/// results that can't be checked now are dropped rather than holding up the scan.
+async fn check_reorgs(reorgs: &mut ReorgWatch, client: &WsClient, db: &ArbDatabase) {
+    let res = match client.get_block_number().await {
+        Ok(head) => reorgs.check(client, head.as_u64(), db).await,
+        Err(err) => Err(err.into()),
+    };
+    match res {
+        Ok(0) => {}
+        Ok(num_orphaned) => info!("marked {} results as orphaned by a reorg", num_orphaned),
+        Err(err) => log_error!("failed to check for reorgs: {}", err),
+    }
+}
+
+/// Records why the sampled events of `fetched` that aren't in `kept` were filtered out before they were simulated.
+fn audit_filtered(
+    audit: &AuditLog,
+    fetched: &[EventHistory],
+    kept: &[EventHistory],
+    filter_topics: &[H256],
+) {
+    let kept = kept
+        .iter()
+        .map(|event| event.hint.hash)
+        .collect::<HashSet<_>>();
+    for event in fetched
+        .iter()
+        .filter(|event| !kept.contains(&event.hint.hash))
+    {
+        // like `filter_events_by_topic`, any topic of any hinted log counts
+        let hints_swap = event
+            .hint
+            .logs
+            .iter()
+            .any(|log| log.topics.iter().any(|topic| filter_topics.contains(topic)));
+        let decision = match hints_swap {
+            true => AuditDecision::ExcludedEvent,
+            false => AuditDecision::NoSwapHint,
+        };
+        audit.record(event, None, decision, None);
+    }
+}
+
+/// Drops the txs of `txs` that were sent by, or to, a known searcher in `labels`, recording them in `audit` (if given).
+/// Returns the number of txs dropped.
+fn drop_searcher_txs(
+    txs: &mut Vec<Transaction>,
+    labels: &Labels,
+    audit: Option<&AuditLog>,
+    event_map: &H256Map<EventHistory>,
+) -> usize {
+    let num_txs = txs.len();
+    txs.retain(|tx| {
+        let is_searcher =
+            labels.is_searcher(&tx.from) || tx.to.as_ref().is_some_and(|to| labels.is_searcher(to));
+        if let (true, Some(audit), Some(event)) = (is_searcher, audit, event_map.get(&tx.hash)) {
+            audit.record(event, None, AuditDecision::SearcherTx, None);
+        }
+        !is_searcher
+    });
+    num_txs - txs.len()
+}
+
+/// Scans events from `event_source` for a single chain, saving simulated arbs to `write_db`.
+///
+/// Progress is added to `summary` as the scan goes, so it covers everything up to an error, too.
+pub async fn run(
+    params: ScanOptions,
+    chain: &ChainConfig,
+    event_source: &dyn EventSource,
     hindsight: &Hindsight,
     write_db: &ArbDatabase,
+    summary: &mut ScanSummary,
 ) -> Result<()> {
+    let params = params.resolve_start(write_db).await?;
     info!(
-        "scanning events starting at block={:?} timestamp={:?}",
-        params.block_start, params.timestamp_start
+        "[{}] scanning events starting at block={:?} timestamp={:?}",
+        chain.name, params.block_start, params.timestamp_start
     );
+    let ws_client = &hindsight.client;
 
     let mut event_params: EventHistoryParams = params.clone().into();
 
-    let filter_topics = uniswap_topics();
+    let filter_topics = hindsight.pool_adapters.swap_topics();
+    let metric_tags = MetricTags {
+        chain: chain.name.to_owned(),
+        chain_id: chain.chain_id,
+        run_label: params.run_label.to_owned(),
+    };
+    let mut metrics = MetricsBuffer::default();
+    // a scan that follows new events simulates blocks that may still be reorged
+    let following_head = params.block_end.is_none() && params.timestamp_end.is_none();
+    if let Some(send_arbs) = &params.send_arbs {
+        send_arbs.check_chain(chain, following_head)?;
+    }
+    let mut reorgs = ReorgWatch::default();
+    let lending_markets = if params.liquidation_borrowers.is_empty() {
+        vec![]
+    } else {
+        known_lending_markets(chain.chain_id)
+    };
+    if !params.liquidation_borrowers.is_empty() && lending_markets.is_empty() {
+        warn!(
+            "[{}] no known lending markets on this chain; not scanning for liquidations",
+            chain.name
+        );
+    }
+    // known searchers' txs aren't user orderflow, so they're not backrun
+    let labels = load_labels(chain.chain_id, Some(write_db), None).await?;
     /* ========================== event processing ====================================== */
     loop {
+        if !reorgs.is_empty() {
+            check_reorgs(&mut reorgs, ws_client, write_db).await;
+        }
+        let batch_size = match &hindsight.control {
+            Some(control) => control.batch_size(),
+            None => params.batch_size,
+        };
         // fetch events
-        let events = mevshare
-            .event_history(&event_history_url(), event_params.to_owned())
-            .await?;
+        let mut events = event_source.event_history(event_params.to_owned()).await?;
         // if the api returns 0 results, we've completely run out of events to process
         // so wait, then restart loop
         if events.len() == 0 {
             // sleep 12s to allow for new events to be indexed
-            std::thread::sleep(std::time::Duration::from_secs(12));
+            tokio::time::sleep(Duration::from_secs(12)).await;
             continue;
         }
 
@@ -83,12 +710,32 @@ pub async fn run(
             events.len(),
             events[0].timestamp
         );
+        if params.save_events {
+            write_db.write_events(&events).await?;
+        }
+        if params.replay_private {
+            let filled = fill_private_hints(ws_client, &hindsight.receipts, &mut events).await;
+            info!(
+                "filled in the logs of {} private events from their receipts",
+                filled
+            );
+        }
         // filter out irrelevant events
-        let events = filter_events_by_topic(&events, &filter_topics);
+        let fetched = events;
+        let mut events = filter_events_by_topic(&fetched, &filter_topics);
+        if let Some(address_lists) = &hindsight.address_lists {
+            let address_lists = address_lists.get();
+            events.retain(|event| address_lists.allows_event(event));
+        }
+        if let Some(audit) = &hindsight.audit {
+            audit_filtered(audit, &fetched, &events, &filter_topics);
+        }
         info!(
             "filtered for uniswap events. {} events ready to process.",
             events.len()
         );
+        summary.add_events(&events);
+        metrics.add_events(&events);
         // map events by hash for fast lookups
         let event_map = events
             .iter()
@@ -104,13 +751,18 @@ pub async fn run(
             let this_batch = events
                 .iter()
                 .skip(events_offset)
-                .take(params.batch_size)
+                .take(batch_size)
                 .map(|event| event.to_owned())
                 .collect::<Vec<EventHistory>>();
             events_offset += this_batch.len();
             // get txs for relevant events
             txs.append(&mut fetch_txs(&ws_client, &this_batch).await?);
         }
+        let num_searcher_txs =
+            drop_searcher_txs(&mut txs, &labels, hindsight.audit.as_ref(), &event_map);
+        if num_searcher_txs > 0 {
+            info!("skipped {} txs of known searchers", num_searcher_txs);
+        }
 
         /* ========================== batch-sized arb processing ========================
            Here, *at least* `batch_size` txs should be passed to `process_orderflow`.
@@ -118,10 +770,61 @@ pub async fn run(
            The last iteration will process only (remaining_txs % batch_size) txs, so it's
            most efficient when (txs.len() % batch_size == 0) and/or (txs.len() much greater than batch_size).
         */
-        hindsight
+        let processed = hindsight
             .to_owned()
-            .process_orderflow(&txs, params.batch_size, Some(write_db.clone()), event_map)
+            .process_orderflow(&txs, batch_size, Some(write_db.clone()), event_map)
             .await?;
+        summary.add_orderflow(txs.len(), &processed);
+        // send first; every block of delay makes an opportunity less likely to still be there
+        if let Some(send_arbs) = &params.send_arbs {
+            send_arbs.send(&processed.arbs, chain, ws_client).await;
+        }
+        if following_head {
+            reorgs.watch(&processed.arbs);
+        }
+        if let Some(sim_cache) = &hindsight.sim_cache {
+            let stats = sim_cache.stats();
+            info!(
+                hits = stats.hits,
+                misses = stats.misses,
+                hit_rate = stats.hit_rate(),
+                "sim cache"
+            );
+            if let Err(err) = sim_cache.save() {
+                log_error!("failed to save sim cache: {}", err);
+            }
+            if let Some(sink) = &params.metrics {
+                write_metrics(sink, &[sim_cache_line(&stats, &metric_tags)]).await;
+            }
+        }
+        if let Some(alerts) = &params.alerts {
+            alerts.send(&processed.arbs, &chain.name).await;
+        }
+        if let Some(paper) = &params.paper {
+            paper.record(&processed.arbs, chain, hindsight).await;
+        }
+        if !lending_markets.is_empty() {
+            scan_liquidations(
+                &txs,
+                &events,
+                &lending_markets,
+                &params,
+                hindsight,
+                write_db,
+            )
+            .await;
+        }
+        if let Some(audit) = &hindsight.audit {
+            if let Err(err) = audit.save() {
+                log_error!("failed to save audit records: {}", err);
+            }
+        }
+        if let Some(sink) = &params.metrics {
+            metrics.add_orderflow(&processed);
+            let mut lines = metrics.drain_complete(&metric_tags);
+            lines.push(progress_line(summary, &metric_tags));
+            write_metrics(sink, &lines).await;
+        }
         info!("simulated arbs for {} transactions", txs.len());
         info!("offset: {:?}", event_params.offset);
 
@@ -130,11 +833,294 @@ pub async fn run(
         if events.len() < event_params.limit.unwrap_or(500) as usize {
             if params.block_end.is_some() || params.timestamp_end.is_some() {
                 // if we're processing a specific block range, we're done
+                if let Some(sink) = &params.metrics {
+                    write_metrics(sink, &metrics.drain_all(&metric_tags)).await;
+                }
                 break;
             }
             // sleep 12s to allow for new events to be indexed
-            std::thread::sleep(std::time::Duration::from_secs(12));
+            tokio::time::sleep(Duration::from_secs(12)).await;
         }
     }
     Ok(())
 }
+
+/// Simulates the liquidations that each of `txs` made possible among `params.liquidation_borrowers`' positions,
+/// and saves them to `write_db`. Txs that fail to simulate are logged & skipped.
+async fn scan_liquidations(
+    txs: &[Transaction],
+    events: &[EventHistory],
+    markets: &[Arc<dyn LendingMarket>],
+    params: &ScanOptions,
+    hindsight: &Hindsight,
+    write_db: &ArbDatabase,
+) {
+    let mut liquidations = vec![];
+    for tx in txs {
+        let event = match events.iter().find(|event| event.hint.hash == tx.hash) {
+            Some(event) => event,
+            None => continue,
+        };
+        match simulate_liquidations(
+            &hindsight.client,
+            &hindsight.forks,
+            tx,
+            event,
+            markets,
+            &params.liquidation_borrowers,
+            &hindsight.quote_assets,
+        )
+        .await
+        {
+            Ok(found) => liquidations.extend(found.into_iter().map(|mut liquidation| {
+                liquidation.chain_id = hindsight.chain_id;
+                liquidation.run_label = params.run_label.to_owned();
+                liquidation
+            })),
+            Err(err) => log_error!("failed to simulate liquidations for {:?}: {}", tx.hash, err),
+        }
+    }
+    if liquidations.is_empty() {
+        return;
+    }
+    info!("found {} liquidations", liquidations.len());
+    if let Err(err) = write_db.write_liquidations(&liquidations).await {
+        log_error!("failed to save liquidations: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::data::{arbs::ArbFilterParams, MemoryStore, MongoConfig};
+    use crate::interfaces::SimArbResultBatch;
+    use crate::mock::MockChain;
+    use crate::sim::events::UniswapV2Swap;
+    use async_trait::async_trait;
+    use ethers::abi::{encode, Token};
+    use ethers::contract::EthEvent;
+    use ethers::types::{Block, TransactionReceipt};
+    use ethers::utils::id;
+    use mev_share_sse::EventTransactionLog;
+
+    /// Events served from memory, paged like the events API.
+    struct MemoryEvents(Vec<EventHistory>);
+
+    #[async_trait]
+    impl EventSource for MemoryEvents {
+        async fn event_history(&self, params: EventHistoryParams) -> Result<Vec<EventHistory>> {
+            Ok(self
+                .0
+                .iter()
+                .skip(params.offset.unwrap_or_default() as usize)
+                .take(params.limit.unwrap_or(500) as usize)
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[test]
+    fn it_summarizes_scans() {
+        let mut summary = ScanSummary::default();
+        assert!(summary
+            .message("mainnet", None, None)
+            .contains("no events processed"));
+
+        let profitable = SimArbResultBatch::test_example();
+        let mut unprofitable = SimArbResultBatch::test_example();
+        unprofitable.max_profit = U256::zero();
+        summary.add_orderflow(
+            3,
+            &ProcessedOrderflow {
+                arbs: vec![profitable.to_owned(), unprofitable],
+                num_failed: 1,
+                failures: vec![],
+            },
+        );
+        summary.first_event = Some((100, 1_700_000_000));
+        summary.last_event = Some((200, 1_700_001_200));
+        let message = summary.message("mainnet", None, Some(&anyhow::format_err!("rpc down")));
+        assert!(message.starts_with("hindsight: scan FAILED on mainnet: rpc down"));
+        assert!(message.contains("blocks 100..=200"));
+        assert!(message.contains("3 txs, 2 arbs (1 profitable), 1 failed simulations"));
+        assert!(message.contains(&format!(
+            "profit: {:.6} {:?}",
+            to_decimal(profitable.max_profit, profitable.profit_decimals),
+            profitable.profit_token
+        )));
+
+        let chain = Config::load_from(|key| match key {
+            "RPC_URL_WS" => Some("ws://127.0.0.1:8545".to_owned()),
+            "MONGO_URL" => Some("mongodb://localhost:27017".to_owned()),
+            _ => None,
+        })
+        .unwrap()
+        .chains[0]
+            .to_owned();
+        let summary_json = summary.to_json(&chain, None);
+        assert_eq!(summary_json["chainId"], json!(1));
+        assert_eq!(
+            summary_json["lastEvent"],
+            json!({ "block": 200, "timestamp": 1_700_001_200 })
+        );
+        assert_eq!(summary_json["numProfitable"], json!(1));
+        assert_eq!(
+            summary_json["profits"][0]["profitToken"],
+            json!(profitable.profit_token)
+        );
+        assert!(summary_json["error"].is_null());
+    }
+
+    fn test_options() -> ScanOptions {
+        ScanOptions {
+            batch_size: 4,
+            auto_tune: None,
+            block_start: Some(17_000_000),
+            block_end: None,
+            timestamp_start: None,
+            timestamp_end: None,
+            db_engine: DbEngine::Mongo(MongoConfig {
+                url: "mongodb://localhost:27017".to_owned(),
+                tls_ca_file_path: None,
+                namespace: "hindsight".to_owned(),
+            }),
+            run_label: Some("nightly".to_owned()),
+            control_socket: None,
+            address_lists: None,
+            save_events: false,
+            replay_private: false,
+            notify: vec![],
+            metrics: None,
+            results_ws: None,
+            alerts: None,
+            paper: None,
+            send_arbs: None,
+            audit: None,
+            sim_budget: SimBudget::default(),
+            tx_limits: Default::default(),
+            sim_cache: false,
+            output: OutputFormat::Table,
+            liquidation_borrowers: vec![],
+            max_hops: None,
+        }
+    }
+
+    #[test]
+    fn it_announces_scans() {
+        let options = test_options();
+        assert_eq!(
+            options.start_message("base"),
+            "hindsight: scan started on base from block 17000000, following new events (run nightly)"
+        );
+        let options = ScanOptions {
+            block_start: None,
+            block_end: Some(17_100_000),
+            run_label: None,
+            ..options
+        };
+        assert_eq!(
+            options.start_message("mainnet"),
+            "hindsight: scan started on mainnet from the last saved arb, until block 17100000"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_block_and_timestamp_starts() {
+        let db: ArbDatabase = Arc::new(MemoryStore::default());
+        let options = ScanOptions {
+            timestamp_start: Some(1_700_000_000),
+            ..test_options()
+        };
+        assert!(options.resolve_start(&db).await.is_err());
+        let options = test_options().resolve_start(&db).await.unwrap();
+        assert_eq!(options.block_start, Some(17_000_000));
+        assert_eq!(options.timestamp_start, Some(1));
+    }
+
+    #[tokio::test]
+    async fn it_scans_events_from_memory_into_a_store() -> Result<()> {
+        let pool = Address::from_low_u64_be(0xa);
+        let event = |hash: u64, logs: Vec<EventTransactionLog>| EventHistory {
+            block: 17_000_001,
+            timestamp: 1_700_000_000,
+            hint: mev_share_sse::Hint {
+                txs: vec![],
+                hash: H256::from_low_u64_be(hash),
+                logs,
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        };
+        // a swap on a pair that trades no quote asset, so it has nothing to backrun, and an event without swaps
+        let swap = event(
+            1,
+            vec![EventTransactionLog {
+                address: pool,
+                topics: vec![UniswapV2Swap::signature()],
+                data: Default::default(),
+            }],
+        );
+        let events = MemoryEvents(vec![swap.clone(), event(2, vec![])]);
+        let tx = Transaction {
+            hash: swap.hint.hash,
+            block_number: Some(swap.block.into()),
+            ..Default::default()
+        };
+        let token = |token: u64| -> ethers::types::Bytes {
+            encode(&[Token::Address(Address::from_low_u64_be(token))]).into()
+        };
+        let client = MockChain::new(1)
+            .with_block(Block {
+                number: Some((swap.block - 1).into()),
+                hash: Some(H256::from_low_u64_be(swap.block - 1)),
+                ..Default::default()
+            })
+            .with_transaction(tx.clone())
+            .with_receipt(TransactionReceipt {
+                transaction_hash: tx.hash,
+                block_number: tx.block_number,
+                ..Default::default()
+            })
+            .with_call(pool, id("token0()").to_vec().into(), token(0x70))
+            .with_call(pool, id("token1()").to_vec().into(), token(0x71))
+            .serve()
+            .await?;
+        let store = MemoryStore::default();
+        let db: ArbDatabase = Arc::new(store.clone());
+        let hindsight = Hindsight::builder()
+            .provider(client)
+            .store(db.clone())
+            .run_tags(1, Some("mock".to_owned()))
+            .build()
+            .await?;
+        let chain = Config::load_from(|key| match key {
+            "RPC_URL_WS" => Some("ws://127.0.0.1:8545".to_owned()),
+            "MONGO_URL" => Some("mongodb://localhost:27017".to_owned()),
+            _ => None,
+        })?
+        .chains[0]
+            .to_owned();
+        let options = ScanOptions {
+            block_end: Some(17_000_010),
+            run_label: Some("mock".to_owned()),
+            ..test_options()
+        };
+
+        // a bounded scan stops after the last (partial) page of events
+        let mut summary = ScanSummary::default();
+        run(options, &chain, &events, &hindsight, &db, &mut summary).await?;
+        assert_eq!(summary.num_events, 1);
+        assert_eq!(summary.num_txs, 1);
+        assert_eq!(summary.num_arbs, 1);
+        assert_eq!(summary.num_failed, 0);
+        let arbs = store
+            .read_arbs(&ArbFilterParams::none(), None, None)
+            .await?;
+        assert_eq!(arbs.len(), 1);
+        assert_eq!(arbs[0].event.hint.hash, swap.hint.hash);
+        assert!(arbs[0].max_profit.is_zero());
+        assert_eq!(arbs[0].run_label.as_deref(), Some("mock"));
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{
+    commands::OutputFormat, config::known_wrapped_native, hindsight::Hindsight, info,
+    interfaces::SimArbResultBatch, Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256, U256},
+    utils::{format_ether, Anvil, AnvilInstance},
+};
+use mev_share_sse::EventHistory;
+use serde::Serialize;
+use serde_json::json;
+
+/// MEV-Share event of a mainnet tx (block 17637019) with a known WETH backrun; also a golden fixture.
+const FIXTURE_EVENT: &str = include_str!("../../fixtures/golden/univ3_weth_juicy/event.json");
+/// Chain that the bundled fixture's tx landed on.
+pub const FIXTURE_CHAIN_ID: u64 = 1;
+
+/// The bundled fixture's event.
+pub fn fixture_event() -> Result<EventHistory> {
+    Ok(serde_json::from_str(FIXTURE_EVENT)?)
+}
+
+/// Starts an Anvil node forked from `rpc_url` at `block`, so the self-test runs against a pinned copy of the chain.
+/// Requires Foundry's `anvil` on the `PATH`; the node stops when the returned instance is dropped.
+pub fn fork_with_anvil(rpc_url: &str, block: u64) -> Result<AnvilInstance> {
+    // `Anvil::spawn` panics if it can't start anvil, so check it's installed first
+    std::process::Command::new("anvil")
+        .arg("--version")
+        .output()
+        .map_err(|err| {
+            anyhow::format_err!(
+                "--anvil requires anvil (https://getfoundry.sh) on the PATH: {}",
+                err
+            )
+        })?;
+    info!("forking {} at block {} with anvil", rpc_url, block);
+    Ok(Anvil::new().fork(rpc_url).fork_block_number(block).spawn())
+}
+
+/// Simulates backruns of `event`'s tx with `hindsight`, returning the most profitable arb found.
+pub async fn simulate_event(
+    hindsight: Hindsight,
+    event: EventHistory,
+) -> Result<SimArbResultBatch> {
+    let tx = hindsight
+        .client
+        .get_transaction(event.hint.hash)
+        .await?
+        .ok_or(anyhow::format_err!("tx {:?} not found", event.hint.hash))?;
+    let event_map = [(event.hint.hash, event)].into_iter().collect();
+    let processed = hindsight
+        .process_orderflow(&vec![tx], 1, None, event_map)
+        .await?;
+    processed.arbs.into_iter().next().ok_or(anyhow::format_err!(
+        "no arb found ({:?})",
+        processed.failures
+    ))
+}
+
+/// Result of a passing self-test.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelftestReport {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub max_profit: U256,
+    pub profit_token: Address,
+}
+
+/// Checks that `arb` is the backrun expected of the bundled fixture: a profitable one that ends in WETH.
+pub fn check_arb(arb: &SimArbResultBatch) -> Result<()> {
+    let weth = known_wrapped_native(FIXTURE_CHAIN_ID).expect("mainnet has WETH");
+    if arb.profit_token != weth {
+        return Err(anyhow::format_err!(
+            "expected an arb in WETH ({:?}), found one in {:?}",
+            weth,
+            arb.profit_token
+        ));
+    }
+    if arb.max_profit.is_zero() {
+        return Err(anyhow::format_err!(
+            "expected a profitable arb, found 0 profit"
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the full pipeline on the bundled fixture with `hindsight`, failing unless it finds the expected arb.
+pub async fn run(hindsight: Hindsight, format: OutputFormat) -> Result<()> {
+    let event = fixture_event()?;
+    let rpc_chain_id = hindsight.client.get_chainid().await?.as_u64();
+    if rpc_chain_id != FIXTURE_CHAIN_ID {
+        return Err(anyhow::format_err!(
+            "the self-test replays a mainnet tx, but the node is on chain {}",
+            rpc_chain_id
+        ));
+    }
+    let (tx_hash, block) = (event.hint.hash, event.block);
+    let arb = simulate_event(hindsight, event)
+        .await
+        .map_err(|err| anyhow::format_err!("self-test failed: {:#}", err))?;
+    check_arb(&arb).map_err(|err| anyhow::format_err!("self-test failed: {}", err))?;
+    let report = SelftestReport {
+        tx_hash,
+        block,
+        max_profit: arb.max_profit,
+        profit_token: arb.profit_token,
+    };
+    match format {
+        OutputFormat::Table => println!(
+            "self-test passed: found a {} WETH backrun of {:?} (block {})",
+            format_ether(report.max_profit),
+            report.tx_hash,
+            report.block
+        ),
+        OutputFormat::Json => println!("{}", json!(report)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_bundles_the_fixture_event() -> Result<()> {
+        let event = fixture_event()?;
+        assert_eq!(event.block, 17637019);
+        assert_eq!(event.hint.logs.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn it_checks_the_fixture_arb() {
+        let weth = known_wrapped_native(FIXTURE_CHAIN_ID).unwrap();
+        let mut arb = SimArbResultBatch::test_example();
+        arb.profit_token = weth;
+        arb.max_profit = U256::from(1_000_000);
+        assert!(check_arb(&arb).is_ok());
+        arb.max_profit = U256::zero();
+        assert!(check_arb(&arb).is_err());
+        arb.max_profit = U256::from(1_000_000);
+        arb.profit_token = Address::from_low_u64_be(1);
+        assert!(check_arb(&arb).is_err());
+    }
+}
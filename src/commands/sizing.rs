@@ -0,0 +1,23 @@
+use crate::analysis::{
+    read_opportunities,
+    sizing::{render_table, size_distributions, SizingGroup},
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::Result;
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    group: SizingGroup,
+    limit: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let distributions = size_distributions(&arbs, group, limit);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&distributions)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&distributions)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use crate::analysis::{
+    read_opportunities,
+    stats::{bucket_stats, render_table, StatsOptions},
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::Result;
+
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    options: StatsOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let stats = bucket_stats(&arbs, &options);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&stats)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+    Ok(())
+}
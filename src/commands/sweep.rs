@@ -0,0 +1,235 @@
+use crate::analysis::{
+    read_all_arbs,
+    sweep::{coverage, render_table, BlockSweep, SweepReport},
+};
+use crate::commands::OutputFormat;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::hindsight::Hindsight;
+use crate::interfaces::SimArbResultBatch;
+use crate::sim::{
+    evm::{commit_tx, run_blocking},
+    fork::{fork_factory, fork_from, ForkCache},
+};
+use crate::{error::HindsightError, info, log_error, Error, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Transaction, TransactionReceipt, H256},
+};
+use futures::future::join_all;
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+use revm::EVM;
+use rusty_sando::{prelude::fork_db::ForkDB, types::BlockInfo};
+
+/// Run label that sweep results are tagged with, unless another is given.
+pub const DEFAULT_SWEEP_LABEL: &str = "sweep";
+
+/// Blocks to sweep, and how many of a block's price-moving txs are backrun at once.
+#[derive(Clone, Debug)]
+pub struct SweepOptions {
+    pub block_start: u64,
+    pub block_end: u64,
+    pub batch_size: usize,
+}
+
+/// Whether `receipt` is of a successful tx that swapped on a pool of a supported AMM, moving its price.
+fn is_price_moving(receipt: &TransactionReceipt, swap_topics: &[H256]) -> bool {
+    receipt.status == Some(1.into())
+        && receipt.logs.iter().any(|log| {
+            log.topics
+                .first()
+                .is_some_and(|topic| swap_topics.contains(topic))
+        })
+}
+
+/// An event for `tx` as if its originator had shared every log, so the sim backruns each pool it swapped on.
+fn full_event(block: u64, timestamp: u64, receipt: &TransactionReceipt) -> EventHistory {
+    EventHistory {
+        block,
+        timestamp,
+        hint: Hint {
+            txs: vec![],
+            hash: receipt.transaction_hash,
+            logs: receipt
+                .logs
+                .iter()
+                .map(|log| EventTransactionLog {
+                    address: log.address,
+                    topics: log.topics.to_owned(),
+                    data: log.data.to_owned(),
+                })
+                .collect(),
+            gas_used: None,
+            mev_gas_price: None,
+        },
+    }
+}
+
+/// Replays `txs` in order on `evm`, returning the state before each tx in `snapshot_before`
+/// (by index into `txs`) and the number of txs that failed to replay, which are skipped.
+async fn replay(
+    mut evm: EVM<ForkDB>,
+    txs: Vec<Transaction>,
+    snapshot_before: Vec<bool>,
+) -> Result<(Vec<(usize, EVM<ForkDB>)>, u64)> {
+    run_blocking(move || {
+        let mut snapshots = vec![];
+        let mut num_failed = 0;
+        for (idx, tx) in txs.into_iter().enumerate() {
+            if snapshot_before[idx] {
+                snapshots.push((idx, evm.clone()));
+            }
+            let tx_hash = tx.hash;
+            if let Err(err) = commit_tx(&mut evm, tx) {
+                log_error!("failed to replay {:?}: {}", tx_hash, err);
+                num_failed += 1;
+            }
+        }
+        Ok((snapshots, num_failed))
+    })
+    .await
+}
+
+/// Replays block `block_num` tx by tx on a fork of its parent, and backruns each price-moving tx
+/// on the state it left, as if it had landed right after the txs before it. Its arbs are saved to `db`.
+async fn sweep_block(
+    hindsight: &Hindsight,
+    db: &ArbDatabase,
+    block_num: u64,
+    batch_size: usize,
+) -> Result<(BlockSweep, Vec<SimArbResultBatch>)> {
+    let client = &hindsight.client;
+    let block = client
+        .get_block_with_txs(block_num)
+        .await?
+        .ok_or::<Error>(HindsightError::BlockNotFound(block_num).into())?;
+    let block_info = BlockInfo {
+        number: block_num.into(),
+        timestamp: block.timestamp,
+        base_fee: block.base_fee_per_gas.unwrap_or(1_000_000_000.into()),
+    };
+    let parent_info = BlockInfo {
+        number: (block_num - 1).into(),
+        ..block_info.clone()
+    };
+    let mut sweep = BlockSweep {
+        block: block_num,
+        num_txs: block.transactions.len() as u64,
+        ..Default::default()
+    };
+
+    let tx_hashes = block
+        .transactions
+        .iter()
+        .map(|tx| tx.hash)
+        .collect::<Vec<_>>();
+    hindsight.receipts.prefetch(client, &tx_hashes).await;
+    let swap_topics = hindsight.pool_adapters.swap_topics();
+    let mut events = vec![];
+    for tx in &block.transactions {
+        let event = match hindsight.receipts.get(client, tx.hash).await? {
+            Some(receipt) if is_price_moving(&receipt, &swap_topics) => {
+                Some(full_event(block_num, block.timestamp.as_u64(), &receipt))
+            }
+            _ => None,
+        };
+        events.push(event);
+    }
+    sweep.num_price_moving = events.iter().flatten().count() as u64;
+    if sweep.num_price_moving == 0 {
+        return Ok((sweep, vec![]));
+    }
+
+    let factory = fork_factory(client, &parent_info).await?;
+    let (snapshots, num_failed) = replay(
+        fork_from(&factory, &block_info),
+        block.transactions.to_owned(),
+        events.iter().map(Option::is_some).collect(),
+    )
+    .await?;
+    sweep.num_replay_failures = num_failed;
+
+    let mut arbs = vec![];
+    let mut jobs = snapshots.into_iter().peekable();
+    while jobs.peek().is_some() {
+        let batch = jobs.by_ref().take(batch_size).collect::<Vec<_>>();
+        let results = join_all(batch.into_iter().map(|(idx, evm)| {
+            let tx = block.transactions[idx].to_owned();
+            let event = events[idx]
+                .to_owned()
+                .expect("snapshots are of price-moving txs");
+            // every fork of the tx's sims starts from the state the txs before it left
+            let hindsight = Hindsight {
+                forks: ForkCache::pinned(evm),
+                ..hindsight.to_owned()
+            };
+            let event_map = [(tx.hash, event)].into_iter().collect();
+            async move {
+                hindsight
+                    .process_orderflow(&vec![tx], 1, Some(db.to_owned()), event_map)
+                    .await
+            }
+        }))
+        .await;
+        for result in results {
+            match result {
+                Ok(processed) => {
+                    sweep.num_sim_failures += processed.num_failed as u64;
+                    arbs.extend(processed.arbs);
+                }
+                Err(err) => {
+                    sweep.num_sim_failures += 1;
+                    log_error!("failed to backrun a tx of block {}: {}", block_num, err);
+                }
+            }
+        }
+    }
+    sweep.num_profitable = arbs.iter().filter(|arb| !arb.max_profit.is_zero()).count() as u64;
+    Ok((sweep, arbs))
+}
+
+/// Sweeps every block from `options.block_start` to `options.block_end`: replays each block's txs in order,
+/// and backruns every price-moving tx with the standard two-pool arb, whether or not it was shared on MEV-Share.
+/// Arbs are saved to `db` with `hindsight`'s run label.
+///
+/// Prints what each block yielded, and how much of the sweep's profit the arbs that other runs found
+/// from hints (read from `db` with `params`) covered.
+pub async fn run(
+    hindsight: &Hindsight,
+    db: &ArbDatabase,
+    params: ArbFilterParams,
+    options: SweepOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut report = SweepReport::default();
+    let mut sweep_arbs = vec![];
+    for block_num in options.block_start..=options.block_end {
+        info!("sweeping block {}", block_num);
+        match sweep_block(hindsight, db, block_num, options.batch_size.max(1)).await {
+            Ok((sweep, mut arbs)) => {
+                info!(
+                    "block {}: {} of {} price-moving txs were profitable",
+                    block_num, sweep.num_profitable, sweep.num_price_moving
+                );
+                report.blocks.push(sweep);
+                sweep_arbs.append(&mut arbs);
+            }
+            Err(err) => log_error!("failed to sweep block {}: {}", block_num, err),
+        }
+    }
+
+    let mut hinted = read_all_arbs(
+        db,
+        &ArbFilterParams {
+            run_label: None,
+            ..params
+        },
+    )
+    .await?;
+    hinted.retain(|arb| arb.run_label != hindsight.run_label);
+    report.coverage = coverage(&sweep_arbs, &hinted);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&report)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use crate::commands::OutputFormat;
+use crate::data::tokens::TokenMetadataStore;
+use crate::interfaces::{QuoteAsset, TokenTax};
+use crate::sim::{pools::PoolAdapters, tax::measure};
+use crate::util::WsClient;
+use crate::{info, log_error, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+};
+
+fn percent(tax: Option<f64>) -> String {
+    tax.map(|tax| format!("{:.2}%", tax * 100.0))
+        .unwrap_or("reverts".to_owned())
+}
+
+fn limit(amount: Option<U256>) -> String {
+    amount
+        .map(|amount| amount.to_string())
+        .unwrap_or("-".to_owned())
+}
+
+fn render_table(taxes: &[TokenTax], store: &TokenMetadataStore) -> String {
+    let mut table = format!(
+        "{:<12} {:<44} {:>9} {:>9} {:>26} {:>26} {:>10}\n",
+        "token", "pool", "buy tax", "sell tax", "max buy", "max sell", "block"
+    );
+    for tax in taxes {
+        table.push_str(&format!(
+            "{:<12} {:<44} {:>9} {:>9} {:>26} {:>26} {:>10}\n",
+            store.symbol(&tax.token),
+            format!("{:?}", tax.pool),
+            percent(Some(tax.buy_tax)),
+            percent(tax.sell_tax),
+            limit(tax.max_buy),
+            limit(tax.max_sell),
+            tax.block
+        ));
+    }
+    table
+}
+
+/// Measures the taxes & transaction limits of each of `tokens` at the end of `block` (the latest block by default),
+/// saves them to the chain's token store (so scans screen those tokens by them), and prints them.
+///
+/// Tokens that were measured before are printed from the store, unless `refresh` is set.
+/// Tokens that can't be measured are logged and skipped.
+pub async fn run(
+    client: &WsClient,
+    chain_id: u64,
+    quote_assets: &[QuoteAsset],
+    tokens: &[Address],
+    block: Option<u64>,
+    refresh: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut store = TokenMetadataStore::load(chain_id)?;
+    store.fetch_missing(client, tokens).await?;
+    let block = match block {
+        Some(block) => block,
+        None => client.get_block_number().await?.as_u64(),
+    };
+    // the CLI only simulates the built-in AMMs
+    let adapters = PoolAdapters::default();
+    let mut measured = vec![];
+    for token in tokens {
+        if !refresh && store.tax(token).is_some() {
+            continue;
+        }
+        info!(
+            "measuring taxes of {} at block {}",
+            store.symbol(token),
+            block
+        );
+        match measure(client, &adapters, quote_assets, *token, block).await {
+            Ok(tax) => measured.push(tax),
+            Err(err) => log_error!("failed to measure taxes of {:?}: {}", token, err),
+        }
+    }
+    store.insert_taxes(measured)?;
+
+    let taxes = tokens
+        .iter()
+        .filter_map(|token| store.tax(token).cloned())
+        .collect::<Vec<_>>();
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&taxes, &store)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&taxes)?),
+    }
+    Ok(())
+}
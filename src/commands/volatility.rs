@@ -0,0 +1,36 @@
+use crate::analysis::{
+    read_opportunities,
+    stats::BucketSize,
+    volatility::{render_table, volatility_reports},
+};
+use crate::commands::OutputFormat;
+use crate::data::{
+    arbs::{ArbDatabase, ArbFilterParams},
+    prices::PriceStore,
+};
+use crate::util::WsClient;
+use crate::Result;
+use ethers::types::Address;
+
+/// Reads the ETH/USD `feed` at the block of every arb matching `params`, then reports how bucketed profit correlates with
+/// the price's volatility.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    client: &WsClient,
+    chain_id: u64,
+    feed: Address,
+    bucket_size: BucketSize,
+    format: OutputFormat,
+) -> Result<()> {
+    let arbs = read_opportunities(read_db, &params).await?;
+    let mut store = PriceStore::load(chain_id, feed)?;
+    let blocks = arbs.iter().map(|arb| arb.event.block).collect::<Vec<_>>();
+    store.fetch_missing(client, &blocks).await?;
+    let reports = volatility_reports(&arbs, store.prices(), bucket_size);
+    match format {
+        OutputFormat::Table => print!("{}", render_table(&reports)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+    }
+    Ok(())
+}
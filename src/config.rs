@@ -1,5 +1,40 @@
-use crate::debug;
-use std::{env, path::PathBuf};
+use crate::{
+    data::db::DB_ENGINE_NAMES,
+    debug,
+    event_history::FLASHBOTS_EVENTS_API_URL,
+    interfaces::{PoolVariant, QuoteAsset},
+    notify::{AlertTemplate, Destination},
+    signer::SignerConfig,
+    sim::budget::{
+        SimBudget, TxSimLimits, DEFAULT_MAX_TOKEN_TAX, DEFAULT_MIN_IMPROVEMENT,
+        DEFAULT_MIN_PRICE_GAP,
+    },
+    Result,
+};
+use ethers::types::Address;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Default DB namespace, used by mainnet so existing deployments keep reading the same data.
+pub const DEFAULT_DB_NAMESPACE: &'static str = "hindsight";
+/// Config file that profiles are read from, unless `HINDSIGHT_CONFIG` is set.
+pub const DEFAULT_CONFIG_FILE: &'static str = "hindsight.toml";
+/// Mainnet factory of Uniswap V2 pairs.
+pub const UNISWAP_V2_FACTORY: &'static str = "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f";
+/// Mainnet factory of Sushiswap (V2 clone) pairs.
+pub const SUSHISWAP_FACTORY: &'static str = "0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac";
+/// Mainnet factory of Uniswap V3 pools.
+pub const UNISWAP_V3_FACTORY: &'static str = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+/// Bundle endpoints of the Flashbots relay, beaverbuild & rsync, that `scan --send-arbs` submits to on mainnet.
+pub const MAINNET_RELAY_URLS: [&str; 3] = [
+    "https://relay.flashbots.net",
+    "https://rpc.beaverbuild.org",
+    "https://rsync-builder.xyz",
+];
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -7,20 +42,1302 @@ pub struct Config {
     pub mongo_url: String,
     pub postgres_url: Option<String>,
     pub tls_ca_file_mongo: Option<PathBuf>,
+    /// Chains to scan. Always contains at least one chain.
+    pub chains: Vec<ChainConfig>,
+    /// Signer used to authenticate with relays, sign `scan --send-arbs`'s txs and deploy the executor. Load it with
+    /// `signer::Signer::load`.
+    pub auth_signer: Option<SignerConfig>,
+    /// DB engine ("mongo" or "postgres") used when a command doesn't specify one.
+    pub default_db: Option<String>,
+    /// Defaults for `scan` args that weren't given on the command line.
+    pub scan: ScanDefaults,
+    /// Maximum number of forks & sims running at once (see `sim::budget::SimBudget`). Defaults to `DEFAULT_SIM_PERMITS`.
+    pub sim_permits: Option<usize>,
+    /// Maximum number of EVM executions per tx (see `sim::budget::TxSimLimits`). Uncapped if unset.
+    pub tx_max_sims: Option<usize>,
+    /// Maximum seconds spent simulating each tx. Uncapped if unset.
+    pub tx_max_sim_secs: Option<f64>,
+    /// Relative profit gain below which a search stops going deeper. Defaults to `DEFAULT_MIN_IMPROVEMENT`; 0 disables.
+    pub sim_min_improvement: Option<f64>,
+    /// Price gap below which a route isn't simulated. Defaults to `DEFAULT_MIN_PRICE_GAP`; 0 simulates every route.
+    pub sim_min_price_gap: Option<f64>,
+    /// Share of a probe that a token's round trip may lose before it's deemed unsafe. Defaults to `DEFAULT_MAX_TOKEN_TAX`; 0 disables the screen.
+    pub sim_max_token_tax: Option<f64>,
+    /// Share of each backrun's profit paid to the block's coinbase in its bundle. Unset (or 0) pays nothing.
+    pub sim_coinbase_share: Option<f64>,
+    /// Webhook (e.g. Slack or Discord) that notifications are posted to.
+    pub notify_url: Option<String>,
+    /// InfluxDB line-protocol write endpoint that scans report per-block metrics to.
+    pub metrics_url: Option<String>,
+    /// API token for `metrics_url` (InfluxDB v2).
+    pub metrics_token: Option<String>,
+    /// Webhooks that high-profit arbs are alerted to.
+    pub alert_urls: Vec<String>,
+    /// Payload posted to `alert_urls` instead of the default message.
+    pub alert_template: Option<AlertTemplate>,
+    /// Telegram & Discord chats that bots post notifications & alerts to.
+    pub chat_bots: Vec<Destination>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanDefaults {
+    pub batch_size: Option<usize>,
+}
+
+/// Settings for a single chain that hindsight scans.
+#[derive(Clone, Debug)]
+pub struct ChainConfig {
+    /// Short name of the chain, used to look up chain-specific env vars (e.g. `RPC_URL_WS_BASE`).
+    pub name: String,
+    pub chain_id: u64,
+    pub rpc_url_ws: String,
+    /// Base URL of the MEV-Share events API that serves this chain.
+    pub events_api_url: String,
+    /// Name of the DB (mongo) or table (postgres) that this chain's arbs are stored in.
+    pub db_namespace: String,
+    /// Wrapped native token (e.g. WETH) on this chain.
+    pub wrapped_native: Address,
+    /// Tokens that arbs are searched against, in order of priority. When a user's trade
+    /// involves more than one, the first is used.
+    pub quote_assets: Vec<QuoteAsset>,
+    /// Executor contract deployed with `deploy-executor`, if any.
+    pub executor: Option<Address>,
+    /// Relays & builders that `scan --send-arbs` submits bundles to. Mainnet defaults to `MAINNET_RELAY_URLS`.
+    pub relay_urls: Vec<String>,
+    /// Factories whose pools the `indexer` walks.
+    pub dex_factories: Vec<DexFactory>,
+}
+
+/// A DEX factory whose deployment events (`PairCreated` or `PoolCreated`) the `indexer` walks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DexFactory {
+    pub factory: Address,
+    /// Variant of the pools it deploys; UniswapV2 clones are `UniswapV2`.
+    pub variant: PoolVariant,
+    /// Block the factory was deployed in, where indexing starts.
+    pub start_block: u64,
+}
+
+/// Every problem found while loading the config.
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid config ({} problems):", self.problems.len())?;
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Returns the chain id for well-known chain names.
+fn known_chain_id(name: &str) -> Option<u64> {
+    match name {
+        "mainnet" | "ethereum" => Some(1),
+        "goerli" => Some(5),
+        "optimism" => Some(10),
+        "polygon" => Some(137),
+        "base" => Some(8453),
+        "arbitrum" => Some(42161),
+        "holesky" => Some(17000),
+        "sepolia" => Some(11155111),
+        _ => None,
+    }
+}
+
+/// Returns the canonical wrapped native token for well-known chain ids.
+pub fn known_wrapped_native(chain_id: u64) -> Option<Address> {
+    let address = match chain_id {
+        1 => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        5 => "0xB4FBF271143F4FBf7B91A5ded31805e42b2208d6",
+        10 | 8453 => "0x4200000000000000000000000000000000000006",
+        137 => "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270",
+        17000 => "0x94373a4919B3240D86eA41593D5eBa789FEF3848",
+        42161 => "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1",
+        11155111 => "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14",
+        _ => return None,
+    };
+    address.parse().ok()
+}
+
+/// Returns the Chainlink ETH/USD price feed for well-known chain ids.
+pub fn known_eth_usd_feed(chain_id: u64) -> Option<Address> {
+    let address = match chain_id {
+        1 => "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419",
+        10 => "0x13e3Ee699D1909E989722E753853AE30b17e08c5",
+        8453 => "0x71041dddad3595F9CEd3DcCFBe3D1F4b0a16Bb70",
+        42161 => "0x639Fe6ab55C921f74e7fac1ee960C0B6293ba612",
+        _ => return None,
+    };
+    address.parse().ok()
+}
+
+/// Returns the name of the DEX that deployed pools from `factory`, for well-known factories.
+pub fn known_dex_name(factory: Address) -> Option<&'static str> {
+    [
+        (UNISWAP_V2_FACTORY, "Uniswap"),
+        (SUSHISWAP_FACTORY, "Sushiswap"),
+        (UNISWAP_V3_FACTORY, "Uniswap"),
+    ]
+    .into_iter()
+    .find(|(address, _)| address.parse::<Address>().ok() == Some(factory))
+    .map(|(_, name)| name)
+}
+
+/// Returns the default quote assets for well-known chain ids, in order of priority.
+pub fn known_quote_assets(chain_id: u64) -> Vec<QuoteAsset> {
+    let asset = |address: Option<Address>, balance_slot: u64, decimals: u32, balance: u64| {
+        address.map(|address| QuoteAsset::new(address, balance_slot, decimals, balance))
+    };
+    let assets = match chain_id {
+        1 => vec![
+            // WETH; 420 matches the braindance contract's built-in balance
+            asset(known_wrapped_native(1), 3, 18, 420),
+            // USDC
+            asset(
+                "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".parse().ok(),
+                9,
+                6,
+                1_000_000,
+            ),
+            // USDT
+            asset(
+                "0xdAC17F958D2ee523a2206206994597C13D831ec7".parse().ok(),
+                2,
+                6,
+                1_000_000,
+            ),
+            // WBTC
+            asset(
+                "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599".parse().ok(),
+                0,
+                8,
+                20,
+            ),
+        ],
+        // WETH9 clones keep balances in slot 3; arbitrum's WETH is a proxy, so it isn't included
+        5 | 10 | 137 | 8453 | 17000 | 11155111 => {
+            vec![asset(known_wrapped_native(chain_id), 3, 18, 420)]
+        }
+        _ => vec![],
+    };
+    assets.into_iter().flatten().collect()
+}
+
+/// Returns the factories of the DEXes that the pool adapters trade on, for well-known chain ids.
+pub fn known_dex_factories(chain_id: u64) -> Vec<DexFactory> {
+    let factory = |address: &str, variant: PoolVariant, start_block: u64| {
+        address.parse().ok().map(|factory| DexFactory {
+            factory,
+            variant,
+            start_block,
+        })
+    };
+    let factories = match chain_id {
+        1 => vec![
+            factory(UNISWAP_V2_FACTORY, PoolVariant::UniswapV2, 10_000_835),
+            factory(SUSHISWAP_FACTORY, PoolVariant::UniswapV2, 10_794_229),
+            factory(UNISWAP_V3_FACTORY, PoolVariant::UniswapV3, 12_369_621),
+        ],
+        _ => vec![],
+    };
+    factories.into_iter().flatten().collect()
+}
+
+/// Parses a `DEX_FACTORIES_<CHAIN>` entry: `<v2|v3>:<factory address>:<deployment block>`.
+fn parse_dex_factory(entry: &str) -> Result<DexFactory, String> {
+    let parts = entry.trim().split(':').collect::<Vec<_>>();
+    let [variant, address, start_block] = parts[..] else {
+        return Err(format!(
+            "invalid DEX factory '{}'; expected <v2|v3>:<address>:<deployment block>",
+            entry
+        ));
+    };
+    let variant = match variant {
+        "v2" => PoolVariant::UniswapV2,
+        "v3" => PoolVariant::UniswapV3,
+        _ => {
+            return Err(format!(
+                "unknown pool variant '{}'; expected v2 or v3",
+                variant
+            ))
+        }
+    };
+    Ok(DexFactory {
+        factory: address
+            .parse()
+            .map_err(|err| format!("invalid address '{}' ({})", address, err))?,
+        variant,
+        start_block: start_block
+            .parse()
+            .map_err(|err| format!("invalid deployment block '{}' ({})", start_block, err))?,
+    })
+}
+
+/// Parses a `QUOTE_ASSETS_<CHAIN>` entry: either the address of a known quote asset, or
+/// `<address>:<balanceOf slot>:<decimals>:<starting balance (whole tokens)>`.
+fn parse_quote_asset(chain_id: u64, entry: &str) -> Result<QuoteAsset, String> {
+    let parts = entry.trim().split(':').collect::<Vec<_>>();
+    let address = parts[0]
+        .parse::<Address>()
+        .map_err(|err| format!("invalid address '{}' ({})", parts[0], err))?;
+    match parts[1..] {
+        [] => known_quote_assets(chain_id)
+            .into_iter()
+            .find(|asset| asset.address == address)
+            .ok_or(format!(
+                "unknown quote asset {:?}; specify it as <address>:<balance slot>:<decimals>:<starting balance>",
+                address
+            )),
+        [balance_slot, decimals, balance] => {
+            let parse_num = |name: &str, value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|err| format!("invalid {} '{}' ({})", name, value, err))
+            };
+            Ok(QuoteAsset::new(
+                address,
+                parse_num("balance slot", balance_slot)?,
+                parse_num("decimals", decimals)? as u32,
+                parse_num("starting balance", balance)?,
+            ))
+        }
+        _ => Err(format!(
+            "invalid quote asset '{}'; expected <address> or <address>:<balance slot>:<decimals>:<starting balance>",
+            entry
+        )),
+    }
+}
+
+fn chain_var_name(key: &str, chain_name: &str) -> String {
+    format!("{}_{}", key, chain_name.to_uppercase().replace('-', "_"))
+}
+
+/// Checks that `url` uses one of `schemes`, recording a problem under `name` if it doesn't.
+fn check_scheme(problems: &mut Vec<String>, name: &str, url: &str, schemes: &[&str]) {
+    if !schemes
+        .iter()
+        .any(|scheme| url.starts_with(&format!("{}://", scheme)))
+    {
+        problems.push(format!(
+            "{} must be a {} URL (got '{}')",
+            name,
+            schemes
+                .iter()
+                .map(|scheme| format!("{}://", scheme))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            url
+        ));
+    }
+}
+
+impl ChainConfig {
+    /// Env var that this chain's executor address is read from, e.g. `EXECUTOR_ADDRESS_MAINNET`.
+    pub fn executor_var(&self) -> String {
+        chain_var_name("EXECUTOR_ADDRESS", &self.name)
+    }
+
+    /// Reads the config for the chain named `name` using `var` to look up settings.
+    ///
+    /// `fallback_rpc_url` is used when `RPC_URL_WS_<NAME>` is not set.
+    /// Returns None if any problems were found; they're appended to `problems`.
+    fn load_from(
+        name: &str,
+        fallback_rpc_url: Option<&str>,
+        var: &impl Fn(&str) -> Option<String>,
+        problems: &mut Vec<String>,
+    ) -> Option<ChainConfig> {
+        let num_problems = problems.len();
+        let chain_id_var = chain_var_name("CHAIN_ID", name);
+        let chain_id = match var(&chain_id_var).map(|id| id.parse::<u64>()) {
+            Some(Ok(chain_id)) => {
+                if let Some(known_id) = known_chain_id(name) {
+                    if known_id != chain_id {
+                        problems.push(format!(
+                            "{}={} conflicts with the well-known chain id of '{}' ({})",
+                            chain_id_var, chain_id, name, known_id
+                        ));
+                    }
+                }
+                Some(chain_id)
+            }
+            Some(Err(err)) => {
+                problems.push(format!("{} must be an integer ({})", chain_id_var, err));
+                None
+            }
+            None => {
+                let chain_id = known_chain_id(name);
+                if chain_id.is_none() {
+                    problems.push(format!("unknown chain '{}'; set {}", name, chain_id_var));
+                }
+                chain_id
+            }
+        };
+
+        let rpc_url_var = chain_var_name("RPC_URL_WS", name);
+        let rpc_url_ws = var(&rpc_url_var).or(fallback_rpc_url.map(|url| url.to_owned()));
+        match &rpc_url_ws {
+            Some(url) => check_scheme(problems, &rpc_url_var, url, &["ws", "wss"]),
+            None => problems.push(format!("{} must be set for chain '{}'", rpc_url_var, name)),
+        }
+
+        let events_api_url_var = chain_var_name("EVENTS_API_URL", name);
+        let events_api_url =
+            var(&events_api_url_var).unwrap_or(FLASHBOTS_EVENTS_API_URL.to_owned());
+        check_scheme(
+            problems,
+            &events_api_url_var,
+            &events_api_url,
+            &["http", "https"],
+        );
+
+        let wrapped_native_var = chain_var_name("WRAPPED_NATIVE", name);
+        let known_wrapped_native = chain_id.and_then(known_wrapped_native);
+        let wrapped_native = match var(&wrapped_native_var).map(|addr| addr.parse::<Address>()) {
+            Some(Ok(address)) => {
+                if let Some(known_address) = known_wrapped_native {
+                    if known_address != address {
+                        problems.push(format!(
+                            "{}={:?} doesn't match the wrapped native token of chain {} ({:?})",
+                            wrapped_native_var,
+                            address,
+                            chain_id.unwrap_or_default(),
+                            known_address
+                        ));
+                    }
+                }
+                Some(address)
+            }
+            Some(Err(err)) => {
+                problems.push(format!(
+                    "{} must be an address ({})",
+                    wrapped_native_var, err
+                ));
+                None
+            }
+            None => {
+                if chain_id.is_some() && known_wrapped_native.is_none() {
+                    problems.push(format!(
+                        "no known wrapped native token for chain '{}'; set {}",
+                        name, wrapped_native_var
+                    ));
+                }
+                known_wrapped_native
+            }
+        };
+
+        let quote_assets_var = chain_var_name("QUOTE_ASSETS", name);
+        let quote_assets = match (var(&quote_assets_var), chain_id) {
+            (Some(entries), Some(chain_id)) => entries
+                .split(',')
+                .filter_map(|entry| match parse_quote_asset(chain_id, entry) {
+                    Ok(asset) => Some(asset),
+                    Err(err) => {
+                        problems.push(format!("{}: {}", quote_assets_var, err));
+                        None
+                    }
+                })
+                .collect::<Vec<_>>(),
+            (None, Some(chain_id)) => known_quote_assets(chain_id),
+            (_, None) => vec![],
+        };
+        if chain_id.is_some() && quote_assets.is_empty() && problems.len() == num_problems {
+            problems.push(format!(
+                "no known quote assets for chain '{}'; set {}",
+                name, quote_assets_var
+            ));
+        }
+
+        let executor_var = chain_var_name("EXECUTOR_ADDRESS", name);
+        let executor = match var(&executor_var).map(|addr| addr.parse::<Address>()) {
+            Some(Ok(address)) => Some(address),
+            Some(Err(err)) => {
+                problems.push(format!("{} must be an address ({})", executor_var, err));
+                None
+            }
+            None => None,
+        };
+
+        let relay_urls_var = chain_var_name("RELAY_URLS", name);
+        let relay_urls = match var(&relay_urls_var) {
+            Some(urls) => urls
+                .split(',')
+                .map(|url| url.trim().to_owned())
+                .filter(|url| !url.is_empty())
+                .collect::<Vec<_>>(),
+            None if chain_id == Some(1) => MAINNET_RELAY_URLS.map(str::to_owned).to_vec(),
+            None => vec![],
+        };
+        for url in &relay_urls {
+            check_scheme(problems, &relay_urls_var, url, &["http", "https"]);
+        }
+
+        let dex_factories_var = chain_var_name("DEX_FACTORIES", name);
+        let dex_factories = match var(&dex_factories_var) {
+            Some(entries) => entries
+                .split(',')
+                .filter_map(|entry| match parse_dex_factory(entry) {
+                    Ok(factory) => Some(factory),
+                    Err(err) => {
+                        problems.push(format!("{}: {}", dex_factories_var, err));
+                        None
+                    }
+                })
+                .collect::<Vec<_>>(),
+            None => chain_id.map(known_dex_factories).unwrap_or_default(),
+        };
+
+        if problems.len() > num_problems {
+            return None;
+        }
+        let chain_id = chain_id?;
+        Some(ChainConfig {
+            name: name.to_owned(),
+            chain_id,
+            rpc_url_ws: rpc_url_ws?,
+            events_api_url,
+            db_namespace: var(&chain_var_name("DB_NAMESPACE", name)).unwrap_or(if chain_id == 1 {
+                DEFAULT_DB_NAMESPACE.to_owned()
+            } else {
+                format!("{}_{}", DEFAULT_DB_NAMESPACE, name)
+            }),
+            wrapped_native: wrapped_native?,
+            quote_assets,
+            executor,
+            relay_urls,
+            dex_factories,
+        })
+    }
+}
+
+/// Reads the auth signer source. Only one of keystore, ledger, or raw key may be set.
+fn auth_signer_from(
+    var: &impl Fn(&str) -> Option<String>,
+    problems: &mut Vec<String>,
+) -> Option<SignerConfig> {
+    let keystore = var("AUTH_SIGNER_KEYSTORE");
+    let ledger = var("AUTH_SIGNER_LEDGER");
+    let key = var("AUTH_SIGNER_KEY");
+    let num_sources = [&keystore, &ledger, &key]
+        .iter()
+        .filter(|source| source.is_some())
+        .count();
+    if num_sources > 1 {
+        problems.push(
+            "only one of AUTH_SIGNER_KEYSTORE, AUTH_SIGNER_LEDGER, AUTH_SIGNER_KEY may be set"
+                .to_owned(),
+        );
+        return None;
+    }
+
+    if let Some(path) = keystore {
+        let password = match (
+            var("AUTH_SIGNER_PASSWORD"),
+            var("AUTH_SIGNER_PASSWORD_FILE"),
+        ) {
+            (Some(_), Some(_)) => {
+                problems.push(
+                    "only one of AUTH_SIGNER_PASSWORD, AUTH_SIGNER_PASSWORD_FILE may be set"
+                        .to_owned(),
+                );
+                None
+            }
+            (Some(password), None) => Some(password),
+            (None, Some(file)) => match std::fs::read_to_string(&file) {
+                Ok(password) => Some(password.trim_end().to_owned()),
+                Err(err) => {
+                    problems.push(format!(
+                        "failed to read AUTH_SIGNER_PASSWORD_FILE {} ({})",
+                        file, err
+                    ));
+                    None
+                }
+            },
+            (None, None) => {
+                problems.push(
+                    "AUTH_SIGNER_KEYSTORE requires AUTH_SIGNER_PASSWORD or AUTH_SIGNER_PASSWORD_FILE"
+                        .to_owned(),
+                );
+                None
+            }
+        };
+        if !std::path::Path::new(&path).is_file() {
+            problems.push(format!("AUTH_SIGNER_KEYSTORE {} is not a file", path));
+        }
+        Some(SignerConfig::Keystore {
+            path: path.into(),
+            password: password?,
+        })
+    } else if let Some(derivation) = ledger {
+        Some(SignerConfig::Ledger { derivation })
+    } else {
+        key.map(SignerConfig::PrivateKey)
+    }
+}
+
+/// Flattens a profile's TOML values into env-style keys, so profiles can set anything env vars can.
+///
+/// Nested tables are joined with `_` (`scan.batch_size` => `SCAN_BATCH_SIZE`), arrays are comma-separated.
+fn flatten_profile(prefix: &str, value: &toml::Value, vars: &mut HashMap<String, String>) {
+    let key = prefix.to_uppercase().replace('-', "_");
+    match value {
+        toml::Value::Table(table) => {
+            for (name, value) in table {
+                let prefix = if prefix.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}_{}", prefix, name)
+                };
+                flatten_profile(&prefix, value, vars);
+            }
+        }
+        toml::Value::String(s) => {
+            vars.insert(key, s.to_owned());
+        }
+        toml::Value::Array(values) => {
+            let values = values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::String(s) => s.to_owned(),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>();
+            vars.insert(key, values.join(","));
+        }
+        value => {
+            vars.insert(key, value.to_string());
+        }
+    }
+}
+
+/// Reads profile `name` from the TOML config `contents`.
+fn parse_profile(contents: &str, name: &str) -> Result<HashMap<String, String>, String> {
+    let file = contents
+        .parse::<toml::Table>()
+        .map_err(|err| format!("failed to parse config file: {}", err))?;
+    let profiles = file
+        .get("profile")
+        .and_then(|profiles| profiles.as_table())
+        .ok_or("config file has no [profile.<name>] sections".to_owned())?;
+    let profile = profiles.get(name).ok_or(format!(
+        "profile '{}' not found (available profiles: {})",
+        name,
+        profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+    ))?;
+    let mut vars = HashMap::new();
+    flatten_profile("", profile, &mut vars);
+    Ok(vars)
 }
 
-impl Default for Config {
-    fn default() -> Config {
+fn load_profile_file(path: &Path, name: &str) -> Result<HashMap<String, String>, ConfigError> {
+    std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read config file {:?}: {}", path, err))
+        .and_then(|contents| parse_profile(&contents, name))
+        .map_err(|problem| ConfigError {
+            problems: vec![format!("{} ({:?})", problem, path)],
+        })
+}
+
+/// Sets `key` to `value` in the env file at `path`, replacing any line that already sets it (commented-out or not).
+/// Creates the file if it doesn't exist.
+pub fn store_env_var(path: &Path, key: &str, value: &str) -> Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let entry = format!("{}={}", key, value);
+    let sets_key = |line: &str| {
+        line.trim_start_matches('#')
+            .trim()
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    };
+    let mut stored = false;
+    let mut lines = contents
+        .lines()
+        .filter_map(|line| match sets_key(line) {
+            true if stored => None,
+            true => {
+                stored = true;
+                Some(entry.to_owned())
+            }
+            false => Some(line.to_owned()),
+        })
+        .collect::<Vec<_>>();
+    if !stored {
+        lines.push(entry);
+    }
+    std::fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+impl Config {
+    /// Loads the config from `.env` and environment variables, validating every setting.
+    ///
+    /// Uses the profile named by `HINDSIGHT_PROFILE`, if set.
+    pub fn load() -> Result<Config, ConfigError> {
+        Self::load_profile(env::var("HINDSIGHT_PROFILE").ok().as_deref())
+    }
+
+    /// Loads the config like `load`, with values from the named profile overriding env vars.
+    ///
+    /// Profiles are read from `HINDSIGHT_CONFIG` (default `./hindsight.toml`).
+    pub fn load_profile(profile: Option<&str>) -> Result<Config, ConfigError> {
         let env_file_res = dotenvy::dotenv()
             .map_err(|err| anyhow::anyhow!("Failed to load .env file. Error: {}", err));
         if let Err(err) = env_file_res {
             debug!("{}", err);
         }
-        Config {
-            mongo_url: env::var("MONGO_URL").expect("MONGO_URL must be set"),
-            postgres_url: env::var("POSTGRES_URL").ok(),
-            rpc_url_ws: env::var("RPC_URL_WS").expect("RPC_URL_WS must be set"),
-            tls_ca_file_mongo: env::var("TLS_CA_FILE_MONGO").map(|s| s.into()).ok(),
+        let profile_vars = match profile {
+            Some(name) => {
+                let path = PathBuf::from(
+                    env::var("HINDSIGHT_CONFIG").unwrap_or(DEFAULT_CONFIG_FILE.to_owned()),
+                );
+                load_profile_file(&path, name)?
+            }
+            None => HashMap::new(),
+        };
+        Self::load_from(|key| profile_vars.get(key).cloned().or(env::var(key).ok()))
+    }
+
+    /// Loads the config using `var` to look up each setting.
+    ///
+    /// Doesn't stop at the first problem; the returned error lists all of them.
+    pub fn load_from(var: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut problems = vec![];
+
+        let rpc_url_ws = var("RPC_URL_WS");
+        if rpc_url_ws.is_none() {
+            problems.push("RPC_URL_WS must be set".to_owned());
+        }
+        let mongo_url = var("MONGO_URL");
+        match &mongo_url {
+            Some(url) => check_scheme(&mut problems, "MONGO_URL", url, &["mongodb", "mongodb+srv"]),
+            None => problems.push("MONGO_URL must be set".to_owned()),
+        }
+        let postgres_url = var("POSTGRES_URL");
+        if let Some(url) = &postgres_url {
+            check_scheme(
+                &mut problems,
+                "POSTGRES_URL",
+                url,
+                &["postgres", "postgresql"],
+            );
+        }
+        let tls_ca_file_mongo = var("TLS_CA_FILE_MONGO").map(PathBuf::from);
+        if let Some(path) = &tls_ca_file_mongo {
+            if !path.is_file() {
+                problems.push(format!("TLS_CA_FILE_MONGO {:?} is not a file", path));
+            }
+        }
+
+        // CHAINS is a comma-separated list of chain names; the first chain falls back to RPC_URL_WS
+        let chain_names = var("CHAINS")
+            .unwrap_or("mainnet".to_owned())
+            .split(',')
+            .map(|name| name.trim().to_owned())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        if chain_names.is_empty() {
+            problems.push("CHAINS must contain at least one chain name".to_owned());
+        }
+        let chains = chain_names
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, name)| {
+                ChainConfig::load_from(
+                    name,
+                    if idx == 0 {
+                        rpc_url_ws.as_deref()
+                    } else {
+                        None
+                    },
+                    &var,
+                    &mut problems,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut chain_ids = HashSet::new();
+        let mut namespaces = HashSet::new();
+        for chain in &chains {
+            if !chain_ids.insert(chain.chain_id) {
+                problems.push(format!(
+                    "chain id {} is configured more than once",
+                    chain.chain_id
+                ));
+            }
+            if !namespaces.insert(chain.db_namespace.to_owned()) {
+                problems.push(format!(
+                    "DB namespace '{}' is used by more than one chain",
+                    chain.db_namespace
+                ));
+            }
+        }
+
+        let auth_signer = auth_signer_from(&var, &mut problems);
+
+        let default_db = var("DB");
+        if let Some(db) = &default_db {
+            if !DB_ENGINE_NAMES.contains(&db.as_str()) {
+                problems.push(format!(
+                    "DB must be one of {} (got '{}')",
+                    DB_ENGINE_NAMES.join(", "),
+                    db
+                ));
+            }
+        }
+        let scan_batch_size = match var("SCAN_BATCH_SIZE").map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => Some(n),
+            Some(_) => {
+                problems.push("SCAN_BATCH_SIZE must be a positive integer".to_owned());
+                None
+            }
+            None => None,
+        };
+        let sim_permits = match var("SIM_PERMITS").map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => Some(n),
+            Some(_) => {
+                problems.push("SIM_PERMITS must be a positive integer".to_owned());
+                None
+            }
+            None => None,
+        };
+        let tx_max_sims = match var("TX_MAX_SIMS").map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => Some(n),
+            Some(_) => {
+                problems.push("TX_MAX_SIMS must be a positive integer".to_owned());
+                None
+            }
+            None => None,
+        };
+        let tx_max_sim_secs = match var("TX_MAX_SIM_SECS").map(|secs| secs.parse::<f64>()) {
+            Some(Ok(secs)) if secs > 0.0 && secs.is_finite() => Some(secs),
+            Some(_) => {
+                problems.push("TX_MAX_SIM_SECS must be a positive number".to_owned());
+                None
+            }
+            None => None,
+        };
+        let sim_min_improvement = match var("SIM_MIN_IMPROVEMENT").map(|gain| gain.parse::<f64>()) {
+            Some(Ok(gain)) if gain >= 0.0 && gain.is_finite() => Some(gain),
+            Some(_) => {
+                problems.push("SIM_MIN_IMPROVEMENT must be a non-negative number".to_owned());
+                None
+            }
+            None => None,
+        };
+        let sim_min_price_gap = match var("SIM_MIN_PRICE_GAP").map(|gap| gap.parse::<f64>()) {
+            Some(Ok(gap)) if gap >= 0.0 && gap.is_finite() => Some(gap),
+            Some(_) => {
+                problems.push("SIM_MIN_PRICE_GAP must be a non-negative number".to_owned());
+                None
+            }
+            None => None,
+        };
+        let sim_max_token_tax = match var("SIM_MAX_TOKEN_TAX").map(|tax| tax.parse::<f64>()) {
+            Some(Ok(tax)) if (0.0..=1.0).contains(&tax) => Some(tax),
+            Some(_) => {
+                problems.push("SIM_MAX_TOKEN_TAX must be a number from 0 to 1".to_owned());
+                None
+            }
+            None => None,
+        };
+        let sim_coinbase_share = match var("SIM_COINBASE_SHARE").map(|share| share.parse::<f64>()) {
+            Some(Ok(share)) if (0.0..=1.0).contains(&share) => Some(share),
+            Some(_) => {
+                problems.push("SIM_COINBASE_SHARE must be a number from 0 to 1".to_owned());
+                None
+            }
+            None => None,
+        };
+        let notify_url = var("NOTIFY_URL");
+        if let Some(url) = &notify_url {
+            check_scheme(&mut problems, "NOTIFY_URL", url, &["http", "https"]);
+        }
+        let metrics_url = var("METRICS_URL");
+        if let Some(url) = &metrics_url {
+            check_scheme(&mut problems, "METRICS_URL", url, &["http", "https"]);
+        }
+        let alert_urls = var("ALERT_URLS")
+            .map(|urls| {
+                urls.split(',')
+                    .map(|url| url.trim().to_owned())
+                    .filter(|url| !url.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for url in &alert_urls {
+            check_scheme(&mut problems, "ALERT_URLS", url, &["http", "https"]);
+        }
+        let mut chat_bots = vec![];
+        for (prefix, chat_key) in [("TELEGRAM", "CHAT_ID"), ("DISCORD", "CHANNEL_ID")] {
+            let token_var = format!("{}_BOT_TOKEN", prefix);
+            let chat_var = format!("{}_{}", prefix, chat_key);
+            match (var(&token_var), var(&chat_var)) {
+                (Some(bot_token), Some(chat_id)) => chat_bots.push(match prefix {
+                    "TELEGRAM" => Destination::Telegram { bot_token, chat_id },
+                    _ => Destination::Discord {
+                        bot_token,
+                        channel_id: chat_id,
+                    },
+                }),
+                (Some(_), None) => problems.push(format!("{} requires {}", token_var, chat_var)),
+                (None, Some(_)) => problems.push(format!("{} requires {}", chat_var, token_var)),
+                (None, None) => {}
+            }
+        }
+        let alert_template = var("ALERT_TEMPLATE").and_then(|template| {
+            AlertTemplate::parse(&template)
+                .map_err(|err| problems.push(format!("ALERT_TEMPLATE is invalid: {}", err)))
+                .ok()
+        });
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+        Ok(Config {
+            rpc_url_ws: rpc_url_ws.unwrap_or_default(),
+            mongo_url: mongo_url.unwrap_or_default(),
+            postgres_url,
+            tls_ca_file_mongo,
+            chains,
+            auth_signer,
+            default_db,
+            scan: ScanDefaults {
+                batch_size: scan_batch_size,
+            },
+            sim_permits,
+            tx_max_sims,
+            tx_max_sim_secs,
+            sim_min_improvement,
+            sim_min_price_gap,
+            sim_max_token_tax,
+            sim_coinbase_share,
+            notify_url,
+            metrics_url,
+            metrics_token: var("METRICS_TOKEN"),
+            alert_urls,
+            alert_template,
+            chat_bots,
+        })
+    }
+
+    /// A budget of `sim_permits` forks & sims, or the default if it isn't set.
+    pub fn sim_budget(&self) -> SimBudget {
+        self.sim_permits.map(SimBudget::new).unwrap_or_default()
+    }
+
+    /// Caps on the sims & time spent on each tx, from `tx_max_sims`, `tx_max_sim_secs`,
+    /// `sim_min_improvement`, `sim_min_price_gap`, `sim_max_token_tax` & `sim_coinbase_share`.
+    pub fn tx_sim_limits(&self) -> TxSimLimits {
+        let min_improvement = self.sim_min_improvement.unwrap_or(DEFAULT_MIN_IMPROVEMENT);
+        let min_price_gap = self.sim_min_price_gap.unwrap_or(DEFAULT_MIN_PRICE_GAP);
+        let max_token_tax = self.sim_max_token_tax.unwrap_or(DEFAULT_MAX_TOKEN_TAX);
+        TxSimLimits {
+            max_sims: self.tx_max_sims,
+            max_duration: self.tx_max_sim_secs.map(Duration::from_secs_f64),
+            min_improvement: (min_improvement > 0.0).then_some(min_improvement),
+            min_price_gap: (min_price_gap > 0.0).then_some(min_price_gap),
+            max_token_tax: (max_token_tax > 0.0).then_some(max_token_tax),
+            coinbase_share: self.sim_coinbase_share.filter(|share| *share > 0.0),
+            verify: false,
+            deterministic: false,
+        }
+    }
+
+    /// Where `--notify` sends notifications: `notify_url` and the chat bots.
+    pub fn notify_destinations(&self) -> Vec<Destination> {
+        self.notify_url
+            .iter()
+            .map(|url| Destination::Webhook(url.to_owned()))
+            .chain(self.chat_bots.to_owned())
+            .collect()
+    }
+
+    /// Where high-profit arbs are alerted: `alert_urls` and the chat bots.
+    pub fn alert_destinations(&self) -> Vec<Destination> {
+        self.alert_urls
+            .iter()
+            .map(|url| Destination::Webhook(url.to_owned()))
+            .chain(self.chat_bots.to_owned())
+            .collect()
+    }
+
+    /// Returns the chain named `name`, or the first configured chain if `name` is None.
+    pub fn chain(&self, name: Option<&str>) -> Result<ChainConfig> {
+        match name {
+            Some(name) => self
+                .chains
+                .iter()
+                .find(|chain| chain.name == name)
+                .cloned()
+                .ok_or(anyhow::format_err!(
+                    "chain '{}' is not configured (configured chains: {})",
+                    name,
+                    self.chains
+                        .iter()
+                        .map(|chain| chain.name.to_owned())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            None => Ok(self.chains[0].to_owned()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        Config::load_from(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn it_loads_minimal_config() {
+        let config = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+        ])
+        .unwrap();
+        assert_eq!(config.chains.len(), 1);
+        assert_eq!(config.chains[0].chain_id, 1);
+        assert_eq!(config.chains[0].db_namespace, DEFAULT_DB_NAMESPACE);
+        assert_eq!(
+            Some(config.chains[0].wrapped_native),
+            known_wrapped_native(1)
+        );
+    }
+
+    #[test]
+    fn it_reports_every_problem() {
+        let err = load(&[
+            ("RPC_URL_WS", "http://127.0.0.1:8545"),
+            ("POSTGRES_URL", "mysql://localhost"),
+            ("CHAINS", "mainnet,mystery"),
+            ("AUTH_SIGNER_KEY", "0x01"),
+            ("AUTH_SIGNER_LEDGER", "live:0"),
+            ("SIM_PERMITS", "0"),
+            ("TX_MAX_SIM_SECS", "-1"),
+        ])
+        .unwrap_err();
+        // bad rpc scheme, missing mongo url, bad postgres scheme,
+        // unknown chain id + missing rpc url for 2nd chain, conflicting signers, no sim permits, negative tx time cap
+        assert_eq!(err.problems.len(), 8, "{}", err);
+    }
+
+    #[test]
+    fn it_loads_alert_webhooks() {
+        let minimal = [
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+        ];
+        let config = load(
+            &[
+                &minimal[..],
+                &[(
+                    "ALERT_URLS",
+                    "https://hooks.slack.com/services/x, https://example.org/hook",
+                )],
+            ]
+            .concat(),
+        )
+        .unwrap();
+        assert_eq!(
+            config.alert_urls,
+            vec![
+                "https://hooks.slack.com/services/x",
+                "https://example.org/hook"
+            ]
+        );
+        assert_eq!(config.alert_template, None);
+
+        let err = load(
+            &[
+                &minimal[..],
+                &[
+                    ("ALERT_URLS", "ftp://example.org"),
+                    ("ALERT_TEMPLATE", r#"{"text": "{{tx}}"}"#),
+                ],
+            ]
+            .concat(),
+        )
+        .unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+    }
+
+    #[test]
+    fn it_loads_chat_bots() {
+        let minimal = [
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            ("NOTIFY_URL", "https://example.org/hook"),
+        ];
+        let config = load(
+            &[
+                &minimal[..],
+                &[
+                    ("TELEGRAM_BOT_TOKEN", "123:abc"),
+                    ("TELEGRAM_CHAT_ID", "-100"),
+                ],
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let telegram = Destination::Telegram {
+            bot_token: "123:abc".to_owned(),
+            chat_id: "-100".to_owned(),
+        };
+        assert_eq!(
+            config.notify_destinations(),
+            vec![
+                Destination::Webhook("https://example.org/hook".to_owned()),
+                telegram.to_owned()
+            ]
+        );
+        assert_eq!(config.alert_destinations(), vec![telegram]);
+
+        let err = load(&[&minimal[..], &[("DISCORD_BOT_TOKEN", "xyz")]].concat()).unwrap_err();
+        assert_eq!(
+            err.problems,
+            vec!["DISCORD_BOT_TOKEN requires DISCORD_CHANNEL_ID".to_owned()]
+        );
+    }
+
+    #[test]
+    fn it_loads_relay_urls() {
+        let minimal = [
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+        ];
+        assert_eq!(
+            load(&minimal).unwrap().chains[0].relay_urls,
+            MAINNET_RELAY_URLS.to_vec()
+        );
+        let config = load(
+            &[
+                &minimal[..],
+                &[("RELAY_URLS_MAINNET", "https://relay.example.org, ")],
+            ]
+            .concat(),
+        )
+        .unwrap();
+        assert_eq!(
+            config.chains[0].relay_urls,
+            vec!["https://relay.example.org"]
+        );
+        let err = load(
+            &[
+                &minimal[..],
+                &[("RELAY_URLS_MAINNET", "ws://relay.example.org")],
+            ]
+            .concat(),
+        )
+        .unwrap_err();
+        assert!(err.problems[0].starts_with("RELAY_URLS_MAINNET must be a http:// or https:// URL"));
+    }
+
+    #[test]
+    fn it_stores_executor_address() -> anyhow::Result<()> {
+        let minimal = [
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+        ];
+        let chain = load(&minimal).unwrap().chains[0].to_owned();
+        assert_eq!(chain.executor, None);
+        assert_eq!(chain.executor_var(), "EXECUTOR_ADDRESS_MAINNET");
+        let err =
+            load(&[&minimal[..], &[("EXECUTOR_ADDRESS_MAINNET", "0x12")]].concat()).unwrap_err();
+        assert!(err.problems[0].starts_with("EXECUTOR_ADDRESS_MAINNET must be an address"));
+
+        let path = std::env::temp_dir().join(format!("hindsight-env-{}", rand::random::<u32>()));
+        let executor = "0x0000000000000000000000000000000000000001";
+        store_env_var(&path, &chain.executor_var(), executor)?;
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            format!("EXECUTOR_ADDRESS_MAINNET={}\n", executor)
+        );
+        std::fs::write(
+            &path,
+            "RPC_URL_WS=ws://127.0.0.1:8545\n#EXECUTOR_ADDRESS_MAINNET=\nEXECUTOR_ADDRESS_MAINNET_OLD=1\n",
+        )?;
+        store_env_var(&path, &chain.executor_var(), executor)?;
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            contents,
+            format!(
+                "RPC_URL_WS=ws://127.0.0.1:8545\nEXECUTOR_ADDRESS_MAINNET={}\nEXECUTOR_ADDRESS_MAINNET_OLD=1\n",
+                executor
+            )
+        );
+        let vars = [&minimal[..], &[("EXECUTOR_ADDRESS_MAINNET", executor)]].concat();
+        assert_eq!(
+            load(&vars).unwrap().chains[0].executor,
+            executor.parse().ok()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_profiles() {
+        let contents = r#"
+            [profile.mainnet-reth]
+            rpc_url_ws = "ws://10.0.0.2:8545"
+            chains = ["mainnet", "base"]
+            rpc_url_ws_base = "ws://10.0.0.3:8545"
+            db = "postgres"
+
+            [profile.mainnet-reth.scan]
+            batch_size = 16
+
+            [profile.alchemy]
+            rpc_url_ws = "wss://eth-mainnet.g.alchemy.com/v2/key"
+        "#;
+        let vars = parse_profile(contents, "mainnet-reth").unwrap();
+        assert_eq!(vars["RPC_URL_WS"], "ws://10.0.0.2:8545");
+        assert_eq!(vars["CHAINS"], "mainnet,base");
+        assert_eq!(vars["SCAN_BATCH_SIZE"], "16");
+
+        let config = Config::load_from(|key| {
+            vars.get(key)
+                .cloned()
+                .or((key == "MONGO_URL").then(|| "mongodb://localhost:27017".to_owned()))
+        })
+        .unwrap();
+        assert_eq!(config.chains.len(), 2);
+        assert_eq!(config.chains[1].rpc_url_ws, "ws://10.0.0.3:8545");
+        assert_eq!(config.default_db.as_deref(), Some("postgres"));
+        assert_eq!(config.scan.batch_size, Some(16));
+
+        let err = parse_profile(contents, "infura").unwrap_err();
+        assert!(
+            err.contains("alchemy") && err.contains("mainnet-reth"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn it_checks_wrapped_native_matches_chain() {
+        let err = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            (
+                "WRAPPED_NATIVE_MAINNET",
+                "0x4200000000000000000000000000000000000006",
+            ),
+        ])
+        .unwrap_err();
+        assert_eq!(err.problems.len(), 1, "{}", err);
+    }
+
+    #[test]
+    fn it_loads_quote_assets() {
+        let config = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            ("CHAINS", "mainnet,base"),
+            ("RPC_URL_WS_BASE", "ws://127.0.0.1:8546"),
+            (
+                "QUOTE_ASSETS_BASE",
+                "0x4200000000000000000000000000000000000006,0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913:9:6:50000",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(config.chains[0].quote_assets, known_quote_assets(1));
+        assert_eq!(
+            config.chains[0].quote_assets[0].address,
+            config.chains[0].wrapped_native
+        );
+        let base_assets = &config.chains[1].quote_assets;
+        assert_eq!(base_assets.len(), 2);
+        assert_eq!(base_assets[0].address, config.chains[1].wrapped_native);
+        assert_eq!(base_assets[1].balance_slot, 9);
+        assert_eq!(
+            base_assets[1].starting_balance,
+            U256::from(50_000_000_000u64)
+        );
+
+        let err = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            (
+                "QUOTE_ASSETS_MAINNET",
+                "0x6B175474E89094C44Da98b954EedeAC495271d0F,0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2:3",
+            ),
+        ])
+        .unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+    }
+
+    #[test]
+    fn it_loads_dex_factories() {
+        let config = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            ("CHAINS", "mainnet,base"),
+            ("RPC_URL_WS_BASE", "ws://127.0.0.1:8546"),
+            (
+                "DEX_FACTORIES_BASE",
+                "v3:0x33128a8fC17869897dcE68Ed026d694621f6FDfD:1371680",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(config.chains[0].dex_factories, known_dex_factories(1));
+        assert_eq!(
+            config.chains[1].dex_factories,
+            vec![DexFactory {
+                factory: "0x33128a8fC17869897dcE68Ed026d694621f6FDfD"
+                    .parse()
+                    .unwrap(),
+                variant: PoolVariant::UniswapV3,
+                start_block: 1_371_680,
+            }]
+        );
+
+        let err = load(&[
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+            (
+                "DEX_FACTORIES_MAINNET",
+                "v4:0x33128a8fC17869897dcE68Ed026d694621f6FDfD:1,v2:0x33128a8fC17869897dcE68Ed026d694621f6FDfD",
+            ),
+        ])
+        .unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+    }
+
+    #[test]
+    fn it_loads_coinbase_share() {
+        let minimal = [
+            ("RPC_URL_WS", "ws://127.0.0.1:8545"),
+            ("MONGO_URL", "mongodb://localhost:27017"),
+        ];
+        let config = load(&minimal).unwrap();
+        assert_eq!(config.tx_sim_limits().coinbase_share, None);
+
+        let config = load(&[&minimal[..], &[("SIM_COINBASE_SHARE", "0.9")]].concat()).unwrap();
+        assert_eq!(config.tx_sim_limits().coinbase_share, Some(0.9));
+        // paying nothing is the same as not paying
+        let config = load(&[&minimal[..], &[("SIM_COINBASE_SHARE", "0")]].concat()).unwrap();
+        assert_eq!(config.tx_sim_limits().coinbase_share, None);
+
+        let err = load(&[&minimal[..], &[("SIM_COINBASE_SHARE", "1.5")]].concat()).unwrap_err();
+        assert_eq!(err.problems.len(), 1, "{}", err);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{debug, info, log_error, util::FetchStats, Result};
+use ethers::{types::U256, utils::format_units};
+use serde_json::json;
+use std::{
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::Semaphore,
+};
+
+/// Settings of a running scan that can be changed without restarting it.
+///
+/// Shared by every chain in a scan. Changes take effect at the start of the next batch.
+#[derive(Debug)]
+pub struct ScanControl {
+    /// Number of txs fetched & simulated per batch.
+    batch_size: AtomicUsize,
+    /// Number of permits that `sim_limit` is meant to have.
+    concurrency: AtomicUsize,
+    /// Limits the number of txs simulated at once, across all chains.
+    sim_limit: Arc<Semaphore>,
+    /// Batches with a lower max profit (in whole units of their profit token) aren't saved.
+    min_profit: RwLock<f64>,
+    paused: AtomicBool,
+    /// Adjusts the batch size & concurrency after each batch, if auto-tuning.
+    tuner: Mutex<Option<BatchTuner>>,
+}
+
+/// Share of a batch's txs & RPC requests that may fail before the batch size is lowered.
+pub const TUNE_MAX_ERROR_RATE: f64 = 0.05;
+/// How many times slower than the fastest batch's RPC requests requests may get before the batch size is lowered.
+pub const TUNE_MAX_LATENCY_FACTOR: u32 = 3;
+
+/// How a batch went, as observed by `ScanControl::observe_batch`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchStats {
+    /// Batch size the batch was started with.
+    pub batch_size: usize,
+    pub num_txs: usize,
+    /// Txs that couldn't be simulated at all.
+    pub num_failed: usize,
+    /// Requests made to fetch the batch's data up front.
+    pub rpc: FetchStats,
+    pub elapsed: Duration,
+}
+
+/// Searches for the batch size with the highest throughput that the RPC node can keep up with.
+///
+/// The size is raised gradually while throughput keeps up, stepped back if raising it made throughput drop,
+/// and halved as soon as errors or RPC latency spike.
+#[derive(Debug)]
+struct BatchTuner {
+    bounds: RangeInclusive<usize>,
+    /// Lowest mean RPC latency of any batch so far.
+    base_latency: Option<Duration>,
+    /// Throughput (txs/s) of the last full batch, and whether the size was raised after it.
+    last: Option<(f64, bool)>,
+}
+
+impl BatchTuner {
+    fn new(bounds: RangeInclusive<usize>) -> Self {
+        Self {
+            bounds,
+            base_latency: None,
+            last: None,
+        }
+    }
+
+    /// Returns the batch size to use after a batch with `stats`, when the size is currently `size`.
+    fn next_batch_size(&mut self, size: usize, stats: &BatchStats) -> usize {
+        let (min, max) = (*self.bounds.start(), *self.bounds.end());
+        let attempts = stats.num_txs + stats.rpc.requests;
+        let error_rate = (stats.num_failed + stats.rpc.errors) as f64 / attempts.max(1) as f64;
+        let overloaded = stats.rpc.requests > 0
+            && self
+                .base_latency
+                .map(|base| stats.rpc.latency > base * TUNE_MAX_LATENCY_FACTOR)
+                .unwrap_or(false);
+        if stats.rpc.requests > 0 {
+            self.base_latency = Some(
+                self.base_latency
+                    .map_or(stats.rpc.latency, |base| base.min(stats.rpc.latency)),
+            );
+        }
+        if error_rate > TUNE_MAX_ERROR_RATE || overloaded {
+            self.last = None;
+            return (size / 2).clamp(min, max);
+        }
+        // a partial batch (e.g. the end of a block range) says nothing about throughput
+        if stats.num_txs < stats.batch_size || stats.elapsed.is_zero() {
+            return size.clamp(min, max);
+        }
+        let throughput = stats.num_txs as f64 / stats.elapsed.as_secs_f64();
+        let step = (size / 8).max(1);
+        let next = match self.last {
+            // raising the size last time didn't pay off, so go back
+            Some((last, true)) if throughput < last * 0.9 => size.saturating_sub(step),
+            _ => size + step,
+        }
+        .clamp(min, max);
+        self.last = Some((throughput, next > size));
+        next
+    }
+}
+
+impl ScanControl {
+    pub fn new(batch_size: usize, concurrency: usize) -> Self {
+        Self {
+            batch_size: AtomicUsize::new(batch_size),
+            concurrency: AtomicUsize::new(concurrency),
+            sim_limit: Arc::new(Semaphore::new(concurrency)),
+            min_profit: RwLock::new(0.0),
+            paused: AtomicBool::new(false),
+            tuner: Mutex::new(None),
+        }
+    }
+
+    /// Tunes the batch size & concurrency (together) within `bounds` after every batch,
+    /// starting from the current batch size.
+    pub fn auto_tune(&self, bounds: RangeInclusive<usize>) -> Result<()> {
+        if *bounds.start() == 0 || bounds.is_empty() {
+            return Err(anyhow::format_err!(
+                "invalid batch size bounds {:?}: must be > 0, with min <= max",
+                bounds
+            ));
+        }
+        let size = self.batch_size().clamp(*bounds.start(), *bounds.end());
+        self.set_batch_size(size)?;
+        self.set_concurrency(size)?;
+        info!("auto-tuning batch size within {:?}", bounds);
+        *self.tuner.lock().unwrap() = Some(BatchTuner::new(bounds));
+        Ok(())
+    }
+
+    /// Stops auto-tuning, leaving the batch size where it is.
+    pub fn stop_auto_tune(&self) {
+        if self.tuner.lock().unwrap().take().is_some() {
+            info!("stopped auto-tuning batch size");
+        }
+    }
+
+    /// Feeds a finished batch to the auto-tuner (if enabled), which may change the batch size & concurrency.
+    pub fn observe_batch(&self, stats: &BatchStats) {
+        let mut tuner = self.tuner.lock().unwrap();
+        let tuner = match tuner.as_mut() {
+            Some(tuner) => tuner,
+            None => return,
+        };
+        let size = self.batch_size();
+        let next = tuner.next_batch_size(size, stats);
+        debug!(?stats, size, next, "observed batch");
+        if next != size {
+            info!("auto-tuned batch size: {} -> {}", size, next);
+            // bounds are validated to be > 0, so these can't fail
+            let _ = self.set_batch_size(next);
+            let _ = self.set_concurrency(next);
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.load(Ordering::Relaxed)
+    }
+
+    pub fn set_batch_size(&self, batch_size: usize) -> Result<()> {
+        if batch_size == 0 {
+            return Err(anyhow::format_err!("batch_size must be > 0"));
+        }
+        self.batch_size.store(batch_size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.load(Ordering::Relaxed)
+    }
+
+    /// Changes the number of txs that may be simulated at once.
+    ///
+    /// Lowering it doesn't cancel sims in flight; the extra permits are retired as those sims finish.
+    pub fn set_concurrency(&self, concurrency: usize) -> Result<()> {
+        if concurrency == 0 {
+            return Err(anyhow::format_err!("concurrency must be > 0"));
+        }
+        let previous = self.concurrency.swap(concurrency, Ordering::Relaxed);
+        if concurrency > previous {
+            self.sim_limit.add_permits(concurrency - previous);
+        } else if concurrency < previous {
+            let sim_limit = self.sim_limit.clone();
+            let excess = (previous - concurrency) as u32;
+            tokio::task::spawn(async move {
+                if let Ok(permits) = sim_limit.acquire_many(excess).await {
+                    permits.forget();
+                }
+            });
+        }
+        Ok(())
+    }
+
+    pub fn sim_limit(&self) -> Arc<Semaphore> {
+        self.sim_limit.clone()
+    }
+
+    pub fn min_profit(&self) -> f64 {
+        *self.min_profit.read().expect("min_profit lock poisoned")
+    }
+
+    pub fn set_min_profit(&self, min_profit: f64) -> Result<()> {
+        if min_profit.is_nan() || min_profit < 0.0 {
+            return Err(anyhow::format_err!("min_profit must be >= 0"));
+        }
+        *self.min_profit.write().expect("min_profit lock poisoned") = min_profit;
+        Ok(())
+    }
+
+    /// Returns true if a profit of `profit` (in the token's smallest unit) meets `min_profit`.
+    pub fn meets_min_profit(&self, profit: U256, decimals: u32) -> bool {
+        let min_profit = self.min_profit();
+        min_profit == 0.0
+            || format_units(profit, decimals)
+                .ok()
+                .and_then(|profit| profit.parse::<f64>().ok())
+                .map(|profit| profit >= min_profit)
+                .unwrap_or(false)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Waits until the scan is resumed, if it's paused.
+    pub async fn wait_if_paused(&self) {
+        if self.paused.load(Ordering::Relaxed) {
+            info!("scan paused");
+            while self.paused.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            info!("scan resumed");
+        }
+    }
+
+    fn status(&self) -> String {
+        json!({
+            "batchSize": self.batch_size(),
+            "concurrency": self.concurrency(),
+            "minProfit": self.min_profit(),
+            "paused": self.paused.load(Ordering::Relaxed),
+            "autoTune": self.tuner.lock().unwrap().as_ref().map(|tuner| {
+                json!({ "min": tuner.bounds.start(), "max": tuner.bounds.end() })
+            }),
+        })
+        .to_string()
+    }
+
+    /// Runs a single control command, returning the resulting status as JSON.
+    ///
+    /// Commands: `status`, `pause`, `resume`, `set <batch_size|concurrency|min_profit> <value>`,
+    /// `auto_tune <min> <max>`, `auto_tune off`. Setting the batch size or concurrency stops auto-tuning.
+    pub fn handle_command(&self, command: &str) -> Result<String> {
+        let words = command.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            [] | ["status"] => {}
+            ["pause"] => self.set_paused(true),
+            ["resume"] => self.set_paused(false),
+            ["set", "batch_size", value] => {
+                self.set_batch_size(value.parse()?)?;
+                self.stop_auto_tune();
+            }
+            ["set", "concurrency", value] => {
+                self.set_concurrency(value.parse()?)?;
+                self.stop_auto_tune();
+            }
+            ["auto_tune", "off"] => self.stop_auto_tune(),
+            ["auto_tune", min, max] => self.auto_tune(min.parse()?..=max.parse()?)?,
+            ["set", "min_profit", value] => self.set_min_profit(value.parse()?)?,
+            _ => return Err(anyhow::format_err!("unknown command '{}'", command)),
+        }
+        Ok(self.status())
+    }
+
+    /// Listens for control commands on a unix socket at `path`, one command per line.
+    ///
+    /// Each command is answered with one line: the current status, or `{"error": ...}`.
+    pub async fn serve(self: Arc<Self>, path: PathBuf) -> Result<()> {
+        // a socket left over from a previous run would make bind fail
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        info!("listening for scan control commands on {:?}", path);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let control = self.clone();
+            tokio::task::spawn(async move {
+                if let Err(err) = control.handle_connection(stream).await {
+                    debug!("control connection closed: {}", err);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match self.handle_command(&line) {
+                Ok(status) => {
+                    info!("control command '{}' => {}", line.trim(), status);
+                    status
+                }
+                Err(err) => {
+                    log_error!("control command '{}' failed: {}", line.trim(), err);
+                    json!({ "error": err.to_string() }).to_string()
+                }
+            };
+            writer
+                .write_all(format!("{}\n", response).as_bytes())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_handles_control_commands() -> Result<()> {
+        let control = ScanControl::new(8, 4);
+        control.handle_command("set batch_size 16")?;
+        control.handle_command("set concurrency 6")?;
+        control.handle_command("set min_profit 0.05")?;
+        assert_eq!(control.batch_size(), 16);
+        assert_eq!(control.sim_limit().available_permits(), 6);
+        assert!(!control.meets_min_profit(U256::exp10(16), 18));
+        assert!(control.meets_min_profit(U256::exp10(17), 18));
+        assert!(control.handle_command("set batch_size 0").is_err());
+        assert!(control.handle_command("set min_profit -1").is_err());
+        assert!(control.handle_command("set min_profit NaN").is_err());
+        assert!(control.handle_command("launch rockets").is_err());
+
+        control.handle_command("set concurrency 2")?;
+        tokio::task::yield_now().await;
+        assert_eq!(control.sim_limit().available_permits(), 2);
+        Ok(())
+    }
+
+    fn batch(batch_size: usize, elapsed_ms: u64, num_failed: usize, latency_ms: u64) -> BatchStats {
+        BatchStats {
+            batch_size,
+            num_txs: batch_size,
+            num_failed,
+            rpc: FetchStats {
+                requests: batch_size,
+                errors: 0,
+                latency: Duration::from_millis(latency_ms),
+            },
+            elapsed: Duration::from_millis(elapsed_ms),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_auto_tunes_batch_size() -> Result<()> {
+        let control = ScanControl::new(64, 64);
+        assert!(control.auto_tune(0..=8).is_err());
+        control.handle_command("auto_tune 4 40")?;
+        // clamped into the bounds
+        assert_eq!(control.batch_size(), 40);
+        control.set_batch_size(16)?;
+
+        // healthy full batches raise the size
+        control.observe_batch(&batch(16, 1000, 0, 10));
+        assert_eq!(control.batch_size(), 18);
+        // throughput dropped after raising it, so it goes back
+        control.observe_batch(&batch(18, 2000, 0, 10));
+        assert_eq!(control.batch_size(), 16);
+        // a partial batch doesn't change it
+        let partial = BatchStats {
+            num_txs: 3,
+            ..batch(16, 100, 0, 10)
+        };
+        control.observe_batch(&partial);
+        assert_eq!(control.batch_size(), 16);
+        // errors & slow RPC requests halve it, down to the min
+        control.observe_batch(&batch(16, 1000, 4, 10));
+        assert_eq!(control.batch_size(), 8);
+        control.observe_batch(&batch(8, 1000, 0, 50));
+        assert_eq!(control.batch_size(), 4);
+        control.observe_batch(&batch(4, 1000, 0, 50));
+        assert_eq!(control.batch_size(), 4);
+        assert_eq!(control.concurrency(), 4);
+
+        // setting the size by hand stops tuning
+        control.handle_command("set batch_size 12")?;
+        control.observe_batch(&batch(12, 1000, 0, 10));
+        assert_eq!(control.batch_size(), 12);
+        Ok(())
+    }
+}
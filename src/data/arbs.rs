@@ -1,17 +1,27 @@
 use futures::future::join_all;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{collections::BTreeSet, sync::Arc};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
 
 use super::db::DbEngine;
 use crate::{
     data::{db::Db, file::FileWriter},
-    info,
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
+    debug, info,
+    interfaces::{
+        AddressLabel, IndexedPool, SimArbResultBatch, SimFailure, SimLiquidationResult,
+        StoredArbsRanges,
+    },
     Result,
 };
 use async_trait::async_trait;
 use deadqueue::unlimited::Queue;
-use ethers::{types::U256, utils::format_ether};
+use ethers::{
+    types::{H256, U256},
+    utils::format_ether,
+};
+use mev_share_sse::EventHistory;
 
 const NUM_ARBS_PER_READ: i64 = 3000;
 
@@ -22,6 +32,10 @@ pub struct ArbFilterParams {
     pub timestamp_start: Option<u32>,
     pub timestamp_end: Option<u32>,
     pub min_profit: Option<U256>,
+    /// Only match arbs simulated on this chain.
+    pub chain_id: Option<u64>,
+    /// Only match arbs produced by the scan run with this label.
+    pub run_label: Option<String>,
 }
 
 impl Default for ArbFilterParams {
@@ -38,6 +52,8 @@ impl ArbFilterParams {
             timestamp_start: None,
             timestamp_end: None,
             min_profit: None,
+            chain_id: None,
+            run_label: None,
         }
     }
 }
@@ -51,6 +67,10 @@ pub enum WriteEngine {
 #[async_trait]
 pub trait ArbDb: Sync + Send {
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()>;
+    /// Saves the `realized` field of arbs that are already stored.
+    async fn write_realized(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()>;
+    /// Saves the `orphaned` flag of arbs that are already stored.
+    async fn write_orphaned(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()>;
     async fn read_arbs(
         &self,
         filter_params: &ArbFilterParams,
@@ -58,7 +78,53 @@ pub trait ArbDb: Sync + Send {
         limit: Option<i64>,
     ) -> Result<Vec<SimArbResultBatch>>;
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64>;
+    /// Saves raw MEV-Share events (simulated or not). Events that are already saved are overwritten.
+    async fn write_events(&self, events: &Vec<EventHistory>) -> Result<()>;
+    /// Reads saved events. Only the block & timestamp ranges of `filter_params` apply.
+    async fn read_events(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<EventHistory>>;
+    /// Saves a batch of results & failures as one write: arbs replace those saved for the same tx,
+    /// and the failures of the batch's txs replace their saved failures, so writing a batch again
+    /// (e.g. re-running a scan that crashed mid-write) converges instead of duplicating it.
+    async fn write_results(
+        &self,
+        arbs: &Vec<SimArbResultBatch>,
+        failures: &Vec<SimFailure>,
+    ) -> Result<()>;
+    /// Saves failed simulations, so that a scan's failures can be triaged later.
+    async fn write_failures(&self, failures: &Vec<SimFailure>) -> Result<()>;
+    /// Reads saved failures. The block & timestamp ranges, chain id, and run label of `filter_params` apply.
+    async fn read_failures(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimFailure>>;
+    /// Saves simulated liquidations. Liquidations saved for the same txs (in the same chain & run) are replaced,
+    /// so scanning a tx again doesn't duplicate them.
+    async fn write_liquidations(&self, liquidations: &[SimLiquidationResult]) -> Result<()>;
+    /// Reads saved liquidations. The block & timestamp ranges, chain id, and run label of `filter_params` apply.
+    async fn read_liquidations(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimLiquidationResult>>;
+    /// Saves pools found by the `indexer`. Pools already saved for the same chain & address are replaced.
+    async fn write_pools(&self, pools: &[IndexedPool]) -> Result<()>;
+    /// Reads the indexed pools of `chain_id`.
+    async fn read_pools(&self, chain_id: u64) -> Result<Vec<IndexedPool>>;
+    /// Saves address labels (see `labels::Labels`). Labels already saved for the same chain & address are replaced.
+    async fn write_labels(&self, labels: &[AddressLabel]) -> Result<()>;
+    /// Reads the saved address labels of `chain_id`.
+    async fn read_labels(&self, chain_id: u64) -> Result<Vec<AddressLabel>>;
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges>;
+    /// Chains that saved arbs were simulated on. Arbs saved before chains were tagged count as mainnet.
+    async fn get_chain_ids(&self) -> Result<Vec<u64>>;
     async fn export_arbs(
         &self,
         write_dest: WriteEngine,
@@ -110,7 +176,7 @@ pub async fn export_arbs_core(
                 break;
             }
             *offset = *offset + NUM_ARBS_PER_READ as u64;
-            println!("offset {}", offset);
+            debug!("offset {}", offset);
             let start_block = arbs.iter().map(|arb| arb.event.block).min().unwrap_or(0);
             let end_block = arbs
                 .iter()
@@ -138,9 +204,9 @@ pub async fn export_arbs_core(
             );
 
             for arb in arbs {
-                println!("im arb: {:?}", arb.event.hint.hash);
+                debug!("im arb: {:?}", arb.event.hint.hash);
                 arb_queue.push(arb);
-                println!("arb q: len {}", arb_queue.len());
+                debug!("arb q: len {}", arb_queue.len());
             }
             // arb_lock is dropped here, unlocking the arb_queue mutex
         }
@@ -153,7 +219,7 @@ pub async fn export_arbs_core(
     // init chosen write engine
     let write_engine = match write_dest.clone() {
         WriteEngine::File(filename) => Arc::new(FileWriter::new(filename)),
-        WriteEngine::Db(db_engine) => Db::new(db_engine).await.connect,
+        WriteEngine::Db(db_engine) => Db::try_new(db_engine).await?.connect,
     };
 
     let total_arbs = Arc::new(Mutex::new(0));
@@ -162,7 +228,7 @@ pub async fn export_arbs_core(
     let write_handle = tokio::spawn(async move {
         info!("starting writer thread...");
         loop {
-            println!("[w] arb q {}", arb_queue.len());
+            debug!("[w] arb q {}", arb_queue.len());
             let mut batch_arbs = vec![];
             for _ in 0..arb_queue.len() {
                 let arb = arb_queue.pop().await;
@@ -202,3 +268,115 @@ pub async fn export_arbs_core(
 }
 
 pub type ArbDatabase = Arc<dyn ArbDb>;
+
+/// Txs that a batch of results covers, keyed by (tx hash, chain id, run label) like their saved records.
+pub fn written_txs(
+    arbs: &[SimArbResultBatch],
+    failures: &[SimFailure],
+) -> BTreeSet<(H256, u64, Option<String>)> {
+    arbs.iter()
+        .map(|arb| (arb.event.hint.hash, arb.chain_id, arb.run_label.to_owned()))
+        .chain(failures.iter().map(|failure| {
+            (
+                failure.tx_hash,
+                failure.chain_id,
+                failure.run_label.to_owned(),
+            )
+        }))
+        .collect()
+}
+
+/// The txs (with their chain & run) that `liquidations` were simulated on.
+pub fn liquidated_txs(
+    liquidations: &[SimLiquidationResult],
+) -> BTreeSet<(H256, u64, Option<String>)> {
+    liquidations
+        .iter()
+        .map(|liquidation| {
+            (
+                liquidation.tx_hash,
+                liquidation.chain_id,
+                liquidation.run_label.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Number of processed txs that can be waiting for an `ArbWriter` before senders have to wait.
+pub const WRITE_QUEUE_SIZE: usize = 1024;
+
+/// Saves results & failures to a DB in the background, as they're sent.
+///
+/// Whatever has queued up while a write was in flight is saved with the next write.
+pub struct ArbWriter {
+    queue: mpsc::Sender<(Option<SimArbResultBatch>, Vec<SimFailure>)>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl ArbWriter {
+    /// Starts writing to `db`.
+    pub fn spawn(db: ArbDatabase) -> Self {
+        let (queue, mut received) = mpsc::channel(WRITE_QUEUE_SIZE);
+        let handle = tokio::task::spawn(async move {
+            while let Some(first) = received.recv().await {
+                let mut arbs = vec![];
+                let mut failures = vec![];
+                let queued = std::iter::from_fn(|| received.try_recv().ok());
+                for (arb, tx_failures) in std::iter::once(first).chain(queued) {
+                    arbs.extend(arb);
+                    failures.extend(tx_failures);
+                }
+                db.write_results(&arbs, &failures).await?;
+                debug!(
+                    num_arbs = arbs.len(),
+                    num_failures = failures.len(),
+                    "wrote results"
+                );
+            }
+            Ok(())
+        });
+        Self { queue, handle }
+    }
+
+    /// Queues a tx's result (if any) & failures to be saved.
+    ///
+    /// Fails if the writer stopped; `finish` returns the error that stopped it.
+    pub async fn write(
+        &self,
+        arb: Option<SimArbResultBatch>,
+        failures: Vec<SimFailure>,
+    ) -> Result<()> {
+        self.queue
+            .send((arb, failures))
+            .await
+            .map_err(|_| anyhow::format_err!("arb writer stopped"))
+    }
+
+    /// Waits for everything queued to be saved, returning the first error that a write hit (if any).
+    pub async fn finish(self) -> Result<()> {
+        drop(self.queue);
+        self.handle.await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keys_written_txs_by_run() {
+        let arb = SimArbResultBatch::test_example();
+        let mut failure = SimFailure::new(&arb.event, None, "revert");
+        failure.run_label = arb.run_label.to_owned();
+        let mut other_run = failure.clone();
+        other_run.run_label = Some("other".to_owned());
+
+        let txs = written_txs(
+            std::slice::from_ref(&arb),
+            &[failure.clone(), failure, other_run],
+        );
+        assert_eq!(txs.len(), 2);
+        assert!(txs.contains(&(arb.event.hint.hash, arb.chain_id, arb.run_label)));
+        assert!(txs.contains(&(arb.event.hint.hash, 1, Some("other".to_owned()))));
+    }
+}
@@ -0,0 +1,310 @@
+use crate::{
+    data::{
+        csv::csv_rows,
+        file::EXPORT_DIR,
+        tokens::{read_cache, write_cache},
+    },
+    info, log_error, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Klines endpoint of Binance's public API.
+const BINANCE_KLINES_URL: &str = "https://api.binance.com/api/v3/klines";
+/// Most 1-minute candles that Binance returns per request.
+const BINANCE_KLINES_LIMIT: u64 = 1000;
+/// Tokens that are priced as the asset they wrap on exchanges.
+const WRAPPED_SYMBOLS: [(&str, &str); 4] = [
+    ("WETH", "ETH"),
+    ("WBTC", "BTC"),
+    ("WMATIC", "MATIC"),
+    ("WBNB", "BNB"),
+];
+/// Symbols that exchanges quote pairs in, in order of preference.
+const QUOTE_SYMBOLS: [&str; 5] = ["USDT", "USDC", "DAI", "BTC", "ETH"];
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CexPricePoint {
+    /// Pair in `BASE/QUOTE` form, e.g. `ETH/USDT`.
+    pub pair: String,
+    /// Unix timestamp (seconds) that the price was observed at.
+    pub timestamp: u64,
+    /// Quote per base, e.g. USDT per ETH.
+    pub price: f64,
+}
+
+/// Where `CexPriceStore` gets its prices from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CexPriceSource {
+    /// A CSV file of `timestamp,pair,price` rows, e.g. `1690000000,ETH/USDT,1890.5`.
+    Csv(PathBuf),
+    /// Binance's 1-minute candles, fetched as needed & cached in `EXPORT_DIR`.
+    Binance,
+}
+
+/// Series of centralized-exchange prices by pair, to compare DEX prices against.
+#[derive(Clone, Debug, Default)]
+pub struct CexPriceStore {
+    /// Where fetched prices are saved; None for prices read from a file.
+    path: Option<PathBuf>,
+    prices: BTreeMap<String, BTreeMap<u64, f64>>,
+}
+
+/// Names the asset that an on-chain token symbol trades as on exchanges, e.g. WETH => ETH.
+pub fn exchange_symbol(symbol: &str) -> String {
+    let symbol = symbol.to_uppercase();
+    WRAPPED_SYMBOLS
+        .iter()
+        .find(|(wrapped, _)| *wrapped == symbol)
+        .map(|(_, asset)| asset.to_string())
+        .unwrap_or(symbol)
+}
+
+/// Orders two exchange symbols into the pair that exchanges list, e.g. (USDC, ETH) => ETH/USDC.
+///
+/// The quote is whichever of `QUOTE_SYMBOLS` comes first; pairs of two other assets are kept as (a, b).
+pub fn exchange_pair(a: &str, b: &str) -> (String, String) {
+    let rank = |symbol: &str| QUOTE_SYMBOLS.iter().position(|quote| *quote == symbol);
+    match (rank(a), rank(b)) {
+        (Some(rank_a), Some(rank_b)) if rank_a < rank_b => (b.to_owned(), a.to_owned()),
+        (Some(_), None) => (b.to_owned(), a.to_owned()),
+        _ => (a.to_owned(), b.to_owned()),
+    }
+}
+
+fn pair_name(base: &str, quote: &str) -> String {
+    format!("{}/{}", base, quote)
+}
+
+/// Parses a CSV of `timestamp,pair,price` rows. A header row, blank lines & `#` comment lines are skipped.
+pub fn parse_csv(contents: &str) -> Result<Vec<CexPricePoint>> {
+    let mut points = vec![];
+    for (i, (line, fields)) in csv_rows(contents).into_iter().enumerate() {
+        if i == 0 && fields[0].eq_ignore_ascii_case("timestamp") {
+            continue;
+        }
+        let point = match fields.as_slice() {
+            [timestamp, pair, price] => pair.split_once('/').and_then(|(base, quote)| {
+                Some(CexPricePoint {
+                    pair: pair_name(&base.to_uppercase(), &quote.to_uppercase()),
+                    timestamp: timestamp.parse().ok()?,
+                    price: price.parse().ok().filter(|price: &f64| *price > 0.0)?,
+                })
+            }),
+            _ => None,
+        };
+        points.push(point.ok_or(anyhow::format_err!(
+            "invalid CEX price on line {}: {:?} (expected timestamp,BASE/QUOTE,price)",
+            line,
+            fields.join(",")
+        ))?);
+    }
+    Ok(points)
+}
+
+/// Parses Binance klines into the open price of each candle, by the candle's start (in seconds).
+fn parse_klines(klines: &[serde_json::Value]) -> Vec<(u64, f64)> {
+    klines
+        .iter()
+        .filter_map(|kline| {
+            let open_time = kline.get(0)?.as_u64()?;
+            let open = kline.get(1)?.as_str()?.parse().ok()?;
+            Some((open_time / 1000, open))
+        })
+        .collect()
+}
+
+impl CexPriceStore {
+    /// Loads the prices of `source`. Binance prices start from what earlier runs cached.
+    pub fn load(source: &CexPriceSource) -> Result<Self> {
+        match source {
+            CexPriceSource::Csv(path) => Self::from_csv(path),
+            CexPriceSource::Binance => {
+                let path = PathBuf::from(EXPORT_DIR).join("cex_binance.json");
+                let mut store = Self::from_points(read_cache::<CexPricePoint>(&path)?);
+                store.path = Some(path);
+                Ok(store)
+            }
+        }
+    }
+
+    pub fn from_csv(path: &Path) -> Result<Self> {
+        let points = parse_csv(&std::fs::read_to_string(path)?)
+            .map_err(|err| anyhow::format_err!("invalid CEX prices {:?}: {}", path, err))?;
+        Ok(Self::from_points(points))
+    }
+
+    pub fn from_points(points: Vec<CexPricePoint>) -> Self {
+        let mut store = Self::default();
+        for point in points {
+            store
+                .prices
+                .entry(point.pair)
+                .or_default()
+                .insert(point.timestamp, point.price);
+        }
+        store
+    }
+
+    /// Price of `base` in `quote` (exchange symbols) as of `timestamp`: the latest price at or before it, if that's no
+    /// more than `max_age` seconds old. Read from the inverse pair if only that's known.
+    pub fn price(&self, base: &str, quote: &str, timestamp: u64, max_age: u64) -> Option<f64> {
+        let latest = |pair: &str| {
+            self.prices
+                .get(pair)?
+                .range(..=timestamp)
+                .next_back()
+                .filter(|(observed, _)| timestamp - **observed <= max_age)
+                .map(|(_, price)| *price)
+        };
+        latest(&pair_name(base, quote)).or_else(|| {
+            latest(&pair_name(quote, base))
+                .filter(|price| *price > 0.0)
+                .map(|price| 1.0 / price)
+        })
+    }
+
+    /// Fetches the Binance candles of each (base, quote, timestamp) that isn't covered yet, then saves the store.
+    /// Does nothing for prices read from a file.
+    ///
+    /// Pairs that Binance doesn't list, and candles that can't be fetched, are skipped (and retried next time).
+    pub async fn fetch_missing(&mut self, needed: &[(String, String, u64)]) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path.to_owned(),
+            None => return Ok(()),
+        };
+        let mut missing: BTreeMap<String, BTreeSet<u64>> = BTreeMap::new();
+        for (base, quote, timestamp) in needed {
+            let (base, quote) = exchange_pair(base, quote);
+            let minute = timestamp - timestamp % 60;
+            let pair = pair_name(&base, &quote);
+            if !self
+                .prices
+                .get(&pair)
+                .is_some_and(|prices| prices.contains_key(&minute))
+            {
+                missing.entry(pair).or_default().insert(minute);
+            }
+        }
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let client = reqwest::Client::new();
+        for (pair, minutes) in missing {
+            info!(
+                "fetching {} minutes of {} from Binance",
+                minutes.len(),
+                pair
+            );
+            let symbol = pair.replace('/', "");
+            let mut covered_until = 0;
+            for minute in minutes {
+                if minute < covered_until {
+                    continue;
+                }
+                covered_until = minute + BINANCE_KLINES_LIMIT * 60;
+                let response = client
+                    .get(BINANCE_KLINES_URL)
+                    .query(&[
+                        ("symbol", symbol.to_owned()),
+                        ("interval", "1m".to_owned()),
+                        ("startTime", (minute * 1000).to_string()),
+                        ("limit", BINANCE_KLINES_LIMIT.to_string()),
+                    ])
+                    .send()
+                    .await;
+                let klines = match response {
+                    Ok(response) if response.status().is_success() => {
+                        response.json::<Vec<serde_json::Value>>().await?
+                    }
+                    Ok(response) => {
+                        // most likely a pair that Binance doesn't list
+                        log_error!(
+                            "Binance returned {} for {}: {}",
+                            response.status(),
+                            symbol,
+                            response.text().await.unwrap_or_default()
+                        );
+                        break;
+                    }
+                    Err(err) => {
+                        log_error!("failed to fetch {} from Binance: {}", symbol, err);
+                        break;
+                    }
+                };
+                self.prices
+                    .entry(pair.to_owned())
+                    .or_default()
+                    .extend(parse_klines(&klines));
+            }
+        }
+        let points = self
+            .prices
+            .iter()
+            .flat_map(|(pair, prices)| {
+                prices.iter().map(|(timestamp, price)| CexPricePoint {
+                    pair: pair.to_owned(),
+                    timestamp: *timestamp,
+                    price: *price,
+                })
+            })
+            .collect::<Vec<_>>();
+        write_cache(&path, points.iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_prices_from_csv() -> Result<()> {
+        let points = parse_csv(
+            "timestamp,pair,price\n\
+             # hourly closes\n\
+             1690000000,eth/usdt,1890.5\n\
+             \n\
+             1690003600,ETH/USDT,1900\n",
+        )?;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].pair, "ETH/USDT");
+        assert!(parse_csv("1690000000,ETHUSDT,1890.5").is_err());
+        assert!(parse_csv("1690000000,ETH/USDT,-1").is_err());
+
+        let store = CexPriceStore::from_points(points);
+        assert_eq!(store.price("ETH", "USDT", 1690000100, 600), Some(1890.5));
+        assert_eq!(store.price("ETH", "USDT", 1690003600, 0), Some(1900.0));
+        // too old, or before the series starts
+        assert_eq!(store.price("ETH", "USDT", 1690000700, 600), None);
+        assert_eq!(store.price("ETH", "USDT", 1689999999, 600), None);
+        // inverse pair
+        let inverse = store.price("USDT", "ETH", 1690003600, 0).unwrap();
+        assert!((inverse - 1.0 / 1900.0).abs() < 1e-15);
+        Ok(())
+    }
+
+    #[test]
+    fn it_names_exchange_pairs() {
+        assert_eq!(exchange_symbol("WETH"), "ETH");
+        assert_eq!(exchange_symbol("link"), "LINK");
+        let pair = |a, b| exchange_pair(a, b);
+        assert_eq!(pair("USDC", "ETH"), ("ETH".to_owned(), "USDC".to_owned()));
+        assert_eq!(pair("ETH", "USDT"), ("ETH".to_owned(), "USDT".to_owned()));
+        assert_eq!(pair("ETH", "LINK"), ("LINK".to_owned(), "ETH".to_owned()));
+        assert_eq!(pair("USDC", "USDT"), ("USDC".to_owned(), "USDT".to_owned()));
+        assert_eq!(pair("PEPE", "LINK"), ("PEPE".to_owned(), "LINK".to_owned()));
+    }
+
+    #[test]
+    fn it_parses_binance_klines() {
+        let klines = serde_json::from_str::<Vec<serde_json::Value>>(
+            r#"[[1690000020000,"1890.50","1891.00","1889.00","1890.00","12.5",1690000079999,"0",10,"0","0","0"]]"#,
+        )
+        .unwrap();
+        assert_eq!(parse_klines(&klines), vec![(1690000020, 1890.5)]);
+    }
+}
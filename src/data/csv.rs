@@ -0,0 +1,46 @@
+/// Splits CSV `contents` into the fields of each row, by its line number (from 1). Blank lines & `#` comment
+/// lines are skipped. Quoted fields may contain commas (and `""` for a quote), and fields are trimmed.
+pub fn csv_rows(contents: &str) -> Vec<(usize, Vec<String>)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| (i + 1, split_csv_line(line)))
+        .collect()
+}
+
+/// Splits a CSV line into its fields, unquoting quoted ones.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields.iter().map(|field| field.trim().to_owned()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_csv_rows() {
+        let rows = csv_rows("a, b ,\"c, \"\"d\"\"\"\n\n  # comment\n1,\"#2\",3");
+        assert_eq!(
+            rows,
+            vec![
+                (1, vec!["a".into(), "b".into(), "c, \"d\"".into()]),
+                (4, vec!["1".into(), "#2".into(), "3".into()]),
+            ]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    config::Config,
     data::{
         arbs::ArbDatabase,
         mongo::{MongoConfig, MongoConnect},
@@ -7,33 +8,42 @@ use crate::{
     Result,
 };
 use std::sync::Arc;
-use strum::{EnumIter, IntoEnumIterator};
+
+/// Names of the supported DB engines, as accepted by `--db` and the `DB` config setting.
+pub const DB_ENGINE_NAMES: [&'static str; 2] = ["mongo", "postgres"];
 
 pub struct Db {
     pub connect: ArbDatabase,
 }
 
-#[derive(Clone, Debug, EnumIter)]
+#[derive(Clone, Debug)]
 pub enum DbEngine {
     Mongo(MongoConfig),
     Postgres(PostgresConfig),
 }
 
 impl DbEngine {
-    pub fn enum_flags() -> String {
-        format!(
-            "{}",
-            DbEngine::iter()
-                .map(|engine| engine.to_string())
-                .reduce(|a, b| format!("{} | {}", a, b))
-                .expect("failed to reduce db engines to string")
-        )
+    /// Returns the same engine, storing arbs under `namespace` (DB name for mongo, table for postgres).
+    pub fn with_namespace(self, namespace: &str) -> Self {
+        match self {
+            DbEngine::Mongo(config) => DbEngine::Mongo(MongoConfig {
+                namespace: namespace.to_owned(),
+                ..config
+            }),
+            DbEngine::Postgres(config) => DbEngine::Postgres(PostgresConfig {
+                namespace: namespace.to_owned(),
+                ..config
+            }),
+        }
     }
-}
 
-impl Default for DbEngine {
-    fn default() -> Self {
-        DbEngine::Mongo(MongoConfig::default())
+    /// Builds the named engine ("mongo" or "postgres") with connection settings from `config`.
+    pub fn from_config(name: &str, config: &Config) -> Result<Self> {
+        match name {
+            "mongo" => Ok(DbEngine::Mongo(MongoConfig::from(config))),
+            "postgres" => Ok(DbEngine::Postgres(PostgresConfig::try_from(config)?)),
+            _ => Err(anyhow::format_err!("invalid db engine: {}", name)),
+        }
     }
 }
 
@@ -46,35 +56,23 @@ impl std::fmt::Display for DbEngine {
     }
 }
 
-// serialize/deserialize from string
-impl std::str::FromStr for DbEngine {
-    type Err = String;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "mongo" => Ok(DbEngine::Mongo(MongoConfig::default())),
-            "postgres" => Ok(DbEngine::Postgres(PostgresConfig::default())),
-            _ => Err(format!("invalid db engine: {}", s)),
-        }
-    }
-}
-
 impl Db {
-    pub async fn new(engine: DbEngine) -> Self {
-        match engine {
-            DbEngine::Mongo(config) => Db {
-                connect: Arc::new(
-                    MongoConnect::new(config.to_owned())
-                        .await
-                        .expect(&format!("failed to connect to mongo db at {}", config.url)),
-                ),
-            },
-            DbEngine::Postgres(config) => {
-                Db {
-                    connect: Arc::new(PostgresConnect::new(config.to_owned()).await.expect(
-                        &format!("failed to connect to postgres db at {:?}", config.url),
-                    )),
-                }
-            }
-        }
+    /// Connects to the DB of `engine`.
+    pub async fn try_new(engine: DbEngine) -> Result<Self> {
+        let target = match &engine {
+            DbEngine::Mongo(config) => format!("mongo db at {}", config.url),
+            DbEngine::Postgres(config) => format!("postgres db at {:?}", config.url),
+        };
+        let connect: Result<ArbDatabase> = match engine {
+            DbEngine::Mongo(config) => MongoConnect::new(config)
+                .await
+                .map(|connect| Arc::new(connect) as ArbDatabase),
+            DbEngine::Postgres(config) => PostgresConnect::new(config)
+                .await
+                .map(|connect| Arc::new(connect) as ArbDatabase),
+        };
+        let connect = connect
+            .map_err(|err| anyhow::format_err!("failed to connect to {}: {}", target, err))?;
+        Ok(Db { connect })
     }
 }
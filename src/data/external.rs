@@ -0,0 +1,250 @@
+use crate::data::{
+    csv::csv_rows,
+    tokens::{read_cache, write_cache},
+    EXPORT_DIR,
+};
+use crate::Result;
+use ethers::types::{Address, H256, I256};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+pub const DATASET_SOURCE_NAMES: [&str; 2] = ["mev-inspect", "dune"];
+
+/// Header names that a dataset's columns may have, in order of preference.
+const TX_HASH_COLUMNS: [&str; 3] = ["transaction_hash", "tx_hash", "hash"];
+const BLOCK_COLUMNS: [&str; 2] = ["block_number", "block"];
+const SEARCHER_COLUMNS: [&str; 3] = ["account_address", "searcher", "tx_from"];
+const PROFIT_TOKEN_COLUMNS: [&str; 3] = ["profit_token_address", "profit_token", "token_address"];
+const PROFIT_AMOUNT_COLUMNS: [&str; 2] = ["profit_amount", "profit_raw"];
+const PROFIT_USD_COLUMNS: [&str; 2] = ["profit_usd", "profit_amount_usd"];
+/// mev-inspect's column for arbitrages whose tx reverted or couldn't be classified.
+const ERROR_COLUMN: &str = "error";
+
+/// Where an external dataset of arbitrages came from.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatasetSource {
+    /// mev-inspect-py's `arbitrages` table, exported as CSV with a header,
+    /// e.g. `\copy (SELECT * FROM arbitrages) TO 'arbs.csv' CSV HEADER`.
+    MevInspect,
+    /// A Dune query's CSV export, with a row per arbitrage tx.
+    Dune,
+}
+
+impl std::str::FromStr for DatasetSource {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mev-inspect" => Ok(DatasetSource::MevInspect),
+            "dune" => Ok(DatasetSource::Dune),
+            _ => Err(format!("invalid dataset source: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for DatasetSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatasetSource::MevInspect => write!(f, "mev-inspect"),
+            DatasetSource::Dune => write!(f, "dune"),
+        }
+    }
+}
+
+/// An arbitrage tx that an external dataset classified, with its profit in one token.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalArb {
+    pub source: DatasetSource,
+    pub tx_hash: H256,
+    pub block: u64,
+    /// The arbitrageur's account, if the dataset has it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub searcher: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profit_token: Option<Address>,
+    /// Profit in the profit token's smallest unit, if the dataset has it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profit_amount: Option<I256>,
+    /// Profit in USD, if the dataset has it (Dune exports usually do).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profit_usd: Option<f64>,
+}
+
+/// Index of the first of `names` in `header`.
+fn column(header: &[String], names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .find_map(|name| header.iter().position(|column| column == name))
+}
+
+/// Parses a CSV export of `source` (see `DatasetSource`) into one arb per tx & profit token.
+///
+/// Columns are found by their header, so extra columns (and any order) are fine. Every dataset needs a tx hash
+/// & block column, and mev-inspect's also needs its profit token & amount. Rows of mev-inspect arbitrages with
+/// an error are skipped, and the profits of several arbitrages in one tx are summed.
+pub fn parse_dataset(source: DatasetSource, contents: &str) -> Result<Vec<ExternalArb>> {
+    let mut rows = csv_rows(contents).into_iter();
+    let header = match rows.next() {
+        Some((_, fields)) => fields
+            .iter()
+            .map(|field| field.to_lowercase())
+            .collect::<Vec<_>>(),
+        None => return Ok(vec![]),
+    };
+    let required = |names: &[&str]| {
+        column(&header, names).ok_or(anyhow::format_err!(
+            "{} dataset has no {} column",
+            source,
+            names[0]
+        ))
+    };
+    let tx_hash_col = required(&TX_HASH_COLUMNS)?;
+    let block_col = required(&BLOCK_COLUMNS)?;
+    let (profit_token_col, profit_amount_col) = match source {
+        DatasetSource::MevInspect => (
+            Some(required(&PROFIT_TOKEN_COLUMNS)?),
+            Some(required(&PROFIT_AMOUNT_COLUMNS)?),
+        ),
+        DatasetSource::Dune => (
+            column(&header, &PROFIT_TOKEN_COLUMNS),
+            column(&header, &PROFIT_AMOUNT_COLUMNS),
+        ),
+    };
+    let searcher_col = column(&header, &SEARCHER_COLUMNS);
+    let profit_usd_col = column(&header, &PROFIT_USD_COLUMNS);
+    let error_col = column(&header, &[ERROR_COLUMN]);
+
+    let mut arbs: BTreeMap<(H256, Option<Address>), ExternalArb> = BTreeMap::new();
+    for (line, fields) in rows {
+        if fields.len() != header.len() {
+            return Err(anyhow::format_err!(
+                "line {} of the {} dataset has {} fields, but the header has {}",
+                line,
+                source,
+                fields.len(),
+                header.len()
+            ));
+        }
+        // empty & NULL fields are missing values
+        let field = |col: Option<usize>| {
+            col.map(|col| fields[col].as_str())
+                .filter(|value| !value.is_empty() && !value.eq_ignore_ascii_case("null"))
+        };
+        if source == DatasetSource::MevInspect && field(error_col).is_some() {
+            continue;
+        }
+        let invalid = |name: &str| {
+            anyhow::format_err!(
+                "invalid {} on line {} of the {} dataset",
+                name,
+                line,
+                source
+            )
+        };
+        let tx_hash: H256 = field(Some(tx_hash_col))
+            .and_then(|value| value.parse().ok())
+            .ok_or(invalid("tx hash"))?;
+        let block = field(Some(block_col))
+            .and_then(|value| value.parse().ok())
+            .ok_or(invalid("block"))?;
+        let searcher = field(searcher_col)
+            .map(|value| value.parse().map_err(|_| invalid("searcher")))
+            .transpose()?;
+        let profit_token = field(profit_token_col)
+            .map(|value| value.parse().map_err(|_| invalid("profit token")))
+            .transpose()?;
+        let profit_amount = field(profit_amount_col)
+            .map(|value| {
+                // NUMERIC columns may be exported with a fraction of zeros
+                let (units, fraction) = value.split_once('.').unwrap_or((value, ""));
+                match fraction.chars().all(|c| c == '0') {
+                    true => I256::from_dec_str(units).map_err(|_| invalid("profit amount")),
+                    false => Err(invalid("profit amount")),
+                }
+            })
+            .transpose()?;
+        let profit_usd = field(profit_usd_col)
+            .map(|value| value.parse::<f64>().map_err(|_| invalid("USD profit")))
+            .transpose()?;
+        let arb = arbs.entry((tx_hash, profit_token)).or_insert(ExternalArb {
+            source,
+            tx_hash,
+            block,
+            searcher,
+            profit_token,
+            profit_amount: None,
+            profit_usd: None,
+        });
+        if let Some(amount) = profit_amount {
+            arb.profit_amount = Some(arb.profit_amount.unwrap_or_default() + amount);
+        }
+        if let Some(usd) = profit_usd {
+            arb.profit_usd = Some(arb.profit_usd.unwrap_or_default() + usd);
+        }
+    }
+    let mut arbs = arbs.into_values().collect::<Vec<_>>();
+    arbs.sort_by_key(|arb| (arb.block, arb.tx_hash));
+    Ok(arbs)
+}
+
+fn store_path(chain_id: u64) -> PathBuf {
+    PathBuf::from(EXPORT_DIR).join(format!("external_arbs_{}.json", chain_id))
+}
+
+/// Reads the arbs imported for `chain_id` (see `import_external_arbs`), ordered by block.
+pub fn load_external_arbs(chain_id: u64) -> Result<Vec<ExternalArb>> {
+    read_cache(&store_path(chain_id))
+}
+
+/// Saves `arbs` to the imported arbs of `chain_id`, in the export directory. Arbs imported before from the same source,
+/// for the same tx & profit token, are replaced, so a dataset can be imported again after it's been updated.
+pub fn import_external_arbs(chain_id: u64, arbs: &[ExternalArb]) -> Result<()> {
+    let key = |arb: &ExternalArb| (arb.source, arb.tx_hash, arb.profit_token);
+    let mut stored = load_external_arbs(chain_id)?
+        .into_iter()
+        .map(|arb| (key(&arb), arb))
+        .collect::<BTreeMap<_, _>>();
+    stored.extend(arbs.iter().map(|arb| (key(arb), arb.to_owned())));
+    let mut stored = stored.into_values().collect::<Vec<_>>();
+    stored.sort_by_key(|arb| (arb.block, arb.tx_hash));
+    write_cache(&store_path(chain_id), stored.iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_datasets() -> Result<()> {
+        let tx = |n: u64| format!("{:?}", H256::from_low_u64_be(n));
+        let weth = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+        // mev-inspect's export: two arbitrages in one tx, and one that reverted
+        let mev_inspect = format!(
+            "id,block_number,transaction_hash,account_address,profit_token_address,start_amount,end_amount,profit_amount,error,protocols
+            1,17500000,{tx1},0x0000000000000000000000000000000000000b07,{weth},100,150,50,,\"{{uniswap_v2,uniswap_v3}}\"
+            2,17500000,{tx1},0x0000000000000000000000000000000000000b07,{weth},100,125,25.0,,\"{{uniswap_v2}}\"
+            3,17500001,{tx2},0x0000000000000000000000000000000000000b07,{weth},100,100,0,Reverted,\"{{uniswap_v2}}\"",
+            tx1 = tx(1),
+            tx2 = tx(2),
+            weth = weth
+        );
+        let arbs = parse_dataset(DatasetSource::MevInspect, &mev_inspect)?;
+        assert_eq!(arbs.len(), 1);
+        assert_eq!(arbs[0].block, 17500000);
+        assert_eq!(arbs[0].tx_hash, H256::from_low_u64_be(1));
+        assert_eq!(arbs[0].profit_token, Some(weth.parse()?));
+        assert_eq!(arbs[0].profit_amount, Some(I256::from(75)));
+        assert_eq!(arbs[0].searcher, Some(Address::from_low_u64_be(0xb07)));
+
+        // Dune's export may only have USD profits
+        let dune = format!("block_number,tx_hash,profit_usd\n17500002,{},12.5\n", tx(3));
+        let arbs = parse_dataset(DatasetSource::Dune, &dune)?;
+        assert_eq!(arbs[0].profit_usd, Some(12.5));
+        assert_eq!(arbs[0].profit_amount, None);
+        // ... but mev-inspect's must have token profits
+        assert!(parse_dataset(DatasetSource::MevInspect, &dune).is_err());
+        assert!(parse_dataset(DatasetSource::Dune, "block,tx_hash\n1,0x1234\n").is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,14 @@
 use crate::{
     data::arbs::{ArbDb, ArbFilterParams, WriteEngine},
     info,
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
+    interfaces::{
+        AddressLabel, IndexedPool, SimArbResultBatch, SimFailure, SimLiquidationResult,
+        StoredArbsRanges,
+    },
     Result,
 };
 use async_trait::async_trait;
+use mev_share_sse::EventHistory;
 use std::{
     fs::File,
     io::{BufWriter, Write},
@@ -26,6 +30,11 @@ fn parse_filename(filename: Option<String>) -> Result<String> {
     })
 }
 
+/// Error for the `ArbDb` operations that files don't support.
+fn unsupported(operation: &str) -> anyhow::Error {
+    anyhow::format_err!("{} is not supported by the file backend", operation)
+}
+
 #[derive(Clone, Debug)]
 pub struct FileWriter {
     pub filename: String,
@@ -65,28 +74,106 @@ impl ArbDb for FileWriter {
         self.save_arbs_to_file(arbs).await
     }
 
-    /* The following aren't really needed, but the trait requires them. Maybe I should break up the trait a bit.
-    (TODO: try breaking ArbDb trait into ArbReader and ArbWriter)
-    */
+    // Files are only an export target, so everything else is an error (rather than a panic) for callers to handle.
+    async fn write_realized(&self, _arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        Err(unsupported("write_realized"))
+    }
+    async fn write_orphaned(&self, _arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        Err(unsupported("write_orphaned"))
+    }
     async fn read_arbs(
         &self,
         _filter_params: &ArbFilterParams,
         _offset: Option<u64>,
         _limit: Option<i64>,
     ) -> Result<Vec<SimArbResultBatch>> {
-        unimplemented!()
+        Err(unsupported("read_arbs"))
     }
     async fn get_num_arbs(&self, _filter_params: &ArbFilterParams) -> Result<u64> {
-        unimplemented!()
+        Err(unsupported("get_num_arbs"))
+    }
+    async fn write_events(&self, _events: &Vec<EventHistory>) -> Result<()> {
+        Err(unsupported("write_events"))
+    }
+    async fn read_events(
+        &self,
+        _filter_params: &ArbFilterParams,
+        _offset: Option<u64>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<EventHistory>> {
+        Err(unsupported("read_events"))
+    }
+    async fn write_results(
+        &self,
+        _arbs: &Vec<SimArbResultBatch>,
+        _failures: &Vec<SimFailure>,
+    ) -> Result<()> {
+        Err(unsupported("write_results"))
+    }
+    async fn write_failures(&self, _failures: &Vec<SimFailure>) -> Result<()> {
+        Err(unsupported("write_failures"))
+    }
+    async fn read_failures(
+        &self,
+        _filter_params: &ArbFilterParams,
+        _offset: Option<u64>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<SimFailure>> {
+        Err(unsupported("read_failures"))
+    }
+    async fn write_liquidations(&self, _liquidations: &[SimLiquidationResult]) -> Result<()> {
+        Err(unsupported("write_liquidations"))
+    }
+    async fn read_liquidations(
+        &self,
+        _filter_params: &ArbFilterParams,
+        _offset: Option<u64>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<SimLiquidationResult>> {
+        Err(unsupported("read_liquidations"))
+    }
+    async fn write_pools(&self, _pools: &[IndexedPool]) -> Result<()> {
+        Err(unsupported("write_pools"))
+    }
+    async fn read_pools(&self, _chain_id: u64) -> Result<Vec<IndexedPool>> {
+        Err(unsupported("read_pools"))
+    }
+    async fn write_labels(&self, _labels: &[AddressLabel]) -> Result<()> {
+        Err(unsupported("write_labels"))
+    }
+    async fn read_labels(&self, _chain_id: u64) -> Result<Vec<AddressLabel>> {
+        Err(unsupported("read_labels"))
     }
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
-        unimplemented!()
+        Err(unsupported("get_previously_saved_ranges"))
+    }
+    async fn get_chain_ids(&self) -> Result<Vec<u64>> {
+        Err(unsupported("get_chain_ids"))
     }
     async fn export_arbs(
         &self,
         _write_dest: WriteEngine,
         _filter_params: &ArbFilterParams,
     ) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("export_arbs"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_rejects_unsupported_operations() {
+        let writer = FileWriter::new(Some("test".to_owned()));
+        let err = writer
+            .read_arbs(&ArbFilterParams::none(), None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "read_arbs is not supported by the file backend"
+        );
+        assert!(writer.get_previously_saved_ranges().await.is_err());
     }
 }
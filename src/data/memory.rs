@@ -0,0 +1,444 @@
+use super::arbs::{
+    export_arbs_core, liquidated_txs, written_txs, ArbDb, ArbFilterParams, WriteEngine,
+};
+use crate::{
+    interfaces::{
+        AddressLabel, IndexedPool, SimArbResultBatch, SimFailure, SimLiquidationResult,
+        StoredArbsRanges,
+    },
+    Result,
+};
+use async_trait::async_trait;
+use mev_share_sse::EventHistory;
+use std::sync::{Arc, Mutex};
+
+/// Keeps arbs, events, failures, liquidations, pools & labels in memory, so the pipeline can be tested without a DB. Clones share the data.
+///
+/// Records are keyed & filtered like MongoDB's: arbs by tx, chain & run, events by tx.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    arbs: Arc<Mutex<Vec<SimArbResultBatch>>>,
+    events: Arc<Mutex<Vec<EventHistory>>>,
+    failures: Arc<Mutex<Vec<SimFailure>>>,
+    liquidations: Arc<Mutex<Vec<SimLiquidationResult>>>,
+    pools: Arc<Mutex<Vec<IndexedPool>>>,
+    labels: Arc<Mutex<Vec<AddressLabel>>>,
+}
+
+/// Whether a record of `chain_id` & `run_label` at `block` & `timestamp` matches `filter`.
+fn matches(
+    filter: &ArbFilterParams,
+    block: u64,
+    timestamp: u64,
+    chain_id: Option<u64>,
+    run_label: &Option<String>,
+) -> bool {
+    filter.block_start.is_none_or(|start| block >= start as u64)
+        && filter.block_end.is_none_or(|end| block <= end as u64)
+        && filter
+            .timestamp_start
+            .is_none_or(|start| timestamp >= start as u64)
+        && filter
+            .timestamp_end
+            .is_none_or(|end| timestamp <= end as u64)
+        && (filter.chain_id.is_none() || chain_id.is_none() || filter.chain_id == chain_id)
+        && (filter.run_label.is_none() || &filter.run_label == run_label)
+}
+
+fn page<T: Clone>(records: Vec<T>, offset: Option<u64>, limit: Option<i64>) -> Vec<T> {
+    records
+        .into_iter()
+        .skip(offset.unwrap_or(0) as usize)
+        .take(limit.map_or(usize::MAX, |limit| limit.max(0) as usize))
+        .collect()
+}
+
+impl MemoryStore {
+    fn matching_arbs(&self, filter_params: &ArbFilterParams) -> Vec<SimArbResultBatch> {
+        self.arbs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|arb| {
+                matches(
+                    filter_params,
+                    arb.event.block,
+                    arb.event.timestamp,
+                    Some(arb.chain_id),
+                    &arb.run_label,
+                ) && arb.max_profit >= filter_params.min_profit.unwrap_or_default()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Sets `update` on the stored arbs of the txs of `arbs`.
+    fn update_arbs(
+        &self,
+        arbs: &[SimArbResultBatch],
+        update: impl Fn(&mut SimArbResultBatch, &SimArbResultBatch),
+    ) {
+        let mut stored = self.arbs.lock().unwrap();
+        for arb in arbs {
+            stored
+                .iter_mut()
+                .filter(|stored| stored.event.hint.hash == arb.event.hint.hash)
+                .for_each(|stored| update(stored, arb));
+        }
+    }
+}
+
+#[async_trait]
+impl ArbDb for MemoryStore {
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        let mut stored = self.arbs.lock().unwrap();
+        for arb in arbs {
+            stored.retain(|stored| {
+                (stored.event.hint.hash, stored.chain_id, &stored.run_label)
+                    != (arb.event.hint.hash, arb.chain_id, &arb.run_label)
+            });
+            stored.push(arb.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn write_results(
+        &self,
+        arbs: &Vec<SimArbResultBatch>,
+        failures: &Vec<SimFailure>,
+    ) -> Result<()> {
+        self.write_arbs(arbs).await?;
+        let txs = written_txs(arbs, failures);
+        let mut stored = self.failures.lock().unwrap();
+        stored.retain(|failure| {
+            !txs.contains(&(
+                failure.tx_hash,
+                failure.chain_id,
+                failure.run_label.to_owned(),
+            ))
+        });
+        stored.extend(failures.iter().cloned());
+        Ok(())
+    }
+
+    async fn write_realized(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        self.update_arbs(arbs, |stored, arb| {
+            stored.realized = arb.realized.to_owned()
+        });
+        Ok(())
+    }
+
+    async fn write_orphaned(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        self.update_arbs(arbs, |stored, arb| stored.orphaned = arb.orphaned);
+        Ok(())
+    }
+
+    async fn read_arbs(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimArbResultBatch>> {
+        Ok(page(self.matching_arbs(filter_params), offset, limit))
+    }
+
+    async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
+        Ok(self.matching_arbs(filter_params).len() as u64)
+    }
+
+    async fn write_events(&self, events: &Vec<EventHistory>) -> Result<()> {
+        let mut stored = self.events.lock().unwrap();
+        for event in events {
+            stored.retain(|stored| stored.hint.hash != event.hint.hash);
+            stored.push(event.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn read_events(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<EventHistory>> {
+        let events = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| {
+                matches(
+                    &ArbFilterParams {
+                        chain_id: None,
+                        run_label: None,
+                        ..filter_params.to_owned()
+                    },
+                    event.block,
+                    event.timestamp,
+                    None,
+                    &None,
+                )
+            })
+            .cloned()
+            .collect();
+        Ok(page(events, offset, limit))
+    }
+
+    async fn write_failures(&self, failures: &Vec<SimFailure>) -> Result<()> {
+        self.failures
+            .lock()
+            .unwrap()
+            .extend(failures.iter().cloned());
+        Ok(())
+    }
+
+    async fn read_failures(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimFailure>> {
+        let failures = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|failure| {
+                matches(
+                    filter_params,
+                    failure.block,
+                    failure.timestamp,
+                    Some(failure.chain_id),
+                    &failure.run_label,
+                )
+            })
+            .cloned()
+            .collect();
+        Ok(page(failures, offset, limit))
+    }
+
+    async fn write_liquidations(&self, liquidations: &[SimLiquidationResult]) -> Result<()> {
+        let txs = liquidated_txs(liquidations);
+        let mut stored = self.liquidations.lock().unwrap();
+        stored.retain(|liquidation| {
+            !txs.contains(&(
+                liquidation.tx_hash,
+                liquidation.chain_id,
+                liquidation.run_label.to_owned(),
+            ))
+        });
+        stored.extend(liquidations.iter().cloned());
+        Ok(())
+    }
+
+    async fn read_liquidations(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimLiquidationResult>> {
+        let liquidations = self
+            .liquidations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|liquidation| {
+                matches(
+                    filter_params,
+                    liquidation.block,
+                    liquidation.timestamp,
+                    Some(liquidation.chain_id),
+                    &liquidation.run_label,
+                )
+            })
+            .cloned()
+            .collect();
+        Ok(page(liquidations, offset, limit))
+    }
+
+    async fn write_pools(&self, pools: &[IndexedPool]) -> Result<()> {
+        let mut stored = self.pools.lock().unwrap();
+        stored.retain(|stored| {
+            !pools
+                .iter()
+                .any(|pool| pool.chain_id == stored.chain_id && pool.address == stored.address)
+        });
+        stored.extend(pools.iter().cloned());
+        Ok(())
+    }
+
+    async fn read_pools(&self, chain_id: u64) -> Result<Vec<IndexedPool>> {
+        Ok(self
+            .pools
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|pool| pool.chain_id == chain_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn write_labels(&self, labels: &[AddressLabel]) -> Result<()> {
+        let mut stored = self.labels.lock().unwrap();
+        stored.retain(|stored| {
+            !labels
+                .iter()
+                .any(|label| label.chain_id == stored.chain_id && label.address == stored.address)
+        });
+        stored.extend(labels.iter().cloned());
+        Ok(())
+    }
+
+    async fn read_labels(&self, chain_id: u64) -> Result<Vec<AddressLabel>> {
+        Ok(self
+            .labels
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|label| label.chain_id == chain_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
+        let arbs = self.arbs.lock().unwrap();
+        let earliest = arbs.iter().min_by_key(|arb| arb.event.timestamp);
+        let latest = arbs.iter().max_by_key(|arb| arb.event.timestamp);
+        // same placeholders as the other stores when there are no arbs
+        Ok(StoredArbsRanges {
+            earliest_block: earliest.map_or(1, |arb| arb.event.block),
+            earliest_timestamp: earliest.map_or(1, |arb| arb.event.timestamp),
+            latest_block: latest.map_or(2, |arb| arb.event.block),
+            latest_timestamp: latest.map_or(2, |arb| arb.event.timestamp),
+        })
+    }
+
+    async fn get_chain_ids(&self) -> Result<Vec<u64>> {
+        let mut chain_ids = self
+            .arbs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|arb| arb.chain_id)
+            .collect::<Vec<_>>();
+        chain_ids.sort();
+        chain_ids.dedup();
+        Ok(chain_ids)
+    }
+
+    async fn export_arbs(
+        &self,
+        write_dest: WriteEngine,
+        filter_params: &ArbFilterParams,
+    ) -> Result<()> {
+        export_arbs_core(Arc::new(self.clone()), write_dest, filter_params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+
+    #[tokio::test]
+    async fn it_rewrites_batches_without_duplicates() -> Result<()> {
+        let store = MemoryStore::default();
+        let arb = SimArbResultBatch::test_example();
+        let failure = SimFailure::new(&arb.event, None, "revert");
+        for _ in 0..2 {
+            store
+                .write_results(&vec![arb.clone()], &vec![failure.clone(), failure.clone()])
+                .await?;
+        }
+        let filter = ArbFilterParams::none();
+        assert_eq!(store.get_num_arbs(&filter).await?, 1);
+        assert_eq!(store.read_failures(&filter, None, None).await?.len(), 2);
+
+        let other_chain = ArbFilterParams {
+            chain_id: Some(8453),
+            ..ArbFilterParams::none()
+        };
+        assert_eq!(store.get_num_arbs(&other_chain).await?, 0);
+        assert_eq!(store.get_chain_ids().await?, vec![arb.chain_id]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_replaces_liquidations_of_rescanned_txs() -> Result<()> {
+        let store = MemoryStore::default();
+        let liquidation = SimLiquidationResult::test_example();
+        let mut other_borrower = liquidation.clone();
+        other_borrower.borrower = Address::from_low_u64_be(0xbb);
+        store
+            .write_liquidations(&[liquidation.clone(), other_borrower])
+            .await?;
+        // a rescan of the same tx only finds one of the liquidations
+        let rescanned = vec![liquidation.clone()];
+        store.write_liquidations(&rescanned).await?;
+        let filter = ArbFilterParams::none();
+        assert_eq!(
+            store.read_liquidations(&filter, None, None).await?,
+            rescanned
+        );
+
+        // a run with another label keeps its own liquidations
+        let mut labeled = liquidation.clone();
+        labeled.run_label = Some("label".to_owned());
+        store.write_liquidations(&[labeled]).await?;
+        assert_eq!(store.read_liquidations(&filter, None, None).await?.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_replaces_reindexed_pools() -> Result<()> {
+        let store = MemoryStore::default();
+        let pool = IndexedPool {
+            chain_id: 1,
+            address: Address::from_low_u64_be(0xa),
+            variant: crate::interfaces::PoolVariant::UniswapV2,
+            factory: Address::from_low_u64_be(0xf),
+            token0: Address::from_low_u64_be(1),
+            token1: Address::from_low_u64_be(2),
+            fee: 3000,
+            created_block: 10,
+        };
+        let other_chain = IndexedPool {
+            chain_id: 8453,
+            ..pool.clone()
+        };
+        store.write_pools(&[pool.clone(), other_chain]).await?;
+        // indexing the same blocks again doesn't duplicate the pool
+        store.write_pools(std::slice::from_ref(&pool)).await?;
+        assert_eq!(store.read_pools(1).await?, vec![pool]);
+        assert_eq!(store.read_pools(8453).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_replaces_relabeled_addresses() -> Result<()> {
+        let store = MemoryStore::default();
+        let label = AddressLabel {
+            chain_id: 1,
+            address: Address::from_low_u64_be(0xb07),
+            kind: crate::interfaces::LabelKind::Searcher,
+            name: "some bot".to_owned(),
+        };
+        store.write_labels(std::slice::from_ref(&label)).await?;
+        let renamed = AddressLabel {
+            name: "jared".to_owned(),
+            ..label.clone()
+        };
+        store
+            .write_labels(&[
+                renamed.clone(),
+                AddressLabel {
+                    chain_id: 10,
+                    ..label
+                },
+            ])
+            .await?;
+        assert_eq!(store.read_labels(1).await?, vec![renamed]);
+        assert_eq!(store.read_labels(10).await?.len(), 1);
+        Ok(())
+    }
+}
@@ -1,8 +1,16 @@
 pub mod arbs;
+pub mod cex;
+mod csv;
 pub mod db;
+pub mod external;
 mod file;
+mod memory;
 mod mongo;
 mod postgres;
+pub mod prices;
+pub mod tokens;
 
+pub use file::EXPORT_DIR;
+pub use memory::MemoryStore;
 pub use mongo::MongoConfig;
 pub use postgres::PostgresConfig;
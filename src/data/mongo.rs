@@ -1,76 +1,151 @@
-use super::arbs::{export_arbs_core, ArbDb, ArbFilterParams, WriteEngine};
+use super::arbs::{
+    export_arbs_core, liquidated_txs, written_txs, ArbDb, ArbFilterParams, WriteEngine,
+};
+use crate::config::{Config, DEFAULT_DB_NAMESPACE};
 use crate::interfaces::SimArbResultBatch;
-use crate::interfaces::StoredArbsRanges;
+use crate::interfaces::{
+    AddressLabel, IndexedPool, SimFailure, SimLiquidationResult, StoredArbsRanges,
+};
 use crate::Result;
 use async_trait::async_trait;
+use ethers::types::H256;
 use futures::stream::TryStreamExt;
+use mev_share_sse::EventHistory;
 use mongodb::bson::Document;
 use mongodb::options::Tls;
 use mongodb::options::TlsOptions;
 use mongodb::{
-    bson::doc,
-    options::{FindOneOptions, FindOptions},
+    bson::{doc, to_bson, Bson},
+    options::{CountOptions, FindOneOptions, FindOptions, ReplaceOptions},
     Collection,
 };
 use mongodb::{options::ClientOptions, Client as DbClient, Database};
 use std::path::PathBuf;
 use std::sync::Arc;
 
-pub const DB_NAME: &'static str = "hindsight";
 const PROJECT_NAME: &'static str = "simulator";
 const ARB_COLLECTION: &'static str = "arbs";
+const EVENT_COLLECTION: &'static str = "events";
+const FAILURE_COLLECTION: &'static str = "failures";
+const LIQUIDATION_COLLECTION: &str = "liquidations";
+const POOL_COLLECTION: &str = "pools";
+const LABEL_COLLECTION: &str = "labels";
 
 #[derive(Debug, Clone)]
 pub struct MongoConnect {
     arb_collection: Arc<Collection<SimArbResultBatch>>,
+    event_collection: Arc<Collection<EventHistory>>,
+    failure_collection: Arc<Collection<SimFailure>>,
+    liquidation_collection: Arc<Collection<SimLiquidationResult>>,
+    pool_collection: Arc<Collection<IndexedPool>>,
+    label_collection: Arc<Collection<AddressLabel>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MongoConfig {
     pub url: String,
     pub tls_ca_file_path: Option<PathBuf>,
+    /// Name of the database that arbs are stored in.
+    pub namespace: String,
 }
 
-impl Default for MongoConfig {
-    fn default() -> Self {
-        let config = crate::config::Config::default();
+impl From<&Config> for MongoConfig {
+    fn from(config: &Config) -> Self {
         Self {
-            url: config.mongo_url,
-            tls_ca_file_path: config.tls_ca_file_mongo,
+            url: config.mongo_url.to_owned(),
+            tls_ca_file_path: config.tls_ca_file_mongo.to_owned(),
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
         }
     }
 }
 
-impl Into<Document> for ArbFilterParams {
-    fn into(self) -> Document {
-        let block_start = self.block_start.unwrap_or(1);
-        let block_end = self.block_end.unwrap_or(u32::MAX);
-        let timestamp_start = self.timestamp_start.unwrap_or(1);
-        let timestamp_end = self.timestamp_end.unwrap_or(u32::MAX);
-        let min_profit = self.min_profit.unwrap_or(0.into());
-        let max_profit = if min_profit > 0.into() {
-            doc! {
-                "$ne": "0x0",
-            }
-        } else {
-            // basically a noop; matches any doc w/ this field, which is all of them
-            doc! {
-                "$exists": true
-            }
-        };
-
+/// Filter for saved arbs.
+fn arb_filter(filter_params: &ArbFilterParams) -> Document {
+    let block_start = filter_params.block_start.unwrap_or(1);
+    let block_end = filter_params.block_end.unwrap_or(u32::MAX);
+    let timestamp_start = filter_params.timestamp_start.unwrap_or(1);
+    let timestamp_end = filter_params.timestamp_end.unwrap_or(u32::MAX);
+    let min_profit = filter_params.min_profit.unwrap_or(0.into());
+    let max_profit = if min_profit > 0.into() {
         doc! {
-                "event.block": {
-                    "$gte": block_start as u32,
-                    "$lte": block_end as u32,
-                },
-                "event.timestamp": {
-                    "$gte": timestamp_start as u32,
-                    "$lte": timestamp_end as u32,
-                },
-                "maxProfit": max_profit,
+            "$ne": "0x0",
         }
+    } else {
+        // basically a noop; matches any doc w/ this field, which is all of them
+        doc! {
+            "$exists": true
+        }
+    };
+
+    let mut filter = doc! {
+            "event.block": {
+                "$gte": block_start as u32,
+                "$lte": block_end as u32,
+            },
+            "event.timestamp": {
+                "$gte": timestamp_start as u32,
+                "$lte": timestamp_end as u32,
+            },
+            "maxProfit": max_profit,
+    };
+    if let Some(chain_id) = filter_params.chain_id {
+        if chain_id == 1 {
+            // arbs saved before chain tagging don't have a chainId, and they're all mainnet
+            filter.insert("chainId", doc! { "$in": [1_i64, null] });
+        } else {
+            filter.insert("chainId", chain_id as i64);
+        }
+    }
+    if let Some(run_label) = &filter_params.run_label {
+        filter.insert("runLabel", run_label);
+    }
+    filter
+}
+
+/// Filter for saved events, which only have block & timestamp fields to match on.
+fn event_filter(filter_params: &ArbFilterParams) -> Document {
+    doc! {
+        "block": {
+            "$gte": filter_params.block_start.unwrap_or(1),
+            "$lte": filter_params.block_end.unwrap_or(u32::MAX),
+        },
+        "timestamp": {
+            "$gte": filter_params.timestamp_start.unwrap_or(1),
+            "$lte": filter_params.timestamp_end.unwrap_or(u32::MAX),
+        },
+    }
+}
+
+/// Filter for saved failures & liquidations, which are tagged with a chain & run like arbs.
+fn failure_filter(filter_params: &ArbFilterParams) -> Document {
+    let mut filter = event_filter(filter_params);
+    if let Some(chain_id) = filter_params.chain_id {
+        filter.insert("chainId", chain_id as i64);
+    }
+    if let Some(run_label) = &filter_params.run_label {
+        filter.insert("runLabel", run_label);
     }
+    filter
+}
+
+/// Matches the saved records of a tx in a chain & run; `hash_field` is where the record keeps the tx hash.
+fn tx_filter(
+    hash_field: &str,
+    tx_hash: H256,
+    chain_id: u64,
+    run_label: Option<String>,
+) -> Document {
+    let mut filter = doc! {};
+    filter.insert(hash_field, format!("{:?}", tx_hash));
+    if chain_id == 1 {
+        // records saved before chain tagging don't have a chainId, and they're all mainnet
+        filter.insert("chainId", doc! { "$in": [1_i64, null] });
+    } else {
+        filter.insert("chainId", chain_id as i64);
+    }
+    // matches records without a label too
+    filter.insert("runLabel", run_label);
+    filter
 }
 
 /// Talks to the database.
@@ -79,8 +154,21 @@ impl MongoConnect {
     pub async fn new(config: MongoConfig) -> Result<Self> {
         let db = MongoConnect::init_db(config).await?;
         let arb_collection = Arc::new(db.collection::<SimArbResultBatch>(ARB_COLLECTION));
+        let event_collection = Arc::new(db.collection::<EventHistory>(EVENT_COLLECTION));
+        let failure_collection = Arc::new(db.collection::<SimFailure>(FAILURE_COLLECTION));
+        let liquidation_collection =
+            Arc::new(db.collection::<SimLiquidationResult>(LIQUIDATION_COLLECTION));
+        let pool_collection = Arc::new(db.collection::<IndexedPool>(POOL_COLLECTION));
+        let label_collection = Arc::new(db.collection::<AddressLabel>(LABEL_COLLECTION));
         // TODO: use indexes
-        Ok(Self { arb_collection })
+        Ok(Self {
+            arb_collection,
+            event_collection,
+            failure_collection,
+            liquidation_collection,
+            pool_collection,
+            label_collection,
+        })
     }
 
     /// if tls_ca_file_path is None, then TLS is disabled
@@ -92,11 +180,11 @@ impl MongoConnect {
         }));
         let db_name = if cfg!(test) {
             // separate test db
-            "test_hindsight"
+            format!("test_{}", config.namespace)
         } else {
-            DB_NAME
+            config.namespace
         };
-        let db = Arc::new(DbClient::with_options(options)?.database(db_name));
+        let db = Arc::new(DbClient::with_options(options)?.database(&db_name));
         Ok(db)
     }
 
@@ -128,19 +216,227 @@ impl MongoConnect {
 
 #[async_trait]
 impl ArbDb for MongoConnect {
-    /// Write given arbs to the DB.
+    /// Write given arbs to the DB, upserted by tx, chain & run so that re-written arbs aren't duplicated.
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
-        self.arb_collection.insert_many(arbs, None).await?;
+        for arb in arbs {
+            self.arb_collection
+                .replace_one(
+                    tx_filter(
+                        "event.hint.hash",
+                        arb.event.hint.hash,
+                        arb.chain_id,
+                        arb.run_label.to_owned(),
+                    ),
+                    arb,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn write_results(
+        &self,
+        arbs: &Vec<SimArbResultBatch>,
+        failures: &Vec<SimFailure>,
+    ) -> Result<()> {
+        // transactions need a replica set, so every step is idempotent instead: a batch that's
+        // interrupted is completed by writing it again
+        self.write_arbs(arbs).await?;
+        for (tx_hash, chain_id, run_label) in written_txs(arbs, failures) {
+            self.failure_collection
+                .delete_many(tx_filter("txHash", tx_hash, chain_id, run_label), None)
+                .await?;
+        }
+        if !failures.is_empty() {
+            self.write_failures(failures).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_realized(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        for arb in arbs {
+            self.arb_collection
+                .update_one(
+                    doc! { "event.hint.hash": format!("{:?}", arb.event.hint.hash) },
+                    doc! { "$set": { "realized": to_bson(&arb.realized)? } },
+                    None,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn write_orphaned(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        for arb in arbs {
+            self.arb_collection
+                .update_one(
+                    doc! { "event.hint.hash": format!("{:?}", arb.event.hint.hash) },
+                    doc! { "$set": { "orphaned": arb.orphaned } },
+                    None,
+                )
+                .await?;
+        }
         Ok(())
     }
 
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
         Ok(self
             .arb_collection
-            .count_documents(Some(filter_params.to_owned().into()), None)
+            .count_documents(Some(arb_filter(filter_params)), None)
             .await?)
     }
 
+    async fn write_events(&self, events: &Vec<EventHistory>) -> Result<()> {
+        // upsert by tx hash so re-scanned events aren't duplicated
+        for event in events {
+            self.event_collection
+                .replace_one(
+                    doc! { "hint.hash": format!("{:?}", event.hint.hash) },
+                    event,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_events(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<EventHistory>> {
+        let mut cursor = self
+            .event_collection
+            .find(
+                Some(event_filter(filter_params)),
+                Some(FindOptions::builder().skip(offset).limit(limit).build()),
+            )
+            .await?;
+        let mut results = vec![];
+        while let Some(res) = cursor.try_next().await? {
+            results.push(res);
+        }
+        Ok(results)
+    }
+
+    async fn write_failures(&self, failures: &Vec<SimFailure>) -> Result<()> {
+        self.failure_collection.insert_many(failures, None).await?;
+        Ok(())
+    }
+
+    async fn read_failures(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimFailure>> {
+        let mut cursor = self
+            .failure_collection
+            .find(
+                Some(failure_filter(filter_params)),
+                Some(FindOptions::builder().skip(offset).limit(limit).build()),
+            )
+            .await?;
+        let mut results = vec![];
+        while let Some(res) = cursor.try_next().await? {
+            results.push(res);
+        }
+        Ok(results)
+    }
+
+    async fn write_liquidations(&self, liquidations: &[SimLiquidationResult]) -> Result<()> {
+        for (tx_hash, chain_id, run_label) in liquidated_txs(liquidations) {
+            self.liquidation_collection
+                .delete_many(tx_filter("txHash", tx_hash, chain_id, run_label), None)
+                .await?;
+        }
+        if !liquidations.is_empty() {
+            self.liquidation_collection
+                .insert_many(liquidations, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_liquidations(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimLiquidationResult>> {
+        let mut cursor = self
+            .liquidation_collection
+            .find(
+                Some(failure_filter(filter_params)),
+                Some(FindOptions::builder().skip(offset).limit(limit).build()),
+            )
+            .await?;
+        let mut results = vec![];
+        while let Some(res) = cursor.try_next().await? {
+            results.push(res);
+        }
+        Ok(results)
+    }
+
+    async fn write_pools(&self, pools: &[IndexedPool]) -> Result<()> {
+        // upsert by chain & address so re-indexed blocks don't duplicate pools
+        for pool in pools {
+            self.pool_collection
+                .replace_one(
+                    doc! {
+                        "chainId": pool.chain_id as i64,
+                        "address": format!("{:?}", pool.address),
+                    },
+                    pool,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_pools(&self, chain_id: u64) -> Result<Vec<IndexedPool>> {
+        let mut cursor = self
+            .pool_collection
+            .find(Some(doc! { "chainId": chain_id as i64 }), None)
+            .await?;
+        let mut pools = vec![];
+        while let Some(pool) = cursor.try_next().await? {
+            pools.push(pool);
+        }
+        Ok(pools)
+    }
+
+    async fn write_labels(&self, labels: &[AddressLabel]) -> Result<()> {
+        for label in labels {
+            self.label_collection
+                .replace_one(
+                    doc! {
+                        "chainId": label.chain_id as i64,
+                        "address": format!("{:?}", label.address),
+                    },
+                    label,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_labels(&self, chain_id: u64) -> Result<Vec<AddressLabel>> {
+        let mut cursor = self
+            .label_collection
+            .find(Some(doc! { "chainId": chain_id as i64 }), None)
+            .await?;
+        let mut labels = vec![];
+        while let Some(label) = cursor.try_next().await? {
+            labels.push(label);
+        }
+        Ok(labels)
+    }
+
     /// Load all arbs from the DB.
     async fn read_arbs(
         &self,
@@ -152,7 +448,7 @@ impl ArbDb for MongoConnect {
         let mut cursor = self
             .arb_collection
             .find(
-                Some(filter_params.to_owned().into()),
+                Some(arb_filter(filter_params)),
                 Some(FindOptions::builder().skip(offset).limit(limit).build()),
             )
             .await?;
@@ -193,6 +489,32 @@ impl ArbDb for MongoConnect {
         })
     }
 
+    async fn get_chain_ids(&self) -> Result<Vec<u64>> {
+        let mut chain_ids = self
+            .arb_collection
+            .distinct("chainId", None, None)
+            .await?
+            .into_iter()
+            .filter_map(|chain_id| match chain_id {
+                Bson::Int32(chain_id) => Some(chain_id as u64),
+                Bson::Int64(chain_id) => Some(chain_id as u64),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // arbs saved before chain tagging don't have a chainId, and they're all mainnet
+        let untagged = self
+            .arb_collection
+            .count_documents(
+                doc! { "chainId": null },
+                CountOptions::builder().limit(1).build(),
+            )
+            .await?;
+        if untagged > 0 && !chain_ids.contains(&1) {
+            chain_ids.push(1);
+        }
+        Ok(chain_ids)
+    }
+
     async fn export_arbs(
         &self,
         write_dest: WriteEngine,
@@ -226,10 +548,11 @@ mod test {
     }
 
     async fn connect() -> Result<MongoConnect> {
-        let config = Config::default();
+        let config = Config::load()?;
         let connect = MongoConnect::new(MongoConfig {
             url: config.mongo_url,
             tls_ca_file_path: config.tls_ca_file_mongo,
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
         })
         .await?;
         Ok(connect)
@@ -267,6 +590,8 @@ mod test {
                     timestamp_start: Some(0x6464beef),
                     timestamp_end: Some(0x6464deaf),
                     min_profit: Some(1.into()),
+                    chain_id: None,
+                    run_label: None,
                 },
                 Some(1),
                 Some(3),
@@ -307,6 +632,32 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn it_filters_by_chain_and_run() {
+        let filter = arb_filter(&ArbFilterParams {
+            chain_id: Some(8453),
+            run_label: Some("l2-backfill".to_owned()),
+            ..ArbFilterParams::none()
+        });
+        assert_eq!(filter.get_i64("chainId").unwrap(), 8453);
+        assert_eq!(filter.get_str("runLabel").unwrap(), "l2-backfill");
+
+        // untagged (legacy) arbs are mainnet arbs
+        let filter = arb_filter(&ArbFilterParams {
+            chain_id: Some(1),
+            ..ArbFilterParams::none()
+        });
+        assert!(filter.get_document("chainId").unwrap().contains_key("$in"));
+
+        // failures are always tagged, so there's no legacy case
+        let filter = failure_filter(&ArbFilterParams {
+            chain_id: Some(1),
+            ..ArbFilterParams::none()
+        });
+        assert_eq!(filter.get_i64("chainId").unwrap(), 1);
+        assert!(filter.contains_key("block"));
+    }
+
     #[tokio::test]
     async fn it_gets_arb_extrema() -> Result<()> {
         let connect = connect().await?;
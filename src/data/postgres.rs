@@ -1,105 +1,567 @@
-use super::arbs::{ArbDb, ArbFilterParams, WriteEngine};
+use super::arbs::{
+    export_arbs_core, liquidated_txs, written_txs, ArbDb, ArbFilterParams, WriteEngine,
+};
 use crate::{
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
+    config::{known_wrapped_native, Config, DEFAULT_DB_NAMESPACE},
+    debug,
+    interfaces::{
+        AddressLabel, IndexedPool, Realized, RealizedBackrun, SimArbResultBatch, SimFailure,
+        SimLiquidationResult, StoredArbsRanges,
+    },
     Result,
 };
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime};
 use ethers::{
-    types::{H256, U256},
-    utils::{format_ether, parse_ether},
+    types::{H256, I256, U256},
+    utils::{format_ether, format_units, parse_units},
 };
-use futures::future::join_all;
 use mev_share_sse::{EventHistory, Hint};
 use rust_decimal::prelude::*;
 use std::sync::Arc;
-use tokio_postgres::{connect, Client, NoTls};
-
-const ARBS_TABLE: &'static str = "hindsight";
+use tokio::sync::Mutex;
+use tokio_postgres::{connect, Client, GenericClient, NoTls, Row};
 
+#[derive(Clone)]
 pub struct PostgresConnect {
     client: Arc<Client>,
+    /// Connection that batches of results are written over, one transaction at a time.
+    /// Kept apart from `client` so other queries don't end up inside a batch's transaction.
+    writer: Arc<Mutex<Client>>,
+    /// Name of the table that arbs are stored in.
+    arbs_table: String,
+    /// Name of the table that raw events are stored in.
+    events_table: String,
+    /// Name of the table that failed simulations are stored in.
+    failures_table: String,
+    /// Name of the table that simulated liquidations are stored in.
+    liquidations_table: String,
+    /// Name of the table that indexed pools are stored in.
+    pools_table: String,
+    /// Name of the table that address labels are stored in.
+    labels_table: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct PostgresConfig {
     pub url: String,
+    /// Name of the table that arbs are stored in.
+    pub namespace: String,
 }
 
-impl Default for PostgresConfig {
-    fn default() -> Self {
-        let config = crate::config::Config::default();
-        Self {
-            url: config.postgres_url.unwrap(),
-        }
+impl TryFrom<&Config> for PostgresConfig {
+    type Error = anyhow::Error;
+    fn try_from(config: &Config) -> Result<Self> {
+        Ok(Self {
+            url: config
+                .postgres_url
+                .to_owned()
+                .ok_or(anyhow::format_err!("POSTGRES_URL is not set"))?,
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
+        })
     }
 }
 
 fn where_filter(filter: &ArbFilterParams) -> String {
-    let mut params = vec![];
+    let mut params = vec!["TRUE".to_owned()];
     if let Some(block_start) = filter.block_start {
-        params.push(format!("block_number >= {}", block_start));
+        params.push(format!("event_block >= {}", block_start));
     }
     if let Some(block_end) = filter.block_end {
-        params.push(format!("block_number <= {}", block_end));
+        params.push(format!("event_block <= {}", block_end));
     }
+    // arbs' timestamps are stored as TIMESTAMPs (in UTC), unlike other tables' unix seconds
     if let Some(timestamp_start) = filter.timestamp_start {
-        params.push(format!("timestamp >= {}", timestamp_start));
+        params.push(format!(
+            "event_timestamp >= to_timestamp({}) AT TIME ZONE 'UTC'",
+            timestamp_start
+        ));
     }
     if let Some(timestamp_end) = filter.timestamp_end {
-        params.push(format!("timestamp <= {}", timestamp_end));
+        params.push(format!(
+            "event_timestamp <= to_timestamp({}) AT TIME ZONE 'UTC'",
+            timestamp_end
+        ));
     }
     if let Some(min_profit) = filter.min_profit {
         params.push(format!("profit__eth__ >= {}", format_ether(min_profit)));
     }
+    if let Some(chain_id) = filter.chain_id {
+        params.push(format!("chain_id = {}", chain_id));
+    }
+    if let Some(run_label) = &filter.run_label {
+        params.push(format!("run_label = '{}'", run_label.replace('\'', "''")));
+    }
     params.join(" AND ")
 }
 
-fn select_arbs_query(filter: &ArbFilterParams) -> String {
-    let mut query = "SELECT * FROM ".to_string();
-    query.push_str(ARBS_TABLE);
-    query.push_str(" WHERE ");
-    query.push_str(&where_filter(filter));
+fn select_arbs_query(
+    table: &str,
+    filter: &ArbFilterParams,
+    offset: Option<u64>,
+    limit: Option<i64>,
+) -> String {
+    // ordered, so pages of an export don't overlap
+    let mut query = format!(
+        "SELECT * FROM {} WHERE {} ORDER BY event_timestamp, tx_hash",
+        table,
+        where_filter(filter)
+    );
+    if let Some(limit) = limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+    if let Some(offset) = offset {
+        query.push_str(&format!(" OFFSET {}", offset));
+    }
     query
 }
 
-fn count_arbs_query(filter: &ArbFilterParams) -> String {
+fn count_arbs_query(table: &str, filter: &ArbFilterParams) -> String {
     let mut query = "SELECT COUNT(*) FROM ".to_string();
-    query.push_str(ARBS_TABLE);
+    query.push_str(table);
     query.push_str(" WHERE ");
     query.push_str(&where_filter(filter));
     query
 }
 
+fn select_events_query(
+    table: &str,
+    filter: &ArbFilterParams,
+    offset: Option<u64>,
+    limit: Option<i64>,
+) -> String {
+    // events only have a block & timestamp to filter by
+    let mut params = vec!["TRUE".to_owned()];
+    if let Some(block_start) = filter.block_start {
+        params.push(format!("event_block >= {}", block_start));
+    }
+    if let Some(block_end) = filter.block_end {
+        params.push(format!("event_block <= {}", block_end));
+    }
+    if let Some(timestamp_start) = filter.timestamp_start {
+        params.push(format!("event_timestamp >= {}", timestamp_start));
+    }
+    if let Some(timestamp_end) = filter.timestamp_end {
+        params.push(format!("event_timestamp <= {}", timestamp_end));
+    }
+    let mut query = format!(
+        "SELECT event FROM {} WHERE {} ORDER BY event_timestamp, tx_hash",
+        table,
+        params.join(" AND ")
+    );
+    if let Some(limit) = limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+    if let Some(offset) = offset {
+        query.push_str(&format!(" OFFSET {}", offset));
+    }
+    query
+}
+
+/// Selects the JSON `column` of the records in `table` (of failures or liquidations) that match `filter`.
+fn select_tagged_query(
+    table: &str,
+    column: &str,
+    filter: &ArbFilterParams,
+    offset: Option<u64>,
+    limit: Option<i64>,
+) -> String {
+    let mut params = vec!["TRUE".to_owned()];
+    if let Some(block_start) = filter.block_start {
+        params.push(format!("event_block >= {}", block_start));
+    }
+    if let Some(block_end) = filter.block_end {
+        params.push(format!("event_block <= {}", block_end));
+    }
+    if let Some(timestamp_start) = filter.timestamp_start {
+        params.push(format!("event_timestamp >= {}", timestamp_start));
+    }
+    if let Some(timestamp_end) = filter.timestamp_end {
+        params.push(format!("event_timestamp <= {}", timestamp_end));
+    }
+    if let Some(chain_id) = filter.chain_id {
+        params.push(format!("chain_id = {}", chain_id));
+    }
+    if let Some(run_label) = &filter.run_label {
+        params.push(format!("run_label = '{}'", run_label.replace('\'', "''")));
+    }
+    let mut query = format!(
+        "SELECT {} FROM {} WHERE {} ORDER BY event_timestamp, tx_hash",
+        column,
+        table,
+        params.join(" AND ")
+    );
+    if let Some(limit) = limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+    if let Some(offset) = offset {
+        query.push_str(&format!(" OFFSET {}", offset));
+    }
+    query
+}
+
+fn realized_from_row(row: &Row) -> Option<Realized> {
+    if !row.get::<_, bool>("realized_checked") {
+        return None;
+    }
+    let backrun = row
+        .get::<_, Option<String>>("realized_tx_hash")
+        .and_then(|tx_hash| {
+            Some(RealizedBackrun {
+                tx_hash: tx_hash.parse().ok()?,
+                block: row.get::<_, Option<i32>>("realized_block")? as u64,
+                from: row
+                    .get::<_, Option<String>>("realized_from")?
+                    .parse()
+                    .ok()?,
+                to: row
+                    .get::<_, Option<String>>("realized_to")
+                    .and_then(|to| to.parse().ok()),
+                profit: I256::from_dec_str(
+                    &row.get::<_, Option<Decimal>>("realized_profit")?
+                        .to_string(),
+                )
+                .ok()?,
+                fee_recipient: row
+                    .get::<_, Option<String>>("realized_fee_recipient")
+                    .and_then(|fee_recipient| fee_recipient.parse().ok()),
+                extra_data: row
+                    .get::<_, Option<String>>("realized_extra_data")
+                    .and_then(|extra_data| extra_data.parse().ok()),
+            })
+        });
+    Some(Realized { backrun })
+}
+
+/// Decodes an arb that `insert_arb` wrote. Only the fields that are stored come back; `results` are empty.
+fn arb_from_row(row: &Row) -> Result<SimArbResultBatch> {
+    let profit_decimals = row.get::<_, i32>("profit_decimals") as u32;
+    // profit__eth__ holds the profit in whole units of profit_token
+    let max_profit = row
+        .get::<_, Option<Decimal>>("profit__eth__")
+        .map(|profit| parse_units(profit.to_string(), profit_decimals))
+        .transpose()?
+        .map(U256::from)
+        .unwrap_or_default();
+    Ok(SimArbResultBatch {
+        event: EventHistory {
+            block: row.get::<_, i32>("event_block") as u64,
+            timestamp: row
+                .get::<_, NaiveDateTime>("event_timestamp")
+                .and_utc()
+                .timestamp() as u64,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::from_str(&row.get::<_, String>("tx_hash"))?,
+                logs: vec![],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        },
+        max_profit,
+        results: vec![],
+        chain_id: row.get::<_, i64>("chain_id") as u64,
+        run_label: row.get::<_, Option<String>>("run_label"),
+        profit_token: row
+            .get::<_, Option<String>>("profit_token")
+            .and_then(|token| token.parse().ok())
+            .unwrap_or(known_wrapped_native(1).unwrap_or_default()),
+        profit_decimals,
+        base_fee: row
+            .get::<_, Option<Decimal>>("base_fee")
+            .and_then(|fee| U256::from_dec_str(&fee.to_string()).ok()),
+        realized: realized_from_row(row),
+        engine_version: row.get::<_, Option<String>>("engine_version"),
+        budget_truncated: row.get::<_, bool>("budget_truncated"),
+        verify_mismatch: row.get::<_, bool>("verify_mismatch"),
+        block_hash: row
+            .get::<_, Option<String>>("block_hash")
+            .and_then(|hash| hash.parse().ok()),
+        orphaned: row.get::<_, bool>("orphaned"),
+    })
+}
+
+/// Columns that `insert_arb` writes, in the order of its parameters.
+const ARB_COLUMNS: [&str; 14] = [
+    "tx_hash",
+    "profit__eth__",
+    "event_block",
+    "event_timestamp",
+    "chain_id",
+    "run_label",
+    "profit_token",
+    "profit_decimals",
+    "base_fee",
+    "engine_version",
+    "budget_truncated",
+    "verify_mismatch",
+    "block_hash",
+    "orphaned",
+];
+
+/// Conflict target of arbs: an arb is kept per tx, chain & run (like Mongo's), with unlabeled runs sharing the
+/// empty label, since a NULL label can't be part of a primary key.
+const ARB_KEY: &str = "tx_hash, chain_id, (COALESCE(run_label, ''))";
+/// Columns of `ARB_KEY`.
+const ARB_KEY_COLUMNS: [&str; 3] = ["tx_hash", "chain_id", "run_label"];
+
+/// Statement that upserts an arb into `arbs_table` by `ARB_KEY`. A conflicting row has every other column replaced,
+/// so writing the same batch again (e.g. with another quote asset) converges to what was written last.
+fn upsert_arb_statement(arbs_table: &str) -> String {
+    let params = (1..=ARB_COLUMNS.len())
+        .map(|idx| format!("${}", idx))
+        .collect::<Vec<_>>();
+    let updates = ARB_COLUMNS
+        .iter()
+        .filter(|column| !ARB_KEY_COLUMNS.contains(column))
+        .map(|column| format!("{} = EXCLUDED.{}", column, column))
+        .collect::<Vec<_>>();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) DO UPDATE SET {}",
+        arbs_table,
+        ARB_COLUMNS.join(", "),
+        params.join(", "),
+        ARB_KEY,
+        updates.join(", ")
+    )
+}
+
+/// Upserts `arb` into `arbs_table` by its tx, chain & run (see `upsert_arb_statement`).
+async fn insert_arb(
+    client: &impl GenericClient,
+    arbs_table: &str,
+    arb: &SimArbResultBatch,
+) -> Result<()> {
+    let txhash = format!("{:?}", arb.event.hint.hash); // must be a better way than this :\
+                                                       // profit__eth__ holds the profit in whole units of profit_token (ETH for WETH)
+    let max_profit = Decimal::from_str(&format_units(arb.max_profit, arb.profit_decimals)?)?;
+    let timestamp = DateTime::from_timestamp(arb.event.timestamp as i64, 0)
+        .map(|timestamp| timestamp.naive_utc())
+        .ok_or(anyhow::format_err!(
+            "invalid event timestamp {}",
+            arb.event.timestamp
+        ))?;
+    let base_fee = arb
+        .base_fee
+        .map(|fee| Decimal::from_str(&fee.to_string()))
+        .transpose()?;
+
+    debug!(
+        "writing arb to postgres: {} {} (token {:?})",
+        txhash, max_profit, arb.profit_token
+    );
+    client
+        .execute(
+            &upsert_arb_statement(arbs_table),
+            &[
+                &txhash,
+                &max_profit,
+                &(arb.event.block as i32),
+                &timestamp,
+                &(arb.chain_id as i64),
+                &arb.run_label,
+                &format!("{:?}", arb.profit_token),
+                &(arb.profit_decimals as i32),
+                &base_fee,
+                &arb.engine_version,
+                &arb.budget_truncated,
+                &arb.verify_mismatch,
+                &arb.block_hash.map(|hash| format!("{:?}", hash)),
+                &arb.orphaned,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Inserts `failure` into `failures_table`.
+async fn insert_failure(
+    client: &impl GenericClient,
+    failures_table: &str,
+    failure: &SimFailure,
+) -> Result<()> {
+    client
+        .execute(
+            &format!(
+                "INSERT INTO {} (tx_hash, event_block, event_timestamp, chain_id, run_label, failure)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                failures_table
+            ),
+            &[
+                &format!("{:?}", failure.tx_hash),
+                &(failure.block as i32),
+                &(failure.timestamp as i64),
+                &(failure.chain_id as i64),
+                &failure.run_label,
+                &serde_json::to_value(failure)?,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Opens a connection to the DB at `url`.
+async fn connect_client(url: &str) -> Result<Client> {
+    // TODO: add env var for postgres tls if/when implemented
+    // let pg_tls = false;
+    // let tls = if pg_tls {
+    //     OpenSsl...
+    // } else {
+    //     NoTls
+    // };
+    let (client, connection) = connect(url, NoTls).await?;
+    // The connection object performs the actual communication with the database,
+    // so spawn it off to run on its own.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+    Ok(client)
+}
+
 impl PostgresConnect {
     pub async fn new(config: PostgresConfig) -> Result<Self> {
-        // TODO: add env var for postgres tls if/when implemented
-        // let pg_tls = false;
-        // let tls = if pg_tls {
-        //     OpenSsl...
-        // } else {
-        //     NoTls
-        // };
-        let (client, connection) = connect(&config.url, NoTls).await?;
-        // The connection object performs the actual communication with the database,
-        // so spawn it off to run on its own.
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
+        let client = connect_client(&config.url).await?;
 
         // create arbs table pessimistically (simplified version for now: {hash, profit})
         client
             .execute(
                 &format!(
                     "CREATE TABLE IF NOT EXISTS {} (
-                        tx_hash VARCHAR(66) NOT NULL PRIMARY KEY,
+                        tx_hash VARCHAR(66) NOT NULL,
                         profit__eth__ NUMERIC,
                         event_block INTEGER NOT NULL,
                         event_timestamp TIMESTAMP NOT NULL
                     )",
-                    ARBS_TABLE
+                    config.namespace
+                ),
+                &[],
+            )
+            .await?;
+        // tables created before chain/run tagging & quote assets need the new columns added
+        client
+            .batch_execute(&format!(
+                "ALTER TABLE {0} ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL DEFAULT 1;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS run_label VARCHAR(128);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS profit_token VARCHAR(42);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS profit_decimals INTEGER NOT NULL DEFAULT 18;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS base_fee NUMERIC;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_checked BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_tx_hash VARCHAR(66);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_block INTEGER;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_from VARCHAR(42);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_to VARCHAR(42);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_profit NUMERIC;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_fee_recipient VARCHAR(42);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS realized_extra_data TEXT;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS engine_version VARCHAR(32);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS budget_truncated BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS verify_mismatch BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS block_hash VARCHAR(66);
+                ALTER TABLE {0} ADD COLUMN IF NOT EXISTS orphaned BOOLEAN NOT NULL DEFAULT FALSE;",
+                config.namespace
+            ))
+            .await?;
+        // arbs used to be keyed by their tx hash alone, so rerunning a tx with another label overwrote the first run
+        client
+            .batch_execute(&format!(
+                "ALTER TABLE {0} DROP CONSTRAINT IF EXISTS {0}_pkey;
+                CREATE UNIQUE INDEX IF NOT EXISTS {0}_run_key ON {0} ({1});",
+                config.namespace, ARB_KEY
+            ))
+            .await?;
+
+        // raw events, kept whole as JSON
+        let events_table = format!("{}_events", config.namespace);
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        tx_hash VARCHAR(66) NOT NULL PRIMARY KEY,
+                        event_block INTEGER NOT NULL,
+                        event_timestamp BIGINT NOT NULL,
+                        event JSONB NOT NULL
+                    )",
+                    events_table
+                ),
+                &[],
+            )
+            .await?;
+
+        // failed simulations, kept whole as JSON; a tx may fail on several pools
+        let failures_table = format!("{}_failures", config.namespace);
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        tx_hash VARCHAR(66) NOT NULL,
+                        event_block INTEGER NOT NULL,
+                        event_timestamp BIGINT NOT NULL,
+                        chain_id BIGINT NOT NULL,
+                        run_label VARCHAR(128),
+                        failure JSONB NOT NULL
+                    )",
+                    failures_table
+                ),
+                &[],
+            )
+            .await?;
+
+        // simulated liquidations, kept whole as JSON; a tx may make several positions liquidatable
+        let liquidations_table = format!("{}_liquidations", config.namespace);
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        tx_hash VARCHAR(66) NOT NULL,
+                        event_block INTEGER NOT NULL,
+                        event_timestamp BIGINT NOT NULL,
+                        chain_id BIGINT NOT NULL,
+                        run_label VARCHAR(128),
+                        liquidation JSONB NOT NULL
+                    )",
+                    liquidations_table
+                ),
+                &[],
+            )
+            .await?;
+
+        // pools found by the indexer, one row per pool
+        let pools_table = format!("{}_pools", config.namespace);
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        chain_id BIGINT NOT NULL,
+                        address VARCHAR(42) NOT NULL,
+                        factory VARCHAR(42) NOT NULL,
+                        token0 VARCHAR(42) NOT NULL,
+                        token1 VARCHAR(42) NOT NULL,
+                        fee INTEGER NOT NULL,
+                        created_block BIGINT NOT NULL,
+                        pool JSONB NOT NULL,
+                        PRIMARY KEY (chain_id, address)
+                    )",
+                    pools_table
+                ),
+                &[],
+            )
+            .await?;
+
+        // names of known addresses, one row per address
+        let labels_table = format!("{}_labels", config.namespace);
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        chain_id BIGINT NOT NULL,
+                        address VARCHAR(42) NOT NULL,
+                        kind VARCHAR(16) NOT NULL,
+                        name TEXT NOT NULL,
+                        PRIMARY KEY (chain_id, address)
+                    )",
+                    labels_table
                 ),
                 &[],
             )
@@ -107,6 +569,13 @@ impl PostgresConnect {
 
         Ok(Self {
             client: Arc::new(client),
+            writer: Arc::new(Mutex::new(connect_client(&config.url).await?)),
+            arbs_table: config.namespace,
+            events_table,
+            failures_table,
+            liquidations_table,
+            pools_table,
+            labels_table,
         })
     }
 }
@@ -114,96 +583,387 @@ impl PostgresConnect {
 #[async_trait]
 impl ArbDb for PostgresConnect {
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
-        let handles = arbs
-            .iter()
-            .map(|arb| {
-                let txhash = format!("{:?}", arb.event.hint.hash); // must be a better way than this :\
-                let max_profit = Decimal::from_str(&format_ether(arb.max_profit))
-                    .expect("failed to encode profit");
-                let timestamp =
-                    NaiveDateTime::from_timestamp_millis(arb.event.timestamp as i64 * 1000)
-                        .expect("failed to parse timestamp");
-
-                println!(
-                    "writing arb to postgres: {} {} eth",
-                    txhash.to_string(),
-                    max_profit
-                );
-                // clone these to give to the tokio thread
-                let client = self.client.clone();
-                let arb = arb.clone();
-
-                tokio::task::spawn(async move {
-                    client
+        for arb in arbs {
+            insert_arb(self.client.as_ref(), &self.arbs_table, arb).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_results(
+        &self,
+        arbs: &Vec<SimArbResultBatch>,
+        failures: &Vec<SimFailure>,
+    ) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        let transaction = writer.transaction().await?;
+        for (tx_hash, chain_id, run_label) in written_txs(arbs, failures) {
+            transaction
+                .execute(
+                    &format!(
+                        "DELETE FROM {} WHERE tx_hash = $1 AND chain_id = $2 AND run_label IS NOT DISTINCT FROM $3",
+                        self.failures_table
+                    ),
+                    &[&format!("{:?}", tx_hash), &(chain_id as i64), &run_label],
+                )
+                .await?;
+        }
+        for arb in arbs {
+            insert_arb(&transaction, &self.arbs_table, arb).await?;
+        }
+        for failure in failures {
+            insert_failure(&transaction, &self.failures_table, failure).await?;
+        }
+        // nothing is saved unless the whole batch is
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn write_realized(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        for arb in arbs {
+            let backrun = arb
+                .realized
+                .as_ref()
+                .and_then(|realized| realized.backrun.as_ref());
+            self.client
+                .execute(
+                    &format!(
+                        "UPDATE {} SET realized_checked = $2, realized_tx_hash = $3, realized_block = $4,
+                            realized_from = $5, realized_to = $6, realized_profit = $7,
+                            realized_fee_recipient = $8, realized_extra_data = $9
+                        WHERE tx_hash = $1 AND chain_id = $10 AND run_label IS NOT DISTINCT FROM $11",
+                        self.arbs_table
+                    ),
+                    &[
+                        &format!("{:?}", arb.event.hint.hash),
+                        &arb.realized.is_some(),
+                        &backrun.map(|backrun| format!("{:?}", backrun.tx_hash)),
+                        &backrun.map(|backrun| backrun.block as i32),
+                        &backrun.map(|backrun| format!("{:?}", backrun.from)),
+                        &backrun.and_then(|backrun| backrun.to.map(|to| format!("{:?}", to))),
+                        // raw amount (smallest unit of the profit token)
+                        &backrun.map(|backrun| {
+                            Decimal::from_str(&backrun.profit.to_string())
+                                .expect("failed to encode realized profit")
+                        }),
+                        &backrun.and_then(|backrun| {
+                            backrun
+                                .fee_recipient
+                                .map(|fee_recipient| format!("{:?}", fee_recipient))
+                        }),
+                        &backrun.and_then(|backrun| {
+                            backrun
+                                .extra_data
+                                .as_ref()
+                                .map(|extra_data| extra_data.to_string())
+                        }),
+                        &(arb.chain_id as i64),
+                        &arb.run_label,
+                    ],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn write_orphaned(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        for arb in arbs {
+            self.client
+                .execute(
+                    &format!(
+                        "UPDATE {} SET orphaned = $2
+                        WHERE tx_hash = $1 AND chain_id = $3 AND run_label IS NOT DISTINCT FROM $4",
+                        self.arbs_table
+                    ),
+                    &[
+                        &format!("{:?}", arb.event.hint.hash),
+                        &arb.orphaned,
+                        &(arb.chain_id as i64),
+                        &arb.run_label,
+                    ],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn write_events(&self, events: &Vec<EventHistory>) -> Result<()> {
+        for event in events {
+            self.client
                 .execute(
-                    &format!("INSERT INTO {} (tx_hash, profit__eth__, event_block, event_timestamp)
+                    &format!(
+                        "INSERT INTO {} (tx_hash, event_block, event_timestamp, event)
                         VALUES ($1, $2, $3, $4)
-                        ON CONFLICT (tx_hash) DO UPDATE SET profit__eth__ = $2",
-                        ARBS_TABLE
+                        ON CONFLICT (tx_hash) DO UPDATE SET event = $4",
+                        self.events_table
+                    ),
+                    &[
+                        &format!("{:?}", event.hint.hash),
+                        &(event.block as i32),
+                        &(event.timestamp as i64),
+                        &serde_json::to_value(event)?,
+                    ],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_events(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<EventHistory>> {
+        let query = select_events_query(&self.events_table, filter_params, offset, limit);
+        let rows = self.client.query(&query, &[]).await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.get::<_, serde_json::Value>(0))?))
+            .collect()
+    }
+
+    async fn write_failures(&self, failures: &Vec<SimFailure>) -> Result<()> {
+        for failure in failures {
+            insert_failure(self.client.as_ref(), &self.failures_table, failure).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_failures(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimFailure>> {
+        let query = select_tagged_query(
+            &self.failures_table,
+            "failure",
+            filter_params,
+            offset,
+            limit,
+        );
+        let rows = self.client.query(&query, &[]).await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.get::<_, serde_json::Value>(0))?))
+            .collect()
+    }
+
+    async fn write_liquidations(&self, liquidations: &[SimLiquidationResult]) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        let transaction = writer.transaction().await?;
+        for (tx_hash, chain_id, run_label) in liquidated_txs(liquidations) {
+            transaction
+                .execute(
+                    &format!(
+                        "DELETE FROM {} WHERE tx_hash = $1 AND chain_id = $2 AND run_label IS NOT DISTINCT FROM $3",
+                        self.liquidations_table
+                    ),
+                    &[&format!("{:?}", tx_hash), &(chain_id as i64), &run_label],
+                )
+                .await?;
+        }
+        for liquidation in liquidations {
+            transaction
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (tx_hash, event_block, event_timestamp, chain_id, run_label, liquidation)
+                        VALUES ($1, $2, $3, $4, $5, $6)",
+                        self.liquidations_table
+                    ),
+                    &[
+                        &format!("{:?}", liquidation.tx_hash),
+                        &(liquidation.block as i32),
+                        &(liquidation.timestamp as i64),
+                        &(liquidation.chain_id as i64),
+                        &liquidation.run_label,
+                        &serde_json::to_value(liquidation)?,
+                    ],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn read_liquidations(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<SimLiquidationResult>> {
+        let query = select_tagged_query(
+            &self.liquidations_table,
+            "liquidation",
+            filter_params,
+            offset,
+            limit,
+        );
+        let rows = self.client.query(&query, &[]).await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.get::<_, serde_json::Value>(0))?))
+            .collect()
+    }
+
+    async fn write_pools(&self, pools: &[IndexedPool]) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        let transaction = writer.transaction().await?;
+        for pool in pools {
+            transaction
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (chain_id, address, factory, token0, token1, fee, created_block, pool)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                        ON CONFLICT (chain_id, address) DO UPDATE SET factory = $3, token0 = $4, token1 = $5, fee = $6, created_block = $7, pool = $8",
+                        self.pools_table
+                    ),
+                    &[
+                        &(pool.chain_id as i64),
+                        &format!("{:?}", pool.address),
+                        &format!("{:?}", pool.factory),
+                        &format!("{:?}", pool.token0),
+                        &format!("{:?}", pool.token1),
+                        &(pool.fee as i32),
+                        &(pool.created_block as i64),
+                        &serde_json::to_value(pool)?,
+                    ],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn read_pools(&self, chain_id: u64) -> Result<Vec<IndexedPool>> {
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT pool FROM {} WHERE chain_id = $1 ORDER BY created_block",
+                    self.pools_table
+                ),
+                &[&(chain_id as i64)],
+            )
+            .await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.get::<_, serde_json::Value>(0))?))
+            .collect()
+    }
+
+    async fn write_labels(&self, labels: &[AddressLabel]) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        let transaction = writer.transaction().await?;
+        for label in labels {
+            transaction
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (chain_id, address, kind, name) VALUES ($1, $2, $3, $4)
+                        ON CONFLICT (chain_id, address) DO UPDATE SET kind = $3, name = $4",
+                        self.labels_table
                     ),
                     &[
-                        &txhash,
-                        &max_profit,
-                        &(arb.event.block as i32),
-                        &timestamp,
+                        &(label.chain_id as i64),
+                        &format!("{:?}", label.address),
+                        &label.kind.to_string(),
+                        &label.name,
                     ],
                 )
-                .await.expect("failed to write arb to postgres");
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn read_labels(&self, chain_id: u64) -> Result<Vec<AddressLabel>> {
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT address, kind, name FROM {} WHERE chain_id = $1 ORDER BY address",
+                    self.labels_table
+                ),
+                &[&(chain_id as i64)],
+            )
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(AddressLabel {
+                    chain_id,
+                    address: row.get::<_, String>(0).parse()?,
+                    kind: row
+                        .get::<_, String>(1)
+                        .parse()
+                        .map_err(|err: String| anyhow::format_err!(err))?,
+                    name: row.get(2),
                 })
             })
-            .collect::<Vec<_>>();
-        join_all(handles).await;
-        Ok(())
+            .collect()
     }
 
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
-        let query = count_arbs_query(filter_params);
+        let query = count_arbs_query(&self.arbs_table, filter_params);
         let row = self.client.query_one(&query, &[]).await?;
-        let count: u32 = row.get(0);
+        let count: i64 = row.get(0);
         Ok(count as u64)
     }
 
     async fn read_arbs(
         &self,
         filter_params: &ArbFilterParams,
-        _offset: Option<u64>,
-        _limit: Option<i64>,
+        offset: Option<u64>,
+        limit: Option<i64>,
     ) -> Result<Vec<SimArbResultBatch>> {
-        let query = select_arbs_query(filter_params);
+        let query = select_arbs_query(&self.arbs_table, filter_params, offset, limit);
         let rows = self.client.query(&query, &[]).await?;
-        let arbs = rows
-            .into_iter()
-            .map(|row| SimArbResultBatch {
-                event: EventHistory {
-                    // TODO: change this once the rest of the fields are added to postgres
-                    block: row.get::<usize, u32>(2) as u64,
-                    timestamp: row.get::<usize, u32>(3) as u64,
-                    hint: Hint {
-                        txs: vec![],
-                        hash: H256::from_str(&row.get::<_, String>(0)).unwrap(),
-                        logs: vec![],
-                        gas_used: None,
-                        mev_gas_price: None,
-                    },
-                },
-                max_profit: parse_ether(row.get::<usize, f64>(1).to_string())
-                    .unwrap_or(U256::zero()),
-                results: vec![],
-            })
-            .collect::<Vec<_>>();
-        Ok(arbs)
+        rows.into_iter().map(|row| arb_from_row(&row)).collect()
     }
 
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
-        todo!()
+        let extremum = |order: &str| {
+            format!(
+                "SELECT event_block, event_timestamp FROM {} ORDER BY event_timestamp {} LIMIT 1",
+                self.arbs_table, order
+            )
+        };
+        let range_end = |row: Option<Row>| {
+            row.map(|row| {
+                (
+                    row.get::<_, i32>(0) as u64,
+                    row.get::<_, NaiveDateTime>(1).and_utc().timestamp() as u64,
+                )
+            })
+        };
+        let earliest = range_end(self.client.query_opt(&extremum("ASC"), &[]).await?);
+        let latest = range_end(self.client.query_opt(&extremum("DESC"), &[]).await?);
+        // same placeholders as the other stores when there are no arbs
+        let (earliest_block, earliest_timestamp) = earliest.unwrap_or((1, 1));
+        let (latest_block, latest_timestamp) = latest.unwrap_or((2, 2));
+        Ok(StoredArbsRanges {
+            earliest_block,
+            latest_block,
+            earliest_timestamp,
+            latest_timestamp,
+        })
+    }
+
+    async fn get_chain_ids(&self) -> Result<Vec<u64>> {
+        let rows = self
+            .client
+            .query(
+                &format!("SELECT DISTINCT chain_id FROM {}", self.arbs_table),
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.get::<_, i64>(0) as u64)
+            .collect())
     }
 
     async fn export_arbs(
         &self,
-        _write_dest: WriteEngine,
-        _filter_params: &ArbFilterParams,
+        write_dest: WriteEngine,
+        filter_params: &ArbFilterParams,
     ) -> Result<()> {
-        todo!()
+        export_arbs_core(Arc::new(self.clone()), write_dest, filter_params).await
     }
 }
 
@@ -214,13 +974,14 @@ mod tests {
 
     #[tokio::test]
     async fn it_connects_postgres() -> Result<()> {
-        let config = Config::default();
+        let config = Config::load()?;
         if config.postgres_url.is_none() {
             println!("no postgres url, skipping test");
             return Ok(());
         }
         let connect = PostgresConnect::new(PostgresConfig {
             url: config.postgres_url.unwrap(),
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
         })
         .await?;
         let res = connect
@@ -242,25 +1003,151 @@ mod tests {
 
     #[tokio::test]
     async fn it_writes_arbs_postgres() -> Result<()> {
-        let config = Config::default();
+        let config = Config::load()?;
         if config.postgres_url.is_none() {
             println!("no postgres url, skipping test");
             return Ok(());
         }
         let connect = PostgresConnect::new(PostgresConfig {
             url: config.postgres_url.unwrap(),
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
         })
         .await?;
         inject_test_arb(&connect).await?;
         let res = connect
             .client
-            .query(&format!("SELECT * FROM {}", ARBS_TABLE), &[])
+            .query(&format!("SELECT * FROM {}", connect.arbs_table), &[])
             .await
             .expect("failed to read arbs from postgres");
         assert!(res.len() > 0);
         Ok(())
     }
 
+    #[test]
+    fn it_pages_arb_queries() {
+        assert_eq!(
+            select_arbs_query("arbs", &ArbFilterParams::none(), Some(3000), Some(3000)),
+            "SELECT * FROM arbs WHERE TRUE ORDER BY event_timestamp, tx_hash LIMIT 3000 OFFSET 3000"
+        );
+        let filter = ArbFilterParams {
+            block_start: Some(1),
+            ..ArbFilterParams::none()
+        };
+        assert_eq!(
+            count_arbs_query("arbs", &filter),
+            "SELECT COUNT(*) FROM arbs WHERE TRUE AND event_block >= 1"
+        );
+    }
+
+    #[test]
+    fn it_replaces_every_column_of_rewritten_arbs() {
+        let statement = upsert_arb_statement("arbs");
+        let (_, updates) = statement.split_once("DO UPDATE SET").unwrap();
+        for column in ARB_COLUMNS {
+            assert_eq!(
+                updates.contains(&format!("{} = EXCLUDED.{}", column, column)),
+                !ARB_KEY_COLUMNS.contains(&column),
+                "{} is updated unlike the key",
+                column
+            );
+        }
+        assert!(statement.contains("$14)"));
+        assert!(statement.contains("ON CONFLICT (tx_hash, chain_id, (COALESCE(run_label, '')))"));
+    }
+
+    #[tokio::test]
+    async fn it_upserts_rerun_arbs_postgres() -> Result<()> {
+        let config = Config::load()?;
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
+        })
+        .await?;
+        let arb = SimArbResultBatch::test_example();
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        // the same batch, rerun with another quote asset
+        let rerun = SimArbResultBatch {
+            profit_token: ethers::types::Address::from_low_u64_be(0xda1),
+            profit_decimals: 6,
+            base_fee: Some(7.into()),
+            ..arb.clone()
+        };
+        connect.write_arbs(&vec![rerun.clone()]).await?;
+        // ... and by labeled runs, which are kept apart
+        let labeled = SimArbResultBatch {
+            run_label: Some("rerun".to_owned()),
+            ..arb.clone()
+        };
+        connect.write_arbs(&vec![labeled.clone(), labeled]).await?;
+        let rows = connect
+            .client
+            .query(
+                &format!(
+                    "SELECT run_label, profit_token, profit_decimals, base_fee FROM {} WHERE tx_hash = $1 ORDER BY run_label NULLS FIRST",
+                    connect.arbs_table
+                ),
+                &[&format!("{:?}", arb.event.hint.hash)],
+            )
+            .await?;
+        assert_eq!(rows.len(), 2);
+        let row = &rows[0];
+        assert_eq!(row.get::<_, Option<String>>(0), None);
+        assert_eq!(row.get::<_, String>(1), format!("{:?}", rerun.profit_token));
+        assert_eq!(row.get::<_, i32>(2), 6);
+        assert_eq!(row.get::<_, Option<Decimal>>(3), Some(Decimal::from(7)));
+        assert_eq!(
+            rows[1].get::<_, Option<String>>(0),
+            Some("rerun".to_owned())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reads_back_written_arbs_postgres() -> Result<()> {
+        let config = Config::load()?;
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+            namespace: DEFAULT_DB_NAMESPACE.to_owned(),
+        })
+        .await?;
+        let arb = SimArbResultBatch {
+            run_label: Some("round-trip".to_owned()),
+            max_profit: U256::from(1_234_567_890_123u64),
+            profit_decimals: 6,
+            base_fee: Some(7.into()),
+            block_hash: Some(H256::from_low_u64_be(0xb10c)),
+            ..SimArbResultBatch::test_example()
+        };
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        let filter = ArbFilterParams {
+            run_label: arb.run_label.clone(),
+            timestamp_start: Some(arb.event.timestamp as u32),
+            ..ArbFilterParams::none()
+        };
+        let arbs = connect.read_arbs(&filter, None, None).await?;
+        let read = arbs
+            .iter()
+            .find(|read| read.event.hint.hash == arb.event.hint.hash)
+            .expect("written arb wasn't read back");
+        assert_eq!(read.run_label, arb.run_label);
+        assert_eq!(read.event.block, arb.event.block);
+        assert_eq!(read.event.timestamp, arb.event.timestamp);
+        assert_eq!(read.max_profit, arb.max_profit);
+        assert_eq!(read.profit_token, arb.profit_token);
+        assert_eq!(read.profit_decimals, arb.profit_decimals);
+        assert_eq!(read.base_fee, arb.base_fee);
+        assert_eq!(read.block_hash, arb.block_hash);
+        Ok(())
+    }
+
     // #[tokio::test]
     // async fn it_reads_from_db() -> Result<()> {
     //     let config = Config::default();
@@ -0,0 +1,99 @@
+use crate::{
+    data::{
+        file::EXPORT_DIR,
+        tokens::{read_cache, write_cache},
+    },
+    debug, info,
+    util::{get_chainlink_answer, get_feed_decimals, WsClient},
+    Result,
+};
+use ethers::{types::Address, utils::format_units};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Number of blocks to read the feed at concurrently.
+const FETCH_BATCH_SIZE: usize = 50;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PricePoint {
+    pub block: u64,
+    /// Answer of the feed as of `block`, e.g. USD per ETH.
+    pub price: f64,
+}
+
+/// Cache of a Chainlink price feed's answers by block, saved as JSON in `EXPORT_DIR`.
+///
+/// Blocks that aren't cached yet are read from the chain with `fetch_missing`.
+#[derive(Clone, Debug)]
+pub struct PriceStore {
+    path: PathBuf,
+    feed: Address,
+    prices: BTreeMap<u64, f64>,
+}
+
+impl PriceStore {
+    /// Loads the store of `feed` on `chain_id`, starting empty if it hasn't been saved before.
+    pub fn load(chain_id: u64, feed: Address) -> Result<Self> {
+        let path = PathBuf::from(EXPORT_DIR).join(format!("prices_{}_{:?}.json", chain_id, feed));
+        let prices = read_cache::<PricePoint>(&path)?
+            .into_iter()
+            .map(|point| (point.block, point.price))
+            .collect();
+        Ok(Self { path, feed, prices })
+    }
+
+    /// Every cached answer, by block.
+    pub fn prices(&self) -> &BTreeMap<u64, f64> {
+        &self.prices
+    }
+
+    /// Reads the feed at each of `blocks` that isn't cached yet, then saves the store.
+    ///
+    /// Blocks that can't be read are skipped (and retried next time).
+    pub async fn fetch_missing(&mut self, client: &WsClient, blocks: &[u64]) -> Result<()> {
+        let mut missing = blocks
+            .iter()
+            .filter(|block| !self.prices.contains_key(block))
+            .copied()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "reading price feed {:?} at {} blocks",
+            self.feed,
+            missing.len()
+        );
+        let feed = self.feed;
+        let decimals = get_feed_decimals(client, feed).await?;
+        for batch in missing.chunks(FETCH_BATCH_SIZE) {
+            let fetched = join_all(batch.iter().map(|block| async move {
+                (*block, get_chainlink_answer(client, feed, *block).await)
+            }))
+            .await;
+            for (block, answer) in fetched {
+                let price = answer
+                    .and_then(|answer| Ok(format_units(answer, decimals as u32)?.parse::<f64>()?));
+                match price {
+                    Ok(price) => {
+                        self.prices.insert(block, price);
+                    }
+                    Err(err) => debug!("failed to read price feed at block {}: {}", block, err),
+                }
+            }
+        }
+        let points = self
+            .prices
+            .iter()
+            .map(|(block, price)| PricePoint {
+                block: *block,
+                price: *price,
+            })
+            .collect::<Vec<_>>();
+        write_cache(&self.path, points.iter().collect())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    data::file::EXPORT_DIR,
+    debug, info,
+    interfaces::{PoolVariant, TokenTax},
+    util::{get_pool_fee, WsClient},
+    Result,
+};
+use ethers::{
+    abi::{self, ParamType},
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest},
+};
+use futures::future::join_all;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
+
+/// Fee of every Uniswap V2 (and clone) pool, in hundredths of a bip.
+pub const V2_POOL_FEE: u32 = 3000;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenMetadata {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolMetadata {
+    pub address: Address,
+    pub variant: PoolVariant,
+    /// Swap fee in hundredths of a bip (e.g. 500 = 0.05%).
+    pub fee: u32,
+}
+
+/// Cache of token symbols & decimals, pool fees and measured token taxes for one chain, saved as JSON in `EXPORT_DIR`.
+///
+/// Entries that aren't cached yet are fetched from the chain with `fetch_missing` & `fetch_missing_pools`;
+/// taxes are measured by `sim::tax` and saved with `insert_taxes`.
+#[derive(Clone, Debug)]
+pub struct TokenMetadataStore {
+    tokens_path: PathBuf,
+    pools_path: PathBuf,
+    taxes_path: PathBuf,
+    tokens: HashMap<Address, TokenMetadata>,
+    pools: HashMap<Address, PoolMetadata>,
+    taxes: HashMap<Address, TokenTax>,
+}
+
+/// Reads a JSON cache file, or nothing if it doesn't exist yet.
+pub fn read_cache<T: DeserializeOwned>(path: &PathBuf) -> Result<Vec<T>> {
+    if path.exists() {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Writes a JSON cache file, creating its directory if needed.
+pub fn write_cache<T: Serialize>(path: &PathBuf, entries: Vec<&T>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&entries)?)?;
+    Ok(())
+}
+
+impl TokenMetadataStore {
+    /// Loads the store for `chain_id`, starting empty if it hasn't been saved before.
+    pub fn load(chain_id: u64) -> Result<Self> {
+        let tokens_path = PathBuf::from(EXPORT_DIR).join(format!("tokens_{}.json", chain_id));
+        let pools_path = PathBuf::from(EXPORT_DIR).join(format!("pools_{}.json", chain_id));
+        let taxes_path = PathBuf::from(EXPORT_DIR).join(format!("token_taxes_{}.json", chain_id));
+        let tokens = read_cache::<TokenMetadata>(&tokens_path)?
+            .into_iter()
+            .map(|token| (token.address, token))
+            .collect();
+        let pools = read_cache::<PoolMetadata>(&pools_path)?
+            .into_iter()
+            .map(|pool| (pool.address, pool))
+            .collect();
+        let taxes = read_cache::<TokenTax>(&taxes_path)?
+            .into_iter()
+            .map(|tax| (tax.token, tax))
+            .collect();
+        Ok(Self {
+            tokens_path,
+            pools_path,
+            taxes_path,
+            tokens,
+            pools,
+            taxes,
+        })
+    }
+
+    pub fn get(&self, token: &Address) -> Option<&TokenMetadata> {
+        self.tokens.get(token)
+    }
+
+    /// Returns the token's symbol, or its address if the symbol isn't known.
+    pub fn symbol(&self, token: &Address) -> String {
+        self.get(token)
+            .map(|token| token.symbol.to_owned())
+            .unwrap_or(format!("{:?}", token))
+    }
+
+    /// Fetches metadata for each of `tokens` that isn't cached yet, then saves the store.
+    ///
+    /// Tokens whose metadata can't be fetched are skipped (and retried next time).
+    pub async fn fetch_missing(&mut self, client: &WsClient, tokens: &[Address]) -> Result<()> {
+        let mut missing = tokens
+            .iter()
+            .filter(|token| !self.tokens.contains_key(token))
+            .copied()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        info!("fetching metadata for {} tokens", missing.len());
+        let fetched = join_all(
+            missing
+                .into_iter()
+                .map(|token| async move { (token, fetch_metadata(client, token).await) }),
+        )
+        .await;
+        for (token, metadata) in fetched {
+            match metadata {
+                Ok(metadata) => {
+                    self.tokens.insert(token, metadata);
+                }
+                Err(err) => debug!("failed to fetch metadata for {:?}: {}", token, err),
+            }
+        }
+        let mut tokens = self.tokens.values().collect::<Vec<_>>();
+        tokens.sort_by_key(|token| token.address);
+        write_cache(&self.tokens_path, tokens)
+    }
+
+    pub fn pool(&self, pool: &Address) -> Option<&PoolMetadata> {
+        self.pools.get(pool)
+    }
+
+    /// Fetches the fee of each of `pools` that isn't cached yet, then saves the store.
+    ///
+    /// V2 pools all charge the same fee, so only V3 pools are looked up.
+    pub async fn fetch_missing_pools(
+        &mut self,
+        client: &WsClient,
+        pools: &[(Address, PoolVariant)],
+    ) -> Result<()> {
+        let mut missing = pools
+            .iter()
+            .filter(|(pool, _)| !self.pools.contains_key(pool))
+            .copied()
+            .collect::<Vec<_>>();
+        missing.sort_by_key(|(pool, _)| *pool);
+        missing.dedup_by_key(|(pool, _)| *pool);
+        if missing.is_empty() {
+            return Ok(());
+        }
+        info!("fetching metadata for {} pools", missing.len());
+        let fetched = join_all(missing.into_iter().map(|(pool, variant)| async move {
+            let fee = match variant {
+                PoolVariant::UniswapV2 => Ok(V2_POOL_FEE),
+                PoolVariant::UniswapV3 => get_pool_fee(client, pool).await,
+                PoolVariant::Custom(_) => {
+                    Err(anyhow::format_err!("fees of custom pools aren't known"))
+                }
+            };
+            (pool, variant, fee)
+        }))
+        .await;
+        for (address, variant, fee) in fetched {
+            match fee {
+                Ok(fee) => {
+                    self.pools.insert(
+                        address,
+                        PoolMetadata {
+                            address,
+                            variant,
+                            fee,
+                        },
+                    );
+                }
+                Err(err) => debug!("failed to fetch fee of pool {:?}: {}", address, err),
+            }
+        }
+        let mut pools = self.pools.values().collect::<Vec<_>>();
+        pools.sort_by_key(|pool| pool.address);
+        write_cache(&self.pools_path, pools)
+    }
+
+    /// Returns the token's measured taxes, if it's been measured.
+    pub fn tax(&self, token: &Address) -> Option<&TokenTax> {
+        self.taxes.get(token)
+    }
+
+    /// Every measured token's taxes.
+    pub fn taxes(&self) -> Vec<TokenTax> {
+        let mut taxes = self.taxes.values().cloned().collect::<Vec<_>>();
+        taxes.sort_by_key(|tax| tax.token);
+        taxes
+    }
+
+    /// Saves newly measured taxes, replacing older measurements of the same tokens.
+    pub fn insert_taxes(&mut self, taxes: Vec<TokenTax>) -> Result<()> {
+        if taxes.is_empty() {
+            return Ok(());
+        }
+        for tax in taxes {
+            self.taxes.insert(tax.token, tax);
+        }
+        let mut taxes = self.taxes.values().collect::<Vec<_>>();
+        taxes.sort_by_key(|tax| tax.token);
+        write_cache(&self.taxes_path, taxes)
+    }
+}
+
+async fn call(client: &WsClient, token: Address, method: &str) -> Result<Bytes> {
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(token)
+        .data(Bytes::from_str(method)?)
+        .into();
+    Ok(client.call(&tx, None).await?)
+}
+
+/// Decodes a `symbol()` result. Some old tokens (e.g. MKR) return `bytes32` instead of `string`.
+fn decode_symbol(output: &[u8]) -> Option<String> {
+    if let Ok(tokens) = abi::decode(&[ParamType::String], output) {
+        return tokens[0].clone().into_string();
+    }
+    let bytes = abi::decode(&[ParamType::FixedBytes(32)], output)
+        .ok()?
+        .remove(0)
+        .into_fixed_bytes()?;
+    let symbol = String::from_utf8(bytes).ok()?;
+    Some(symbol.trim_end_matches('\0').to_owned())
+}
+
+async fn fetch_metadata(client: &WsClient, token: Address) -> Result<TokenMetadata> {
+    let symbol = decode_symbol(&call(client, token, "0x95d89b41").await?) // symbol()
+        .ok_or(anyhow::format_err!("failed to decode symbol"))?;
+    let decimals = abi::decode(
+        &[ParamType::Uint(8)],
+        &call(client, token, "0x313ce567").await?, // decimals()
+    )?[0]
+        .clone()
+        .into_uint()
+        .ok_or(anyhow::format_err!("failed to decode decimals"))?;
+    Ok(TokenMetadata {
+        address: token,
+        symbol,
+        decimals: decimals.as_u32() as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::Token;
+
+    #[test]
+    fn it_decodes_string_and_bytes32_symbols() {
+        let string_symbol = abi::encode(&[Token::String("SHIB".to_owned())]);
+        assert_eq!(decode_symbol(&string_symbol), Some("SHIB".to_owned()));
+        let mut mkr = [0u8; 32];
+        mkr[..3].copy_from_slice(b"MKR");
+        let bytes32_symbol = abi::encode(&[Token::FixedBytes(mkr.to_vec())]);
+        assert_eq!(decode_symbol(&bytes32_symbol), Some("MKR".to_owned()));
+    }
+}
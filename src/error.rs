@@ -15,8 +15,20 @@ pub enum HindsightError {
     CallError(String),
     /// Failed to perform math operation.
     MathError(String),
+    /// The result of a math operation doesn't fit in a U256.
+    Overflow(String),
     /// Failed to parse data into revm core types.
     EvmParseError(String),
+    /// A pool's reserves (or in-range liquidity) are empty or dust, so it can't be priced or traded against.
+    InsufficientLiquidity(String),
+    /// A MEV-Share hint's logs don't have the layout of any EVM log.
+    MalformedHint(String),
+    /// The user's tx reverted or halted when replayed on the fork, or couldn't be executed at all.
+    UserTxReverted(H256, String),
+    /// A token failed the safety screen (e.g. it can't be sold, or is taxed), so it isn't arbed.
+    UnsafeToken(Address, String),
+    /// The node or the store is on a different chain than the one configured (by its id).
+    ChainMismatch(u64, String),
 }
 
 impl Into<Error> for HindsightError {
@@ -38,9 +50,27 @@ impl Into<Error> for HindsightError {
             HindsightError::MathError(msg) => {
                 anyhow::format_err!("math error: {}", msg,)
             }
+            HindsightError::Overflow(msg) => anyhow::format_err!("overflow: {}", msg),
+            HindsightError::MalformedHint(msg) => anyhow::format_err!("malformed hint: {}", msg),
+            HindsightError::UserTxReverted(tx_hash, reason) => {
+                anyhow::format_err!("user tx reverted (hash={}): {}", tx_hash, reason)
+            }
+            HindsightError::UnsafeToken(token, reason) => {
+                anyhow::format_err!("unsafe token (address={:?}): {}", token, reason)
+            }
+            HindsightError::ChainMismatch(chain_id, reason) => {
+                anyhow::format_err!(
+                    "chain mismatch (configured chain id={}): {}",
+                    chain_id,
+                    reason
+                )
+            }
             HindsightError::EvmParseError(msg) => {
                 anyhow::format_err!("evm parse error: {}", msg,)
             }
+            HindsightError::InsufficientLiquidity(msg) => {
+                anyhow::format_err!("insufficient liquidity: {}", msg)
+            }
         }
     }
 }
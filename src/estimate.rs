@@ -0,0 +1,347 @@
+//! Predicts the work a scan of a range would do (txs simulated, EVM runs, RPC requests & runtime)
+//! from the range's events alone, so a job can be sized before it runs against an archive node.
+//!
+//! Estimates follow the pipeline's shape, but some of its costs depend on chain state that isn't fetched
+//! (e.g. how many pools trade each pair, or how deep each search goes), so those are assumed from the constants below.
+
+use crate::{
+    event_history::EventSource,
+    filter::AddressLists,
+    info,
+    interfaces::IndexedPool,
+    sim::core::{MAX_DEPTH, STEP_INTERVALS},
+    util::{filter_events_by_topic, WsClient},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256},
+};
+use mev_share_sse::{EventHistory, EventHistoryParams};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    time::Instant,
+};
+
+/// Other pools assumed to trade the pair of a swapped pool that isn't indexed (see `indexer`).
+pub const ASSUMED_ALT_POOLS: f64 = 2.0;
+/// Search steps that a route's search is assumed to take before it stops improving; it takes at most `MAX_DEPTH` + 1.
+pub const ASSUMED_SEARCH_DEPTH: usize = 3;
+/// Requests assumed to fetch the state that a block's fork first reads (the block, its accounts & storage).
+pub const ASSUMED_FORK_REQUESTS: u64 = 20;
+/// Requests assumed to fetch the pool & token state that each route's sims read from a fork, beyond the block's.
+pub const ASSUMED_ROUTE_REQUESTS: u64 = 10;
+/// Milliseconds assumed per EVM run (a swap or a backrun) on state that's already been fetched.
+pub const ASSUMED_EVM_RUN_MS: f64 = 3.0;
+/// Requests that find the pools of a token pair: one per UniswapV3 fee tier, and one for UniswapV2.
+const PAIR_LOOKUP_REQUESTS: u64 = 5;
+/// Requests that fetch an unindexed pool's tokens, factory & a token's decimals.
+const POOL_LOOKUP_REQUESTS: u64 = 3;
+/// Number of requests timed to measure the node's latency.
+const LATENCY_SAMPLES: u32 = 3;
+
+/// Scan settings that the work depends on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkloadSettings {
+    /// Txs simulated at once, which also bounds the requests in flight.
+    pub batch_size: usize,
+    /// Forks & sims that may run at once (see `SimBudget`), bounded by the host's cores.
+    pub evm_parallelism: usize,
+    /// Cap on each tx's EVM runs (see `TxSimLimits::max_sims`).
+    pub max_sims: Option<usize>,
+    /// Whether each trade's token is screened first (see `TxSimLimits::max_token_tax`).
+    pub screen_tokens: bool,
+    /// Whether the receipts of events that hint no logs are fetched (see `ScanOptions::replay_private`).
+    pub replay_private: bool,
+    /// Cycles through more pools backrun per trade, if `ScanOptions::max_hops` is set.
+    pub cycles_per_trade: usize,
+}
+
+/// Predicted work of a scan of one chain's range.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkloadEstimate {
+    pub chain_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_block: Option<u64>,
+    /// Events in the range.
+    pub num_events: usize,
+    /// Events whose txs would be simulated: those hinting a swap (& allowed by the address lists, if any).
+    pub num_candidates: usize,
+    /// Blocks of the candidates, each of which is forked.
+    pub num_blocks: usize,
+    /// Trades to backrun: the pools swapped on by each candidate.
+    pub num_trades: usize,
+    /// Distinct pools swapped on.
+    pub num_pools: usize,
+    /// Of `num_pools`, those that the `indexer` saved, whose tokens & pairs are known without requests.
+    pub num_indexed_pools: usize,
+    /// Routes (backrun searches) expected, including cycles.
+    pub num_routes: u64,
+    /// EVM runs expected, if searches take `ASSUMED_SEARCH_DEPTH` steps.
+    pub evm_runs: u64,
+    /// EVM runs if every search went to `MAX_DEPTH`, within each tx's `max_sims`.
+    pub max_evm_runs: u64,
+    /// RPC requests expected, besides fetching the events.
+    pub rpc_requests: u64,
+    /// Measured latency of a request to the node.
+    pub rpc_latency_ms: f64,
+    /// Expected wall time of the scan, in seconds.
+    pub runtime_secs: f64,
+}
+
+/// Other pools expected to trade the pair of each swapped pool: those indexed with the same tokens,
+/// or `ASSUMED_ALT_POOLS` if the pool isn't indexed.
+fn alt_pools(pools: &BTreeSet<Address>, indexed: &[IndexedPool]) -> HashMap<Address, f64> {
+    let by_address = indexed
+        .iter()
+        .map(|pool| (pool.address, pool))
+        .collect::<HashMap<_, _>>();
+    let mut pools_per_pair = HashMap::<(Address, Address), usize>::new();
+    for pool in indexed {
+        *pools_per_pair
+            .entry((pool.token0, pool.token1))
+            .or_default() += 1;
+    }
+    pools
+        .iter()
+        .map(|pool| {
+            let alts = match by_address.get(pool) {
+                Some(pool) => pools_per_pair[&(pool.token0, pool.token1)].saturating_sub(1) as f64,
+                None => ASSUMED_ALT_POOLS,
+            };
+            (*pool, alts)
+        })
+        .collect()
+}
+
+/// Predicts the work of scanning `events`, given the pools that the `indexer` saved (if any) and a request latency.
+pub fn estimate_workload(
+    chain_id: u64,
+    events: &[EventHistory],
+    swap_topics: &[H256],
+    lists: Option<&AddressLists>,
+    indexed: &[IndexedPool],
+    settings: &WorkloadSettings,
+    rpc_latency_ms: f64,
+) -> WorkloadEstimate {
+    let mut candidates = filter_events_by_topic(&events.to_vec(), &swap_topics.to_vec());
+    if let Some(lists) = lists {
+        candidates.retain(|event| lists.allows_event(event));
+    }
+    // one trade per pool that a tx swaps on
+    let trades = candidates
+        .iter()
+        .map(|event| {
+            event
+                .hint
+                .logs
+                .iter()
+                .filter(|log| {
+                    log.topics
+                        .first()
+                        .is_some_and(|topic| swap_topics.contains(topic))
+                })
+                .map(|log| log.address)
+                .collect::<BTreeSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let pools = trades.iter().flatten().copied().collect::<BTreeSet<_>>();
+    let alt_pools = alt_pools(&pools, indexed);
+    let indexed_pools = indexed
+        .iter()
+        .filter(|pool| pools.contains(&pool.address))
+        .collect::<Vec<_>>();
+    let num_indexed_pools = indexed_pools.len();
+    let blocks = candidates
+        .iter()
+        .map(|event| event.block)
+        .collect::<BTreeSet<_>>();
+
+    // EVM runs of each route: the user's tx, then each step's intervals
+    let route_runs = |depths: usize| 1 + (STEP_INTERVALS * depths) as u64;
+    let screen_runs = if settings.screen_tokens { 2 } else { 0 };
+    let (mut num_routes, mut evm_runs, mut max_evm_runs) = (0u64, 0u64, 0u64);
+    for trade in &trades {
+        let (mut tx_runs, mut tx_max_runs) = (0u64, 0u64);
+        for pool in trade {
+            let routes = alt_pools[pool].round() as u64 + settings.cycles_per_trade as u64;
+            num_routes += routes;
+            // every other pool is quoted on one fork first
+            let fixed = screen_runs + alt_pools[pool].round() as u64;
+            tx_runs += fixed + routes * route_runs(ASSUMED_SEARCH_DEPTH);
+            tx_max_runs += fixed + routes * route_runs(MAX_DEPTH + 1);
+        }
+        let cap = |runs: u64| match settings.max_sims {
+            Some(max_sims) => runs.min(max_sims as u64),
+            None => runs,
+        };
+        evm_runs += cap(tx_runs);
+        max_evm_runs += cap(tx_max_runs);
+    }
+
+    // each candidate's tx & receipt, each block's fork, and each route's state
+    let mut rpc_requests = 2 * candidates.len() as u64
+        + (1 + ASSUMED_FORK_REQUESTS) * blocks.len() as u64
+        + ASSUMED_ROUTE_REQUESTS * num_routes;
+    if settings.replay_private {
+        rpc_requests += events
+            .iter()
+            .filter(|event| event.hint.logs.is_empty())
+            .count() as u64;
+    }
+    // indexed pools' tokens & factories are known, but their tokens' decimals & pairs' pools are fetched
+    let num_unindexed = (pools.len() - num_indexed_pools) as u64;
+    let indexed_tokens = indexed_pools
+        .iter()
+        .flat_map(|pool| [pool.token0, pool.token1])
+        .collect::<HashSet<_>>();
+    let indexed_pairs = indexed_pools
+        .iter()
+        .map(|pool| (pool.token0, pool.token1))
+        .collect::<HashSet<_>>();
+    rpc_requests += (POOL_LOOKUP_REQUESTS + PAIR_LOOKUP_REQUESTS) * num_unindexed
+        + indexed_tokens.len() as u64
+        + PAIR_LOOKUP_REQUESTS * indexed_pairs.len() as u64;
+
+    let request_secs =
+        rpc_requests as f64 * rpc_latency_ms / 1000.0 / settings.batch_size.max(1) as f64;
+    let evm_secs =
+        evm_runs as f64 * ASSUMED_EVM_RUN_MS / 1000.0 / settings.evm_parallelism.max(1) as f64;
+    WorkloadEstimate {
+        chain_id,
+        first_block: events.first().map(|event| event.block),
+        last_block: events.last().map(|event| event.block),
+        num_events: events.len(),
+        num_candidates: candidates.len(),
+        num_blocks: blocks.len(),
+        num_trades: trades.iter().map(|trade| trade.len()).sum(),
+        num_pools: pools.len(),
+        num_indexed_pools,
+        num_routes,
+        evm_runs,
+        max_evm_runs,
+        rpc_requests,
+        rpc_latency_ms,
+        runtime_secs: request_secs + evm_secs,
+    }
+}
+
+/// Fetches every event in the range of `params` (starting at its offset) from `event_source`, page by page,
+/// until a page comes back empty.
+pub async fn fetch_range(
+    event_source: &dyn EventSource,
+    mut params: EventHistoryParams,
+) -> Result<Vec<EventHistory>> {
+    let mut events = vec![];
+    loop {
+        let page = event_source.event_history(params.to_owned()).await?;
+        if page.is_empty() {
+            break;
+        }
+        params.offset = Some(params.offset.unwrap_or(0) + page.len() as u64);
+        events.extend(page);
+        info!("fetched {} events", events.len());
+    }
+    Ok(events)
+}
+
+/// Measures the node's latency, in milliseconds, as the mean of a few `eth_blockNumber` requests.
+pub async fn measure_latency(client: &WsClient) -> Result<f64> {
+    let start = Instant::now();
+    for _ in 0..LATENCY_SAMPLES {
+        client.get_block_number().await?;
+    }
+    Ok(start.elapsed().as_secs_f64() * 1000.0 / LATENCY_SAMPLES as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::PoolVariant;
+    use mev_share_sse::{EventTransactionLog, Hint};
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn event(block: u64, logs: Vec<EventTransactionLog>) -> EventHistory {
+        EventHistory {
+            block,
+            timestamp: block * 12,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::from_low_u64_be(block),
+                logs,
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        }
+    }
+
+    fn log(pool: u64, topic: H256) -> EventTransactionLog {
+        EventTransactionLog {
+            address: address(pool),
+            topics: vec![topic],
+            data: Default::default(),
+        }
+    }
+
+    #[test]
+    fn it_estimates_the_work_of_a_range() {
+        let swap = H256::repeat_byte(1);
+        let events = [
+            // two swaps on the same pool are one trade
+            event(1, vec![log(0xa, swap), log(0xa, swap)]),
+            event(1, vec![log(0xb, swap), log(0xc, H256::repeat_byte(2))]),
+            event(2, vec![]),
+        ];
+        // pool a's pair has 3 indexed pools
+        let indexed = [0xa, 0xd, 0xe].map(|pool| IndexedPool {
+            chain_id: 1,
+            address: address(pool),
+            variant: PoolVariant::UniswapV2,
+            factory: address(0xf),
+            token0: address(1),
+            token1: address(2),
+            fee: 3000,
+            created_block: 1,
+        });
+        let settings = WorkloadSettings {
+            batch_size: 2,
+            evm_parallelism: 4,
+            ..Default::default()
+        };
+        let estimate = estimate_workload(1, &events, &[swap], None, &indexed, &settings, 10.0);
+        assert_eq!(
+            (
+                estimate.num_events,
+                estimate.num_candidates,
+                estimate.num_blocks
+            ),
+            (3, 2, 1)
+        );
+        assert_eq!(
+            (
+                estimate.num_trades,
+                estimate.num_pools,
+                estimate.num_indexed_pools
+            ),
+            (2, 2, 1)
+        );
+        // 2 alt pools of pool a (indexed) & 2 assumed for pool b
+        assert_eq!(estimate.num_routes, 4);
+        assert_eq!(estimate.evm_runs, 4 + 4 * (1 + 15 * 3));
+        assert!(estimate.max_evm_runs > estimate.evm_runs);
+
+        let capped = WorkloadSettings {
+            max_sims: Some(10),
+            ..settings
+        };
+        let estimate = estimate_workload(1, &events, &[swap], None, &indexed, &capped, 10.0);
+        assert_eq!((estimate.evm_runs, estimate.max_evm_runs), (20, 20));
+    }
+}
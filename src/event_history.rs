@@ -1,13 +1,52 @@
-use crate::Result;
+use crate::{info, Result};
+use async_trait::async_trait;
 use mev_share_sse::{EventClient, EventHistory, EventHistoryParams};
 
-const FLASHBOTS_EVENTS_API_URL: &'static str = "https://mev-share.flashbots.net/api/v1";
+pub const FLASHBOTS_EVENTS_API_URL: &'static str = "https://mev-share.flashbots.net/api/v1";
 
 pub fn event_history_info_url() -> String {
     format!("{}/{}", FLASHBOTS_EVENTS_API_URL, "history/info")
 }
 pub fn event_history_url() -> String {
-    format!("{}/{}", FLASHBOTS_EVENTS_API_URL, "history")
+    event_history_url_for(FLASHBOTS_EVENTS_API_URL)
+}
+/// History endpoint of the events API hosted at `api_url`.
+pub fn event_history_url_for(api_url: &str) -> String {
+    format!("{}/{}", api_url.trim_end_matches('/'), "history")
+}
+
+/// Where scans get MEV-Share events from. Implement it to scan events from elsewhere (e.g. a file or a queue).
+#[async_trait]
+pub trait EventSource: Sync + Send {
+    /// Fetches up to `params.limit` events in the range of `params`, starting at `params.offset`, oldest first.
+    async fn event_history(&self, params: EventHistoryParams) -> Result<Vec<EventHistory>>;
+}
+
+/// Events served by the history endpoint of a MEV-Share events API.
+#[derive(Clone, Debug)]
+pub struct MevShareEvents {
+    pub client: EventClient,
+    /// Base URL of the events API, e.g. `FLASHBOTS_EVENTS_API_URL`.
+    pub api_url: String,
+}
+
+impl MevShareEvents {
+    pub fn new(client: EventClient, api_url: &str) -> Self {
+        Self {
+            client,
+            api_url: api_url.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSource for MevShareEvents {
+    async fn event_history(&self, params: EventHistoryParams) -> Result<Vec<EventHistory>> {
+        Ok(self
+            .client
+            .event_history(&event_history_url_for(&self.api_url), params)
+            .await?)
+    }
 }
 
 /// Fetches events from the Flashbots MEV-Share SSE API. Iteratively queries for
@@ -41,7 +80,7 @@ pub async fn fetch_latest_events(
         current_offset += chunk_len;
         events.append(&mut chunk);
         done = chunk_len < params.limit.unwrap_or(500);
-        println!(
+        info!(
             "Fetched {} events ({} events total)",
             chunk_len,
             events.len()
@@ -0,0 +1,197 @@
+use crate::{interfaces::PoolVariant, signer::Signer, util::WsClient, Result};
+use ethers::{
+    abi::AbiEncode,
+    prelude::abigen,
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Bytes, Eip1559TransactionRequest, U256,
+    },
+};
+use std::path::Path;
+
+abigen!(
+    Executor,
+    r#"[
+        struct Swap { address pool; uint8 variant; address tokenIn; address tokenOut; }
+        function owner() external view returns (address)
+        function backrun(uint256 amountIn, Swap[] calldata swaps, uint256 minProfit) external returns (uint256 profit)
+        function uniswapV3SwapCallback(int256 amount0Delta, int256 amount1Delta, bytes calldata data) external
+        function withdraw(address token, uint256 amount) external
+        error NotOwner()
+        error InvalidRoute()
+        error UnknownVariant(uint8 variant)
+        error UnexpectedCallback(address caller)
+        error TransferFailed(address token)
+        error Unprofitable(uint256 balanceBefore, uint256 balanceAfter)
+    ]"#
+);
+
+/// Forge artifact that `deploy-executor` reads by default, built with `forge build --root contracts`.
+pub const DEFAULT_ARTIFACT: &str = "contracts/out/Executor.sol/Executor.json";
+
+/// One swap of an executor route on `pool`. Only UniswapV2 & V3 pools (and their clones) are supported.
+pub fn swap_leg(
+    pool: Address,
+    variant: &PoolVariant,
+    token_in: Address,
+    token_out: Address,
+) -> Result<Swap> {
+    let variant = match variant {
+        PoolVariant::UniswapV2 => 0,
+        PoolVariant::UniswapV3 => 1,
+        PoolVariant::Custom(id) => {
+            return Err(anyhow::format_err!(
+                "the executor can't swap on custom pool {:?} (adapter {})",
+                pool,
+                id
+            ))
+        }
+    };
+    Ok(Swap {
+        pool,
+        variant,
+        token_in,
+        token_out,
+    })
+}
+
+/// Route of a backrun like the ones hindsight simulates: buy `token` with `quote` on the start pool, then sell it
+/// back for `quote` on the end pool.
+pub fn backrun_route(
+    start: (Address, &PoolVariant),
+    end: (Address, &PoolVariant),
+    quote: Address,
+    token: Address,
+) -> Result<Vec<Swap>> {
+    Ok(vec![
+        swap_leg(start.0, start.1, quote, token)?,
+        swap_leg(end.0, end.1, token, quote)?,
+    ])
+}
+
+/// Calldata of an executor `backrun` that swaps `amount_in` through `route`, reverting unless it makes `min_profit`.
+pub fn backrun_calldata(amount_in: U256, route: Vec<Swap>, min_profit: U256) -> Bytes {
+    BackrunCall {
+        amount_in,
+        swaps: route,
+        min_profit,
+    }
+    .encode()
+    .into()
+}
+
+/// Reads the creation bytecode from a compiled contract artifact. Accepts Forge's (`bytecode.object`) and
+/// Hardhat's or solc's (`bytecode`) formats.
+pub fn parse_artifact_bytecode(artifact: &str) -> Result<Bytes> {
+    let artifact: serde_json::Value = serde_json::from_str(artifact)?;
+    let bytecode = artifact
+        .get("bytecode")
+        .and_then(|bytecode| bytecode.get("object").or(Some(bytecode)))
+        .and_then(|bytecode| bytecode.as_str())
+        .ok_or(anyhow::format_err!("artifact has no bytecode"))?;
+    let bytecode = bytecode.parse::<Bytes>()?;
+    if bytecode.is_empty() {
+        return Err(anyhow::format_err!(
+            "artifact's bytecode is empty; is it an interface?"
+        ));
+    }
+    Ok(bytecode)
+}
+
+/// Loads the executor's creation bytecode from the artifact at `path`.
+pub fn load_artifact(path: &Path) -> Result<Bytes> {
+    let artifact = std::fs::read_to_string(path).map_err(|err| {
+        anyhow::format_err!(
+            "failed to read {:?} ({}); build it with `forge build --root contracts`",
+            path,
+            err
+        )
+    })?;
+    parse_artifact_bytecode(&artifact)
+        .map_err(|err| anyhow::format_err!("invalid artifact {:?}: {}", path, err))
+}
+
+/// Deploys the executor from `signer`, which becomes its owner, and returns its address once the deployment lands.
+pub async fn deploy(client: &WsClient, signer: &Signer, bytecode: Bytes) -> Result<Address> {
+    let chain_id = client.get_chainid().await?;
+    let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+        .from(signer.address())
+        .data(bytecode)
+        .chain_id(chain_id.as_u64())
+        .into();
+    client.fill_transaction(&mut tx, None).await?;
+    let signature = signer.sign_transaction(&tx).await?;
+    let receipt = client
+        .send_raw_transaction(tx.rlp_signed(&signature))
+        .await?
+        .await?
+        .ok_or(anyhow::format_err!("deployment tx was dropped"))?;
+    if receipt.status != Some(1.into()) {
+        return Err(anyhow::format_err!(
+            "deployment tx {:?} reverted",
+            receipt.transaction_hash
+        ));
+    }
+    receipt.contract_address.ok_or(anyhow::format_err!(
+        "deployment tx {:?} created no contract",
+        receipt.transaction_hash
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::AbiDecode;
+
+    #[test]
+    fn it_encodes_backrun_calldata() -> Result<()> {
+        let (quote, token) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let (start_pool, end_pool) = (Address::from_low_u64_be(3), Address::from_low_u64_be(4));
+        let route = backrun_route(
+            (start_pool, &PoolVariant::UniswapV3),
+            (end_pool, &PoolVariant::UniswapV2),
+            quote,
+            token,
+        )?;
+        let data = backrun_calldata(U256::exp10(18), route, U256::from(1000));
+        let call = BackrunCall::decode(&data)?;
+        assert_eq!(call.amount_in, U256::exp10(18));
+        assert_eq!(call.min_profit, U256::from(1000));
+        assert_eq!(
+            call.swaps,
+            vec![
+                Swap {
+                    pool: start_pool,
+                    variant: 1,
+                    token_in: quote,
+                    token_out: token,
+                },
+                Swap {
+                    pool: end_pool,
+                    variant: 0,
+                    token_in: token,
+                    token_out: quote,
+                },
+            ]
+        );
+        assert!(swap_leg(start_pool, &PoolVariant::Custom(7), quote, token).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_artifact_bytecode() -> Result<()> {
+        let forge = r#"{"abi":[],"bytecode":{"object":"0x6080604052","linkReferences":{}}}"#;
+        let hardhat = r#"{"abi":[],"bytecode":"0x6080604052"}"#;
+        assert_eq!(
+            parse_artifact_bytecode(forge)?.to_vec(),
+            vec![0x60, 0x80, 0x60, 0x40, 0x52]
+        );
+        assert_eq!(
+            parse_artifact_bytecode(hardhat)?,
+            parse_artifact_bytecode(forge)?
+        );
+        assert!(parse_artifact_bytecode(r#"{"abi":[],"bytecode":"0x"}"#).is_err());
+        assert!(parse_artifact_bytecode(r#"{"abi":[]}"#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{info, log_error, Result};
+use ethers::types::Address;
+use mev_share_sse::EventHistory;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct AddressList {
+    tokens: HashSet<Address>,
+    pools: HashSet<Address>,
+}
+
+/// Tokens & pools to include in or exclude from a scan.
+///
+/// Read from a TOML file:
+/// ```toml
+/// [deny]
+/// tokens = ["0x..."]
+/// pools = ["0x..."]
+///
+/// [allow]
+/// # if set, only these tokens are traded
+/// tokens = []
+/// ```
+/// Denied addresses are always skipped. A non-empty allow list skips everything not on it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AddressLists {
+    allow: AddressList,
+    deny: AddressList,
+}
+
+fn is_allowed(address: &Address, allow: &HashSet<Address>, deny: &HashSet<Address>) -> bool {
+    !deny.contains(address) && (allow.is_empty() || allow.contains(address))
+}
+
+impl AddressLists {
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    pub fn allows_token(&self, token: &Address) -> bool {
+        is_allowed(token, &self.allow.tokens, &self.deny.tokens)
+    }
+
+    pub fn allows_pool(&self, pool: &Address) -> bool {
+        is_allowed(pool, &self.allow.pools, &self.deny.pools)
+    }
+
+    /// Returns true if any of the event's logs came from an allowed pool.
+    ///
+    /// Hints don't reveal tokens, so token lists are applied later, once the pool's tokens are known.
+    pub fn allows_event(&self, event: &EventHistory) -> bool {
+        event
+            .hint
+            .logs
+            .iter()
+            .any(|log| self.allows_pool(&log.address))
+    }
+
+    pub fn len(&self) -> usize {
+        self.allow.tokens.len()
+            + self.allow.pools.len()
+            + self.deny.tokens.len()
+            + self.deny.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Address lists that are reloaded whenever their file changes.
+#[derive(Debug)]
+pub struct WatchedAddressLists {
+    path: PathBuf,
+    lists: RwLock<Arc<AddressLists>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl WatchedAddressLists {
+    /// Loads the lists from `path`. Fails if the file can't be read or parsed.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let watched = Self {
+            path,
+            lists: RwLock::new(Arc::new(AddressLists::default())),
+            modified: Mutex::new(None),
+        };
+        watched.reload_if_changed()?;
+        Ok(watched)
+    }
+
+    /// Returns the latest lists.
+    pub fn get(&self) -> Arc<AddressLists> {
+        self.lists
+            .read()
+            .expect("address lists lock poisoned")
+            .clone()
+    }
+
+    /// Reloads the lists if the file was modified since it was last read. Returns true if it was reloaded.
+    fn reload_if_changed(&self) -> Result<bool> {
+        let modified = std::fs::metadata(&self.path)?.modified()?;
+        let mut last_modified = self.modified.lock().expect("address lists lock poisoned");
+        if *last_modified == Some(modified) {
+            return Ok(false);
+        }
+        let lists = AddressLists::parse(&std::fs::read_to_string(&self.path)?)
+            .map_err(|err| anyhow::format_err!("invalid address lists {:?}: {}", self.path, err))?;
+        info!("loaded {} addresses from {:?}", lists.len(), self.path);
+        *self.lists.write().expect("address lists lock poisoned") = Arc::new(lists);
+        *last_modified = Some(modified);
+        Ok(true)
+    }
+
+    /// Checks the file for changes every `interval`, forever.
+    ///
+    /// If the file becomes unreadable or invalid, the previous lists stay in effect.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = self.reload_if_changed() {
+                log_error!("failed to reload address lists: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_applies_allow_and_deny_lists() -> Result<()> {
+        let honeypot = Address::from_low_u64_be(0x1);
+        let shib = Address::from_low_u64_be(0x2);
+        let pool = Address::from_low_u64_be(0x3);
+        let lists = AddressLists::parse(&format!(
+            r#"
+            [deny]
+            tokens = ["{:?}"]
+            "#,
+            honeypot
+        ))?;
+        assert!(!lists.allows_token(&honeypot));
+        assert!(lists.allows_token(&shib));
+        assert!(lists.allows_pool(&pool));
+        assert_eq!(lists.len(), 1);
+        assert!(!lists.is_empty());
+        assert!(AddressLists::parse("")?.is_empty());
+
+        let lists = AddressLists::parse(&format!(
+            r#"
+            [allow]
+            pools = ["{:?}"]
+            "#,
+            pool
+        ))?;
+        assert!(lists.allows_pool(&pool));
+        assert!(!lists.allows_pool(&shib));
+        assert!(lists.allows_token(&honeypot));
+        Ok(())
+    }
+
+    #[test]
+    fn it_reloads_changed_lists() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("hindsight-lists-{}.toml", rand::random::<u32>()));
+        let token = Address::from_low_u64_be(0x1);
+        std::fs::write(&path, "")?;
+        let watched = WatchedAddressLists::load(path.clone())?;
+        assert!(watched.get().allows_token(&token));
+
+        std::fs::write(&path, format!("[deny]\ntokens = [\"{:?}\"]", token))?;
+        // filesystems with coarse timestamps may not register the change, so forget the last one
+        *watched.modified.lock().unwrap() = None;
+        assert!(watched.reload_if_changed()?);
+        assert!(!watched.get().allows_token(&token));
+
+        std::fs::write(&path, "not [valid toml")?;
+        *watched.modified.lock().unwrap() = None;
+        assert!(watched.reload_if_changed().is_err());
+        assert!(!watched.get().allows_token(&token));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Golden fixtures: historical backruns that the engine is known to find, replayed from recorded node responses,
+//! so a change to the engine that loses (or invents) profit on them fails the tests.
+//!
+//! Each fixture is a directory in `fixtures/golden` with the tx's MEV-Share event (`event.json`), a cassette of the
+//! node's responses to its simulation (`rpc.jsonl`), and the arb that was found when it was recorded (`expected.json`).
+//! Run the tests with `GOLDEN_RECORD=1` (and `RPC_URL_WS` pointing at an archive node) to (re-)record them.
+use crate::{
+    cassette::{serve, CassetteMode},
+    commands::selftest::simulate_event,
+    config::Config,
+    hindsight::Hindsight,
+    interfaces::SimArbResultBatch,
+    util::get_ws_client,
+    Result,
+};
+use ethers::types::{Address, U256};
+use mev_share_sse::EventHistory;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/golden");
+/// How far (in basis points of the expected profit) a replayed arb's profit may drift before the fixture fails.
+const PROFIT_TOLERANCE_BPS: u64 = 100;
+
+/// The arb found for a fixture when it was recorded.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Expected {
+    max_profit: U256,
+    profit_token: Address,
+}
+
+impl From<&SimArbResultBatch> for Expected {
+    fn from(arb: &SimArbResultBatch) -> Self {
+        Self {
+            max_profit: arb.max_profit,
+            profit_token: arb.profit_token,
+        }
+    }
+}
+
+fn fixtures() -> Result<Vec<PathBuf>> {
+    let mut dirs = std::fs::read_dir(FIXTURES_DIR)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    dirs.retain(|dir| dir.join("event.json").exists());
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Simulates the fixture in `dir` against its cassette, recording the cassette from the node at `rpc_url` in `Record` mode.
+async fn simulate(dir: &Path, mode: CassetteMode, rpc_url: &str) -> Result<SimArbResultBatch> {
+    let event: EventHistory =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("event.json"))?)?;
+    let url = serve(mode, &dir.join("rpc.jsonl"), rpc_url).await?;
+    let client = get_ws_client(Some(url)).await?;
+    let hindsight = Hindsight::builder().provider(client).build().await?;
+    simulate_event(hindsight, event).await
+}
+
+/// Whether `actual` is within `PROFIT_TOLERANCE_BPS` of `expected`.
+fn within_tolerance(actual: U256, expected: U256) -> bool {
+    let diff = if actual > expected {
+        actual - expected
+    } else {
+        expected - actual
+    };
+    diff * 10_000 <= expected * PROFIT_TOLERANCE_BPS
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn it_reproduces_golden_arbs() -> Result<()> {
+    // replays don't connect to a node, so they don't need a configured environment
+    let rpc_url = match std::env::var("GOLDEN_RECORD") {
+        Ok(_) => Some(Config::load()?.rpc_url_ws),
+        Err(_) => None,
+    };
+    let dirs = fixtures()?;
+    assert!(!dirs.is_empty(), "no golden fixtures in {}", FIXTURES_DIR);
+    // an unrecorded fixture checks nothing, so it fails rather than passing silently
+    let unrecorded = dirs
+        .iter()
+        .filter(|dir| !dir.join("rpc.jsonl").exists() || !dir.join("expected.json").exists())
+        .map(|dir| dir.file_name().unwrap_or_default().to_string_lossy())
+        .collect::<Vec<_>>();
+    assert!(
+        rpc_url.is_some() || unrecorded.is_empty(),
+        "golden fixtures not recorded: {}; record them with GOLDEN_RECORD=1 against an archive node",
+        unrecorded.join(", ")
+    );
+    for dir in dirs {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(rpc_url) = &rpc_url {
+            let _ = std::fs::remove_file(dir.join("rpc.jsonl"));
+            let arb = simulate(&dir, CassetteMode::Record, rpc_url).await?;
+            let expected = serde_json::to_string_pretty(&Expected::from(&arb))?;
+            std::fs::write(dir.join("expected.json"), expected + "\n")?;
+            continue;
+        }
+        let expected: Expected =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("expected.json"))?)?;
+        let arb = simulate(&dir, CassetteMode::Replay, "").await?;
+        assert_eq!(arb.profit_token, expected.profit_token, "{}", name);
+        assert!(
+            within_tolerance(arb.max_profit, expected.max_profit),
+            "{}: found {} profit, expected {}",
+            name,
+            arb.max_profit,
+            expected.max_profit
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn it_tolerates_small_profit_drift() {
+    let expected = U256::from(1_000_000);
+    assert!(within_tolerance(expected, expected));
+    assert!(within_tolerance(U256::from(990_000), expected));
+    assert!(within_tolerance(U256::from(1_010_000), expected));
+    assert!(!within_tolerance(U256::from(989_999), expected));
+    assert!(!within_tolerance(U256::zero(), expected));
+}
@@ -0,0 +1,97 @@
+use crate::{interfaces::SimArbResultBatch, service::SimService};
+use ethers::types::H256;
+use futures::{future, Stream, StreamExt};
+use std::{net::SocketAddr, pin::Pin};
+use tonic::{transport::Server, Request, Response, Status};
+
+/// Types & service generated from `proto/hindsight.proto`.
+pub mod proto {
+    tonic::include_proto!("hindsight");
+}
+
+use proto::{
+    simulator_server::{Simulator, SimulatorServer},
+    ArbResult, GetStatsRequest, SimulateBackrunRequest, Stats, StreamResultsRequest,
+};
+
+impl From<&SimArbResultBatch> for ArbResult {
+    fn from(arb: &SimArbResultBatch) -> Self {
+        Self {
+            tx_hash: format!("{:?}", arb.event.hint.hash),
+            block: arb.event.block,
+            timestamp: arb.event.timestamp,
+            chain_id: arb.chain_id,
+            profit_token: format!("{:?}", arb.profit_token),
+            profit_decimals: arb.profit_decimals,
+            max_profit: arb.max_profit.to_string(),
+            json: serde_json::to_string(arb).unwrap_or_default(),
+        }
+    }
+}
+
+/// Serves a `SimService` over gRPC.
+pub struct GrpcSimulator {
+    pub service: SimService,
+}
+
+#[tonic::async_trait]
+impl Simulator for GrpcSimulator {
+    async fn simulate_backrun(
+        &self,
+        request: Request<SimulateBackrunRequest>,
+    ) -> Result<Response<ArbResult>, Status> {
+        let tx_hash = request
+            .into_inner()
+            .tx_hash
+            .parse::<H256>()
+            .map_err(|err| Status::invalid_argument(format!("invalid tx_hash: {}", err)))?;
+        let arb = self
+            .service
+            .simulate_backrun(tx_hash)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(ArbResult::from(&arb)))
+    }
+
+    type StreamResultsStream = Pin<Box<dyn Stream<Item = Result<ArbResult, Status>> + Send>>;
+
+    async fn stream_results(
+        &self,
+        request: Request<StreamResultsRequest>,
+    ) -> Result<Response<Self::StreamResultsStream>, Status> {
+        let profitable_only = request.into_inner().profitable_only;
+        let results = self
+            .service
+            .subscribe()
+            .filter(move |arb| future::ready(!profitable_only || !arb.max_profit.is_zero()))
+            .map(|arb| Ok::<_, Status>(ArbResult::from(&arb)));
+        Ok(Response::new(Box::pin(results)))
+    }
+
+    async fn get_stats(
+        &self,
+        _request: Request<GetStatsRequest>,
+    ) -> Result<Response<Stats>, Status> {
+        let stats = self
+            .service
+            .stats()
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(Stats {
+            num_requests: stats.num_requests,
+            num_simulated: stats.num_simulated,
+            num_profitable: stats.num_profitable,
+            num_failed: stats.num_failed,
+            num_stored: stats.num_stored,
+        }))
+    }
+}
+
+/// Serves `service` over gRPC on `addr` until the server fails.
+pub async fn serve(service: SimService, addr: SocketAddr) -> crate::Result<()> {
+    Server::builder()
+        .add_service(SimulatorServer::new(GrpcSimulator { service }))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -1,97 +1,602 @@
 use crate::{
-    data::arbs::ArbDatabase,
+    audit::{AuditDecision, AuditLog},
+    commands::scan::{self, ScanOptions, ScanSummary},
+    config::{known_quote_assets, known_wrapped_native, ChainConfig},
+    control::{BatchStats, ScanControl},
+    data::arbs::{ArbDatabase, ArbWriter},
+    debug,
+    error::HindsightError,
+    event_history::EventSource,
+    filter::WatchedAddressLists,
     info,
-    sim::processor::{simulate_backrun_arbs, H256Map},
-    util::{get_ws_client, WsClient},
+    interfaces::{QuoteAsset, SimArbResultBatch, SimFailure},
+    sim::{
+        budget::{SimBudget, TxSimLimits},
+        cache::SimCache,
+        core::SearchContext,
+        fork::ForkCache,
+        graph::PathFinder,
+        pools::{PoolAdapter, PoolAdapters},
+        processor::{simulate_backrun_arbs, H256Map},
+        registry::PoolRegistry,
+    },
+    util::{get_ws_client, panic_message, ReceiptCache, WsClient},
     Result,
 };
-use ethers::types::Transaction;
-use futures::future;
+use ethers::{
+    providers::Middleware,
+    types::{Address, Transaction},
+};
+use futures::{
+    stream::{FuturesOrdered, FuturesUnordered},
+    FutureExt, StreamExt,
+};
 use mev_share_sse::EventHistory;
+use std::{sync::Arc, time::Instant};
+use tokio::sync::{broadcast, Semaphore};
+use tracing::Instrument;
+
+/// Arbs found by `Hindsight::process_orderflow`.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessedOrderflow {
+    /// Results that were saved (i.e. that met the min profit, if any).
+    pub arbs: Vec<SimArbResultBatch>,
+    /// Number of txs whose simulation failed (including txs that had no arb to simulate).
+    pub num_failed: usize,
+    /// Every failed simulation, of whole txs or of single pools. Saved into the DB alongside arbs.
+    pub failures: Vec<SimFailure>,
+}
+
+/// How txs are simulated, independent of the chain they're on.
+#[derive(Clone, Debug, Default)]
+pub struct SimOptions {
+    /// Tokens that arbs are searched against, in order of priority. Empty to use the chain's known quote assets.
+    pub quote_assets: Vec<QuoteAsset>,
+    /// The chain's wrapped native token, that gas & coinbase payments are paid in. None to use the chain's known one.
+    pub wrapped_native: Option<Address>,
+    /// Tokens & pools to skip.
+    pub address_lists: Option<Arc<WatchedAddressLists>>,
+    /// Limits the number of txs simulated at once; see `Hindsight::sim_limit`.
+    pub sim_limit: Option<Arc<Semaphore>>,
+    /// AMMs whose pools are simulated. Defaults to UniswapV2 & V3.
+    pub pool_adapters: PoolAdapters,
+    /// Bounds the forks & sims running at once across all txs; see `SimBudget`.
+    pub sim_budget: SimBudget,
+    /// Caps the sims & time spent on each tx. Uncapped by default.
+    pub tx_limits: TxSimLimits,
+    /// Outcomes of earlier backrun sims to reuse, and to keep new outcomes in. Not cached by default.
+    pub sim_cache: Option<SimCache>,
+    /// Records why each sampled tx's pools & routes did or didn't produce a result. Not audited by default.
+    pub audit: Option<AuditLog>,
+    /// Proposes backruns through more pools than the user's pair. Only two-pool routes are searched by default.
+    pub path_finder: Option<PathFinder>,
+}
 
 /// Transaction processor for hindsight. Requires a websocket connection to an archive node.
-#[derive(Clone, Debug)]
+///
+/// Build one with `Hindsight::builder` to inject a provider, store, or event source.
+#[derive(Clone)]
 pub struct Hindsight {
     pub client: WsClient,
+    /// Limits the number of txs simulated at once. May be shared between
+    /// multiple `Hindsight` instances (e.g. one per chain) to bound total load.
+    pub sim_limit: Option<Arc<Semaphore>>,
+    /// Chain id that results are tagged with.
+    pub chain_id: u64,
+    /// Run label that results are tagged with.
+    pub run_label: Option<String>,
+    /// Tokens that arbs are searched against, in order of priority.
+    pub quote_assets: Vec<QuoteAsset>,
+    /// The chain's wrapped native token, if known. Gas is only weighed against, and the coinbase only paid by,
+    /// backruns that profit in it.
+    pub wrapped_native: Option<Address>,
+    /// Runtime-tunable settings; when set, they override the `batch_size` passed to `process_orderflow`.
+    pub control: Option<Arc<ScanControl>>,
+    /// Tokens & pools to skip. The latest lists are used for each tx.
+    pub address_lists: Option<Arc<WatchedAddressLists>>,
+    /// AMMs whose pools are decoded, priced & traded.
+    pub pool_adapters: PoolAdapters,
+    /// Fork factories of recently simulated blocks, shared by every sim (and clone) of this instance.
+    pub forks: ForkCache,
+    /// Bounds the forks & sims running at once, however many txs, pools & intervals are simulated.
+    /// May be shared between multiple `Hindsight` instances, like `sim_limit`.
+    pub sim_budget: SimBudget,
+    /// Caps the sims & time spent on each tx; a tx that hits them keeps the best results found so far.
+    pub tx_limits: TxSimLimits,
+    /// Receipts of recently simulated txs. Each batch's receipts are prefetched before it's simulated.
+    pub receipts: ReceiptCache,
+    /// Pools & tokens of the events being simulated, warmed before each call to `process_orderflow` simulates them.
+    pub pools: PoolRegistry,
+    /// Backrun sims that don't need to run again; see `SimCache`.
+    pub sim_cache: Option<SimCache>,
+    /// Decisions made about sampled txs; see `AuditLog`.
+    pub audit: Option<AuditLog>,
+    /// Cycles through more pools to backrun each trade with; see `PathFinder`.
+    pub path_finder: Option<PathFinder>,
+    /// Where results & failures are saved when `process_orderflow` isn't given a DB.
+    pub store: Option<ArbDatabase>,
+    /// Where `scan` reads events from.
+    pub event_source: Option<Arc<dyn EventSource>>,
+    /// Every result is sent here as soon as it's produced, for live consumers.
+    pub results: Option<broadcast::Sender<SimArbResultBatch>>,
+}
+
+impl std::fmt::Debug for Hindsight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hindsight")
+            .field("client", &self.client)
+            .field("sim_limit", &self.sim_limit)
+            .field("chain_id", &self.chain_id)
+            .field("run_label", &self.run_label)
+            .field("quote_assets", &self.quote_assets)
+            .field("wrapped_native", &self.wrapped_native)
+            .field("control", &self.control)
+            .field("address_lists", &self.address_lists)
+            .field("pool_adapters", &self.pool_adapters)
+            .field("forks", &self.forks)
+            .field("sim_budget", &self.sim_budget)
+            .field("tx_limits", &self.tx_limits)
+            .field("receipts", &self.receipts)
+            .field("pools", &self.pools)
+            .field("sim_cache", &self.sim_cache)
+            .field("audit", &self.audit.is_some())
+            .field("path_finder", &self.path_finder)
+            .field("store", &self.store.is_some())
+            .field("event_source", &self.event_source.is_some())
+            .field("results", &self.results)
+            .finish()
+    }
+}
+
+/// Builds a `Hindsight` from injected components. Anything not given falls back to
+/// what `Hindsight::new` uses: mainnet, its known quote assets, and no store or event source.
+#[derive(Default)]
+pub struct HindsightBuilder {
+    client: Option<WsClient>,
+    rpc_url_ws: Option<String>,
+    store: Option<ArbDatabase>,
+    event_source: Option<Arc<dyn EventSource>>,
+    results: Option<broadcast::Sender<SimArbResultBatch>>,
+    sim_options: SimOptions,
+    chain_id: Option<u64>,
+    run_label: Option<String>,
+    control: Option<Arc<ScanControl>>,
+}
+
+impl HindsightBuilder {
+    /// Simulate with `client`, which must be connected to an archive node.
+    pub fn provider(self, client: WsClient) -> Self {
+        Self {
+            client: Some(client),
+            ..self
+        }
+    }
+
+    /// Connect to the archive node at `rpc_url_ws` when building. Ignored if a provider is given.
+    pub fn rpc_url(self, rpc_url_ws: String) -> Self {
+        Self {
+            rpc_url_ws: Some(rpc_url_ws),
+            ..self
+        }
+    }
+
+    /// Save results & failures into `store`.
+    pub fn store(self, store: ArbDatabase) -> Self {
+        Self {
+            store: Some(store),
+            ..self
+        }
+    }
+
+    /// Read events to scan from `event_source`.
+    pub fn event_source(self, event_source: Arc<dyn EventSource>) -> Self {
+        Self {
+            event_source: Some(event_source),
+            ..self
+        }
+    }
+
+    /// Send every result to `results` as soon as it's produced.
+    pub fn results_feed(self, results: broadcast::Sender<SimArbResultBatch>) -> Self {
+        Self {
+            results: Some(results),
+            ..self
+        }
+    }
+
+    pub fn sim_options(self, sim_options: SimOptions) -> Self {
+        Self {
+            sim_options,
+            ..self
+        }
+    }
+
+    /// Tag all results with `chain_id` and `run_label`.
+    pub fn run_tags(self, chain_id: u64, run_label: Option<String>) -> Self {
+        Self {
+            chain_id: Some(chain_id),
+            run_label,
+            ..self
+        }
+    }
+
+    /// Take batch size, concurrency, profit threshold, and pausing from `control`.
+    pub fn control(self, control: Arc<ScanControl>) -> Self {
+        Self {
+            control: Some(control),
+            ..self
+        }
+    }
+
+    /// Connects to the node (unless a provider was given) and builds the processor.
+    pub async fn build(self) -> Result<Hindsight> {
+        let client = match self.client {
+            Some(client) => client,
+            None => get_ws_client(self.rpc_url_ws).await?,
+        };
+        // refuse to mix results of different chains, e.g. an L2's node configured as mainnet's
+        if let Some(chain_id) = self.chain_id {
+            let rpc_chain_id = client.get_chainid().await?.as_u64();
+            let stored_chain_ids = match &self.store {
+                Some(store) => store.get_chain_ids().await?,
+                None => vec![],
+            };
+            check_chain_ids(chain_id, rpc_chain_id, &stored_chain_ids)?;
+        }
+        let chain_id = self.chain_id.unwrap_or(1);
+        let quote_assets = if self.sim_options.quote_assets.is_empty() {
+            known_quote_assets(chain_id)
+        } else {
+            self.sim_options.quote_assets
+        };
+        let wrapped_native = self
+            .sim_options
+            .wrapped_native
+            .or(known_wrapped_native(chain_id));
+        // the control's limit takes precedence, like `Hindsight::with_control`
+        let sim_limit = match &self.control {
+            Some(control) => Some(control.sim_limit()),
+            None => self.sim_options.sim_limit,
+        };
+        Ok(Hindsight {
+            client,
+            sim_limit,
+            chain_id,
+            run_label: self.run_label,
+            quote_assets,
+            wrapped_native,
+            control: self.control,
+            address_lists: self.sim_options.address_lists,
+            pool_adapters: self.sim_options.pool_adapters,
+            forks: ForkCache::default(),
+            sim_budget: self.sim_options.sim_budget,
+            tx_limits: self.sim_options.tx_limits,
+            receipts: ReceiptCache::default(),
+            pools: PoolRegistry::default(),
+            sim_cache: self.sim_options.sim_cache,
+            audit: self.sim_options.audit,
+            path_finder: self.sim_options.path_finder,
+            store: self.store,
+            event_source: self.event_source,
+            results: self.results,
+        })
+    }
+}
+
+/// Fails unless the node (on `rpc_chain_id`) and every arb in the store (on `stored_chain_ids`) are on `chain_id`.
+pub fn check_chain_ids(chain_id: u64, rpc_chain_id: u64, stored_chain_ids: &[u64]) -> Result<()> {
+    if rpc_chain_id != chain_id {
+        return Err(HindsightError::ChainMismatch(
+            chain_id,
+            format!("the node is on chain {}", rpc_chain_id),
+        )
+        .into());
+    }
+    let other_chain_ids = stored_chain_ids
+        .iter()
+        .filter(|stored| **stored != chain_id)
+        .collect::<Vec<_>>();
+    if !other_chain_ids.is_empty() {
+        return Err(HindsightError::ChainMismatch(
+            chain_id,
+            format!(
+                "the DB namespace holds arbs of chain(s) {:?}; each chain needs its own namespace",
+                other_chain_ids
+            ),
+        )
+        .into());
+    }
+    Ok(())
 }
 
 impl Hindsight {
     pub async fn new(rpc_url_ws: String) -> Result<Self> {
-        let client = get_ws_client(Some(rpc_url_ws)).await?;
-        Ok(Self { client })
+        Self::builder().rpc_url(rpc_url_ws).build().await
+    }
+
+    pub fn builder() -> HindsightBuilder {
+        HindsightBuilder::default()
+    }
+
+    /// Skip tokens & pools excluded by `address_lists`.
+    pub fn with_address_lists(self, address_lists: Arc<WatchedAddressLists>) -> Self {
+        Self {
+            address_lists: Some(address_lists),
+            ..self
+        }
+    }
+
+    /// Simulate pools of the AMM supported by `adapter`, replacing the adapter of its variant (if any).
+    pub fn with_pool_adapter(mut self, adapter: Arc<dyn PoolAdapter>) -> Self {
+        self.pool_adapters.register(adapter);
+        self
+    }
+
+    /// Take batch size, concurrency, profit threshold, and pausing from `control`.
+    pub fn with_control(self, control: Arc<ScanControl>) -> Self {
+        Self {
+            sim_limit: Some(control.sim_limit()),
+            control: Some(control),
+            ..self
+        }
+    }
+
+    /// Search for arbs against `quote_assets` (in order of priority) instead of mainnet's defaults.
+    pub fn with_quote_assets(self, quote_assets: Vec<QuoteAsset>) -> Self {
+        Self {
+            quote_assets,
+            ..self
+        }
+    }
+
+    /// Tag all results produced by this processor with `chain_id` and `run_label`.
+    pub fn with_run_tags(self, chain_id: u64, run_label: Option<String>) -> Self {
+        Self {
+            chain_id,
+            run_label,
+            ..self
+        }
+    }
+
+    /// Send every result to `results` as soon as it's produced.
+    pub fn with_results_feed(self, results: broadcast::Sender<SimArbResultBatch>) -> Self {
+        Self {
+            results: Some(results),
+            ..self
+        }
+    }
+
+    /// Share `sim_limit` with other processors; each tx simulation holds one permit.
+    pub fn with_sim_limit(self, sim_limit: Arc<Semaphore>) -> Self {
+        Self {
+            sim_limit: Some(sim_limit),
+            ..self
+        }
     }
 
     /// For each tx in `txs`, simulates an optimal backrun-arbitrage in a parallel thread,
     /// caching results in batches of size `batch_size`.
     ///
-    /// Saves results into `db` after each batch is processed. Returns when all txs are processed.
+    /// Saves each tx's result & failures into `db` (or the processor's store, if `db` is None) as soon as its sim finishes.
+    /// Returns them when all txs are processed.
+    #[tracing::instrument(skip_all, fields(chain_id = self.chain_id, num_txs = txs.len()))]
     pub async fn process_orderflow(
         self,
         txs: &Vec<Transaction>,
         batch_size: usize,
         db: Option<ArbDatabase>,
         event_map: H256Map<EventHistory>,
-    ) -> Result<()> {
+    ) -> Result<ProcessedOrderflow> {
         info!("loaded {} transactions total...", txs.len());
+        let db = db.or(self.store.to_owned());
+        // shared by every tx's task, which only look up their own event
+        let event_map = Arc::new(event_map);
+        let writer = db.map(ArbWriter::spawn);
+        // look up every pool the events swap on in one pass, rather than tx by tx
+        self.pools
+            .warm(&self.client, &self.pool_adapters, event_map.values())
+            .await;
+        let mut processed = ProcessedOrderflow::default();
         let mut processed_txs = 0;
         while processed_txs < txs.len() {
+            let batch_size = match &self.control {
+                Some(control) => {
+                    control.wait_if_paused().await;
+                    control.batch_size()
+                }
+                None => batch_size,
+            };
             let mut handlers = vec![];
             let txs_batch = txs
                 .iter()
                 .skip(processed_txs)
                 .take(batch_size)
-                .map(|tx| tx.to_owned())
-                .collect::<Vec<Transaction>>();
+                .map(|tx| Arc::new(tx.to_owned()))
+                .collect::<Vec<_>>();
             processed_txs += txs_batch.len();
             info!("processing {} txs", txs_batch.len());
-            for tx in txs_batch {
+            let batch_start = Instant::now();
+            let tx_hashes = txs_batch.iter().map(|tx| tx.hash).collect::<Vec<_>>();
+            let rpc_stats = self.receipts.prefetch(&self.client, &tx_hashes).await;
+            for tx in txs_batch.iter().cloned() {
                 let event_map = event_map.clone();
                 let client = self.client.clone();
-                handlers.push(tokio::task::spawn(async move {
-                    simulate_backrun_arbs(&client, tx, &event_map).await.ok()
-                }));
+                let sim_limit = self.sim_limit.clone();
+                let quote_assets = self.quote_assets.clone();
+                let wrapped_native = self.wrapped_native;
+                let pool_adapters = self.pool_adapters.clone();
+                let forks = self.forks.clone();
+                let sim_budget = self.sim_budget.clone();
+                let tx_limits = self.tx_limits;
+                let receipts = self.receipts.clone();
+                let pools = self.pools.clone();
+                let sim_cache = self.sim_cache.clone();
+                let audit = self.audit.clone();
+                let path_finder = self.path_finder.clone();
+                let lists = self
+                    .address_lists
+                    .as_ref()
+                    .map(|lists| lists.get())
+                    .unwrap_or_default();
+                handlers.push(tokio::task::spawn(
+                    async move {
+                        // hold a permit (if limited) for the duration of the sim
+                        let _permit = match &sim_limit {
+                            Some(sim_limit) => Some(sim_limit.acquire().await?),
+                            None => None,
+                        };
+                        let search = SearchContext {
+                            client: &client,
+                            quote_assets: &quote_assets,
+                            wrapped_native,
+                            lists: &lists,
+                            adapters: &pool_adapters,
+                            forks: &forks,
+                            budget: &sim_budget,
+                            receipts: &receipts,
+                            registry: &pools,
+                            path_finder: path_finder.as_ref(),
+                            sim_cache: sim_cache.as_ref(),
+                            audit: audit.as_ref(),
+                        };
+                        simulate_backrun_arbs(&search, tx, &event_map, tx_limits).await
+                    }
+                    .in_current_span(),
+                ));
             }
-            let results = future::join_all(handlers).await;
-            let results = results
-                // TODO: can this be cleaned up? so ugly
-                .into_iter()
-                .filter(|res| res.is_ok())
-                .map(|res| res.unwrap())
-                .filter(|res| res.is_some())
-                .map(|res| res.unwrap())
-                .collect::<Vec<_>>();
-            info!("batch results: {:#?}", results);
-            if let Some(db) = db.to_owned() {
-                // can't do && with a `let` in the conditional
-                if !results.is_empty() {
-                    db.to_owned().write_arbs(&results).await?;
+            let pending = txs_batch
+                .iter()
+                .map(|tx| tx.hash)
+                .zip(handlers)
+                .map(|(tx_hash, handle)| handle.map(move |res| (tx_hash, res)));
+            // handle each tx as soon as its sim finishes, so a slow tx doesn't hold up the rest,
+            // unless results must come out in the same order every run
+            let mut pending = match self.tx_limits.deterministic {
+                true => pending.collect::<FuturesOrdered<_>>().boxed(),
+                false => pending.collect::<FuturesUnordered<_>>().boxed(),
+            };
+            let (mut num_results, mut num_failures, mut num_failed) = (0, 0, 0);
+            while let Some((tx_hash, res)) = pending.next().await {
+                let mut tx_failed = |error: String| {
+                    num_failed += 1;
+                    // every tx comes from an event, so this only misses if the caller passed a partial map
+                    event_map
+                        .get(&tx_hash)
+                        .map(|event| {
+                            if let Some(audit) = &self.audit {
+                                let decision = AuditDecision::of_tx_error(&error);
+                                audit.record(event, None, decision, Some(&error));
+                            }
+                            SimFailure::new(event, None, &error)
+                        })
+                        .into_iter()
+                        .collect()
+                };
+                let (arb, failures) = match res {
+                    Ok(Ok((arb, pool_failures))) => (Some(arb), pool_failures),
+                    Ok(Err(err)) => (None, tx_failed(err.to_string())),
+                    Err(err) => (None, tx_failed(panic_message(err))),
+                };
+                let failures = failures
+                    .into_iter()
+                    .map(|failure| SimFailure {
+                        chain_id: self.chain_id,
+                        run_label: self.run_label.clone(),
+                        ..failure
+                    })
+                    .collect::<Vec<_>>();
+                let arb = arb
+                    .map(|res| SimArbResultBatch {
+                        chain_id: self.chain_id,
+                        run_label: self.run_label.clone(),
+                        ..res
+                    })
+                    .filter(|res| match &self.control {
+                        Some(control) => {
+                            control.meets_min_profit(res.max_profit, res.profit_decimals)
+                        }
+                        None => true,
+                    });
+                debug!("tx result: {:?}", arb);
+                if let (Some(feed), Some(arb)) = (&self.results, &arb) {
+                    // fails only if nobody is listening
+                    let _ = feed.send(arb.to_owned());
+                }
+                let written = match &writer {
+                    Some(writer) => writer.write(arb.to_owned(), failures.to_owned()).await,
+                    None => Ok(()),
+                };
+                if let Err(err) = written {
+                    // the writer stopped because a write failed, which is the more useful error
+                    let finished = writer.expect("only a writer fails").finish().await;
+                    return Err(finished.err().unwrap_or(err));
                 }
+                num_results += arb.is_some() as usize;
+                num_failures += failures.len();
+                processed.arbs.extend(arb);
+                processed.failures.extend(failures);
             }
+            processed.num_failed += num_failed;
+            if let Some(control) = &self.control {
+                control.observe_batch(&BatchStats {
+                    batch_size,
+                    num_txs: txs_batch.len(),
+                    num_failed,
+                    rpc: rpc_stats,
+                    elapsed: batch_start.elapsed(),
+                });
+            }
+            info!(num_results, num_failures, "batch processed");
         }
-        Ok(())
+        if let Some(writer) = writer {
+            writer.finish().await?;
+        }
+        Ok(processed)
+    }
+
+    /// Scans `chain`'s events from the processor's event source into its store, like the `scan` command.
+    ///
+    /// Progress is added to `summary` as the scan goes. Fails if no event source or store was given.
+    pub async fn scan(
+        &self,
+        params: ScanOptions,
+        chain: &ChainConfig,
+        summary: &mut ScanSummary,
+    ) -> Result<()> {
+        let event_source = self
+            .event_source
+            .to_owned()
+            .ok_or(anyhow::format_err!("scanning requires an event source"))?;
+        let store = self
+            .store
+            .to_owned()
+            .ok_or(anyhow::format_err!("scanning requires a store"))?;
+        scan::run(params, chain, event_source.as_ref(), self, &store, summary).await
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use ethers::{providers::Middleware, types::H256};
+    use ethers::types::H256;
     use serde_json::json;
 
     use crate::{
         config::Config,
         data::{
-            arbs::ArbFilterParams,
+            arbs::{ArbDb, ArbFilterParams},
             db::{Db, DbEngine},
-            MongoConfig,
+            MemoryStore, MongoConfig,
         },
+        mock::MockChain,
     };
+    use ethers::types::{Block, TransactionReceipt};
 
     use super::*;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn it_processes_orderflow() -> Result<()> {
-        let config = Config::default();
-        let hindsight = Hindsight::new(config.rpc_url_ws).await?;
+        let config = Config::load()?;
+        let hindsight = Hindsight::new(config.rpc_url_ws.to_owned()).await?;
 
         // data from an actual juicy event
         let juicy_event: EventHistory = serde_json::from_value(json!({
@@ -139,7 +644,7 @@ mod tests {
             .iter()
             .map(|event| (event.hint.hash, event.to_owned()))
             .collect::<H256Map<EventHistory>>();
-        let test_db = Db::new(DbEngine::Mongo(MongoConfig::default())).await;
+        let test_db = Db::try_new(DbEngine::Mongo(MongoConfig::from(&config))).await?;
 
         // run the sim, it will save a result to the "test" DB
         hindsight
@@ -163,4 +668,98 @@ mod tests {
             .contains(&juicy_tx_hash));
         Ok(())
     }
+
+    #[test]
+    fn it_refuses_mismatched_chains() {
+        assert!(check_chain_ids(8453, 8453, &[]).is_ok());
+        assert!(check_chain_ids(1, 1, &[1]).is_ok());
+        let err = check_chain_ids(1, 8453, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("the node is on chain 8453"),
+            "{}",
+            err
+        );
+        let err = check_chain_ids(8453, 8453, &[8453, 1]).unwrap_err();
+        assert!(err.to_string().contains("chain(s) [1]"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn it_takes_the_configured_wrapped_native() -> Result<()> {
+        let hindsight = Hindsight::builder()
+            .provider(MockChain::new(1).serve().await?)
+            .run_tags(1, None)
+            .build()
+            .await?;
+        assert_eq!(hindsight.wrapped_native, known_wrapped_native(1));
+
+        // a chain that isn't known only has the wrapped native token it's configured with
+        let weth = Address::from_low_u64_be(0x77);
+        let hindsight = Hindsight::builder()
+            .provider(MockChain::new(424242).serve().await?)
+            .run_tags(424242, None)
+            .sim_options(SimOptions {
+                wrapped_native: Some(weth),
+                ..Default::default()
+            })
+            .build()
+            .await?;
+        assert_eq!(hindsight.wrapped_native, Some(weth));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_processes_orderflow_without_a_node_or_db() -> Result<()> {
+        // an event that hints no swaps, so its tx has nothing to backrun
+        let event = SimArbResultBatch::test_example().event;
+        let tx = Transaction {
+            hash: event.hint.hash,
+            block_number: Some(event.block.into()),
+            ..Default::default()
+        };
+        let client = MockChain::new(1)
+            .with_block(Block {
+                number: Some((event.block - 1).into()),
+                hash: Some(H256::from_low_u64_be(event.block - 1)),
+                ..Default::default()
+            })
+            .with_receipt(TransactionReceipt {
+                transaction_hash: tx.hash,
+                block_number: tx.block_number,
+                ..Default::default()
+            })
+            .serve()
+            .await?;
+        let store = MemoryStore::default();
+        let hindsight = Hindsight::builder()
+            .provider(client.clone())
+            .store(Arc::new(store.clone()))
+            .run_tags(1, Some("mock".to_owned()))
+            .build()
+            .await?;
+
+        let event_map = [(event.hint.hash, event)].into_iter().collect();
+        let processed = hindsight
+            .process_orderflow(&vec![tx], 1, None, event_map)
+            .await?;
+        assert_eq!(processed.num_failed, 0, "{:?}", processed.failures);
+        let arbs = store
+            .read_arbs(&ArbFilterParams::none(), None, None)
+            .await?;
+        assert_eq!(arbs.len(), 1);
+        assert_eq!(processed.arbs.len(), 1);
+        assert!(arbs
+            .iter()
+            .all(|arb| arb.max_profit.is_zero() && arb.run_label.as_deref() == Some("mock")));
+
+        // the store's arbs are on chain 1, so it can't take another chain's
+        let err = Hindsight::builder()
+            .provider(MockChain::new(8453).serve().await?)
+            .store(Arc::new(store))
+            .run_tags(8453, None)
+            .build()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("chain mismatch"), "{}", err);
+        Ok(())
+    }
 }
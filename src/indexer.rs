@@ -0,0 +1,267 @@
+//! Indexes every pool that the configured DEX factories deployed, so pools' tokens, factories & fees can be read
+//! from the DB instead of being fetched for each event that swaps on them.
+
+use crate::{
+    config::DexFactory,
+    data::arbs::ArbDatabase,
+    debug, info,
+    interfaces::{IndexedPool, PoolVariant},
+    log_error,
+    sim::{
+        events::{decode_log, UniswapV2PairCreated, UniswapV3PoolCreated},
+        graph::UNISWAP_V2_FEE,
+    },
+    util::WsClient,
+    Result,
+};
+use ethers::{
+    contract::EthEvent,
+    providers::Middleware,
+    types::{Address, Filter, Log, H256},
+};
+use std::{collections::HashMap, time::Duration};
+
+/// Blocks of a factory's logs requested at once. Factories deploy few pools per block, so wide ranges stay small.
+pub const INDEX_CHUNK_BLOCKS: u64 = 10_000;
+
+/// Topic of the event that a factory of pools of `variant` emits when it deploys one.
+fn creation_topic(variant: PoolVariant) -> Result<H256> {
+    match variant {
+        PoolVariant::UniswapV2 => Ok(UniswapV2PairCreated::signature()),
+        PoolVariant::UniswapV3 => Ok(UniswapV3PoolCreated::signature()),
+        PoolVariant::Custom(_) => Err(anyhow::format_err!(
+            "can't index factories of {:?} pools",
+            variant
+        )),
+    }
+}
+
+/// Decodes the pool that `factory` deployed with `log` (a `PairCreated` or `PoolCreated` log, by its variant).
+pub fn pool_from_log(chain_id: u64, factory: &DexFactory, log: &Log) -> Result<IndexedPool> {
+    let created_block = log
+        .block_number
+        .ok_or(anyhow::format_err!("log of {:?} is pending", log.address))?
+        .as_u64();
+    let (address, token0, token1, fee) = match factory.variant {
+        PoolVariant::UniswapV2 => {
+            let created = decode_log::<UniswapV2PairCreated>(log)?;
+            (created.pair, created.token0, created.token1, UNISWAP_V2_FEE)
+        }
+        PoolVariant::UniswapV3 => {
+            let created = decode_log::<UniswapV3PoolCreated>(log)?;
+            (created.pool, created.token0, created.token1, created.fee)
+        }
+        PoolVariant::Custom(_) => {
+            return Err(anyhow::format_err!(
+                "can't index factories of {:?} pools",
+                factory.variant
+            ))
+        }
+    };
+    Ok(IndexedPool {
+        chain_id,
+        address,
+        variant: factory.variant,
+        factory: factory.factory,
+        token0,
+        token1,
+        fee,
+        created_block,
+    })
+}
+
+/// Block that indexing `factory` resumes from: the block of the newest of its pools in `saved`
+/// (which may have deployed more pools after it), or its deployment block if none are saved.
+pub fn resume_block(factory: &DexFactory, saved: &[IndexedPool]) -> u64 {
+    saved
+        .iter()
+        .filter(|pool| pool.factory == factory.factory)
+        .map(|pool| pool.created_block)
+        .max()
+        .unwrap_or(factory.start_block)
+        .max(factory.start_block)
+}
+
+/// Walks the deployment events of a chain's DEX factories, saving each pool they deployed to a DB.
+#[derive(Clone)]
+pub struct PoolIndexer {
+    pub client: WsClient,
+    pub db: ArbDatabase,
+    pub chain_id: u64,
+    pub factories: Vec<DexFactory>,
+    /// Blocks of logs requested at once; see `INDEX_CHUNK_BLOCKS`.
+    pub chunk_blocks: u64,
+}
+
+impl PoolIndexer {
+    /// Indexes the pools that `factory` deployed from block `from` to `to`, saving each chunk's pools as it's read,
+    /// so an interrupted run resumes where it left off. Logs that don't decode are skipped.
+    ///
+    /// Returns the number of pools saved.
+    pub async fn index_factory(&self, factory: &DexFactory, from: u64, to: u64) -> Result<usize> {
+        let topic = creation_topic(factory.variant)?;
+        let mut num_indexed = 0;
+        let mut chunk_start = from;
+        while chunk_start <= to {
+            let chunk_end = (chunk_start + self.chunk_blocks.max(1) - 1).min(to);
+            let filter = Filter::new()
+                .address(factory.factory)
+                .topic0(topic)
+                .from_block(chunk_start)
+                .to_block(chunk_end);
+            let pools = self
+                .client
+                .get_logs(&filter)
+                .await?
+                .iter()
+                .filter_map(|log| {
+                    pool_from_log(self.chain_id, factory, log)
+                        .map_err(|err| debug!("skipping log of {:?}: {}", factory.factory, err))
+                        .ok()
+                })
+                .collect::<Vec<_>>();
+            if !pools.is_empty() {
+                self.db.write_pools(&pools).await?;
+            }
+            num_indexed += pools.len();
+            debug!(
+                factory = ?factory.factory,
+                "indexed {} pools of blocks {}..={}",
+                pools.len(),
+                chunk_start,
+                chunk_end
+            );
+            chunk_start = chunk_end + 1;
+        }
+        Ok(num_indexed)
+    }
+
+    /// Block that indexing each factory (by address) resumes from, given the pools already saved.
+    pub async fn resume_blocks(&self) -> Result<HashMap<Address, u64>> {
+        let saved = self.db.read_pools(self.chain_id).await?;
+        Ok(self
+            .factories
+            .iter()
+            .map(|factory| (factory.factory, resume_block(factory, &saved)))
+            .collect())
+    }
+
+    /// Indexes every factory from its block in `next_blocks` (by factory address) to the head,
+    /// then moves each past the head. Returns the number of pools saved, by factory.
+    pub async fn index_to_head(
+        &self,
+        next_blocks: &mut HashMap<Address, u64>,
+    ) -> Result<HashMap<Address, usize>> {
+        let head = self.client.get_block_number().await?.as_u64();
+        let mut num_indexed = HashMap::new();
+        for factory in &self.factories {
+            let from = next_blocks
+                .get(&factory.factory)
+                .copied()
+                .unwrap_or(factory.start_block);
+            if from > head {
+                continue;
+            }
+            info!(
+                "indexing pools of {:?} from block {} to {}",
+                factory.factory, from, head
+            );
+            let num_pools = self.index_factory(factory, from, head).await?;
+            num_indexed.insert(factory.factory, num_pools);
+            next_blocks.insert(factory.factory, head + 1);
+        }
+        Ok(num_indexed)
+    }
+
+    /// Indexes new blocks every `interval`, forever, starting from each factory's block in `next_blocks`
+    /// (see `index_to_head`). Failed rounds are logged, and their blocks are indexed with the next round's.
+    pub async fn follow(&self, mut next_blocks: HashMap<Address, u64>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.index_to_head(&mut next_blocks).await {
+                Ok(num_indexed) => {
+                    let num_pools = num_indexed.values().sum::<usize>();
+                    if num_pools > 0 {
+                        info!("indexed {} new pools", num_pools);
+                    }
+                }
+                Err(err) => log_error!("failed to index new pools: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn topic(address: Address) -> H256 {
+        H256::from(address)
+    }
+
+    #[test]
+    fn it_decodes_deployed_pools() {
+        let v2 = DexFactory {
+            factory: address(0xf2),
+            variant: PoolVariant::UniswapV2,
+            start_block: 10,
+        };
+        let pair_created = Log {
+            address: v2.factory,
+            topics: vec![
+                UniswapV2PairCreated::signature(),
+                topic(address(1)),
+                topic(address(2)),
+            ],
+            data: encode(&[Token::Address(address(0xa)), Token::Uint(7.into())]).into(),
+            block_number: Some(12.into()),
+            ..Default::default()
+        };
+        let pool = pool_from_log(1, &v2, &pair_created).unwrap();
+        assert_eq!(
+            pool,
+            IndexedPool {
+                chain_id: 1,
+                address: address(0xa),
+                variant: PoolVariant::UniswapV2,
+                factory: v2.factory,
+                token0: address(1),
+                token1: address(2),
+                fee: UNISWAP_V2_FEE,
+                created_block: 12,
+            }
+        );
+
+        let v3 = DexFactory {
+            factory: address(0xf3),
+            variant: PoolVariant::UniswapV3,
+            start_block: 10,
+        };
+        let pool_created = Log {
+            address: v3.factory,
+            topics: vec![
+                UniswapV3PoolCreated::signature(),
+                topic(address(1)),
+                topic(address(3)),
+                H256::from_low_u64_be(500),
+            ],
+            data: encode(&[Token::Int(10.into()), Token::Address(address(0xb))]).into(),
+            block_number: Some(13.into()),
+            ..Default::default()
+        };
+        let pool = pool_from_log(1, &v3, &pool_created).unwrap();
+        assert_eq!((pool.address, pool.fee), (address(0xb), 500));
+        // a V2 factory's log isn't a V3 pool
+        assert!(pool_from_log(1, &v3, &pair_created).is_err());
+
+        // the newest saved pool's block is indexed again, in case the factory deployed more pools in it
+        let saved = [pool.clone()];
+        assert_eq!(resume_block(&v3, &saved), 13);
+        assert_eq!(resume_block(&v2, &saved), 10);
+    }
+}
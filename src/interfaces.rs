@@ -1,4 +1,5 @@
-use ethers::types::{Address, I256, U256};
+use crate::config::known_wrapped_native;
+use ethers::types::{Address, Bytes, H256, I256, U256};
 use mev_share_sse::EventHistory;
 use serde::{self, Deserialize, Serialize};
 
@@ -19,6 +20,46 @@ pub struct BackrunResult {
     pub end_pool: Address,
     pub start_variant: PoolVariant,
     pub end_variant: PoolVariant,
+    /// Factory of the start pool, which identifies its DEX. Not recorded before DEXes were tagged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_factory: Option<Address>,
+    /// Factory of the end pool, which identifies its DEX. Not recorded before DEXes were tagged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_factory: Option<Address>,
+    /// Approximate gas used by the backrun, if it was profitable. Not recorded before gas was measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<u64>,
+    /// Native token (wei) that the backrun's bundle paid the block's coinbase, out of its profit, when sims were run
+    /// with a coinbase share (`TxSimLimits::coinbase_share`). None if it paid nothing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase_payment: Option<U256>,
+    /// Profit of the amounts in that the search simulated, by ascending `amount_in`. Only amounts that earned more
+    /// than every smaller one are kept, so the curve ends at the best backrun. Empty if nothing was profitable,
+    /// or before curves were recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profit_curve: Vec<CurvePoint>,
+    /// Every swap of a backrun through more than two pools (see `PathFinder`), from `start_pool` to `end_pool`.
+    /// Empty for backruns between two pools of the user's pair.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<RouteHop>,
+}
+
+/// One swap of a multi-pool backrun.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteHop {
+    pub pool: Address,
+    pub variant: PoolVariant,
+    pub token_in: Address,
+    pub token_out: Address,
+}
+
+/// Profit of a backrun of one `amount_in`, as simulated while searching for the best one.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CurvePoint {
+    pub amount_in: U256,
+    pub profit: U256,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -27,6 +68,89 @@ pub struct SimArbResultBatch {
     pub event: EventHistory,
     pub results: Vec<SimArbResult>,
     pub max_profit: U256,
+    /// Chain that the event was simulated on. Records saved before chains were tagged are mainnet.
+    #[serde(default = "mainnet_chain_id")]
+    pub chain_id: u64,
+    /// Label of the scan run that produced this result, if one was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_label: Option<String>,
+    /// Token that `max_profit` is denominated in. Records saved before quote assets were configurable are WETH.
+    #[serde(default = "mainnet_weth")]
+    pub profit_token: Address,
+    #[serde(default = "default_profit_decimals")]
+    pub profit_decimals: u32,
+    /// Base fee (wei) that the backrun was simulated with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee: Option<U256>,
+    /// What actually happened on-chain after the user's tx. None until the chain has been checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realized: Option<Realized>,
+    /// `sim::ENGINE_VERSION` of the engine that simulated this result. Not recorded before engines were versioned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_version: Option<String>,
+    /// Whether the search was cut short by the tx's sim budget (`sim::budget::TxSimLimits`),
+    /// in which case the results are the best found before it ran out.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub budget_truncated: bool,
+    /// Whether re-simulating a profitable result on a fresh fork (`TxSimLimits::verify`) didn't reproduce it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_mismatch: bool,
+    /// Hash of the block that the sim forked from (the parent of the user's block). Not recorded before reorgs were checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<H256>,
+    /// Whether `block_hash` was reorged out of the chain after the result was saved (see `reorg::ReorgWatch`),
+    /// in which case the result was simulated on state that never became canonical. Analyses skip orphaned results.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub orphaned: bool,
+}
+
+/// Result of checking the chain for a backrun of a user's tx.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Realized {
+    /// First backrun found after the user's tx, if any.
+    pub backrun: Option<RealizedBackrun>,
+}
+
+/// An arbitrage tx that landed after a user's tx and traded on the same pools.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RealizedBackrun {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub from: Address,
+    /// Contract that the tx called (usually the searcher's bot).
+    pub to: Option<Address>,
+    /// Net amount of the profit token that the tx's sender & contract received, before gas.
+    pub profit: I256,
+    /// Fee recipient (coinbase) of the block that the backrun landed in. Identifies the builder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_recipient: Option<Address>,
+    /// Extra data of the block that the backrun landed in, which most builders tag their blocks with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_data: Option<Bytes>,
+}
+
+impl SimArbResultBatch {
+    /// Returns the most profitable result that's quoted in `profit_token`, if any.
+    pub fn best_result(&self) -> Option<&SimArbResult> {
+        self.results
+            .iter()
+            .filter(|result| result.user_trade.tokens.quote == self.profit_token)
+            .max_by_key(|result| result.backrun_trade.profit)
+    }
+}
+
+fn mainnet_chain_id() -> u64 {
+    1
+}
+
+fn mainnet_weth() -> Address {
+    known_wrapped_native(1).unwrap_or_default()
+}
+
+fn default_profit_decimals() -> u32 {
+    18
 }
 
 /// Information derived from user's trade tx.
@@ -38,24 +162,165 @@ pub struct UserTradeParams {
     pub token_out: Address,
     pub amount0_sent: I256,
     pub amount1_sent: I256,
-    pub token0_is_weth: bool,
+    #[serde(alias = "token0IsWeth")]
+    pub token0_is_quote: bool,
     pub pool: Address,
+    /// Factory of `pool`, if it could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Address>,
     pub price: U256,
     pub tokens: TokenPair,
     pub arb_pools: Vec<PairPool>,
+    /// Quote asset that the backrun starts & ends in. Only used during simulation.
+    #[serde(skip)]
+    pub quote_asset: QuoteAsset,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenPair {
-    pub weth: Address,
+    /// Token that the arb starts & ends in; profits are denominated in it.
+    /// Results saved before quote assets were configurable always quote in WETH.
+    #[serde(alias = "weth")]
+    pub quote: Address,
     pub token: Address,
 }
 
+/// Token that arbs can start & end in (e.g. WETH, USDC).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuoteAsset {
+    pub address: Address,
+    /// Storage slot of the token's `balanceOf` mapping, used to fund the simulated arb contract.
+    pub balance_slot: u64,
+    pub decimals: u32,
+    /// Balance that the arb contract starts with, in the token's smallest unit.
+    /// This is the upper bound of the `amount_in` search.
+    pub starting_balance: U256,
+}
+
+impl QuoteAsset {
+    /// `balance` is the arb contract's starting balance in whole tokens.
+    pub fn new(address: Address, balance_slot: u64, decimals: u32, balance: u64) -> Self {
+        Self {
+            address,
+            balance_slot,
+            decimals,
+            starting_balance: U256::from(balance) * U256::exp10(decimals as usize),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct PairPool {
     pub variant: PoolVariant,
     pub address: Address,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Address>,
+}
+
+/// Taxes & transaction limits of a token, measured by trading it against a quote asset on a fork (see `sim::tax`).
+///
+/// Taxes are shares of the amount sent that didn't arrive, so they don't include the pool's fee.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenTax {
+    pub token: Address,
+    /// Quote asset that the token was traded against.
+    pub quote: Address,
+    /// Pool that the token was traded on.
+    pub pool: Address,
+    /// Block whose state the token was measured at.
+    pub block: u64,
+    /// Share of the tokens that the pool sent on a buy that didn't arrive.
+    pub buy_tax: f64,
+    /// Share of the tokens sent to the pool on a sell that didn't arrive. `None` if selling reverted.
+    pub sell_tax: Option<f64>,
+    /// Largest buy (in the quote asset) that went through, if a larger one reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_buy: Option<U256>,
+    /// Largest sell (in the token) that went through, if a larger one reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sell: Option<U256>,
+}
+
+/// A pool found by the `indexer` in its factory's deployment events.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedPool {
+    pub chain_id: u64,
+    pub address: Address,
+    pub variant: PoolVariant,
+    pub factory: Address,
+    pub token0: Address,
+    pub token1: Address,
+    /// Swap fee, in hundredths of a basis point (like UniswapV3's `fee()`).
+    pub fee: u32,
+    /// Block the pool was deployed in.
+    pub created_block: u64,
+}
+
+pub const LABEL_KIND_NAMES: [&str; 6] = [
+    "router",
+    "searcher",
+    "builder",
+    "stablecoin",
+    "wrapped-native",
+    "token",
+];
+
+/// What a labeled address is.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum LabelKind {
+    /// A DEX router or aggregator that users swap through.
+    Router,
+    /// A MEV bot's contract or sender.
+    Searcher,
+    /// A block builder's fee recipient.
+    Builder,
+    Stablecoin,
+    /// The wrapped native token of its chain, e.g. WETH on mainnet.
+    WrappedNative,
+    /// Any other token.
+    Token,
+}
+
+impl std::str::FromStr for LabelKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "router" => Ok(LabelKind::Router),
+            "searcher" => Ok(LabelKind::Searcher),
+            "builder" => Ok(LabelKind::Builder),
+            "stablecoin" => Ok(LabelKind::Stablecoin),
+            "wrapped-native" => Ok(LabelKind::WrappedNative),
+            "token" => Ok(LabelKind::Token),
+            _ => Err(format!("invalid label kind: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for LabelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LabelKind::Router => write!(f, "router"),
+            LabelKind::Searcher => write!(f, "searcher"),
+            LabelKind::Builder => write!(f, "builder"),
+            LabelKind::Stablecoin => write!(f, "stablecoin"),
+            LabelKind::WrappedNative => write!(f, "wrapped-native"),
+            LabelKind::Token => write!(f, "token"),
+        }
+    }
+}
+
+/// A known entity's name for an address on one chain (see `labels::Labels`).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressLabel {
+    pub chain_id: u64,
+    pub address: Address,
+    pub kind: LabelKind,
+    pub name: String,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -71,14 +336,242 @@ pub struct StoredArbsRanges {
 pub enum PoolVariant {
     UniswapV2,
     UniswapV3,
+    /// Pools of an AMM supported by a third-party `PoolAdapter`, identified by the adapter's id.
+    Custom(u32),
+}
+
+/// Why a simulation failed, as far as can be told from its error.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureCategory {
+    /// The backrun reverted or halted in the EVM.
+    Reverted,
+    /// The user's tx reverted or halted when replayed on the fork (e.g. its deadline had passed), so it wasn't backrun.
+    UserTxReverted,
+    /// No other pool trades the user's pair, so there's nothing to arb against.
+    NoAltPool,
+    /// A pool of the route is empty or holds only dust, so it was skipped.
+    InsufficientLiquidity,
+    /// The event's hinted logs didn't have the layout of EVM logs.
+    MalformedHint,
+    /// The traded token failed the safety screen, e.g. it couldn't be sold back, or was taxed.
+    UnsafeToken,
+    /// The node returned an error, or didn't have the block/tx/receipt.
+    Rpc,
+    Timeout,
+    /// Logs, call results, or tx fields couldn't be decoded.
+    Decoding,
+    /// The simulation panicked for a reason that doesn't fit another category.
+    Panic,
+    Other,
 }
 
+impl FailureCategory {
+    /// Categorizes a failure by its error message.
+    pub fn of(error: &str) -> Self {
+        let error = error.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| error.contains(needle));
+        if mentions(&["no other pool found"]) {
+            FailureCategory::NoAltPool
+        } else if mentions(&["insufficient liquidity"]) {
+            FailureCategory::InsufficientLiquidity
+        } else if mentions(&["user tx reverted"]) {
+            FailureCategory::UserTxReverted
+        } else if mentions(&["malformed hint"]) {
+            FailureCategory::MalformedHint
+        } else if mentions(&["unsafe token"]) {
+            FailureCategory::UnsafeToken
+        } else if mentions(&["timed out", "timeout", "deadline has elapsed"]) {
+            FailureCategory::Timeout
+        } else if mentions(&[
+            "block not found",
+            "tx not landed",
+            "failed to get block",
+            "(code:",
+            "jsonrpc",
+            "provider",
+            "websocket",
+            "connection",
+        ]) {
+            FailureCategory::Rpc
+        } else if mentions(&["revert", "halted"]) {
+            FailureCategory::Reverted
+        } else if mentions(&["decode", "parse error", "invalid", "failed to cast"]) {
+            FailureCategory::Decoding
+        } else if mentions(&["panicked"]) {
+            FailureCategory::Panic
+        } else {
+            FailureCategory::Other
+        }
+    }
+}
+
+impl std::fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureCategory::Reverted => write!(f, "reverted"),
+            FailureCategory::UserTxReverted => write!(f, "user reverted"),
+            FailureCategory::NoAltPool => write!(f, "no alt pool"),
+            FailureCategory::InsufficientLiquidity => write!(f, "no liquidity"),
+            FailureCategory::MalformedHint => write!(f, "malformed hint"),
+            FailureCategory::UnsafeToken => write!(f, "unsafe token"),
+            FailureCategory::Rpc => write!(f, "rpc"),
+            FailureCategory::Timeout => write!(f, "timeout"),
+            FailureCategory::Decoding => write!(f, "decoding"),
+            FailureCategory::Panic => write!(f, "panic"),
+            FailureCategory::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// A failed simulation, saved during a scan so that failures can be triaged later.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SimFailure {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub timestamp: u64,
+    /// Pool whose backrun failed. None if the whole tx failed before any pool was tried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<Address>,
+    pub category: FailureCategory,
+    pub error: String,
+    pub chain_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_label: Option<String>,
+}
+
+impl SimFailure {
+    /// Records `error` as a failure to simulate `event` (or one of its pools).
+    /// Tagged as mainnet without a run label; the caller tags it like the arbs it produces.
+    pub fn new(event: &EventHistory, pool: Option<Address>, error: &str) -> Self {
+        Self {
+            tx_hash: event.hint.hash,
+            block: event.block,
+            timestamp: event.timestamp,
+            pool,
+            category: FailureCategory::of(error),
+            error: error.to_owned(),
+            chain_id: 1,
+            run_label: None,
+        }
+    }
+}
+
+/// Lending protocol that a liquidated position was in.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum LendingProtocol {
+    AaveV3,
+    CompoundV3,
+}
+
+/// A position that a user's tx made liquidatable, and the liquidation simulated right after the tx.
+///
+/// Values are in USD with `USD_DECIMALS` decimals, at the protocol's oracle prices after the user's tx.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SimLiquidationResult {
+    pub tx_hash: H256,
+    pub block: u64,
+    pub timestamp: u64,
+    pub chain_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_label: Option<String>,
+    pub protocol: LendingProtocol,
+    /// Pool (Aave) or Comet (Compound) contract that the position is in.
+    pub market: Address,
+    pub borrower: Address,
+    /// Health factors before & after the user's tx (liquidatable below 1). None for protocols that don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_before: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_after: Option<f64>,
+    /// Token the liquidator repaid (Aave) or paid for the seized collateral (Compound), and how much of it.
+    pub debt_asset: Address,
+    pub debt_repaid: U256,
+    /// Collateral the liquidator received, and how much of it.
+    pub collateral_asset: Address,
+    pub collateral_received: U256,
+    /// Value of the collateral received minus the value of the debt repaid. Gas isn't deducted.
+    pub profit_usd: I256,
+    /// Gas used by the liquidation calls.
+    pub gas_used: u64,
+}
+
+/// Decimals of the USD values of `SimLiquidationResult`s, which both Aave's & Compound's oracles price in.
+pub const USD_DECIMALS: u32 = 8;
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use ethers::types::H256;
     use mev_share_sse::Hint;
     use rand::Rng;
+    impl SimArbResult {
+        /// A WETH/`token` backrun between two V2 pools.
+        pub fn test_example(token: Address, amount_in: U256, profit: U256) -> Self {
+            let tokens = TokenPair {
+                quote: mainnet_weth(),
+                token,
+            };
+            Self {
+                user_trade: UserTradeParams {
+                    pool_variant: PoolVariant::UniswapV2,
+                    token_in: tokens.quote,
+                    token_out: token,
+                    amount0_sent: I256::zero(),
+                    amount1_sent: I256::zero(),
+                    token0_is_quote: true,
+                    pool: Address::from_low_u64_be(0xa),
+                    factory: None,
+                    price: U256::zero(),
+                    tokens,
+                    arb_pools: vec![],
+                    quote_asset: QuoteAsset::default(),
+                },
+                backrun_trade: BackrunResult {
+                    amount_in,
+                    balance_end: amount_in + profit,
+                    profit,
+                    start_pool: Address::from_low_u64_be(0xa),
+                    end_pool: Address::from_low_u64_be(0xb),
+                    start_variant: PoolVariant::UniswapV2,
+                    end_variant: PoolVariant::UniswapV2,
+                    start_factory: None,
+                    end_factory: None,
+                    gas_used: None,
+                    coinbase_payment: None,
+                    profit_curve: vec![],
+                    path: vec![],
+                },
+            }
+        }
+    }
+
+    impl SimLiquidationResult {
+        /// An Aave V3 liquidation of a WETH debt.
+        pub fn test_example() -> Self {
+            Self {
+                tx_hash: H256::from_low_u64_be(0x1),
+                block: 9001,
+                timestamp: 9001,
+                chain_id: 1,
+                run_label: None,
+                protocol: LendingProtocol::AaveV3,
+                market: Address::from_low_u64_be(0xa),
+                borrower: Address::from_low_u64_be(0xb),
+                health_before: Some(1.01),
+                health_after: Some(0.98),
+                debt_asset: mainnet_weth(),
+                debt_repaid: 0x1000.into(),
+                collateral_asset: Address::from_low_u64_be(0xc),
+                collateral_received: 0x2000.into(),
+                profit_usd: I256::from(-0x10),
+                gas_used: 250_000,
+            }
+        }
+    }
+
     impl SimArbResultBatch {
         pub fn test_example() -> Self {
             // get random u64
@@ -98,7 +591,72 @@ mod test {
                 },
                 results: vec![],
                 max_profit: 0x1337.into(),
+                chain_id: 1,
+                run_label: None,
+                profit_token: mainnet_weth(),
+                profit_decimals: 18,
+                base_fee: None,
+                realized: None,
+                engine_version: None,
+                budget_truncated: false,
+                verify_mismatch: false,
+                block_hash: None,
+                orphaned: false,
             }
         }
     }
+
+    /// A result with every field set, so that every field shows up in its serialized form.
+    fn full_result() -> SimArbResult {
+        let mut result =
+            SimArbResult::test_example(Address::from_low_u64_be(0xc), 0x1000.into(), 0x10.into());
+        result.user_trade.amount0_sent = I256::from(0x1000);
+        result.user_trade.amount1_sent = I256::from(-0x2000);
+        result.user_trade.factory = Some(Address::from_low_u64_be(0xf));
+        result.user_trade.price = 0x2.into();
+        result.user_trade.arb_pools = vec![PairPool {
+            variant: PoolVariant::UniswapV3,
+            address: Address::from_low_u64_be(0xb),
+            factory: Some(Address::from_low_u64_be(0xe)),
+        }];
+        result.backrun_trade.end_variant = PoolVariant::UniswapV3;
+        result.backrun_trade.start_factory = Some(Address::from_low_u64_be(0xf));
+        result.backrun_trade.end_factory = Some(Address::from_low_u64_be(0xe));
+        result.backrun_trade.gas_used = Some(150_000);
+        result
+    }
+
+    // Results are stored (in MongoDB as BSON) and exported (as JSON) in these schemas. If a snapshot changes,
+    // make sure that stored results still deserialize, then review & accept it with `cargo insta review`.
+    #[test]
+    fn it_serializes_results_to_json() {
+        insta::assert_snapshot!(serde_json::to_string_pretty(&full_result()).unwrap());
+    }
+
+    #[test]
+    fn it_serializes_results_to_bson() {
+        // canonical extended JSON tells BSON's types apart, e.g. Int32 from Int64
+        let document = mongodb::bson::to_bson(&full_result()).unwrap();
+        let extjson = document.into_canonical_extjson();
+        insta::assert_snapshot!(serde_json::to_string_pretty(&extjson).unwrap());
+    }
+
+    #[test]
+    fn it_serializes_liquidations_to_json() {
+        let mut liquidation = SimLiquidationResult::test_example();
+        liquidation.run_label = Some("label".to_owned());
+        insta::assert_snapshot!(serde_json::to_string_pretty(&liquidation).unwrap());
+    }
+
+    #[test]
+    fn it_serializes_result_batches_to_json() {
+        let mut batch = SimArbResultBatch::test_example();
+        batch.event.hint.hash = H256::from_low_u64_be(0x1);
+        batch.results = vec![full_result()];
+        batch.run_label = Some("label".to_owned());
+        batch.base_fee = Some(0x3b9aca00.into());
+        batch.engine_version = Some("1".to_owned());
+        batch.block_hash = Some(H256::from_low_u64_be(0x2));
+        insta::assert_snapshot!(serde_json::to_string_pretty(&batch).unwrap());
+    }
 }
@@ -0,0 +1,442 @@
+use crate::{
+    config::known_wrapped_native,
+    data::arbs::ArbDatabase,
+    interfaces::{AddressLabel, LabelKind, RealizedBackrun},
+    log_error, Result,
+};
+use ethers::types::{Address, Bytes};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+/// Tags that well-known builders put in their blocks' extra data, and the builder's name.
+const KNOWN_BUILDER_TAGS: [(&'static str, &'static str); 7] = [
+    ("beaverbuild", "beaverbuild"),
+    ("titan", "titan"),
+    ("rsync", "rsync"),
+    ("flashbots", "flashbots"),
+    ("builder0x69", "builder0x69"),
+    ("bloxroute", "bloxroute"),
+    ("buildai", "buildai"),
+];
+
+/// Well-known mainnet addresses of each kind: (address, name).
+const MAINNET_ROUTERS: [(&str, &str); 11] = [
+    (
+        "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+        "uniswap v2 router",
+    ),
+    (
+        "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+        "uniswap v3 router",
+    ),
+    (
+        "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+        "uniswap v3 router 2",
+    ),
+    (
+        "0xEf1c6E67703c7BD7107eed8303Fbe6EC2554BF6B",
+        "uniswap universal router (old)",
+    ),
+    (
+        "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD",
+        "uniswap universal router",
+    ),
+    (
+        "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F",
+        "sushiswap router",
+    ),
+    (
+        "0x1111111254EEB25477B68fb85Ed929f73A960582",
+        "1inch v5 router",
+    ),
+    (
+        "0x111111125421cA6dc452d289314280a0f8842A65",
+        "1inch v6 router",
+    ),
+    (
+        "0xDef1C0ded9bec7F1a1670819833240f027b25EfF",
+        "0x exchange proxy",
+    ),
+    (
+        "0x881D40237659C251811CEC9c364ef91dC08D300C",
+        "metamask swap router",
+    ),
+    (
+        "0x9008D19f58AAbD9eD0D60971565AA8510560ab41",
+        "cow protocol settlement",
+    ),
+];
+const MAINNET_SEARCHERS: [(&str, &str); 2] = [
+    (
+        "0x6b75d8AF000000e20B7a7DDf000Ba900b4009A80",
+        "jaredfromsubway",
+    ),
+    (
+        "0xae2Fc483527B8EF99EB5D9B44875F005ba1FaE13",
+        "jaredfromsubway",
+    ),
+];
+/// Builders' fee recipients.
+const MAINNET_BUILDERS: [(&str, &str); 4] = [
+    ("0x95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5", "beaverbuild"),
+    ("0x4838B106FCe9647Bdf1E7877BF73cE8B0BAD5f97", "titan"),
+    ("0x1f9090aaE28b8a3dCeaDf281B0F12828e676c326", "rsync"),
+    ("0xDAFEA492D9c6733ae3d56b7Ed1ADB60692c98Bc5", "flashbots"),
+];
+const MAINNET_STABLECOINS: [(&str, &str); 3] = [
+    ("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC"),
+    ("0xdAC17F958D2ee523a2206206994597C13D831ec7", "USDT"),
+    ("0x6B175474E89094C44Da98b954EedeAC495271d0F", "DAI"),
+];
+const MAINNET_TOKENS: [(&str, &str); 1] = [("0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", "WBTC")];
+const BASE_STABLECOINS: [(&str, &str); 1] =
+    [("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC")];
+
+/// Labels that ship with hindsight for `chain_id`: DEX routers, well-known searchers & builders,
+/// stablecoins, and the chain's wrapped native token.
+pub fn known_labels(chain_id: u64) -> Vec<AddressLabel> {
+    let known: Vec<(LabelKind, &[(&str, &str)])> = match chain_id {
+        1 => vec![
+            (LabelKind::Router, &MAINNET_ROUTERS),
+            (LabelKind::Searcher, &MAINNET_SEARCHERS),
+            (LabelKind::Builder, &MAINNET_BUILDERS),
+            (LabelKind::Stablecoin, &MAINNET_STABLECOINS),
+            (LabelKind::Token, &MAINNET_TOKENS),
+        ],
+        8453 => vec![(LabelKind::Stablecoin, &BASE_STABLECOINS)],
+        _ => vec![],
+    };
+    let mut labels = known
+        .into_iter()
+        .flat_map(|(kind, addresses)| {
+            addresses.iter().map(move |(address, name)| AddressLabel {
+                chain_id,
+                address: address.parse().expect("known label address is valid"),
+                kind,
+                name: (*name).to_owned(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if let Some(address) = known_wrapped_native(chain_id) {
+        labels.push(AddressLabel {
+            chain_id,
+            address,
+            kind: LabelKind::WrappedNative,
+            name: if chain_id == 137 { "WMATIC" } else { "WETH" }.to_owned(),
+        });
+    }
+    labels
+}
+
+/// Labels of `chain_id`: the known ones, overridden by those saved in `db` (if given),
+/// overridden by those in the TOML file at `path` (if given).
+///
+/// Failing to read the saved labels is logged rather than returned, so a missing DB doesn't stop a command that only names addresses.
+pub async fn load_labels(
+    chain_id: u64,
+    db: Option<&ArbDatabase>,
+    path: Option<&Path>,
+) -> Result<Labels> {
+    let mut labels = Labels::known(chain_id);
+    if let Some(db) = db {
+        match db.read_labels(chain_id).await {
+            Ok(saved) => labels.extend(saved),
+            Err(err) => log_error!("failed to read saved labels: {}", err),
+        }
+    }
+    if let Some(path) = path {
+        labels.merge(Labels::load(path)?);
+    }
+    Ok(labels)
+}
+
+/// Name & kind of a labeled address.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub name: String,
+}
+
+/// Names of known addresses, used to attribute realized backruns, skip searchers' txs, and name addresses in reports.
+///
+/// Built from the labels that ship with hindsight (see `known_labels`), those saved in the DB,
+/// and a TOML file, each overriding the ones before it:
+/// ```toml
+/// [searchers]
+/// # bot contract or sender => name
+/// "0x..." = "some searcher"
+///
+/// [builders]
+/// # fee recipient => name
+/// "0x..." = "some builder"
+///
+/// [builder_tags]
+/// # text found in a block's extra data => name
+/// "mybuilder" = "some builder"
+///
+/// [addresses]
+/// # any address => kind (router, searcher, builder, stablecoin, wrapped-native or token) & name
+/// "0x..." = { kind = "router", name = "some router" }
+/// ```
+/// Builders not found in the labels are recognized by the tags that well-known builders use.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Labels {
+    searchers: HashMap<Address, String>,
+    builders: HashMap<Address, String>,
+    builder_tags: HashMap<String, String>,
+    addresses: HashMap<Address, Label>,
+}
+
+impl Labels {
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|err| anyhow::format_err!("invalid labels {:?}: {}", path, err))
+    }
+
+    /// The labels that ship with hindsight for `chain_id`.
+    pub fn known(chain_id: u64) -> Self {
+        let mut labels = Self::default();
+        labels.extend(known_labels(chain_id));
+        labels
+    }
+
+    /// Adds `labels`, replacing those of the same addresses.
+    pub fn extend(&mut self, labels: impl IntoIterator<Item = AddressLabel>) {
+        for label in labels {
+            self.insert(label.address, label.kind, label.name);
+        }
+    }
+
+    /// Adds every label of `other`, replacing those of the same addresses (or builder tags).
+    pub fn merge(&mut self, other: Labels) {
+        for (address, name) in other.searchers {
+            self.insert(address, LabelKind::Searcher, name);
+        }
+        for (address, name) in other.builders {
+            self.insert(address, LabelKind::Builder, name);
+        }
+        for (address, label) in other.addresses {
+            self.insert(address, label.kind, label.name);
+        }
+        self.builder_tags.extend(other.builder_tags);
+    }
+
+    fn insert(&mut self, address: Address, kind: LabelKind, name: String) {
+        self.searchers.remove(&address);
+        self.builders.remove(&address);
+        self.addresses.insert(address, Label { kind, name });
+    }
+
+    /// Label of `address`, if it's known.
+    pub fn label(&self, address: &Address) -> Option<Label> {
+        if let Some(name) = self.searchers.get(address) {
+            return Some(Label {
+                kind: LabelKind::Searcher,
+                name: name.to_owned(),
+            });
+        }
+        if let Some(name) = self.builders.get(address) {
+            return Some(Label {
+                kind: LabelKind::Builder,
+                name: name.to_owned(),
+            });
+        }
+        self.addresses.get(address).cloned()
+    }
+
+    /// Name of `address` if it's labeled, else the address itself.
+    pub fn name_of(&self, address: &Address) -> String {
+        self.label(address)
+            .map(|label| label.name)
+            .unwrap_or(format!("{:?}", address))
+    }
+
+    fn name_of_kind(&self, address: &Address, kind: LabelKind) -> Option<String> {
+        self.label(address)
+            .filter(|label| label.kind == kind)
+            .map(|label| label.name)
+    }
+
+    /// Whether `address` is a known searcher's contract or sender.
+    pub fn is_searcher(&self, address: &Address) -> bool {
+        self.name_of_kind(address, LabelKind::Searcher).is_some()
+    }
+
+    /// Every label (except builder tags) as labels of `chain_id`'s addresses, ordered by address.
+    pub fn address_labels(&self, chain_id: u64) -> Vec<AddressLabel> {
+        let addresses = self
+            .searchers
+            .keys()
+            .chain(self.builders.keys())
+            .chain(self.addresses.keys());
+        let mut labels = addresses
+            .filter_map(|address| {
+                self.label(address).map(|label| AddressLabel {
+                    chain_id,
+                    address: *address,
+                    kind: label.kind,
+                    name: label.name,
+                })
+            })
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|label| label.address);
+        labels.dedup_by_key(|label| label.address);
+        labels
+    }
+
+    /// Names the searcher behind `backrun`: the label of its contract or sender, else the contract's address.
+    pub fn searcher(&self, backrun: &RealizedBackrun) -> String {
+        backrun
+            .to
+            .and_then(|to| self.name_of_kind(&to, LabelKind::Searcher))
+            .or(self.name_of_kind(&backrun.from, LabelKind::Searcher))
+            .unwrap_or(format!("{:?}", backrun.to.unwrap_or(backrun.from)))
+    }
+
+    /// Names the builder of the block that `backrun` landed in.
+    ///
+    /// Tries the fee recipient, then extra data tags (from the file, then well-known ones),
+    /// then falls back to the extra data text, or the fee recipient's address.
+    pub fn builder(&self, backrun: &RealizedBackrun) -> String {
+        if let Some(name) = backrun
+            .fee_recipient
+            .and_then(|fee_recipient| self.name_of_kind(&fee_recipient, LabelKind::Builder))
+        {
+            return name;
+        }
+        let extra_data = backrun
+            .extra_data
+            .as_ref()
+            .map(extra_data_text)
+            .unwrap_or_default();
+        let lowercase = extra_data.to_lowercase();
+        let tags = self
+            .builder_tags
+            .iter()
+            .map(|(tag, name)| (tag.as_str(), name.as_str()))
+            .chain(KNOWN_BUILDER_TAGS);
+        for (tag, name) in tags {
+            if lowercase.contains(&tag.to_lowercase()) {
+                return name.to_owned();
+            }
+        }
+        if !extra_data.is_empty() {
+            extra_data
+        } else if let Some(fee_recipient) = backrun.fee_recipient {
+            format!("{:?}", fee_recipient)
+        } else {
+            "unknown".to_owned()
+        }
+    }
+}
+
+/// Printable text in a block's extra data.
+fn extra_data_text(extra_data: &Bytes) -> String {
+    String::from_utf8_lossy(extra_data)
+        .chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{H256, I256};
+
+    #[test]
+    fn it_labels_searchers_and_builders() -> Result<()> {
+        let bot = Address::from_low_u64_be(0xb07);
+        let coinbase = Address::from_low_u64_be(0xc0);
+        let labels = Labels::parse(&format!(
+            r#"
+            [searchers]
+            "{:?}" = "jared"
+
+            [builder_tags]
+            "mybuilder" = "my builder"
+            "#,
+            bot
+        ))?;
+        let backrun = |to: Option<Address>, extra_data: &str| RealizedBackrun {
+            tx_hash: H256::zero(),
+            block: 1,
+            from: Address::from_low_u64_be(0xf00),
+            to,
+            profit: I256::zero(),
+            fee_recipient: Some(coinbase),
+            extra_data: Some(Bytes::from(extra_data.as_bytes().to_vec())),
+        };
+        assert_eq!(labels.searcher(&backrun(Some(bot), "")), "jared");
+        assert_eq!(
+            labels.searcher(&backrun(None, "")),
+            format!("{:?}", Address::from_low_u64_be(0xf00))
+        );
+        assert_eq!(
+            labels.builder(&backrun(None, "beaverbuild.org")),
+            "beaverbuild"
+        );
+        assert_eq!(labels.builder(&backrun(None, "MyBuilder v2")), "my builder");
+        assert_eq!(labels.builder(&backrun(None, "geth")), "geth");
+        assert_eq!(
+            labels.builder(&backrun(None, "")),
+            format!("{:?}", coinbase)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_merges_known_saved_and_file_labels() -> Result<()> {
+        let jared: Address = "0x6b75d8AF000000e20B7a7DDf000Ba900b4009A80".parse()?;
+        let weth = known_wrapped_native(1).unwrap();
+        let mut labels = Labels::known(1);
+        assert!(labels.is_searcher(&jared));
+        assert_eq!(labels.name_of(&weth), "WETH");
+        assert!(!labels.is_searcher(&weth));
+
+        // saved labels override the known ones, and the file overrides both
+        let bot = Address::from_low_u64_be(0xb07);
+        labels.extend([AddressLabel {
+            chain_id: 1,
+            address: bot,
+            kind: LabelKind::Router,
+            name: "not a bot".to_owned(),
+        }]);
+        labels.merge(Labels::parse(&format!(
+            r#"
+            [searchers]
+            "{:?}" = "my bot"
+
+            [addresses]
+            "{:?}" = {{ kind = "token", name = "wrapped ether" }}
+            "#,
+            bot, weth
+        ))?);
+        assert!(labels.is_searcher(&bot));
+        assert_eq!(labels.name_of(&bot), "my bot");
+        assert_eq!(
+            labels.label(&weth),
+            Some(Label {
+                kind: LabelKind::Token,
+                name: "wrapped ether".to_owned()
+            })
+        );
+        assert_eq!(
+            labels.name_of(&Address::from_low_u64_be(1)),
+            format!("{:?}", Address::from_low_u64_be(1))
+        );
+
+        let saved = labels.address_labels(1);
+        assert_eq!(saved.len(), known_labels(1).len() + 1);
+        assert!(saved
+            .windows(2)
+            .all(|pair| pair[0].address < pair[1].address));
+        Ok(())
+    }
+}
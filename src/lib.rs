@@ -1,12 +1,57 @@
+//! Simulates backrun-arbitrage on historical MEV-Share orderflow.
+//!
+//! The `hindsight` binary is a thin CLI over this library. To embed the simulator in another service:
+//! - `Hindsight` simulates backruns of landed txs (`Hindsight::process_orderflow`), returning `SimArbResultBatch`es.
+//!   Wire in a provider, store, event source, and `SimOptions` with `Hindsight::builder`.
+//! - `ArbDb` stores results; connect one of the built-in stores with `data::db::Db::try_new`, or implement it.
+//! - `EventSource` provides MEV-Share events; `MevShareEvents` reads them from an events API.
+//! - `ScanOptions` & `Hindsight::scan` scan a chain's events into a store, like `hindsight scan`.
+//! - `sim::pools::PoolAdapter` adds support for another AMM; register it with `Hindsight::with_pool_adapter`.
+//! - `relay::ArbSender` signs arbs of a live scan's opportunities & submits them to relays (`ScanOptions::send_arbs`).
+//! - `estimate::estimate_workload` predicts the txs, EVM runs & RPC requests of scanning a range of events.
+//! - `labels::load_labels` names well-known addresses (routers, searchers, builders, tokens), plus those saved with
+//!   `ArbDb::write_labels`.
+//! - `indexer::PoolIndexer` saves every pool that a chain's DEX factories deployed; `PoolRegistry::seed` reads them back.
+//! - `mock::MockChain` (a scripted chain) & `data::MemoryStore` stand in for a node & a DB in tests.
+
+pub mod analysis;
+pub mod audit;
+pub mod cassette;
 pub mod commands;
 pub mod config;
+pub mod control;
 pub mod data;
 pub mod error;
+pub mod estimate;
 pub mod event_history;
+pub mod executor;
+pub mod filter;
+#[cfg(test)]
+mod golden;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod hindsight;
+pub mod indexer;
 pub mod interfaces;
+pub mod labels;
+pub mod live;
+pub mod metrics;
+pub mod mock;
+pub mod notify;
+pub mod paper;
+pub mod relay;
+pub mod reorg;
+pub mod service;
+pub mod signer;
 pub mod sim;
+pub mod telemetry;
 pub mod util;
 
 pub use anyhow::{Error, Result};
 pub use tracing::{debug, error as log_error, info, warn};
+
+pub use crate::commands::scan::{ScanOptions, ScanSummary};
+pub use crate::data::arbs::{ArbDatabase, ArbDb, ArbFilterParams};
+pub use crate::event_history::{EventSource, MevShareEvents};
+pub use crate::hindsight::{Hindsight, HindsightBuilder, ProcessedOrderflow, SimOptions};
+pub use crate::interfaces::{SimArbResult, SimArbResultBatch, SimFailure};
@@ -0,0 +1,82 @@
+use crate::{debug, info, interfaces::SimArbResultBatch, service::subscribe, Result};
+use futures::{SinkExt, Stream, StreamExt};
+use std::net::SocketAddr;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+/// Pushes every result sent to `results` to the WebSocket clients connected to `addr`, as JSON text messages.
+///
+/// Clients only get results produced after they connect. Runs until the listener fails.
+pub async fn serve(addr: SocketAddr, results: broadcast::Sender<SimArbResultBatch>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("streaming results to websocket clients on {}", addr);
+    serve_listener(listener, results).await
+}
+
+/// Like `serve`, on a listener that's already bound.
+pub async fn serve_listener(
+    listener: TcpListener,
+    results: broadcast::Sender<SimArbResultBatch>,
+) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        // subscribe before the handshake, so the client doesn't miss results produced while it completes
+        let subscription = subscribe(&results);
+        tokio::task::spawn(async move {
+            if let Err(err) = push_results(stream, subscription).await {
+                debug!("websocket client {} disconnected: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Sends each result of `results` to the client on `stream` until either side closes.
+async fn push_results(
+    stream: TcpStream,
+    results: impl Stream<Item = SimArbResultBatch>,
+) -> Result<()> {
+    let (mut sink, mut incoming) = accept_async(stream).await?.split();
+    let mut results = Box::pin(results);
+    loop {
+        tokio::select! {
+            arb = results.next() => match arb {
+                Some(arb) => sink.send(Message::Text(serde_json::to_string(&arb)?)).await?,
+                None => break,
+            },
+            // reading handles pings; anything else from the client is ignored
+            message = incoming.next() => match message {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H256;
+    use tokio_tungstenite::connect_async;
+
+    #[tokio::test]
+    async fn it_pushes_results_to_clients() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (results, _) = broadcast::channel(16);
+        tokio::task::spawn(serve_listener(listener, results.clone()));
+
+        let (mut client, _) = connect_async(format!("ws://{}", addr)).await?;
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.hint.hash = H256::from_low_u64_be(0xabc);
+        results.send(arb)?;
+        let message = client.next().await.expect("stream ended")?;
+        let received: SimArbResultBatch = serde_json::from_str(message.to_text()?)?;
+        assert_eq!(received.event.hint.hash, H256::from_low_u64_be(0xabc));
+        Ok(())
+    }
+}
@@ -1,37 +1,91 @@
-use ethers::types::U256;
+use ethers::{types::U256, utils::parse_units};
 use hindsight::{
-    commands::{self},
-    config::Config,
+    analysis::{
+        allocation::{AllocationOptions, MAX_RESOLUTION},
+        gas::{GasSweepOptions, DEFAULT_BASE_FEES_GWEI},
+        pnl::PnlOptions,
+        stats::{StatsOptions, ESTIMATED_BACKRUN_GAS},
+    },
+    audit::AuditLog,
+    cassette::{use_cassettes, CassetteMode},
+    commands::{
+        self,
+        cex_dex::CexDexOptions,
+        cross_chain::{ChainQuery, CrossChainOptions},
+        drift::DriftOptions,
+        hot_routes::HotRouteOptions,
+        inventory::InventorySettings,
+        report::ReportOptions,
+        sweep::SweepOptions,
+        OutputFormat,
+    },
+    config::{known_eth_usd_feed, ChainConfig, Config},
     data::{
-        arbs::{ArbFilterParams, WriteEngine},
-        db::Db,
+        arbs::{ArbDatabase, ArbFilterParams, WriteEngine},
+        cex::CexPriceSource,
+        db::{Db, DbEngine},
+        tokens::TokenMetadataStore,
     },
     // debug,
-    hindsight::Hindsight,
+    indexer::PoolIndexer,
     info,
+    labels::load_labels,
+    log_error,
+    metrics::MetricsSink,
+    notify::{ArbAlerts, Destination},
+    paper::{PaperAssumptions, PaperTrader},
+    relay::{check_single_chain, ArbSender, SendLimits},
+    signer::Signer,
+    sim::{budget::TxSimLimits, liquidation::load_borrowers},
+    telemetry,
     util::get_ws_client,
 };
+use hindsight::{service::SimService, Hindsight, SimOptions};
 use mev_share_sse::EventClient;
 use revm::primitives::bitvec::macros::internal::funty::Fundamental;
-use std::thread::available_parallelism;
+use serde_json::json;
+use std::{sync::Arc, thread::available_parallelism};
 mod cli;
-use cli::{Cli, Commands};
+use cli::{ArbQuery, Cli, Commands};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    let config = Config::default();
     let cli = Cli::parse_args();
+    telemetry::init(cli.otlp, cli.log_format)?;
+    if cli.output != Some(OutputFormat::Json) {
+        return run(cli).await;
+    }
+    // report errors on stdout too, where scripts read the output from
+    if let Err(err) = run(cli).await {
+        println!("{}", json!({ "error": format!("{:#}", err) }));
+        telemetry::shutdown();
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    let output = cli.output.unwrap_or_default();
+    let mut config = Config::load_profile(cli.profile.as_deref())?;
+    match (cli.record, cli.replay) {
+        (Some(dir), _) => use_cassettes(&mut config, CassetteMode::Record, &dir).await?,
+        (_, Some(dir)) => use_cassettes(&mut config, CassetteMode::Replay, &dir).await?,
+        _ => {}
+    }
+    let default_db = config.default_db.to_owned().unwrap_or("mongo".to_owned());
+    let tx_limits = TxSimLimits {
+        deterministic: cli.deterministic,
+        ..config.tx_sim_limits()
+    };
 
     ctrlc::set_handler(move || {
-        println!("\nstopping hindsight!");
+        eprintln!("\nstopping hindsight!");
+        telemetry::shutdown();
         std::process::exit(0);
     })
     .expect("Error setting Ctrl-C handler");
 
-    let ws_client = get_ws_client(None).await?;
     let mevshare = EventClient::default();
-    let hindsight = Hindsight::new(config.rpc_url_ws).await?;
 
     match cli.command {
         Some(Commands::Scan {
@@ -41,57 +95,162 @@ async fn main() -> anyhow::Result<()> {
             timestamp_end,
             timestamp_start,
             batch_size,
+            auto_tune,
+            min_batch_size,
+            max_batch_size,
             db_engine,
+            run_label,
+            control_socket,
+            address_lists,
+            save_events,
+            replay_private,
+            sim_cache,
+            verify,
+            notify,
+            metrics,
+            results_ws,
+            alert_min_profit,
+            alert_rate_limit,
+            paper,
+            paper_tip,
+            paper_bribe,
+            paper_latency_blocks,
+            paper_latency_ms,
+            send_arbs,
+            send_arbs_daily_limit,
+            send_arbs_tip,
+            audit,
+            audit_sample,
+            liquidations,
+            max_hops,
+            estimate,
         }) => {
-            /* If no start/end params are defined,
-                refine params based on ranges present in DB.
-                Overwriting old results may be accomplished by setting the start/end timestamp/block params.
-                Replace the provided timestamp/block params with the latest respective
-                value + 1 in the DB if it's higher than the param.
-                We add 1 to prevent duplicates. If an arb is saved in the DB,
-                then we know we've scanned & simulated up to that point.
-                Timestamp is evaluated by default, falls back to block.
-            */
-            let db_engine = db_engine.unwrap_or_default();
-            let db = Db::new(db_engine.to_owned()).await;
-            let (block_start, timestamp_start) =
-                if block_start.is_none() && timestamp_start.is_none() {
-                    let db_ranges = db.connect.get_previously_saved_ranges().await?;
-                    info!("previously saved event ranges: {:?}", db_ranges);
-                    let block_start = db_ranges.latest_block;
-                    let timestamp_start = db_ranges.latest_timestamp;
-                    (block_start as u32, timestamp_start as u32)
-                } else {
-                    if block_start.is_some() && timestamp_start.is_some() {
-                        panic!("cannot specify both block_start and timestamp_start");
+            let notify = notify_destinations(notify, &config)?;
+            let metrics = match (metrics, &config.metrics_url) {
+                (true, None) => {
+                    return Err(anyhow::format_err!(
+                        "--metrics requires METRICS_URL to be set"
+                    ))
+                }
+                (true, Some(url)) => Some(MetricsSink {
+                    url: url.to_owned(),
+                    token: config.metrics_token.to_owned(),
+                }),
+                (false, _) => None,
+            };
+            let alerts = match alert_min_profit {
+                Some(min_profit) => {
+                    let destinations = config.alert_destinations();
+                    if destinations.is_empty() {
+                        return Err(anyhow::format_err!(
+                            "--alert-min-profit requires ALERT_URLS or a Telegram/Discord bot to be set"
+                        ));
                     }
-                    // use whichever is specified; the other (being 1) will not alter the selection
-                    (block_start.unwrap_or(1), timestamp_start.unwrap_or(1))
-                };
-
-            let batch_size = batch_size.unwrap_or(
+                    if min_profit.is_nan() || min_profit < 0.0 {
+                        return Err(anyhow::format_err!("alert_min_profit must be >= 0"));
+                    }
+                    Some(ArbAlerts::new(
+                        destinations,
+                        min_profit,
+                        config.alert_template.to_owned(),
+                        alert_rate_limit,
+                    ))
+                }
+                None => None,
+            };
+            let paper = match paper {
+                Some(session) => Some(Arc::new(PaperTrader::open(
+                    &session,
+                    PaperAssumptions {
+                        tip_gwei: paper_tip,
+                        bribe_percent: paper_bribe,
+                        latency_blocks: paper_latency_blocks,
+                        latency_ms: paper_latency_ms,
+                    },
+                )?)),
+                None => None,
+            };
+            let send_arbs = match (send_arbs, send_arbs_daily_limit) {
+                (true, Some(daily_limit)) => {
+                    check_single_chain(&config.chains)?;
+                    let signer_config = config.auth_signer.as_ref().ok_or(anyhow::format_err!(
+                        "--send-arbs requires an auth signer; set AUTH_SIGNER_KEYSTORE, AUTH_SIGNER_LEDGER or AUTH_SIGNER_KEY"
+                    ))?;
+                    let signer = Signer::load(signer_config, config.chains[0].chain_id).await?;
+                    info!("sending arbs: signing bundles from {:?}", signer.address());
+                    Some(Arc::new(ArbSender::new(
+                        signer,
+                        SendLimits {
+                            daily_limit,
+                            tip_gwei: send_arbs_tip,
+                        },
+                    )?))
+                }
+                _ => None,
+            };
+            let audit = match audit {
+                Some(session) => Some(AuditLog::open(&session, audit_sample)?),
+                None => None,
+            };
+            let liquidation_borrowers = match liquidations {
+                Some(path) => load_borrowers(&path)?,
+                None => vec![],
+            };
+            if max_hops.is_some_and(|max_hops| max_hops < 3) {
+                return Err(anyhow::format_err!(
+                    "max_hops must be >= 3; two-pool routes are always searched"
+                ));
+            }
+            let db_engine = DbEngine::from_config(&db_engine.unwrap_or(default_db), &config)?;
+            let batch_size = batch_size.or(config.scan.batch_size).unwrap_or(
                 available_parallelism()
                     .map(|n| usize::from(n) / 2)
                     .unwrap_or(4)
                     .max(1),
             );
             info!("batch size: {}", batch_size);
+            let auto_tune =
+                auto_tune.then(|| min_batch_size..=max_batch_size.unwrap_or(batch_size * 4));
             let scan_options = commands::scan::ScanOptions {
                 block_start,
                 block_end,
                 timestamp_start,
                 timestamp_end,
                 batch_size,
+                auto_tune,
                 db_engine,
+                run_label,
+                control_socket,
+                address_lists,
+                save_events,
+                replay_private,
+                notify,
+                metrics,
+                results_ws,
+                alerts,
+                paper,
+                send_arbs,
+                sim_budget: config.sim_budget(),
+                tx_limits: TxSimLimits {
+                    verify,
+                    ..tx_limits
+                },
+                sim_cache,
+                audit,
+                output,
+                liquidation_borrowers,
+                max_hops,
             };
-            commands::scan::run(
-                scan_options.to_owned(),
-                &ws_client,
-                &mevshare,
-                &hindsight,
-                &db.connect,
-            )
-            .await?;
+            if estimate {
+                if block_start.is_none() && timestamp_start.is_none() {
+                    return Err(anyhow::format_err!(
+                        "--estimate needs --block-start or --timestamp-start"
+                    ));
+                }
+                commands::scan::estimate_chains(scan_options, &config.chains, &mevshare).await?;
+            } else {
+                commands::scan::run_chains(scan_options, &config.chains, &mevshare).await?;
+            }
         }
         Some(Commands::Export {
             // cli args:
@@ -103,24 +262,29 @@ async fn main() -> anyhow::Result<()> {
             min_profit,
             read_db,
             write_db,
+            chain,
+            run_label,
         }) => {
             let min_profit = min_profit.unwrap_or(0f64);
-            if min_profit < 0f64 {
-                panic!("min_profit must be >= 0");
+            if min_profit.is_nan() || min_profit < 0f64 {
+                return Err(anyhow::format_err!("min_profit must be >= 0"));
             } else if min_profit > 0.0 && min_profit * 1e9 < 1.0 {
-                panic!("min_profit must be >= 1e9 wei");
+                return Err(anyhow::format_err!("min_profit must be >= 1e9 wei"));
             }
             let umin_profit = U256::from((min_profit * 1e9) as u64) * U256::from(1e9.as_u64());
 
-            let db_engine = read_db.unwrap_or_default();
-            let read_db = Db::new(db_engine.to_owned()).await.connect;
+            let chain = config.chain(chain.as_deref())?;
+            let db_engine = DbEngine::from_config(&read_db.unwrap_or(default_db), &config)?;
+            let read_db = Db::try_new(db_engine.with_namespace(&chain.db_namespace))
+                .await?
+                .connect;
             // if filename is specified, use that, otherwise try write_db
             // if filename & write_db are both None, use file exporter & default filename
             let write_dest = if filename.is_some() {
                 WriteEngine::File(filename)
             } else {
                 if let Some(write_db) = write_db {
-                    WriteEngine::Db(write_db)
+                    WriteEngine::Db(DbEngine::from_config(&write_db, &config)?)
                 } else {
                     WriteEngine::File(None)
                 }
@@ -133,9 +297,502 @@ async fn main() -> anyhow::Result<()> {
                     timestamp_end,
                     timestamp_start,
                     min_profit: Some(umin_profit),
+                    chain_id: Some(chain.chain_id),
+                    run_label,
                 },
                 &read_db,
                 write_dest,
+                output,
+            )
+            .await?;
+        }
+        Some(Commands::Stats {
+            bucket,
+            format,
+            backrun_gas,
+            by_dex,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::stats::run(
+                params,
+                &read_db,
+                StatsOptions {
+                    bucket_size: bucket,
+                    backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+                    native_token: chain.wrapped_native,
+                    by_dex,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Leaderboard {
+            by,
+            limit,
+            format,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::leaderboard::run(
+                params,
+                &read_db,
+                &client,
+                chain.chain_id,
+                by,
+                limit,
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Realized {
+            recheck,
+            attribute,
+            labels,
+            format,
+            query,
+        }) => {
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let labels = load_labels(chain.chain_id, Some(&db), labels.as_deref()).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::realized::run(params, &db, &client, recheck, attribute, &labels, format)
+                .await?;
+        }
+        Some(Commands::Sizing {
+            by,
+            limit,
+            format,
+            query,
+        }) => {
+            let (_, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::sizing::run(params, &read_db, by, limit, format).await?;
+        }
+        Some(Commands::Pnl {
+            bankroll,
+            lockup_blocks,
+            compound,
+            token,
+            backrun_gas,
+            format,
+            query,
+        }) => {
+            if bankroll.is_nan() || bankroll <= 0.0 {
+                return Err(anyhow::format_err!("bankroll must be > 0"));
+            }
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let profit_token = token.unwrap_or(chain.wrapped_native);
+            let decimals = chain
+                .quote_assets
+                .iter()
+                .find(|asset| asset.address == profit_token)
+                .map(|asset| asset.decimals)
+                .unwrap_or(18);
+            commands::pnl::run(
+                params,
+                &read_db,
+                chain.chain_id,
+                PnlOptions {
+                    profit_token,
+                    bankroll: parse_units(bankroll.to_string(), decimals)?.into(),
+                    lockup_blocks,
+                    compound,
+                    backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+                    native_token: chain.wrapped_native,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Inventory {
+            balances,
+            settle_into,
+            value_in,
+            conversion_cost_bps,
+            prices,
+            max_price_age,
+            backrun_gas,
+            format,
+            query,
+        }) => {
+            if !(0.0..10_000.0).contains(&conversion_cost_bps) {
+                return Err(anyhow::format_err!(
+                    "conversion cost must be >= 0 and < 10000 bps"
+                ));
+            }
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            let settings = InventorySettings {
+                balances,
+                settle_into,
+                value_in,
+                conversion_cost_bps,
+                source: prices.map_or(CexPriceSource::Binance, CexPriceSource::Csv),
+                max_age: max_price_age,
+                backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+            };
+            commands::inventory::run(params, &read_db, &client, &chain, settings, format).await?;
+        }
+        Some(Commands::Allocate {
+            budget,
+            resolution,
+            token,
+            backrun_gas,
+            format,
+            query,
+        }) => {
+            if budget.is_nan() || budget <= 0.0 {
+                return Err(anyhow::format_err!("budget must be > 0"));
+            }
+            if resolution == 0 || resolution > MAX_RESOLUTION {
+                return Err(anyhow::format_err!(
+                    "resolution must be between 1 and {}",
+                    MAX_RESOLUTION
+                ));
+            }
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let profit_token = token.unwrap_or(chain.wrapped_native);
+            let decimals = chain
+                .quote_assets
+                .iter()
+                .find(|asset| asset.address == profit_token)
+                .map(|asset| asset.decimals)
+                .unwrap_or(18);
+            commands::allocate::run(
+                params,
+                &read_db,
+                chain.chain_id,
+                AllocationOptions {
+                    profit_token,
+                    budget: parse_units(budget.to_string(), decimals)?.into(),
+                    resolution,
+                    backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+                    native_token: chain.wrapped_native,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Report {
+            format,
+            filename,
+            bucket,
+            limit,
+            backrun_gas,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::report::run(
+                params,
+                &read_db,
+                &client,
+                &chain,
+                ReportOptions {
+                    format,
+                    bucket_size: bucket,
+                    limit,
+                    backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+                    filename,
+                    output,
+                },
+            )
+            .await?;
+        }
+        Some(Commands::Hints { format, query }) => {
+            let (_, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::hints::run(params, &read_db, format).await?;
+        }
+        Some(Commands::HotRoutes {
+            min_blocks,
+            limit,
+            notify,
+            format,
+            query,
+        }) => {
+            let notify = notify_destinations(notify, &config)?;
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::hot_routes::run(
+                params,
+                &read_db,
+                &client,
+                &chain,
+                HotRouteOptions {
+                    min_blocks,
+                    limit,
+                    notify,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Volatility {
+            bucket,
+            feed,
+            format,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let feed = feed
+                .or(known_eth_usd_feed(chain.chain_id))
+                .ok_or(anyhow::format_err!(
+                    "no ETH/USD price feed is known for chain {}; set one with --feed",
+                    chain.chain_id
+                ))?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::volatility::run(
+                params,
+                &read_db,
+                &client,
+                chain.chain_id,
+                feed,
+                bucket,
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::CexDex {
+            prices,
+            min_spread_bps,
+            max_price_age,
+            format,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            let options = CexDexOptions {
+                source: prices.map_or(CexPriceSource::Binance, CexPriceSource::Csv),
+                min_spread_bps,
+                max_age: max_price_age,
+            };
+            commands::cex_dex::run(params, &read_db, &client, chain.chain_id, options, format)
+                .await?;
+        }
+        Some(Commands::CrossChain {
+            chains,
+            window,
+            min_spread_bps,
+            min_duration,
+            format,
+            query,
+        }) => {
+            if query.chain.is_some() || query.block_start.is_some() || query.block_end.is_some() {
+                return Err(anyhow::format_err!(
+                    "cross-chain compares the chains in --chains, from --timestamp-start to --timestamp-end"
+                ));
+            }
+            let names = chains.unwrap_or(
+                config
+                    .chains
+                    .iter()
+                    .map(|chain| chain.name.to_owned())
+                    .collect(),
+            );
+            let mut queries = vec![];
+            for name in names {
+                let (chain, db, params) = open_query(
+                    ArbQuery {
+                        timestamp_start: query.timestamp_start,
+                        timestamp_end: query.timestamp_end,
+                        block_start: None,
+                        block_end: None,
+                        read_db: query.read_db.to_owned(),
+                        chain: Some(name),
+                        run_label: query.run_label.to_owned(),
+                    },
+                    &config,
+                    &default_db,
+                )
+                .await?;
+                let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+                queries.push(ChainQuery {
+                    chain,
+                    db,
+                    params,
+                    client,
+                });
+            }
+            let options = CrossChainOptions {
+                window,
+                min_spread_bps,
+                min_duration,
+            };
+            commands::cross_chain::run(&queries, options, format).await?;
+        }
+        Some(Commands::Opportunities {
+            duplicates_only,
+            format,
+            query,
+        }) => {
+            let (_, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::opportunities::run(params, &read_db, duplicates_only, format).await?;
+        }
+        Some(Commands::Latency {
+            max_delay,
+            sample,
+            format,
+            query,
+        }) => {
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::latency::run(
+                params,
+                &db,
+                &client,
+                &chain.quote_assets,
+                max_delay,
+                sample,
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::HintCounterfactual {
+            sample,
+            format,
+            query,
+        }) => {
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let hindsight = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .run_tags(chain.chain_id, None)
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    tx_limits,
+                    ..Default::default()
+                })
+                .build()
+                .await?;
+            commands::counterfactual::run(params, &db, &hindsight, sample, format).await?;
+        }
+        Some(Commands::Sweep {
+            batch_size,
+            format,
+            query,
+        }) => {
+            let (Some(block_start), Some(block_end)) = (query.block_start, query.block_end) else {
+                return Err(anyhow::format_err!(
+                    "sweep requires --block-start and --block-end"
+                ));
+            };
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let run_label = params
+                .run_label
+                .to_owned()
+                .unwrap_or(commands::sweep::DEFAULT_SWEEP_LABEL.to_owned());
+            let hindsight = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .run_tags(chain.chain_id, Some(run_label))
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    // verification forks a fresh copy of the parent block, without the txs before each swept tx
+                    tx_limits: TxSimLimits {
+                        verify: false,
+                        ..tx_limits
+                    },
+                    ..Default::default()
+                })
+                .build()
+                .await?;
+            commands::sweep::run(
+                &hindsight,
+                &db,
+                params,
+                SweepOptions {
+                    block_start: block_start as u64,
+                    block_end: block_end as u64,
+                    batch_size,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Position {
+            sample,
+            format,
+            query,
+        }) => {
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::position::run(params, &db, &client, &chain.quote_assets, sample, format)
+                .await?;
+        }
+        Some(Commands::Drift {
+            baseline,
+            sample,
+            tolerance,
+            max_drift,
+            examples,
+            format,
+            query,
+        }) => {
+            let (chain, db, params) = open_query(query, &config, &default_db).await?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::drift::run(
+                params,
+                &db,
+                &client,
+                &chain.quote_assets,
+                DriftOptions {
+                    baseline,
+                    sample_size: sample,
+                    tolerance,
+                    max_drift,
+                    num_examples: examples,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::GasSweep {
+            base_fees,
+            tips,
+            backrun_gas,
+            format,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::gas::run(
+                params,
+                &read_db,
+                GasSweepOptions {
+                    base_fees_gwei: base_fees.unwrap_or(DEFAULT_BASE_FEES_GWEI.to_vec()),
+                    tips_gwei: tips,
+                    backrun_gas: backrun_gas.unwrap_or(ESTIMATED_BACKRUN_GAS),
+                    native_token: chain.wrapped_native,
+                },
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Failures {
+            examples,
+            format,
+            query,
+        }) => {
+            let (_, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::failures::run(params, &read_db, examples, format).await?;
+        }
+        Some(Commands::Reconcile {
+            import,
+            source,
+            examples,
+            format,
+            query,
+        }) => {
+            let (chain, read_db, params) = open_query(query, &config, &default_db).await?;
+            commands::reconcile::run(
+                params,
+                &read_db,
+                chain.chain_id,
+                import.as_deref(),
+                source,
+                examples,
+                format,
             )
             .await?;
         }
@@ -143,7 +800,255 @@ async fn main() -> anyhow::Result<()> {
             let program = std::env::args().next().unwrap_or("hindsight".to_owned());
             println!("for usage, run: {} --help", program);
         }
+        Some(Commands::Paper { session, format }) => {
+            commands::paper::run(&session, format).await?;
+        }
+        Some(Commands::Audit {
+            session,
+            examples,
+            format,
+        }) => {
+            commands::audit::run(&session, examples, format)?;
+        }
+        Some(Commands::Pipe { chain, concurrency }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let hindsight = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .run_tags(chain.chain_id, None)
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    tx_limits,
+                    ..Default::default()
+                })
+                .build()
+                .await?;
+            commands::pipe::run(
+                &SimService::new(hindsight),
+                tokio::io::BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+                concurrency,
+            )
+            .await?;
+        }
+        Some(Commands::Repl {
+            chain,
+            db_engine,
+            format,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let hindsight = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .run_tags(chain.chain_id, None)
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    tx_limits,
+                    ..Default::default()
+                })
+                .build()
+                .await?;
+            let db_engine = DbEngine::from_config(&db_engine.unwrap_or(default_db), &config)?
+                .with_namespace(&chain.db_namespace);
+            // sims & prices don't need the DB, so the session starts without it
+            let db = match Db::try_new(db_engine).await {
+                Ok(db) => Some(db.connect),
+                Err(err) => {
+                    log_error!("{}; `top` is unavailable", err);
+                    None
+                }
+            };
+            let mut repl = commands::repl::Repl {
+                service: SimService::new(hindsight),
+                db,
+                store: TokenMetadataStore::load(chain.chain_id)?,
+                format,
+            };
+            commands::repl::run(
+                &mut repl,
+                tokio::io::BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+            )
+            .await?;
+        }
+        Some(Commands::Selftest { chain, anvil }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let block = commands::selftest::fixture_event()?.block;
+            // kept until the test is done; dropping it stops the node
+            let anvil = match anvil {
+                true => Some(commands::selftest::fork_with_anvil(
+                    &chain.rpc_url_ws,
+                    block,
+                )?),
+                false => None,
+            };
+            let rpc_url = anvil
+                .as_ref()
+                .map(|anvil| anvil.ws_endpoint())
+                .unwrap_or(chain.rpc_url_ws.to_owned());
+            let hindsight = Hindsight::builder()
+                .rpc_url(rpc_url)
+                .run_tags(chain.chain_id, None)
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    tx_limits,
+                    ..Default::default()
+                })
+                .build()
+                .await?;
+            commands::selftest::run(hindsight, output).await?;
+        }
+        Some(Commands::Indexer {
+            chain,
+            db_engine,
+            follow,
+            interval,
+            chunk_blocks,
+            format,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            if chain.dex_factories.is_empty() {
+                return Err(anyhow::format_err!(
+                    "no known DEX factories for chain '{}'; set DEX_FACTORIES_{}",
+                    chain.name,
+                    chain.name.to_uppercase().replace('-', "_")
+                ));
+            }
+            let db_engine = DbEngine::from_config(&db_engine.unwrap_or(default_db), &config)?
+                .with_namespace(&chain.db_namespace);
+            let indexer = PoolIndexer {
+                client: get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?,
+                db: Db::try_new(db_engine).await?.connect,
+                chain_id: chain.chain_id,
+                factories: chain.dex_factories.to_owned(),
+                chunk_blocks,
+            };
+            commands::indexer::run(
+                &indexer,
+                follow.then_some(std::time::Duration::from_secs(interval)),
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::Labels {
+            chain,
+            db_engine,
+            import,
+            kind,
+            format,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let db_engine = DbEngine::from_config(&db_engine.unwrap_or(default_db), &config)?
+                .with_namespace(&chain.db_namespace);
+            let db = Db::try_new(db_engine).await?.connect;
+            commands::labels::run(&db, chain.chain_id, import.as_deref(), kind, format).await?;
+        }
+        Some(Commands::TokenTax {
+            tokens,
+            chain,
+            block,
+            refresh,
+            format,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::token_tax::run(
+                &client,
+                chain.chain_id,
+                &chain.quote_assets,
+                &tokens,
+                block,
+                refresh,
+                format,
+            )
+            .await?;
+        }
+        Some(Commands::DeployExecutor {
+            chain,
+            artifact,
+            env_file,
+            no_save,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let client = get_ws_client(Some(chain.rpc_url_ws.to_owned())).await?;
+            commands::deploy_executor::run(
+                &client,
+                &config,
+                &chain,
+                &artifact,
+                (!no_save).then_some(env_file.as_path()),
+            )
+            .await?;
+        }
+        #[cfg(feature = "grpc")]
+        Some(Commands::Serve {
+            addr,
+            chain,
+            db_engine,
+        }) => {
+            let chain = config.chain(chain.as_deref())?;
+            let mut builder = Hindsight::builder()
+                .rpc_url(chain.rpc_url_ws.to_owned())
+                .run_tags(chain.chain_id, None)
+                .sim_options(SimOptions {
+                    quote_assets: chain.quote_assets.to_owned(),
+                    wrapped_native: Some(chain.wrapped_native),
+                    sim_budget: config.sim_budget(),
+                    tx_limits,
+                    ..Default::default()
+                });
+            if let Some(db_engine) = db_engine {
+                let db_engine =
+                    DbEngine::from_config(&db_engine, &config)?.with_namespace(&chain.db_namespace);
+                builder = builder.store(Db::try_new(db_engine).await?.connect);
+            }
+            let service = SimService::new(builder.build().await?);
+            info!("serving simulations over gRPC on {}", addr);
+            hindsight::grpc::serve(service, addr).await?;
+        }
     }
 
+    telemetry::shutdown();
     Ok(())
 }
+
+/// Connects to the DB that `query` reads from, and builds its filter.
+async fn open_query(
+    query: ArbQuery,
+    config: &Config,
+    default_db: &str,
+) -> anyhow::Result<(ChainConfig, ArbDatabase, ArbFilterParams)> {
+    let chain = config.chain(query.chain.as_deref())?;
+    let db_engine = DbEngine::from_config(query.read_db.as_deref().unwrap_or(default_db), config)?;
+    let read_db = Db::try_new(db_engine.with_namespace(&chain.db_namespace))
+        .await?
+        .connect;
+    let params = ArbFilterParams {
+        block_start: query.block_start,
+        block_end: query.block_end,
+        timestamp_start: query.timestamp_start,
+        timestamp_end: query.timestamp_end,
+        min_profit: None,
+        chain_id: Some(chain.chain_id),
+        run_label: query.run_label,
+    };
+    Ok((chain, read_db, params))
+}
+
+/// Where to send notifications if `--notify` was passed; errors if nowhere is configured.
+fn notify_destinations(notify: bool, config: &Config) -> anyhow::Result<Vec<Destination>> {
+    if !notify {
+        return Ok(vec![]);
+    }
+    let destinations = config.notify_destinations();
+    if destinations.is_empty() {
+        return Err(anyhow::format_err!(
+            "--notify requires NOTIFY_URL or a Telegram/Discord bot to be set"
+        ));
+    }
+    Ok(destinations)
+}
@@ -0,0 +1,264 @@
+use crate::analysis::to_decimal;
+use crate::commands::scan::ScanSummary;
+use crate::hindsight::ProcessedOrderflow;
+use crate::sim::cache::SimCacheStats;
+use crate::Result;
+use ethers::types::{Address, U256};
+use mev_share_sse::EventHistory;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An InfluxDB line-protocol write endpoint that scans report their findings to.
+#[derive(Clone, Debug)]
+pub struct MetricsSink {
+    /// Full write URL, e.g. `http://localhost:8086/api/v2/write?org=me&bucket=hindsight` (v2)
+    /// or `http://localhost:8086/write?db=hindsight` (v1).
+    pub url: String,
+    /// InfluxDB v2 API token, sent as `Authorization: Token <token>`.
+    pub token: Option<String>,
+}
+
+impl MetricsSink {
+    /// Posts `lines` to the sink in a single request. Timestamps are in nanoseconds (the default precision).
+    pub async fn write(&self, lines: &[String]) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut request = reqwest::Client::new()
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(lines.join("\n"));
+        if let Some(token) = &self.token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Tags that every point of a chain's scan is written with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricTags {
+    pub chain: String,
+    pub chain_id: u64,
+    pub run_label: Option<String>,
+}
+
+impl MetricTags {
+    /// Renders the tags as the `,key=value` part of a line that follows the measurement.
+    fn render(&self) -> String {
+        let mut tags = format!(
+            ",chain={},chain_id={}",
+            escape_tag(&self.chain),
+            self.chain_id
+        );
+        if let Some(run_label) = &self.run_label {
+            tags.push_str(&format!(",run={}", escape_tag(run_label)));
+        }
+        tags
+    }
+}
+
+/// Escapes the characters that line protocol gives a meaning to in tag values.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn to_nanos(timestamp: u64) -> u64 {
+    timestamp * 1_000_000_000
+}
+
+/// What was found in one block.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BlockMetrics {
+    timestamp: u64,
+    num_events: u64,
+    num_arbs: u64,
+    num_profitable: u64,
+    num_failed_sims: u64,
+    /// Sum of max profits, number of profitable arbs, and decimals, by profit token.
+    profits: BTreeMap<Address, (U256, u64, u32)>,
+}
+
+/// Collects per-block metrics while a scan runs, until each block is complete.
+///
+/// Events arrive in block order, but one block's events may be split across batches; a block
+/// is only written once a later block has been seen, so that its point isn't overwritten by a partial one.
+#[derive(Clone, Debug, Default)]
+pub struct MetricsBuffer {
+    blocks: BTreeMap<u64, BlockMetrics>,
+}
+
+impl MetricsBuffer {
+    pub fn add_events(&mut self, events: &[EventHistory]) {
+        for event in events {
+            let block = self.blocks.entry(event.block).or_default();
+            block.timestamp = event.timestamp;
+            block.num_events += 1;
+        }
+    }
+
+    pub fn add_orderflow(&mut self, processed: &ProcessedOrderflow) {
+        for arb in &processed.arbs {
+            let block = self.blocks.entry(arb.event.block).or_default();
+            block.timestamp = arb.event.timestamp;
+            block.num_arbs += 1;
+            if arb.max_profit.is_zero() {
+                continue;
+            }
+            block.num_profitable += 1;
+            let (profit, num_profitable, decimals) =
+                block.profits.entry(arb.profit_token).or_default();
+            *profit += arb.max_profit;
+            *num_profitable += 1;
+            *decimals = arb.profit_decimals;
+        }
+        for failure in &processed.failures {
+            let block = self.blocks.entry(failure.block).or_default();
+            block.timestamp = failure.timestamp;
+            block.num_failed_sims += 1;
+        }
+    }
+
+    /// Removes the blocks that can't get any more events, i.e. all but the latest, and renders them as lines.
+    pub fn drain_complete(&mut self, tags: &MetricTags) -> Vec<String> {
+        let latest = match self.blocks.keys().last() {
+            Some(latest) => *latest,
+            None => return vec![],
+        };
+        let latest = self.blocks.split_off(&latest);
+        let complete = std::mem::replace(&mut self.blocks, latest);
+        render_blocks(complete, tags)
+    }
+
+    /// Removes every block and renders them as lines, for when the scan ends.
+    pub fn drain_all(&mut self, tags: &MetricTags) -> Vec<String> {
+        render_blocks(std::mem::take(&mut self.blocks), tags)
+    }
+}
+
+/// Renders a `hindsight_block` point for each block, and a `hindsight_profit` point for each of its profit tokens.
+fn render_blocks(blocks: BTreeMap<u64, BlockMetrics>, tags: &MetricTags) -> Vec<String> {
+    let tags = tags.render();
+    let mut lines = vec![];
+    for (number, block) in blocks {
+        let timestamp = to_nanos(block.timestamp);
+        lines.push(format!(
+            "hindsight_block{} block={}i,events={}i,arbs={}i,profitable={}i,failed_sims={}i {}",
+            tags,
+            number,
+            block.num_events,
+            block.num_arbs,
+            block.num_profitable,
+            block.num_failed_sims,
+            timestamp
+        ));
+        for (token, (profit, num_profitable, decimals)) in block.profits {
+            lines.push(format!(
+                "hindsight_profit{},profit_token={:?} block={}i,profit={},profitable={}i {}",
+                tags,
+                token,
+                number,
+                to_decimal(profit, decimals),
+                num_profitable,
+                timestamp
+            ));
+        }
+    }
+    lines
+}
+
+/// Renders a `hindsight_scan` point of the scan's running totals, timestamped now.
+pub fn progress_line(summary: &ScanSummary, tags: &MetricTags) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default();
+    let last_block = summary
+        .last_event
+        .map(|(block, _)| format!(",last_block={}i", block))
+        .unwrap_or_default();
+    format!(
+        "hindsight_scan{} events={}i,txs={}i,arbs={}i,profitable={}i,failed={}i{} {}",
+        tags.render(),
+        summary.num_events,
+        summary.num_txs,
+        summary.num_arbs,
+        summary.num_profitable,
+        summary.num_failed,
+        last_block,
+        to_nanos(now)
+    )
+}
+
+/// Renders a `hindsight_sim_cache` point with the sim cache's lookups so far, stamped with the current time.
+pub fn sim_cache_line(stats: &SimCacheStats, tags: &MetricTags) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default();
+    format!(
+        "hindsight_sim_cache{} hits={}i,misses={}i,entries={}i,hit_rate={} {}",
+        tags.render(),
+        stats.hits,
+        stats.misses,
+        stats.entries,
+        stats.hit_rate(),
+        to_nanos(now)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::{SimArbResultBatch, SimFailure};
+
+    #[test]
+    fn it_writes_complete_blocks_as_lines() {
+        let tags = MetricTags {
+            chain: "main net".to_owned(),
+            chain_id: 1,
+            run_label: Some("a,b".to_owned()),
+        };
+        let arb = |block: u64, profit: u64| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.block = block;
+            arb.event.timestamp = block * 12;
+            arb.max_profit = U256::from(profit) * U256::exp10(15);
+            arb.profit_decimals = 18;
+            arb
+        };
+        let mut buffer = MetricsBuffer::default();
+        buffer.add_orderflow(&ProcessedOrderflow {
+            arbs: vec![arb(100, 500), arb(100, 0), arb(101, 250)],
+            num_failed: 0,
+            failures: vec![SimFailure::new(
+                &arb(100, 0).event,
+                None,
+                "all swaps reverted",
+            )],
+        });
+
+        // block 101 may still get more arbs
+        let lines = buffer.drain_complete(&tags);
+        let profit_token = format!("{:?}", arb(100, 0).profit_token);
+        assert_eq!(
+            lines,
+            vec![
+                "hindsight_block,chain=main\\ net,chain_id=1,run=a\\,b block=100i,events=0i,arbs=2i,profitable=1i,failed_sims=1i 1200000000000".to_owned(),
+                format!("hindsight_profit,chain=main\\ net,chain_id=1,run=a\\,b,profit_token={} block=100i,profit=0.5,profitable=1i 1200000000000", profit_token),
+            ]
+        );
+        let lines = buffer.drain_all(&tags);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("block=101i,profit=0.25,"));
+        assert!(buffer.drain_all(&tags).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    cassette::{request_key, rpc_error, serve_rpc, Recorded, RpcResponder},
+    util::{get_ws_client, WsClient},
+    Result,
+};
+use async_trait::async_trait;
+use ethers::types::{Address, Block, Bytes, Transaction, TransactionReceipt, H256, U256, U64};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+
+/// A scripted chain that's served like a node, so the pipeline can be tested without one.
+///
+/// Requests are answered with the responses scripted for their exact method & params, except `eth_call`,
+/// which is answered by the call's target & calldata. Anything else fails like an RPC error.
+#[derive(Clone, Debug, Default)]
+pub struct MockChain {
+    responses: HashMap<String, Value>,
+    calls: HashMap<(Address, Bytes), Bytes>,
+}
+
+impl MockChain {
+    /// A chain that answers `eth_chainId` with `chain_id`.
+    pub fn new(chain_id: u64) -> Self {
+        Self::default().respond("eth_chainId", (), U256::from(chain_id))
+    }
+
+    /// Answers requests for `method` with `params` with `result`.
+    pub fn respond(mut self, method: &str, params: impl Serialize, result: impl Serialize) -> Self {
+        self.responses
+            .insert(request_key(method, &json!(params)), json!(result));
+        self
+    }
+
+    /// Answers `eth_blockNumber` with `number`.
+    pub fn with_block_number(self, number: u64) -> Self {
+        self.respond("eth_blockNumber", (), U64::from(number))
+    }
+
+    /// Serves `block` (without its txs) by number & by hash. A block without a number or hash is only served by the other.
+    pub fn with_block(self, block: Block<H256>) -> Self {
+        let by_number = match block.number {
+            Some(number) => self.respond("eth_getBlockByNumber", (number, false), &block),
+            None => self,
+        };
+        match block.hash {
+            Some(hash) => by_number.respond("eth_getBlockByHash", (hash, false), &block),
+            None => by_number,
+        }
+    }
+
+    /// Serves `tx` by its hash.
+    pub fn with_transaction(self, tx: Transaction) -> Self {
+        self.respond("eth_getTransactionByHash", [tx.hash], &tx)
+    }
+
+    /// Serves `receipt` by the hash of its tx.
+    pub fn with_receipt(self, receipt: TransactionReceipt) -> Self {
+        self.respond(
+            "eth_getTransactionReceipt",
+            [receipt.transaction_hash],
+            &receipt,
+        )
+    }
+
+    /// Answers calls of `to` with `data`, on any block and from any sender, with `output`.
+    pub fn with_call(mut self, to: Address, data: Bytes, output: Bytes) -> Self {
+        self.calls.insert((to, data), output);
+        self
+    }
+
+    /// Serves the chain on a local port, returning a client connected to it.
+    pub async fn serve(self) -> Result<WsClient> {
+        let url = serve_rpc(Arc::new(self)).await?;
+        get_ws_client(Some(url)).await
+    }
+
+    fn call(&self, params: &Value) -> Option<Value> {
+        let tx = &params[0];
+        let to = serde_json::from_value(tx["to"].clone()).ok()?;
+        let data = serde_json::from_value(tx.get("data").or(tx.get("input"))?.clone()).ok()?;
+        self.calls.get(&(to, data)).map(|output| json!(output))
+    }
+}
+
+#[async_trait]
+impl RpcResponder for MockChain {
+    async fn respond(&self, method: &str, params: &Value) -> Recorded {
+        let key = request_key(method, params);
+        let result = match method {
+            "eth_call" => self.call(params),
+            _ => self.responses.get(&key).cloned(),
+        };
+        match result {
+            Some(result) => Recorded::Result(result),
+            None => rpc_error(format!("mock chain has no response to {}", key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::Middleware;
+
+    #[tokio::test]
+    async fn it_serves_scripted_responses() -> Result<()> {
+        let pool = Address::from_low_u64_be(0xa);
+        let block = Block {
+            number: Some(100.into()),
+            hash: Some(H256::from_low_u64_be(1)),
+            ..Default::default()
+        };
+        let client = MockChain::new(1)
+            .with_block_number(101)
+            .with_block(block)
+            .with_call(pool, vec![1, 2].into(), vec![3].into())
+            .serve()
+            .await?;
+
+        assert_eq!(client.get_chainid().await?, U256::one());
+        assert_eq!(client.get_block_number().await?, U64::from(101));
+        let by_hash = client.get_block(H256::from_low_u64_be(1)).await?;
+        assert_eq!(by_hash.and_then(|block| block.number), Some(100.into()));
+        let by_number = client.get_block(100).await?;
+        assert_eq!(
+            by_number.and_then(|block| block.hash),
+            Some(H256::from_low_u64_be(1))
+        );
+
+        let mut call = ethers::types::TransactionRequest::new()
+            .to(pool)
+            .data(vec![1, 2]);
+        assert_eq!(
+            client.call(&call.clone().into(), None).await?,
+            Bytes::from(vec![3])
+        );
+        call = call.data(vec![4]);
+        let err = client.call(&call.into(), None).await.unwrap_err();
+        assert!(err.to_string().contains("no response"), "{}", err);
+        Ok(())
+    }
+}
@@ -0,0 +1,542 @@
+use crate::analysis::to_decimal;
+use crate::data::tokens::TokenMetadataStore;
+use crate::interfaces::SimArbResultBatch;
+use crate::{log_error, warn, Result};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Discord rejects messages longer than this.
+const DISCORD_MAX_CONTENT: usize = 2000;
+/// Telegram rejects messages longer than this.
+const TELEGRAM_MAX_TEXT: usize = 4096;
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+const DISCORD_API_URL: &str = "https://discord.com/api/v10";
+/// Values that an alert template can refer to as `{{name}}`.
+pub const ALERT_PLACEHOLDERS: [&str; 11] = [
+    "chain",
+    "chain_id",
+    "tx_hash",
+    "block",
+    "timestamp",
+    "profit",
+    "profit_token",
+    "profit_symbol",
+    "start_pool",
+    "end_pool",
+    "suppressed",
+];
+
+/// Kind of webhook that a notification is posted to, which decides the shape of the payload.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WebhookKind {
+    Slack,
+    Discord,
+    /// Any other endpoint; gets the same `{"text": ...}` payload as Slack.
+    Generic,
+}
+
+impl WebhookKind {
+    /// Guesses the kind of webhook from its URL.
+    pub fn of(url: &str) -> Self {
+        if url.contains("hooks.slack.com") {
+            WebhookKind::Slack
+        } else if url.contains("discord.com/api/webhooks")
+            || url.contains("discordapp.com/api/webhooks")
+        {
+            WebhookKind::Discord
+        } else {
+            WebhookKind::Generic
+        }
+    }
+
+    /// Wraps `text` in the JSON payload this kind of webhook expects.
+    pub fn payload(&self, text: &str) -> serde_json::Value {
+        match self {
+            WebhookKind::Discord => json!({ "content": truncate(text, DISCORD_MAX_CONTENT) }),
+            WebhookKind::Slack | WebhookKind::Generic => json!({ "text": text }),
+        }
+    }
+}
+
+/// Cuts `text` down to `max` characters, ending it with an ellipsis if it was cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    let mut truncated = text.chars().take(max - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// Somewhere that notifications & alerts are sent: a webhook, or a chat that a bot posts to.
+#[derive(Clone, PartialEq)]
+pub enum Destination {
+    Webhook(String),
+    /// A Telegram chat (or channel) that the bot has been added to.
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+    /// A Discord channel that the bot can post in.
+    Discord {
+        bot_token: String,
+        channel_id: String,
+    },
+}
+
+impl std::fmt::Debug for Destination {
+    // bot tokens are secrets; keep them out of logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Webhook(url) => f.debug_tuple("Webhook").field(url).finish(),
+            Destination::Telegram { chat_id, .. } => f
+                .debug_struct("Telegram")
+                .field("chat_id", chat_id)
+                .finish_non_exhaustive(),
+            Destination::Discord { channel_id, .. } => f
+                .debug_struct("Discord")
+                .field("channel_id", channel_id)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Destination {
+    /// Returns the URL, JSON payload, and `Authorization` header (if any) that post `text` to this destination.
+    fn request(&self, text: &str) -> (String, serde_json::Value, Option<String>) {
+        match self {
+            Destination::Webhook(url) => (url.to_owned(), WebhookKind::of(url).payload(text), None),
+            Destination::Telegram { bot_token, chat_id } => (
+                format!("{}/bot{}/sendMessage", TELEGRAM_API_URL, bot_token),
+                json!({
+                    "chat_id": chat_id,
+                    "text": truncate(text, TELEGRAM_MAX_TEXT),
+                    "disable_web_page_preview": true,
+                }),
+                None,
+            ),
+            Destination::Discord {
+                bot_token,
+                channel_id,
+            } => (
+                format!("{}/channels/{}/messages", DISCORD_API_URL, channel_id),
+                json!({ "content": truncate(text, DISCORD_MAX_CONTENT) }),
+                Some(format!("Bot {}", bot_token)),
+            ),
+        }
+    }
+
+    /// Posts `text` to this destination.
+    pub async fn send(&self, text: &str) -> Result<()> {
+        let (url, payload, authorization) = self.request(text);
+        post_json(&url, &payload, authorization.as_deref()).await
+    }
+}
+
+/// Posts `text` to every destination in `destinations`, even if some fail.
+/// Returns the first error, if any.
+pub async fn notify_all(destinations: &[Destination], text: &str) -> Result<()> {
+    let mut res = Ok(());
+    for destination in destinations {
+        if let Err(err) = destination.send(text).await {
+            log_error!("failed to notify {:?}: {}", destination, err);
+            if res.is_ok() {
+                res = Err(err);
+            }
+        }
+    }
+    res
+}
+
+/// Posts `text` to the webhook at `url`, formatted for Slack, Discord, or a generic JSON endpoint.
+pub async fn notify(url: &str, text: &str) -> Result<()> {
+    Destination::Webhook(url.to_owned()).send(text).await
+}
+
+async fn post_json(
+    url: &str,
+    payload: &serde_json::Value,
+    authorization: Option<&str>,
+) -> Result<()> {
+    let mut request = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(payload)?);
+    if let Some(authorization) = authorization {
+        request = request.header(reqwest::header::AUTHORIZATION, authorization);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// A JSON payload with `{{placeholder}}`s (see `ALERT_PLACEHOLDERS`) that alerts fill in.
+///
+/// Values are escaped to go inside JSON strings, so placeholders must be quoted,
+/// e.g. `{"content": "{{profit}} {{profit_symbol}} in {{tx_hash}}"}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertTemplate(String);
+
+impl AlertTemplate {
+    /// Checks that `template` only uses known placeholders, and renders to valid JSON.
+    pub fn parse(template: &str) -> Result<Self> {
+        let rendered = fill_placeholders(template, |name| {
+            if !ALERT_PLACEHOLDERS.contains(&name) {
+                return Err(anyhow::format_err!(
+                    "unknown placeholder '{{{{{}}}}}' in alert template (expected one of {})",
+                    name,
+                    ALERT_PLACEHOLDERS.join(", ")
+                ));
+            }
+            Ok(String::new())
+        })?;
+        serde_json::from_str::<serde_json::Value>(&rendered)
+            .map_err(|err| anyhow::format_err!("alert template isn't valid JSON: {}", err))?;
+        Ok(Self(template.to_owned()))
+    }
+
+    /// Fills in each `(placeholder, value)` of `values`, and parses the result.
+    pub fn render(&self, values: &[(&str, String)]) -> Result<serde_json::Value> {
+        let rendered = fill_placeholders(&self.0, |name| {
+            let (_, value) = values
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .ok_or(anyhow::format_err!("no value for placeholder '{}'", name))?;
+            // escape the value like a JSON string, without its quotes
+            let escaped = serde_json::to_string(value)?;
+            Ok(escaped[1..escaped.len() - 1].to_owned())
+        })?;
+        Ok(serde_json::from_str(&rendered)?)
+    }
+}
+
+/// Replaces each `{{placeholder}}` of `template` (its name trimmed of whitespace) with `value(name)`.
+fn fill_placeholders(template: &str, value: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}").ok_or(anyhow::format_err!(
+            "unclosed placeholder in alert template"
+        ))?;
+        filled.push_str(&rest[..start]);
+        filled.push_str(&value(rest[start + 2..start + end].trim())?);
+        rest = &rest[start + end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+/// Allows at most `max` events per `window`, counting the events it turns away.
+#[derive(Clone, Debug)]
+struct RateLimiter {
+    max: usize,
+    window: Duration,
+    sent: VecDeque<Instant>,
+    suppressed: usize,
+}
+
+impl RateLimiter {
+    /// Returns the number of events suppressed since the last allowed one if an event is allowed at `now`.
+    fn try_acquire(&mut self, now: Instant) -> Option<usize> {
+        while let Some(sent) = self.sent.front() {
+            if now.duration_since(*sent) < self.window {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.max {
+            self.suppressed += 1;
+            return None;
+        }
+        self.sent.push_back(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+/// Posts an alert to webhooks & chats whenever a simulated arb's profit reaches a threshold.
+///
+/// Clones share one rate limit, so scanning several chains doesn't multiply the number of alerts.
+#[derive(Clone, Debug)]
+pub struct ArbAlerts {
+    pub destinations: Vec<Destination>,
+    /// Minimum profit to alert on, in whole units of the arb's profit token.
+    pub min_profit: f64,
+    /// Payload to post to webhooks instead of the default message. Chats always get the default message.
+    pub template: Option<AlertTemplate>,
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl ArbAlerts {
+    /// Alerts on arbs of at least `min_profit`, sending no more than `max_per_minute` alerts a minute.
+    pub fn new(
+        destinations: Vec<Destination>,
+        min_profit: f64,
+        template: Option<AlertTemplate>,
+        max_per_minute: usize,
+    ) -> Self {
+        Self {
+            destinations,
+            min_profit,
+            template,
+            limiter: Arc::new(Mutex::new(RateLimiter {
+                max: max_per_minute,
+                window: Duration::from_secs(60),
+                sent: VecDeque::new(),
+                suppressed: 0,
+            })),
+        }
+    }
+
+    /// Whether `arb` is profitable enough to alert on.
+    pub fn exceeds(&self, arb: &SimArbResultBatch) -> bool {
+        !arb.max_profit.is_zero()
+            && to_decimal(arb.max_profit, arb.profit_decimals) >= self.min_profit
+    }
+
+    /// Posts an alert about each arb of `arbs` that exceeds the threshold, unless the rate limit was hit.
+    /// Failures are logged; an alerting outage shouldn't stop the scan.
+    pub async fn send(&self, arbs: &[SimArbResultBatch], chain_name: &str) {
+        let arbs = arbs
+            .iter()
+            .filter(|arb| self.exceeds(arb))
+            .collect::<Vec<_>>();
+        if arbs.is_empty() {
+            return;
+        }
+        // only cached symbols are used; fetching missing ones isn't worth delaying the alert
+        let store = TokenMetadataStore::load(arbs[0].chain_id).ok();
+        for arb in arbs {
+            let suppressed = match self
+                .limiter
+                .lock()
+                .expect("alert rate limiter poisoned")
+                .try_acquire(Instant::now())
+            {
+                Some(suppressed) => suppressed,
+                None => {
+                    warn!(
+                        "alert rate limit reached; not alerting on {:?}",
+                        arb.event.hint.hash
+                    );
+                    continue;
+                }
+            };
+            let values = alert_values(arb, chain_name, store.as_ref(), suppressed);
+            for destination in &self.destinations {
+                let res = match (destination, &self.template) {
+                    (Destination::Webhook(url), Some(template)) => match template.render(&values) {
+                        Ok(payload) => post_json(url, &payload, None).await,
+                        Err(err) => Err(err),
+                    },
+                    (Destination::Webhook(url), None) => {
+                        post_json(url, &alert_payload(WebhookKind::of(url), &values), None).await
+                    }
+                    (_, _) => destination.send(&alert_text(&values)).await,
+                };
+                if let Err(err) = res {
+                    log_error!("failed to alert on {:?}: {}", arb.event.hint.hash, err);
+                }
+            }
+        }
+    }
+}
+
+/// Values of the `ALERT_PLACEHOLDERS` for `arb`.
+fn alert_values(
+    arb: &SimArbResultBatch,
+    chain_name: &str,
+    store: Option<&TokenMetadataStore>,
+    suppressed: usize,
+) -> Vec<(&'static str, String)> {
+    let best = arb
+        .results
+        .iter()
+        .max_by_key(|res| res.backrun_trade.profit)
+        .map(|res| &res.backrun_trade);
+    vec![
+        ("chain", chain_name.to_owned()),
+        ("chain_id", arb.chain_id.to_string()),
+        ("tx_hash", format!("{:?}", arb.event.hint.hash)),
+        ("block", arb.event.block.to_string()),
+        ("timestamp", arb.event.timestamp.to_string()),
+        (
+            "profit",
+            format!("{:.6}", to_decimal(arb.max_profit, arb.profit_decimals)),
+        ),
+        ("profit_token", format!("{:?}", arb.profit_token)),
+        (
+            "profit_symbol",
+            store
+                .map(|store| store.symbol(&arb.profit_token))
+                .unwrap_or(format!("{:?}", arb.profit_token)),
+        ),
+        (
+            "start_pool",
+            best.map(|trade| format!("{:?}", trade.start_pool))
+                .unwrap_or_default(),
+        ),
+        (
+            "end_pool",
+            best.map(|trade| format!("{:?}", trade.end_pool))
+                .unwrap_or_default(),
+        ),
+        ("suppressed", suppressed.to_string()),
+    ]
+}
+
+/// Default alert message.
+fn alert_text(values: &[(&str, String)]) -> String {
+    let value = |name: &str| {
+        values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let mut text = format!(
+        "hindsight: {} {} backrun of {} on {} (block {})",
+        value("profit"),
+        value("profit_symbol"),
+        value("tx_hash"),
+        value("chain"),
+        value("block")
+    );
+    if !matches!(value("suppressed"), "" | "0") {
+        text.push_str(&format!(
+            "\n({} more alerts were rate-limited)",
+            value("suppressed")
+        ));
+    }
+    text
+}
+
+/// Default alert payload: the message for chat webhooks, which generic endpoints also get every value of.
+fn alert_payload(kind: WebhookKind, values: &[(&str, String)]) -> serde_json::Value {
+    let mut payload = kind.payload(&alert_text(values));
+    if kind == WebhookKind::Generic {
+        for (name, value) in values {
+            payload[*name] = json!(value);
+        }
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+
+    #[test]
+    fn it_formats_payloads_for_each_webhook() {
+        let slack = WebhookKind::of("https://hooks.slack.com/services/T0/B0/x");
+        assert_eq!(slack, WebhookKind::Slack);
+        assert_eq!(slack.payload("hi"), json!({ "text": "hi" }));
+
+        let discord = WebhookKind::of("https://discord.com/api/webhooks/1/x");
+        assert_eq!(discord, WebhookKind::Discord);
+        assert_eq!(discord.payload("hi"), json!({ "content": "hi" }));
+        let long = "a".repeat(3000);
+        let content = discord.payload(&long)["content"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!(content.chars().count(), DISCORD_MAX_CONTENT);
+
+        assert_eq!(
+            WebhookKind::of("https://example.org/hook"),
+            WebhookKind::Generic
+        );
+    }
+
+    #[test]
+    fn it_posts_to_chat_bots() {
+        let telegram = Destination::Telegram {
+            bot_token: "123:abc".to_owned(),
+            chat_id: "-100".to_owned(),
+        };
+        let (url, payload, authorization) = telegram.request("hi");
+        assert_eq!(url, "https://api.telegram.org/bot123:abc/sendMessage");
+        assert_eq!(
+            payload,
+            json!({ "chat_id": "-100", "text": "hi", "disable_web_page_preview": true })
+        );
+        assert_eq!(authorization, None);
+        let long = "a".repeat(5000);
+        let (_, payload, _) = telegram.request(&long);
+        assert_eq!(
+            payload["text"].as_str().unwrap().chars().count(),
+            TELEGRAM_MAX_TEXT
+        );
+
+        let discord = Destination::Discord {
+            bot_token: "xyz".to_owned(),
+            channel_id: "42".to_owned(),
+        };
+        let (url, payload, authorization) = discord.request("hi");
+        assert_eq!(url, "https://discord.com/api/v10/channels/42/messages");
+        assert_eq!(payload, json!({ "content": "hi" }));
+        assert_eq!(authorization.as_deref(), Some("Bot xyz"));
+        assert!(!format!("{:?}", discord).contains("xyz"));
+    }
+
+    #[test]
+    fn it_renders_alert_templates() {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.max_profit = U256::exp10(17);
+        arb.profit_decimals = 18;
+        let values = alert_values(&arb, "main\"net", None, 2);
+
+        let template = AlertTemplate::parse(
+            r#"{"content": "{{profit }} on {{ chain }}", "n": "{{suppressed}}"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&values).unwrap(),
+            json!({ "content": "0.100000 on main\"net", "n": "2" })
+        );
+        assert!(AlertTemplate::parse(r#"{"content": "{{profits}}"}"#).is_err());
+        assert!(AlertTemplate::parse(r#"{"content": {{profit}}}"#).is_err());
+
+        let payload = alert_payload(WebhookKind::Generic, &values);
+        assert_eq!(
+            payload["tx_hash"],
+            json!(format!("{:?}", arb.event.hint.hash))
+        );
+        assert!(payload["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("(2 more alerts were rate-limited)"));
+        let payload = alert_payload(WebhookKind::Discord, &values);
+        assert!(payload.get("tx_hash").is_none());
+
+        let alerts = ArbAlerts::new(vec![], 0.5, None, 1);
+        assert!(!alerts.exceeds(&arb));
+        arb.max_profit = U256::exp10(18);
+        assert!(alerts.exceeds(&arb));
+    }
+
+    #[test]
+    fn it_rate_limits_alerts() {
+        let mut limiter = RateLimiter {
+            max: 2,
+            window: Duration::from_secs(60),
+            sent: VecDeque::new(),
+            suppressed: 0,
+        };
+        let start = Instant::now();
+        assert_eq!(limiter.try_acquire(start), Some(0));
+        assert_eq!(
+            limiter.try_acquire(start + Duration::from_secs(10)),
+            Some(0)
+        );
+        assert_eq!(limiter.try_acquire(start + Duration::from_secs(20)), None);
+        assert_eq!(limiter.try_acquire(start + Duration::from_secs(30)), None);
+        // the first alert has left the window
+        assert_eq!(
+            limiter.try_acquire(start + Duration::from_secs(61)),
+            Some(2)
+        );
+        assert_eq!(limiter.try_acquire(start + Duration::from_secs(62)), None);
+    }
+}
@@ -0,0 +1,638 @@
+use crate::{
+    analysis::{
+        backrun_gas,
+        latency::simulate_delay,
+        stats::{format_date, format_timestamp, ESTIMATED_BACKRUN_GAS},
+        to_decimal,
+    },
+    config::ChainConfig,
+    data::EXPORT_DIR,
+    hindsight::Hindsight,
+    info,
+    interfaces::SimArbResultBatch,
+    log_error,
+    util::WsClient,
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// What a paper-traded backrun is assumed to cost, on top of the profit found by the simulation.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperAssumptions {
+    /// Priority fee paid on top of the base fee, in gwei.
+    pub tip_gwei: f64,
+    /// Share of the profit left after gas that's paid to the builder to land the backrun (0 to 100).
+    pub bribe_percent: f64,
+    /// Blocks it takes the bot to react. With 0, each backrun is taken as simulated;
+    /// otherwise it's re-simulated on the state this many blocks later.
+    pub latency_blocks: u64,
+    /// Milliseconds from the bot receiving an event to its backrun reaching the builder. If set, each backrun is
+    /// delayed by the blocks it would've missed given when its event was emitted and the observed block times,
+    /// instead of by `latency_blocks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+/// Number of blocks whose average block time is used to tell which block a late backrun would've landed in.
+pub const BLOCK_TIME_WINDOW: u64 = 32;
+
+impl PaperAssumptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.tip_gwei < 0.0 {
+            return Err(anyhow::format_err!("paper tip must be >= 0"));
+        }
+        if !(0.0..=100.0).contains(&self.bribe_percent) {
+            return Err(anyhow::format_err!("paper bribe must be between 0 and 100"));
+        }
+        if self.latency_ms.is_some() && self.latency_blocks > 0 {
+            return Err(anyhow::format_err!(
+                "paper latency can be given in blocks or milliseconds, not both"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The trade a bot would've made on `arb` for a `gross_profit` (in the chain's native token),
+    /// or None if it wouldn't have been profitable after gas.
+    pub fn trade(&self, arb: &SimArbResultBatch, gross_profit: U256) -> Option<PaperTrade> {
+        let gas_price =
+            arb.base_fee.unwrap_or_default() + U256::from((self.tip_gwei * 1e9) as u128);
+        let gas_cost = gas_price * backrun_gas(arb, ESTIMATED_BACKRUN_GAS);
+        if gross_profit <= gas_cost {
+            return None;
+        }
+        let bribe = (gross_profit - gas_cost) * U256::from((self.bribe_percent * 100.0) as u64)
+            / U256::from(10_000);
+        Some(PaperTrade {
+            tx_hash: arb.event.hint.hash,
+            chain_id: arb.chain_id,
+            block: arb.event.block,
+            timestamp: arb.event.timestamp,
+            gross_profit,
+            gas_cost,
+            bribe,
+            net_profit: gross_profit - gas_cost - bribe,
+            delay_blocks: 0,
+        })
+    }
+}
+
+/// A backrun that a bot would've sent, with its costs. Amounts are in the chain's native token (wei).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperTrade {
+    /// Hash of the user's tx that was backrun.
+    pub tx_hash: H256,
+    pub chain_id: u64,
+    pub block: u64,
+    pub timestamp: u64,
+    pub gross_profit: U256,
+    pub gas_cost: U256,
+    pub bribe: U256,
+    pub net_profit: U256,
+    /// Blocks after the user's tx that the backrun landed; 0 if it made the user's block.
+    #[serde(default)]
+    pub delay_blocks: u64,
+}
+
+/// Blocks by which a backrun misses its user's block, if the bot receives the user's tx at `event_timestamp`
+/// (seconds) and takes `latency_ms` to get the backrun to the builder. The user's block, stamped at
+/// `block_timestamp`, takes bundles until its slot starts; each block after it takes `block_time_ms` longer.
+pub fn missed_blocks(
+    event_timestamp: u64,
+    block_timestamp: u64,
+    latency_ms: u64,
+    block_time_ms: u64,
+) -> u64 {
+    let arrival = event_timestamp * 1000 + latency_ms;
+    let deadline = block_timestamp * 1000;
+    if arrival <= deadline {
+        return 0;
+    }
+    (arrival - deadline).div_ceil(block_time_ms.max(1))
+}
+
+async fn block_timestamp(client: &WsClient, block: u64) -> Result<u64> {
+    client
+        .get_block(block)
+        .await?
+        .map(|block| block.timestamp.as_u64())
+        .ok_or(anyhow::format_err!("block {} not found", block))
+}
+
+/// Blocks by which a bot with `latency_ms` of latency would've missed `arb`'s user tx (see `missed_blocks`),
+/// using the average block time of the `BLOCK_TIME_WINDOW` blocks up to the user's. Returns None if the tx
+/// hasn't landed.
+pub async fn latency_delay(
+    client: &WsClient,
+    arb: &SimArbResultBatch,
+    latency_ms: u64,
+) -> Result<Option<u64>> {
+    let user_block = match client
+        .get_transaction(arb.event.hint.hash)
+        .await?
+        .and_then(|tx| tx.block_number)
+    {
+        Some(block) => block.as_u64(),
+        None => return Ok(None),
+    };
+    let user_timestamp = block_timestamp(client, user_block).await?;
+    let window = BLOCK_TIME_WINDOW.min(user_block);
+    let window_start = block_timestamp(client, user_block - window).await?;
+    let block_time_ms = match window {
+        0 => 12_000,
+        _ => user_timestamp.saturating_sub(window_start) * 1000 / window,
+    };
+    Ok(Some(missed_blocks(
+        arb.event.timestamp,
+        user_timestamp,
+        latency_ms,
+        block_time_ms,
+    )))
+}
+
+/// Hypothetical PnL of a bot trading every opportunity that a live scan finds, since the session started.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperLedger {
+    pub session: String,
+    /// Unix timestamps of when the session started & was last updated.
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub assumptions: PaperAssumptions,
+    /// Number of arbs profitable (before costs) in the native token.
+    pub num_opportunities: u64,
+    /// Number of opportunities that weren't profitable after latency & gas.
+    pub num_unprofitable: u64,
+    /// Number of profitable arbs in other tokens, whose costs can't be compared.
+    pub num_other_tokens: u64,
+    /// Number of opportunities that couldn't be re-simulated with latency.
+    pub num_failed: u64,
+    /// Number of opportunities that the bot would've reached after the user's block, whose backruns were
+    /// re-simulated on later blocks' state.
+    #[serde(default)]
+    pub num_late: u64,
+    pub trades: Vec<PaperTrade>,
+}
+
+impl PaperLedger {
+    pub fn new(session: &str, assumptions: PaperAssumptions, now: u64) -> Self {
+        Self {
+            session: session.to_owned(),
+            started_at: now,
+            updated_at: now,
+            assumptions,
+            num_opportunities: 0,
+            num_unprofitable: 0,
+            num_other_tokens: 0,
+            num_failed: 0,
+            num_late: 0,
+            trades: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?))
+    }
+
+    /// Writes the ledger to a temp file first, so that a crash mid-write doesn't lose the session.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Records a paper trade of `gross_profit` on `arb`, landed `delay_blocks` after the user's tx, if it's
+    /// profitable after costs and the same tx wasn't traded already (e.g. by a scan that resumed over it).
+    pub fn add(&mut self, arb: &SimArbResultBatch, gross_profit: U256, delay_blocks: u64) {
+        if self
+            .trades
+            .iter()
+            .any(|trade| trade.tx_hash == arb.event.hint.hash)
+        {
+            return;
+        }
+        self.num_opportunities += 1;
+        if delay_blocks > 0 {
+            self.num_late += 1;
+        }
+        match self.assumptions.trade(arb, gross_profit) {
+            Some(trade) => self.trades.push(PaperTrade {
+                delay_blocks,
+                ..trade
+            }),
+            None => self.num_unprofitable += 1,
+        }
+    }
+}
+
+/// Where `session`'s ledger is saved.
+pub fn ledger_path(session: &str) -> PathBuf {
+    PathBuf::from(format!("{}/paper_{}.json", EXPORT_DIR, session))
+}
+
+/// Keeps a session's ledger up to date with the arbs found by a scan, saving it after each batch.
+#[derive(Debug)]
+pub struct PaperTrader {
+    path: PathBuf,
+    ledger: Mutex<PaperLedger>,
+}
+
+impl PaperTrader {
+    /// Resumes `session` if its ledger exists, or starts it. Resuming with different assumptions fails,
+    /// since the ledger's trades would've been costed differently.
+    pub fn open(session: &str, assumptions: PaperAssumptions) -> Result<Self> {
+        assumptions.validate()?;
+        let path = ledger_path(session);
+        let ledger = match PaperLedger::load(&path)? {
+            Some(ledger) => {
+                if ledger.assumptions != assumptions {
+                    return Err(anyhow::format_err!(
+                        "paper session {:?} was started with different assumptions: {:?}",
+                        session,
+                        ledger.assumptions
+                    ));
+                }
+                info!(
+                    "resuming paper session {:?} with {} trades",
+                    session,
+                    ledger.trades.len()
+                );
+                ledger
+            }
+            None => PaperLedger::new(session, assumptions, now()),
+        };
+        Ok(Self {
+            path,
+            ledger: Mutex::new(ledger),
+        })
+    }
+
+    /// Paper-trades the profitable arbs of `arbs`, re-simulating them with the assumed latency first.
+    /// With `latency_ms`, only backruns that would've missed the user's block are re-simulated, on the block
+    /// they'd have landed in. Failures are logged; the ledger shouldn't stop the scan.
+    pub async fn record(
+        &self,
+        arbs: &[SimArbResultBatch],
+        chain: &ChainConfig,
+        hindsight: &Hindsight,
+    ) {
+        let assumptions = self.ledger.lock().unwrap().assumptions.to_owned();
+        let mut profits = vec![];
+        let mut num_other_tokens = 0;
+        let mut num_failed = 0;
+        for arb in arbs.iter().filter(|arb| !arb.max_profit.is_zero()) {
+            if arb.profit_token != chain.wrapped_native {
+                num_other_tokens += 1;
+                continue;
+            }
+            let delay = match assumptions.latency_ms {
+                Some(latency_ms) => latency_delay(&hindsight.client, arb, latency_ms).await,
+                None => Ok(Some(assumptions.latency_blocks)),
+            };
+            let delay = match delay {
+                Ok(Some(0)) => {
+                    profits.push((arb, arb.max_profit, 0));
+                    continue;
+                }
+                Ok(Some(delay)) => delay,
+                Ok(None) => {
+                    num_failed += 1;
+                    continue;
+                }
+                Err(err) => {
+                    log_error!(
+                        "failed to time the paper trade of {:?}: {}",
+                        arb.event.hint.hash,
+                        err
+                    );
+                    num_failed += 1;
+                    continue;
+                }
+            };
+            match simulate_delay(
+                &hindsight.client,
+                arb,
+                &hindsight.quote_assets,
+                delay,
+                &hindsight.pool_adapters,
+            )
+            .await
+            {
+                Ok(Some(profit)) => profits.push((arb, profit, delay)),
+                Ok(None) => num_failed += 1,
+                Err(err) => {
+                    log_error!(
+                        "failed to paper-trade {:?} with latency: {}",
+                        arb.event.hint.hash,
+                        err
+                    );
+                    num_failed += 1;
+                }
+            }
+        }
+
+        let mut ledger = self.ledger.lock().unwrap();
+        for (arb, profit, delay) in profits {
+            ledger.add(arb, profit, delay);
+        }
+        ledger.num_other_tokens += num_other_tokens;
+        ledger.num_failed += num_failed;
+        ledger.updated_at = now();
+        if let Err(err) = ledger.save(&self.path) {
+            log_error!("failed to save paper ledger to {:?}: {}", self.path, err);
+        }
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Paper trades of one day, in whole native tokens.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperDay {
+    /// UTC date, e.g. "2023-09-18".
+    pub day: String,
+    pub num_trades: u64,
+    pub net_profit: f64,
+}
+
+/// Paper trades on one chain, in whole native tokens.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperChainPnl {
+    pub chain_id: u64,
+    pub num_trades: u64,
+    pub gross_profit: f64,
+    pub gas_cost: f64,
+    pub bribes: f64,
+    pub net_profit: f64,
+    pub days: Vec<PaperDay>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperSummary {
+    pub session: String,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub assumptions: PaperAssumptions,
+    pub num_opportunities: u64,
+    pub num_unprofitable: u64,
+    pub num_other_tokens: u64,
+    pub num_failed: u64,
+    pub num_late: u64,
+    pub chains: Vec<PaperChainPnl>,
+}
+
+/// Totals `ledger`'s trades by chain, and by day within each chain.
+pub fn summarize(ledger: &PaperLedger) -> PaperSummary {
+    let mut chains: BTreeMap<u64, Vec<&PaperTrade>> = BTreeMap::new();
+    for trade in &ledger.trades {
+        chains.entry(trade.chain_id).or_default().push(trade);
+    }
+    let sum = |trades: &[&PaperTrade], amount: fn(&PaperTrade) -> U256| {
+        to_decimal(
+            trades
+                .iter()
+                .fold(U256::zero(), |total, trade| total + amount(trade)),
+            18,
+        )
+    };
+    let chains = chains
+        .into_iter()
+        .map(|(chain_id, trades)| {
+            let mut days: BTreeMap<String, Vec<&PaperTrade>> = BTreeMap::new();
+            for trade in &trades {
+                days.entry(format_date(trade.timestamp))
+                    .or_default()
+                    .push(trade);
+            }
+            PaperChainPnl {
+                chain_id,
+                num_trades: trades.len() as u64,
+                gross_profit: sum(&trades, |trade| trade.gross_profit),
+                gas_cost: sum(&trades, |trade| trade.gas_cost),
+                bribes: sum(&trades, |trade| trade.bribe),
+                net_profit: sum(&trades, |trade| trade.net_profit),
+                days: days
+                    .into_iter()
+                    .map(|(day, trades)| PaperDay {
+                        day,
+                        num_trades: trades.len() as u64,
+                        net_profit: sum(&trades, |trade| trade.net_profit),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    PaperSummary {
+        session: ledger.session.to_owned(),
+        started_at: ledger.started_at,
+        updated_at: ledger.updated_at,
+        assumptions: ledger.assumptions.to_owned(),
+        num_opportunities: ledger.num_opportunities,
+        num_unprofitable: ledger.num_unprofitable,
+        num_other_tokens: ledger.num_other_tokens,
+        num_failed: ledger.num_failed,
+        num_late: ledger.num_late,
+        chains,
+    }
+}
+
+/// Renders `summary` as plain text, with a table of daily net profits per chain.
+pub fn render_table(summary: &PaperSummary) -> String {
+    let assumptions = &summary.assumptions;
+    let mut table = format!(
+        "paper session {:?}: {} to {}\n",
+        summary.session,
+        format_timestamp(summary.started_at),
+        format_timestamp(summary.updated_at)
+    );
+    let latency = match assumptions.latency_ms {
+        Some(latency_ms) => format!("{}ms", latency_ms),
+        None => format!("{} blocks", assumptions.latency_blocks),
+    };
+    table.push_str(&format!(
+        "assuming a {} gwei tip, {}% bribe, {} of latency\n",
+        assumptions.tip_gwei, assumptions.bribe_percent, latency
+    ));
+    table.push_str(&format!(
+        "{} opportunities: {} unprofitable after costs, {} failed to re-simulate ({} in other tokens skipped)\n",
+        summary.num_opportunities,
+        summary.num_unprofitable,
+        summary.num_failed,
+        summary.num_other_tokens
+    ));
+    if summary.num_late > 0 {
+        table.push_str(&format!(
+            "{} opportunities reached after the user's block, traded on later blocks\n",
+            summary.num_late
+        ));
+    }
+    for chain in &summary.chains {
+        table.push_str(&format!(
+            "\nchain {}: {} trades, {:.6} gross - {:.6} gas - {:.6} bribes = {:.6} net\n",
+            chain.chain_id,
+            chain.num_trades,
+            chain.gross_profit,
+            chain.gas_cost,
+            chain.bribes,
+            chain.net_profit
+        ));
+        table.push_str(&format!(
+            "{:>12} {:>8} {:>16}\n",
+            "day", "trades", "net profit"
+        ));
+        for day in &chain.days {
+            table.push_str(&format!(
+                "{:>12} {:>8} {:>16.6}\n",
+                day.day, day.num_trades, day.net_profit
+            ));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assumptions() -> PaperAssumptions {
+        PaperAssumptions {
+            tip_gwei: 1.0,
+            bribe_percent: 50.0,
+            latency_blocks: 0,
+            latency_ms: None,
+        }
+    }
+
+    fn opportunity(tx: u64, timestamp: u64, profit_gwei: u64) -> SimArbResultBatch {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.hint.hash = H256::from_low_u64_be(tx);
+        arb.event.timestamp = timestamp;
+        arb.max_profit = U256::from(profit_gwei) * U256::exp10(9);
+        arb.base_fee = Some(U256::from(9) * U256::exp10(9));
+        arb
+    }
+
+    #[test]
+    fn it_costs_paper_trades() {
+        // 10 gwei gas price * 300k gas = 3M gwei
+        let trade = assumptions()
+            .trade(
+                &opportunity(1, 0, 5_000_000),
+                U256::from(5_000_000) * U256::exp10(9),
+            )
+            .expect("profitable after gas");
+        let gwei = |amount: u64| U256::from(amount) * U256::exp10(9);
+        assert_eq!(trade.gas_cost, gwei(3_000_000));
+        assert_eq!(trade.bribe, gwei(1_000_000));
+        assert_eq!(trade.net_profit, gwei(1_000_000));
+
+        let mut ledger = PaperLedger::new("test", assumptions(), 0);
+        ledger.add(&opportunity(1, 0, 5_000_000), gwei(5_000_000), 0);
+        // resumed over the same tx
+        ledger.add(&opportunity(1, 0, 5_000_000), gwei(5_000_000), 0);
+        ledger.add(&opportunity(2, 0, 2_000_000), gwei(2_000_000), 0);
+        ledger.add(&opportunity(3, 0, 5_000_000), gwei(5_000_000), 2);
+        assert_eq!(ledger.trades.len(), 2);
+        assert_eq!(ledger.trades[1].delay_blocks, 2);
+        assert_eq!(
+            (
+                ledger.num_opportunities,
+                ledger.num_unprofitable,
+                ledger.num_late
+            ),
+            (3, 1, 1)
+        );
+
+        assert!(PaperAssumptions {
+            bribe_percent: 101.0,
+            ..assumptions()
+        }
+        .validate()
+        .is_err());
+        assert!(PaperAssumptions {
+            latency_blocks: 1,
+            latency_ms: Some(500),
+            ..assumptions()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn it_summarizes_paper_sessions() -> Result<()> {
+        let mut ledger = PaperLedger::new("test", assumptions(), 1_695_000_000);
+        let day = 86_400;
+        for (tx, timestamp) in [
+            (1, 1_695_000_000),
+            (2, 1_695_000_000 + day),
+            (3, 1_695_000_000 + day),
+        ] {
+            let arb = opportunity(tx, timestamp, 5_000_000);
+            ledger.add(&arb, arb.max_profit, 0);
+        }
+
+        let path = std::env::temp_dir().join(format!("paper_{}.json", std::process::id()));
+        ledger.save(&path)?;
+        let loaded = PaperLedger::load(&path)?.expect("ledger was saved");
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded, ledger);
+
+        let summary = summarize(&loaded);
+        assert_eq!(summary.chains.len(), 1);
+        let chain = &summary.chains[0];
+        assert_eq!(chain.num_trades, 3);
+        assert!((chain.net_profit - 0.003).abs() < 1e-9);
+        assert_eq!(chain.days.len(), 2);
+        assert_eq!(chain.days[1].num_trades, 2);
+        assert!(render_table(&summary).contains("3 trades"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_times_late_backruns() {
+        // event at t=100s, user's block stamped t=101s, 12s blocks
+        assert_eq!(missed_blocks(100, 101, 0, 12_000), 0);
+        assert_eq!(missed_blocks(100, 101, 1_000, 12_000), 0);
+        assert_eq!(missed_blocks(100, 101, 1_001, 12_000), 1);
+        assert_eq!(missed_blocks(100, 101, 13_000, 12_000), 1);
+        assert_eq!(missed_blocks(100, 101, 13_001, 12_000), 2);
+        // 2s blocks (e.g. an L2) are missed faster
+        assert_eq!(missed_blocks(100, 101, 6_000, 2_000), 3);
+    }
+
+    #[test]
+    fn it_resumes_ledgers_from_before_latency_ms() -> Result<()> {
+        let ledger = PaperLedger::new("test", assumptions(), 0);
+        let mut json = serde_json::to_value(&ledger)?;
+        json.as_object_mut().unwrap().remove("numLate");
+        let loaded: PaperLedger = serde_json::from_value(json)?;
+        assert_eq!(loaded, ledger);
+        Ok(())
+    }
+}
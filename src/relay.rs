@@ -0,0 +1,540 @@
+use crate::{
+    analysis::stats::format_date,
+    config::ChainConfig,
+    data::EXPORT_DIR,
+    executor::{backrun_calldata, backrun_route, swap_leg, Swap},
+    info,
+    interfaces::{SimArbResult, SimArbResultBatch},
+    log_error,
+    signer::Signer,
+    util::WsClient,
+    Result,
+};
+use ethers::{
+    abi::AbiDecode,
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockNumber, Bytes, Eip1559TransactionRequest,
+        H256, U256,
+    },
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Most blocks that an opportunity's event may be behind the chain's head for its arb to be sent.
+pub const MAX_SEND_AGE_BLOCKS: u64 = 2;
+
+/// Caps on what `scan --send-arbs` may spend. They apply to the one chain that's scanned (see `check_single_chain`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct SendLimits {
+    /// Most gas (in the chain's whole native tokens) that the bundles submitted on one UTC day may cost, if they all
+    /// land.
+    pub daily_limit: f64,
+    /// Priority fee paid on top of the base fee, in gwei.
+    pub tip_gwei: f64,
+}
+
+impl SendLimits {
+    pub fn validate(&self) -> Result<()> {
+        if self.daily_limit.is_nan() || self.daily_limit <= 0.0 {
+            return Err(anyhow::format_err!("send-arbs daily limit must be > 0"));
+        }
+        if self.tip_gwei.is_nan() || self.tip_gwei < 0.0 {
+            return Err(anyhow::format_err!("send-arbs tip must be >= 0"));
+        }
+        Ok(())
+    }
+
+    /// The daily limit in wei.
+    pub fn daily_limit_wei(&self) -> U256 {
+        U256::from((self.daily_limit * 1e18) as u128)
+    }
+}
+
+/// A bundle of one arb tx that was submitted to at least one relay. Amounts are in the chain's native token (wei).
+///
+/// The bundle doesn't hold the user's tx: it's sent after that tx landed, to arb what's left of the price gap.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmittedBundle {
+    /// Hash of the user's tx whose opportunity was arbed.
+    pub user_tx: H256,
+    #[serde(alias = "backrunTx")]
+    pub arb_tx: H256,
+    pub target_block: u64,
+    pub amount_in: U256,
+    /// Profit of the executor's call on the latest state, before gas.
+    pub expected_profit: U256,
+    /// Gas limit times max fee; what the bundle costs at most if it lands.
+    pub max_cost: U256,
+    /// Relays that accepted the bundle.
+    pub relays: Vec<String>,
+    /// Unix timestamp of the submission.
+    pub submitted_at: u64,
+}
+
+/// Bundles submitted on one chain, and what they may cost by UTC day.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendLedger {
+    /// Sum of the submitted bundles' `max_cost`, by UTC date (e.g. "2023-09-18").
+    pub spent: BTreeMap<String, U256>,
+    pub bundles: Vec<SubmittedBundle>,
+}
+
+impl SpendLedger {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the ledger to a temp file first, so that a crash mid-write doesn't forget what was spent.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn spent_on(&self, day: &str) -> U256 {
+        self.spent.get(day).copied().unwrap_or_default()
+    }
+
+    /// Whether a bundle that costs up to `cost` can be submitted on `day` without exceeding `limit`.
+    pub fn can_spend(&self, day: &str, cost: U256, limit: U256) -> bool {
+        self.spent_on(day).saturating_add(cost) <= limit
+    }
+
+    /// Counts `bundle`'s max cost against the day it was submitted on, whether it lands or not.
+    pub fn record(&mut self, bundle: SubmittedBundle) {
+        *self
+            .spent
+            .entry(format_date(bundle.submitted_at))
+            .or_default() += bundle.max_cost;
+        self.bundles.push(bundle);
+    }
+}
+
+/// Fails if arbs would be sent on more than one chain. The daily limit is in one chain's native token, and each chain
+/// keeps its own ledger, so firing on several chains would spend the limit once per chain.
+pub fn check_single_chain(chains: &[ChainConfig]) -> Result<()> {
+    if chains.len() > 1 {
+        return Err(anyhow::format_err!(
+            "--send-arbs scans one chain at a time, since its daily limit is in that chain's native token; set CHAINS to one of {}",
+            chains
+                .iter()
+                .map(|chain| chain.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Where `chain_id`'s spend ledger is saved.
+pub fn ledger_path(chain_id: u64) -> PathBuf {
+    PathBuf::from(format!("{}/sent_arbs_{}.json", EXPORT_DIR, chain_id))
+}
+
+/// Where `chain_id`'s spend ledger used to be saved; read if there's none at `ledger_path` yet, so the day's spend carries over.
+fn legacy_ledger_path(chain_id: u64) -> PathBuf {
+    PathBuf::from(format!("{}/live_fire_{}.json", EXPORT_DIR, chain_id))
+}
+
+/// Executor route of `result`'s backrun: every hop of a multi-pool backrun, otherwise a swap on the start pool and
+/// one back on the end pool.
+pub fn executor_route(result: &SimArbResult) -> Result<Vec<Swap>> {
+    let backrun = &result.backrun_trade;
+    if !backrun.path.is_empty() {
+        return backrun
+            .path
+            .iter()
+            .map(|hop| swap_leg(hop.pool, &hop.variant, hop.token_in, hop.token_out))
+            .collect();
+    }
+    let tokens = &result.user_trade.tokens;
+    backrun_route(
+        (backrun.start_pool, &backrun.start_variant),
+        (backrun.end_pool, &backrun.end_variant),
+        tokens.quote,
+        tokens.token,
+    )
+}
+
+/// Body of an `eth_sendBundle` request of `raw_txs`, to land in `block`.
+pub fn bundle_request(raw_txs: &[Bytes], block: u64) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_sendBundle",
+        "params": [{
+            "txs": raw_txs,
+            "blockNumber": format!("{:#x}", block),
+        }],
+    })
+    .to_string()
+}
+
+/// `X-Flashbots-Signature` header of a relay request with `body`: the signer's address & its signature of the
+/// body's hash.
+pub async fn flashbots_signature(signer: &Signer, body: &str) -> Result<String> {
+    let message = format!("{:?}", H256::from(keccak256(body)));
+    let signature = signer.sign_message(message.as_bytes()).await?;
+    Ok(format!("{:?}:0x{}", signer.address(), signature))
+}
+
+/// Posts a signed bundle request to the relay at `url`.
+pub async fn send_bundle(url: &str, body: &str, signature: &str) -> Result<()> {
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Flashbots-Signature", signature)
+        .body(body.to_owned())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    match response.get("error") {
+        Some(error) => Err(anyhow::format_err!("relay rejected the bundle: {}", error)),
+        None => Ok(()),
+    }
+}
+
+/// Signs & submits arbs of the opportunities that a live scan finds to each chain's relays, through the
+/// chain's executor (see `deploy-executor`).
+///
+/// These aren't backruns: the scan only simulates txs that have landed, so each arb is sent on its own for the next
+/// block, to take what's left of the price gap that the user's trade opened. Nothing is sent unless the executor's
+/// call is still profitable after gas on the latest state, and the day's bundles stay within the spend limit.
+#[derive(Debug)]
+pub struct ArbSender {
+    signer: Signer,
+    limits: SendLimits,
+    ledgers: Mutex<HashMap<u64, SpendLedger>>,
+}
+
+impl ArbSender {
+    pub fn new(signer: Signer, limits: SendLimits) -> Result<Self> {
+        limits.validate()?;
+        Ok(Self {
+            signer,
+            limits,
+            ledgers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fails unless arbs can be sent on `chain`: the scan follows new events, and the chain has an executor & relays.
+    pub fn check_chain(&self, chain: &ChainConfig, following_head: bool) -> Result<()> {
+        if !following_head {
+            return Err(anyhow::format_err!(
+                "--send-arbs only sends while following new events; drop --block-end & --timestamp-end"
+            ));
+        }
+        if chain.executor.is_none() {
+            return Err(anyhow::format_err!(
+                "--send-arbs needs an executor on chain '{}'; deploy one with deploy-executor or set {}",
+                chain.name,
+                chain.executor_var()
+            ));
+        }
+        if chain.relay_urls.is_empty() {
+            return Err(anyhow::format_err!(
+                "--send-arbs needs relays on chain '{}'; set RELAY_URLS_{}",
+                chain.name,
+                chain.name.to_uppercase()
+            ));
+        }
+        // a corrupt ledger should stop the scan before anything is sent
+        self.ledger(chain.chain_id)?;
+        Ok(())
+    }
+
+    /// Sends the profitable arbs of `arbs` that are quoted in `chain`'s wrapped native token. Skips & failures
+    /// are logged; sending arbs shouldn't stop the scan.
+    pub async fn send(&self, arbs: &[SimArbResultBatch], chain: &ChainConfig, client: &WsClient) {
+        for arb in arbs
+            .iter()
+            .filter(|arb| !arb.max_profit.is_zero() && arb.profit_token == chain.wrapped_native)
+        {
+            if let Err(err) = self.send_arb(arb, chain, client).await {
+                log_error!(
+                    "[{}] failed to send the arb of {:?}: {}",
+                    chain.name,
+                    arb.event.hint.hash,
+                    err
+                );
+            }
+        }
+    }
+
+    async fn send_arb(
+        &self,
+        arb: &SimArbResultBatch,
+        chain: &ChainConfig,
+        client: &WsClient,
+    ) -> Result<()> {
+        let user_tx = arb.event.hint.hash;
+        let executor = chain
+            .executor
+            .ok_or(anyhow::format_err!("no executor configured"))?;
+        let Some(result) = arb.best_result() else {
+            return Ok(());
+        };
+        let route = executor_route(result)?;
+        let amount_in = result.backrun_trade.amount_in;
+
+        let latest = client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(anyhow::format_err!("latest block not found"))?;
+        let latest_number = latest.number.unwrap_or_default().as_u64();
+        if latest_number.saturating_sub(arb.event.block) > MAX_SEND_AGE_BLOCKS {
+            info!(
+                "[{}] not sending the arb of {:?}: its event is {} blocks old",
+                chain.name,
+                user_tx,
+                latest_number - arb.event.block
+            );
+            return Ok(());
+        }
+        let tip = U256::from((self.limits.tip_gwei * 1e9) as u128);
+        // leaves room for the base fee to rise by the most it can in one block
+        let max_fee = latest.base_fee_per_gas.unwrap_or_default() * 9 / 8 + tip;
+        let arb_tx = |min_profit: U256| -> TypedTransaction {
+            Eip1559TransactionRequest::new()
+                .from(self.signer.address())
+                .to(executor)
+                .data(backrun_calldata(amount_in, route.to_owned(), min_profit))
+                .max_fee_per_gas(max_fee)
+                .max_priority_fee_per_gas(tip)
+                .chain_id(chain.chain_id)
+                .into()
+        };
+
+        // simulate first: the arb must still pay for its gas on the latest state
+        let probe = arb_tx(U256::zero());
+        let expected_profit = match client.call(&probe, None).await {
+            Ok(output) => U256::decode(output)?,
+            Err(err) => {
+                info!(
+                    "[{}] not sending the arb of {:?}: the executor's call reverts ({})",
+                    chain.name, user_tx, err
+                );
+                return Ok(());
+            }
+        };
+        let gas_limit = client.estimate_gas(&probe, None).await? * 6 / 5;
+        let max_cost = gas_limit * max_fee;
+        if expected_profit <= max_cost {
+            info!(
+                "[{}] not sending the arb of {:?}: it makes {} wei but may cost {} wei of gas",
+                chain.name, user_tx, expected_profit, max_cost
+            );
+            return Ok(());
+        }
+        let now = now();
+        let day = format_date(now);
+        let limit = self.limits.daily_limit_wei();
+        if !self
+            .ledger(chain.chain_id)?
+            .can_spend(&day, max_cost, limit)
+        {
+            info!(
+                "[{}] not sending the arb of {:?}: it would exceed the daily limit of {} on {}",
+                chain.name, user_tx, self.limits.daily_limit, day
+            );
+            return Ok(());
+        }
+
+        // the executor reverts unless the arb still covers its gas when it lands
+        let mut tx = arb_tx(max_cost);
+        tx.set_gas(gas_limit);
+        tx.set_nonce(
+            client
+                .get_transaction_count(self.signer.address(), Some(BlockNumber::Pending.into()))
+                .await?,
+        );
+        let signature = self.signer.sign_transaction(&tx).await?;
+        let arb_hash = tx.hash(&signature);
+        let target_block = latest_number + 1;
+        let body = bundle_request(&[tx.rlp_signed(&signature)], target_block);
+        let header = flashbots_signature(&self.signer, &body).await?;
+        let mut relays = vec![];
+        for url in &chain.relay_urls {
+            match send_bundle(url, &body, &header).await {
+                Ok(()) => relays.push(url.to_owned()),
+                Err(err) => log_error!("[{}] relay {} failed: {}", chain.name, url, err),
+            }
+        }
+        if relays.is_empty() {
+            return Err(anyhow::format_err!("no relay accepted the bundle"));
+        }
+        info!(
+            "[{}] sent arb {:?} of {:?} for block {} to {} relays",
+            chain.name,
+            arb_hash,
+            user_tx,
+            target_block,
+            relays.len()
+        );
+        let mut ledgers = self.ledgers.lock().unwrap();
+        let ledger = ledgers.entry(chain.chain_id).or_default();
+        ledger.record(SubmittedBundle {
+            user_tx,
+            arb_tx: arb_hash,
+            target_block,
+            amount_in,
+            expected_profit,
+            max_cost,
+            relays,
+            submitted_at: now,
+        });
+        ledger.save(&ledger_path(chain.chain_id))
+    }
+
+    /// `chain_id`'s spend ledger, loaded from disk the first time it's needed.
+    fn ledger(&self, chain_id: u64) -> Result<SpendLedger> {
+        let mut ledgers = self.ledgers.lock().unwrap();
+        if let Entry::Vacant(entry) = ledgers.entry(chain_id) {
+            let mut path = ledger_path(chain_id);
+            if !path.exists() {
+                path = legacy_ledger_path(chain_id);
+            }
+            entry.insert(SpendLedger::load(&path)?);
+        }
+        Ok(ledgers[&chain_id].to_owned())
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interfaces::{PoolVariant, RouteHop},
+        signer::SignerConfig,
+    };
+    use ethers::types::{Address, Signature};
+
+    fn bundle(submitted_at: u64, max_cost: u64) -> SubmittedBundle {
+        SubmittedBundle {
+            user_tx: H256::from_low_u64_be(1),
+            arb_tx: H256::from_low_u64_be(2),
+            target_block: 18_000_001,
+            amount_in: U256::exp10(18),
+            expected_profit: U256::from(max_cost * 2),
+            max_cost: U256::from(max_cost),
+            relays: vec!["https://relay.flashbots.net".to_owned()],
+            submitted_at,
+        }
+    }
+
+    #[test]
+    fn it_limits_spend_per_day() {
+        let limit = U256::from(100);
+        let mut ledger = SpendLedger::default();
+        // 2023-09-18 12:00 UTC
+        ledger.record(bundle(1_695_038_400, 60));
+        assert_eq!(ledger.spent_on("2023-09-18"), U256::from(60));
+        assert!(ledger.can_spend("2023-09-18", U256::from(40), limit));
+        assert!(!ledger.can_spend("2023-09-18", U256::from(41), limit));
+        // the next day starts over
+        assert!(ledger.can_spend("2023-09-19", U256::from(100), limit));
+        ledger.record(bundle(1_695_038_400 + 3600, 40));
+        assert!(!ledger.can_spend("2023-09-18", U256::from(1), limit));
+        // ledgers saved before arbs were told apart from backruns still load
+        let legacy = serde_json::to_string(&ledger)
+            .unwrap()
+            .replace("arbTx", "backrunTx");
+        assert_eq!(
+            serde_json::from_str::<SpendLedger>(&legacy).unwrap(),
+            ledger
+        );
+
+        assert!(SendLimits {
+            daily_limit: 0.0,
+            tip_gwei: 1.0
+        }
+        .validate()
+        .is_err());
+        assert_eq!(
+            SendLimits {
+                daily_limit: 0.5,
+                tip_gwei: 1.0
+            }
+            .daily_limit_wei(),
+            U256::exp10(17) * 5
+        );
+    }
+
+    #[tokio::test]
+    async fn it_signs_relay_requests() -> Result<()> {
+        let key = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+        let signer = Signer::load(&SignerConfig::PrivateKey(key.to_owned()), 1).await?;
+        let body = bundle_request(&[Bytes::from(vec![0x02, 0xf8])], 18_000_001);
+        let request: serde_json::Value = serde_json::from_str(&body)?;
+        assert_eq!(request["method"], "eth_sendBundle");
+        assert_eq!(request["params"][0]["txs"][0], "0x02f8");
+        assert_eq!(request["params"][0]["blockNumber"], "0x112a881");
+
+        let header = flashbots_signature(&signer, &body).await?;
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address.parse::<Address>()?, signer.address());
+        let signature = signature.parse::<Signature>()?;
+        let message = format!("{:?}", H256::from(keccak256(&body)));
+        assert_eq!(signature.recover(message)?, signer.address());
+        Ok(())
+    }
+
+    #[test]
+    fn it_routes_backruns_through_the_executor() -> Result<()> {
+        let mut result = SimArbResult::test_example(
+            Address::from_low_u64_be(0x70),
+            U256::exp10(18),
+            U256::exp10(16),
+        );
+        let tokens = result.user_trade.tokens.to_owned();
+        let route = executor_route(&result)?;
+        assert_eq!(route.len(), 2);
+        assert_eq!(route[0].pool, result.backrun_trade.start_pool);
+        assert_eq!(
+            (route[0].token_in, route[1].token_out),
+            (tokens.quote, tokens.quote)
+        );
+
+        let hop = |pool: u64, token_in: Address, token_out: Address| RouteHop {
+            pool: Address::from_low_u64_be(pool),
+            variant: PoolVariant::UniswapV2,
+            token_in,
+            token_out,
+        };
+        let other = Address::from_low_u64_be(0xbeef);
+        result.backrun_trade.path = vec![
+            hop(1, tokens.quote, tokens.token),
+            hop(2, tokens.token, other),
+            hop(3, other, tokens.quote),
+        ];
+        let route = executor_route(&result)?;
+        assert_eq!(route.len(), 3);
+        assert_eq!(route[1].token_out, other);
+        Ok(())
+    }
+}
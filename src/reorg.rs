@@ -0,0 +1,183 @@
+use crate::{data::arbs::ArbDatabase, interfaces::SimArbResultBatch, util::WsClient, warn, Result};
+use ethers::{providers::Middleware, types::H256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Number of blocks that must be built on top of an event's block before the block its results forked from is re-checked.
+pub const REORG_CONFIRMATIONS: u64 = 3;
+
+/// Results of a live scan that wait for their blocks to be buried, so the blocks they were simulated on
+/// can be checked against the canonical chain.
+///
+/// A result whose block was reorged out is saved with `orphaned` set, so analyses can skip it.
+#[derive(Debug, Default)]
+pub struct ReorgWatch {
+    /// Results by the block of their event, waiting for `REORG_CONFIRMATIONS`.
+    pending: BTreeMap<u64, Vec<SimArbResultBatch>>,
+}
+
+impl ReorgWatch {
+    /// Watches `arbs` that recorded the block hash they were simulated on.
+    pub fn watch(&mut self, arbs: &[SimArbResultBatch]) {
+        for arb in arbs.iter().filter(|arb| arb.block_hash.is_some()) {
+            self.pending
+                .entry(arb.event.block)
+                .or_default()
+                .push(arb.to_owned());
+        }
+    }
+
+    /// Number of results waiting to be checked.
+    pub fn len(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Removes & returns the results that are buried under at least `REORG_CONFIRMATIONS` blocks at `head`.
+    pub fn take_confirmed(&mut self, head: u64) -> Vec<SimArbResultBatch> {
+        let pending = self
+            .pending
+            .split_off(&head.saturating_sub(REORG_CONFIRMATIONS).saturating_add(1));
+        std::mem::replace(&mut self.pending, pending)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// Checks the blocks of the results that are buried at `head`, saving those that were orphaned to `db`.
+    /// Returns the number of orphaned results.
+    ///
+    /// Results stay watched until their blocks were checked & the orphaned ones were saved,
+    /// so a failed request only delays their check.
+    pub async fn check(&mut self, client: &WsClient, head: u64, db: &ArbDatabase) -> Result<usize> {
+        let buried = self
+            .pending
+            .range(..=head.saturating_sub(REORG_CONFIRMATIONS))
+            .flat_map(|(_, arbs)| arbs.iter().filter_map(|arb| arb.block_hash))
+            .collect::<HashSet<_>>();
+        let mut canonical = HashMap::new();
+        for block_hash in buried {
+            canonical.insert(block_hash, is_canonical(client, block_hash).await?);
+        }
+        let mut orphaned = vec![];
+        for mut arb in self.take_confirmed(head) {
+            let Some(block_hash) = arb.block_hash else {
+                continue;
+            };
+            if canonical.get(&block_hash) == Some(&false) {
+                warn!(
+                    tx_hash = ?arb.event.hint.hash,
+                    ?block_hash,
+                    "result was simulated on a block that was reorged out"
+                );
+                arb.orphaned = true;
+                orphaned.push(arb);
+            }
+        }
+        if !orphaned.is_empty() {
+            if let Err(err) = db.write_orphaned(&orphaned).await {
+                self.watch(&orphaned);
+                return Err(err);
+            }
+        }
+        Ok(orphaned.len())
+    }
+}
+
+/// Whether the block with `block_hash` is (still) the canonical block at its height.
+pub async fn is_canonical(client: &WsClient, block_hash: H256) -> Result<bool> {
+    // nodes may forget blocks that were reorged out
+    let Some(number) = client
+        .get_block(block_hash)
+        .await?
+        .and_then(|block| block.number)
+    else {
+        return Ok(false);
+    };
+    let canonical = client.get_block(number).await?;
+    Ok(canonical.and_then(|block| block.hash) == Some(block_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::MemoryStore, mock::MockChain};
+    use ethers::types::Block;
+
+    #[test]
+    fn it_waits_for_confirmations() {
+        let arb = |block: u64, block_hash: Option<H256>| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.block = block;
+            arb.block_hash = block_hash;
+            arb
+        };
+        let hash = Some(H256::from_low_u64_be(1));
+        let mut watch = ReorgWatch::default();
+        watch.watch(&[arb(100, hash), arb(101, hash), arb(102, None)]);
+        assert_eq!(watch.len(), 2);
+
+        assert!(watch.take_confirmed(102).is_empty());
+        let confirmed = watch.take_confirmed(100 + REORG_CONFIRMATIONS);
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].event.block, 100);
+        assert_eq!(watch.take_confirmed(u64::MAX).len(), 1);
+        assert!(watch.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_checks_blocks_against_the_chain() -> Result<()> {
+        let block = |hash: u64| Block {
+            number: Some(100.into()),
+            hash: Some(H256::from_low_u64_be(hash)),
+            ..Default::default()
+        };
+        let forgotten = H256::from_low_u64_be(3);
+        // the reorged block is served by hash only, like a node that still has it
+        let client = MockChain::new(1)
+            .with_block(block(1))
+            .respond(
+                "eth_getBlockByHash",
+                (H256::from_low_u64_be(2), false),
+                block(2),
+            )
+            .respond("eth_getBlockByHash", (forgotten, false), ())
+            .serve()
+            .await?;
+        assert!(is_canonical(&client, H256::from_low_u64_be(1)).await?);
+        assert!(!is_canonical(&client, H256::from_low_u64_be(2)).await?);
+        assert!(!is_canonical(&client, forgotten).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_results_whose_blocks_failed_to_check() -> Result<()> {
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.block = 100;
+        arb.block_hash = Some(H256::from_low_u64_be(1));
+        let mut watch = ReorgWatch::default();
+        watch.watch(&[arb]);
+        let db: ArbDatabase = std::sync::Arc::new(MemoryStore::default());
+
+        // the chain serves no blocks, so the check fails
+        let client = MockChain::new(1).serve().await?;
+        assert!(watch
+            .check(&client, 100 + REORG_CONFIRMATIONS, &db)
+            .await
+            .is_err());
+        assert_eq!(watch.len(), 1);
+
+        let client = MockChain::new(1)
+            .respond("eth_getBlockByHash", (H256::from_low_u64_be(1), false), ())
+            .serve()
+            .await?;
+        assert_eq!(
+            watch.check(&client, 100 + REORG_CONFIRMATIONS, &db).await?,
+            1
+        );
+        assert!(watch.is_empty());
+        Ok(())
+    }
+}
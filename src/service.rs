@@ -0,0 +1,315 @@
+use crate::{
+    data::arbs::ArbFilterParams,
+    debug,
+    hindsight::Hindsight,
+    interfaces::SimArbResultBatch,
+    sim::processor::H256Map,
+    util::{fetch_all, get_block_info, ReceiptCache, WsClient},
+    Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Transaction, TransactionReceipt, H256},
+};
+use futures::{stream, Stream};
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Number of results a slow subscriber may fall behind by before it starts missing them.
+pub const RESULTS_CHANNEL_SIZE: usize = 1024;
+
+/// Counters of a running `SimService`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceStats {
+    /// Number of `simulate_backrun` requests.
+    pub num_requests: u64,
+    /// Number of requests that produced a result, profitable or not.
+    pub num_simulated: u64,
+    pub num_profitable: u64,
+    pub num_failed: u64,
+    /// Number of results in the processor's store, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_stored: Option<u64>,
+}
+
+/// On-demand backrun simulations, for serving to other infrastructure over the network.
+///
+/// Every result is published to the processor's results feed, so `subscribe`rs see results
+/// of all requests (and of a scan sharing the same `Hindsight`) as they're produced.
+#[derive(Clone, Debug)]
+pub struct SimService {
+    pub hindsight: Hindsight,
+    results: broadcast::Sender<SimArbResultBatch>,
+    num_requests: Arc<AtomicU64>,
+    num_simulated: Arc<AtomicU64>,
+    num_profitable: Arc<AtomicU64>,
+    num_failed: Arc<AtomicU64>,
+}
+
+impl SimService {
+    /// Serves simulations with `hindsight`, publishing results to its results feed (which is added if it has none).
+    pub fn new(hindsight: Hindsight) -> Self {
+        let results = hindsight
+            .results
+            .to_owned()
+            .unwrap_or(broadcast::channel(RESULTS_CHANNEL_SIZE).0);
+        Self {
+            hindsight: hindsight.with_results_feed(results.clone()),
+            results,
+            num_requests: Arc::new(AtomicU64::new(0)),
+            num_simulated: Arc::new(AtomicU64::new(0)),
+            num_profitable: Arc::new(AtomicU64::new(0)),
+            num_failed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Simulates backruns of the landed tx `tx_hash`, saving the result into the processor's store (if any).
+    ///
+    /// The tx doesn't need a MEV-Share event: its event is made up from its receipt's logs.
+    pub async fn simulate_backrun(&self, tx_hash: H256) -> Result<SimArbResultBatch> {
+        self.num_requests.fetch_add(1, Ordering::Relaxed);
+        let res = self.simulate(tx_hash).await;
+        self.count(res)
+    }
+
+    /// Simulates backruns of the landed tx of `event` like `simulate_backrun`, using the event's own hint.
+    pub async fn simulate_event_backrun(&self, event: EventHistory) -> Result<SimArbResultBatch> {
+        self.num_requests.fetch_add(1, Ordering::Relaxed);
+        let res = self.simulate_event(event).await;
+        self.count(res)
+    }
+
+    fn count(&self, res: Result<SimArbResultBatch>) -> Result<SimArbResultBatch> {
+        match &res {
+            Ok(arb) => {
+                self.num_simulated.fetch_add(1, Ordering::Relaxed);
+                if !arb.max_profit.is_zero() {
+                    self.num_profitable.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(_) => {
+                self.num_failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        res
+    }
+
+    async fn simulate(&self, tx_hash: H256) -> Result<SimArbResultBatch> {
+        let client = &self.hindsight.client;
+        let tx = client
+            .get_transaction(tx_hash)
+            .await?
+            .ok_or(anyhow::format_err!("tx not found (hash={:?})", tx_hash))?;
+        let receipt = self
+            .hindsight
+            .receipts
+            .get(client, tx_hash)
+            .await?
+            .ok_or(anyhow::format_err!("tx not landed (hash={:?})", tx_hash))?;
+        let block = receipt
+            .block_number
+            .ok_or(anyhow::format_err!("tx not landed (hash={:?})", tx_hash))?;
+        let block_info = get_block_info(client, block.as_u64()).await?;
+        let event = event_from_receipt(&receipt, block_info.timestamp.as_u64());
+        self.process(tx, event).await
+    }
+
+    async fn simulate_event(&self, event: EventHistory) -> Result<SimArbResultBatch> {
+        let tx_hash = event.hint.hash;
+        let tx = self
+            .hindsight
+            .client
+            .get_transaction(tx_hash)
+            .await?
+            .ok_or(anyhow::format_err!("tx not found (hash={:?})", tx_hash))?;
+        self.process(tx, event).await
+    }
+
+    async fn process(&self, tx: Transaction, event: EventHistory) -> Result<SimArbResultBatch> {
+        let tx_hash = tx.hash;
+        let event_map = [(tx_hash, event)].into_iter().collect::<H256Map<_>>();
+        let processed = self
+            .hindsight
+            .to_owned()
+            .process_orderflow(&vec![tx], 1, None, event_map)
+            .await?;
+        match (
+            processed.arbs.into_iter().next(),
+            processed.failures.first(),
+        ) {
+            (Some(arb), _) => Ok(arb),
+            (None, Some(failure)) => Err(anyhow::format_err!("{}", failure.error)),
+            // the result didn't meet the processor's min profit
+            (None, None) => Err(anyhow::format_err!(
+                "no result met the min profit (hash={:?})",
+                tx_hash
+            )),
+        }
+    }
+
+    pub async fn stats(&self) -> Result<ServiceStats> {
+        let num_stored = match &self.hindsight.store {
+            Some(store) => Some(
+                store
+                    .get_num_arbs(&ArbFilterParams {
+                        chain_id: Some(self.hindsight.chain_id),
+                        ..ArbFilterParams::none()
+                    })
+                    .await?,
+            ),
+            None => None,
+        };
+        Ok(ServiceStats {
+            num_requests: self.num_requests.load(Ordering::Relaxed),
+            num_simulated: self.num_simulated.load(Ordering::Relaxed),
+            num_profitable: self.num_profitable.load(Ordering::Relaxed),
+            num_failed: self.num_failed.load(Ordering::Relaxed),
+            num_stored,
+        })
+    }
+
+    /// Streams every result produced from now on. Results are skipped if the subscriber falls too far behind.
+    pub fn subscribe(&self) -> impl Stream<Item = SimArbResultBatch> {
+        subscribe(&self.results)
+    }
+}
+
+/// Streams the results sent to `results` from now on, skipping those that a slow subscriber missed.
+pub fn subscribe(
+    results: &broadcast::Sender<SimArbResultBatch>,
+) -> impl Stream<Item = SimArbResultBatch> {
+    stream::unfold(results.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(arb) => return Some((arb, receiver)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Makes up the MEV-Share event of a landed tx, sharing its logs' addresses & topics like a hint would.
+pub fn event_from_receipt(receipt: &TransactionReceipt, timestamp: u64) -> EventHistory {
+    EventHistory {
+        block: receipt.block_number.unwrap_or_default().as_u64(),
+        timestamp,
+        hint: Hint {
+            txs: vec![],
+            hash: receipt.transaction_hash,
+            logs: receipt
+                .logs
+                .iter()
+                .map(|log| EventTransactionLog {
+                    address: log.address,
+                    topics: log.topics.to_owned(),
+                    // hints don't share log data
+                    data: Default::default(),
+                })
+                .collect(),
+            gas_used: receipt.gas_used,
+            mev_gas_price: None,
+        },
+    }
+}
+
+/// Fills in the logs of `events` that hint none (fully private txs) from their receipts, like `event_from_receipt`,
+/// so their swaps can be found & simulated like hinted ones.
+/// Events of txs that haven't landed, or whose receipts can't be fetched, are left without logs.
+///
+/// Returns the number of events whose logs were filled in.
+pub async fn fill_private_hints(
+    client: &WsClient,
+    receipts: &ReceiptCache,
+    events: &mut [EventHistory],
+) -> usize {
+    let private = events
+        .iter()
+        .filter(|event| event.hint.logs.is_empty())
+        .map(|event| event.hint.hash)
+        .collect::<Vec<_>>();
+    let fetched = fetch_all(&private, |tx_hash| receipts.get(client, tx_hash)).await;
+    let receipts = private
+        .into_iter()
+        .zip(fetched)
+        .filter_map(|(tx_hash, res)| match res {
+            Ok(receipt) => receipt.map(|receipt| (tx_hash, receipt)),
+            Err(err) => {
+                debug!(
+                    "failed to fetch receipt of private tx {:?}: {}",
+                    tx_hash, err
+                );
+                None
+            }
+        })
+        .collect::<H256Map<_>>();
+    let mut filled = 0;
+    for event in events.iter_mut() {
+        if let (true, Some(receipt)) = (event.hint.logs.is_empty(), receipts.get(&event.hint.hash))
+        {
+            event.hint.logs = event_from_receipt(receipt, event.timestamp).hint.logs;
+            filled += 1;
+        }
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Address, Log};
+    use futures::StreamExt;
+
+    #[test]
+    fn it_makes_events_from_receipts() {
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(0xabc),
+            block_number: Some(17_000_000.into()),
+            logs: vec![Log {
+                address: Address::from_low_u64_be(0xa),
+                topics: vec![H256::from_low_u64_be(0x1)],
+                data: vec![1, 2, 3].into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let event = event_from_receipt(&receipt, 1_700_000_000);
+        assert_eq!((event.block, event.timestamp), (17_000_000, 1_700_000_000));
+        assert_eq!(event.hint.hash, H256::from_low_u64_be(0xabc));
+        assert_eq!(event.hint.logs[0].address, Address::from_low_u64_be(0xa));
+        assert_eq!(event.hint.logs[0].topics, vec![H256::from_low_u64_be(0x1)]);
+        assert!(event.hint.logs[0].data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_streams_results_to_subscribers() {
+        let (results, _) = broadcast::channel(2);
+        let subscriber = subscribe(&results);
+        let arbs = (0..3)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::from_low_u64_be(i);
+                arb
+            })
+            .collect::<Vec<_>>();
+        for arb in &arbs {
+            results.send(arb.to_owned()).unwrap();
+        }
+        drop(results);
+        let received = subscriber
+            .map(|arb| arb.event.hint.hash)
+            .collect::<Vec<_>>()
+            .await;
+        // the first result was dropped for the slow subscriber
+        assert_eq!(
+            received,
+            vec![arbs[1].event.hint.hash, arbs[2].event.hint.hash]
+        );
+    }
+}
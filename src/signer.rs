@@ -0,0 +1,174 @@
+use crate::Result;
+#[cfg(feature = "ledger")]
+use ethers::signers::{HDPath, Ledger};
+use ethers::{
+    signers::{LocalWallet, Signer as EthersSigner},
+    types::{transaction::eip2718::TypedTransaction, Address, Signature},
+};
+use std::{fmt, path::PathBuf, sync::Arc};
+
+/// Where to load the auth signer from.
+#[derive(Clone)]
+pub enum SignerConfig {
+    /// Encrypted JSON keystore file and its password.
+    Keystore { path: PathBuf, password: String },
+    /// Ledger hardware wallet. `derivation` is either `live:<index>`, `legacy:<index>`,
+    /// or a full HD path (e.g. `m/44'/60'/0'/0/0`).
+    Ledger { derivation: String },
+    /// Raw hex-encoded private key. Prefer a keystore; this exists for local testing.
+    PrivateKey(String),
+}
+
+// Never print key material, not even by accident in a `{:?}` of the whole config.
+impl fmt::Debug for SignerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerConfig::Keystore { path, .. } => f
+                .debug_struct("Keystore")
+                .field("path", path)
+                .field("password", &"<redacted>")
+                .finish(),
+            SignerConfig::Ledger { derivation } => f
+                .debug_struct("Ledger")
+                .field("derivation", derivation)
+                .finish(),
+            SignerConfig::PrivateKey(_) => write!(f, "PrivateKey(<redacted>)"),
+        }
+    }
+}
+
+enum SignerKind {
+    Local(LocalWallet),
+    #[cfg(feature = "ledger")]
+    Ledger(Ledger),
+}
+
+/// Handle to the auth signer, used to sign bundles and relay requests.
+///
+/// Cheap to clone. The key itself is never exposed; `Debug` only prints the address.
+#[derive(Clone)]
+pub struct Signer {
+    inner: Arc<SignerKind>,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signer({:?})", self.address())
+    }
+}
+
+#[cfg(feature = "ledger")]
+fn parse_hd_path(derivation: &str) -> Result<HDPath> {
+    let parse_index = |idx: &str| {
+        idx.parse::<usize>()
+            .map_err(|err| anyhow::format_err!("invalid ledger index '{}': {}", idx, err))
+    };
+    Ok(if let Some(idx) = derivation.strip_prefix("live:") {
+        HDPath::LedgerLive(parse_index(idx)?)
+    } else if let Some(idx) = derivation.strip_prefix("legacy:") {
+        HDPath::Legacy(parse_index(idx)?)
+    } else {
+        HDPath::Other(derivation.to_owned())
+    })
+}
+
+impl Signer {
+    /// Loads the signer described by `config`. Transactions are signed for `chain_id`.
+    pub async fn load(config: &SignerConfig, chain_id: u64) -> Result<Self> {
+        let kind = match config.to_owned() {
+            SignerConfig::Keystore { path, password } => {
+                // scrypt is deliberately slow; keep it off the async runtime
+                let wallet = tokio::task::spawn_blocking(move || {
+                    LocalWallet::decrypt_keystore(&path, password).map_err(|err| {
+                        anyhow::format_err!("failed to decrypt keystore {:?}: {}", path, err)
+                    })
+                })
+                .await??;
+                SignerKind::Local(wallet.with_chain_id(chain_id))
+            }
+            SignerConfig::PrivateKey(key) => SignerKind::Local(
+                key.trim_start_matches("0x")
+                    .parse::<LocalWallet>()
+                    .map_err(|_| anyhow::format_err!("failed to parse auth signer private key"))?
+                    .with_chain_id(chain_id),
+            ),
+            #[cfg(feature = "ledger")]
+            SignerConfig::Ledger { derivation } => {
+                SignerKind::Ledger(Ledger::new(parse_hd_path(&derivation)?, chain_id).await?)
+            }
+            #[cfg(not(feature = "ledger"))]
+            SignerConfig::Ledger { .. } => {
+                return Err(anyhow::format_err!(
+                    "ledger signer requested, but hindsight was built without the `ledger` feature"
+                ))
+            }
+        };
+        Ok(Self {
+            inner: Arc::new(kind),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        match self.inner.as_ref() {
+            SignerKind::Local(wallet) => wallet.address(),
+            #[cfg(feature = "ledger")]
+            SignerKind::Ledger(ledger) => ledger.address(),
+        }
+    }
+
+    /// Signs an EIP-191 personal message.
+    pub async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        Ok(match self.inner.as_ref() {
+            SignerKind::Local(wallet) => wallet.sign_message(message).await?,
+            #[cfg(feature = "ledger")]
+            SignerKind::Ledger(ledger) => ledger.sign_message(message).await?,
+        })
+    }
+
+    pub async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature> {
+        Ok(match self.inner.as_ref() {
+            SignerKind::Local(wallet) => wallet.sign_transaction(tx).await?,
+            #[cfg(feature = "ledger")]
+            SignerKind::Ledger(ledger) => ledger.sign_transaction(tx).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_loads_keystore_signer() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("hindsight-keystore-{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir)?;
+        let (wallet, name) =
+            LocalWallet::new_keystore(&dir, &mut rand::thread_rng(), "hunter2", None)?;
+        let config = SignerConfig::Keystore {
+            path: dir.join(&name),
+            password: "hunter2".to_owned(),
+        };
+        let signer = Signer::load(&config, 1).await?;
+        assert_eq!(signer.address(), wallet.address());
+        assert!(!format!("{:?}", config).contains("hunter2"));
+
+        let bad_config = SignerConfig::Keystore {
+            path: dir.join(&name),
+            password: "wrong".to_owned(),
+        };
+        assert!(Signer::load(&bad_config, 1).await.is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_redacts_private_key() -> Result<()> {
+        let key = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+        let config = SignerConfig::PrivateKey(key.to_owned());
+        let signer = Signer::load(&config, 1).await?;
+        assert!(!format!("{:?}", config).contains(&key[2..]));
+        assert!(!format!("{:?}", signer).contains(&key[2..]));
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{info, Result};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Number of forks & sims that may run at once by default.
+pub const DEFAULT_SIM_PERMITS: usize = 64;
+/// Relative profit gain (0.01%) below which a search stops going deeper, unless configured otherwise.
+pub const DEFAULT_MIN_IMPROVEMENT: f64 = 0.0001;
+/// Relative price gap (0.01%) between two pools below which their route isn't simulated, unless configured otherwise.
+/// Below the fees of any two pools, so no route that could be profitable is skipped.
+pub const DEFAULT_MIN_PRICE_GAP: f64 = 0.0001;
+/// Share of a probe (5%) that a buy & sell of a token may lose before the token is deemed unsafe, unless configured otherwise.
+/// Well above the fees of a round trip through any pool.
+pub const DEFAULT_MAX_TOKEN_TAX: f64 = 0.05;
+
+/// Bounds the number of EVM forks, sims & RPC-heavy steps running at once, however they're nested
+/// (txs × pools × intervals), so memory use & provider load don't grow with the batch size.
+///
+/// Only leaf work holds a permit: nothing waits on other tasks while holding one, so a budget can't deadlock.
+/// Clones share the permits, e.g. across the chains of a scan.
+#[derive(Clone, Debug)]
+pub struct SimBudget {
+    permits: Arc<Semaphore>,
+    size: usize,
+}
+
+impl Default for SimBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIM_PERMITS)
+    }
+}
+
+impl SimBudget {
+    /// Lets up to `permits` (at least 1) forks & sims run at once.
+    pub fn new(permits: usize) -> Self {
+        let size = permits.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(size)),
+            size,
+        }
+    }
+
+    /// Waits for a permit, which is returned when the guard is dropped.
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>> {
+        Ok(self.permits.acquire().await?)
+    }
+
+    /// Total number of permits.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of permits not held right now.
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+}
+
+/// Caps on the work spent simulating one tx, across all of its routes & search depths. None is uncapped.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TxSimLimits {
+    /// Maximum number of EVM executions (the user's tx on each route, and each backrun attempt).
+    pub max_sims: Option<usize>,
+    /// Maximum time to spend on the tx, from when its simulation starts.
+    pub max_duration: Option<Duration>,
+    /// Minimum relative gain in profit that a step of a search must make for the search to go deeper,
+    /// e.g. 0.001 stops once a step improves the best profit by less than 0.1%.
+    pub min_improvement: Option<f64>,
+    /// Minimum gap between the prices of a route's pools, relative to the higher one, for the route to be simulated.
+    pub min_price_gap: Option<f64>,
+    /// Share of a probe that a buy & sell of the traded token may lose before its routes are skipped (see `screen_token`).
+    pub max_token_tax: Option<f64>,
+    /// Share of each profitable backrun's profit that its bundle pays the block's coinbase (the builder) for inclusion,
+    /// e.g. 0.9 pays 90%. Only backruns quoted in the chain's wrapped native token pay, since the payment is in the
+    /// native token; their bundle is re-simulated with the payment, which must leave the backrun profitable after gas.
+    pub coinbase_share: Option<f64>,
+    /// Re-simulate every profitable result once more on a freshly built fork, flagging results it doesn't reproduce.
+    pub verify: bool,
+    /// Simulate a tx's routes, and each step's intervals, one after another in a fixed order instead of concurrently,
+    /// so they spend the tx's sims in the same order every run. `max_duration` is ignored, since how far a search
+    /// gets in a given time depends on the machine. Slower, but runs over the same chain state give identical results.
+    pub deterministic: bool,
+}
+
+/// Work spent simulating one tx so far, measured against its `TxSimLimits`. Clones share the count.
+///
+/// Once the budget runs out, searches stop with the best result they've found, and the tx is marked truncated.
+#[derive(Clone, Debug)]
+pub struct TxSimBudget {
+    limits: TxSimLimits,
+    start: Instant,
+    sims: Arc<AtomicUsize>,
+    truncated: Arc<AtomicBool>,
+}
+
+impl TxSimBudget {
+    /// Starts measuring a tx's work against `limits`.
+    pub fn new(limits: TxSimLimits) -> Self {
+        Self {
+            limits,
+            start: Instant::now(),
+            sims: Default::default(),
+            truncated: Default::default(),
+        }
+    }
+
+    /// Counts an EVM execution, unless the budget has run out; returns whether it may run.
+    pub fn try_spend(&self) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+        let spent = self.sims.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.limits.max_sims.is_some_and(|max| spent > max) {
+            self.truncate();
+            return false;
+        }
+        true
+    }
+
+    /// Returns true (and marks the tx truncated) if the tx is out of sims or time.
+    pub fn is_exhausted(&self) -> bool {
+        let out_of_sims = self
+            .limits
+            .max_sims
+            .is_some_and(|max| self.sims.load(Ordering::Relaxed) >= max);
+        let out_of_time = !self.limits.deterministic
+            && self
+                .limits
+                .max_duration
+                .is_some_and(|max| self.start.elapsed() >= max);
+        if out_of_sims || out_of_time {
+            self.truncate();
+        }
+        out_of_sims || out_of_time
+    }
+
+    /// Limits the tx is measured against.
+    pub fn limits(&self) -> &TxSimLimits {
+        &self.limits
+    }
+
+    /// Whether any search was cut short by the budget.
+    pub fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Number of EVM executions counted so far.
+    pub fn spent(&self) -> usize {
+        self.sims.load(Ordering::Relaxed)
+    }
+
+    fn truncate(&self) {
+        if !self.truncated.swap(true, Ordering::Relaxed) {
+            info!(
+                sims = self.spent(),
+                elapsed = ?self.start.elapsed(),
+                "tx sim budget exhausted, keeping the best results so far"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_shares_permits_between_clones() -> Result<()> {
+        let budget = SimBudget::new(2);
+        let other = budget.clone();
+        let first = budget.acquire().await?;
+        let _second = other.acquire().await?;
+        assert_eq!(budget.available(), 0);
+        assert!(budget.permits.try_acquire().is_err());
+        drop(first);
+        assert_eq!(other.available(), 1);
+        assert_eq!(SimBudget::new(0).size(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_caps_sims_per_tx() {
+        let budget = TxSimBudget::new(TxSimLimits {
+            max_sims: Some(2),
+            max_duration: None,
+            ..Default::default()
+        });
+        let other = budget.clone();
+        assert!(budget.try_spend());
+        assert!(other.try_spend());
+        assert!(!budget.truncated());
+        assert!(!other.try_spend());
+        assert!(budget.truncated());
+        assert_eq!(budget.spent(), 2);
+
+        let uncapped = TxSimBudget::new(TxSimLimits::default());
+        assert!((0..100).all(|_| uncapped.try_spend()));
+        assert!(!uncapped.truncated());
+
+        let timed = TxSimBudget::new(TxSimLimits {
+            max_sims: None,
+            max_duration: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        assert!(timed.is_exhausted() && !timed.try_spend() && timed.truncated());
+
+        // a deterministic search isn't cut short by the clock
+        let deterministic = TxSimBudget::new(TxSimLimits {
+            max_sims: Some(1),
+            max_duration: Some(Duration::ZERO),
+            deterministic: true,
+            ..Default::default()
+        });
+        assert!(deterministic.try_spend() && !deterministic.truncated());
+        assert!(!deterministic.try_spend() && deterministic.truncated());
+    }
+}
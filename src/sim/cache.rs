@@ -0,0 +1,395 @@
+use crate::{
+    data::{tokens::read_cache, EXPORT_DIR},
+    interfaces::{PoolVariant, UserTradeParams},
+    sim::ENGINE_VERSION,
+    Result,
+};
+use ethers::{
+    abi::{self, Token},
+    types::{Address, H256, U256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Identifies a backrun sim by everything its outcome depends on: the engine version, the block it forks,
+/// the user's tx, the traded tokens & the arb contract's starting balance, the route, and `amount_in`.
+pub fn sim_key(
+    block: u64,
+    tx_hash: H256,
+    params: &UserTradeParams,
+    start_pair_variant: (Address, PoolVariant),
+    end_pair_variant: (Address, PoolVariant),
+    amount_in: U256,
+) -> H256 {
+    let pool = |(address, variant): (Address, PoolVariant)| {
+        [
+            Token::Address(address),
+            Token::String(format!("{:?}", variant)),
+        ]
+    };
+    let mut tokens = vec![
+        Token::String(ENGINE_VERSION.to_owned()),
+        Token::Uint(block.into()),
+        Token::FixedBytes(tx_hash.as_bytes().to_vec()),
+        Token::Address(params.tokens.quote),
+        Token::Address(params.tokens.token),
+        Token::Uint(params.quote_asset.starting_balance),
+    ];
+    tokens.extend(pool(start_pair_variant));
+    tokens.extend(pool(end_pair_variant));
+    tokens.push(Token::Uint(amount_in));
+    H256::from(keccak256(abi::encode(&tokens)))
+}
+
+/// What a backrun sim came to. Only outcomes that a rerun would repeat are kept.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SimOutcome {
+    #[serde(rename_all = "camelCase")]
+    Backrun {
+        balance_out: U256,
+        gas_used: u64,
+    },
+    Reverted {
+        error: String,
+    },
+}
+
+impl SimOutcome {
+    /// Outcome of a sim's `(amount_in, balance_out, gas_used)` result, unless it failed for a reason
+    /// outside the EVM (e.g. an RPC error) that may not happen again.
+    pub fn of(res: &Result<(U256, U256, u64)>) -> Option<Self> {
+        match res {
+            Ok((_, balance_out, gas_used)) => Some(Self::Backrun {
+                balance_out: *balance_out,
+                gas_used: *gas_used,
+            }),
+            Err(err) if err.to_string().contains("swap reverted") => Some(Self::Reverted {
+                error: err.to_string(),
+            }),
+            Err(_) => None,
+        }
+    }
+
+    /// Rebuilds the result of the sim of `amount_in` that this outcome was taken from.
+    pub fn to_result(&self, amount_in: U256) -> Result<(U256, U256, u64)> {
+        match self {
+            Self::Backrun {
+                balance_out,
+                gas_used,
+            } => Ok((amount_in, *balance_out, *gas_used)),
+            Self::Reverted { error } => Err(anyhow::anyhow!(error.to_owned())),
+        }
+    }
+}
+
+/// Most outcomes that a `SimCache` keeps. Past it, the least recently used tenth are evicted,
+/// so the cache (and the file it's saved to) stay bounded however many blocks are scanned.
+pub const MAX_SIM_CACHE_ENTRIES: usize = 250_000;
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    key: H256,
+    #[serde(flatten)]
+    outcome: SimOutcome,
+}
+
+/// An outcome in a `SimCache`, with when it was last inserted or read.
+#[derive(Clone, Debug)]
+struct Cached {
+    outcome: SimOutcome,
+    last_used: u64,
+}
+
+/// Evicts all but the `keep` most recently used outcomes.
+fn evict(outcomes: &mut HashMap<H256, Cached>, keep: usize) {
+    if outcomes.len() <= keep {
+        return;
+    }
+    let mut uses = outcomes
+        .values()
+        .map(|cached| cached.last_used)
+        .collect::<Vec<_>>();
+    uses.sort_unstable();
+    let oldest_kept = uses[uses.len() - keep];
+    outcomes.retain(|_, cached| cached.last_used >= oldest_kept);
+}
+
+/// How well a `SimCache` has done since it was loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Number of outcomes in the cache.
+    pub entries: usize,
+}
+
+impl SimCacheStats {
+    /// Fraction of lookups that were hits, or 0 before any lookup.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Outcomes of backrun sims by `sim_key`, so sims that were already run (e.g. by an earlier scan of
+/// the same blocks) are read back instead of run again. Clones share the cache.
+///
+/// A cache loaded with `load` is saved as JSON in `EXPORT_DIR` with `save`; `default` is only kept in memory.
+/// Keys include `ENGINE_VERSION`, so outcomes of an older engine are never read back.
+/// It holds at most `MAX_SIM_CACHE_ENTRIES` outcomes (see `with_max_entries`), evicting the least recently used.
+#[derive(Clone)]
+pub struct SimCache {
+    path: Option<PathBuf>,
+    outcomes: Arc<Mutex<HashMap<H256, Cached>>>,
+    max_entries: usize,
+    /// Counts inserts & reads, to order outcomes by when they were last used.
+    clock: Arc<AtomicU64>,
+    /// Whether there are outcomes that haven't been saved yet.
+    dirty: Arc<AtomicBool>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl Default for SimCache {
+    fn default() -> Self {
+        Self {
+            path: None,
+            outcomes: Default::default(),
+            max_entries: MAX_SIM_CACHE_ENTRIES,
+            clock: Default::default(),
+            dirty: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for SimCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SimCache")
+            .field("path", &self.path)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl SimCache {
+    /// Loads the cache of `chain_id`, starting empty if it hasn't been saved before.
+    pub fn load(chain_id: u64) -> Result<Self> {
+        let path = PathBuf::from(EXPORT_DIR).join(format!("sims_{}.json", chain_id));
+        // entries are saved from least to most recently used
+        let entries = read_cache::<CacheEntry>(&path)?;
+        let clock = entries.len() as u64;
+        let mut outcomes = entries
+            .into_iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let cached = Cached {
+                    outcome: entry.outcome,
+                    last_used: idx as u64,
+                };
+                (entry.key, cached)
+            })
+            .collect();
+        evict(&mut outcomes, MAX_SIM_CACHE_ENTRIES);
+        Ok(Self {
+            path: Some(path),
+            outcomes: Arc::new(Mutex::new(outcomes)),
+            clock: Arc::new(AtomicU64::new(clock)),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the same cache, keeping at most `max_entries` (at least 1) outcomes.
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        let max_entries = max_entries.max(1);
+        evict(&mut self.outcomes.lock().unwrap(), max_entries);
+        Self {
+            max_entries,
+            ..self
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns the outcome of the sim identified by `key`, if it's been run before.
+    pub fn get(&self, key: &H256) -> Option<SimOutcome> {
+        let outcome = self.outcomes.lock().unwrap().get_mut(key).map(|cached| {
+            cached.last_used = self.tick();
+            cached.outcome.to_owned()
+        });
+        let counter = if outcome.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        outcome
+    }
+
+    /// Adds the outcome of the sim identified by `key`, evicting the least recently used tenth of the cache if it's full.
+    pub fn insert(&self, key: H256, outcome: SimOutcome) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        let last_used = self.tick();
+        outcomes.insert(key, Cached { outcome, last_used });
+        if outcomes.len() > self.max_entries {
+            evict(&mut outcomes, self.max_entries - self.max_entries / 10);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> SimCacheStats {
+        SimCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.outcomes.lock().unwrap().len(),
+        }
+    }
+
+    /// Saves the cache to its file (as compact JSON, from least to most recently used outcome)
+    /// if it was loaded from one and has new outcomes.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut entries = self
+            .outcomes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, cached)| {
+                let entry = CacheEntry {
+                    key: *key,
+                    outcome: cached.outcome.to_owned(),
+                };
+                (cached.last_used, entry)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(last_used, _)| *last_used);
+        let entries = entries
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+        let saved = write_entries(path, &entries);
+        if saved.is_err() {
+            // try again next time
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        saved
+    }
+}
+
+fn write_entries(path: &Path, entries: &[CacheEntry]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string(entries)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_keys_sims_by_their_inputs() {
+        let params =
+            SimArbResult::test_example(Address::from_low_u64_be(2), 0.into(), 0.into()).user_trade;
+        let start = (Address::from_low_u64_be(3), PoolVariant::UniswapV2);
+        let end = (Address::from_low_u64_be(4), PoolVariant::UniswapV3);
+        let key = |block: u64, amount_in: u64| {
+            sim_key(block, H256::zero(), &params, start, end, amount_in.into())
+        };
+        assert_eq!(key(1, 100), key(1, 100));
+        assert_ne!(key(1, 100), key(2, 100));
+        assert_ne!(key(1, 100), key(1, 101));
+        assert_ne!(
+            key(1, 100),
+            sim_key(1, H256::zero(), &params, end, start, 100.into())
+        );
+    }
+
+    #[test]
+    fn it_caches_deterministic_outcomes() {
+        let cache = SimCache::default();
+        let (key, amount_in) = (H256::repeat_byte(1), U256::from(100));
+        assert_eq!(cache.get(&key), None);
+
+        let backrun = (amount_in, U256::from(120), 90_000);
+        cache.insert(key, SimOutcome::of(&Ok(backrun)).unwrap());
+        let cached = cache.get(&key).unwrap().to_result(amount_in).unwrap();
+        assert_eq!(cached, backrun);
+
+        let reverted = Err(anyhow::anyhow!("swap reverted: 0x (gas used: 21000)"));
+        assert_eq!(
+            SimOutcome::of(&reverted),
+            Some(SimOutcome::Reverted {
+                error: "swap reverted: 0x (gas used: 21000)".to_owned()
+            })
+        );
+        assert_eq!(SimOutcome::of(&Err(anyhow::anyhow!("rpc timeout"))), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+        // in-memory caches aren't saved anywhere
+        cache.save().unwrap();
+    }
+
+    #[test]
+    fn it_evicts_least_recently_used_outcomes() {
+        let cache = SimCache::default().with_max_entries(10);
+        let outcome = SimOutcome::Reverted {
+            error: "swap reverted".to_owned(),
+        };
+        for n in 0..10 {
+            cache.insert(H256::from_low_u64_be(n), outcome.to_owned());
+        }
+        assert_eq!(cache.stats().entries, 10);
+        // reading the oldest outcome keeps it
+        assert!(cache.get(&H256::from_low_u64_be(0)).is_some());
+        cache.insert(H256::from_low_u64_be(10), outcome.to_owned());
+        assert_eq!(cache.stats().entries, 9);
+        assert!(cache.get(&H256::from_low_u64_be(0)).is_some());
+        assert!(cache.get(&H256::from_low_u64_be(1)).is_none());
+        assert!(cache.get(&H256::from_low_u64_be(2)).is_none());
+        assert!(cache.get(&H256::from_low_u64_be(10)).is_some());
+    }
+
+    #[test]
+    fn it_serializes_outcomes() {
+        let entry = CacheEntry {
+            key: H256::zero(),
+            outcome: SimOutcome::Backrun {
+                balance_out: 5.into(),
+                gas_used: 7,
+            },
+        };
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["kind"], "backrun");
+        assert_eq!(json["gasUsed"], 7);
+        let entry: CacheEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            entry.outcome.to_result(1.into()).unwrap(),
+            (1.into(), 5.into(), 7)
+        );
+    }
+}
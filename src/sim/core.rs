@@ -1,167 +1,267 @@
+use crate::analysis::stats::ESTIMATED_BACKRUN_GAS;
+use crate::audit::{AuditDecision, AuditLog};
 use crate::error::HindsightError;
+use crate::filter::AddressLists;
 use crate::interfaces::{
-    BackrunResult, PairPool, PoolVariant, SimArbResult, TokenPair, UserTradeParams,
+    BackrunResult, CurvePoint, PairPool, PoolVariant, QuoteAsset, RouteHop, SimArbResult,
+    SimFailure, TokenPair, UserTradeParams,
 };
-use crate::sim::evm::{commit_braindance_swap, sim_bundle, sim_price_v2, sim_price_v3};
+use crate::sim::budget::{SimBudget, TxSimBudget};
+use crate::sim::cache::{sim_key, SimCache, SimOutcome};
+use crate::sim::events::validate_hint_log;
+use crate::sim::evm::{
+    commit_braindance_swap, commit_coinbase_transfer, commit_user_tx, fund_braindance,
+    run_blocking, token_balance,
+};
+use crate::sim::fork::{fork_factory, fork_from, ForkCache};
+use crate::sim::graph::PathFinder;
+use crate::sim::pools::{DecodedSwap, PoolAdapter, PoolAdapters};
+use crate::sim::registry::PoolRegistry;
+use crate::sim::screen::screen_token;
 use crate::util::{
-    get_all_trading_pools, get_decimals, get_pair_tokens, get_price_v2, get_price_v3, WsClient,
+    apply_share, checked_mul_div, compare_share, convert_at_price, get_price_v2, get_reserves_v2,
+    panic_message, ReceiptCache, WsClient, PPB,
 };
 use crate::{debug, info};
 use crate::{Error, Result};
 use async_recursion::async_recursion;
-use ethers::providers::Middleware;
-use ethers::types::{AccountDiff, Address, BlockNumber, Transaction, H160, H256, I256, U256};
-use futures::future;
+use ethers::types::{Address, Log, Transaction, H256, I256, U256};
+use futures::{future, Future, FutureExt};
 use mev_share_sse::{EventHistory, EventTransactionLog};
-use revm::primitives::U256 as rU256;
 use revm::EVM;
 use rusty_sando::prelude::fork_db::ForkDB;
-use rusty_sando::simulate::{
-    attach_braindance_module, braindance_starting_balance, setup_block_state,
-};
+use rusty_sando::simulate::{braindance_address, braindance_controller_address};
 use rusty_sando::types::BlockInfo;
-use rusty_sando::{forked_db::fork_factory::ForkFactory, utils::state_diff};
-use std::collections::BTreeMap;
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tracing::Instrument;
 
-const MAX_DEPTH: usize = 7;
-const STEP_INTERVALS: usize = 15;
+/// Deepest step of a route's search; each step narrows the range of amounts in around the best one so far.
+pub const MAX_DEPTH: usize = 7;
+/// Amounts in simulated at each step of a route's search.
+pub const STEP_INTERVALS: usize = 15;
+/// Gas that every tx pays before executing any code.
+const TX_BASE_GAS: u64 = 21_000;
+
+/// Balance out of each amount in that a route's search has simulated, shared by all of its steps.
+type SearchCurve = Arc<Mutex<BTreeMap<U256, U256>>>;
 
 /// Return an evm instance forked from the provided block info and client state
 /// with braindance module initialized.
 /// Braindance contracts starts w/ braindance_starting_balance, which is 420 WETH.
+///
+/// Every call fetches the block's state again; use a `ForkCache` to fork the same block repeatedly.
 pub async fn fork_evm(client: &WsClient, block_info: &BlockInfo) -> Result<EVM<ForkDB>> {
-    let fork_block_num = BlockNumber::Number(block_info.number);
-    let fork_block = Some(ethers::types::BlockId::Number(fork_block_num));
-
-    let state_diffs =
-        if let Some(sd) = state_diff::get_from_txs(&client, &vec![], fork_block_num).await {
-            sd
-        } else {
-            BTreeMap::<H160, AccountDiff>::new()
-        };
-    let initial_db = state_diff::to_cache_db(&state_diffs, fork_block, &client).await?;
-    let mut fork_factory = ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
-    attach_braindance_module(&mut fork_factory);
-
-    let mut evm = EVM::new();
-    evm.database(fork_factory.new_sandbox_fork());
-    setup_block_state(&mut evm, block_info);
-    Ok(evm)
+    Ok(fork_from(
+        &fork_factory(client, block_info).await?,
+        block_info,
+    ))
 }
 
-/// Returns None if trade params can't be derived.
+/// Returns the hinted logs of `event` that have one of `swap_topics`, skipping malformed logs.
 ///
-/// May derive multiple trades from a single tx.
-async fn derive_trade_params(
-    client: &WsClient,
-    tx: Transaction,
+/// Fails with the last malformed log's error if every log that might have been a swap was malformed,
+/// so the hint is recorded as a failure rather than silently skipped.
+pub fn hinted_swap_logs(
     event: &EventHistory,
-) -> Result<Vec<UserTradeParams>> {
-    // Swap(address,address,int256,int256,uint160,uint128,int24)
-    let univ3_topic =
-        H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")?;
-    let sync_topic =
-        H256::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1")?;
-    let uniswap_topics = vec![
-        // univ3
-        H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")?,
-        // univ2
-        // Swap(address,uint256,uint256,uint256,uint256,address)
-        H256::from_str("0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822")?,
-    ];
-
-    // get potential pool addresses from event, relying on mev-share hints
+    swap_topics: &[H256],
+) -> Result<Vec<EventTransactionLog>> {
+    let mut malformed = None;
     let swap_logs = event
         .hint
         .logs
         .iter()
-        .filter(|log| uniswap_topics.contains(&log.topics[0]))
+        .filter(|log| match validate_hint_log(log) {
+            Ok(()) => swap_topics.contains(&log.topics[0]),
+            Err(err) => {
+                debug!("skipping hint log: {}", err);
+                malformed = Some(err);
+                false
+            }
+        })
         .map(|log| log.to_owned())
-        .collect::<Vec<EventTransactionLog>>();
+        .collect::<Vec<_>>();
+    match (swap_logs.is_empty(), malformed) {
+        (true, Some(err)) => Err(err),
+        _ => Ok(swap_logs),
+    }
+}
+
+/// Decodes every swap on `pool` (logs with `swap_topic`) among a tx's `logs` with `adapter`, netted into one trade.
+///
+/// Returns None if the tx didn't swap on the pool.
+pub fn decode_pool_swaps(
+    adapter: &dyn PoolAdapter,
+    pool: Address,
+    swap_topic: H256,
+    logs: &[Log],
+    token0_decimals: U256,
+) -> Result<Option<DecodedSwap>> {
+    let swaps = logs
+        .iter()
+        .filter(|log| log.topics.contains(&swap_topic) && log.address == pool)
+        .map(|log| adapter.decode_swap(log, logs, token0_decimals))
+        .collect::<Result<Vec<_>>>()?;
+    if swaps.len() > 1 {
+        debug!("netting {} swaps on pool {:?}", swaps.len(), pool);
+    }
+    Ok(DecodedSwap::net(swaps))
+}
+
+/// What searching a tx for backruns needs besides the tx itself, shared by every tx of a batch.
+#[derive(Clone, Copy)]
+pub struct SearchContext<'a> {
+    pub client: &'a WsClient,
+    /// Assets that backruns start & end in, by priority.
+    pub quote_assets: &'a [QuoteAsset],
+    /// The chain's wrapped native token, that gas & coinbase payments are paid in, if known.
+    pub wrapped_native: Option<Address>,
+    /// Tokens & pools that aren't traded.
+    pub lists: &'a AddressLists,
+    /// Adapter that prices & trades each kind of pool.
+    pub adapters: &'a PoolAdapters,
+    /// Forks of the blocks that sims run on.
+    pub forks: &'a ForkCache,
+    /// Permits that forks, sims & RPC-heavy steps each hold.
+    pub budget: &'a SimBudget,
+    /// Receipts of the txs that are searched.
+    pub receipts: &'a ReceiptCache,
+    /// Pools, tokens & pairs of the chain.
+    pub registry: &'a PoolRegistry,
+    /// Proposes cycles through more pools than the user's pair, if given.
+    pub path_finder: Option<&'a PathFinder>,
+    /// Outcomes of backrun sims that don't need to run again.
+    pub sim_cache: Option<&'a SimCache>,
+    /// Records what was decided about the pools & routes of sampled txs.
+    pub audit: Option<&'a AuditLog>,
+}
+
+/// Returns None if trade params can't be derived.
+///
+/// May derive multiple trades from a single tx. Swaps that don't trade any of the search's `quote_assets`,
+/// that involve tokens or pools excluded by its `lists`, or that none of its `adapters` decodes, are skipped.
+/// The tx's receipt is read from `receipts`, and its pools, tokens & pairs are looked up in `registry`.
+/// Why each pool was skipped is recorded in `audit`.
+#[tracing::instrument(skip_all, fields(tx_hash = ?tx.hash))]
+async fn derive_trade_params(
+    search: &SearchContext<'_>,
+    tx: &Transaction,
+    event: &EventHistory,
+) -> Result<Vec<UserTradeParams>> {
+    let SearchContext {
+        client,
+        quote_assets,
+        lists,
+        adapters,
+        receipts,
+        registry,
+        audit,
+        ..
+    } = *search;
+    let record = |pool, decision| {
+        if let Some(audit) = audit {
+            audit.record(event, pool, decision, None);
+        }
+    };
+    // get potential pool addresses from event, relying on mev-share hints
+    let swap_logs = hinted_swap_logs(event, &adapters.swap_topics())?;
     debug!("swap logs {:?}", swap_logs);
+    if swap_logs.is_empty() {
+        record(None, AuditDecision::NoSwapHint);
+    }
     // derive trade direction from (full) tx logs
-    let tx_receipt = client
-        .get_transaction_receipt(tx.hash)
+    let tx_receipt = receipts
+        .get(client, tx.hash)
         .await?
         .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?;
 
     // collect trade params for each pair derived from swap logs
     let mut trade_params = vec![];
+    let mut seen_pools = HashSet::new();
     for swap_log in swap_logs {
         let pool_address = swap_log.address;
         let swap_topic = swap_log.topics[0]; // MEV-Share puts the swap topic in the 0th position, following txs are zeroed out by default
         debug!("pool address: {:?}", pool_address);
         debug!("swap topic: {:?}", swap_topic);
+        if !lists.allows_pool(&pool_address) {
+            debug!("pool {:?} is excluded, skipping", pool_address);
+            record(Some(pool_address), AuditDecision::ExcludedPool);
+            continue;
+        }
+        // all of the tx's swaps on the pool are netted into one trade, however many hints share it
+        if !seen_pools.insert(pool_address) {
+            continue;
+        }
 
-        let swap_log = tx_receipt
-            .logs
-            .iter()
-            .find(|log| log.topics.contains(&swap_topic) && log.address == pool_address)
+        // derive pool variant from event log topics
+        let adapter = adapters
+            .by_swap_topic(&swap_topic)
             .ok_or(anyhow::format_err!(
-                "no swap logs found for tx {:?}",
-                tx.hash
+                "no adapter for swap topic {:?}",
+                swap_topic
             ))?;
-
-        // derive pool variant from event log topics
-        let pool_variant = if swap_topic == univ3_topic {
-            PoolVariant::UniswapV3
-        } else {
-            PoolVariant::UniswapV2 // (wrong but cheap) assume all non-V3 events are V2
-        };
+        let pool_variant = adapter.variant();
         debug!("pool variant: {:?}", pool_variant);
 
         // get token addrs from pool address
         // tokens may vary per swap log -- many swaps can happen in one tx
-        let (token0, token1) = get_pair_tokens(client, pool_address).await?;
+        let (token0, token1) = registry.pair_tokens(client, pool_address).await?;
         debug!("token0\t{:?}\ntoken1\t{:?}", token0, token1);
-        let token0_is_weth =
-            token0 == "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".parse::<H160>()?;
-        let token0_decimals = get_decimals(client, token0).await?;
-
-        // if a Sync event (UniV2) is detected from the tx logs, it can be used to get the new price
-        let sync_log: Option<_> = tx_receipt
-            .logs
+        if !lists.allows_token(&token0) || !lists.allows_token(&token1) {
+            debug!("pool {:?} trades an excluded token, skipping", pool_address);
+            record(Some(pool_address), AuditDecision::ExcludedToken);
+            continue;
+        }
+        // quote in the highest-priority asset that this pair trades
+        let quote_asset = match quote_assets
             .iter()
-            .find(|log| log.topics[0] == sync_topic && log.address == pool_address);
-
-        // derive user's trade amounts & post-tx price from log data
-        let (amount0_sent, amount1_sent, new_price) = match pool_variant {
-            PoolVariant::UniswapV3 => {
-                let amount0 = I256::from_raw(U256::from_big_endian(&swap_log.data[0..32]));
-                let amount1 = I256::from_raw(U256::from_big_endian(&swap_log.data[32..64]));
-                let sqrt_price = U256::from_big_endian(&swap_log.data[64..96]); // u160
-                let liquidity = U256::from_big_endian(&swap_log.data[96..128]); // u128
-                let new_price = get_price_v3(liquidity, sqrt_price, token0_decimals)?;
-                (
-                    /* amount0_sent */
-                    if amount0.le(&0.into()) {
-                        0.into()
-                    } else {
-                        amount0
-                    },
-                    /* amount1_sent */
-                    if amount1.le(&0.into()) {
-                        0.into()
-                    } else {
-                        amount1
-                    },
-                    /* new_price */
-                    new_price,
-                )
-            }
-            PoolVariant::UniswapV2 => {
-                let amount0_out = I256::from_raw(U256::from_big_endian(&swap_log.data[64..96]));
-                let amount1_out = I256::from_raw(U256::from_big_endian(&swap_log.data[96..128]));
-                let mut new_price = U256::zero();
-                if let Some(sync_log) = sync_log {
-                    let reserve0 = U256::from_big_endian(&sync_log.data[0..32]);
-                    let reserve1 = U256::from_big_endian(&sync_log.data[32..64]);
-                    new_price = get_price_v2(reserve0, reserve1, token0_decimals)?;
-                }
-                (amount0_out, amount1_out, new_price)
+            .find(|asset| asset.address == token0 || asset.address == token1)
+        {
+            Some(asset) => asset.to_owned(),
+            None => {
+                debug!(
+                    "pool {:?} doesn't trade a quote asset, skipping",
+                    pool_address
+                );
+                record(Some(pool_address), AuditDecision::NoQuoteAsset);
+                continue;
             }
         };
+        let token0_is_quote = token0 == quote_asset.address;
+        let token0_decimals = registry.decimals(client, token0).await?;
+
+        // derive user's trade amounts & post-tx price from log data
+        let swap = decode_pool_swaps(
+            adapter,
+            pool_address,
+            swap_topic,
+            &tx_receipt.logs,
+            token0_decimals,
+        )?
+        .ok_or(anyhow::format_err!(
+            "no swap logs found for tx {:?}",
+            tx.hash
+        ))?;
+        // only what the user sent on net counts
+        let sent = |amount: I256| amount.max(I256::zero());
+        let (amount0_sent, amount1_sent, mut new_price) =
+            (sent(swap.amount0_sent), sent(swap.amount1_sent), swap.price);
+        if new_price.is_zero() && pool_variant == PoolVariant::UniswapV2 {
+            // no Sync log to read the reserves from; the pair's reserves at the end of the tx's block
+            // are the closest we get without a fork (later txs in the block may have moved them)
+            let block = tx_receipt
+                .block_number
+                .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?;
+            let (reserve0, reserve1) =
+                get_reserves_v2(client, pool_address, block.as_u64()).await?;
+            new_price = get_price_v2(reserve0, reserve1, token0_decimals)?;
+            debug!(
+                "no Sync log, priced pool {:?} from its reserves at block {}",
+                pool_address, block
+            );
+        }
 
         let swap_0_for_1 = amount0_sent.gt(&0.into());
         debug!(
@@ -171,12 +271,30 @@ async fn derive_trade_params(
         );
         let token_in = if swap_0_for_1 { token0 } else { token1 };
         let token_out = if swap_0_for_1 { token1 } else { token0 };
+        if token_in != quote_asset.address {
+            // what the user's trade was worth in the quote asset, for scale; the search always spans the starting balance
+            let amount_in = if swap_0_for_1 {
+                amount0_sent
+            } else {
+                amount1_sent
+            };
+            if let Ok(value) = convert_at_price(
+                amount_in.into_raw(),
+                new_price,
+                token0_decimals,
+                swap_0_for_1,
+            ) {
+                debug!(%value, quote = ?quote_asset.address, "user's trade in the quote asset");
+            }
+        }
         // find all pairs that aren't the one that the user swapped on
-        let arb_pools: Vec<PairPool> = get_all_trading_pools(client, (token_in, token_out))
+        let arb_pools: Vec<PairPool> = registry
+            .find_pools(client, adapters, (token_in, token_out))
             .await?
             .into_iter()
             .filter(|pool| !pool.address.is_zero())
             .filter(|pool| pool.address != pool_address)
+            .filter(|pool| lists.allows_pool(&pool.address))
             .collect();
         trade_params.push(UserTradeParams {
             pool_variant,
@@ -185,49 +303,90 @@ async fn derive_trade_params(
             amount0_sent,
             amount1_sent,
             pool: pool_address,
+            factory: registry.factory(client, pool_address).await.ok(),
             arb_pools,
             price: new_price,
-            token0_is_weth,
+            token0_is_quote,
             tokens: TokenPair {
-                weth: if token0_is_weth { token0 } else { token1 },
-                token: if token0_is_weth { token1 } else { token0 },
+                quote: quote_asset.address,
+                token: if token0_is_quote { token1 } else { token0 },
             },
+            quote_asset,
         })
     }
     Ok(trade_params)
 }
 
+/// A backrun route that `step_arb` searches, with what every step of the search shares.
+struct RouteSearch {
+    /// State after the user's tx (see `sim_user_tx`), which every attempt backruns a copy of.
+    post_user_evm: EVM<ForkDB>,
+    user_tx: Arc<Transaction>,
+    block_info: BlockInfo,
+    params: Arc<UserTradeParams>,
+    /// Number of amounts tried in each step.
+    intervals: usize,
+    start_pair_variant: (Address, PoolVariant),
+    end_pair_variant: (Address, PoolVariant),
+    adapters: PoolAdapters,
+    budget: SimBudget,
+    tx_budget: TxSimBudget,
+    sim_cache: Option<SimCache>,
+    /// Outcome of every attempt that ran.
+    curve: SearchCurve,
+}
+
 /// Recursively finds the best possible arbitrage trade for a given set of params.
+///
+/// Every attempt backruns a copy of the route's `post_user_evm` while holding a permit of its `budget`.
+/// Attempts count against its `tx_budget`; once it runs out, the best so far is returned.
+/// The outcome of every attempt that ran is added to its `curve`.
+#[tracing::instrument(level = "debug", skip_all, fields(
+    tx_hash = ?route.user_tx.hash,
+    depth = depth.unwrap_or(0),
+    start_pool = ?route.start_pair_variant.0,
+    end_pool = ?route.end_pair_variant.0,
+))]
 #[async_recursion]
 async fn step_arb(
-    client: WsClient,
-    user_tx: Transaction,
-    block_info: BlockInfo,
-    params: UserTradeParams,
+    route: &RouteSearch,
     best_amount_in_out: Option<(U256, U256)>,
     range: [U256; 2],
-    intervals: usize,
     depth: Option<usize>,
-    start_pair_variant: (Address, PoolVariant),
-    end_pair_variant: (Address, PoolVariant),
 ) -> Result<(U256, U256)> {
+    let RouteSearch {
+        post_user_evm,
+        user_tx,
+        block_info,
+        params,
+        adapters,
+        budget,
+        tx_budget,
+        sim_cache,
+        curve,
+        ..
+    } = route;
+    let (intervals, start_pair_variant, end_pair_variant) = (
+        route.intervals,
+        route.start_pair_variant,
+        route.end_pair_variant,
+    );
     info!(
-        "step_arb
-        best (weth_in, weth_bal)\t{:?}
-        depth:\t{:?}
-        range:\t{:?}
-        user_tx:\t{:?}
-        (start_pair, variant):\t{:?}
-        (end_pair, variant):\t{:?}
-    ",
-        best_amount_in_out, depth, range, user_tx.hash, start_pair_variant, end_pair_variant
+        tx_hash = ?user_tx.hash,
+        depth = depth.unwrap_or(0),
+        start_pool = ?start_pair_variant.0,
+        end_pool = ?end_pair_variant.0,
+        ?best_amount_in_out,
+        ?range,
+        "step_arb"
     );
     // unwrap current best result or assign defaults for init case
+    let start_balance = params.quote_asset.starting_balance;
     let (mut best_amount_in, mut best_amount_out) =
-        best_amount_in_out.unwrap_or((0.into(), braindance_starting_balance()));
+        best_amount_in_out.unwrap_or((0.into(), start_balance));
 
     // convenience closures for stop cases
-    let done_unprofitable = || return Ok((0.into(), braindance_starting_balance()));
+    let done_unprofitable = || return Ok((0.into(), start_balance));
     let done_profitable = || return Ok((best_amount_in, best_amount_out));
 
     /*  ============================================================
@@ -239,40 +398,36 @@ async fn step_arb(
     }
     // if the ranges get tight enough together, we can quit early
     // we'll call a 0.1% difference "tight enough"
-    if (range[1] - range[0]) <= (range[0] / 1000) {
+    if range[1].saturating_sub(range[0]) <= (range[0] / 1000) {
         info!("range tight enough, finishing early {:?}", range);
         return done_profitable();
     }
     /*  INIT CASE:
        User possibly passed None for best_amount_in_out, so we use our catch-all values defined above and recurse.
     */
-    if depth.is_none() {
+    let Some(depth) = depth else {
         return step_arb(
-            client,
-            user_tx,
-            block_info,
-            params,
+            route,
             Some((best_amount_in, best_amount_out)),
             range,
-            intervals,
             Some(0),
-            start_pair_variant,
-            end_pair_variant,
         )
         .await;
+    };
+    // stop case: the tx has used up its sims or time, so settle for the best found so far
+    if tx_budget.is_exhausted() {
+        return done_profitable();
     }
-    // this could just be unwrapped, but paranoia is good when it's just in your code.
-    let depth = depth.expect("depth should have been defined (recursively) by this point.");
 
     // stop case: we have recursed three times and the range minimum is STILL 0, AND no profit
-    if range[0] == 0.into() && depth >= 3 && best_amount_out <= braindance_starting_balance() {
+    if range[0] == 0.into() && depth >= 3 && best_amount_out <= start_balance {
         // Return (0, start_balance) to indicate that there was no arbitrage opportunity,
         // but the arb params (tokens, pools, etc) were still valid.
         // This ensures that the attempt is logged in the DB.
         info!("amount_in trending towards zero, quitting sim.");
         return done_unprofitable();
     }
-    // stop case: we hit the max depth, or the best amount of WETH in is lower than the gas cost of the backrun tx
+    // stop case: we hit the max depth, or the best amount of quote asset in is lower than the gas cost of the backrun tx
     if depth > MAX_DEPTH {
         info!("depth limit reached, quitting sim.");
         return done_profitable();
@@ -282,42 +437,65 @@ async fn step_arb(
     ============== PARALLEL SIMULATION PROCESSING ==================
     ============================================================  */
     // run sims with current params
-    let mut handles = vec![];
+    let mut sims = vec![];
     let band_width = (range[1] - range[0]) / U256::from(intervals);
     for i in 0..intervals {
         // prep data for consumption by async task
         let amount_in = range[0] + band_width * U256::from(i);
-        let user_tx = user_tx.clone();
+        let evm = post_user_evm.clone();
+        let tx_hash = user_tx.hash;
         let block_info = block_info.clone();
         let params = params.clone();
-        let client = client.clone();
-        // spawn the task, hold on to its handle
-        handles.push(tokio::task::spawn(async move {
-            let evm = fork_evm(&client, &block_info).await?;
-            sim_arb_single(
-                evm,
-                user_tx,
-                &block_info,
-                &params,
-                amount_in,
-                start_pair_variant,
-                end_pair_variant,
-            )
-            .await
-        }));
+        let adapters = adapters.clone();
+        let budget = budget.clone();
+        let tx_budget = tx_budget.clone();
+        let sim_cache = sim_cache.clone();
+        let key = sim_key(
+            block_info.number.as_u64(),
+            tx_hash,
+            &params,
+            start_pair_variant,
+            end_pair_variant,
+            amount_in,
+        );
+        // run the task in this step's span
+        let sim = async move {
+            let sim = async move {
+                if !tx_budget.try_spend() {
+                    return Err(anyhow::anyhow!("tx sim budget exhausted"));
+                }
+                let _permit = budget.acquire().await?;
+                run_blocking(move || {
+                    sim_arb_single(
+                        evm,
+                        tx_hash,
+                        &block_info,
+                        &params,
+                        amount_in,
+                        start_pair_variant,
+                        end_pair_variant,
+                        &adapters,
+                    )
+                })
+                .await
+            };
+            run_cached(sim_cache.as_ref(), key, amount_in, sim).await
+        };
+        sims.push(sim.in_current_span());
     }
 
     /*  ============================================================
     ===================== RESULT FILTERING =========================
     ============================================================  */
-    let revenues = future::join_all(handles).await;
+    let revenues = run_tasks(sims, tx_budget.limits().deterministic).await;
     let revenue_len = revenues.len();
+    let prev_amount_out = best_amount_out;
     let mut num_reverts = 0;
     // pick best result and update best_amount_in & best_amount_out
     for result in revenues {
-        if let Ok(result) = result {
-            if let Ok(result) = result {
-                let (amount_in, balance_out) = result;
+        match result {
+            Ok(Ok((amount_in, balance_out, _gas_used))) => {
+                curve.lock().unwrap().insert(amount_in, balance_out);
                 if balance_out > best_amount_out {
                     best_amount_in = amount_in;
                     best_amount_out = balance_out;
@@ -326,74 +504,175 @@ async fn step_arb(
                         best_amount_in_out
                     );
                 }
-            } else {
+            }
+            Ok(Err(err)) => {
                 // TODO: use real error types, not this garbage
-                let err = result.as_ref().unwrap_err().to_string();
+                let err = err.to_string();
                 debug!("{}", err);
                 if err.contains("no other pool found") {
                     // fail the whole batch by returning this error immediately
-                    return result;
+                    return Err(anyhow::anyhow!(err));
                 } else if err.contains("swap reverted") {
                     num_reverts += 1;
                 }
             }
-        } else {
-            return Err(anyhow::anyhow!(
-                "system error in step_arb. error in a sim_arb_single result: {}",
-                result.as_ref().unwrap_err().to_string() // TODO: use a more idiomatic approach to returning the error w/ custom tagging data
-            ));
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "system error in step_arb. sim_arb_single task {}",
+                    panic_message(err)
+                ));
+            }
         }
         if num_reverts == revenue_len {
             return Err(anyhow::anyhow!("all swaps reverted"));
         }
     }
 
+    // stop case: this step barely improved on the last one, so deeper steps won't find much more
+    if let Some(min_improvement) = tx_budget.limits().min_improvement {
+        let prev_profit = prev_amount_out.saturating_sub(start_balance);
+        if is_diminishing(
+            prev_profit,
+            best_amount_out - prev_amount_out,
+            min_improvement,
+        ) {
+            info!(depth, "diminishing returns, finishing early");
+            return Ok((best_amount_in, best_amount_out));
+        }
+    }
+
     /*  ============================================================
     ===================== IM RECURSIIIIING =========================
     ============================================================  */
-    let r_amount: rU256 = best_amount_in.into();
     let range = [
-        if best_amount_in < band_width {
-            0.into()
-        } else {
-            best_amount_in - band_width
-        },
-        if U256::MAX - r_amount < band_width.into() {
-            U256::MAX.into()
-        } else {
-            best_amount_in + band_width
-        },
+        best_amount_in.saturating_sub(band_width),
+        best_amount_in.saturating_add(band_width),
     ];
     return step_arb(
-        client,
-        user_tx,
-        block_info,
-        params,
+        route,
         Some((best_amount_in, best_amount_out)),
         range,
-        intervals,
         Some(depth + 1),
-        start_pair_variant,
-        end_pair_variant,
     )
     .await;
 }
 
-/// Find the optimal backrun for a given tx.
+/// Runs each of `tasks` on a task of its own, or one after another if `sequential`, returning their outcomes in order.
+async fn run_tasks<T: Send + 'static>(
+    tasks: Vec<impl Future<Output = T> + Send + 'static>,
+    sequential: bool,
+) -> Vec<std::result::Result<T, tokio::task::JoinError>> {
+    if !sequential {
+        return future::join_all(tasks.into_iter().map(tokio::task::spawn)).await;
+    }
+    let mut outcomes = vec![];
+    for task in tasks {
+        outcomes.push(Ok(task.await));
+    }
+    outcomes
+}
+
+/// Returns the outcome of the backrun sim identified by `key` from `cache` if it's there,
+/// and otherwise runs `sim` (a `sim_arb_single` of `amount_in`) and keeps its outcome in `cache`.
+async fn run_cached(
+    cache: Option<&SimCache>,
+    key: H256,
+    amount_in: U256,
+    sim: impl Future<Output = Result<(U256, U256, u64)>>,
+) -> Result<(U256, U256, u64)> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return sim.await,
+    };
+    if let Some(outcome) = cache.get(&key) {
+        return outcome.to_result(amount_in);
+    }
+    let res = sim.await;
+    if let Some(outcome) = SimOutcome::of(&res) {
+        cache.insert(key, outcome);
+    }
+    res
+}
+
+/// Whether a step that raised the best profit by `improvement` over `prev_profit` gained less than
+/// `min_improvement` (relative to `prev_profit`). Never true before a step has found some profit.
+fn is_diminishing(prev_profit: U256, improvement: U256, min_improvement: f64) -> bool {
+    !prev_profit.is_zero() && compare_share(improvement, prev_profit, min_improvement).is_lt()
+}
+
+/// Find the optimal backrun for a given tx, starting & ending in one of the search's `quote_assets`.
+///
+/// Tokens & pools excluded by its `lists` aren't traded, and pools are priced & traded with their adapter in `adapters`.
+/// Every sim forks the block from `forks`, and forks, sims & RPC-heavy steps each hold a permit of `budget`.
+/// The user's tx on each route and every backrun attempt count against `tx_budget`, which is shared by all routes.
+/// The tx's receipt is read from `receipts`, and its pools, tokens & pairs are looked up in `registry`.
+/// If `path_finder` is given, the cycles through more pools than the user's pair that it proposes are backrun, too.
+/// Backrun sims whose outcome is in `sim_cache` aren't run again, and the outcomes of those that run are added to it.
+/// If the tx is sampled by `audit`, what was decided about each of its pools & routes is recorded in it.
+/// Returns the backrun found against each pool, and a failure for each pool that couldn't be simulated.
+/// If the user's tx reverts when replayed on the block, no pool is tried and the tx fails as a whole.
 pub async fn find_optimal_backrun_amount_in_out(
-    client: &WsClient,
-    user_tx: Transaction,
+    search: &SearchContext<'_>,
+    user_tx: Arc<Transaction>,
     event: &EventHistory,
     block_info: &BlockInfo,
-) -> Result<Vec<SimArbResult>> {
-    let start_balance = braindance_starting_balance();
-    let params = derive_trade_params(client, user_tx.to_owned(), event).await?;
-    info!("params {:?}", params);
+    tx_budget: &TxSimBudget,
+) -> Result<(Vec<SimArbResult>, Vec<SimFailure>)> {
+    let SearchContext {
+        client,
+        wrapped_native,
+        lists,
+        adapters,
+        forks,
+        budget,
+        receipts,
+        registry,
+        path_finder,
+        sim_cache,
+        audit,
+        ..
+    } = *search;
+    let record = |pool, decision, error: Option<&str>| {
+        if let Some(audit) = audit {
+            audit.record(event, pool, decision, error);
+        }
+    };
+    let permit = budget.acquire().await?;
+    let params = derive_trade_params(search, &user_tx, event).await?;
+    drop(permit);
+    info!(
+        tx_hash = ?user_tx.hash,
+        num_trades = params.len(),
+        "derived trade params"
+    );
+    // cycles are priced at the state the user's tx left its pools in
+    let receipt_logs = match path_finder {
+        Some(_) if !params.is_empty() => receipts
+            .get(client, user_tx.hash)
+            .await?
+            .map(|receipt| receipt.logs)
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+    debug!("params {:?}", params);
 
-    // look at price (TKN/ETH) on each exchange to determine which exchange to arb on
+    // every route replays the user's tx on the same block, so if it doesn't go through once
+    // (e.g. its deadline is behind the forked block), searching for backruns of it is pointless
+    if !params.is_empty() {
+        let _permit = budget.acquire().await?;
+        let mut evm = forks.fork(client, block_info).await?;
+        let tx = user_tx.as_ref().to_owned();
+        if let Err(err) = run_blocking(move || commit_user_tx(&mut evm, tx)).await {
+            info!(tx_hash = ?user_tx.hash, %err, "user tx reverted on the fork, skipping its backruns");
+            record(None, AuditDecision::UserTxReverted, Some(&err.to_string()));
+            return Ok((vec![], vec![SimFailure::new(event, None, &err.to_string())]));
+        }
+    }
+
+    // look at price (TKN/QUOTE) on each exchange to determine which exchange to arb on
     // if priceA > priceB after user tx creates price impact, then buy TKN on exchange B and sell on exchange A
 
-    let mut pool_handles = vec![];
+    let mut pool_sims = vec![];
     /*
      Δ
     Δ Δ Branch for each pool.
@@ -405,7 +684,7 @@ pub async fn find_optimal_backrun_amount_in_out(
                                            / \       / \ \
                                           /   \     /   \ \
                                          /     \   /     \ ...
-    [pool_handles] <--bg thread <-- ... pool,pool,pool,pool
+    [pool_sims]    <--bg thread <-- ... pool,pool,pool,pool
                                           |
                                         step_arb
                                          / \
@@ -415,164 +694,661 @@ pub async fn find_optimal_backrun_amount_in_out(
     Simulate an arb for every pool and throw out the ones that
     don't turn a profit.
 
-    `pool_handles` will hold the joinable background thread handlers,
+    `pool_sims` will hold the sims, each run in a background thread,
     each of which will return a result. Each handle is responsible for
     determining whether it was profitable, and terminating its execution
     early if it finds a failure case.
     When we join the results, we'll filter out the error/null values,
     which leaves us with only the profitable sims.
     */
+    let mut failures = vec![];
+    // pool that each handle backruns against, to attribute failures
+    let mut handle_pools = vec![];
     for params in params {
-        if params.arb_pools.len() == 0 {
+        let cycles = path_finder
+            .map(|finder| finder.candidates(&params, &receipt_logs))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|cycle| {
+                cycle
+                    .hops
+                    .iter()
+                    .all(|hop| lists.allows_pool(&hop.pool) && lists.allows_token(&hop.token_out))
+            })
+            .collect::<Vec<_>>();
+        if params.arb_pools.len() == 0 && cycles.is_empty() {
             debug!("skipping this set of params, no arb pools found.");
+            let err: Error = HindsightError::PoolNotFound(params.pool).into();
+            record(Some(params.pool), AuditDecision::NoAltPool, None);
+            failures.push(SimFailure::new(event, Some(params.pool), &err.to_string()));
             continue;
         }
-        for other_pool in params.arb_pools.to_owned() {
+        // shared by every route's tasks, which only read it
+        let params = Arc::new(params);
+        // quote every other pool on one fork, rather than forking an EVM per route just to read its price,
+        // then screen the token on it; every route trades the same token, so all of them are skipped if it's unsafe
+        let (alt_prices, screened) = {
+            let _permit = budget.acquire().await?;
+            let mut evm = forks.fork(client, block_info).await?;
+            let alt_prices = quote_prices(&mut evm, adapters, &params).await;
+            // tokens whose taxes were measured ahead of time (see `sim::tax`) are screened by them
+            let measured = registry.token_tax(params.tokens.token);
+            let screened = match (tx_budget.limits().max_token_tax, measured) {
+                (Some(max_tax), Some(tax)) => tax.check(max_tax),
+                (Some(max_tax), None) => {
+                    let adapters = adapters.clone();
+                    let (params, base_fee) = (params.clone(), block_info.base_fee);
+                    run_blocking(move || {
+                        let adapter = adapters.get(params.pool_variant)?;
+                        screen_token(&mut evm, adapter, &params, base_fee, max_tax)
+                    })
+                    .await
+                }
+                (None, _) => Ok(()),
+            };
+            (alt_prices, screened)
+        };
+        if let Err(err) = screened {
+            info!(pool = ?params.pool, %err, "token failed the safety screen, skipping its routes");
+            record(
+                Some(params.pool),
+                AuditDecision::UnsafeToken,
+                Some(&err.to_string()),
+            );
+            failures.push(SimFailure::new(event, Some(params.pool), &err.to_string()));
+            continue;
+        }
+        record(Some(params.pool), AuditDecision::Routed, None);
+        // gas & coinbase payments are paid in the native token, so they only apply to backruns quoted in it
+        let quoted_in_native = wrapped_native == Some(params.tokens.quote);
+        let min_price_gap = tx_budget.limits().min_price_gap;
+        let gas_cost = (min_price_gap.is_some() && quoted_in_native)
+            .then(|| U256::from(ESTIMATED_BACKRUN_GAS).saturating_mul(block_info.base_fee));
+        let mut routes = vec![];
+        for (other_pool, alt_price) in params.arb_pools.to_owned().into_iter().zip(alt_prices) {
+            let alt_price = match alt_price {
+                Ok(alt_price) => alt_price,
+                Err(err) => {
+                    let err = format!("failed to quote price: {}", err);
+                    record(
+                        Some(other_pool.address),
+                        AuditDecision::QuoteFailed,
+                        Some(&err),
+                    );
+                    failures.push(SimFailure::new(event, Some(other_pool.address), &err));
+                    continue;
+                }
+            };
+            debug!("alt price {:?}", alt_price);
+            let Some((start, end)) = route_pools(&params, &other_pool, alt_price) else {
+                debug!(
+                    other_pool = ?other_pool.address,
+                    "pools are priced the same, skipping route"
+                );
+                record(Some(other_pool.address), AuditDecision::SamePrice, None);
+                continue;
+            };
+            if let Some(min_price_gap) = min_price_gap {
+                if !clears_price_gap(params.price, alt_price, min_price_gap) {
+                    debug!(
+                        other_pool = ?other_pool.address,
+                        "price gap too small to arb, skipping route"
+                    );
+                    record(Some(other_pool.address), AuditDecision::BelowSpread, None);
+                    continue;
+                }
+            }
+            if let Some(gas_cost) = gas_cost {
+                let start_balance = params.quote_asset.starting_balance;
+                if !covers_gas(params.price, alt_price, start_balance, gas_cost) {
+                    debug!(
+                        other_pool = ?other_pool.address,
+                        %gas_cost,
+                        "price gap can't pay for the backrun's gas, skipping route"
+                    );
+                    record(Some(other_pool.address), AuditDecision::BelowGasCost, None);
+                    continue;
+                }
+            }
+            routes.push((other_pool, start, end, spread_ppb(params.price, alt_price)));
+        }
+        // widest gaps first, so they're the routes that get the tx's sims if its budget runs short
+        routes.sort_by_key(|route| std::cmp::Reverse(route.3));
+        for (other_pool, (start_pool, start_pool_variant), (end_pool, end_pool_variant), _) in
+            routes
+        {
+            handle_pools.push(other_pool.address);
+            record(Some(other_pool.address), AuditDecision::Simulated, None);
             let client = client.clone();
             let user_tx = user_tx.clone();
             let block_info = block_info.clone();
             let params = params.clone();
+            let adapters = adapters.clone();
+            let forks = forks.clone();
+            let budget = budget.clone();
+            let tx_budget = tx_budget.clone();
+            let sim_cache = sim_cache.cloned();
+            let span = tracing::info_span!(
+                "backrun_pool",
+                pool = ?params.pool,
+                other_pool = ?other_pool.address
+            );
             /* SPAWN A NEW (GREEN) THREAD */
-            let handle = tokio::task::spawn(async move {
-                let mut evm = fork_evm(&client, &block_info)
-                    .await
-                    .expect("failed to fork evm");
-
-                // find price on other exchange
-                let alt_price = match other_pool.variant {
-                    PoolVariant::UniswapV2 => sim_price_v2(
-                        other_pool.address,
-                        params.token_in,
-                        params.token_out,
-                        &mut evm,
-                    )
-                    .await
-                    .expect(&format!(
-                        "sim_price_v2 panicked. address={:?} token_in={:?} token_out={:?}",
-                        other_pool.address, params.token_in, params.token_out
-                    )),
-                    PoolVariant::UniswapV3 => sim_price_v3(
-                        other_pool.address,
-                        params.token_in,
-                        params.token_out,
-                        &mut evm,
-                    )
-                    .await
-                    .expect(&format!(
-                        "sim_price_v3 panicked. address={:?} token_in={:?} token_out={:?}",
-                        other_pool.address, params.token_in, params.token_out
-                    )),
-                };
-                debug!("alt price {:?}", alt_price);
-
-                let (start_pool, start_pool_variant, end_pool, end_pool_variant) =
-                    if params.token0_is_weth {
-                        // if tkn0 is weth, then price is denoted in tkn1/eth, so look for highest price
-                        if params.price.gt(&alt_price) {
-                            (
-                                params.pool,
-                                params.pool_variant,
-                                other_pool.address,
-                                other_pool.variant,
-                            )
-                        } else {
-                            (
-                                other_pool.address,
-                                other_pool.variant,
-                                params.pool,
-                                params.pool_variant,
-                            )
-                        }
+            let sim = async move {
+                // the user's tx is this route's first EVM execution
+                if !tx_budget.try_spend() {
+                    return Err(anyhow::anyhow!(
+                        "tx sim budget exhausted before this route was tried"
+                    ));
+                }
+                let coinbase_share = tx_budget.limits().coinbase_share;
+                // released before `step_arb`, which spawns sims that need permits of their own
+                let permit = budget.acquire().await?;
+
+                let factory_of = |pool: Address| {
+                    if pool == params.pool {
+                        params.factory
                     } else {
-                        // else if tkn1 is weth, then price is denoted in eth/tkn0, so look for lowest price
-                        if params.price.gt(&alt_price) {
-                            (
-                                other_pool.address,
-                                other_pool.variant,
-                                params.pool,
-                                params.pool_variant,
-                            )
-                        } else {
-                            (
-                                params.pool,
-                                params.pool_variant,
-                                other_pool.address,
-                                other_pool.variant,
-                            )
-                        }
-                    };
+                        other_pool.factory
+                    }
+                };
+                let (start_factory, end_factory) = (factory_of(start_pool), factory_of(end_pool));
+
+                // set amount_in_start to the arb contract's balance of the quote asset
+                let start_balance = params.quote_asset.starting_balance;
+                let initial_range = [0.into(), start_balance];
 
-                // set amount_in_start to the arb contract balance; ours has 420 WETH
-                let initial_range = [0.into(), braindance_starting_balance()];
-
-                // a new EVM is spawned inside this function, where the user tx is executed on a fresh fork before our backrun
-                let res = step_arb(
-                    client.clone(),
-                    user_tx,
-                    block_info,
-                    params.to_owned(),
-                    None,
-                    initial_range,
-                    STEP_INTERVALS,
-                    None,
-                    (start_pool, start_pool_variant),
-                    (end_pool, end_pool_variant),
+                // the user tx is executed once for this route, and every backrun attempt starts from its state
+                let post_user_evm = sim_user_tx(
+                    forks.fork(&client, &block_info).await?,
+                    &user_tx,
+                    &params.quote_asset,
                 )
-                .await;
+                .await?;
+                drop(permit);
+                let route = RouteSearch {
+                    post_user_evm: post_user_evm.clone(),
+                    user_tx: user_tx.clone(),
+                    block_info: block_info.clone(),
+                    params: params.clone(),
+                    intervals: STEP_INTERVALS,
+                    start_pair_variant: (start_pool, start_pool_variant),
+                    end_pair_variant: (end_pool, end_pool_variant),
+                    adapters: adapters.clone(),
+                    budget: budget.clone(),
+                    tx_budget,
+                    sim_cache: sim_cache.clone(),
+                    curve: SearchCurve::default(),
+                };
+                let res = step_arb(&route, None, initial_range, None).await;
                 debug!("*** step_arb complete: {:?}", res);
-                if let Ok(res) = res {
-                    Some(SimArbResult {
-                        user_trade: params,
-                        backrun_trade: BackrunResult {
-                            amount_in: res.0,
-                            balance_end: res.1,
-                            profit: if res.1 > start_balance {
-                                res.1 - start_balance
-                            } else {
-                                0.into()
-                            },
-                            start_pool: start_pool,
-                            end_pool: end_pool,
-                            start_variant: start_pool_variant,
-                            end_variant: end_pool_variant,
-                        },
-                    })
+                let res = res?;
+                let profit_curve = profit_curve(&route.curve.lock().unwrap(), start_balance);
+                // the payment is in the native token, so only backruns that profit in it (wrapped) can make it
+                let coinbase_payment = match coinbase_share {
+                    Some(share) if res.1 > start_balance && quoted_in_native => {
+                        let _permit = budget.acquire().await?;
+                        let (evm, block_info, params, adapters) = (
+                            post_user_evm.clone(),
+                            block_info.clone(),
+                            params.clone(),
+                            adapters.clone(),
+                        );
+                        let amount_in = res.0;
+                        // the backrun's bundle: the best backrun, then the payment
+                        let payment = run_blocking(move || {
+                            let mut evm = evm;
+                            let (_, balance_out, gas_used) = sim_backrun_swaps(
+                                &mut evm,
+                                &block_info,
+                                &params.tokens,
+                                start_balance,
+                                amount_in,
+                                (start_pool, start_pool_variant),
+                                (end_pool, end_pool_variant),
+                                &adapters,
+                            )?;
+                            let profit = balance_out.saturating_sub(start_balance);
+                            pay_coinbase(&mut evm, &block_info, profit, gas_used, share)
+                        })
+                        .await?;
+                        Some(payment)
+                    }
+                    _ => None,
+                };
+                // re-run the best backrun to measure its gas, so gas costs can be evaluated later without re-simulating
+                // (with a cache, the search already kept it)
+                let gas_used = if res.1 > start_balance {
+                    let key = sim_key(
+                        block_info.number.as_u64(),
+                        user_tx.hash,
+                        &params,
+                        (start_pool, start_pool_variant),
+                        (end_pool, end_pool_variant),
+                        res.0,
+                    );
+                    let sim = async {
+                        let _permit = budget.acquire().await?;
+                        let params = params.clone();
+                        run_blocking(move || {
+                            sim_arb_single(
+                                post_user_evm,
+                                user_tx.hash,
+                                &block_info,
+                                &params,
+                                res.0,
+                                (start_pool, start_pool_variant),
+                                (end_pool, end_pool_variant),
+                                &adapters,
+                            )
+                        })
+                        .await
+                    };
+                    run_cached(sim_cache.as_ref(), key, res.0, sim)
+                        .await
+                        .map(|(_, _, gas_used)| gas_used)
+                        .ok()
                 } else {
                     None
+                };
+                Ok::<_, Error>(SimArbResult {
+                    user_trade: params.as_ref().to_owned(),
+                    backrun_trade: BackrunResult {
+                        amount_in: res.0,
+                        balance_end: res.1,
+                        profit: if res.1 > start_balance {
+                            res.1 - start_balance
+                        } else {
+                            0.into()
+                        },
+                        start_pool,
+                        end_pool,
+                        start_variant: start_pool_variant,
+                        end_variant: end_pool_variant,
+                        start_factory,
+                        end_factory,
+                        gas_used,
+                        coinbase_payment,
+                        profit_curve,
+                        path: vec![],
+                    },
+                })
+            };
+            pool_sims.push(sim.instrument(span).boxed());
+        }
+
+        /* Backrun each cycle through more pools, proposed from the pool graph. */
+        for cycle in cycles {
+            let (first, last) = (cycle.hops[0], cycle.hops[cycle.hops.len() - 1]);
+            handle_pools.push(first.pool);
+            record(Some(first.pool), AuditDecision::Simulated, None);
+            let client = client.clone();
+            let user_tx = user_tx.clone();
+            let block_info = block_info.clone();
+            let params = params.clone();
+            let adapters = adapters.clone();
+            let forks = forks.clone();
+            let budget = budget.clone();
+            let tx_budget = tx_budget.clone();
+            let span = tracing::info_span!(
+                "backrun_cycle",
+                pool = ?params.pool,
+                hops = cycle.hops.len()
+            );
+            let sim = async move {
+                if !tx_budget.try_spend() {
+                    return Err(anyhow::anyhow!(
+                        "tx sim budget exhausted before this cycle was tried"
+                    ));
                 }
-            });
-            pool_handles.push(handle);
+                let permit = budget.acquire().await?;
+                let post_user_evm = sim_user_tx(
+                    forks.fork(&client, &block_info).await?,
+                    &user_tx,
+                    &params.quote_asset,
+                )
+                .await?;
+                drop(permit);
+                let hops = Arc::new(cycle.hops);
+                let ((amount_in, balance_out, gas_used), profit_curve) = search_cycle(
+                    post_user_evm.clone(),
+                    &block_info,
+                    &params,
+                    hops.clone(),
+                    &adapters,
+                    &budget,
+                    &tx_budget,
+                )
+                .await?;
+                let start_balance = params.quote_asset.starting_balance;
+                let profitable = balance_out > start_balance;
+                // the same bundle as a route's: the best backrun, then the payment
+                let coinbase_payment = match tx_budget.limits().coinbase_share {
+                    Some(share) if profitable && quoted_in_native => {
+                        let _permit = budget.acquire().await?;
+                        let (block_info, hops) = (block_info.clone(), hops.clone());
+                        let payment = run_blocking(move || {
+                            let mut evm = post_user_evm;
+                            sim_cycle_swaps(
+                                &mut evm,
+                                &block_info,
+                                &hops,
+                                start_balance,
+                                amount_in,
+                                &adapters,
+                            )?;
+                            let profit = balance_out - start_balance;
+                            pay_coinbase(&mut evm, &block_info, profit, gas_used, share)
+                        })
+                        .await?;
+                        Some(payment)
+                    }
+                    _ => None,
+                };
+                let factory_of = |pool: Address| {
+                    if pool == params.pool {
+                        params.factory
+                    } else {
+                        None
+                    }
+                };
+                Ok::<_, Error>(SimArbResult {
+                    user_trade: params.as_ref().to_owned(),
+                    backrun_trade: BackrunResult {
+                        amount_in,
+                        balance_end: balance_out,
+                        profit: balance_out.saturating_sub(start_balance),
+                        start_pool: first.pool,
+                        end_pool: last.pool,
+                        start_variant: first.variant,
+                        end_variant: last.variant,
+                        start_factory: factory_of(first.pool),
+                        end_factory: factory_of(last.pool),
+                        gas_used: profitable.then_some(gas_used),
+                        coinbase_payment,
+                        profit_curve,
+                        path: hops.to_vec(),
+                    },
+                })
+            };
+            pool_sims.push(sim.instrument(span).boxed());
         }
     }
 
-    // Collect all the results for this batch, filter out any errors or empty results before returning.
-    let results: Vec<_> = future::join_all(pool_handles).await;
-    Ok(results
-        .into_iter()
-        .filter(|res| res.is_ok())
-        .map(|res| res.unwrap())
-        .filter(|res| res.is_some())
-        .map(|res| res.to_owned().unwrap())
-        .collect::<Vec<_>>())
+    // Collect all the results for this batch, setting errors aside as failures.
+    // in deterministic mode, routes run one after another, widest gap first
+    let results = run_tasks(pool_sims, tx_budget.limits().deterministic).await;
+    let mut sims = vec![];
+    for (pool, res) in handle_pools.into_iter().zip(results) {
+        let res = res
+            .map_err(panic_message)
+            .and_then(|res| res.map_err(|err| err.to_string()));
+        let decision = AuditDecision::of_sim(
+            res.as_ref().map_err(|err| err.as_str()),
+            tx_budget.truncated(),
+        );
+        record(
+            Some(pool),
+            decision,
+            res.as_ref().err().map(|err| err.as_str()),
+        );
+        match res {
+            Ok(res) => sims.push(res),
+            Err(err) => failures.push(SimFailure::new(event, Some(pool), &err)),
+        }
+    }
+    Ok((sims, failures))
 }
 
-/// Simulate a two-step arbitrage on a forked EVM with fixed trade amount & path.
+/// Re-simulates the backrun of `result` from scratch: on a fork of `block_info` built afresh (rather than taken from a
+/// `ForkCache`), without reading a `SimCache`, while holding a permit of `budget`.
 ///
-/// 1. Buy `amount_in` WETH worth of token on start_pair
+/// A backrun around a cycle (one with a `path`) is re-simulated hop by hop.
+///
+/// Returns the backrun's balance out, which matches `result.backrun_trade.balance_end` if the sims are deterministic.
+pub async fn resimulate_backrun(
+    client: &WsClient,
+    user_tx: &Transaction,
+    block_info: &BlockInfo,
+    result: &SimArbResult,
+    adapters: &PoolAdapters,
+    budget: &SimBudget,
+) -> Result<U256> {
+    let _permit = budget.acquire().await?;
+    let factory = fork_factory(client, block_info).await?;
+    let params = result.user_trade.to_owned();
+    let evm = sim_user_tx(
+        fork_from(&factory, block_info),
+        user_tx,
+        &params.quote_asset,
+    )
+    .await?;
+    let trade = result.backrun_trade.to_owned();
+    let (tx_hash, block_info, adapters) =
+        (user_tx.hash, block_info.to_owned(), adapters.to_owned());
+    let (_, balance_end, _) = run_blocking(move || {
+        if !trade.path.is_empty() {
+            let mut evm = evm;
+            return sim_cycle_swaps(
+                &mut evm,
+                &block_info,
+                &trade.path,
+                params.quote_asset.starting_balance,
+                trade.amount_in,
+                &adapters,
+            );
+        }
+        sim_arb_single(
+            evm,
+            tx_hash,
+            &block_info,
+            &params,
+            trade.amount_in,
+            (trade.start_pool, trade.start_variant),
+            (trade.end_pool, trade.end_variant),
+            &adapters,
+        )
+    })
+    .await?;
+    Ok(balance_end)
+}
+
+/// Quotes the price of each of `params.arb_pools` on `evm` with its adapter in `adapters`, in order.
+async fn quote_prices(
+    evm: &mut EVM<ForkDB>,
+    adapters: &PoolAdapters,
+    params: &UserTradeParams,
+) -> Vec<Result<U256>> {
+    let mut prices = vec![];
+    for pool in &params.arb_pools {
+        let price = match adapters.get(pool.variant) {
+            Ok(adapter) => {
+                adapter
+                    .quote_price(evm, pool.address, params.token_in, params.token_out)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        prices.push(price);
+    }
+    prices
+}
+
+/// Orders a route between the user's pool and `other_pool` as ((start pool, variant), (end pool, variant)):
+/// the backrun buys the token where it's cheaper, and sells it where it's dearer.
 ///
-/// 2. Sell balance of token on end_pair for WETH, completing the arb.
-async fn sim_arb_single(
+/// Returns None if both pools quote the same (known) price, since there's nothing to arb.
+/// If either price is unknown (zero), the direction is a guess, left to the sims to prove.
+fn route_pools(
+    params: &UserTradeParams,
+    other_pool: &PairPool,
+    alt_price: U256,
+) -> Option<((Address, PoolVariant), (Address, PoolVariant))> {
+    if !alt_price.is_zero() && params.price == alt_price {
+        return None;
+    }
+    let user_pool = (params.pool, params.pool_variant);
+    let other_pool = (other_pool.address, other_pool.variant);
+    // if tkn0 is the quote asset, then price is denoted in tkn1/quote, so the token is cheapest at the highest price;
+    // else if tkn1 is the quote asset, then price is denoted in quote/tkn0, so it's cheapest at the lowest price
+    let user_pool_cheaper = (params.price > alt_price) == params.token0_is_quote;
+    Some(match user_pool_cheaper {
+        true => (user_pool, other_pool),
+        false => (other_pool, user_pool),
+    })
+}
+
+/// Gap between two pools' prices in parts per billion of the higher one, or None if either is unknown (zero).
+fn spread_ppb(price: U256, alt_price: U256) -> Option<U256> {
+    if price.is_zero() || alt_price.is_zero() {
+        return None;
+    }
+    let (low, high) = (price.min(alt_price), price.max(alt_price));
+    checked_mul_div(high - low, PPB.into(), high).ok()
+}
+
+/// Whether a route between pools at these prices could make enough to pay `gas_cost` (in the quote asset).
+/// No backrun makes more than the price gap on the whole `start_balance`, so a route that can't is skipped.
+/// An unknown (zero) price always could, like in `clears_price_gap`.
+fn covers_gas(price: U256, alt_price: U256, start_balance: U256, gas_cost: U256) -> bool {
+    if price.is_zero() || alt_price.is_zero() {
+        return true;
+    }
+    let (low, high) = (price.min(alt_price), price.max(alt_price));
+    checked_mul_div(start_balance, high - low, high)
+        .map_or(true, |max_profit| max_profit > gas_cost)
+}
+
+/// Whether the prices of two pools are at least `min_gap` apart, relative to the higher one.
+/// An unknown (zero) price always clears it, so its route is left to the sims to judge.
+fn clears_price_gap(price: U256, alt_price: U256, min_gap: f64) -> bool {
+    if price.is_zero() || alt_price.is_zero() {
+        return true;
+    }
+    let (low, high) = (price.min(alt_price), price.max(alt_price));
+    compare_share(high - low, high, min_gap).is_ge()
+}
+
+/// Funds the braindance contract on `evm` and executes the user's tx on it (on a blocking thread),
+/// returning the state that every backrun of the tx starts from. Fails if the user's tx doesn't go through.
+async fn sim_user_tx(
     mut evm: EVM<ForkDB>,
-    user_tx: Transaction,
+    user_tx: &Transaction,
+    quote_asset: &QuoteAsset,
+) -> Result<EVM<ForkDB>> {
+    let user_tx = user_tx.to_owned();
+    let quote_asset = quote_asset.to_owned();
+    run_blocking(move || {
+        fund_braindance(&mut evm, &quote_asset)?;
+        commit_user_tx(&mut evm, user_tx)?;
+        Ok(evm)
+    })
+    .await
+}
+
+/// Simulate a two-step arbitrage with fixed trade amount & path on `post_user_evm`,
+/// which already executed the user's tx (see `sim_user_tx`).
+///
+/// 1. Buy `amount_in` of the quote asset worth of token on start_pair
+///
+/// 2. Sell balance of token on end_pair for the quote asset, completing the arb.
+///
+/// Returns `(amount_in, balance_out, gas_used)`, where `gas_used` approximates the gas of the backrun as one tx.
+#[tracing::instrument(level = "debug", skip_all, fields(
+    ?tx_hash,
+    %amount_in,
+    start_pool = ?start_pair_variant.0,
+    end_pool = ?end_pair_variant.0,
+))]
+fn sim_arb_single(
+    mut post_user_evm: EVM<ForkDB>,
+    tx_hash: H256,
     block_info: &BlockInfo,
     params: &UserTradeParams,
     amount_in: U256,
     start_pair_variant: (Address, PoolVariant),
     end_pair_variant: (Address, PoolVariant),
-) -> Result<(U256, U256)> {
+    adapters: &PoolAdapters,
+) -> Result<(U256, U256, u64)> {
+    sim_backrun_swaps(
+        &mut post_user_evm,
+        block_info,
+        &params.tokens,
+        params.quote_asset.starting_balance,
+        amount_in,
+        start_pair_variant,
+        end_pair_variant,
+        adapters,
+    )
+}
+
+/// Pays `coinbase_share` of a backrun's `profit` to the block's coinbase on `evm`, which just ran the backrun
+/// (using `gas_used`), completing the backrun's bundle. The braindance controller pays it in the native token,
+/// standing in for the wrapped native token that the backrun made.
+///
+/// Returns the payment. Fails if the payment doesn't go through, or if `profit` doesn't cover the payment
+/// plus both txs' gas at the block's base fee, since the bundle would lose money.
+fn pay_coinbase(
+    evm: &mut EVM<ForkDB>,
+    block_info: &BlockInfo,
+    profit: U256,
+    gas_used: u64,
+    coinbase_share: f64,
+) -> Result<U256> {
+    let payment = coinbase_payment(profit, coinbase_share);
+    let payer = Address::from(braindance_controller_address().0);
+    let payment_gas = commit_coinbase_transfer(evm, payer, payment, block_info.base_fee)?;
+    let gas_cost = U256::from(gas_used + payment_gas).saturating_mul(block_info.base_fee);
+    if profit <= payment.saturating_add(gas_cost) {
+        return Err(anyhow::anyhow!(
+            "backrun profit ({}) doesn't cover its coinbase payment ({}) and gas ({})",
+            profit,
+            payment,
+            gas_cost
+        ));
+    }
+    Ok(payment)
+}
+
+/// Frontier of the profit curve that a route's search simulated (see `BackrunResult::profit_curve`),
+/// from the balance out of each amount in: the profitable amounts that earned more than every smaller amount.
+fn profit_curve(balances_out: &BTreeMap<U256, U256>, start_balance: U256) -> Vec<CurvePoint> {
+    let mut curve: Vec<CurvePoint> = vec![];
+    for (amount_in, balance_out) in balances_out {
+        let profit = balance_out.saturating_sub(start_balance);
+        if profit > curve.last().map(|point| point.profit).unwrap_or_default() {
+            curve.push(CurvePoint {
+                amount_in: *amount_in,
+                profit,
+            });
+        }
+    }
+    curve
+}
+
+/// Share of `profit` that a bundle paying `coinbase_share` of it pays the coinbase, rounded down.
+fn coinbase_payment(profit: U256, coinbase_share: f64) -> U256 {
+    apply_share(profit, coinbase_share)
+}
+
+/// Runs both swaps of a backrun on `evm`, which must already hold the braindance contract's funds.
+///
+/// Returns `(amount_in, balance_out, gas_used)` like `sim_arb_single`. `balance_out` is `start_balance` (what the
+/// contract was funded with) plus what the backrun gained, measured from the contract's actual balance when it starts,
+/// which differs for quote assets whose balances rebase (e.g. in the user's tx) or aren't stored as-is (e.g. as shares).
+fn sim_backrun_swaps(
+    evm: &mut EVM<ForkDB>,
+    block_info: &BlockInfo,
+    tokens: &TokenPair,
+    start_balance: U256,
+    amount_in: U256,
+    start_pair_variant: (Address, PoolVariant),
+    end_pair_variant: (Address, PoolVariant),
+    adapters: &PoolAdapters,
+) -> Result<(U256, U256, u64)> {
     let (start_pool, start_variant) = start_pair_variant;
     let (end_pool, end_variant) = end_pair_variant;
-    sim_bundle(&mut evm, vec![user_tx.to_owned()]).await?;
+    let balance_before = token_balance(evm, tokens.quote, braindance_address())?;
 
     /*
     - if the price is denoted in TKN/ETH, we want to buy where the price is highest
@@ -582,46 +1358,459 @@ async fn sim_arb_single(
 
     /* Buy tokens on one exchange. */
     let res = commit_braindance_swap(
-        &mut evm,
-        start_variant,
+        evm,
+        adapters.get(start_variant)?,
         amount_in,
         start_pool,
-        params.tokens.weth,
-        params.tokens.token,
+        tokens.quote,
+        tokens.token,
         block_info.base_fee,
         None,
     );
     debug!("braindance 1 completed. {:?}", res);
-    let amount_received = res.unwrap_or(0.into());
+    let (amount_received, start_gas_used) = res.unwrap_or((0.into(), 0));
     debug!("amount received {:?}", amount_received);
 
     /* Sell them on other exchange. */
     let res = commit_braindance_swap(
-        &mut evm,
-        end_variant,
+        evm,
+        adapters.get(end_variant)?,
         amount_received,
         end_pool,
-        params.tokens.token,
-        params.tokens.weth,
+        tokens.token,
+        tokens.quote,
         block_info.base_fee + (block_info.base_fee * 2500) / 10000,
         None,
     )?;
     debug!("braindance 2 completed. {:?}", res);
-    Ok((amount_in, res))
+    let (balance_out, end_gas_used) = res;
+    let balance_out = if balance_out >= balance_before {
+        start_balance.saturating_add(balance_out - balance_before)
+    } else {
+        start_balance.saturating_sub(balance_before - balance_out)
+    };
+    // both swaps were separate txs here, but a real backrun only pays the base tx cost once
+    let gas_used = (start_gas_used + end_gas_used).saturating_sub(TX_BASE_GAS);
+    Ok((amount_in, balance_out, gas_used))
+}
+
+/// Runs every swap of a cycle of `hops` on `evm`, which must already hold the braindance contract's funds:
+/// `amount_in` of the first hop's token (the quote asset) in, then all of each hop's output into the next.
+///
+/// Returns `(amount_in, balance_out, gas_used)` like `sim_backrun_swaps`.
+fn sim_cycle_swaps(
+    evm: &mut EVM<ForkDB>,
+    block_info: &BlockInfo,
+    hops: &[RouteHop],
+    start_balance: U256,
+    amount_in: U256,
+    adapters: &PoolAdapters,
+) -> Result<(U256, U256, u64)> {
+    let quote = hops
+        .first()
+        .ok_or(anyhow::format_err!("cycle has no hops"))?
+        .token_in;
+    let balance_before = token_balance(evm, quote, braindance_address())?;
+    let (mut amount, mut balance_after, mut gas_used) = (amount_in, balance_before, 0);
+    for hop in hops {
+        // the contract may already hold a hop's output (e.g. another quote asset), so only what it gained is traded on
+        let held = token_balance(evm, hop.token_out, braindance_address())?;
+        let (balance, hop_gas_used) = commit_braindance_swap(
+            evm,
+            adapters.get(hop.variant)?,
+            amount,
+            hop.pool,
+            hop.token_in,
+            hop.token_out,
+            block_info.base_fee,
+            None,
+        )?;
+        amount = balance.saturating_sub(held);
+        balance_after = balance;
+        gas_used += hop_gas_used;
+    }
+    let balance_out = if balance_after >= balance_before {
+        start_balance.saturating_add(balance_after - balance_before)
+    } else {
+        start_balance.saturating_sub(balance_before - balance_after)
+    };
+    // every swap was a separate tx here, but a real backrun only pays the base tx cost once
+    let extra_base_gas = TX_BASE_GAS * (hops.len() as u64 - 1);
+    Ok((
+        amount_in,
+        balance_out,
+        gas_used.saturating_sub(extra_base_gas),
+    ))
+}
+
+/// Searches for the amount in that backruns the user's tx (already run on `post_user_evm`) around a cycle of `hops`
+/// most profitably, narrowing in on the best amount like `step_arb`. Sims aren't cached.
+/// Every sim holds a permit of `budget` and counts against `tx_budget`.
+///
+/// Returns `(amount_in, balance_out, gas_used)` of the best sim (`(0, start_balance, 0)` if none was profitable),
+/// and the profit curve of the search.
+async fn search_cycle(
+    post_user_evm: EVM<ForkDB>,
+    block_info: &BlockInfo,
+    params: &UserTradeParams,
+    hops: Arc<Vec<RouteHop>>,
+    adapters: &PoolAdapters,
+    budget: &SimBudget,
+    tx_budget: &TxSimBudget,
+) -> Result<((U256, U256, u64), Vec<CurvePoint>)> {
+    let start_balance = params.quote_asset.starting_balance;
+    let mut best = (U256::zero(), start_balance, 0);
+    let mut range = [U256::zero(), start_balance];
+    let mut curve = BTreeMap::new();
+    for depth in 0..=MAX_DEPTH {
+        if range[1].saturating_sub(range[0]) <= (range[0] / 1000) || tx_budget.is_exhausted() {
+            break;
+        }
+        let band_width = (range[1] - range[0]) / U256::from(STEP_INTERVALS);
+        let sims = (0..STEP_INTERVALS)
+            .map(|i| {
+                let amount_in = range[0] + band_width * U256::from(i);
+                let mut evm = post_user_evm.clone();
+                let block_info = block_info.clone();
+                let hops = hops.clone();
+                let adapters = adapters.clone();
+                let budget = budget.clone();
+                let tx_budget = tx_budget.clone();
+                async move {
+                    if !tx_budget.try_spend() {
+                        return Err(anyhow::anyhow!("tx sim budget exhausted"));
+                    }
+                    let _permit = budget.acquire().await?;
+                    run_blocking(move || {
+                        sim_cycle_swaps(
+                            &mut evm,
+                            &block_info,
+                            &hops,
+                            start_balance,
+                            amount_in,
+                            &adapters,
+                        )
+                    })
+                    .await
+                }
+                .in_current_span()
+            })
+            .collect::<Vec<_>>();
+        let prev_balance_out = best.1;
+        let results = run_tasks(sims, tx_budget.limits().deterministic).await;
+        let num_results = results.len();
+        let mut num_reverts = 0;
+        for result in results {
+            match result {
+                Ok(Ok((amount_in, balance_out, gas_used))) => {
+                    curve.insert(amount_in, balance_out);
+                    if balance_out > best.1 {
+                        best = (amount_in, balance_out, gas_used);
+                    }
+                }
+                Ok(Err(err)) => {
+                    debug!("{}", err);
+                    if err.to_string().contains("swap reverted") {
+                        num_reverts += 1;
+                    }
+                }
+                Err(err) => {
+                    return Err(anyhow::anyhow!(
+                        "system error in search_cycle. sim_cycle_swaps task {}",
+                        panic_message(err)
+                    ));
+                }
+            }
+        }
+        if num_reverts == num_results {
+            return Err(anyhow::anyhow!("all swaps reverted"));
+        }
+        // same stop cases as `step_arb`: nothing found after a few steps, or a step that barely improved
+        if range[0].is_zero() && depth >= 3 && best.1 <= start_balance {
+            break;
+        }
+        if let Some(min_improvement) = tx_budget.limits().min_improvement {
+            let prev_profit = prev_balance_out.saturating_sub(start_balance);
+            if is_diminishing(prev_profit, best.1 - prev_balance_out, min_improvement) {
+                break;
+            }
+        }
+        range = [
+            best.0.saturating_sub(band_width),
+            best.0.saturating_add(band_width),
+        ];
+    }
+    Ok((best, profit_curve(&curve, start_balance)))
+}
+
+/// Runs `trade`'s backrun on `evm` like `sim_backrun_swaps`, or hop by hop like `sim_cycle_swaps` if it went
+/// around a cycle (has a `path`).
+fn sim_trade_swaps(
+    evm: &mut EVM<ForkDB>,
+    block_info: &BlockInfo,
+    tokens: &TokenPair,
+    trade: &BackrunResult,
+    start_balance: U256,
+    amount_in: U256,
+    adapters: &PoolAdapters,
+) -> Result<(U256, U256, u64)> {
+    if !trade.path.is_empty() {
+        return sim_cycle_swaps(
+            evm,
+            block_info,
+            &trade.path,
+            start_balance,
+            amount_in,
+            adapters,
+        );
+    }
+    sim_backrun_swaps(
+        evm,
+        block_info,
+        tokens,
+        start_balance,
+        amount_in,
+        (trade.start_pool, trade.start_variant),
+        (trade.end_pool, trade.end_variant),
+        adapters,
+    )
+}
+
+/// Re-simulates the backrun route of `result` on the state at the end of `block_info.number`,
+/// without the user's tx (which must have landed by then), at each of `amounts_in`.
+/// The route's pools (every hop of a backrun around a cycle) are traded with their adapter in `adapters`.
+///
+/// Returns the highest profit of those amounts, or 0 if none were profitable.
+pub async fn sim_backrun_at(
+    client: &WsClient,
+    block_info: &BlockInfo,
+    result: &SimArbResult,
+    quote_asset: &QuoteAsset,
+    amounts_in: &[U256],
+    adapters: &PoolAdapters,
+) -> Result<U256> {
+    let trade = Arc::new(result.backrun_trade.to_owned());
+    // every amount is tried on the same block, so they share its state
+    let factory = Arc::new(fork_factory(client, block_info).await?);
+    let handles = amounts_in.iter().map(|amount_in| {
+        let factory = factory.clone();
+        let block_info = block_info.clone();
+        let tokens = result.user_trade.tokens.clone();
+        let quote_asset = quote_asset.clone();
+        let adapters = adapters.clone();
+        let trade = trade.clone();
+        let amount_in = *amount_in;
+        run_blocking(move || {
+            let mut evm = fork_from(&factory, &block_info);
+            fund_braindance(&mut evm, &quote_asset)?;
+            sim_trade_swaps(
+                &mut evm,
+                &block_info,
+                &tokens,
+                &trade,
+                quote_asset.starting_balance,
+                amount_in,
+                &adapters,
+            )
+        })
+    });
+    let mut best_profit = U256::zero();
+    for res in future::join_all(handles).await {
+        match res {
+            Ok((_, balance_out, _)) if balance_out > quote_asset.starting_balance => {
+                best_profit = best_profit.max(balance_out - quote_asset.starting_balance);
+            }
+            Ok(_) => {}
+            Err(err) => debug!("delayed backrun failed: {}", err),
+        }
+    }
+    Ok(best_profit)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::util::{get_all_trading_pools, get_block_info, test::get_test_ws_client, ETH};
+    use crate::config::known_quote_assets;
+    use crate::sim::evm::sim_bundle;
+    use crate::util::{get_block_info, test::get_test_ws_client, ETH};
     use anyhow::Result;
     use ethers::providers::Middleware;
+    use ethers::types::H256;
+    use std::str::FromStr;
+
+    #[test]
+    fn it_skips_routes_without_a_price_gap() {
+        let price = U256::from(2000) * ETH;
+        // 0.05% apart
+        let alt_price = price + price / 2000;
+        assert!(clears_price_gap(price, alt_price, 0.0001));
+        assert!(clears_price_gap(alt_price, price, 0.0001));
+        assert!(!clears_price_gap(price, alt_price, 0.001));
+        assert!(!clears_price_gap(price, price, 0.0001));
+        // unknown prices are left to the sims
+        assert!(clears_price_gap(0.into(), price, 0.5));
+    }
+
+    #[test]
+    fn it_orders_routes_from_the_cheaper_pool() {
+        let mut params =
+            SimArbResult::test_example(Address::from_low_u64_be(0xc), ETH, 0.into()).user_trade;
+        params.price = U256::from(2000) * ETH;
+        let other_pool = PairPool {
+            variant: PoolVariant::UniswapV3,
+            address: Address::from_low_u64_be(0xb),
+            factory: None,
+        };
+        let user = (params.pool, params.pool_variant);
+        let other = (other_pool.address, other_pool.variant);
+        let (lower, higher) = (params.price - ETH, params.price + ETH);
+
+        // priced in tkn1/quote: the token is cheaper where the price is higher
+        params.token0_is_quote = true;
+        assert_eq!(
+            route_pools(&params, &other_pool, lower),
+            Some((user, other))
+        );
+        assert_eq!(
+            route_pools(&params, &other_pool, higher),
+            Some((other, user))
+        );
+        // priced in quote/tkn0: the token is cheaper where the price is lower
+        params.token0_is_quote = false;
+        assert_eq!(
+            route_pools(&params, &other_pool, lower),
+            Some((other, user))
+        );
+        assert_eq!(
+            route_pools(&params, &other_pool, higher),
+            Some((user, other))
+        );
+        // nothing to arb between equal prices, whichever pool is which
+        assert_eq!(route_pools(&params, &other_pool, params.price), None);
+        assert!(route_pools(&params, &other_pool, 0.into()).is_some());
+
+        // wider gaps rank first; unknown ones last
+        let mut spreads = [
+            spread_ppb(params.price, lower),
+            spread_ppb(params.price, 0.into()),
+            spread_ppb(params.price, params.price * 2),
+        ];
+        spreads.sort_by_key(|spread| std::cmp::Reverse(*spread));
+        assert_eq!(spreads[0], Some(500_000_000.into()));
+        assert_eq!(spreads[2], None);
+    }
+
+    #[test]
+    fn it_skips_gaps_that_cant_pay_for_gas() {
+        let price = U256::from(2000) * ETH;
+        let start_balance = U256::from(420) * ETH;
+        let gas_cost = U256::from(ESTIMATED_BACKRUN_GAS) * U256::from(30_000_000_000u64);
+        // 0.001% of 420 ETH is 0.0042 ETH, less than 300k gas at 30 gwei (0.009 ETH)
+        assert!(!covers_gas(
+            price,
+            price + price / 100_000,
+            start_balance,
+            gas_cost
+        ));
+        // 0.01% is 0.042 ETH
+        assert!(covers_gas(
+            price,
+            price + price / 10_000,
+            start_balance,
+            gas_cost
+        ));
+        assert!(covers_gas(0.into(), price, start_balance, gas_cost));
+    }
+
+    #[test]
+    fn it_sizes_coinbase_payments() {
+        assert_eq!(coinbase_payment(ETH, 0.9), ETH * 9 / 10);
+        assert_eq!(coinbase_payment(ETH, 0.0), 0.into());
+        assert_eq!(coinbase_payment(ETH, 1.0), ETH);
+        // shares past 100% pay the whole profit, never more
+        assert_eq!(coinbase_payment(ETH, 2.0), ETH);
+        // rounded down to the wei
+        assert_eq!(coinbase_payment(3.into(), 0.5), 1.into());
+    }
+
+    #[test]
+    fn it_keeps_the_frontier_of_profit_curves() {
+        let start = U256::from(100) * ETH;
+        let balances = [(0, 100), (1, 102), (2, 101), (3, 104), (4, 104), (5, 99)]
+            .into_iter()
+            .map(|(amount_in, balance)| (ETH * amount_in, ETH * balance))
+            .collect::<BTreeMap<_, _>>();
+        let curve = profit_curve(&balances, start);
+        assert_eq!(
+            curve,
+            vec![
+                CurvePoint {
+                    amount_in: ETH,
+                    profit: ETH * 2
+                },
+                CurvePoint {
+                    amount_in: ETH * 3,
+                    profit: ETH * 4
+                },
+            ]
+        );
+        assert!(profit_curve(&BTreeMap::new(), start).is_empty());
+    }
+
+    #[test]
+    fn it_detects_diminishing_returns() {
+        let eth = ETH;
+        // no profit yet, keep searching
+        assert!(!is_diminishing(0.into(), 0.into(), 0.001));
+        // +0.05% < 0.1%
+        assert!(is_diminishing(eth, eth / 2000, 0.001));
+        // +1% >= 0.1%
+        assert!(!is_diminishing(eth, eth / 100, 0.001));
+        assert!(is_diminishing(eth, 0.into(), 0.001));
+        assert!(!is_diminishing(U256::MAX, U256::MAX, 0.001));
+    }
 
     async fn setup_test_evm(client: &WsClient, block_num: u64) -> Result<EVM<ForkDB>> {
         let block_info = get_block_info(&client, block_num).await?;
         fork_evm(&client, &block_info).await
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn it_runs_tasks_in_order_when_sequential() {
+        let started = Arc::new(std::sync::Mutex::new(vec![]));
+        let tasks = |n: u64| {
+            (0..n)
+                .map(|i| {
+                    let started = started.clone();
+                    async move {
+                        started.lock().unwrap().push(i);
+                        // later tasks finish first if they run concurrently
+                        tokio::time::sleep(std::time::Duration::from_millis(10 * (n - i))).await;
+                        i
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let outcomes = run_tasks(tasks(4), true).await;
+        assert_eq!(
+            outcomes
+                .into_iter()
+                .map(|res| res.unwrap())
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(*started.lock().unwrap(), vec![0, 1, 2, 3]);
+        // concurrent tasks still report their outcomes in order
+        let outcomes = run_tasks(tasks(4), false).await;
+        assert_eq!(
+            outcomes
+                .into_iter()
+                .map(|res| res.unwrap())
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn it_simulates_tx() -> Result<()> {
         let client = get_test_ws_client().await?;
@@ -631,13 +1820,74 @@ mod test {
         let tx = client.get_transaction(tx_hash).await?.unwrap();
         let block_num = tx.block_number.unwrap() - 1;
         let mut evm = setup_test_evm(&client, block_num.as_u64()).await?;
-        let res = sim_bundle(&mut evm, vec![tx]).await;
+        let res = sim_bundle(&mut evm, vec![tx]);
         assert!(res.is_ok());
         let res = res.unwrap();
         assert!(res[0].is_success());
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_replays_backruns_along_their_path() -> Result<()> {
+        let client = get_test_ws_client().await?;
+        let block_num = client.get_block_number().await?;
+        let block_info = get_block_info(&client, block_num.as_u64() - 4).await?;
+        let mut evm = fork_evm(&client, &block_info).await?;
+        let quote_asset = known_quote_assets(1)[0].to_owned();
+        fund_braindance(&mut evm, &quote_asset)?;
+        let weth = quote_asset.address;
+        let tkn = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE".parse::<Address>()?; // SHIB (mainnet)
+        let adapters = PoolAdapters::default();
+        let pools = adapters.find_pools(&client, (weth, tkn)).await?;
+        let hop = |pool: &PairPool, token_in, token_out| RouteHop {
+            pool: pool.address,
+            variant: pool.variant,
+            token_in,
+            token_out,
+        };
+        let path = vec![hop(&pools[0], weth, tkn), hop(&pools[1], tkn, weth)];
+        let start_balance = quote_asset.starting_balance;
+
+        // a cycle buys on the first pool & sells on the second, while its stored pools are the other way around
+        let mut result = SimArbResult::test_example(tkn, ETH, 0.into());
+        let trade = &mut result.backrun_trade;
+        (trade.start_pool, trade.start_variant) = (pools[1].address, pools[1].variant);
+        (trade.end_pool, trade.end_variant) = (pools[0].address, pools[0].variant);
+        trade.path = path.clone();
+        let tokens = result.user_trade.tokens.clone();
+        let replayed = sim_trade_swaps(
+            &mut evm.clone(),
+            &block_info,
+            &tokens,
+            &result.backrun_trade,
+            start_balance,
+            ETH,
+            &adapters,
+        )?;
+        let cycle = sim_cycle_swaps(
+            &mut evm.clone(),
+            &block_info,
+            &path,
+            start_balance,
+            ETH,
+            &adapters,
+        )?;
+        assert_eq!(replayed, cycle);
+
+        result.backrun_trade.path.clear();
+        let reversed = sim_trade_swaps(
+            &mut evm,
+            &block_info,
+            &tokens,
+            &result.backrun_trade,
+            start_balance,
+            ETH,
+            &adapters,
+        )?;
+        assert_ne!(reversed.1, cycle.1);
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn it_simulates_swaps() -> Result<()> {
         let client = get_test_ws_client().await?;
@@ -645,13 +1895,14 @@ mod test {
         let mut evm = setup_test_evm(&client, block_num.as_u64() - 4).await?;
         let weth = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse::<Address>()?;
         let tkn = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE".parse::<Address>()?; // SHIB (mainnet)
-        let pools = get_all_trading_pools(&client, (weth, tkn)).await?;
+        let adapters = PoolAdapters::default();
+        let pools = adapters.find_pools(&client, (weth, tkn)).await?;
         let gas_price = U256::from(1_000_000_000) * 420; // 420 gwei
 
         // buy 69 ETH worth of SHIB on exchange 0
-        let res = commit_braindance_swap(
+        let (res, _) = commit_braindance_swap(
             &mut evm,
-            pools[0].variant,
+            adapters.get(pools[0].variant)?,
             ETH * 69,
             pools[0].address,
             weth,
@@ -663,7 +1914,7 @@ mod test {
         // sell all the SHIB on exchange 1
         let _ = commit_braindance_swap(
             &mut evm,
-            pools[1].variant,
+            adapters.get(pools[1].variant)?,
             res,
             pools[1].address,
             tkn,
@@ -673,4 +1924,163 @@ mod test {
         )?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn it_derives_hinted_v2_trades_from_a_mock_chain() -> Result<()> {
+        use crate::config::{SUSHISWAP_FACTORY, UNISWAP_V2_FACTORY, UNISWAP_V3_FACTORY};
+        use crate::mock::MockChain;
+        use crate::sim::events::UniswapV2Swap;
+        use ethers::abi::{encode, Token};
+        use ethers::contract::EthEvent;
+        use ethers::types::{Bytes, TransactionReceipt};
+        use ethers::utils::id;
+
+        let call = |signature: &str, args: &[Token]| -> Bytes {
+            [&id(signature)[..], &encode(args)].concat().into()
+        };
+        let returns = |address: Address| -> Bytes { encode(&[Token::Address(address)]).into() };
+        let (token, weth) = (
+            Address::from_low_u64_be(0x70),
+            Address::from_low_u64_be(0x77),
+        );
+        let (pair, alt_pair) = (Address::from_low_u64_be(0xa), Address::from_low_u64_be(0xb));
+        let uni_factory: Address = UNISWAP_V2_FACTORY.parse()?;
+        let sushi_factory: Address = SUSHISWAP_FACTORY.parse()?;
+        let tx = Transaction {
+            hash: H256::from_low_u64_be(0x1234),
+            block_number: Some(100.into()),
+            ..Default::default()
+        };
+        // the user swapped 1 WETH for 2000 tokens, without a Sync log to read the new price from
+        let swap_log = Log {
+            address: pair,
+            topics: vec![
+                UniswapV2Swap::signature(),
+                H256::from(Address::from_low_u64_be(0x5e)),
+                H256::from(Address::from_low_u64_be(0x5e)),
+            ],
+            data: encode(&[
+                Token::Uint(0.into()),
+                Token::Uint(ETH),
+                Token::Uint(U256::from(2000) * ETH),
+                Token::Uint(0.into()),
+            ])
+            .into(),
+            ..Default::default()
+        };
+        let mut chain = MockChain::new(1)
+            .with_receipt(TransactionReceipt {
+                transaction_hash: tx.hash,
+                block_number: tx.block_number,
+                logs: vec![swap_log],
+                ..Default::default()
+            })
+            .with_call(pair, call("token0()", &[]), returns(token))
+            .with_call(pair, call("token1()", &[]), returns(weth))
+            .with_call(pair, call("factory()", &[]), returns(uni_factory))
+            .with_call(
+                token,
+                call("decimals()", &[]),
+                encode(&[Token::Uint(18.into())]).into(),
+            )
+            // reserves after the swap: 98000 tokens & 51 WETH
+            .with_call(
+                pair,
+                call("getReserves()", &[]),
+                encode(&[
+                    Token::Uint(U256::from(98_000) * ETH),
+                    Token::Uint(U256::from(51) * ETH),
+                    Token::Uint(0.into()),
+                ])
+                .into(),
+            );
+        // the pair's tokens may be looked up in either order
+        for (a, b) in [(token, weth), (weth, token)] {
+            let tokens = [Token::Address(a), Token::Address(b)];
+            chain = chain
+                .with_call(
+                    uni_factory,
+                    call("getPair(address,address)", &tokens),
+                    returns(pair),
+                )
+                .with_call(
+                    sushi_factory,
+                    call("getPair(address,address)", &tokens),
+                    returns(alt_pair),
+                )
+                .with_call(
+                    UNISWAP_V3_FACTORY.parse()?,
+                    call(
+                        "getPool(address,address,uint24)",
+                        &[
+                            Token::Address(a),
+                            Token::Address(b),
+                            Token::Uint(3000.into()),
+                        ],
+                    ),
+                    returns(Address::zero()),
+                );
+        }
+        let client = chain.serve().await?;
+
+        let event = EventHistory {
+            block: 100,
+            timestamp: 1_700_000_000,
+            hint: mev_share_sse::Hint {
+                txs: vec![],
+                hash: tx.hash,
+                logs: vec![EventTransactionLog {
+                    address: pair,
+                    topics: vec![UniswapV2Swap::signature()],
+                    data: Default::default(),
+                }],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        };
+        let quote_assets = [QuoteAsset::new(weth, 3, 18, 10)];
+        let search = SearchContext {
+            client: &client,
+            quote_assets: &quote_assets,
+            wrapped_native: Some(weth),
+            lists: &AddressLists::default(),
+            adapters: &PoolAdapters::default(),
+            forks: &ForkCache::default(),
+            budget: &SimBudget::default(),
+            receipts: &ReceiptCache::default(),
+            registry: &PoolRegistry::default(),
+            path_finder: None,
+            sim_cache: None,
+            audit: None,
+        };
+        let trades = derive_trade_params(&search, &tx, &event).await?;
+        assert_eq!(trades.len(), 1);
+        let trade = &trades[0];
+        assert_eq!(trade.pool, pair);
+        assert_eq!(trade.pool_variant, PoolVariant::UniswapV2);
+        assert_eq!(trade.factory, Some(uni_factory));
+        assert_eq!((trade.tokens.quote, trade.tokens.token), (weth, token));
+        assert!(!trade.token0_is_quote);
+        assert_eq!(
+            [trade.token_in, trade.token_out]
+                .iter()
+                .collect::<HashSet<_>>(),
+            [token, weth].iter().collect()
+        );
+        // priced from the pair's reserves, in WETH per token
+        assert_eq!(
+            trade.price,
+            get_price_v2(U256::from(98_000) * ETH, U256::from(51) * ETH, 18.into())?
+        );
+        // the user's pair is the one being backrun, so only the other V2 pair (and no V3 pool) is an arb pool
+        assert_eq!(
+            trade
+                .arb_pools
+                .iter()
+                .map(|pool| (pool.address, pool.variant))
+                .collect::<Vec<_>>(),
+            vec![(alt_pair, PoolVariant::UniswapV2)]
+        );
+        Ok(())
+    }
 }
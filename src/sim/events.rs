@@ -0,0 +1,135 @@
+use crate::{error::HindsightError, Result};
+use ethers::{
+    contract::EthEvent,
+    types::{Address, Log, I256, U256},
+};
+use mev_share_sse::EventTransactionLog;
+
+/// UniswapV2 pair's `Swap` event.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(
+    name = "Swap",
+    abi = "Swap(address,uint256,uint256,uint256,uint256,address)"
+)]
+pub struct UniswapV2Swap {
+    #[ethevent(indexed)]
+    pub sender: Address,
+    pub amount0_in: U256,
+    pub amount1_in: U256,
+    pub amount0_out: U256,
+    pub amount1_out: U256,
+    #[ethevent(indexed)]
+    pub to: Address,
+}
+
+/// UniswapV2 pair's `Sync` event, emitted with the pair's reserves after every change.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(name = "Sync", abi = "Sync(uint112,uint112)")]
+pub struct UniswapV2Sync {
+    pub reserve0: U256,
+    pub reserve1: U256,
+}
+
+/// UniswapV3 pool's `Swap` event. Amounts are positive if the pool received them.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(
+    name = "Swap",
+    abi = "Swap(address,address,int256,int256,uint160,uint128,int24)"
+)]
+pub struct UniswapV3Swap {
+    #[ethevent(indexed)]
+    pub sender: Address,
+    #[ethevent(indexed)]
+    pub recipient: Address,
+    pub amount0: I256,
+    pub amount1: I256,
+    pub sqrt_price_x96: U256,
+    pub liquidity: u128,
+    pub tick: i32,
+}
+
+/// UniswapV2 factory's `PairCreated` event, emitted by its clones' factories too.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(
+    name = "PairCreated",
+    abi = "PairCreated(address,address,address,uint256)"
+)]
+pub struct UniswapV2PairCreated {
+    #[ethevent(indexed)]
+    pub token0: Address,
+    #[ethevent(indexed)]
+    pub token1: Address,
+    pub pair: Address,
+    pub num_pairs: U256,
+}
+
+/// UniswapV3 factory's `PoolCreated` event.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(
+    name = "PoolCreated",
+    abi = "PoolCreated(address,address,uint24,int24,address)"
+)]
+pub struct UniswapV3PoolCreated {
+    #[ethevent(indexed)]
+    pub token0: Address,
+    #[ethevent(indexed)]
+    pub token1: Address,
+    #[ethevent(indexed)]
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub pool: Address,
+}
+
+/// ERC20 `Transfer` event.
+#[derive(Clone, Debug, EthEvent, PartialEq)]
+#[ethevent(name = "Transfer", abi = "Transfer(address,address,uint256)")]
+pub struct Erc20Transfer {
+    #[ethevent(indexed)]
+    pub from: Address,
+    #[ethevent(indexed)]
+    pub to: Address,
+    pub value: U256,
+}
+
+/// Checks that a hinted log could have been emitted by the EVM: 1 to 4 topics, and data of whole words.
+///
+/// Hints are decoded by MEV-Share's stream, not the chain, so they're checked before anything indexes into them.
+pub fn validate_hint_log(log: &EventTransactionLog) -> Result<()> {
+    let problem = if log.topics.is_empty() {
+        "no topics".to_owned()
+    } else if log.topics.len() > 4 {
+        format!("{} topics", log.topics.len())
+    } else if !log.data.len().is_multiple_of(32) {
+        format!("{} bytes of data", log.data.len())
+    } else {
+        return Ok(());
+    };
+    Err(HindsightError::MalformedHint(format!("log of {:?} has {}", log.address, problem)).into())
+}
+
+/// Decodes `log` as an `E` event, failing if its topics or data don't match `E`'s ABI.
+pub fn decode_log<E: EthEvent>(log: &Log) -> Result<E> {
+    E::decode_log(&log.to_owned().into())
+        .map_err(|err| anyhow::format_err!("failed to decode {} log: {}", E::name(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H256;
+
+    #[test]
+    fn it_rejects_malformed_hint_logs() {
+        let log = |topics: usize, data: usize| EventTransactionLog {
+            address: Address::from_low_u64_be(0xa),
+            topics: vec![H256::from_low_u64_be(1); topics],
+            data: vec![0; data].into(),
+        };
+        assert!(validate_hint_log(&log(1, 0)).is_ok());
+        assert!(validate_hint_log(&log(4, 64)).is_ok());
+        for (topics, data) in [(0, 0), (5, 0), (1, 31)] {
+            let err = validate_hint_log(&log(topics, data)).unwrap_err();
+            assert!(err.to_string().starts_with("malformed hint"), "{}", err);
+        }
+    }
+}
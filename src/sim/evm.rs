@@ -1,51 +1,49 @@
 use crate::{
-    debug, error::HindsightError, interfaces::PoolVariant, util::get_price_v3, Error, Result,
+    debug,
+    error::HindsightError,
+    interfaces::QuoteAsset,
+    sim::{
+        events::{decode_log, Erc20Transfer},
+        pools::PoolAdapter,
+    },
+    util::{get_price_v2, get_price_v3, panic_message},
+    Error, Result,
 };
 use ethers::{
-    abi::{self, ParamType},
+    abi::{self, ParamType, Token},
     prelude::abigen,
-    types::{Address, Bytes, Transaction, TransactionRequest, I256, U256, U64},
+    types::{Address, Bytes, Log, Transaction, TransactionRequest, H256, U256, U64},
+    utils::keccak256,
 };
 use revm::{
     primitives::{ExecutionResult, Output, ResultAndState, TransactTo, B160, U256 as rU256},
-    EVM,
+    Database, EVM,
 };
 use rusty_sando::{
-    prelude::fork_db::ForkDB,
-    simulate::{braindance_address, braindance_controller_address},
-    types::SimulationError,
-    utils::{constants::get_eth_dev, tx_builder::braindance},
+    prelude::fork_db::ForkDB, simulate::braindance_address, types::SimulationError,
+    utils::constants::get_eth_dev,
 };
-use std::{ops::Mul, str::FromStr};
+use std::str::FromStr;
 
-/// Execute a braindance swap on the forked EVM, commiting its state changes to the EVM's ForkDB.
+/// Execute a swap built by `adapter` on the forked EVM, commiting its state changes to the EVM's ForkDB.
 ///
-/// Returns balance of token_out after tx is executed.
+/// Returns balance of token_out after tx is executed (see `credited_balance`), and the gas the tx used.
 pub fn commit_braindance_swap(
     evm: &mut EVM<ForkDB>,
-    pool_variant: PoolVariant,
+    adapter: &dyn PoolAdapter,
     amount_in: U256,
     target_pool: Address,
     token_in: Address,
     token_out: Address,
     base_fee: U256,
     _nonce: Option<u64>,
-) -> Result<U256> {
-    let swap_data = match pool_variant {
-        PoolVariant::UniswapV2 => {
-            braindance::build_swap_v2_data(amount_in, target_pool, token_in, token_out)
-        }
-        PoolVariant::UniswapV3 => braindance::build_swap_v3_data(
-            I256::from_raw(amount_in),
-            target_pool,
-            token_in,
-            token_out,
-        ),
-    };
+) -> Result<(U256, u64)> {
+    let swap = adapter.build_swap_call(target_pool, amount_in, token_in, token_out)?;
+    let balance_before = token_balance(evm, token_out, braindance_address())?;
 
-    evm.env.tx.caller = braindance_controller_address();
-    evm.env.tx.transact_to = TransactTo::Call(braindance_address().0.into());
-    evm.env.tx.data = swap_data.0;
+    evm.env.tx.caller = swap.caller.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(swap.to.0.into());
+    evm.env.tx.data = swap.data.0;
     evm.env.tx.gas_limit = 700000;
     evm.env.tx.gas_price = base_fee.into();
     evm.env.tx.value = rU256::ZERO;
@@ -54,10 +52,15 @@ pub fn commit_braindance_swap(
         Ok(res) => res,
         Err(e) => return Err(anyhow::anyhow!("failed to commit swap: {:?}", e)),
     };
-    let output = match res.to_owned() {
-        ExecutionResult::Success { output, .. } => match output {
-            Output::Call(o) => o,
-            Output::Create(o, _) => o,
+    let (output, gas_used, logs) = match res.to_owned() {
+        ExecutionResult::Success {
+            output,
+            gas_used,
+            logs,
+            ..
+        } => match output {
+            Output::Call(o) => (o, gas_used, logs),
+            Output::Create(o, _) => (o, gas_used, logs),
         },
         ExecutionResult::Revert { output, gas_used } => {
             return Err(anyhow::anyhow!(
@@ -70,17 +73,144 @@ pub fn commit_braindance_swap(
             return Err(anyhow::anyhow!("swap halted: {:?}", reason))
         }
     };
-    let (_amount_out, balance) = match pool_variant {
-        PoolVariant::UniswapV2 => match braindance::decode_swap_v2_result(output.into()) {
-            Ok(output) => output,
-            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
-        },
-        PoolVariant::UniswapV3 => match braindance::decode_swap_v3_result(output.into()) {
-            Ok(output) => output,
-            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
+    let balance = adapter.decode_swap_output(evm, output.into(), token_out)?;
+    let transferred = transferred_to(&logs, token_out, braindance_address());
+    Ok((
+        credited_balance(balance_before, balance, transferred),
+        gas_used,
+    ))
+}
+
+/// Balance of a token to count after a swap moved it from `before` to `after`, when `transferred` was logged
+/// as transferred in: the balance, but no more than `before + transferred`, so that balance changes without transfers
+/// (e.g. a rebase of a stETH-like token) aren't counted as swap output.
+///
+/// Tokens that charge fees on transfer (or round shares down) credit less than was logged, so their balance counts;
+/// tokens that don't log transfers at all are taken at their balance, too.
+pub fn credited_balance(before: U256, after: U256, transferred: Option<U256>) -> U256 {
+    match transferred {
+        Some(transferred) if after.saturating_sub(before) > transferred => {
+            debug!(
+                %before, %after, %transferred,
+                "balance moved by more than was transferred, crediting the transfers"
+            );
+            before.saturating_add(transferred)
+        }
+        _ => after,
+    }
+}
+
+/// Total amount of `token` that `logs` transferred to `account`, or None if they didn't log any transfer of it to `account`.
+fn transferred_to(
+    logs: &[revm::primitives::Log],
+    token: Address,
+    account: Address,
+) -> Option<U256> {
+    logs.iter()
+        .filter(|log| Address::from(log.address.0) == token)
+        .filter_map(|log| {
+            let log = Log {
+                address: token,
+                topics: log.topics.iter().map(|topic| H256::from(topic.0)).collect(),
+                data: log.data.to_owned().into(),
+                ..Default::default()
+            };
+            decode_log::<Erc20Transfer>(&log).ok()
+        })
+        .filter(|transfer| transfer.to == account)
+        .map(|transfer| transfer.value)
+        .reduce(|total, value| total.saturating_add(value))
+}
+
+/// Returns `account`'s balance of `token` in the forked EVM, without committing anything.
+pub fn token_balance(evm: &mut EVM<ForkDB>, token: Address, account: Address) -> Result<U256> {
+    let args = [Token::Address(account)];
+    let output = call_with_args(evm, get_eth_dev(), "0x70a08231", &args, token)?; // balanceOf(address)
+    abi::decode(&[ParamType::Uint(256)], &output)?[0]
+        .to_owned()
+        .into_uint()
+        .ok_or(anyhow::format_err!("balanceOf returned no uint"))
+}
+
+/// Whether `token`'s `transfer` returns a bool, as the ERC20 standard says, rather than nothing (like USDT's).
+///
+/// Tries a transfer of 0 from `holder` to itself, without committing it.
+pub fn transfer_returns_bool(
+    evm: &mut EVM<ForkDB>,
+    token: Address,
+    holder: Address,
+) -> Result<bool> {
+    let args = [Token::Address(holder), Token::Uint(U256::zero())];
+    let output = call_with_args(evm, holder, "0xa9059cbb", &args, token)?; // transfer(address,uint256)
+    Ok(output.len() >= 32)
+}
+
+/// Sets the braindance contract's balance of `asset` to its starting balance,
+/// by writing directly to the token's `balanceOf` mapping.
+pub fn fund_braindance(evm: &mut EVM<ForkDB>, asset: &QuoteAsset) -> Result<()> {
+    set_token_balance(evm, asset, braindance_address(), asset.starting_balance)
+        .map_err(|err| anyhow::format_err!("failed to fund braindance contract: {}", err))
+}
+
+/// Sets `account`'s balance of `asset` to `balance`, by writing directly to the token's `balanceOf` mapping.
+pub fn set_token_balance(
+    evm: &mut EVM<ForkDB>,
+    asset: &QuoteAsset,
+    account: Address,
+    balance: U256,
+) -> Result<()> {
+    let balance_key = keccak256(abi::encode(&[
+        Token::Address(account),
+        Token::Uint(asset.balance_slot.into()),
+    ]));
+    evm.db()
+        .ok_or(anyhow::format_err!("evm has no database"))?
+        .insert_account_storage(
+            asset.address.0.into(),
+            rU256::from_be_bytes(balance_key),
+            balance.into(),
+        )
+        .map_err(|err| anyhow::format_err!("failed to set balance of {:?}: {:?}", account, err))
+}
+
+/// Calls `method` (a selector) of `contract` with `args` from `caller` in the forked EVM, like `call_with_args`,
+/// but commits its state changes. `caller` pays for gas at `base_fee`.
+///
+/// Returns the call's output and the gas it used. Fails if it reverts or halts.
+pub fn commit_call_with_args(
+    evm: &mut EVM<ForkDB>,
+    caller: Address,
+    method: &str,
+    args: &[Token],
+    contract: Address,
+    base_fee: U256,
+) -> Result<(Bytes, u64)> {
+    let mut data = Bytes::from_str(method)?.to_vec();
+    data.extend(abi::encode(args));
+    evm.env.tx.caller = caller.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(contract.0.into());
+    evm.env.tx.data = data.into();
+    evm.env.tx.gas_limit = 2_000_000;
+    evm.env.tx.gas_price = base_fee.into();
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.value = rU256::ZERO;
+    let res = evm
+        .transact_commit()
+        .map_err(|err| anyhow::anyhow!("failed to commit call to {:?}: {:?}", contract, err))?;
+    match res {
+        ExecutionResult::Success {
+            output, gas_used, ..
+        } => match output {
+            Output::Call(o) => Ok((o.into(), gas_used)),
+            Output::Create(o, _) => Ok((o.into(), gas_used)),
         },
-    };
-    Ok(balance)
+        ExecutionResult::Revert { output, .. } => {
+            Err(anyhow::format_err!(SimulationError::EvmReverted(output)))
+        }
+        ExecutionResult::Halt { reason, .. } => {
+            Err(anyhow::format_err!(SimulationError::EvmHalted(reason)))
+        }
+    }
 }
 
 /// returns price of token1/token0 in forked EVM.
@@ -110,11 +240,11 @@ pub async fn sim_price_v3(
         ],
         &output,
     )?;
-    let sqrt_price = slot0_tokens[0].clone().into_uint().expect("sqrt_price");
+    let sqrt_price = first_uint(slot0_tokens, "slot0", target_pool)?;
 
     let output = call_function(evm, "0x1a686502", target_pool)?; // liquidity()
     let liquidity_tokens = abi::decode(&vec![ParamType::Uint(128)], &output)?;
-    let liquidity = liquidity_tokens[0].clone().into_uint().expect("liquidity");
+    let liquidity = first_uint(liquidity_tokens, "liquidity", target_pool)?;
 
     let token0 = match input_token < output_token {
         true => input_token,
@@ -122,14 +252,26 @@ pub async fn sim_price_v3(
     };
     let output = call_function(evm, "0x313ce567", token0)?; // decimals()
     let token0_decimals_tokens = abi::decode(&vec![ParamType::Uint(8)], &output)?;
-    let token0_decimals = token0_decimals_tokens[0]
-        .clone()
-        .into_uint()
-        .expect("token0_decimals");
+    let token0_decimals = first_uint(token0_decimals_tokens, "decimals", token0)?;
 
     get_price_v3(liquidity, sqrt_price, token0_decimals)
 }
 
+/// Takes the first value decoded from the return data of `contract`'s `method`, which must be a uint.
+fn first_uint(tokens: Vec<Token>, method: &str, contract: Address) -> Result<U256> {
+    tokens
+        .into_iter()
+        .next()
+        .and_then(|token| token.into_uint())
+        .ok_or_else(|| {
+            HindsightError::EvmParseError(format!(
+                "{}() of {:?} returned no uint",
+                method, contract
+            ))
+            .into()
+        })
+}
+
 /// returns price of token1/token0 in forked EVM.
 pub async fn sim_price_v2(
     target_pool: Address,
@@ -197,16 +339,8 @@ pub async fn sim_price_v2(
         .into_uint()
         .ok_or::<Error>(HindsightError::CallError("token decimals not found".to_owned()).into())?;
 
-    Ok(reserves_1
-        .mul(U256::from(10).pow(token0_decimals))
-        .checked_div(reserves_0)
-        .ok_or::<Error>(
-            HindsightError::MathError(format!(
-                "failed to divide reserves (reserves_0, reserves_1)=({},{})",
-                reserves_0, reserves_1
-            ))
-            .into(),
-        )?)
+    // empty & dust pools fail with `InsufficientLiquidity`, so their routes are skipped
+    get_price_v2(reserves_0, reserves_1, token0_decimals)
 }
 
 pub fn call_function(evm: &mut EVM<ForkDB>, method: &str, contract: Address) -> Result<Bytes> {
@@ -224,6 +358,29 @@ pub fn call_function(evm: &mut EVM<ForkDB>, method: &str, contract: Address) ->
     sim_tx_request(evm, tx)
 }
 
+/// Calls `method` (a selector) of `contract` with `args` from `caller` in the forked EVM, like `call_function`.
+pub fn call_with_args(
+    evm: &mut EVM<ForkDB>,
+    caller: Address,
+    method: &str,
+    args: &[Token],
+    contract: Address,
+) -> Result<Bytes> {
+    let mut data = Bytes::from_str(method)?.to_vec();
+    data.extend(abi::encode(args));
+    let tx = TransactionRequest {
+        from: Some(caller),
+        to: Some(contract.into()),
+        gas: Some(U256::from(900_000_u64)),
+        gas_price: Some(U256::from(1_000_000_000_000_u64)),
+        value: None,
+        data: Some(data.into()),
+        nonce: None,
+        chain_id: Some(U64::from(1)),
+    };
+    sim_tx_request(evm, tx)
+}
+
 pub fn sim_tx_request(evm: &mut EVM<ForkDB>, tx: TransactionRequest) -> Result<Bytes> {
     evm.env.tx.caller = B160::from(tx.from.unwrap_or(get_eth_dev()));
     evm.env.tx.transact_to = TransactTo::Call(B160::from(
@@ -298,16 +455,33 @@ fn inject_tx(evm: &mut EVM<ForkDB>, tx: &Transaction) -> Result<()> {
     Ok(())
 }
 
+/// Runs `execute` on the runtime's blocking threads, so that CPU-bound EVM execution doesn't stall
+/// the async tasks (RPC requests, streams) sharing the runtime's workers. `execute` runs in the caller's span.
+///
+/// If `execute` panics, the panic is returned as an error, so it fails the sim rather than the task awaiting it.
+pub async fn run_blocking<T, F>(execute: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let span = tracing::Span::current();
+    match tokio::task::spawn_blocking(move || span.in_scope(execute)).await {
+        Ok(res) => res,
+        Err(err) if err.is_panic() => Err(anyhow::anyhow!("evm execution {}", panic_message(err))),
+        Err(err) => Err(anyhow::anyhow!("evm execution failed: {}", err)),
+    }
+}
+
 /// Simulate a bundle of transactions, commiting each tx to the EVM's ForkDB.
 ///
 /// Returns array containing each tx's simulation result.
-pub async fn sim_bundle(
+pub fn sim_bundle(
     evm: &mut EVM<ForkDB>,
     signed_txs: Vec<Transaction>,
 ) -> Result<Vec<ExecutionResult>> {
     let mut results = vec![];
     for tx in signed_txs {
-        let res = commit_tx(evm, tx).await;
+        let res = commit_tx(evm, tx);
         if let Ok(res) = res {
             results.push(res.to_owned());
         }
@@ -316,14 +490,84 @@ pub async fn sim_bundle(
     Ok(results)
 }
 
+/// Pays `amount` (wei) of the native token from `payer` to the block's coinbase on the forked EVM, commiting it,
+/// like the last tx of a bundle that pays its builder for inclusion. `payer` pays for gas at `base_fee`,
+/// which leaves the coinbase no priority fee, so it receives exactly `amount`.
+///
+/// Returns the gas the transfer used. Fails if it reverts or halts, or the coinbase didn't receive `amount`.
+pub fn commit_coinbase_transfer(
+    evm: &mut EVM<ForkDB>,
+    payer: Address,
+    amount: U256,
+    base_fee: U256,
+) -> Result<u64> {
+    let coinbase = evm.env.block.coinbase;
+    let balance_before = native_balance(evm, coinbase)?;
+    evm.env.tx.caller = payer.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(coinbase);
+    evm.env.tx.data = Default::default();
+    // builders' fee recipients may be contracts, which use more than a plain transfer
+    evm.env.tx.gas_limit = 100_000;
+    evm.env.tx.gas_price = base_fee.into();
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.value = amount.into();
+    let res = evm
+        .transact_commit()
+        .map_err(|err| anyhow::anyhow!("failed to pay coinbase: {:?}", err))?;
+    let gas_used = match res {
+        ExecutionResult::Success { gas_used, .. } => gas_used,
+        ExecutionResult::Revert { output, .. } => {
+            return Err(anyhow::format_err!(SimulationError::EvmReverted(output)))
+        }
+        ExecutionResult::Halt { reason, .. } => {
+            return Err(anyhow::format_err!(SimulationError::EvmHalted(reason)))
+        }
+    };
+    let received = native_balance(evm, coinbase)?.saturating_sub(balance_before);
+    let expected: rU256 = amount.into();
+    if received != expected {
+        return Err(anyhow::anyhow!(
+            "coinbase received {} wei instead of {}",
+            received,
+            amount
+        ));
+    }
+    Ok(gas_used)
+}
+
+/// Returns `account`'s balance of the native token in the forked EVM.
+fn native_balance(evm: &mut EVM<ForkDB>, account: B160) -> Result<rU256> {
+    let db = evm.db().ok_or(anyhow::format_err!("evm has no database"))?;
+    let info = Database::basic(db, account)
+        .map_err(|err| anyhow::anyhow!("failed to read balance of {:?}: {:?}", account, err))?;
+    Ok(info.map(|info| info.balance).unwrap_or_default())
+}
+
+/// Executes the user's tx on the forked EVM, commiting its state changes to the EVM's ForkDB.
+///
+/// Fails with `HindsightError::UserTxReverted` if the tx reverts or halts, or can't be executed on the fork at all
+/// (e.g. its nonce was already used), since there's nothing to backrun then.
+pub fn commit_user_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<()> {
+    let tx_hash = tx.hash;
+    let reverted =
+        |reason: String| -> Error { HindsightError::UserTxReverted(tx_hash, reason).into() };
+    match commit_tx(evm, tx).map_err(|err| reverted(err.to_string()))? {
+        ExecutionResult::Success { .. } => Ok(()),
+        ExecutionResult::Revert { output, gas_used } => {
+            Err(reverted(format!("{:?} (gas used: {:?})", output, gas_used)))
+        }
+        ExecutionResult::Halt { reason, .. } => Err(reverted(format!("halted: {:?}", reason))),
+    }
+}
+
 /// Execute a transaction on the forked EVM, commiting its state changes to the EVM's ForkDB.
-pub async fn commit_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ExecutionResult> {
+pub fn commit_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ExecutionResult> {
     inject_tx(evm, &tx)?;
     let res = evm.transact_commit();
     Ok(res.map_err(|err| anyhow::anyhow!("failed to simulate tx {:?}: {:?}", tx.hash, err))?)
 }
 
-pub async fn call_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ResultAndState> {
+pub fn call_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ResultAndState> {
     inject_tx(evm, &tx)?;
     let res = evm.transact();
     Ok(res.map_err(|err| anyhow::anyhow!("failed to simulate tx {:?}: {:?}", tx.hash, err))?)
@@ -370,4 +614,56 @@ mod tests {
         assert_ne!(price, U256::from(0));
         Ok(())
     }
+    #[tokio::test]
+    async fn it_runs_blocking_and_catches_panics() -> Result<()> {
+        assert_eq!(super::run_blocking(|| Ok(42)).await?, 42);
+        let panicked = super::run_blocking(|| -> Result<()> { panic!("boom") })
+            .await
+            .unwrap_err();
+        assert_eq!(panicked.to_string(), "evm execution panicked: boom");
+        Ok(())
+    }
+
+    #[test]
+    fn it_credits_transferred_balances() {
+        use super::{credited_balance, transferred_to};
+        use crate::sim::events::Erc20Transfer;
+        use ethers::{abi::AbiEncode, contract::EthEvent, types::H256};
+        use revm::primitives::{Log, B160, B256};
+
+        let (token, account) = (Address::from_low_u64_be(0xa), Address::from_low_u64_be(0xb));
+        let transfer = |token: Address, to: Address, value: u64| Log {
+            address: B160::from(token.0),
+            topics: vec![
+                B256::from(Erc20Transfer::signature().0),
+                B256::from(H256::from(Address::zero()).0),
+                B256::from(H256::from(to).0),
+            ],
+            data: U256::from(value).encode().into(),
+        };
+        let logs = vec![
+            transfer(token, account, 60),
+            transfer(token, Address::zero(), 1000),
+            transfer(Address::zero(), account, 1000),
+            transfer(token, account, 40),
+        ];
+        assert_eq!(transferred_to(&logs, token, account), Some(U256::from(100)));
+        assert_eq!(transferred_to(&logs[1..3], token, account), None);
+
+        // a rebase during the swap isn't output
+        assert_eq!(
+            credited_balance(1000.into(), 1150.into(), Some(100.into())),
+            U256::from(1100)
+        );
+        // fees on transfer (or shares rounded down) are
+        assert_eq!(
+            credited_balance(1000.into(), 1099.into(), Some(100.into())),
+            U256::from(1099)
+        );
+        // tokens that don't log transfers are taken at their balance
+        assert_eq!(
+            credited_balance(1000.into(), 1150.into(), None),
+            U256::from(1150)
+        );
+    }
 }
@@ -0,0 +1,149 @@
+use crate::{debug, util::WsClient, Result};
+use ethers::types::BlockNumber;
+use revm::EVM;
+use rusty_sando::{
+    forked_db::fork_factory::ForkFactory,
+    prelude::fork_db::ForkDB,
+    simulate::{attach_braindance_module, setup_block_state},
+    types::BlockInfo,
+    utils::state_diff,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+/// Number of blocks whose fork factories are kept by default.
+pub const FORK_CACHE_BLOCKS: usize = 8;
+
+/// Initializes a fork factory on the state at the end of `block_info.number`, with the braindance module attached.
+pub async fn fork_factory(client: &WsClient, block_info: &BlockInfo) -> Result<ForkFactory> {
+    let fork_block_num = BlockNumber::Number(block_info.number);
+    let fork_block = Some(ethers::types::BlockId::Number(fork_block_num));
+
+    let state_diffs = state_diff::get_from_txs(client, &vec![], fork_block_num)
+        .await
+        .unwrap_or_default();
+    let initial_db = state_diff::to_cache_db(&state_diffs, fork_block, client).await?;
+    let mut fork_factory = ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
+    attach_braindance_module(&mut fork_factory);
+    Ok(fork_factory)
+}
+
+/// Returns an evm on a new sandbox fork of `factory`, set up for `block_info`.
+pub fn fork_from(factory: &ForkFactory, block_info: &BlockInfo) -> EVM<ForkDB> {
+    let mut evm = EVM::new();
+    evm.database(factory.new_sandbox_fork());
+    setup_block_state(&mut evm, block_info);
+    evm
+}
+
+/// Factory of each cached block, initialized on first use.
+type FactoryCells = BTreeMap<u64, Arc<OnceCell<Arc<ForkFactory>>>>;
+
+/// Fork factories of the most recently simulated blocks, so that every sim of a block
+/// (each interval of each route of each tx) forks from one factory instead of fetching the block's state again.
+///
+/// Clones share the cache. A cache must only be used with one chain's client.
+#[derive(Clone)]
+pub struct ForkCache {
+    capacity: usize,
+    factories: Arc<Mutex<FactoryCells>>,
+    /// State that every fork starts from instead of its block's, when pinned (see `ForkCache::pinned`).
+    pinned: Option<Arc<Mutex<EVM<ForkDB>>>>,
+}
+
+impl Default for ForkCache {
+    fn default() -> Self {
+        Self::with_capacity(FORK_CACHE_BLOCKS)
+    }
+}
+
+impl std::fmt::Debug for ForkCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ForkCache")
+            .field("capacity", &self.capacity)
+            .field("blocks", &self.factories.lock().unwrap().keys())
+            .field("pinned", &self.pinned.is_some())
+            .finish()
+    }
+}
+
+impl ForkCache {
+    /// Keeps the factories of up to `capacity` blocks, evicting the lowest block numbers first.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            factories: Default::default(),
+            pinned: None,
+        }
+    }
+
+    /// Returns a cache whose every fork is a copy of `evm`, whatever block it's asked for,
+    /// e.g. to simulate a tx on the state partway through its block, after the txs before it.
+    pub fn pinned(evm: EVM<ForkDB>) -> Self {
+        Self {
+            pinned: Some(Arc::new(Mutex::new(evm))),
+            ..Self::with_capacity(1)
+        }
+    }
+
+    /// Returns an evm forked from the state at the end of `block_info.number`, like `fork_evm`.
+    ///
+    /// The block's factory is initialized by the first caller; concurrent callers wait for it
+    /// rather than initializing their own. If initializing fails, the next caller tries again.
+    pub async fn fork(&self, client: &WsClient, block_info: &BlockInfo) -> Result<EVM<ForkDB>> {
+        if let Some(pinned) = &self.pinned {
+            return Ok(pinned.lock().unwrap().clone());
+        }
+        let block = block_info.number.as_u64();
+        let cell = {
+            let mut factories = self.factories.lock().unwrap();
+            let cell = factories.entry(block).or_default().clone();
+            for evicted in evict_oldest(&mut factories, self.capacity, block) {
+                debug!("evicted fork factory of block {}", evicted);
+            }
+            cell
+        };
+        let factory = cell
+            .get_or_try_init(|| async { fork_factory(client, block_info).await.map(Arc::new) })
+            .await?;
+        Ok(fork_from(factory, block_info))
+    }
+}
+
+/// Removes the lowest keys of `entries` (except `keep`) until at most `capacity` remain, returning them.
+fn evict_oldest<T>(entries: &mut BTreeMap<u64, T>, capacity: usize, keep: u64) -> Vec<u64> {
+    let mut evicted = vec![];
+    while entries.len() > capacity {
+        let oldest = match entries.keys().find(|key| **key != keep) {
+            Some(oldest) => *oldest,
+            None => break,
+        };
+        entries.remove(&oldest);
+        evicted.push(oldest);
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_evicts_oldest_blocks() {
+        let mut entries = (100..104)
+            .map(|block| (block, ()))
+            .collect::<BTreeMap<_, _>>();
+        assert!(evict_oldest(&mut entries, 4, 103).is_empty());
+
+        // a late tx from an old block keeps its own entry
+        entries.insert(50, ());
+        assert_eq!(evict_oldest(&mut entries, 3, 50), vec![100, 101]);
+        assert_eq!(
+            entries.keys().copied().collect::<Vec<_>>(),
+            vec![50, 102, 103]
+        );
+    }
+}
@@ -0,0 +1,695 @@
+use crate::{
+    analysis::to_decimal,
+    data::{
+        tokens::{read_cache, write_cache},
+        EXPORT_DIR,
+    },
+    debug, info,
+    interfaces::{PoolVariant, RouteHop, UserTradeParams},
+    log_error,
+    sim::{
+        events::{decode_log, UniswapV2Sync, UniswapV3Swap},
+        registry::PoolRegistry,
+    },
+    util::{WsClient, FETCH_CONCURRENCY},
+    Result,
+};
+use ethers::{
+    contract::{abigen, EthEvent},
+    providers::Middleware,
+    types::{Address, Filter, Log},
+};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Fee of UniswapV2 pairs (and the clones indexed with them), in hundredths of a basis point.
+pub const UNISWAP_V2_FEE: u32 = 3000;
+/// Blocks indexed before following the head, if the graph hasn't indexed anything more recent.
+pub const GRAPH_BACKFILL_BLOCKS: u64 = 1000;
+/// Blocks of logs requested at once while indexing.
+const LOG_CHUNK_BLOCKS: u64 = 100;
+/// Pools holding at most this much of either token (in its smallest unit) are dust, and aren't traded through.
+const MIN_RESERVE: f64 = 1000.0;
+/// Cycles of two pools trade one pair, which the search already covers with each pair's other pools.
+const MIN_CYCLE_HOPS: usize = 3;
+
+/// A pool in the graph: an edge between its tokens.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolEdge {
+    pub pool: Address,
+    pub variant: PoolVariant,
+    pub token0: Address,
+    pub token1: Address,
+    /// Swap fee, in hundredths of a basis point (like UniswapV3's `fee()`).
+    pub fee: u32,
+    /// Reserves of each token in its smallest unit; for UniswapV3 pools, the virtual reserves of the active range.
+    pub reserve0: f64,
+    pub reserve1: f64,
+    /// Block of the log that the reserves were read from.
+    pub block: u64,
+}
+
+impl PoolEdge {
+    /// Edge of a UniswapV2 pair (or clone) of `tokens` from its `Sync` log, which carries its reserves.
+    pub fn from_sync_log(log: &Log, tokens: (Address, Address)) -> Result<Self> {
+        let sync = decode_log::<UniswapV2Sync>(log)?;
+        Ok(Self {
+            pool: log.address,
+            variant: PoolVariant::UniswapV2,
+            token0: tokens.0,
+            token1: tokens.1,
+            fee: UNISWAP_V2_FEE,
+            reserve0: to_decimal(sync.reserve0, 0),
+            reserve1: to_decimal(sync.reserve1, 0),
+            block: log.block_number.unwrap_or_default().as_u64(),
+        })
+    }
+
+    /// Edge of a UniswapV3 pool of `tokens` charging `fee` from its `Swap` log. Its reserves are the virtual reserves
+    /// of the active range, which price swaps that stay in the range like a UniswapV2 pair's.
+    pub fn from_v3_swap_log(log: &Log, tokens: (Address, Address), fee: u32) -> Result<Self> {
+        let swap = decode_log::<UniswapV3Swap>(log)?;
+        let sqrt_price = to_decimal(swap.sqrt_price_x96, 0) / 2f64.powi(96);
+        if sqrt_price == 0.0 {
+            return Err(anyhow::format_err!("pool {:?} has no price", log.address));
+        }
+        let liquidity = swap.liquidity as f64;
+        Ok(Self {
+            pool: log.address,
+            variant: PoolVariant::UniswapV3,
+            token0: tokens.0,
+            token1: tokens.1,
+            fee,
+            reserve0: liquidity / sqrt_price,
+            reserve1: liquidity * sqrt_price,
+            block: log.block_number.unwrap_or_default().as_u64(),
+        })
+    }
+
+    /// Amount of the other token out per `token_in` for a small trade, after fees.
+    /// None if the pool doesn't trade `token_in`, or only holds dust.
+    pub fn rate(&self, token_in: Address) -> Option<f64> {
+        let (reserve_in, reserve_out) = match token_in {
+            token if token == self.token0 => (self.reserve0, self.reserve1),
+            token if token == self.token1 => (self.reserve1, self.reserve0),
+            _ => return None,
+        };
+        if reserve_in <= MIN_RESERVE || reserve_out <= MIN_RESERVE {
+            return None;
+        }
+        Some(reserve_out / reserve_in * (1.0 - self.fee as f64 / 1_000_000.0))
+    }
+
+    fn hop(&self, token_in: Address) -> RouteHop {
+        RouteHop {
+            pool: self.pool,
+            variant: self.variant,
+            token_in,
+            token_out: match token_in == self.token0 {
+                true => self.token1,
+                false => self.token0,
+            },
+        }
+    }
+}
+
+/// A candidate backrun: swaps through several pools, starting & ending in the same token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    pub hops: Vec<RouteHop>,
+    /// Product of the hops' rates; a small trade around the cycle returns this much per token in.
+    pub rate: f64,
+}
+
+/// A swap through one pool in one direction, weighted by the negative log of its rate,
+/// so that the weights of a profitable cycle add up to less than 0.
+#[derive(Clone, Copy, Debug)]
+struct Leg {
+    from: Address,
+    to: Address,
+    weight: f64,
+    hop: RouteHop,
+}
+
+impl Leg {
+    fn new(edge: &PoolEdge, token_in: Address) -> Option<Self> {
+        let hop = edge.hop(token_in);
+        Some(Self {
+            from: hop.token_in,
+            to: hop.token_out,
+            weight: -edge.rate(token_in)?.ln(),
+            hop,
+        })
+    }
+}
+
+/// Lightest path of exactly `k` legs (at index `k`) between an origin token and each token,
+/// as (weight, (next token towards the origin, leg)).
+type Layers = Vec<HashMap<Address, (f64, Option<(Address, usize)>)>>;
+
+/// Bellman-Ford from (or, if `backward`, to) `origin`, over paths of up to `max_legs` of `legs`.
+/// Paths don't pass through the origin, which only starts or ends a cycle.
+fn relax(legs: &[Leg], origin: Address, max_legs: usize, backward: bool) -> Layers {
+    let mut layers: Layers = vec![HashMap::from([(origin, (0.0, None))])];
+    for _ in 0..max_legs {
+        let prev = layers.last().expect("layers start with the origin");
+        let mut next: HashMap<Address, (f64, Option<(Address, usize)>)> = HashMap::new();
+        for (idx, leg) in legs.iter().enumerate() {
+            let (near, far) = match backward {
+                true => (leg.to, leg.from),
+                false => (leg.from, leg.to),
+            };
+            if far == origin {
+                continue;
+            }
+            let Some((weight, _)) = prev.get(&near) else {
+                continue;
+            };
+            let weight = weight + leg.weight;
+            if next.get(&far).is_none_or(|(best, _)| weight < *best) {
+                next.insert(far, (weight, Some((near, idx))));
+            }
+        }
+        layers.push(next);
+    }
+    layers
+}
+
+/// Legs of the lightest path of `k` legs between the origin of `layers` and `token`, in the order they're traded.
+fn trace(layers: &Layers, mut token: Address, k: usize, backward: bool) -> Vec<usize> {
+    let mut path = vec![];
+    for layer in layers[1..=k].iter().rev() {
+        let Some((_, Some((near, idx)))) = layer.get(&token) else {
+            break;
+        };
+        path.push(*idx);
+        token = *near;
+    }
+    if !backward {
+        path.reverse();
+    }
+    path
+}
+
+/// Whether `hops` visit each token & pool once.
+fn is_simple(hops: &[RouteHop]) -> bool {
+    let tokens = hops.iter().map(|hop| hop.token_in).collect::<HashSet<_>>();
+    let pools = hops.iter().map(|hop| hop.pool).collect::<HashSet<_>>();
+    tokens.len() == hops.len() && pools.len() == hops.len()
+}
+
+/// Pools (as edges between their tokens) with the reserves of their latest indexed swap,
+/// so that backruns can be searched along paths of several pools rather than only each pair's other pools.
+///
+/// Kept up to date by `follow`, and saved as JSON in `EXPORT_DIR` if loaded with `load`. Clones share the graph.
+/// Only UniswapV2 & V3 pools (and their clones) are indexed.
+#[derive(Clone, Default)]
+pub struct PoolGraph {
+    path: Option<PathBuf>,
+    edges: Arc<Mutex<HashMap<Address, PoolEdge>>>,
+    /// Whether there are edges that haven't been saved yet.
+    dirty: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for PoolGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolGraph")
+            .field("path", &self.path)
+            .field("pools", &self.len())
+            .finish()
+    }
+}
+
+impl PoolGraph {
+    /// Loads the graph of `chain_id`, starting empty if it hasn't been saved before.
+    pub fn load(chain_id: u64) -> Result<Self> {
+        let path = PathBuf::from(EXPORT_DIR).join(format!("pool_graph_{}.json", chain_id));
+        let graph = Self {
+            path: Some(path.to_owned()),
+            ..Default::default()
+        };
+        for edge in read_cache::<PoolEdge>(&path)? {
+            graph.update(edge);
+        }
+        graph.dirty.store(false, Ordering::Relaxed);
+        Ok(graph)
+    }
+
+    /// Adds `edge`, unless the graph already has its pool from a later block.
+    pub fn update(&self, edge: PoolEdge) {
+        let mut edges = self.edges.lock().unwrap();
+        if edges
+            .get(&edge.pool)
+            .is_none_or(|indexed| indexed.block <= edge.block)
+        {
+            edges.insert(edge.pool, edge);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self, pool: &Address) -> Option<PoolEdge> {
+        self.edges.lock().unwrap().get(pool).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Latest block that an edge was read from, if any.
+    pub fn latest_block(&self) -> Option<u64> {
+        self.edges
+            .lock()
+            .unwrap()
+            .values()
+            .map(|edge| edge.block)
+            .max()
+    }
+
+    /// Edges of the graph's pools as `logs` left them, read from the last `Sync` (UniswapV2) or `Swap` (UniswapV3) log
+    /// of each. Pools that aren't in the graph are skipped, since their tokens (and fees) aren't known.
+    pub fn edges_from_logs(&self, logs: &[Log]) -> Vec<PoolEdge> {
+        let mut edges: HashMap<Address, PoolEdge> = HashMap::new();
+        for log in logs {
+            let Some(indexed) = self.get(&log.address) else {
+                continue;
+            };
+            let tokens = (indexed.token0, indexed.token1);
+            let edge = match log.topics.first() {
+                Some(topic) if *topic == UniswapV2Sync::signature() => {
+                    PoolEdge::from_sync_log(log, tokens)
+                }
+                Some(topic) if *topic == UniswapV3Swap::signature() => {
+                    PoolEdge::from_v3_swap_log(log, tokens, indexed.fee)
+                }
+                _ => continue,
+            };
+            if let Ok(edge) = edge {
+                edges.insert(edge.pool, edge);
+            }
+        }
+        edges.into_values().collect()
+    }
+
+    /// Finds cycles of up to `max_hops` pools that start & end in `start` and trade through `through`, which
+    /// the graph's rates say are profitable for a small trade, best first. `overrides` replace the graph's edges
+    /// of their pools (e.g. with the state a user's tx left them in).
+    ///
+    /// Runs a bounded-hop Bellman-Ford on the log of each swap's rate, from `start` to each side of `through`
+    /// and back, so each length & direction yields the best cycle it finds rather than every cycle.
+    /// Cycles of two pools (one pair) aren't returned.
+    pub fn find_cycles(
+        &self,
+        start: Address,
+        through: Address,
+        max_hops: usize,
+        overrides: &[PoolEdge],
+    ) -> Vec<Cycle> {
+        let mut edges = self.edges.lock().unwrap().clone();
+        for edge in overrides {
+            edges.insert(edge.pool, edge.to_owned());
+        }
+        let Some(through) = edges.remove(&through) else {
+            return vec![];
+        };
+        if max_hops < MIN_CYCLE_HOPS {
+            return vec![];
+        }
+        let legs = edges
+            .values()
+            .flat_map(|edge| [Leg::new(edge, edge.token0), Leg::new(edge, edge.token1)])
+            .flatten()
+            .collect::<Vec<_>>();
+        let to_through = relax(&legs, start, max_hops - 1, false);
+        let from_through = relax(&legs, start, max_hops - 1, true);
+
+        let mut cycles = vec![];
+        let mut seen = HashSet::new();
+        let vias = [
+            Leg::new(&through, through.token0),
+            Leg::new(&through, through.token1),
+        ];
+        for via in vias.into_iter().flatten() {
+            for legs_in in 0..max_hops {
+                for legs_out in 0..max_hops - legs_in {
+                    if legs_in + 1 + legs_out < MIN_CYCLE_HOPS {
+                        continue;
+                    }
+                    let (Some((weight_in, _)), Some((weight_out, _))) = (
+                        to_through[legs_in].get(&via.from),
+                        from_through[legs_out].get(&via.to),
+                    ) else {
+                        continue;
+                    };
+                    let rate = (-(weight_in + via.weight + weight_out)).exp();
+                    if rate <= 1.0 {
+                        continue;
+                    }
+                    let mut hops = trace(&to_through, via.from, legs_in, false)
+                        .into_iter()
+                        .map(|idx| legs[idx].hop)
+                        .collect::<Vec<_>>();
+                    hops.push(via.hop);
+                    hops.extend(
+                        trace(&from_through, via.to, legs_out, true)
+                            .into_iter()
+                            .map(|idx| legs[idx].hop),
+                    );
+                    if hops.len() != legs_in + 1 + legs_out || !is_simple(&hops) {
+                        continue;
+                    }
+                    if seen.insert(hops.iter().map(|hop| hop.pool).collect::<Vec<_>>()) {
+                        cycles.push(Cycle { hops, rate });
+                    }
+                }
+            }
+        }
+        cycles.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+        cycles
+    }
+
+    /// Indexes the latest `Sync` (UniswapV2) or `Swap` (UniswapV3) log of every pool that traded from block `from`
+    /// to `to`, looking up each new pool's tokens in `registry` (and its fee, for UniswapV3 pools).
+    ///
+    /// Pools whose tokens or fee can't be read are skipped. Returns the number of pools indexed.
+    pub async fn index_blocks(
+        &self,
+        client: &WsClient,
+        registry: &PoolRegistry,
+        from: u64,
+        to: u64,
+    ) -> Result<usize> {
+        let topics = vec![UniswapV2Sync::signature(), UniswapV3Swap::signature()];
+        // logs come in chain order, so each pool's last log has its latest state
+        let mut latest: HashMap<Address, Log> = HashMap::new();
+        let mut chunk_start = from;
+        while chunk_start <= to {
+            let chunk_end = (chunk_start + LOG_CHUNK_BLOCKS - 1).min(to);
+            let filter = Filter::new()
+                .from_block(chunk_start)
+                .to_block(chunk_end)
+                .topic0(topics.to_owned());
+            for log in client.get_logs(&filter).await? {
+                latest.insert(log.address, log);
+            }
+            chunk_start = chunk_end + 1;
+        }
+        let edges = stream::iter(latest.into_values())
+            .map(|log| async move { self.edge_of(client, registry, &log).await })
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+        let mut num_indexed = 0;
+        for edge in edges {
+            match edge {
+                Ok(edge) => {
+                    self.update(edge);
+                    num_indexed += 1;
+                }
+                Err(err) => debug!("skipping pool: {}", err),
+            }
+        }
+        Ok(num_indexed)
+    }
+
+    async fn edge_of(
+        &self,
+        client: &WsClient,
+        registry: &PoolRegistry,
+        log: &Log,
+    ) -> Result<PoolEdge> {
+        let tokens = registry.pair_tokens(client, log.address).await?;
+        if log.topics.first() == Some(&UniswapV2Sync::signature()) {
+            return PoolEdge::from_sync_log(log, tokens);
+        }
+        let fee = match self
+            .get(&log.address)
+            .map(|edge| edge.fee)
+            .or(registry.indexed_fee(log.address))
+        {
+            Some(fee) => fee,
+            None => get_v3_fee(client, log.address).await?,
+        };
+        PoolEdge::from_v3_swap_log(log, tokens, fee)
+    }
+
+    /// Indexes new blocks every `interval`, forever, saving the graph after each round. Starts after the latest block
+    /// the graph has indexed, or `GRAPH_BACKFILL_BLOCKS` before the head if that's more recent.
+    ///
+    /// Failed rounds are logged, and their blocks are indexed with the next round's.
+    pub async fn follow(self, client: WsClient, registry: PoolRegistry, interval: Duration) {
+        let mut next_block = None;
+        loop {
+            if let Err(err) = self
+                .index_new_blocks(&client, &registry, &mut next_block)
+                .await
+            {
+                log_error!("failed to index the pool graph: {}", err);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn index_new_blocks(
+        &self,
+        client: &WsClient,
+        registry: &PoolRegistry,
+        next_block: &mut Option<u64>,
+    ) -> Result<()> {
+        let head = client.get_block_number().await?.as_u64();
+        let from = next_block.unwrap_or_else(|| {
+            let backfill = head.saturating_sub(GRAPH_BACKFILL_BLOCKS);
+            self.latest_block()
+                .map_or(backfill, |block| (block + 1).max(backfill))
+        });
+        if from > head {
+            return Ok(());
+        }
+        let num_indexed = self.index_blocks(client, registry, from, head).await?;
+        info!(
+            "indexed {} pools of blocks {}..={} ({} pools in the graph)",
+            num_indexed,
+            from,
+            head,
+            self.len()
+        );
+        *next_block = Some(head + 1);
+        self.save()
+    }
+
+    /// Saves the graph to its file if it was loaded from one and has new edges.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let edges = self
+            .edges
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let saved = write_cache(path, edges.iter().collect());
+        if saved.is_err() {
+            // try again next time
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        saved
+    }
+}
+
+/// Returns the fee of the UniswapV3 `pool`, in hundredths of a basis point.
+async fn get_v3_fee(client: &WsClient, pool: Address) -> Result<u32> {
+    abigen!(
+        IUniswapV3PoolFee,
+        r#"[
+            function fee() external view returns (uint24)
+        ]"#
+    );
+    Ok(IUniswapV3PoolFee::new(pool, client.clone())
+        .fee()
+        .call()
+        .await?)
+}
+
+/// Searches a `PoolGraph` for backruns through more pools than the user's pair.
+#[derive(Clone, Debug)]
+pub struct PathFinder {
+    pub graph: PoolGraph,
+    /// Most pools that a cycle trades through, including the user's.
+    pub max_hops: usize,
+    /// Most cycles simulated per user trade, best first.
+    pub max_cycles: usize,
+}
+
+impl PathFinder {
+    /// Cycles through the pool of the user's trade `params` that start & end in its quote asset, priced at
+    /// the state that the user's tx (with `receipt_logs`) left the graph's pools in.
+    ///
+    /// Pools that the tx didn't trade are priced as of their latest indexed swap, so cycles are only candidates
+    /// for the simulator to try. A UniswapV2 user pool that hasn't been indexed yet is priced from its `Sync` log.
+    pub fn candidates(&self, params: &UserTradeParams, receipt_logs: &[Log]) -> Vec<Cycle> {
+        let mut overrides = self.graph.edges_from_logs(receipt_logs);
+        if params.pool_variant == PoolVariant::UniswapV2
+            && !overrides.iter().any(|edge| edge.pool == params.pool)
+        {
+            let tokens = match params.token0_is_quote {
+                true => (params.tokens.quote, params.tokens.token),
+                false => (params.tokens.token, params.tokens.quote),
+            };
+            let sync = receipt_logs.iter().rev().find(|log| {
+                log.address == params.pool
+                    && log.topics.first() == Some(&UniswapV2Sync::signature())
+            });
+            if let Some(edge) = sync.and_then(|log| PoolEdge::from_sync_log(log, tokens).ok()) {
+                overrides.push(edge);
+            }
+        }
+        let mut cycles =
+            self.graph
+                .find_cycles(params.tokens.quote, params.pool, self.max_hops, &overrides);
+        cycles.truncate(self.max_cycles);
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{H256, U256};
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    /// A V2 pair of tokens `a` & `b` holding `reserve_a` & `reserve_b` (in millions).
+    fn edge(pool: u64, a: u64, b: u64, reserve_a: f64, reserve_b: f64) -> PoolEdge {
+        PoolEdge {
+            pool: address(pool),
+            variant: PoolVariant::UniswapV2,
+            token0: address(a),
+            token1: address(b),
+            fee: UNISWAP_V2_FEE,
+            reserve0: reserve_a * 1e6,
+            reserve1: reserve_b * 1e6,
+            block: 1,
+        }
+    }
+
+    fn pools(cycle: &Cycle) -> Vec<Address> {
+        cycle.hops.iter().map(|hop| hop.pool).collect()
+    }
+
+    #[test]
+    fn it_finds_profitable_cycles_through_a_pool() {
+        let (quote, a, b) = (1, 2, 3);
+        let graph = PoolGraph::default();
+        // quote -> a at 1:1, a -> b at 1:2 (the user's pool, mispriced), b -> quote at 1:1
+        graph.update(edge(0xa, quote, a, 100.0, 100.0));
+        graph.update(edge(0xb, a, b, 100.0, 200.0));
+        graph.update(edge(0xc, b, quote, 100.0, 100.0));
+        // only dust, so it's never traded through
+        graph.update(edge(0xd, b, quote, 0.0001, 100.0));
+
+        let cycles = graph.find_cycles(address(quote), address(0xb), 3, &[]);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(
+            pools(&cycles[0]),
+            vec![address(0xa), address(0xb), address(0xc)]
+        );
+        assert_eq!(cycles[0].hops[0].token_in, address(quote));
+        assert_eq!(cycles[0].hops[1].token_out, address(b));
+        assert!((cycles[0].rate - 2.0 * 0.997f64.powi(3)).abs() < 1e-9);
+
+        // too few hops, or a pool the graph doesn't have
+        assert!(graph
+            .find_cycles(address(quote), address(0xb), 2, &[])
+            .is_empty());
+        assert!(graph
+            .find_cycles(address(quote), address(0xe), 3, &[])
+            .is_empty());
+        // once the user's pool is back in line, there's nothing to arb
+        let overrides = [edge(0xb, a, b, 100.0, 100.0)];
+        assert!(graph
+            .find_cycles(address(quote), address(0xb), 3, &overrides)
+            .is_empty());
+    }
+
+    #[test]
+    fn it_reads_edges_from_logs() {
+        let words = |words: &[U256]| {
+            words
+                .iter()
+                .flat_map(|word| {
+                    let mut bytes = [0u8; 32];
+                    word.to_big_endian(&mut bytes);
+                    bytes
+                })
+                .collect::<Vec<_>>()
+                .into()
+        };
+        let graph = PoolGraph::default();
+        graph.update(edge(0xa, 1, 2, 1.0, 1.0));
+        let mut v3 = edge(0xb, 1, 3, 1.0, 1.0);
+        v3.variant = PoolVariant::UniswapV3;
+        v3.fee = 500;
+        graph.update(v3);
+
+        let sync = |reserve0: u64| Log {
+            address: address(0xa),
+            topics: vec![UniswapV2Sync::signature()],
+            data: words(&[reserve0.into(), 4000.into()]),
+            ..Default::default()
+        };
+        // a price of 4 (sqrt 2) with liquidity 1000
+        let swap = Log {
+            address: address(0xb),
+            topics: vec![UniswapV3Swap::signature(), H256::zero(), H256::zero()],
+            data: words(&[
+                0.into(),
+                0.into(),
+                U256::from(2) << 96,
+                1000.into(),
+                0.into(),
+            ]),
+            ..Default::default()
+        };
+        let unindexed = Log {
+            address: address(0xc),
+            ..sync(1)
+        };
+        let mut edges = graph.edges_from_logs(&[sync(1000), swap, sync(2000), unindexed]);
+        edges.sort_by_key(|edge| edge.pool);
+        assert_eq!(edges.len(), 2);
+        // the pair's last sync counts
+        assert_eq!((edges[0].reserve0, edges[0].reserve1), (2000.0, 4000.0));
+        assert_eq!(edges[0].rate(address(1)), Some(2.0 * 0.997));
+        assert_eq!((edges[1].reserve0, edges[1].reserve1), (500.0, 2000.0));
+        assert_eq!(edges[1].fee, 500);
+        assert_eq!(edges[1].rate(address(4)), None);
+
+        // older edges don't replace newer ones
+        let mut stale = edge(0xa, 1, 2, 5.0, 5.0);
+        stale.block = 0;
+        graph.update(stale);
+        assert_eq!(graph.get(&address(0xa)).unwrap().reserve0, 1e6);
+        assert_eq!(graph.latest_block(), Some(1));
+    }
+}
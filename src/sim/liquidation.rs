@@ -0,0 +1,768 @@
+use crate::{
+    analysis::to_decimal,
+    error::HindsightError,
+    info,
+    interfaces::{LendingProtocol, QuoteAsset, SimLiquidationResult},
+    log_error,
+    sim::{
+        evm::{
+            call_with_args, commit_call_with_args, commit_user_tx, run_blocking, set_token_balance,
+            token_balance,
+        },
+        fork::ForkCache,
+    },
+    util::WsClient,
+    Error, Result,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{Address, Transaction, I256, U256},
+    utils::{hex, id},
+};
+use mev_share_sse::EventHistory;
+use revm::EVM;
+use rusty_sando::{
+    prelude::fork_db::ForkDB, simulate::braindance_controller_address, types::BlockInfo,
+    utils::constants::get_eth_dev,
+};
+use std::{path::Path, sync::Arc};
+
+/// Aave V3's pool on mainnet.
+pub const AAVE_V3_POOL: &str = "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2";
+/// Compound V3's USDC & WETH markets (Comets) on mainnet.
+pub const COMPOUND_V3_USDC: &str = "0xc3d688B66703497DAA19211EEdff47f25384cdc3";
+pub const COMPOUND_V3_WETH: &str = "0xA17581A9E3356d9A858b789D68B4d866e593aE94";
+
+/// What the liquidator is funded with before repaying a position's debt; more than any position could owe.
+const LIQUIDATOR_BALANCE: u64 = 30;
+
+/// A borrower's position in a lending market.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionHealth {
+    pub liquidatable: bool,
+    /// Health factor (liquidatable below 1), for markets that have one. None if the position has no debt.
+    pub health_factor: Option<f64>,
+}
+
+/// A liquidation simulated by a `LendingMarket`. Values are in USD with `USD_DECIMALS` decimals.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Liquidation {
+    pub debt_asset: Address,
+    pub debt_repaid: U256,
+    pub collateral_asset: Address,
+    pub collateral_received: U256,
+    pub profit_usd: I256,
+    pub gas_used: u64,
+}
+
+/// Support for finding & simulating liquidations in one lending market, like `PoolAdapter` is for AMMs.
+pub trait LendingMarket: Send + Sync {
+    fn protocol(&self) -> LendingProtocol;
+
+    /// Pool (Aave) or Comet (Compound) contract of the market.
+    fn address(&self) -> Address;
+
+    /// Reads `borrower`'s position in the forked EVM, without committing anything.
+    fn health(&self, evm: &mut EVM<ForkDB>, borrower: Address) -> Result<PositionHealth>;
+
+    /// Liquidates `borrower`'s position in the forked EVM from `liquidator`, an account that can pay for gas at
+    /// `base_fee`. The liquidator is funded with the debt it repays, which must be one of `quote_assets` (whose
+    /// balance slots are known). Returns None if the position can't be liquidated with a quote asset.
+    fn liquidate(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        borrower: Address,
+        liquidator: Address,
+        quote_assets: &[QuoteAsset],
+        base_fee: U256,
+    ) -> Result<Option<Liquidation>>;
+}
+
+/// The lending markets that liquidations are searched in on `chain_id`. Only mainnet has any.
+pub fn known_lending_markets(chain_id: u64) -> Vec<Arc<dyn LendingMarket>> {
+    let address = |address: &str| address.parse::<Address>().expect("valid address");
+    match chain_id {
+        1 => vec![
+            Arc::new(AaveV3Market {
+                pool: address(AAVE_V3_POOL),
+            }),
+            Arc::new(CompoundV3Market {
+                comet: address(COMPOUND_V3_USDC),
+            }),
+            Arc::new(CompoundV3Market {
+                comet: address(COMPOUND_V3_WETH),
+            }),
+        ],
+        _ => vec![],
+    }
+}
+
+/// Reads the borrowers to watch from `path`: one address per line. Blank lines & `#` comments are skipped.
+pub fn load_borrowers(path: &Path) -> Result<Vec<Address>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::format_err!("failed to read borrowers {:?}: {}", path, err))?;
+    parse_borrowers(&contents)
+}
+
+fn parse_borrowers(contents: &str) -> Result<Vec<Address>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(idx, line)| {
+            line.parse::<Address>().map_err(|err| {
+                anyhow::format_err!("line {}: invalid address '{}' ({})", idx + 1, line, err)
+            })
+        })
+        .collect()
+}
+
+fn selector(signature: &str) -> String {
+    format!("0x{}", hex::encode(id(signature)))
+}
+
+/// Calls the view function `signature` of `contract` with `args`, decoding its output as `outputs`.
+fn view(
+    evm: &mut EVM<ForkDB>,
+    contract: Address,
+    signature: &str,
+    args: &[Token],
+    outputs: &[ParamType],
+) -> Result<Vec<Token>> {
+    let output = call_with_args(evm, get_eth_dev(), &selector(signature), args, contract)?;
+    Ok(abi::decode(outputs, &output)?)
+}
+
+fn view_uint(
+    evm: &mut EVM<ForkDB>,
+    contract: Address,
+    signature: &str,
+    args: &[Token],
+) -> Result<U256> {
+    view(evm, contract, signature, args, &[ParamType::Uint(256)])?
+        .remove(0)
+        .into_uint()
+        .ok_or(
+            HindsightError::EvmParseError(format!(
+                "{} of {:?} returned no uint",
+                signature, contract
+            ))
+            .into(),
+        )
+}
+
+fn view_address(
+    evm: &mut EVM<ForkDB>,
+    contract: Address,
+    signature: &str,
+    args: &[Token],
+) -> Result<Address> {
+    view(evm, contract, signature, args, &[ParamType::Address])?
+        .remove(0)
+        .into_address()
+        .ok_or(
+            HindsightError::EvmParseError(format!(
+                "{} of {:?} returned no address",
+                signature, contract
+            ))
+            .into(),
+        )
+}
+
+fn uint_at(tokens: &[Token], idx: usize) -> U256 {
+    tokens
+        .get(idx)
+        .and_then(|token| token.to_owned().into_uint())
+        .unwrap_or_default()
+}
+
+fn address_at(tokens: &[Token], idx: usize) -> Address {
+    tokens
+        .get(idx)
+        .and_then(|token| token.to_owned().into_address())
+        .unwrap_or_default()
+}
+
+/// Value of `amount` of a token with `scale` (10^decimals) units per token, at `price` (USD, 8 decimals).
+pub fn usd_value(amount: U256, price: U256, scale: U256) -> U256 {
+    match scale.is_zero() {
+        true => U256::zero(),
+        false => amount
+            .full_mul(price)
+            .checked_div(scale.into())
+            .map_or(U256::MAX, |value| {
+                U256::try_from(value).unwrap_or(U256::MAX)
+            }),
+    }
+}
+
+/// Funds `liquidator` with `asset` and lets `spender` spend it.
+fn fund_liquidator(
+    evm: &mut EVM<ForkDB>,
+    asset: &QuoteAsset,
+    liquidator: Address,
+    spender: Address,
+    base_fee: U256,
+) -> Result<()> {
+    set_token_balance(
+        evm,
+        asset,
+        liquidator,
+        U256::exp10(LIQUIDATOR_BALANCE as usize),
+    )?;
+    commit_call_with_args(
+        evm,
+        liquidator,
+        &selector("approve(address,uint256)"),
+        &[Token::Address(spender), Token::Uint(U256::MAX)],
+        asset.address,
+        base_fee,
+    )?;
+    Ok(())
+}
+
+/// A reserve that a position borrows or uses as collateral, with the position's balance of it.
+#[derive(Clone, Debug)]
+struct Holding {
+    asset: Address,
+    amount: U256,
+    /// USD price (8 decimals), and units per whole token.
+    price: U256,
+    scale: U256,
+}
+
+impl Holding {
+    fn value(&self) -> U256 {
+        usd_value(self.amount, self.price, self.scale)
+    }
+}
+
+/// A market of Aave V3, or one of its forks (e.g. Spark).
+#[derive(Clone, Debug)]
+pub struct AaveV3Market {
+    pub pool: Address,
+}
+
+impl AaveV3Market {
+    /// The position's borrowed & collateral reserves, valued at the market's oracle prices.
+    fn holdings(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        borrower: Address,
+    ) -> Result<(Vec<Holding>, Vec<Holding>)> {
+        let reserves = view(
+            evm,
+            self.pool,
+            "getReservesList()",
+            &[],
+            &[ParamType::Array(Box::new(ParamType::Address))],
+        )?
+        .remove(0)
+        .into_array()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|token| token.into_address())
+        .collect::<Vec<_>>();
+        let user_config = view_uint(
+            evm,
+            self.pool,
+            "getUserConfiguration(address)",
+            &[Token::Address(borrower)],
+        )?;
+        let provider = view_address(evm, self.pool, "ADDRESSES_PROVIDER()", &[])?;
+        let oracle = view_address(evm, provider, "getPriceOracle()", &[])?;
+
+        let (mut debts, mut collaterals) = (vec![], vec![]);
+        for (idx, asset) in reserves.into_iter().enumerate() {
+            // two bits per reserve: borrowing, then using as collateral
+            let borrowing = user_config.bit(idx * 2);
+            let collateral = user_config.bit(idx * 2 + 1);
+            if !borrowing && !collateral {
+                continue;
+            }
+            // ReserveData: configuration, 5 indexes & rates, lastUpdateTimestamp, id, aToken, stableDebtToken,
+            // variableDebtToken, interestRateStrategy, accruedToTreasury, unbacked, isolationModeTotalDebt
+            let reserve = view(
+                evm,
+                self.pool,
+                "getReserveData(address)",
+                &[Token::Address(asset)],
+                &[
+                    vec![ParamType::Uint(256)],
+                    vec![ParamType::Uint(128); 5],
+                    vec![ParamType::Uint(40), ParamType::Uint(16)],
+                    vec![ParamType::Address; 4],
+                    vec![ParamType::Uint(128); 3],
+                ]
+                .concat(),
+            )?;
+            let price = view_uint(
+                evm,
+                oracle,
+                "getAssetPrice(address)",
+                &[Token::Address(asset)],
+            )?;
+            let scale = U256::exp10(view_uint(evm, asset, "decimals()", &[])?.as_usize());
+            let mut holding = |token: Address| -> Result<U256> {
+                match token.is_zero() {
+                    true => Ok(U256::zero()),
+                    false => token_balance(evm, token, borrower),
+                }
+            };
+            if borrowing {
+                let amount = holding(address_at(&reserve, 9))?
+                    .saturating_add(holding(address_at(&reserve, 10))?);
+                debts.push(Holding {
+                    asset,
+                    amount,
+                    price,
+                    scale,
+                });
+            }
+            if collateral {
+                let amount = holding(address_at(&reserve, 8))?;
+                collaterals.push(Holding {
+                    asset,
+                    amount,
+                    price,
+                    scale,
+                });
+            }
+        }
+        Ok((debts, collaterals))
+    }
+}
+
+impl LendingMarket for AaveV3Market {
+    fn protocol(&self) -> LendingProtocol {
+        LendingProtocol::AaveV3
+    }
+
+    fn address(&self) -> Address {
+        self.pool
+    }
+
+    fn health(&self, evm: &mut EVM<ForkDB>, borrower: Address) -> Result<PositionHealth> {
+        // totalCollateralBase, totalDebtBase, availableBorrowsBase, currentLiquidationThreshold, ltv, healthFactor
+        let data = view(
+            evm,
+            self.pool,
+            "getUserAccountData(address)",
+            &[Token::Address(borrower)],
+            &vec![ParamType::Uint(256); 6],
+        )?;
+        if uint_at(&data, 1).is_zero() {
+            return Ok(PositionHealth {
+                liquidatable: false,
+                health_factor: None,
+            });
+        }
+        let health_factor = uint_at(&data, 5);
+        Ok(PositionHealth {
+            liquidatable: health_factor < U256::exp10(18),
+            health_factor: Some(to_decimal(health_factor, 18)),
+        })
+    }
+
+    fn liquidate(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        borrower: Address,
+        liquidator: Address,
+        quote_assets: &[QuoteAsset],
+        base_fee: U256,
+    ) -> Result<Option<Liquidation>> {
+        let (debts, collaterals) = self.holdings(evm, borrower)?;
+        // repay the largest debt that the liquidator can be funded with, for the largest other collateral
+        let debt = debts
+            .into_iter()
+            .filter(|debt| !debt.amount.is_zero())
+            .filter_map(|debt| {
+                quote_assets
+                    .iter()
+                    .find(|asset| asset.address == debt.asset)
+                    .map(|asset| (debt, asset))
+            })
+            .max_by_key(|(debt, _)| debt.value());
+        let (debt, debt_quote_asset) = match debt {
+            Some(debt) => debt,
+            None => return Ok(None),
+        };
+        let collateral = collaterals
+            .into_iter()
+            .filter(|collateral| collateral.asset != debt.asset && !collateral.amount.is_zero())
+            .max_by_key(|collateral| collateral.value());
+        let collateral = match collateral {
+            Some(collateral) => collateral,
+            None => return Ok(None),
+        };
+
+        fund_liquidator(evm, debt_quote_asset, liquidator, self.pool, base_fee)?;
+        let debt_before = token_balance(evm, debt.asset, liquidator)?;
+        let collateral_before = token_balance(evm, collateral.asset, liquidator)?;
+        // a debt to cover of uint max repays as much as the close factor allows
+        let (_, gas_used) = commit_call_with_args(
+            evm,
+            liquidator,
+            &selector("liquidationCall(address,address,address,uint256,bool)"),
+            &[
+                Token::Address(collateral.asset),
+                Token::Address(debt.asset),
+                Token::Address(borrower),
+                Token::Uint(U256::MAX),
+                Token::Bool(false),
+            ],
+            self.pool,
+            base_fee,
+        )?;
+        let debt_repaid = debt_before.saturating_sub(token_balance(evm, debt.asset, liquidator)?);
+        let collateral_received =
+            token_balance(evm, collateral.asset, liquidator)?.saturating_sub(collateral_before);
+
+        Ok(Some(Liquidation {
+            debt_asset: debt.asset,
+            debt_repaid,
+            collateral_asset: collateral.asset,
+            collateral_received,
+            profit_usd: I256::from_raw(usd_value(
+                collateral_received,
+                collateral.price,
+                collateral.scale,
+            )) - I256::from_raw(usd_value(debt_repaid, debt.price, debt.scale)),
+            gas_used,
+        }))
+    }
+}
+
+/// A Compound V3 market (Comet), which lends one base asset against several collateral assets.
+///
+/// Liquidating is two steps: `absorb` seizes the position's collateral into the protocol's reserves, then
+/// `buyCollateral` buys it at the protocol's discount with the base asset. Only the most valuable collateral is bought.
+#[derive(Clone, Debug)]
+pub struct CompoundV3Market {
+    pub comet: Address,
+}
+
+impl CompoundV3Market {
+    /// The position's most valuable collateral.
+    fn largest_collateral(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        borrower: Address,
+    ) -> Result<Option<Holding>> {
+        let num_assets = view_uint(evm, self.comet, "numAssets()", &[])?.as_u64();
+        let mut largest: Option<Holding> = None;
+        for idx in 0..num_assets {
+            // AssetInfo: offset, asset, priceFeed, scale, borrowCollateralFactor, liquidateCollateralFactor,
+            // liquidationFactor, supplyCap
+            let info = view(
+                evm,
+                self.comet,
+                "getAssetInfo(uint8)",
+                &[Token::Uint(idx.into())],
+                &[
+                    vec![ParamType::Uint(8), ParamType::Address, ParamType::Address],
+                    vec![ParamType::Uint(64); 4],
+                    vec![ParamType::Uint(128)],
+                ]
+                .concat(),
+            )?;
+            let (asset, price_feed, scale) = (
+                address_at(&info, 1),
+                address_at(&info, 2),
+                uint_at(&info, 3),
+            );
+            let amount = view(
+                evm,
+                self.comet,
+                "userCollateral(address,address)",
+                &[Token::Address(borrower), Token::Address(asset)],
+                &[ParamType::Uint(128), ParamType::Uint(128)],
+            )
+            .map(|balances| uint_at(&balances, 0))?;
+            if amount.is_zero() {
+                continue;
+            }
+            let price = view_uint(
+                evm,
+                self.comet,
+                "getPrice(address)",
+                &[Token::Address(price_feed)],
+            )?;
+            let holding = Holding {
+                asset,
+                amount,
+                price,
+                scale,
+            };
+            if largest
+                .as_ref()
+                .is_none_or(|largest| holding.value() > largest.value())
+            {
+                largest = Some(holding);
+            }
+        }
+        Ok(largest)
+    }
+}
+
+impl LendingMarket for CompoundV3Market {
+    fn protocol(&self) -> LendingProtocol {
+        LendingProtocol::CompoundV3
+    }
+
+    fn address(&self) -> Address {
+        self.comet
+    }
+
+    fn health(&self, evm: &mut EVM<ForkDB>, borrower: Address) -> Result<PositionHealth> {
+        let liquidatable = view(
+            evm,
+            self.comet,
+            "isLiquidatable(address)",
+            &[Token::Address(borrower)],
+            &[ParamType::Bool],
+        )?
+        .remove(0)
+        .into_bool()
+        .unwrap_or_default();
+        Ok(PositionHealth {
+            liquidatable,
+            health_factor: None,
+        })
+    }
+
+    fn liquidate(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        borrower: Address,
+        liquidator: Address,
+        quote_assets: &[QuoteAsset],
+        base_fee: U256,
+    ) -> Result<Option<Liquidation>> {
+        let base = view_address(evm, self.comet, "baseToken()", &[])?;
+        let base_asset = match quote_assets.iter().find(|asset| asset.address == base) {
+            Some(asset) => asset,
+            None => return Ok(None),
+        };
+        let collateral = match self.largest_collateral(evm, borrower)? {
+            Some(collateral) => collateral,
+            None => return Ok(None),
+        };
+
+        let (_, absorb_gas) = commit_call_with_args(
+            evm,
+            liquidator,
+            &selector("absorb(address,address[])"),
+            &[
+                Token::Address(liquidator),
+                Token::Array(vec![Token::Address(borrower)]),
+            ],
+            self.comet,
+            base_fee,
+        )?;
+        fund_liquidator(evm, base_asset, liquidator, self.comet, base_fee)?;
+        // collateral is sold at a fixed discount, so its price per base unit gives the cost of all of it
+        let base_scale = view_uint(evm, self.comet, "baseScale()", &[])?;
+        let collateral_per_base = view_uint(
+            evm,
+            self.comet,
+            "quoteCollateral(address,uint256)",
+            &[Token::Address(collateral.asset), Token::Uint(base_scale)],
+        )?;
+        if collateral_per_base.is_zero() {
+            return Ok(None);
+        }
+        let base_amount = collateral.amount * base_scale / collateral_per_base;
+        let base_before = token_balance(evm, base, liquidator)?;
+        let collateral_before = token_balance(evm, collateral.asset, liquidator)?;
+        // reverts with NotForSale while the protocol's reserves are above their target
+        let (_, buy_gas) = commit_call_with_args(
+            evm,
+            liquidator,
+            &selector("buyCollateral(address,uint256,uint256,address)"),
+            &[
+                Token::Address(collateral.asset),
+                Token::Uint(U256::zero()),
+                Token::Uint(base_amount),
+                Token::Address(liquidator),
+            ],
+            self.comet,
+            base_fee,
+        )
+        .map_err(|err| anyhow::format_err!("buyCollateral failed: {}", err))?;
+        let debt_repaid = base_before.saturating_sub(token_balance(evm, base, liquidator)?);
+        let collateral_received =
+            token_balance(evm, collateral.asset, liquidator)?.saturating_sub(collateral_before);
+
+        let base_feed = view_address(evm, self.comet, "baseTokenPriceFeed()", &[])?;
+        let base_price = view_uint(
+            evm,
+            self.comet,
+            "getPrice(address)",
+            &[Token::Address(base_feed)],
+        )?;
+        Ok(Some(Liquidation {
+            debt_asset: base,
+            debt_repaid,
+            collateral_asset: collateral.asset,
+            collateral_received,
+            profit_usd: I256::from_raw(usd_value(
+                collateral_received,
+                collateral.price,
+                collateral.scale,
+            )) - I256::from_raw(usd_value(debt_repaid, base_price, base_scale)),
+            gas_used: absorb_gas + buy_gas,
+        }))
+    }
+}
+
+/// Simulates the liquidations of `borrowers` that `tx` made liquidatable in any of `markets`: positions that
+/// were healthy at the start of the tx's block, and liquidatable right after it.
+///
+/// Each liquidation runs from the braindance controller (which has ETH for gas) right after the user's tx.
+/// Positions that can't be liquidated (e.g. their debt isn't a quote asset) or whose liquidation fails are skipped.
+#[tracing::instrument(skip_all, fields(tx_hash = ?tx.hash))]
+pub async fn simulate_liquidations(
+    client: &WsClient,
+    forks: &ForkCache,
+    tx: &Transaction,
+    event: &EventHistory,
+    markets: &[Arc<dyn LendingMarket>],
+    borrowers: &[Address],
+    quote_assets: &[QuoteAsset],
+) -> Result<Vec<SimLiquidationResult>> {
+    if markets.is_empty() || borrowers.is_empty() {
+        return Ok(vec![]);
+    }
+    let tx_block = tx
+        .block_number
+        .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?
+        .as_u64();
+    let sim_block_num = tx_block - 1;
+    let block = client
+        .get_block(sim_block_num)
+        .await?
+        .ok_or::<Error>(HindsightError::BlockNotFound(sim_block_num).into())?;
+    let block_info = BlockInfo {
+        number: sim_block_num.into(),
+        timestamp: block.timestamp,
+        base_fee: block.base_fee_per_gas.unwrap_or(1_000_000_000.into()),
+    };
+    let mut evm = forks.fork(client, &block_info).await?;
+
+    let tx = tx.to_owned();
+    let event = event.to_owned();
+    let markets = markets.to_vec();
+    let borrowers = borrowers.to_vec();
+    let quote_assets = quote_assets.to_vec();
+    run_blocking(move || {
+        let mut healthy = vec![];
+        for market in &markets {
+            for borrower in &borrowers {
+                match market.health(&mut evm, *borrower) {
+                    Ok(health) if !health.liquidatable => healthy.push((market, *borrower, health)),
+                    Ok(_) => {}
+                    Err(err) => log_error!(
+                        "failed to check {:?} in {:?}: {}",
+                        borrower,
+                        market.address(),
+                        err
+                    ),
+                }
+            }
+        }
+        commit_user_tx(&mut evm, tx.to_owned())?;
+
+        let mut liquidations = vec![];
+        for (market, borrower, before) in healthy {
+            let after = match market.health(&mut evm, borrower) {
+                Ok(after) if after.liquidatable => after,
+                _ => continue,
+            };
+            info!(
+                ?borrower,
+                market = ?market.address(),
+                "tx made a position liquidatable"
+            );
+            let liquidation = market.liquidate(
+                &mut evm.clone(),
+                borrower,
+                braindance_controller_address().0.into(),
+                &quote_assets,
+                block_info.base_fee,
+            );
+            match liquidation {
+                Ok(Some(liquidation)) => liquidations.push(SimLiquidationResult {
+                    tx_hash: tx.hash,
+                    block: event.block,
+                    timestamp: event.timestamp,
+                    chain_id: 1,
+                    run_label: None,
+                    protocol: market.protocol(),
+                    market: market.address(),
+                    borrower,
+                    health_before: before.health_factor,
+                    health_after: after.health_factor,
+                    debt_asset: liquidation.debt_asset,
+                    debt_repaid: liquidation.debt_repaid,
+                    collateral_asset: liquidation.collateral_asset,
+                    collateral_received: liquidation.collateral_received,
+                    profit_usd: liquidation.profit_usd,
+                    gas_used: liquidation.gas_used,
+                }),
+                Ok(None) => info!(?borrower, "position can't be liquidated with a quote asset"),
+                Err(err) => log_error!(
+                    "failed to liquidate {:?} in {:?}: {}",
+                    borrower,
+                    market.address(),
+                    err
+                ),
+            }
+        }
+        Ok(liquidations)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_borrowers() -> Result<()> {
+        let borrowers = parse_borrowers(
+            "# at-risk aave borrowers\n0x0000000000000000000000000000000000000001\n\n  0x0000000000000000000000000000000000000002 # whale\n",
+        )?;
+        assert_eq!(
+            borrowers,
+            vec![Address::from_low_u64_be(1), Address::from_low_u64_be(2)]
+        );
+        let err = parse_borrowers("0x01\n0xnope").unwrap_err();
+        assert!(err.to_string().starts_with("line 1: invalid address"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_values_amounts_in_usd() {
+        // 2 WETH at $1,800.00000000
+        let price = U256::from(1_800) * U256::exp10(8);
+        assert_eq!(
+            usd_value(U256::from(2) * U256::exp10(18), price, U256::exp10(18)),
+            U256::from(3_600) * U256::exp10(8)
+        );
+        // 1,000 USDC at $1
+        assert_eq!(
+            usd_value(U256::from(1_000_000_000), U256::exp10(8), U256::exp10(6)),
+            U256::from(1_000) * U256::exp10(8)
+        );
+        assert_eq!(usd_value(U256::MAX, U256::MAX, U256::one()), U256::MAX);
+        assert!(usd_value(U256::one(), U256::one(), U256::zero()).is_zero());
+        assert!(known_lending_markets(1)
+            .iter()
+            .any(|market| market.protocol() == LendingProtocol::CompoundV3));
+        assert!(known_lending_markets(8453).is_empty());
+    }
+}
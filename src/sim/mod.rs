@@ -1,3 +1,19 @@
+pub mod budget;
+pub mod cache;
 pub mod core;
+pub mod events;
 pub mod evm;
+pub mod fork;
+pub mod graph;
+pub mod liquidation;
+pub mod pools;
 pub mod processor;
+pub mod registry;
+pub mod screen;
+pub mod tax;
+
+/// Version of the simulation engine, saved with each result so results of different engines can be told apart.
+///
+/// Bump this with any change that could change simulation results (e.g. the search, gas accounting,
+/// or which pools are tried), then check the new engine against the old one's results with `drift`.
+pub const ENGINE_VERSION: &'static str = "1";
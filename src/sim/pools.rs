@@ -0,0 +1,617 @@
+use crate::{
+    interfaces::{PairPool, PoolVariant},
+    sim::{
+        events::{decode_log, UniswapV2Swap, UniswapV2Sync, UniswapV3Swap},
+        evm::{sim_price_v2, sim_price_v3},
+    },
+    util::{get_price_v2, get_price_v3, get_v2_pools, get_v3_pools, WsClient},
+    Result,
+};
+use async_trait::async_trait;
+use ethers::{
+    contract::EthEvent,
+    types::{Address, Bytes, Log, H256, I256, U256},
+};
+use revm::EVM;
+use rusty_sando::{
+    prelude::fork_db::ForkDB,
+    simulate::{braindance_address, braindance_controller_address},
+    utils::tx_builder::braindance,
+};
+use std::sync::Arc;
+
+/// Swap(address,address,int256,int256,uint160,uint128,int24)
+pub const UNISWAP_V3_SWAP_TOPIC: &str =
+    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+/// Swap(address,uint256,uint256,uint256,uint256,address)
+pub const UNISWAP_V2_SWAP_TOPIC: &str =
+    "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+
+/// A user's trade, decoded from the log of their swap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodedSwap {
+    /// The user swapped token0 for token1 if this is positive.
+    /// Amounts are net of what moved the other way, so they may be negative.
+    pub amount0_sent: I256,
+    pub amount1_sent: I256,
+    /// Price (token1/token0) of the pool after the swap, or 0 if the logs don't tell.
+    /// UniswapV2 swaps priced 0 are priced from the pair's reserves when their trade params are derived.
+    pub price: U256,
+}
+
+/// A call that swaps on a pool in the forked EVM, paid with the braindance contract's funds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapCall {
+    pub caller: Address,
+    pub to: Address,
+    pub data: Bytes,
+}
+
+impl DecodedSwap {
+    /// Nets several swaps on one pool (e.g. a router's split route) into one trade, priced after the last of them.
+    ///
+    /// `swaps` must be in log order. Returns None if there are none.
+    pub fn net(swaps: impl IntoIterator<Item = DecodedSwap>) -> Option<Self> {
+        swaps.into_iter().reduce(|total, swap| Self {
+            amount0_sent: total.amount0_sent.saturating_add(swap.amount0_sent),
+            amount1_sent: total.amount1_sent.saturating_add(swap.amount1_sent),
+            price: swap.price,
+        })
+    }
+}
+
+impl SwapCall {
+    /// Calls the braindance contract, which swaps on UniswapV2 & V3 pools (and their clones).
+    pub fn braindance(data: Bytes) -> Self {
+        Self {
+            caller: Address::from(braindance_controller_address().0),
+            to: braindance_address(),
+            data,
+        }
+    }
+}
+
+/// Support for simulating backruns on the pools of one AMM.
+///
+/// Implement this to add an AMM that hindsight doesn't support, then register it with
+/// `Hindsight::with_pool_adapter` (or in `SimOptions::pool_adapters`). Its pools are tagged
+/// with its `variant` in results, so custom adapters should use a unique `PoolVariant::Custom` id.
+#[async_trait]
+pub trait PoolAdapter: Send + Sync {
+    fn variant(&self) -> PoolVariant;
+
+    /// Topic of the AMM's swap event, which MEV-Share hints share in `topics[0]`.
+    fn swap_topic(&self) -> H256;
+
+    /// Decodes the user's trade from `swap_log`. `logs` are all logs of the user's tx.
+    ///
+    /// A tx may swap on the same pool several times; each of its swap logs is decoded, then they're netted with `DecodedSwap::net`.
+    fn decode_swap(
+        &self,
+        swap_log: &Log,
+        logs: &[Log],
+        token0_decimals: U256,
+    ) -> Result<DecodedSwap>;
+
+    /// Returns the price (token1/token0) of `pool` in the forked EVM.
+    ///
+    /// The pools of a tx's routes are quoted one after another on the same fork, so this must not commit changes to it.
+    async fn quote_price(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<U256>;
+
+    /// Builds the call that swaps `amount_in` of `token_in` for `token_out` on `pool`.
+    ///
+    /// The swap must be paid from (and its proceeds sent to) the braindance contract, which holds the backrun's funds.
+    fn build_swap_call(
+        &self,
+        pool: Address,
+        amount_in: U256,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<SwapCall>;
+
+    /// Returns the braindance contract's balance of `token_out` after the swap call returned `output`.
+    fn decode_swap_output(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        output: Bytes,
+        token_out: Address,
+    ) -> Result<U256>;
+
+    /// Finds the AMM's pools that trade `tokens`. Pools that don't exist may be returned with a zero address.
+    async fn find_pools(
+        &self,
+        client: &WsClient,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UniswapV2Adapter;
+
+#[async_trait]
+impl PoolAdapter for UniswapV2Adapter {
+    fn variant(&self) -> PoolVariant {
+        PoolVariant::UniswapV2
+    }
+
+    fn swap_topic(&self) -> H256 {
+        UniswapV2Swap::signature()
+    }
+
+    fn decode_swap(
+        &self,
+        swap_log: &Log,
+        logs: &[Log],
+        token0_decimals: U256,
+    ) -> Result<DecodedSwap> {
+        let swap = decode_log::<UniswapV2Swap>(swap_log)?;
+        // if a Sync event is detected from the tx logs, it can be used to get the new price.
+        // pairs emit Sync right before Swap, so with several swaps on the pair, the swap's own Sync is the last one before it
+        let is_sync = |log: &&Log| {
+            log.topics.first() == Some(&UniswapV2Sync::signature())
+                && log.address == swap_log.address
+        };
+        let swap_index = logs.iter().position(|log| log == swap_log).unwrap_or(0);
+        let sync_log = logs[..swap_index]
+            .iter()
+            .rev()
+            .find(is_sync)
+            .or_else(|| logs[swap_index..].iter().find(is_sync));
+        let price = match sync_log {
+            Some(sync_log) => {
+                let sync = decode_log::<UniswapV2Sync>(sync_log)?;
+                get_price_v2(sync.reserve0, sync.reserve1, token0_decimals)?
+            }
+            None => U256::zero(),
+        };
+        // reserves are uint112, so these can't overflow
+        Ok(DecodedSwap {
+            amount0_sent: I256::from_raw(swap.amount0_out) - I256::from_raw(swap.amount0_in),
+            amount1_sent: I256::from_raw(swap.amount1_out) - I256::from_raw(swap.amount1_in),
+            price,
+        })
+    }
+
+    async fn quote_price(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<U256> {
+        sim_price_v2(pool, token_in, token_out, evm).await
+    }
+
+    fn build_swap_call(
+        &self,
+        pool: Address,
+        amount_in: U256,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<SwapCall> {
+        Ok(SwapCall::braindance(braindance::build_swap_v2_data(
+            amount_in, pool, token_in, token_out,
+        )))
+    }
+
+    fn decode_swap_output(
+        &self,
+        _evm: &mut EVM<ForkDB>,
+        output: Bytes,
+        _token_out: Address,
+    ) -> Result<U256> {
+        let (_amount_out, balance) = braindance::decode_swap_v2_result(output)
+            .map_err(|e| anyhow::anyhow!("failed to decode swap result: {:?}", e))?;
+        Ok(balance)
+    }
+
+    async fn find_pools(
+        &self,
+        client: &WsClient,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>> {
+        get_v2_pools(client, tokens).await
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UniswapV3Adapter;
+
+#[async_trait]
+impl PoolAdapter for UniswapV3Adapter {
+    fn variant(&self) -> PoolVariant {
+        PoolVariant::UniswapV3
+    }
+
+    fn swap_topic(&self) -> H256 {
+        UniswapV3Swap::signature()
+    }
+
+    fn decode_swap(
+        &self,
+        swap_log: &Log,
+        _logs: &[Log],
+        token0_decimals: U256,
+    ) -> Result<DecodedSwap> {
+        let swap = decode_log::<UniswapV3Swap>(swap_log)?;
+
+        // negative amounts were received by the user
+        Ok(DecodedSwap {
+            amount0_sent: swap.amount0,
+            amount1_sent: swap.amount1,
+            price: get_price_v3(swap.liquidity.into(), swap.sqrt_price_x96, token0_decimals)?,
+        })
+    }
+
+    async fn quote_price(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<U256> {
+        sim_price_v3(pool, token_in, token_out, evm).await
+    }
+
+    fn build_swap_call(
+        &self,
+        pool: Address,
+        amount_in: U256,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<SwapCall> {
+        Ok(SwapCall::braindance(braindance::build_swap_v3_data(
+            I256::from_raw(amount_in),
+            pool,
+            token_in,
+            token_out,
+        )))
+    }
+
+    fn decode_swap_output(
+        &self,
+        _evm: &mut EVM<ForkDB>,
+        output: Bytes,
+        _token_out: Address,
+    ) -> Result<U256> {
+        let (_amount_out, balance) = braindance::decode_swap_v3_result(output)
+            .map_err(|e| anyhow::anyhow!("failed to decode swap result: {:?}", e))?;
+        Ok(balance)
+    }
+
+    async fn find_pools(
+        &self,
+        client: &WsClient,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>> {
+        get_v3_pools(client, tokens).await
+    }
+}
+
+/// The AMMs that backruns are simulated on, looked up by pool variant or swap topic.
+///
+/// Defaults to the built-in UniswapV3 & UniswapV2 adapters (which also cover their clones, e.g. Sushiswap).
+#[derive(Clone)]
+pub struct PoolAdapters {
+    adapters: Vec<Arc<dyn PoolAdapter>>,
+}
+
+impl Default for PoolAdapters {
+    fn default() -> Self {
+        Self {
+            adapters: vec![Arc::new(UniswapV3Adapter), Arc::new(UniswapV2Adapter)],
+        }
+    }
+}
+
+impl std::fmt::Debug for PoolAdapters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.adapters.iter().map(|adapter| adapter.variant()))
+            .finish()
+    }
+}
+
+impl PoolAdapters {
+    /// Adds `adapter`, replacing the adapter of its variant (if any).
+    ///
+    /// If adapters share a swap topic, swaps are decoded by the last one registered.
+    pub fn register(&mut self, adapter: Arc<dyn PoolAdapter>) {
+        self.adapters
+            .retain(|other| other.variant() != adapter.variant());
+        self.adapters.push(adapter);
+    }
+
+    /// Adds `adapter` like `register`.
+    pub fn with(mut self, adapter: Arc<dyn PoolAdapter>) -> Self {
+        self.register(adapter);
+        self
+    }
+
+    /// Returns the adapter of `variant`'s pools.
+    pub fn get(&self, variant: PoolVariant) -> Result<&dyn PoolAdapter> {
+        self.adapters
+            .iter()
+            .find(|adapter| adapter.variant() == variant)
+            .map(|adapter| adapter.as_ref())
+            .ok_or(anyhow::format_err!(
+                "no adapter registered for pool variant {:?}",
+                variant
+            ))
+    }
+
+    /// Returns the adapter that decodes swap logs with `topic`.
+    pub fn by_swap_topic(&self, topic: &H256) -> Option<&dyn PoolAdapter> {
+        self.adapters
+            .iter()
+            .rev()
+            .find(|adapter| adapter.swap_topic() == *topic)
+            .map(|adapter| adapter.as_ref())
+    }
+
+    /// Swap topics of all adapters, for filtering events that can be simulated.
+    pub fn swap_topics(&self) -> Vec<H256> {
+        let mut topics = vec![];
+        for adapter in &self.adapters {
+            let topic = adapter.swap_topic();
+            if !topics.contains(&topic) {
+                topics.push(topic);
+            }
+        }
+        topics
+    }
+
+    /// Returns the price (token1/token0) of `pool` in the forked EVM, and its variant, quoted by the first adapter
+    /// (in the order they were registered) whose quote doesn't fail. For pools whose variant isn't known.
+    pub async fn quote_any(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        pool: Address,
+        (token0, token1): (Address, Address),
+    ) -> Result<(PoolVariant, U256)> {
+        let mut errors = vec![];
+        for adapter in &self.adapters {
+            match adapter.quote_price(evm, pool, token0, token1).await {
+                Ok(price) => return Ok((adapter.variant(), price)),
+                Err(err) => errors.push(format!("{:?}: {}", adapter.variant(), err)),
+            }
+        }
+        Err(anyhow::format_err!(
+            "no adapter quotes pool {:?} ({})",
+            pool,
+            errors.join(", ")
+        ))
+    }
+
+    /// Finds the pools of every AMM that trade `tokens`, in the order the adapters were registered.
+    pub async fn find_pools(
+        &self,
+        client: &WsClient,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>> {
+        let mut pools = vec![];
+        for adapter in &self.adapters {
+            pools.append(&mut adapter.find_pools(client, tokens).await?);
+        }
+        Ok(pools)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::ETH;
+    use std::str::FromStr;
+
+    /// An AMM whose swaps look like UniswapV2's, e.g. a V2 fork with a different fee.
+    struct ForkAdapter;
+
+    #[async_trait]
+    impl PoolAdapter for ForkAdapter {
+        fn variant(&self) -> PoolVariant {
+            PoolVariant::Custom(1)
+        }
+
+        fn swap_topic(&self) -> H256 {
+            UniswapV2Adapter.swap_topic()
+        }
+
+        fn decode_swap(&self, swap_log: &Log, logs: &[Log], decimals: U256) -> Result<DecodedSwap> {
+            UniswapV2Adapter.decode_swap(swap_log, logs, decimals)
+        }
+
+        async fn quote_price(
+            &self,
+            _evm: &mut EVM<ForkDB>,
+            _pool: Address,
+            _token_in: Address,
+            _token_out: Address,
+        ) -> Result<U256> {
+            Ok(ETH)
+        }
+
+        fn build_swap_call(
+            &self,
+            pool: Address,
+            _amount_in: U256,
+            _token_in: Address,
+            _token_out: Address,
+        ) -> Result<SwapCall> {
+            Ok(SwapCall::braindance(pool.as_bytes().to_vec().into()))
+        }
+
+        fn decode_swap_output(
+            &self,
+            _evm: &mut EVM<ForkDB>,
+            output: Bytes,
+            _token_out: Address,
+        ) -> Result<U256> {
+            Ok(U256::from_big_endian(&output))
+        }
+
+        async fn find_pools(
+            &self,
+            _client: &WsClient,
+            _tokens: (Address, Address),
+        ) -> Result<Vec<PairPool>> {
+            Ok(vec![])
+        }
+    }
+
+    fn log_data(words: &[U256]) -> Bytes {
+        words
+            .iter()
+            .flat_map(|word| {
+                let mut bytes = [0u8; 32];
+                word.to_big_endian(&mut bytes);
+                bytes
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn it_looks_up_adapters() -> Result<()> {
+        let v2_topic = H256::from_str(UNISWAP_V2_SWAP_TOPIC)?;
+        let v3_topic = H256::from_str(UNISWAP_V3_SWAP_TOPIC)?;
+        let adapters = PoolAdapters::default();
+        assert_eq!(adapters.swap_topics(), vec![v3_topic, v2_topic]);
+        assert_eq!(
+            adapters.get(PoolVariant::UniswapV2)?.variant(),
+            PoolVariant::UniswapV2
+        );
+        assert!(adapters.get(PoolVariant::Custom(1)).is_err());
+
+        // a custom adapter takes over the swaps of the topic it shares
+        let adapters = adapters.with(Arc::new(ForkAdapter));
+        assert_eq!(adapters.swap_topics(), vec![v3_topic, v2_topic]);
+        assert_eq!(
+            adapters.by_swap_topic(&v2_topic).map(|a| a.variant()),
+            Some(PoolVariant::Custom(1))
+        );
+        assert_eq!(
+            adapters.by_swap_topic(&v3_topic).map(|a| a.variant()),
+            Some(PoolVariant::UniswapV3)
+        );
+        assert!(adapters.get(PoolVariant::Custom(1)).is_ok());
+        assert!(adapters.by_swap_topic(&H256::zero()).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn it_decodes_v2_swaps() -> Result<()> {
+        let pool = Address::from_low_u64_be(0xa);
+        let user = H256::from(Address::from_low_u64_be(0xb));
+        let swap_log = Log {
+            address: pool,
+            topics: vec![H256::from_str(UNISWAP_V2_SWAP_TOPIC)?, user, user],
+            data: log_data(&[ETH, 0.into(), 0.into(), U256::from(2000) * ETH]),
+            ..Default::default()
+        };
+        let sync_log = Log {
+            address: pool,
+            topics: vec![UniswapV2Sync::signature()],
+            data: log_data(&[U256::from(100) * ETH, U256::from(200_000) * ETH]),
+            ..Default::default()
+        };
+        let logs = vec![swap_log.clone(), sync_log];
+        let swap = UniswapV2Adapter.decode_swap(&swap_log, &logs, 18.into())?;
+        assert_eq!(swap.amount0_sent, -I256::from_raw(ETH));
+        assert_eq!(swap.amount1_sent, I256::from_raw(U256::from(2000) * ETH));
+        assert_eq!(swap.price, U256::from(2000) * ETH);
+
+        // without a Sync log, the price isn't known
+        let swap = UniswapV2Adapter.decode_swap(&swap_log, &logs[..1], 18.into())?;
+        assert_eq!(swap.price, U256::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn it_nets_swaps_on_the_same_pool() -> Result<()> {
+        let pool = Address::from_low_u64_be(0xa);
+        let user = H256::from(Address::from_low_u64_be(0xb));
+        let swap_log = |amount0_in: u64, amount1_out: u64| Log {
+            address: pool,
+            topics: vec![UniswapV2Swap::signature(), user, user],
+            data: log_data(&[
+                U256::from(amount0_in) * ETH,
+                0.into(),
+                0.into(),
+                U256::from(amount1_out) * ETH,
+            ]),
+            ..Default::default()
+        };
+        let sync_log = |reserve0: u64, reserve1: u64| Log {
+            address: pool,
+            topics: vec![UniswapV2Sync::signature()],
+            data: log_data(&[U256::from(reserve0) * ETH, U256::from(reserve1) * ETH]),
+            ..Default::default()
+        };
+        // pairs emit Sync, then Swap
+        let logs = vec![
+            sync_log(101, 202_000),
+            swap_log(1, 2000),
+            sync_log(102, 202_980),
+            swap_log(1, 1000),
+        ];
+        let swaps = [&logs[1], &logs[3]]
+            .into_iter()
+            .map(|log| UniswapV2Adapter.decode_swap(log, &logs, 18.into()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(swaps[0].price, U256::from(2000) * ETH);
+        let swap = DecodedSwap::net(swaps).unwrap();
+        assert_eq!(swap.amount0_sent, -I256::from_raw(U256::from(2) * ETH));
+        assert_eq!(swap.amount1_sent, I256::from_raw(U256::from(3000) * ETH));
+        assert_eq!(swap.price, U256::from(1990) * ETH);
+        assert_eq!(DecodedSwap::net(vec![]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_malformed_swap_logs() -> Result<()> {
+        let user = H256::from(Address::from_low_u64_be(0xb));
+        let swap_log = Log {
+            topics: vec![H256::from_str(UNISWAP_V3_SWAP_TOPIC)?, user, user],
+            data: log_data(&[ETH]),
+            ..Default::default()
+        };
+        assert!(UniswapV3Adapter
+            .decode_swap(&swap_log, &[], 18.into())
+            .is_err());
+        // a V3 swap isn't a V2 swap, even with enough data
+        let swap_log = Log {
+            data: log_data(&[ETH; 5]),
+            ..swap_log
+        };
+        assert!(UniswapV3Adapter
+            .decode_swap(&swap_log, &[], 18.into())
+            .is_ok());
+        assert!(UniswapV2Adapter
+            .decode_swap(&swap_log, &[], 18.into())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_binds_events_to_their_topics() -> Result<()> {
+        assert_eq!(
+            UniswapV2Swap::signature(),
+            H256::from_str(UNISWAP_V2_SWAP_TOPIC)?
+        );
+        assert_eq!(
+            UniswapV3Swap::signature(),
+            H256::from_str(UNISWAP_V3_SWAP_TOPIC)?
+        );
+        assert_eq!(
+            UniswapV2Sync::signature(),
+            H256::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1")?
+        );
+        Ok(())
+    }
+}
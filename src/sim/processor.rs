@@ -1,22 +1,43 @@
 use crate::error::HindsightError;
-use crate::interfaces::SimArbResultBatch;
-use crate::{info, Error, Result};
-use crate::{sim::core::find_optimal_backrun_amount_in_out, util::WsClient};
+use crate::interfaces::{SimArbResult, SimArbResultBatch, SimFailure};
+use crate::{info, warn, Error, Result};
+use crate::{
+    sim::{
+        budget::{SimBudget, TxSimBudget, TxSimLimits},
+        core::{find_optimal_backrun_amount_in_out, resimulate_backrun, SearchContext},
+        pools::PoolAdapters,
+        ENGINE_VERSION,
+    },
+    util::WsClient,
+};
 use ethers::{
     providers::Middleware,
     types::{Transaction, H256, U256},
 };
+use futures::future;
 use mev_share_sse::EventHistory;
 use rusty_sando::types::BlockInfo;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 pub type H256Map<T> = HashMap<H256, T>;
 
+/// Simulates backruns of `tx` against each of its pools' alternatives (see `find_optimal_backrun_amount_in_out`),
+/// within `tx_limits`. Returns the arbs found, and a failure for each pool that couldn't be simulated.
+#[tracing::instrument(skip_all, fields(tx_hash = ?tx.hash))]
 pub async fn simulate_backrun_arbs(
-    client: &WsClient,
-    tx: Transaction,
+    search: &SearchContext<'_>,
+    tx: Arc<Transaction>,
     event_map: &H256Map<EventHistory>,
-) -> Result<SimArbResultBatch> {
+    tx_limits: TxSimLimits,
+) -> Result<(SimArbResultBatch, Vec<SimFailure>)> {
+    let SearchContext {
+        client,
+        quote_assets,
+        adapters,
+        budget,
+        ..
+    } = *search;
+    let tx_budget = TxSimBudget::new(tx_limits);
     let event = event_map
         .get(&tx.hash)
         .ok_or::<Error>(HindsightError::EventNotCached(tx.hash).into())?;
@@ -36,24 +57,107 @@ pub async fn simulate_backrun_arbs(
         base_fee: block.base_fee_per_gas.unwrap_or(1_000_000_000.into()),
     };
 
-    let res = find_optimal_backrun_amount_in_out(&client, tx, &event, &block_info).await?;
+    let (res, failures) =
+        find_optimal_backrun_amount_in_out(search, tx.clone(), event, &block_info, &tx_budget)
+            .await?;
+    let verify_mismatch =
+        tx_limits.verify && !verify_results(client, &tx, &block_info, &res, adapters, budget).await;
+    // profits in different tokens can't be compared, so only count results quoted in
+    // the highest-priority quote asset that any result used
+    let profit_asset = quote_assets
+        .iter()
+        .find(|asset| {
+            res.iter()
+                .any(|res| res.user_trade.tokens.quote == asset.address)
+        })
+        .or(quote_assets.first())
+        .ok_or(anyhow::format_err!("no quote assets configured"))?;
     let mut max_profit = U256::from(0);
     /*
        Sum up the profit from each result. Generally there should only be one result, but if
        there are >1 results, we assume that we'd do both backruns in one tx.
     */
     for res in &res {
+        if res.user_trade.tokens.quote != profit_asset.address {
+            continue;
+        }
         if res.backrun_trade.profit > max_profit {
             info!(
-                "sim was profitable: input={:?}\tend_balance={:?}",
-                res.backrun_trade.amount_in, res.backrun_trade.balance_end
+                tx_hash = ?event.hint.hash,
+                pool = ?res.user_trade.pool,
+                amount_in = %res.backrun_trade.amount_in,
+                balance_end = %res.backrun_trade.balance_end,
+                profit = %res.backrun_trade.profit,
+                "sim was profitable"
             );
             max_profit = res.backrun_trade.profit;
         }
     }
-    Ok(SimArbResultBatch {
+    let batch = SimArbResultBatch {
         event: event.to_owned(),
         max_profit,
         results: res,
-    })
+        // tagged by the caller, which knows which chain & run it's processing
+        chain_id: 1,
+        run_label: None,
+        profit_token: profit_asset.address,
+        profit_decimals: profit_asset.decimals,
+        base_fee: Some(block_info.base_fee),
+        realized: None,
+        engine_version: Some(ENGINE_VERSION.to_owned()),
+        budget_truncated: tx_budget.truncated(),
+        verify_mismatch,
+        block_hash: block.hash,
+        orphaned: false,
+    };
+    Ok((batch, failures))
+}
+
+/// Re-simulates each profitable result of `results` on a fresh fork (see `resimulate_backrun`),
+/// returning false if any doesn't reproduce its balance out.
+async fn verify_results(
+    client: &WsClient,
+    tx: &Transaction,
+    block_info: &BlockInfo,
+    results: &[SimArbResult],
+    adapters: &PoolAdapters,
+    budget: &SimBudget,
+) -> bool {
+    let profitable = results
+        .iter()
+        .filter(|res| !res.backrun_trade.profit.is_zero())
+        .collect::<Vec<_>>();
+    let reruns = future::join_all(
+        profitable
+            .iter()
+            .map(|res| resimulate_backrun(client, tx, block_info, res, adapters, budget)),
+    )
+    .await;
+    let mut verified = true;
+    for (res, rerun) in profitable.into_iter().zip(reruns) {
+        let expected = res.backrun_trade.balance_end;
+        match rerun {
+            Ok(balance_end) if balance_end == expected => {}
+            Ok(balance_end) => {
+                warn!(
+                    tx_hash = ?tx.hash,
+                    pool = ?res.user_trade.pool,
+                    %expected,
+                    %balance_end,
+                    "profitable result didn't reproduce on a fresh fork"
+                );
+                verified = false;
+            }
+            Err(err) => {
+                warn!(
+                    tx_hash = ?tx.hash,
+                    pool = ?res.user_trade.pool,
+                    "profitable result failed to re-simulate on a fresh fork: {}",
+                    err
+                );
+                verified = false;
+            }
+        }
+    }
+    verified
 }
@@ -0,0 +1,281 @@
+use crate::{
+    debug, info,
+    interfaces::{IndexedPool, PairPool, TokenTax},
+    sim::pools::PoolAdapters,
+    util::{
+        get_decimals, get_pair_tokens, get_pool_factory, PoolCache, WsClient, FETCH_CONCURRENCY,
+    },
+    Result,
+};
+use ethers::types::{Address, U256};
+use futures::{stream, Future, StreamExt};
+use mev_share_sse::EventHistory;
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+/// Values that never change once fetched (e.g. a pool's tokens), by key. Failed fetches aren't kept.
+#[derive(Clone, Debug)]
+struct Memo<K, V> {
+    values: Arc<Mutex<HashMap<K, V>>>,
+}
+
+impl<K, V> Default for Memo<K, V> {
+    fn default() -> Self {
+        Self {
+            values: Default::default(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Copy, V: Clone> Memo<K, V> {
+    async fn get_or_fetch<Fut: Future<Output = Result<V>>>(
+        &self,
+        key: K,
+        fetch: impl FnOnce() -> Fut,
+    ) -> Result<V> {
+        if let Some(value) = self.values.lock().unwrap().get(&key) {
+            return Ok(value.to_owned());
+        }
+        let value = fetch().await?;
+        self.values.lock().unwrap().insert(key, value.to_owned());
+        Ok(value)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: K, value: V) {
+        self.values.lock().unwrap().insert(key, value);
+    }
+
+    fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+}
+
+/// Onchain facts about pools & tokens that trade prep reads for every swap: each pool's tokens & factory,
+/// each token's decimals, and the pools of each token pair.
+///
+/// `warm` fetches them for a whole set of events up front, in parallel, so that deriving each tx's trades
+/// only reads from memory. Anything not warmed is fetched (and kept) on first use. Pools found by the `indexer`
+/// can be `seed`ed into it, so their tokens & factory are never fetched, and so can tokens' measured taxes
+/// (see `seed_taxes`). Clones share the registry.
+/// A registry must only be used with one chain's client & one set of adapters.
+#[derive(Clone, Default)]
+pub struct PoolRegistry {
+    pair_tokens: Memo<Address, (Address, Address)>,
+    factories: Memo<Address, Address>,
+    /// Fees of seeded pools, in hundredths of a basis point.
+    fees: Memo<Address, u32>,
+    decimals: Memo<Address, U256>,
+    /// Measured taxes of seeded tokens.
+    taxes: Memo<Address, TokenTax>,
+    pairs: PoolCache,
+}
+
+impl std::fmt::Debug for PoolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolRegistry")
+            .field("pools", &self.pair_tokens.len())
+            .field("tokens", &self.decimals.len())
+            .field("pairs", &self.pairs)
+            .finish()
+    }
+}
+
+impl PoolRegistry {
+    /// Returns (token0, token1) of `pool`.
+    pub async fn pair_tokens(
+        &self,
+        client: &WsClient,
+        pool: Address,
+    ) -> Result<(Address, Address)> {
+        self.pair_tokens
+            .get_or_fetch(pool, || get_pair_tokens(client, pool))
+            .await
+    }
+
+    /// Returns the factory that deployed `pool`.
+    pub async fn factory(&self, client: &WsClient, pool: Address) -> Result<Address> {
+        self.factories
+            .get_or_fetch(pool, || get_pool_factory(client, pool))
+            .await
+    }
+
+    /// Returns the fee of `pool` if it was seeded, in hundredths of a basis point.
+    pub fn indexed_fee(&self, pool: Address) -> Option<u32> {
+        self.fees.get(&pool)
+    }
+
+    /// Keeps the tokens, factory & fee of each of `pools`, as if they'd been fetched.
+    pub fn seed(&self, pools: &[IndexedPool]) {
+        for pool in pools {
+            self.pair_tokens
+                .insert(pool.address, (pool.token0, pool.token1));
+            self.factories.insert(pool.address, pool.factory);
+            self.fees.insert(pool.address, pool.fee);
+        }
+    }
+
+    /// Returns the measured taxes of `token`, if they were seeded.
+    pub fn token_tax(&self, token: Address) -> Option<TokenTax> {
+        self.taxes.get(&token)
+    }
+
+    /// Keeps the measured taxes of each token in `taxes`, so sims screen those tokens by them
+    /// instead of probing each one on a fork.
+    pub fn seed_taxes(&self, taxes: &[TokenTax]) {
+        for tax in taxes {
+            self.taxes.insert(tax.token, tax.clone());
+        }
+    }
+
+    pub async fn decimals(&self, client: &WsClient, token: Address) -> Result<U256> {
+        self.decimals
+            .get_or_fetch(token, || get_decimals(client, token))
+            .await
+    }
+
+    /// Returns the pools of every AMM in `adapters` that trade `tokens` (see `PoolCache::find_pools`).
+    pub async fn find_pools(
+        &self,
+        client: &WsClient,
+        adapters: &PoolAdapters,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>> {
+        self.pairs.find_pools(client, adapters, tokens).await
+    }
+
+    /// Fetches everything about the pools that `events` swap on (that an adapter in `adapters` decodes),
+    /// with about `FETCH_CONCURRENCY` requests in flight.
+    ///
+    /// Failures are logged, and left for the sims that need them to try again. Returns the number of pools found.
+    pub async fn warm<'a>(
+        &self,
+        client: &WsClient,
+        adapters: &PoolAdapters,
+        events: impl IntoIterator<Item = &'a EventHistory>,
+    ) -> usize {
+        let pools = swapped_pools(events, adapters);
+        // tokens & factory of each pool
+        let pair_tokens = stream::iter(pools.iter().copied())
+            .map(|pool| async move {
+                let (tokens, _) =
+                    futures::join!(self.pair_tokens(client, pool), self.factory(client, pool));
+                tokens
+                    .map_err(|err| debug!("failed to get tokens of pool {:?}: {}", pool, err))
+                    .ok()
+            })
+            .buffer_unordered(FETCH_CONCURRENCY / 2)
+            .filter_map(|tokens| async move { tokens })
+            .collect::<BTreeSet<_>>()
+            .await;
+        // then the decimals of each token, and the pools of each pair
+        let tokens = pair_tokens
+            .iter()
+            .flat_map(|(token0, token1)| [*token0, *token1])
+            .collect::<BTreeSet<_>>();
+        let decimals = stream::iter(tokens)
+            .map(|token| async move {
+                if let Err(err) = self.decimals(client, token).await {
+                    debug!("failed to get decimals of token {:?}: {}", token, err);
+                }
+            })
+            .buffer_unordered(FETCH_CONCURRENCY / 2)
+            .collect::<Vec<_>>();
+        let pairs = stream::iter(pair_tokens)
+            .map(|tokens| async move {
+                if let Err(err) = self.find_pools(client, adapters, tokens).await {
+                    debug!("failed to find pools for {:?}: {}", tokens, err);
+                }
+            })
+            .buffer_unordered(FETCH_CONCURRENCY / 2)
+            .collect::<Vec<_>>();
+        futures::join!(decimals, pairs);
+        info!(num_pools = pools.len(), "warmed pool registry");
+        pools.len()
+    }
+}
+
+/// Addresses of the pools that `events` swap on, according to their hinted logs.
+fn swapped_pools<'a>(
+    events: impl IntoIterator<Item = &'a EventHistory>,
+    adapters: &PoolAdapters,
+) -> BTreeSet<Address> {
+    let swap_topics = adapters.swap_topics();
+    events
+        .into_iter()
+        .flat_map(|event| event.hint.logs.iter())
+        .filter(|log| {
+            log.topics
+                .first()
+                .map(|topic| swap_topics.contains(topic))
+                .unwrap_or(false)
+        })
+        .map(|log| log.address)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H256;
+    use mev_share_sse::{EventTransactionLog, Hint};
+
+    fn event(logs: Vec<EventTransactionLog>) -> EventHistory {
+        EventHistory {
+            block: 1,
+            timestamp: 1,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::zero(),
+                logs,
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        }
+    }
+
+    fn log(address: u64, topic: H256) -> EventTransactionLog {
+        EventTransactionLog {
+            address: Address::from_low_u64_be(address),
+            topics: vec![topic],
+            data: Default::default(),
+        }
+    }
+
+    #[test]
+    fn it_finds_swapped_pools() {
+        let adapters = PoolAdapters::default();
+        let swap = adapters.swap_topics()[0];
+        let events = [
+            event(vec![log(1, swap), log(2, H256::repeat_byte(1))]),
+            event(vec![log(1, swap), log(3, swap)]),
+        ];
+        assert_eq!(
+            swapped_pools(&events, &adapters),
+            [1, 3].into_iter().map(Address::from_low_u64_be).collect()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_memoizes_fetches() -> Result<()> {
+        let memo = Memo::default();
+        assert!(memo
+            .get_or_fetch(1, || async { Err(anyhow::anyhow!("rpc error")) })
+            .await
+            .is_err());
+        assert_eq!(memo.get_or_fetch(1, || async { Ok(18) }).await?, 18);
+        assert_eq!(
+            memo.get_or_fetch(1, || async { panic!("should be memoized") })
+                .await?,
+            18
+        );
+        assert_eq!(memo.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    debug,
+    error::HindsightError,
+    info,
+    interfaces::UserTradeParams,
+    sim::{
+        evm::{commit_braindance_swap, fund_braindance, token_balance, transfer_returns_bool},
+        pools::PoolAdapter,
+    },
+    util::exceeds_share,
+    Error, Result,
+};
+use ethers::types::U256;
+use revm::EVM;
+use rusty_sando::{prelude::fork_db::ForkDB, simulate::braindance_address};
+
+/// Fraction of the quote asset that the screen's probe trades, of the braindance contract's starting balance.
+pub const PROBE_FRACTION: u64 = 1000;
+
+/// Screens the token that `params` trades against the quote asset, by buying it with a small probe of the quote asset
+/// on the user's pool, then selling all of it back on the same pool.
+///
+/// Fails with `HindsightError::UnsafeToken` if either swap reverts (e.g. the token paused transfers, or blacklisted
+/// the braindance contract), if the buy returns no tokens, or if the round trip loses more than `max_tax` of the probe
+/// (e.g. to a sell tax). The round trip pays the pool's fee twice, so `max_tax` should be well above it.
+///
+/// Swaps are committed to `evm`, so it should be a fork that's thrown away afterwards.
+pub fn screen_token(
+    evm: &mut EVM<ForkDB>,
+    adapter: &dyn PoolAdapter,
+    params: &UserTradeParams,
+    base_fee: U256,
+    max_tax: f64,
+) -> Result<()> {
+    let (quote, token) = (params.tokens.quote, params.tokens.token);
+    let unsafe_token =
+        |reason: String| -> Error { HindsightError::UnsafeToken(token, reason).into() };
+    let start_balance = params.quote_asset.starting_balance;
+    let probe = (start_balance / PROBE_FRACTION).max(1.into());
+    fund_braindance(evm, &params.quote_asset)?;
+    // the quote asset's balance may not be the funded amount, e.g. if it's stored as shares
+    let quote_before = token_balance(evm, quote, braindance_address())?;
+
+    let (bought, _) = commit_braindance_swap(
+        evm,
+        adapter,
+        probe,
+        params.pool,
+        quote,
+        token,
+        base_fee,
+        None,
+    )
+    .map_err(|err| unsafe_token(format!("buy failed: {}", err)))?;
+    if bought.is_zero() {
+        return Err(unsafe_token("buy returned no tokens".to_owned()));
+    }
+    // swaps are measured by balances, so tokens that don't follow the standard here can still be arbed
+    match transfer_returns_bool(evm, token, braindance_address()) {
+        Ok(true) => {}
+        Ok(false) => info!(?token, "token's transfer returns no bool"),
+        Err(err) => debug!(?token, "failed to check the token's transfer: {}", err),
+    }
+    let (balance, _) = commit_braindance_swap(
+        evm,
+        adapter,
+        bought,
+        params.pool,
+        token,
+        quote,
+        base_fee,
+        None,
+    )
+    .map_err(|err| unsafe_token(format!("sell failed: {}", err)))?;
+
+    // the rest of the starting balance was never traded
+    let returned = balance.saturating_add(probe).saturating_sub(quote_before);
+    let loss = probe.saturating_sub(returned);
+    if exceeds_share(loss, probe, max_tax) {
+        return Err(unsafe_token(format!(
+            "round trip of {} returned {}, a loss over the max token tax of {}",
+            probe, returned, max_tax
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_detects_taxes_over_the_max() {
+        let probe = U256::from(1_000_000);
+        // two 0.3% pool fees
+        assert!(!exceeds_share(6_000.into(), probe, 0.05));
+        assert!(!exceeds_share(50_000.into(), probe, 0.05));
+        assert!(exceeds_share(50_001.into(), probe, 0.05));
+        assert!(exceeds_share(probe, probe, 0.05));
+        assert!(!exceeds_share(U256::MAX, U256::MAX, 1.0));
+    }
+}
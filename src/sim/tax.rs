@@ -0,0 +1,232 @@
+use crate::{
+    debug,
+    error::HindsightError,
+    interfaces::{QuoteAsset, TokenTax},
+    sim::{
+        evm::{commit_braindance_swap, fund_braindance, run_blocking, token_balance},
+        fork::{fork_factory, fork_from},
+        pools::{PoolAdapter, PoolAdapters},
+        screen::PROBE_FRACTION,
+    },
+    util::{get_block_info, share_of, WsClient},
+    Error, Result,
+};
+use ethers::types::{Address, U256};
+use revm::EVM;
+use rusty_sando::{prelude::fork_db::ForkDB, simulate::braindance_address};
+
+/// Multiples of the probe (see `PROBE_FRACTION`) that are bought & sold back, to find the token's transaction limits.
+pub const TAX_PROBE_MULTIPLES: [u64; 4] = [1, 10, 100, 1000];
+
+impl TokenTax {
+    /// Fails with `HindsightError::UnsafeToken` if the token can't be sold, or if buying & selling it
+    /// loses more than `max_tax` to taxes (like the safety screen, but without the pools' fees).
+    pub fn check(&self, max_tax: f64) -> Result<()> {
+        let reason = match self.sell_tax {
+            None => "selling it reverted".to_owned(),
+            Some(sell_tax) if self.buy_tax + sell_tax > max_tax => format!(
+                "measured taxes of {:.2}% (buy) & {:.2}% (sell) exceed the max token tax of {}",
+                self.buy_tax * 100.0,
+                sell_tax * 100.0,
+                max_tax
+            ),
+            Some(_) => return Ok(()),
+        };
+        Err(HindsightError::UnsafeToken(self.token, reason).into())
+    }
+}
+
+/// Tokens that moved in a swap: what left the braindance contract & arrived at the pool, and what left the pool
+/// & arrived at the contract.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct SwapFlows {
+    in_sent: U256,
+    in_arrived: U256,
+    out_sent: U256,
+    out_arrived: U256,
+}
+
+/// Share of `sent` that didn't arrive.
+fn tax_of(sent: U256, arrived: U256) -> f64 {
+    share_of(sent.saturating_sub(arrived), sent)
+}
+
+/// Swaps `amount_in` of `token_in` for `token_out` on `pool` from the braindance contract, committing it to `evm`.
+fn swap_flows(
+    evm: &mut EVM<ForkDB>,
+    adapter: &dyn PoolAdapter,
+    pool: Address,
+    amount_in: U256,
+    (token_in, token_out): (Address, Address),
+    base_fee: U256,
+) -> Result<SwapFlows> {
+    let contract = braindance_address();
+    let balances = |evm: &mut EVM<ForkDB>| -> Result<[U256; 4]> {
+        Ok([
+            token_balance(evm, token_in, contract)?,
+            token_balance(evm, token_in, pool)?,
+            token_balance(evm, token_out, pool)?,
+            token_balance(evm, token_out, contract)?,
+        ])
+    };
+    let before = balances(evm)?;
+    commit_braindance_swap(
+        evm, adapter, amount_in, pool, token_in, token_out, base_fee, None,
+    )?;
+    let after = balances(evm)?;
+    Ok(SwapFlows {
+        in_sent: before[0].saturating_sub(after[0]),
+        in_arrived: after[1].saturating_sub(before[1]),
+        out_sent: before[2].saturating_sub(after[2]),
+        out_arrived: after[3].saturating_sub(before[3]),
+    })
+}
+
+/// Measures the taxes & transaction limits of `token` by trading it against `asset` on `pool` on copies of `evm`:
+/// buys of each of `TAX_PROBE_MULTIPLES` of the probe, each sold back right after.
+///
+/// The smallest buy & sell measure the taxes. The first larger buy (or sell) that reverts sets the limit
+/// at the one before it; that may also be the pool running out of liquidity. Fails with `HindsightError::UnsafeToken`
+/// if even the smallest buy reverts or returns no tokens.
+pub fn measure_token_tax(
+    evm: &EVM<ForkDB>,
+    adapter: &dyn PoolAdapter,
+    pool: Address,
+    asset: &QuoteAsset,
+    token: Address,
+    base_fee: U256,
+) -> Result<TokenTax> {
+    let quote = asset.address;
+    let mut funded = evm.clone();
+    fund_braindance(&mut funded, asset)?;
+    let probe = (asset.starting_balance / PROBE_FRACTION).max(1.into());
+
+    let mut buys: Vec<(U256, SwapFlows, EVM<ForkDB>)> = vec![];
+    let mut max_buy = None;
+    for multiple in TAX_PROBE_MULTIPLES {
+        let amount_in = probe
+            .saturating_mul(multiple.into())
+            .min(asset.starting_balance);
+        if buys
+            .last()
+            .is_some_and(|(bought_with, ..)| *bought_with >= amount_in)
+        {
+            break;
+        }
+        let mut evm = funded.clone();
+        match swap_flows(&mut evm, adapter, pool, amount_in, (quote, token), base_fee) {
+            Ok(flows) if !flows.out_arrived.is_zero() => buys.push((amount_in, flows, evm)),
+            res if buys.is_empty() => {
+                let reason = match res {
+                    Err(err) => format!("buy failed: {}", err),
+                    Ok(_) => "buy returned no tokens".to_owned(),
+                };
+                return Err(HindsightError::UnsafeToken(token, reason).into());
+            }
+            _ => {
+                max_buy = buys.last().map(|(bought_with, ..)| *bought_with);
+                break;
+            }
+        }
+    }
+    let buy_tax = tax_of(buys[0].1.out_sent, buys[0].1.out_arrived);
+
+    let (mut sell_tax, mut max_sell) = (None, None);
+    let mut last_sold: Option<U256> = None;
+    for (idx, (_, bought, mut evm)) in buys.into_iter().enumerate() {
+        let amount_in = bought.out_arrived;
+        match swap_flows(&mut evm, adapter, pool, amount_in, (token, quote), base_fee) {
+            Ok(sold) if idx == 0 => {
+                sell_tax = Some(tax_of(sold.in_sent, sold.in_arrived));
+                last_sold = Some(amount_in);
+            }
+            Ok(_) => last_sold = Some(amount_in),
+            Err(err) => {
+                debug!(?token, %amount_in, "sell failed: {}", err);
+                max_sell = last_sold;
+                break;
+            }
+        }
+    }
+    Ok(TokenTax {
+        token,
+        quote,
+        pool,
+        block: evm.env.block.number.as_limbs()[0],
+        buy_tax,
+        sell_tax,
+        max_buy,
+        max_sell,
+    })
+}
+
+/// Measures `token`'s taxes & limits (see `measure_token_tax`) at the end of `block`, on the first pool
+/// (of an AMM in `adapters`) that trades it against one of `quote_assets`, in their order of priority.
+///
+/// Pools that the token can't be bought on are skipped; if none can, the last pool's error is returned.
+pub async fn measure(
+    client: &WsClient,
+    adapters: &PoolAdapters,
+    quote_assets: &[QuoteAsset],
+    token: Address,
+    block: u64,
+) -> Result<TokenTax> {
+    let block_info = get_block_info(client, block).await?;
+    let factory = fork_factory(client, &block_info).await?;
+    let mut last_err: Option<Error> = None;
+    for asset in quote_assets.iter().filter(|asset| asset.address != token) {
+        let pools = adapters.find_pools(client, (asset.address, token)).await?;
+        for pool in pools.into_iter().filter(|pool| !pool.address.is_zero()) {
+            let evm = fork_from(&factory, &block_info);
+            let (adapters, asset) = (adapters.clone(), asset.clone());
+            let base_fee = block_info.base_fee;
+            let res = run_blocking(move || {
+                let adapter = adapters.get(pool.variant)?;
+                measure_token_tax(&evm, adapter, pool.address, &asset, token, base_fee)
+            })
+            .await;
+            match res {
+                Ok(tax) => return Ok(TokenTax { block, ..tax }),
+                Err(err) => {
+                    debug!(?token, pool = ?pool.address, "failed to measure token tax: {}", err);
+                    last_err = Some(err);
+                }
+            }
+        }
+    }
+    Err(last_err.unwrap_or(anyhow::format_err!(
+        "no pool trades {:?} against a quote asset",
+        token
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_measured_taxes() {
+        assert_eq!(tax_of(1000.into(), 1000.into()), 0.0);
+        assert_eq!(tax_of(1000.into(), 950.into()), 0.05);
+        assert_eq!(tax_of(0.into(), 0.into()), 0.0);
+
+        let tax = TokenTax {
+            token: Address::from_low_u64_be(1),
+            quote: Address::from_low_u64_be(2),
+            pool: Address::from_low_u64_be(3),
+            block: 1,
+            buy_tax: 0.02,
+            sell_tax: Some(0.03),
+            max_buy: None,
+            max_sell: None,
+        };
+        assert!(tax.check(0.05).is_ok());
+        assert!(tax.check(0.04).is_err());
+        let honeypot = TokenTax {
+            sell_tax: None,
+            ..tax
+        };
+        let err = honeypot.check(1.0).unwrap_err();
+        assert!(err.to_string().starts_with("unsafe token"), "{}", err);
+    }
+}
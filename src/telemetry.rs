@@ -0,0 +1,161 @@
+use crate::Result;
+use std::{fmt, str::FromStr};
+use tracing::{level_filters::LevelFilter, Subscriber};
+use tracing_subscriber::{fmt::MakeWriter, prelude::*, registry::LookupSpan, Layer};
+
+/// Name that exported spans are tagged with (`service.name`).
+pub const SERVICE_NAME: &str = "hindsight";
+
+pub const LOG_FORMAT_NAMES: [&'static str; 2] = ["text", "json"];
+
+/// How logs are written to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with each event's fields (e.g. `tx_hash`, `pool`, `depth`, `profit`)
+    /// at the top level and the fields of the spans it happened in under `spans`.
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::format_err!("invalid log format: {}", s)),
+        }
+    }
+}
+
+/// Logs to stderr (keeping stdout for command output) in `log_format` and, if `otlp` is set,
+/// exports the sim pipeline's spans over OTLP/HTTP.
+///
+/// The collector (e.g. Jaeger, Tempo) is read from `OTEL_EXPORTER_OTLP_ENDPOINT`, defaulting to `http://localhost:4318`.
+/// Exporting requires building with `--features otel`.
+pub fn init(otlp: bool, log_format: LogFormat) -> Result<()> {
+    if !otlp {
+        tracing_subscriber::registry()
+            .with(fmt_layer(log_format, std::io::stderr))
+            .try_init()?;
+        return Ok(());
+    }
+    init_otlp(log_format)
+}
+
+/// Writes info-level logs to `writer` in `log_format`.
+fn fmt_layer<S, W>(log_format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match log_format {
+        LogFormat::Text => layer.with_filter(LevelFilter::INFO).boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_filter(LevelFilter::INFO)
+            .boxed(),
+    }
+}
+
+#[cfg(feature = "otel")]
+fn init_otlp(log_format: LogFormat) -> Result<()> {
+    use opentelemetry::{sdk::trace, sdk::Resource, KeyValue};
+    use tracing::Level;
+    use tracing_subscriber::filter::Targets;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().http())
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+    tracing_subscriber::registry()
+        .with(fmt_layer(log_format, std::io::stderr))
+        // export the per-step spans too, but not those of dependencies (e.g. every RPC request)
+        .with(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(Targets::new().with_target("hindsight", Level::DEBUG)),
+        )
+        .try_init()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "otel"))]
+fn init_otlp(_log_format: LogFormat) -> Result<()> {
+    Err(anyhow::format_err!(
+        "exporting spans over OTLP requires building with `--features otel`"
+    ))
+}
+
+/// Flushes spans that haven't been exported yet. Call before exiting.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_logs_json_lines() -> Result<()> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("backrun_pool", pool = "0xa");
+            let _entered = span.enter();
+            crate::info!(depth = 2, profit = "1000", "sim was profitable");
+            crate::debug!("filtered out");
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().to_owned())?;
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+        let line: serde_json::Value = serde_json::from_str(lines[0])?;
+        assert_eq!(line["message"], "sim was profitable");
+        assert_eq!(line["depth"], 2);
+        assert_eq!(line["profit"], "1000");
+        assert_eq!(line["spans"][0]["name"], "backrun_pool");
+        assert_eq!(line["spans"][0]["pool"], "0xa");
+        assert_eq!("json".parse::<LogFormat>()?, LogFormat::Json);
+        Ok(())
+    }
+}
@@ -1,18 +1,29 @@
 use crate::{
-    config::Config,
+    config::{Config, SUSHISWAP_FACTORY, UNISWAP_V2_FACTORY, UNISWAP_V3_FACTORY},
+    debug,
+    error::HindsightError,
     info,
     interfaces::{PairPool, PoolVariant},
-    Result,
+    sim::pools::PoolAdapters,
+    Error, Result,
 };
 use ethers::{
     prelude::{abigen, H160},
-    providers::{Middleware, Provider, Ws},
-    types::{transaction::eip2718::TypedTransaction, Address, Transaction, H256, U256},
+    providers::{Middleware, Provider, ProviderError, Ws},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Transaction, TransactionReceipt, H256,
+        I256, U256,
+    },
 };
-use futures::future;
+use futures::{stream, Future, StreamExt};
 use mev_share_sse::EventHistory;
 use rusty_sando::types::BlockInfo;
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
 use uniswap_v3_math::{full_math::mul_div, sqrt_price_math::Q96};
 
 pub use ethers::utils::WEI_IN_ETHER as ETH;
@@ -22,43 +33,168 @@ pub async fn get_ws_client(rpc_url: Option<String>) -> Result<WsClient> {
     let rpc_url = if let Some(rpc_url) = rpc_url {
         rpc_url
     } else {
-        Config::default().rpc_url_ws
+        Config::load()?.rpc_url_ws
     };
     let provider = Provider::<Ws>::connect(rpc_url).await?;
     Ok(Arc::new(provider))
 }
 
+/// Number of txs or receipts requested at once by `fetch_txs` & `ReceiptCache::prefetch`.
+pub const FETCH_CONCURRENCY: usize = 50;
+/// Attempts per request before giving up on it.
+const FETCH_ATTEMPTS: u32 = 3;
+/// Number of receipts kept by a `ReceiptCache`.
+pub const RECEIPT_CACHE_SIZE: usize = 4096;
+
+/// Sends the request made by `request` until it succeeds, up to `FETCH_ATTEMPTS` times, backing off between attempts.
+async fn with_retry<T, F, Fut>(mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, ProviderError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Ok(res) => return Ok(res),
+            Err(err) if attempt < FETCH_ATTEMPTS => {
+                debug!("request failed (attempt {}): {}", attempt, err);
+                tokio::time::sleep(Duration::from_millis(100 << attempt)).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Fetches each of `tx_hashes` with `fetch`, with up to `FETCH_CONCURRENCY` requests in flight.
+/// Results are in the order of `tx_hashes`.
+pub async fn fetch_all<T, F, Fut>(tx_hashes: &[H256], fetch: F) -> Vec<Result<T>>
+where
+    F: Fn(H256) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    stream::iter(tx_hashes.iter().map(|tx_hash| fetch(*tx_hash)))
+        .buffered(FETCH_CONCURRENCY)
+        .collect()
+        .await
+}
+
+/// Fetches the txs of `events`, skipping those that haven't landed or couldn't be fetched.
 pub async fn fetch_txs(client: &WsClient, events: &Vec<EventHistory>) -> Result<Vec<Transaction>> {
     let tx_hashes: Vec<H256> = events.iter().map(|e: &EventHistory| e.hint.hash).collect();
-    let mut handles = vec![];
-
-    for tx_hash in tx_hashes.into_iter() {
-        let client = client.clone();
-        handles.push(tokio::task::spawn(async move {
-            let tx = &client.get_transaction(tx_hash.to_owned()).await;
-            if let Ok(tx) = tx {
-                if let Some(tx) = tx {
-                    info!("tx found onchain\t{:?}", tx_hash.to_owned());
-                    return Some(tx.clone());
-                } else {
-                    info!("tx not found onchain\t{:?}", tx_hash.to_owned());
-                    None
+    let results = fetch_all(&tx_hashes, |tx_hash| {
+        with_retry(move || client.get_transaction(tx_hash))
+    })
+    .await;
+    let mut txs = vec![];
+    for (tx_hash, res) in tx_hashes.iter().zip(results) {
+        match res {
+            Ok(Some(tx)) => {
+                info!("tx found onchain\t{:?}", tx_hash);
+                txs.push(tx);
+            }
+            Ok(None) => info!("tx not found onchain\t{:?}", tx_hash),
+            Err(err) => info!("error fetching tx {:?}: {}", tx_hash, err),
+        }
+    }
+    Ok(txs)
+}
+
+/// Outcome of a set of RPC requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FetchStats {
+    pub requests: usize,
+    /// Requests that still failed after retrying.
+    pub errors: usize,
+    /// Mean time per request, including retries.
+    pub latency: Duration,
+}
+
+/// Receipts of recently simulated txs, so that each is fetched once, rather than by every step that reads it.
+///
+/// Clones share the cache. Only landed txs' receipts are cached.
+#[derive(Clone, Debug, Default)]
+pub struct ReceiptCache {
+    receipts: Arc<Mutex<CachedReceipts>>,
+}
+
+#[derive(Debug, Default)]
+struct CachedReceipts {
+    by_hash: HashMap<H256, TransactionReceipt>,
+    /// Hashes in the order they were cached, to evict the oldest first.
+    order: VecDeque<H256>,
+}
+
+impl ReceiptCache {
+    /// Returns the receipt of `tx_hash`, fetching it if it isn't cached. Returns None if the tx hasn't landed.
+    pub async fn get(
+        &self,
+        client: &WsClient,
+        tx_hash: H256,
+    ) -> Result<Option<TransactionReceipt>> {
+        if let Some(receipt) = self.cached(&tx_hash) {
+            return Ok(Some(receipt));
+        }
+        let receipt = with_retry(|| client.get_transaction_receipt(tx_hash)).await?;
+        if let Some(receipt) = &receipt {
+            self.insert(receipt.to_owned());
+        }
+        Ok(receipt)
+    }
+
+    /// Fetches the receipts of `tx_hashes` that aren't cached yet, up to `FETCH_CONCURRENCY` at a time.
+    /// Failures are logged, and left for `get` to try again.
+    ///
+    /// Returns how the requests went, as a sample of the RPC node's health.
+    pub async fn prefetch(&self, client: &WsClient, tx_hashes: &[H256]) -> FetchStats {
+        let missing = tx_hashes
+            .iter()
+            .filter(|tx_hash| self.cached(tx_hash).is_none())
+            .copied()
+            .collect::<Vec<_>>();
+        let results = fetch_all(&missing, |tx_hash| async move {
+            let start = Instant::now();
+            let res = with_retry(move || client.get_transaction_receipt(tx_hash)).await;
+            Ok((res, start.elapsed()))
+        })
+        .await;
+        let mut stats = FetchStats::default();
+        let mut total_latency = Duration::ZERO;
+        for (tx_hash, res) in missing.iter().zip(results.into_iter().flatten()) {
+            let (res, latency) = res;
+            stats.requests += 1;
+            total_latency += latency;
+            match res {
+                Ok(Some(receipt)) => self.insert(receipt),
+                Ok(None) => debug!("no receipt for tx {:?}", tx_hash),
+                Err(err) => {
+                    stats.errors += 1;
+                    debug!("failed to prefetch receipt of {:?}: {}", tx_hash, err)
                 }
-            } else {
-                info!("error fetching tx: {:?}", tx);
-                None
             }
-        }));
+        }
+        if stats.requests > 0 {
+            stats.latency = total_latency / stats.requests as u32;
+        }
+        stats
+    }
+
+    fn cached(&self, tx_hash: &H256) -> Option<TransactionReceipt> {
+        self.receipts.lock().unwrap().by_hash.get(tx_hash).cloned()
+    }
+
+    fn insert(&self, receipt: TransactionReceipt) {
+        let mut receipts = self.receipts.lock().unwrap();
+        let tx_hash = receipt.transaction_hash;
+        if receipts.by_hash.insert(tx_hash, receipt).is_none() {
+            receipts.order.push_back(tx_hash);
+        }
+        while receipts.order.len() > RECEIPT_CACHE_SIZE {
+            if let Some(oldest) = receipts.order.pop_front() {
+                receipts.by_hash.remove(&oldest);
+            }
+        }
     }
-    let results = future::join_all(handles)
-        .await
-        .into_iter()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap())
-        .filter(|r| r.is_some())
-        .map(|r| r.unwrap())
-        .collect::<Vec<_>>();
-    Ok(results)
 }
 
 pub async fn get_pair_tokens(client: &WsClient, pair: Address) -> Result<(Address, Address)> {
@@ -87,21 +223,19 @@ pub async fn get_block_info(client: &WsClient, block_num: u64) -> Result<BlockIn
     })
 }
 
-async fn get_v2_pairs(client: &WsClient, pair_tokens: (Address, Address)) -> Result<Vec<Address>> {
+/// Returns (factory, pair) for each V2 clone that has a pair for the tokens.
+async fn get_v2_pairs(
+    client: &WsClient,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<(Address, Address)>> {
     abigen!(
         IUniswapV2Factory,
         r#"[
             function getPair(address tokenA, address tokenB) external view returns (address pair)
         ]"#
     );
-    let uni_factory = IUniswapV2Factory::new(
-        "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f".parse::<H160>()?,
-        client.clone(),
-    );
-    let sushi_factory = IUniswapV2Factory::new(
-        "0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac".parse::<H160>()?,
-        client.clone(),
-    );
+    let uni_factory = IUniswapV2Factory::new(UNISWAP_V2_FACTORY.parse::<H160>()?, client.clone());
+    let sushi_factory = IUniswapV2Factory::new(SUSHISWAP_FACTORY.parse::<H160>()?, client.clone());
 
     let uni_pair: Result<Address, _> = uni_factory
         .get_pair(pair_tokens.0, pair_tokens.1)
@@ -113,10 +247,10 @@ async fn get_v2_pairs(client: &WsClient, pair_tokens: (Address, Address)) -> Res
         .await;
     let mut pairs = vec![];
     if let Ok(uni_pair) = uni_pair {
-        pairs.push(uni_pair);
+        pairs.push((uni_factory.address(), uni_pair));
     }
     if let Ok(sushi_pair) = sushi_pair {
-        pairs.push(sushi_pair);
+        pairs.push((sushi_factory.address(), sushi_pair));
     }
 
     Ok(pairs)
@@ -129,10 +263,7 @@ async fn get_v3_pair(client: &WsClient, pair_tokens: (Address, Address)) -> Resu
             function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
         ]"#
     );
-    let contract = IUniswapV3Factory::new(
-        "0x1F98431c8aD98523631AE4a59f267346ea31F984".parse::<H160>()?,
-        client.clone(),
-    );
+    let contract = IUniswapV3Factory::new(UNISWAP_V3_FACTORY.parse::<H160>()?, client.clone());
     Ok(contract
         .get_pool(pair_tokens.0, pair_tokens.1, 3000)
         .call()
@@ -145,37 +276,232 @@ pub async fn get_all_trading_pools(
     client: &WsClient,
     pair_tokens: (Address, Address),
 ) -> Result<Vec<PairPool>> {
-    let mut all_pairs = vec![];
-    // push v3 pair (there should only be one for a given fee, which we hard-code to 3000 in get_v3_pair)
-    all_pairs.push(PairPool {
+    let mut all_pairs = get_v3_pools(client, pair_tokens).await?;
+    all_pairs.append(&mut get_v2_pools(client, pair_tokens).await?);
+    Ok(all_pairs)
+}
+
+/// Returns the UniswapV3 pool for the tokens (there should only be one for a given fee, which we hard-code to 3000 in get_v3_pair).
+pub async fn get_v3_pools(
+    client: &WsClient,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<PairPool>> {
+    Ok(vec![PairPool {
         address: get_v3_pair(client, pair_tokens).await?,
         variant: PoolVariant::UniswapV3,
-    });
-    // v2 pairs pull from multiple v2 clones
-    let v2_pairs = get_v2_pairs(client, pair_tokens).await?;
-    all_pairs.append(
-        &mut v2_pairs
-            .into_iter()
-            .map(|pair| PairPool {
-                address: pair,
-                variant: PoolVariant::UniswapV2,
+        factory: UNISWAP_V3_FACTORY.parse().ok(),
+    }])
+}
+
+/// Returns the pair of each V2 clone that trades the tokens.
+pub async fn get_v2_pools(
+    client: &WsClient,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<PairPool>> {
+    Ok(get_v2_pairs(client, pair_tokens)
+        .await?
+        .into_iter()
+        .map(|(factory, pair)| PairPool {
+            address: pair,
+            variant: PoolVariant::UniswapV2,
+            factory: Some(factory),
+        })
+        .collect())
+}
+
+/// How long a `PoolCache` trusts a lookup, so long-running processes see pools deployed after they started.
+pub const POOL_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Pools found for each token pair, so that the pair's factories are queried once per scan
+/// instead of for every swap that trades it.
+///
+/// Pairs without pools are cached too (as an empty list, or pools with a zero address), but failed lookups aren't.
+/// Concurrent lookups of a pair wait for the first one. Clones share the cache.
+#[derive(Clone)]
+pub struct PoolCache {
+    ttl: Duration,
+    lookups: Arc<Mutex<PoolLookups>>,
+}
+
+/// Lookup of each token pair (lowest address first), finished when the cell is set.
+type PoolLookups = HashMap<(Address, Address), Arc<OnceCell<(Instant, Vec<PairPool>)>>>;
+
+impl Default for PoolCache {
+    fn default() -> Self {
+        Self::with_ttl(POOL_CACHE_TTL)
+    }
+}
+
+impl std::fmt::Debug for PoolCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolCache")
+            .field("ttl", &self.ttl)
+            .field("pairs", &self.lookups.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl PoolCache {
+    /// Looks up each pair again once its lookup is older than `ttl`.
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            lookups: Default::default(),
+        }
+    }
+
+    /// Returns the pools of every AMM in `adapters` that trade `tokens`, like `PoolAdapters::find_pools`.
+    /// A cache must only be used with one chain's client & one set of adapters.
+    pub async fn find_pools(
+        &self,
+        client: &WsClient,
+        adapters: &PoolAdapters,
+        tokens: (Address, Address),
+    ) -> Result<Vec<PairPool>> {
+        self.get_or_find(tokens, || adapters.find_pools(client, tokens))
+            .await
+    }
+
+    async fn get_or_find<F, Fut>(
+        &self,
+        tokens: (Address, Address),
+        find: F,
+    ) -> Result<Vec<PairPool>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<PairPool>>>,
+    {
+        // pools trade both ways, so (a, b) & (b, a) share a lookup
+        let pair = (tokens.0.min(tokens.1), tokens.0.max(tokens.1));
+        let cell = {
+            let mut lookups = self.lookups.lock().unwrap();
+            let cell = lookups.entry(pair).or_default();
+            if let Some((found_at, _)) = cell.get() {
+                if found_at.elapsed() >= self.ttl {
+                    *cell = Default::default();
+                }
+            }
+            cell.clone()
+        };
+        let (_, pools) = cell
+            .get_or_try_init(|| async {
+                debug!("finding pools for {:?}", pair);
+                Ok::<_, Error>((Instant::now(), find().await?))
             })
-            .collect::<Vec<_>>(),
-    );
-    Ok(all_pairs)
+            .await?;
+        Ok(pools.to_owned())
+    }
 }
 
+/// Reserves (or in-range liquidity) at or below which a pool is treated as empty.
+/// UniswapV2 locks this much liquidity in every pair forever, so a pair holding no more has been drained or never funded.
+pub const DUST_RESERVES: u64 = 1000;
+
 /// Returns the price (token1 per token0).
+///
+/// Fails with `HindsightError::InsufficientLiquidity` if either reserve is dust.
 pub fn get_price_v2(reserves0: U256, reserves1: U256, token0_decimals: U256) -> Result<U256> {
-    Ok((reserves1 * U256::from(10).pow(token0_decimals)) / reserves0)
+    if reserves0 <= DUST_RESERVES.into() || reserves1 <= DUST_RESERVES.into() {
+        return Err(HindsightError::InsufficientLiquidity(format!(
+            "reserves ({}, {})",
+            reserves0, reserves1
+        ))
+        .into());
+    }
+    checked_mul_div(reserves1, pow10(token0_decimals)?, reserves0)
+}
+
+/// Returns `10^exponent`, e.g. the number of base units in one whole token of `exponent` decimals.
+///
+/// Fails with `HindsightError::Overflow` if it doesn't fit in a U256 (`exponent` > 77).
+pub fn pow10(exponent: U256) -> Result<U256> {
+    U256::from(10)
+        .checked_pow(exponent)
+        .ok_or(HindsightError::Overflow(format!("10^{}", exponent)).into())
+}
+
+/// Returns `a * b / denominator`, rounded down. The product is taken in 512 bits, so only the result has to fit in a U256.
+///
+/// Fails with `HindsightError::MathError` if `denominator` is 0, or `HindsightError::Overflow` if the result doesn't fit.
+pub fn checked_mul_div(a: U256, b: U256, denominator: U256) -> Result<U256> {
+    if denominator.is_zero() {
+        return Err(HindsightError::MathError(format!("{} * {} / 0", a, b)).into());
+    }
+    mul_div(a, b, denominator)
+        .map_err(|_| HindsightError::Overflow(format!("{} * {} / {}", a, b, denominator)).into())
+}
+
+/// Parts per billion, that shares (fractions) of token amounts are taken in, so huge amounts don't lose precision in floats.
+pub const PPB: u64 = 1_000_000_000;
+
+/// `share` in parts per billion; negative (or NaN) shares are 0.
+fn share_ppb(share: f64) -> U256 {
+    U256::from((share * PPB as f64) as u64)
+}
+
+/// Compares `part` to `share` of `whole`, in parts per billion.
+pub fn compare_share(part: U256, whole: U256, share: f64) -> std::cmp::Ordering {
+    part.saturating_mul(PPB.into())
+        .cmp(&whole.saturating_mul(share_ppb(share)))
+}
+
+/// Whether `part` is more than `share` of `whole`, in parts per billion.
+pub fn exceeds_share(part: U256, whole: U256, share: f64) -> bool {
+    compare_share(part, whole, share).is_gt()
+}
+
+/// `share` (clamped to 0..=1) of `amount`, in parts per billion & rounded down.
+pub fn apply_share(amount: U256, share: f64) -> U256 {
+    checked_mul_div(amount, share_ppb(share.clamp(0.0, 1.0)), PPB.into()).unwrap_or(amount)
+}
+
+/// Share of `whole` that `part` is, to a part per billion. A `part` of nothing is 0.
+pub fn share_of(part: U256, whole: U256) -> f64 {
+    if whole.is_zero() {
+        return 0.0;
+    }
+    let ppb = part.saturating_mul(PPB.into()) / whole;
+    ppb.low_u64() as f64 / PPB as f64
 }
 
 /// Returns the price (token1 per token0).
+///
+/// Fails with `HindsightError::InsufficientLiquidity` if the in-range liquidity is dust or the pool isn't initialized.
 pub fn get_price_v3(liquidity: U256, sqrt_price_x96: U256, token0_decimals: U256) -> Result<U256> {
-    let reserves0 = mul_div(liquidity, Q96, sqrt_price_x96)?;
-    let reserves1 = mul_div(liquidity, sqrt_price_x96, Q96)?;
+    if liquidity <= DUST_RESERVES.into() || sqrt_price_x96.is_zero() {
+        return Err(HindsightError::InsufficientLiquidity(format!(
+            "liquidity {}, sqrt price {}",
+            liquidity, sqrt_price_x96
+        ))
+        .into());
+    }
+    let reserves0 = checked_mul_div(liquidity, Q96, sqrt_price_x96)?;
+    let reserves1 = checked_mul_div(liquidity, sqrt_price_x96, Q96)?;
+    if reserves0.is_zero() {
+        // priced so high that the pool's virtual token0 reserves round to nothing
+        return Err(HindsightError::InsufficientLiquidity(format!(
+            "no token0 at liquidity {}, sqrt price {}",
+            liquidity, sqrt_price_x96
+        ))
+        .into());
+    }
+    checked_mul_div(reserves1, pow10(token0_decimals)?, reserves0)
+}
 
-    Ok((reserves1 * U256::from(10).pow(token0_decimals)) / reserves0)
+/// Converts `amount` of one token of a pool into the other at `price` (token1 per token0, as returned by `get_price_v2`
+/// & `get_price_v3`), in base units: token0 into token1 if `zero_for_one`, else token1 into token0.
+///
+/// `price` is scaled by token0's decimals, so only `token0_decimals` is needed to convert between e.g. USDC (6) & WETH (18).
+pub fn convert_at_price(
+    amount: U256,
+    price: U256,
+    token0_decimals: U256,
+    zero_for_one: bool,
+) -> Result<U256> {
+    match zero_for_one {
+        true => checked_mul_div(amount, price, pow10(token0_decimals)?),
+        false => checked_mul_div(amount, pow10(token0_decimals)?, price),
+    }
 }
 
 pub async fn get_decimals(client: &WsClient, token: Address) -> Result<U256> {
@@ -190,6 +516,68 @@ pub async fn get_decimals(client: &WsClient, token: Address) -> Result<U256> {
     Ok(decimals)
 }
 
+/// Returns the swap fee of a Uniswap V3 pool, in hundredths of a bip.
+pub async fn get_pool_fee(client: &WsClient, pool: Address) -> Result<u32> {
+    abigen!(
+        IUniswapV3PoolFee,
+        r#"[
+            function fee() external view returns (uint24)
+        ]"#
+    );
+    let contract = IUniswapV3PoolFee::new(pool, client.clone());
+    Ok(contract.fee().call().await?)
+}
+
+/// Returns a UniswapV2 pair's reserves (reserve0, reserve1) at the end of `block`.
+pub async fn get_reserves_v2(client: &WsClient, pair: Address, block: u64) -> Result<(U256, U256)> {
+    abigen!(
+        IUniswapV2Reserves,
+        r#"[
+            function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
+        ]"#
+    );
+    let contract = IUniswapV2Reserves::new(pair, client.clone());
+    let (reserve0, reserve1, _) = contract.get_reserves().block(block).call().await?;
+    Ok((reserve0.into(), reserve1.into()))
+}
+
+/// Returns the factory that deployed a pool, which identifies the DEX (V2 clones share `PoolVariant::UniswapV2`).
+pub async fn get_pool_factory(client: &WsClient, pool: Address) -> Result<Address> {
+    abigen!(
+        IPoolFactory,
+        r#"[
+            function factory() external view returns (address)
+        ]"#
+    );
+    let contract = IPoolFactory::new(pool, client.clone());
+    Ok(contract.factory().call().await?)
+}
+
+/// Returns the answer of a Chainlink price feed as of `block`, in units of the feed's decimals.
+pub async fn get_chainlink_answer(client: &WsClient, feed: Address, block: u64) -> Result<I256> {
+    abigen!(
+        IAggregatorV3,
+        r#"[
+            function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+        ]"#
+    );
+    let contract = IAggregatorV3::new(feed, client.clone());
+    let (_, answer, _, _, _) = contract.latest_round_data().block(block).call().await?;
+    Ok(answer)
+}
+
+/// Returns the number of decimals of a Chainlink price feed's answers.
+pub async fn get_feed_decimals(client: &WsClient, feed: Address) -> Result<u8> {
+    abigen!(
+        IAggregatorDecimals,
+        r#"[
+            function decimals() external view returns (uint8)
+        ]"#
+    );
+    let contract = IAggregatorDecimals::new(feed, client.clone());
+    Ok(contract.decimals().call().await?)
+}
+
 pub async fn get_balance_call(
     client: &WsClient,
     token: Address,
@@ -229,6 +617,286 @@ pub fn filter_events_by_topic(
         .collect::<Vec<_>>()
 }
 
+/// Describes why a spawned task didn't finish, including its panic message if it has one.
+pub fn panic_message(err: tokio::task::JoinError) -> String {
+    if !err.is_panic() {
+        return err.to_string();
+    }
+    let payload = err.into_panic();
+    let message = payload
+        .downcast_ref::<String>()
+        .map(|message| message.to_owned())
+        .or(payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string()))
+        .unwrap_or("unknown cause".to_owned());
+    format!("panicked: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn pool(address: u64) -> PairPool {
+        PairPool {
+            variant: PoolVariant::UniswapV2,
+            address: Address::from_low_u64_be(address),
+            factory: None,
+        }
+    }
+
+    #[test]
+    fn it_rejects_empty_pools() -> Result<()> {
+        let reserves = U256::from(100) * ETH;
+        assert_eq!(
+            get_price_v2(reserves, reserves * 2000, 18.into())?,
+            ETH * 2000
+        );
+        for (reserves0, reserves1) in [(0.into(), reserves), (reserves, DUST_RESERVES.into())] {
+            let err = get_price_v2(reserves0, reserves1, 18.into()).unwrap_err();
+            assert!(err.to_string().starts_with("insufficient liquidity"));
+        }
+        assert!(get_price_v3(0.into(), Q96, 18.into()).is_err());
+        assert!(get_price_v3(ETH, 0.into(), 18.into()).is_err());
+        assert_eq!(get_price_v3(ETH, Q96, 18.into())?, ETH);
+        Ok(())
+    }
+
+    /// A random number of random bits, so that magnitudes are spread over the whole U256 range.
+    fn random_u256(rng: &mut impl rand::Rng) -> U256 {
+        U256::from_big_endian(&rng.gen::<[u8; 32]>()) >> rng.gen_range(0..256)
+    }
+
+    #[test]
+    fn it_prices_extreme_pools_without_panicking() {
+        use ethers::types::U512;
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let fits = |value: U512| U256::try_from(value).ok();
+        for _ in 0..10_000 {
+            let (a, b) = (random_u256(&mut rng), random_u256(&mut rng));
+            let decimals = U256::from(rng.gen_range(0..100));
+            let scale = fits(U512::from(10).pow(U512::from(decimals)));
+
+            // v2 matches exact 512-bit math whenever the price fits, and fails otherwise
+            let expected = scale.filter(|_| a > DUST_RESERVES.into() && b > DUST_RESERVES.into());
+            let expected = expected.and_then(|scale| fits(b.full_mul(scale) / U512::from(a)));
+            assert_eq!(
+                get_price_v2(a, b, decimals).ok(),
+                expected,
+                "v2 {} {} {}",
+                a,
+                b,
+                decimals
+            );
+
+            // v3 pools hold at most u128 liquidity at a u160 sqrt price
+            let (liquidity, sqrt_price) = (a >> 128, b >> 96);
+            if let Ok(price) = get_price_v3(liquidity, sqrt_price, decimals) {
+                let reserves0 = liquidity.full_mul(Q96) / U512::from(sqrt_price);
+                let reserves1 = liquidity.full_mul(sqrt_price) / U512::from(Q96);
+                let reserves1 = fits(reserves1).unwrap();
+                let expected = reserves1.full_mul(scale.unwrap()) / reserves0;
+                assert_eq!(Some(price), fits(expected), "v3 {} {} {}", a, b, decimals);
+            }
+        }
+    }
+
+    #[test]
+    fn it_checks_mul_div() {
+        assert_eq!(
+            checked_mul_div(U256::MAX, 2.into(), 4.into()).unwrap(),
+            U256::MAX / 2
+        );
+        assert!(checked_mul_div(U256::MAX, 2.into(), 1.into())
+            .unwrap_err()
+            .to_string()
+            .starts_with("overflow"));
+        assert!(checked_mul_div(1.into(), 1.into(), 0.into()).is_err());
+        assert_eq!(pow10(77.into()).unwrap(), U256::exp10(77));
+        assert!(pow10(78.into()).is_err());
+    }
+
+    #[test]
+    fn it_takes_shares_in_parts_per_billion() {
+        let whole = U256::MAX / 2;
+        assert!(exceeds_share(whole / 2 + 1, whole, 0.5));
+        assert!(!exceeds_share(whole / 2, whole, 0.5));
+        assert!(compare_share(U256::zero(), whole, 0.0).is_eq());
+        assert_eq!(apply_share(U256::from(1000), 0.25), U256::from(250));
+        assert_eq!(apply_share(U256::from(1000), 2.0), U256::from(1000));
+        assert_eq!(apply_share(U256::from(1000), f64::NAN), U256::zero());
+        assert_eq!(share_of(U256::from(1), U256::from(4)), 0.25);
+        assert_eq!(share_of(U256::from(1), U256::zero()), 0.0);
+    }
+
+    #[test]
+    fn it_converts_amounts_across_decimals() {
+        // USDC (6 decimals) is token0 of its WETH pair; at 2000 USDC/ETH, a whole USDC is worth 1/2000 ETH
+        let usdc_decimals = U256::from(6);
+        let price = get_price_v2(
+            U256::from(2000) * U256::exp10(6) * U256::exp10(6),
+            U256::exp10(6) * ETH,
+            usdc_decimals,
+        )
+        .unwrap();
+        assert_eq!(price, ETH / 2000);
+        let usdc = U256::from(2000) * U256::exp10(6);
+        assert_eq!(
+            convert_at_price(usdc, price, usdc_decimals, true).unwrap(),
+            ETH
+        );
+        assert_eq!(
+            convert_at_price(ETH, price, usdc_decimals, false).unwrap(),
+            usdc
+        );
+        // without scaling, 2000 USDC would be "worth" a million ETH
+        assert_eq!(usdc * price, U256::from(1_000_000) * ETH);
+
+        // WBTC (8 decimals) is token0 of its WETH pair; at 15 ETH/BTC
+        let wbtc_decimals = U256::from(8);
+        let price = U256::from(15) * ETH;
+        let half_wbtc = U256::exp10(8) / 2;
+        assert_eq!(
+            convert_at_price(half_wbtc, price, wbtc_decimals, true).unwrap(),
+            U256::from(75) * ETH / 10
+        );
+        assert_eq!(
+            convert_at_price(U256::from(75) * ETH / 10, price, wbtc_decimals, false).unwrap(),
+            half_wbtc
+        );
+        // a 1-satoshi trade is still worth its share of an ETH
+        assert_eq!(
+            convert_at_price(1.into(), price, wbtc_decimals, true).unwrap(),
+            U256::from(15) * U256::exp10(10)
+        );
+        assert!(convert_at_price(ETH, 0.into(), wbtc_decimals, false).is_err());
+    }
+
+    /// U256s of any magnitude: random bits shifted down by a random amount, like `random_u256`.
+    fn any_u256() -> impl Strategy<Value = U256> {
+        (any::<[u8; 32]>(), 0..256usize)
+            .prop_map(|(bytes, shift)| U256::from_big_endian(&bytes) >> shift)
+    }
+
+    proptest! {
+        #[test]
+        fn it_prices_v2_monotonically(
+            reserves0 in any_u256(),
+            reserves1 in any_u256(),
+            more in any_u256(),
+            decimals in 0..80u64,
+        ) {
+            let decimals = U256::from(decimals);
+            let Ok(price) = get_price_v2(reserves0, reserves1, decimals) else {
+                return Ok(());
+            };
+            // more token1 (or less token0) never makes token0 cheaper
+            if let Some(reserves1) = reserves1.checked_add(more) {
+                if let Ok(higher) = get_price_v2(reserves0, reserves1, decimals) {
+                    prop_assert!(higher >= price);
+                }
+            }
+            if let Some(reserves0) = reserves0.checked_add(more) {
+                let lower = get_price_v2(reserves0, reserves1, decimals);
+                prop_assert!(matches!(lower, Ok(lower) if lower <= price));
+            }
+            // one more decimal scales the price by (at least, after rounding) 10x
+            if let Ok(scaled) = get_price_v2(reserves0, reserves1, decimals + 1) {
+                prop_assert!(scaled >= price * 10);
+            }
+        }
+
+        #[test]
+        fn it_prices_v3_monotonically(
+            liquidity in any::<u128>(),
+            sqrt_price in any_u256(),
+            more in any_u256(),
+            decimals in 0..80u64,
+        ) {
+            // v3 pools hold at most u128 liquidity at a u160 sqrt price
+            let (liquidity, sqrt_price, more) = (U256::from(liquidity), sqrt_price >> 96, more >> 96);
+            let decimals = U256::from(decimals);
+            let Ok(price) = get_price_v3(liquidity, sqrt_price, decimals) else {
+                return Ok(());
+            };
+            if let Ok(higher) = get_price_v3(liquidity, sqrt_price + more, decimals) {
+                prop_assert!(higher >= price);
+            }
+            if let Ok(scaled) = get_price_v3(liquidity, sqrt_price, decimals + 1) {
+                prop_assert!(scaled >= price * 10);
+            }
+        }
+
+        #[test]
+        fn it_converts_at_price_monotonically(
+            amount in any_u256(),
+            more in any_u256(),
+            price in any_u256(),
+            decimals in 0..80u64,
+            zero_for_one in any::<bool>(),
+        ) {
+            let decimals = U256::from(decimals);
+            let Ok(converted) = convert_at_price(amount, price, decimals, zero_for_one) else {
+                return Ok(());
+            };
+            // a bigger amount is never worth less
+            if let Some(bigger) = amount.checked_add(more) {
+                if let Ok(bigger) = convert_at_price(bigger, price, decimals, zero_for_one) {
+                    prop_assert!(bigger >= converted);
+                }
+            }
+            // both ways round down, so converting there & back never gains anything
+            if let Ok(back) = convert_at_price(converted, price, decimals, !zero_for_one) {
+                prop_assert!(back <= amount);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_caches_pool_lookups() -> Result<()> {
+        let cache = PoolCache::default();
+        let (a, b, c) = (
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            Address::from_low_u64_be(3),
+        );
+        let pools = cache
+            .get_or_find((a, b), || async { Ok(vec![pool(10)]) })
+            .await?;
+        assert_eq!(pools[0].address, pool(10).address);
+        // reversed pair is served from the cache
+        let pools = cache
+            .get_or_find((b, a), || async { panic!("pair should be cached") })
+            .await?;
+        assert_eq!(pools.len(), 1);
+
+        // failures aren't cached, nonexistent pools are
+        assert!(cache
+            .get_or_find((a, c), || async { Err(anyhow::anyhow!("rpc error")) })
+            .await
+            .is_err());
+        assert!(cache
+            .get_or_find((a, c), || async { Ok(vec![]) })
+            .await?
+            .is_empty());
+        assert!(cache
+            .get_or_find((c, a), || async { panic!("pair should be cached") })
+            .await?
+            .is_empty());
+
+        // expired lookups are repeated
+        let cache = PoolCache::with_ttl(Duration::ZERO);
+        cache.get_or_find((a, b), || async { Ok(vec![]) }).await?;
+        let pools = cache
+            .get_or_find((a, b), || async { Ok(vec![pool(11)]) })
+            .await?;
+        assert_eq!(pools.len(), 1);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::util::{get_ws_client, WsClient};